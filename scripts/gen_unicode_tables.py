@@ -0,0 +1,80 @@
+#!/usr/bin/env python3
+"""Generate src/unicode_tables.rs from Python's unicodedata module.
+
+The tables follow the Unicode version of the Python running this script,
+which is recorded in the header of the generated file:
+
+    python3 scripts/gen_unicode_tables.py > src/unicode_tables.rs
+"""
+
+import unicodedata
+
+# Hangul syllables are composed and decomposed algorithmically
+HANGUL = range(0xAC00, 0xD7A4)
+
+
+def hex_code(code):
+    return '0x%04X' % code
+
+
+def rows(items, per_line):
+    lines = []
+    for start in range(0, len(items), per_line):
+        lines.append('    ' + ' '.join(item + ',' for item in items[start:start + per_line]))
+    return '\n'.join(lines)
+
+
+def main():
+    classes = []
+    decompositions = []
+    compositions = []
+    for code in range(0x110000):
+        if code in HANGUL:
+            continue
+        char = chr(code)
+        cls = unicodedata.combining(char)
+        if cls:
+            if classes and classes[-1][1] == code - 1 and classes[-1][2] == cls:
+                classes[-1][1] = code
+            else:
+                classes.append([code, code, cls])
+        decomposition = unicodedata.decomposition(char)
+        # Compatibility decompositions start with a tag such as <font>
+        if not decomposition or decomposition.startswith('<'):
+            continue
+        parts = [int(part, 16) for part in decomposition.split()]
+        decompositions.append((code, parts[0], parts[1] if len(parts) > 1 else 0))
+        # Singletons and composition exclusions do not compose back
+        if len(parts) == 2 and unicodedata.normalize('NFC', char) == char:
+            compositions.append((parts[0], parts[1], code))
+    compositions.sort()
+
+    print('//! Tables of the Unicode Character Database %s for `unicode.rs`,'
+          % unicodedata.unidata_version)
+    print("//! generated from Python's `unicodedata` module by")
+    print('//! `scripts/gen_unicode_tables.py`; Hangul syllables are composed and')
+    print('//! decomposed algorithmically and left out')
+    print()
+    print('/// Canonical combining classes other than 0, as `(first, last, class)` ranges')
+    print('#[rustfmt::skip]')
+    print('pub const COMBINING_CLASSES: &[(u32, u32, u8)] = &[')
+    print(rows(['(%s, %s, %d)' % (hex_code(first), hex_code(last), cls)
+                for first, last, cls in classes], 5))
+    print('];')
+    print()
+    print('/// Canonical decompositions, as `(character, first, second)` with a second')
+    print('/// of 0 for singletons, sorted by character')
+    print('#[rustfmt::skip]')
+    print('pub const DECOMPOSITIONS: &[(u32, u32, u32)] = &[')
+    print(rows(['(%s, %s, %s)' % tuple(map(hex_code, entry)) for entry in decompositions], 4))
+    print('];')
+    print()
+    print('/// Primary composites, as `(first, second, composite)` sorted by pair')
+    print('#[rustfmt::skip]')
+    print('pub const COMPOSITIONS: &[(u32, u32, u32)] = &[')
+    print(rows(['(%s, %s, %s)' % tuple(map(hex_code, entry)) for entry in compositions], 4))
+    print('];')
+
+
+if __name__ == '__main__':
+    main()
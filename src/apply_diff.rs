@@ -0,0 +1,269 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufWriter, Write};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::atomic::PendingFile;
+use crate::claim_parser::parse_claim_filter;
+use crate::compression::{
+    create_compressed_writer, create_input_reader, determine_compression, finish_output,
+    INPUT_BUFFER_SIZE, OUTPUT_BUFFER_SIZE,
+};
+use crate::filter::EntityFilter;
+use crate::idset::IdSet;
+use crate::json::dump_array_elements;
+use crate::{http, FilterError};
+
+/// Arguments for the `apply-diff` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ApplyDiffArgs {
+    /// Filtered NDJSON extract to update
+    #[arg()]
+    extract: String,
+
+    /// Incremental dumps (JSON entities and deletion records), applied in order
+    #[arg(required = true)]
+    diffs: Vec<String>,
+
+    /// Updated extract; may be the extract itself, which is replaced once complete
+    #[arg(long)]
+    output: String,
+
+    /// Filter by claim (e.g., P31:Q5, P31:Q5,Q6256, P31:Q5&P18)
+    #[arg(short, long)]
+    claim: Option<String>,
+
+    /// Entity type to filter: item, property, or both
+    #[arg(short = 't', long, default_value = "both")]
+    r#type: String,
+
+    /// Keep only specified subject entity IDs (comma-separated, e.g., Q31,Q42)
+    #[arg(long)]
+    subject: Option<String>,
+
+    /// Filter languages for labels/descriptions (comma-separated, e.g., en,de,fr)
+    #[arg(short = 'l', long)]
+    languages: Option<String>,
+}
+
+/// The fields of a diff entry that decide what happens to the entity
+#[derive(Deserialize)]
+struct DiffEntry<'a> {
+    #[serde(borrow)]
+    id: std::borrow::Cow<'a, str>,
+    #[serde(default)]
+    deleted: bool,
+    /// Redirected to another entity, which replaces it
+    redirect: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct EntityId<'a> {
+    #[serde(borrow)]
+    id: std::borrow::Cow<'a, str>,
+}
+
+/// Newest state of every entity in the diffs: the output line if it matches
+/// the filter, None if it was deleted or no longer matches
+#[derive(Default)]
+struct Changes {
+    entities: HashMap<String, Option<String>>,
+    /// IDs in the order they first appear, for the entities added at the end
+    order: Vec<String>,
+}
+
+impl Changes {
+    /// Record one diff entry
+    fn apply(&mut self, entry: &str, filter: &EntityFilter) -> Result<(), FilterError> {
+        let diff: DiffEntry = serde_json::from_str(entry)?;
+        let line = if diff.deleted || diff.redirect.is_some() {
+            None
+        } else {
+            let entity: Value = serde_json::from_str(entry)?;
+            filter
+                .matches_json(&entity)
+                .then(|| serde_json::to_string(&filter.filter_json_entity(&entity)))
+                .transpose()?
+        };
+        if !self.entities.contains_key(diff.id.as_ref()) {
+            self.order.push(diff.id.to_string());
+        }
+        self.entities.insert(diff.id.into_owned(), line);
+        Ok(())
+    }
+
+    /// Read all entries of a diff file
+    fn read(&mut self, reader: impl BufRead, filter: &EntityFilter) -> Result<(), FilterError> {
+        for line in reader.lines() {
+            let line = line?;
+            for entry in dump_array_elements(&line) {
+                self.apply(entry, filter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What happened to the entities of the extract
+#[derive(Default, Debug, PartialEq)]
+struct Summary {
+    kept: u64,
+    replaced: u64,
+    removed: u64,
+    added: u64,
+}
+
+/// Copy `extract` to `output` with the changes applied; entities that are
+/// new to the extract are added at the end
+fn merge<R: BufRead, W: Write>(
+    mut extract: R,
+    changes: &Changes,
+    output: &mut W,
+) -> Result<Summary, FilterError> {
+    let mut summary = Summary::default();
+    let mut seen = HashSet::new();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if extract.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        let content = line.strip_suffix(b"\n").unwrap_or(&line);
+        if content.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let EntityId { id } = serde_json::from_slice(content)?;
+        match changes.entities.get(id.as_ref()) {
+            None => {
+                output.write_all(content)?;
+                output.write_all(b"\n")?;
+                summary.kept += 1;
+            }
+            Some(change) => {
+                seen.insert(id.into_owned());
+                match change {
+                    Some(new_line) => {
+                        output.write_all(new_line.as_bytes())?;
+                        output.write_all(b"\n")?;
+                        summary.replaced += 1;
+                    }
+                    None => summary.removed += 1,
+                }
+            }
+        }
+    }
+    for id in &changes.order {
+        if let (false, Some(Some(new_line))) = (seen.contains(id), changes.entities.get(id)) {
+            output.write_all(new_line.as_bytes())?;
+            output.write_all(b"\n")?;
+            summary.added += 1;
+        }
+    }
+    Ok(summary)
+}
+
+/// Run the `apply-diff` subcommand
+pub fn run(args: ApplyDiffArgs) -> Result<(), FilterError> {
+    let claim_filter = args.claim.as_deref().map(parse_claim_filter).transpose()?;
+    let split_ids =
+        |s: &String| -> HashSet<String> { s.split(',').map(|id| id.trim().to_string()).collect() };
+    let filter = Arc::new(EntityFilter {
+        claim_filter,
+        subject_filter: args.subject.as_ref().map(split_ids).map(IdSet::from),
+        language_filter: args.languages.as_ref().map(split_ids),
+        entity_type: args.r#type.clone(),
+        ..Default::default()
+    });
+
+    let mut changes = Changes::default();
+    for path in &args.diffs {
+        let (reader, _) = create_input_reader(path, "json", INPUT_BUFFER_SIZE)?;
+        changes.read(reader, &filter)?;
+    }
+    eprintln!(
+        "Read {} changed entities from {} diff files",
+        changes.entities.len(),
+        args.diffs.len()
+    );
+
+    let (extract, _) = create_input_reader(&args.extract, "json", INPUT_BUFFER_SIZE)?;
+    let mut pending = PendingFile::new(&args.output, false, http::DEFAULT_RETRIES)?;
+    let compression = determine_compression("none", Some(&args.output));
+    let mut output = BufWriter::with_capacity(
+        OUTPUT_BUFFER_SIZE,
+        create_compressed_writer(pending.create()?, &compression),
+    );
+    let summary = merge(extract, &changes, &mut output)?;
+    finish_output(output)?;
+    pending.commit()?;
+
+    eprintln!(
+        "Kept {}, replaced {}, removed {}, added {} entities in {}",
+        summary.kept, summary.replaced, summary.removed, summary.added, args.output
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_merge() {
+        let filter = EntityFilter {
+            claim_filter: Some(parse_claim_filter("P31:Q5").unwrap()),
+            ..Default::default()
+        };
+        let human = |id: &str, label: &str| {
+            format!(
+                r#"{{"id":"{}","type":"item","labels":{{"en":{{"language":"en","value":"{}"}}}},"claims":{{"P31":[{{"mainsnak":{{"snaktype":"value","property":"P31","datavalue":{{"value":{{"entity-type":"item","id":"Q5"}},"type":"wikibase-entityid"}}}}}}]}}}}"#,
+                id, label
+            )
+        };
+        let extract = format!(
+            "{}\n{}\n{}\n{}\n",
+            human("Q1", "one"),
+            human("Q2", "two"),
+            human("Q3", "three"),
+            human("Q4", "four")
+        );
+        // Q2 changes twice, Q3 is deleted, Q4 no longer matches, Q5 is new
+        let diff = format!(
+            "[\n{},\n{},\n{{\"id\":\"Q3\",\"deleted\":true}},\n{{\"id\":\"Q4\",\"type\":\"item\",\"claims\":{{}}}},\n{},\n{}\n]\n",
+            human("Q2", "two v2"),
+            human("Q5", "five"),
+            human("Q2", "two v3"),
+            human("Q6", "six").replace("Q5\"}", "Q6\"}")
+        );
+        let mut changes = Changes::default();
+        changes.read(io::Cursor::new(diff), &filter).unwrap();
+
+        let mut output = Vec::new();
+        let summary = merge(io::Cursor::new(extract), &changes, &mut output).unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                kept: 1,
+                replaced: 1,
+                removed: 2,
+                added: 1
+            }
+        );
+        let ids: Vec<String> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let entity: Value = serde_json::from_str(line).unwrap();
+                format!(
+                    "{} {}",
+                    entity["id"].as_str().unwrap(),
+                    entity["labels"]["en"]["value"].as_str().unwrap()
+                )
+            })
+            .collect();
+        assert_eq!(ids, ["Q1 one", "Q2 two v3", "Q5 five"]);
+    }
+}
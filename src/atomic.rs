@@ -0,0 +1,173 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::s3;
+
+/// Suffix of output files that are still being written
+const PENDING_SUFFIX: &str = ".tmp";
+
+/// Path an output is written to until it is complete
+///
+/// Special files such as /dev/null or named pipes are written directly, and
+/// object storage uploads only appear once completed.
+pub fn pending_path(path: &str) -> String {
+    if s3::is_object_url(path) {
+        return path.to_string();
+    }
+    match fs::metadata(path) {
+        Ok(metadata) if !metadata.is_file() => path.to_string(),
+        _ => format!("{}{}", path, PENDING_SUFFIX),
+    }
+}
+
+/// An output file written under a temporary name and renamed when complete
+///
+/// Dropping it before `commit` removes the partial file, so a failed run
+/// leaves nothing that looks like finished output. s3:// and gs:// outputs
+/// are multipart uploads, completed on `commit` and aborted otherwise.
+pub struct PendingFile {
+    path: String,
+    pending: String,
+    upload: Option<s3::Upload>,
+    no_clobber: bool,
+    /// Request retries of uploads (--http-retries)
+    retries: u32,
+    /// Remove the partial file when dropped
+    discard: bool,
+}
+
+impl PendingFile {
+    /// Prepare writing `path`; with `no_clobber`, an existing file is an error
+    pub fn new(path: &str, no_clobber: bool, retries: u32) -> io::Result<Self> {
+        let exists = match s3::is_object_url(path) {
+            true if no_clobber => s3::exists(path, retries)?,
+            true => false,
+            false => Path::new(path).exists(),
+        };
+        if no_clobber && exists {
+            return Err(clobber_error(path));
+        }
+        Ok(Self {
+            path: path.to_string(),
+            pending: pending_path(path),
+            upload: None,
+            no_clobber,
+            retries,
+            discard: true,
+        })
+    }
+
+    /// Keep the partial file if the run fails (checkpointed runs resume it)
+    pub fn keep_on_failure(mut self) -> Self {
+        self.discard = false;
+        self
+    }
+
+    /// Create the file at the temporary path, and its directory, or start the upload
+    pub fn create(&mut self) -> io::Result<Box<dyn Write>> {
+        if s3::is_object_url(&self.path) {
+            let upload = s3::Upload::start(&self.path, self.retries)?;
+            let writer = upload.writer();
+            self.upload = Some(upload);
+            return Ok(writer);
+        }
+        if let Some(dir) = Path::new(&self.pending).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(Box::new(fs::File::create(&self.pending)?))
+    }
+
+    /// Move the complete file to its path
+    pub fn commit(mut self) -> io::Result<()> {
+        self.discard = false;
+        if let Some(upload) = self.upload.take() {
+            return upload.complete();
+        }
+        if self.pending == self.path {
+            return Ok(());
+        }
+        if self.no_clobber {
+            // Linking fails if the path was created while the run was writing
+            fs::hard_link(&self.pending, &self.path).map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => clobber_error(&self.path),
+                _ => e,
+            })?;
+            fs::remove_file(&self.pending)
+        } else {
+            fs::rename(&self.pending, &self.path)
+        }
+    }
+
+    /// Leave the partial file under its temporary name (interrupted runs);
+    /// None if it was an upload, which is aborted
+    pub fn keep(mut self) -> Option<String> {
+        self.discard = false;
+        if let Some(upload) = self.upload.take() {
+            let _ = upload.abort();
+            return None;
+        }
+        Some(std::mem::take(&mut self.pending))
+    }
+
+    /// The final path of the output
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Drop for PendingFile {
+    fn drop(&mut self) {
+        if let Some(upload) = self.upload.take() {
+            if self.discard {
+                let _ = upload.abort();
+            }
+        } else if self.discard && self.pending != self.path {
+            let _ = fs::remove_file(&self.pending);
+        }
+    }
+}
+
+fn clobber_error(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} already exists (--no-clobber)", path),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_commit_and_discard() {
+        let dir = TempDir::new("atomic");
+        let path = dir.file("out.nt");
+
+        let mut pending = PendingFile::new(&path, false, 0).unwrap();
+        pending.create().unwrap().write_all(b"partial").unwrap();
+        assert!(Path::new(&format!("{}.tmp", path)).exists());
+        drop(pending);
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+        assert!(!Path::new(&path).exists());
+
+        let mut pending = PendingFile::new(&path, false, 0).unwrap();
+        pending.create().unwrap().write_all(b"complete").unwrap();
+        pending.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "complete");
+
+        assert!(PendingFile::new(&path, true, 0).is_err());
+        let mut pending = PendingFile::new(&path, false, 0).unwrap();
+        pending.create().unwrap();
+        assert_eq!(pending.keep(), Some(format!("{}.tmp", path)));
+        assert!(Path::new(&format!("{}.tmp", path)).exists());
+    }
+
+    #[test]
+    fn test_special_files_are_written_directly() {
+        assert_eq!(pending_path("/dev/null"), "/dev/null");
+        let pending = PendingFile::new("/dev/null", false, 0).unwrap();
+        pending.commit().unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+
+use flate2::read::GzDecoder;
+
+use crate::http;
+
+/// Start of a BGZF block: decompressed and stored offset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Block {
+    start: u64,
+    stored: u64,
+}
+
+/// Blocks read so far, to map decompressed offsets back to seekable positions
+#[derive(Debug, Clone, Default)]
+pub struct BlockIndex(Arc<Mutex<VecDeque<Block>>>);
+
+impl BlockIndex {
+    fn push(&self, block: Block) {
+        self.0
+            .lock()
+            .expect("block index poisoned")
+            .push_back(block);
+    }
+
+    /// Stored offset of the block holding decompressed offset `offset`, and
+    /// the number of bytes of that block before it
+    ///
+    /// Offsets are expected in increasing order; earlier blocks are dropped.
+    pub fn locate(&self, offset: u64) -> Option<(u64, u64)> {
+        let mut blocks = self.0.lock().expect("block index poisoned");
+        while blocks.len() > 1 && blocks[1].start <= offset {
+            blocks.pop_front();
+        }
+        blocks
+            .front()
+            .filter(|block| block.start <= offset)
+            .map(|block| (block.stored, offset - block.start))
+    }
+}
+
+/// Whether a file or URL starts with a BGZF block (blocked gzip as written by `bgzip`)
+pub fn is_bgzf(path: &str, retries: u32) -> io::Result<bool> {
+    let mut header = [0u8; 18];
+    let mut file = http::open_source(path, 0, retries)?;
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(block_size(&header).is_some()),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Total size of the block starting with this gzip header, if it is a BGZF block
+fn block_size(header: &[u8; 18]) -> Option<usize> {
+    let is_bgzf = header[..4] == [0x1f, 0x8b, 8, 4]
+        && u16::from_le_bytes([header[10], header[11]]) >= 6
+        && header[12..16] == [b'B', b'C', 2, 0];
+    is_bgzf.then(|| u16::from_le_bytes([header[16], header[17]]) as usize + 1)
+}
+
+/// Decompresses BGZF block by block, recording where each block starts
+pub struct BgzfReader<R> {
+    inner: R,
+    index: BlockIndex,
+    /// Stored and decompressed offset of the next block
+    next: Block,
+    block: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> BgzfReader<R> {
+    /// Read blocks from `inner`, which is positioned at the block `next`
+    pub fn new(inner: R, stored: u64, start: u64, index: BlockIndex) -> Self {
+        Self {
+            inner,
+            index,
+            next: Block { start, stored },
+            block: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Load the next non-empty block; returns false at the end of the input
+    fn read_block(&mut self) -> io::Result<bool> {
+        loop {
+            let mut header = [0u8; 18];
+            let read = read_full(&mut self.inner, &mut header)?;
+            if read == 0 {
+                return Ok(false);
+            }
+            let size = match block_size(&header) {
+                Some(size) if read == header.len() && size >= 26 => size,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid BGZF block at byte {}", self.next.stored),
+                    ))
+                }
+            };
+            let mut compressed = header.to_vec();
+            compressed.resize(size, 0);
+            self.inner.read_exact(&mut compressed[header.len()..])?;
+
+            self.block.clear();
+            GzDecoder::new(&compressed[..]).read_to_end(&mut self.block)?;
+            self.pos = 0;
+
+            let block = self.next;
+            self.next = Block {
+                start: block.start + self.block.len() as u64,
+                stored: block.stored + size as u64,
+            };
+            // The empty end-of-file block holds no offsets
+            if !self.block.is_empty() {
+                self.index.push(block);
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Like `read_exact`, but returns the number of bytes read at end of input
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.block.len() && !self.read_block()? {
+            return Ok(0);
+        }
+        let n = buf.len().min(self.block.len() - self.pos);
+        buf[..n].copy_from_slice(&self.block[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    /// Compress `data` into BGZF blocks of at most `block_len` bytes
+    fn bgzf(data: &[u8], block_len: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        let chunks = data.chunks(block_len).chain(std::iter::once(&[][..]));
+        for chunk in chunks {
+            let mut encoder = flate2::GzBuilder::new()
+                .extra(vec![b'B', b'C', 2, 0, 0, 0])
+                .write(Vec::new(), flate2::Compression::default());
+            encoder.write_all(chunk).unwrap();
+            let mut block = GzEncoder::finish(encoder).unwrap();
+            let size = (block.len() - 1) as u16;
+            block[16..18].copy_from_slice(&size.to_le_bytes());
+            out.extend(block);
+        }
+        out
+    }
+
+    #[test]
+    fn test_read_and_locate_blocks() {
+        let data: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let compressed = bgzf(&data, 300);
+        let index = BlockIndex::default();
+        let mut decoded = Vec::new();
+        BgzfReader::new(&compressed[..], 0, 0, index.clone())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let (stored, skip) = index.locate(650).unwrap();
+        assert_eq!(skip, 50);
+        let mut resumed = Vec::new();
+        BgzfReader::new(&compressed[stored as usize..], stored, 600, index.clone())
+            .read_to_end(&mut resumed)
+            .unwrap();
+        assert_eq!(resumed, data[600..]);
+    }
+
+    #[test]
+    fn test_block_size_rejects_plain_gzip() {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"not blocked").unwrap();
+        let plain = encoder.finish().unwrap();
+        assert_eq!(block_size(plain[..18].try_into().unwrap()), None);
+        let blocked = bgzf(b"blocked", 100);
+        assert_eq!(
+            block_size(blocked[..18].try_into().unwrap()),
+            Some(blocked.len() - 28)
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use serde_json::{json, Value};
+
+/// Index and document ID templates for Elasticsearch/OpenSearch `_bulk` output
+///
+/// Templates may use the placeholders `{id}` and `{type}`, e.g. `wikidata-{type}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BulkTemplate {
+    pub index: String,
+    pub id: String,
+}
+
+impl Default for BulkTemplate {
+    fn default() -> Self {
+        Self {
+            index: "wikidata".to_string(),
+            id: "{id}".to_string(),
+        }
+    }
+}
+
+impl BulkTemplate {
+    /// Expand a template with the entity's ID and type
+    fn expand(template: &str, entity: &Value) -> String {
+        let field = |name: &str| {
+            entity
+                .get(name)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+        };
+        template
+            .replace("{id}", field("id"))
+            .replace("{type}", field("type"))
+    }
+
+    /// Append the action and source lines for one entity to the buffer
+    pub fn push_entity(&self, buffer: &mut String, entity: &Value) {
+        let action = json!({
+            "index": {
+                "_index": Self::expand(&self.index, entity),
+                "_id": Self::expand(&self.id, entity),
+            }
+        });
+        buffer.push_str(&action.to_string());
+        buffer.push('\n');
+        buffer.push_str(&entity.to_string());
+        buffer.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_entity() {
+        let entity = json!({"id": "Q64", "type": "item", "labels": {"en": {"language": "en", "value": "Berlin"}}});
+        let mut buffer = String::new();
+        BulkTemplate::default().push_entity(&mut buffer, &entity);
+
+        let lines: Vec<&str> = buffer.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], r#"{"index":{"_id":"Q64","_index":"wikidata"}}"#);
+        assert_eq!(serde_json::from_str::<Value>(lines[1]).unwrap(), entity);
+    }
+
+    #[test]
+    fn test_templates() {
+        let entity = json!({"id": "P31", "type": "property"});
+        let template = BulkTemplate {
+            index: "wd-{type}".to_string(),
+            id: "wikidata:{id}".to_string(),
+        };
+        let mut buffer = String::new();
+        template.push_entity(&mut buffer, &entity);
+        assert!(buffer.starts_with(r#"{"index":{"_id":"wikidata:P31","_index":"wd-property"}}"#));
+    }
+}
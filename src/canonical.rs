@@ -0,0 +1,122 @@
+use serde_json::Value;
+
+use crate::rdf::RdfEntity;
+
+/// Sort key for array elements without a natural identifier
+fn serialized(value: &Value) -> String {
+    value.to_string()
+}
+
+/// Sort an array of objects by a string field, falling back to the serialized value
+fn sort_by_field(values: &mut [Value], field: &str) {
+    values.sort_by_cached_key(|v| match v.get(field).and_then(|f| f.as_str()) {
+        Some(key) => (0, key.to_string()),
+        None => (1, serialized(v)),
+    });
+}
+
+/// Sort the references of a statement by hash
+///
+/// Qualifiers and reference snaks keep their order, as do `qualifiers-order`
+/// and `snaks-order`: those record the order editors gave the properties.
+fn canonicalize_statement(statement: &mut Value) {
+    if let Some(references) = statement
+        .get_mut("references")
+        .and_then(|r| r.as_array_mut())
+    {
+        sort_by_field(references, "hash");
+    }
+}
+
+/// Bring a JSON entity into a deterministic form for diffing
+///
+/// Object keys are already sorted by serde_json's map. Arrays whose order
+/// depends on edit history are sorted: statements by ID, references by hash,
+/// aliases by value, and sitelink badges.
+pub fn canonicalize_json(entity: &mut Value) {
+    if let Some(claims) = entity.get_mut("claims").and_then(|c| c.as_object_mut()) {
+        for statements in claims.values_mut() {
+            if let Some(arr) = statements.as_array_mut() {
+                for statement in arr.iter_mut() {
+                    canonicalize_statement(statement);
+                }
+                sort_by_field(arr, "id");
+            }
+        }
+    }
+
+    if let Some(aliases) = entity.get_mut("aliases").and_then(|a| a.as_object_mut()) {
+        for values in aliases.values_mut() {
+            if let Some(arr) = values.as_array_mut() {
+                sort_by_field(arr, "value");
+            }
+        }
+    }
+
+    if let Some(sitelinks) = entity.get_mut("sitelinks").and_then(|s| s.as_object_mut()) {
+        for sitelink in sitelinks.values_mut() {
+            if let Some(badges) = sitelink.get_mut("badges").and_then(|b| b.as_array_mut()) {
+                badges.sort_by_cached_key(serialized);
+            }
+        }
+    }
+}
+
+/// Bring an RDF entity into a deterministic form for diffing
+pub fn canonicalize_rdf_entity(entity: &mut RdfEntity) {
+    entity.metadata.sort();
+    entity.triples.sort();
+    for values in entity.aliases.values_mut() {
+        values.sort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_canonicalize_json() {
+        let mut a = json!({
+            "id": "Q1",
+            "aliases": {"en": [{"language": "en", "value": "b"}, {"language": "en", "value": "a"}]},
+            "claims": {"P31": [
+                {"id": "Q1$2", "mainsnak": {}, "references": [{"hash": "y"}, {"hash": "x"}]},
+                {"id": "Q1$1", "mainsnak": {}, "qualifiers-order": ["P2", "P1"]}
+            ]},
+            "sitelinks": {"enwiki": {"site": "enwiki", "title": "X", "badges": ["Q2", "Q1"]}}
+        });
+        let mut b = json!({
+            "sitelinks": {"enwiki": {"badges": ["Q1", "Q2"], "title": "X", "site": "enwiki"}},
+            "claims": {"P31": [
+                {"id": "Q1$1", "mainsnak": {}, "qualifiers-order": ["P1", "P2"]},
+                {"id": "Q1$2", "mainsnak": {}, "references": [{"hash": "x"}, {"hash": "y"}]}
+            ]},
+            "aliases": {"en": [{"language": "en", "value": "a"}, {"language": "en", "value": "b"}]},
+            "id": "Q1"
+        });
+        canonicalize_json(&mut a);
+        canonicalize_json(&mut b);
+        assert_eq!(a["claims"]["P31"][0]["id"], "Q1$1");
+        // The order of the qualifier properties is kept
+        assert_eq!(
+            a["claims"]["P31"][0]["qualifiers-order"],
+            json!(["P2", "P1"])
+        );
+        b["claims"]["P31"][0]["qualifiers-order"] = json!(["P2", "P1"]);
+        assert_eq!(a.to_string(), b.to_string());
+    }
+
+    #[test]
+    fn test_canonicalize_rdf_entity() {
+        let mut entity = RdfEntity::new("Q1");
+        entity.triples = vec!["<b> <p> <o> .".to_string(), "<a> <p> <o> .".to_string()];
+        entity
+            .aliases
+            .insert("en".to_string(), vec!["y".to_string(), "x".to_string()]);
+        canonicalize_rdf_entity(&mut entity);
+        assert_eq!(entity.triples[0], "<a> <p> <o> .");
+        assert_eq!(entity.aliases["en"], vec!["x", "y"]);
+    }
+}
@@ -0,0 +1,343 @@
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bgzf::{BgzfReader, BlockIndex};
+use crate::compression::MemberBoundary;
+use crate::http;
+use crate::rdf::{OutputFormat, ProcessingOptions};
+use crate::s3;
+use crate::stats::Counting;
+use crate::tar;
+use crate::FilterError;
+
+/// How often `--checkpoint` saves progress
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Progress of a run, saved by `--checkpoint` and read by `--resume`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub input: String,
+    pub output: String,
+    /// Input lines whose entities are completely written
+    pub lines: u64,
+    /// Offset of the next line in the decompressed input
+    pub offset: u64,
+    /// Stored byte offset to seek to (for BGZF, the start of the block holding `offset`)
+    pub seek: u64,
+    /// Decompressed bytes to discard after seeking
+    pub skip: u64,
+    /// Output length at the time of the checkpoint; anything after it is truncated
+    pub output_length: u64,
+    /// Set once the run finished and its output was committed, so there is
+    /// nothing left to resume
+    #[serde(default)]
+    pub complete: bool,
+}
+
+impl Checkpoint {
+    pub fn load(path: &str) -> Result<Self, FilterError> {
+        let checkpoint = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&checkpoint)?)
+    }
+
+    /// Replace the checkpoint file atomically, so a crash leaves the previous one
+    fn save(&self, path: &str) -> io::Result<()> {
+        let temp = format!("{}.tmp", path);
+        std::fs::write(&temp, serde_json::to_string_pretty(self)? + "\n")?;
+        std::fs::rename(temp, path)
+    }
+
+    /// Mark the checkpoint at `path` complete after the output is committed;
+    /// a run that never saved one has nothing to mark
+    pub fn mark_complete(path: &str) -> Result<(), FilterError> {
+        if !std::path::Path::new(path).exists() {
+            return Ok(());
+        }
+        let mut checkpoint = Self::load(path)?;
+        checkpoint.complete = true;
+        Ok(checkpoint.save(path)?)
+    }
+
+    /// Check that a checkpoint belongs to this input and output
+    pub fn check_paths(&self, input: &str, output: &str) -> Result<(), FilterError> {
+        if self.input != input || self.output != output {
+            return Err(FilterError::Parse(format!(
+                "Checkpoint is for {} -> {}, not {} -> {}",
+                self.input, self.output, input, output
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Where and how often to save checkpoints
+#[derive(Debug, Clone)]
+pub struct CheckpointConfig {
+    pub path: String,
+    pub input: String,
+    pub output: String,
+    pub interval: Duration,
+    /// Block positions of BGZF input
+    pub blocks: Option<BlockIndex>,
+    /// Ends the member of compressed --append output at each checkpoint
+    pub member_boundary: MemberBoundary,
+}
+
+/// Saves checkpoints from the writer as batches are written in input order
+pub struct Checkpointer {
+    config: CheckpointConfig,
+    last_saved: Instant,
+    /// Line number and byte offset of the first input line not yet written
+    position: Option<(u64, u64)>,
+}
+
+impl Checkpointer {
+    pub fn new(config: CheckpointConfig) -> Self {
+        Self {
+            config,
+            last_saved: Instant::now(),
+            position: None,
+        }
+    }
+
+    /// Note that the input before `position` is written, saving if the interval passed
+    pub fn record<W: Write>(&mut self, output: &mut W, position: (u64, u64)) -> io::Result<()> {
+        self.position = Some(position);
+        if self.last_saved.elapsed() >= self.config.interval {
+            self.save(output)?;
+        }
+        Ok(())
+    }
+
+    /// Save the final position at the end of the run
+    pub fn finish<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        self.save(output)
+    }
+
+    fn save<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        let (line, offset) = match self.position {
+            Some(position) => position,
+            None => return Ok(()),
+        };
+        let (seek, skip) = match self.config.blocks {
+            Some(ref blocks) => blocks.locate(offset).unwrap_or((0, offset)),
+            None => (offset, 0),
+        };
+        self.config.member_boundary.request();
+        output.flush()?;
+        let checkpoint = Checkpoint {
+            input: self.config.input.clone(),
+            output: self.config.output.clone(),
+            lines: line - 1,
+            offset,
+            seek,
+            skip,
+            output_length: std::fs::metadata(&self.config.output)?.len(),
+            complete: false,
+        };
+        checkpoint.save(&self.config.path)?;
+        self.last_saved = Instant::now();
+        Ok(())
+    }
+}
+
+/// Open the input file or URL for a checkpointed run, positioned at `resume` if given
+///
+/// BGZF input is decompressed block by block, recording block starts in `blocks`.
+pub fn open_input(
+    path: &str,
+    resume: Option<&Checkpoint>,
+    blocks: Option<&BlockIndex>,
+    bytes_read: Arc<AtomicU64>,
+    buffer_size: usize,
+    retries: u32,
+) -> io::Result<Box<dyn BufRead + Send>> {
+    let (seek, skip, offset) = resume.map_or((0, 0, 0), |c| (c.seek, c.skip, c.offset));
+    // URLs continue with a Range request from the stored offset
+    let source = Counting::new(http::open_source(path, seek, retries)?, bytes_read);
+    let mut reader: Box<dyn BufRead + Send> = match blocks {
+        Some(blocks) => {
+            // Blocks are at most 64 KiB, read in pieces of `buffer_size`
+            let source = BufReader::with_capacity(buffer_size, source);
+            let decoder = BgzfReader::new(source, seek, offset - skip, blocks.clone());
+            Box::new(BufReader::with_capacity(buffer_size, decoder))
+        }
+        None => Box::new(BufReader::with_capacity(buffer_size, source)),
+    };
+    io::copy(&mut (&mut reader).take(skip), &mut io::sink())?;
+    Ok(reader)
+}
+
+/// Open the output of a resumed run, dropping anything written after the checkpoint
+pub fn open_output(checkpoint: &Checkpoint) -> io::Result<std::fs::File> {
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(&checkpoint.output)?;
+    if file.metadata()?.len() < checkpoint.output_length {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "{} is shorter than the checkpoint's {} bytes",
+                checkpoint.output, checkpoint.output_length
+            ),
+        ));
+    }
+    file.set_len(checkpoint.output_length)?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(file)
+}
+
+/// Whether the output format has a header or footer around the whole stream,
+/// so that output cannot be cut or continued
+pub fn framed_output(options: &ProcessingOptions) -> bool {
+    match options.output_format {
+        OutputFormat::Arrow | OutputFormat::GraphMl => true,
+        OutputFormat::Json => options.json_style != crate::json::JsonStyle::NdJson,
+        _ => false,
+    }
+}
+
+/// Why `--checkpoint`/`--resume` cannot be used for a run, if it cannot
+///
+/// Compressed output needs `append`, which writes it as members that end at
+/// every checkpoint.
+pub fn unsupported_reason(
+    input: Option<&str>,
+    output: Option<&str>,
+    format: &str,
+    compression: &str,
+    append: bool,
+    options: &ProcessingOptions,
+) -> Option<&'static str> {
+    let input = match input {
+        Some(input) => input.to_lowercase(),
+        None => return Some("needs an input file"),
+    };
+    match output {
+        None => return Some("needs an --output file"),
+        Some(output) if s3::is_object_url(output) => return Some("needs a local --output file"),
+        Some(_) => {}
+    }
+    if input.ends_with(".bz2") || input.ends_with(".lz4") {
+        return Some("needs uncompressed or BGZF input");
+    }
+    if tar::is_tar(&input) {
+        return Some("does not support tar archives");
+    }
+    if !matches!(format, "rdf" | "ntriples" | "nt" | "json" | "ndjson") {
+        return Some("only applies to N-Triples and JSON input");
+    }
+    if compression != "none" && !append {
+        return Some("needs uncompressed output, or --append for gzip/lz4 output");
+    }
+    if framed_output(options) {
+        return Some("does not support arrow, graphml or array-style JSON output");
+    }
+    if options.skip_lines > 0 || options.skip_entities > 0 {
+        return Some("does not support --skip-lines/--skip-entities");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn config(dir: &std::path::Path) -> CheckpointConfig {
+        CheckpointConfig {
+            path: dir.join("run.checkpoint").to_string_lossy().into_owned(),
+            input: "dump.nt".to_string(),
+            output: dir.join("out.nt").to_string_lossy().into_owned(),
+            interval: Duration::from_secs(3600),
+            blocks: None,
+            member_boundary: MemberBoundary::default(),
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_records_flushed_output() {
+        let dir = TempDir::new("checkpoint");
+        let config = config(dir.path());
+        let file = std::fs::File::create(&config.output).unwrap();
+        let mut output = io::BufWriter::new(file);
+
+        let mut checkpointer = Checkpointer::new(config.clone());
+        output.write_all(b"first entity\n").unwrap();
+        checkpointer.record(&mut output, (3, 120)).unwrap();
+        // Not saved before the interval passed
+        assert!(!std::path::Path::new(&config.path).exists());
+        checkpointer.finish(&mut output).unwrap();
+
+        let checkpoint = Checkpoint::load(&config.path).unwrap();
+        assert_eq!(checkpoint.lines, 2);
+        assert_eq!(
+            (checkpoint.offset, checkpoint.seek, checkpoint.skip),
+            (120, 120, 0)
+        );
+        assert_eq!(checkpoint.output_length, 13);
+        assert!(checkpoint.check_paths("dump.nt", &config.output).is_ok());
+        assert!(checkpoint.check_paths("other.nt", &config.output).is_err());
+    }
+
+    #[test]
+    fn test_finished_run_is_complete() {
+        let dir = TempDir::new("checkpoint-complete");
+        let final_path = dir.file("out.nt");
+        let mut config = config(dir.path());
+        config.output = crate::atomic::pending_path(&final_path);
+        let mut pending = crate::atomic::PendingFile::new(&final_path, false, 0)
+            .unwrap()
+            .keep_on_failure();
+        let mut output = io::BufWriter::new(pending.create().unwrap());
+
+        let mut checkpointer = Checkpointer::new(config.clone());
+        output.write_all(b"entity\n").unwrap();
+        checkpointer.record(&mut output, (2, 50)).unwrap();
+        checkpointer.finish(&mut output).unwrap();
+        drop(output);
+        pending.commit().unwrap();
+        Checkpoint::mark_complete(&config.path).unwrap();
+
+        // The temporary output is gone, and resuming has nothing left to do
+        assert!(!std::path::Path::new(&config.output).exists());
+        let checkpoint = Checkpoint::load(&config.path).unwrap();
+        assert!(checkpoint.complete);
+        assert_eq!(checkpoint.output_length, 7);
+
+        // Checkpoints of earlier versions have no flag and are not complete
+        let mut value = serde_json::to_value(&checkpoint).unwrap();
+        value.as_object_mut().unwrap().remove("complete");
+        let old: Checkpoint = serde_json::from_value(value).unwrap();
+        assert!(!old.complete);
+    }
+
+    #[test]
+    fn test_unsupported_reason() {
+        let options = ProcessingOptions::default();
+        let reason = |input, output, compression| {
+            unsupported_reason(input, output, "rdf", compression, false, &options)
+        };
+        assert_eq!(reason(Some("dump.nt"), Some("out.nt"), "none"), None);
+        assert_eq!(reason(Some("dump.nt.gz"), Some("out.nt"), "none"), None);
+        assert!(reason(Some("dump.nt.bz2"), Some("out.nt"), "none").is_some());
+        assert!(reason(Some("dumps.tar"), Some("out.nt"), "none").is_some());
+        assert!(reason(Some("dump.nt"), Some("s3://b/out.nt"), "none").is_some());
+        assert!(reason(Some("dump.nt"), Some("out.nt.gz"), "gzip").is_some());
+        let append = unsupported_reason(
+            Some("dump.nt"),
+            Some("out.nt.gz"),
+            "rdf",
+            "gzip",
+            true,
+            &options,
+        );
+        assert_eq!(append, None);
+        assert!(reason(None, Some("out.nt"), "none").is_some());
+        assert!(reason(Some("dump.nt"), None, "none").is_some());
+    }
+}
@@ -1,8 +1,13 @@
+use std::borrow::Cow;
 use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 
-use crate::filter::ClaimFilter;
+use crate::filter::{read_id_file, ClaimFilter};
+use crate::hierarchy::ClaimIndex;
+use crate::statement::{
+    Comparison, Literal, Pattern, RankSet, SnakCondition, StatementFilter, ValueTest,
+};
 use crate::FilterError;
 
 /// Parse a claim filter string like "P31:Q5,Q6256&P18|P279:Q5"
@@ -15,10 +20,45 @@ use crate::FilterError;
 /// - P31:Q5|P279:Q5 - P31:Q5 OR P279:Q5
 /// - ~P31:Q5 - NOT P31:Q5
 /// - P31:Q5&~P18 - P31:Q5 AND NOT P18
+/// - P31:Q5* or P31:<Q5 - P31 has Q5 or a transitive subclass of it
+/// - P31/P279:Q5 - P31 has a value whose P279 is Q5
+/// - P106:@occupations.txt - P106 has one of the IDs in the file (one per line)
+/// - P1082>1000000 - P1082 has a quantity (or date) greater than 1000000;
+///   also <, <=, = and >=, and `P2046<=50 Q712226` asks for the unit too
+/// - P569:1900..1950 - P569 has a date from 1900 to 1950 (or a number in a
+///   range), compared at the coarser precision of both sides; `1900..` and
+///   `..1950` leave one end open, and P569:1952 asks for the year (or date)
+/// - P212:"978-3-.*" - P212 has a string (or external ID, URL) the regular
+///   expression matches as a whole; P856~"\.de/" matches anywhere in it
+/// - P1343:* - P1343 has an entity value (P1343 alone also matches literals)
+/// - P31:Q! - P31 has an item value (P! for properties, L! for lexemes)
+/// - P570:somevalue - P570 has an unknown value (novalue: explicitly none)
+/// - P39:Q11696{P580>=1990} - a P39 statement of Q11696 with a start time
+///   (P580) qualifier in or after 1990; {P642:Q5} and {P580} test a
+///   qualifier's values or presence, and several groups must all hold
+/// - P569[ref:P248:Q36578] - a P569 statement with a reference stated in
+///   (P248) Q36578; [ref] alone asks for any reference
+/// - P2048:*[unit:Q11573] - a P2048 quantity in metres (Q11573);
+///   [unit:Q11573,Q174728] allows several units, [unit:1] none
+/// - P1082@preferred - a preferred P1082 statement (ranks: preferred, normal,
+///   deprecated, comma-separated; preferred and normal if not given)
+/// - (P31:Q5|P31:Q6256)&P18 - parentheses group subexpressions
+/// - ~(P31:Q5&P106:Q82955) - NOT applies to the group that follows
 ///
-/// Precedence: | (OR) has lower precedence than & (AND)
-/// So "A&B|C" means "A AND (B OR C)"
+/// Precedence: ~ (NOT) binds tightest, then & (AND), then | (OR),
+/// so "A&B|~C" means "(A AND B) OR (NOT C)"
+///
+/// Transitive values and property paths need a `ClaimIndex` and are
+/// rejected here, see `parse_claim_expression`.
 pub fn parse_claim_filter(input: &str) -> Result<ClaimFilter, FilterError> {
+    let mut filter = parse_claim_expression(input)?;
+    filter.resolve(&ClaimIndex::default())?;
+    Ok(filter)
+}
+
+/// Parse a claim filter without resolving transitive values and property
+/// paths, for `ClaimFilter::resolve`
+pub fn parse_claim_expression(input: &str) -> Result<ClaimFilter, FilterError> {
     let input = input.trim();
 
     // Check if input is a file path
@@ -31,88 +71,461 @@ pub fn parse_claim_filter(input: &str) -> Result<ClaimFilter, FilterError> {
         input.to_string()
     };
 
-    parse_or_expression(&claim_str)
+    Parser::parse(&claim_str)
 }
 
-/// Parse OR expressions (lowest precedence)
-fn parse_or_expression(input: &str) -> Result<ClaimFilter, FilterError> {
-    let parts = split_top_level(input, '|');
+/// Recursive-descent parser over a claim expression
+///
+/// Grammar, from lowest to highest precedence:
+///
+/// ```text
+/// or    := and ('|' and)*
+/// and   := unary ('&' unary)*
+/// unary := '~' unary | '(' or ')' | atom
+/// atom  := property filter up to the next | & ~ ( or )
+/// ```
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
 
-    if parts.len() == 1 {
-        return parse_and_expression(&parts[0]);
+impl<'a> Parser<'a> {
+    /// Parse the whole input as one expression
+    fn parse(input: &'a str) -> Result<ClaimFilter, FilterError> {
+        let mut parser = Parser { input, pos: 0 };
+        let filter = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some(')') => Err(parser.error("Unmatched ')'")),
+            Some(_) => Err(parser.error("Expected '&', '|' or the end")),
+        }
     }
 
-    let mut filters = Vec::new();
-    for part in parts {
-        filters.push(parse_and_expression(&part)?);
+    /// Next character after whitespace, without consuming it
+    fn peek(&mut self) -> Option<char> {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+        self.input[self.pos..].chars().next()
     }
 
-    Ok(ClaimFilter::Or(filters))
-}
+    fn error(&self, message: &str) -> FilterError {
+        FilterError::InvalidClaim(format!(
+            "{} at position {} in '{}'",
+            message,
+            self.pos + 1,
+            self.input
+        ))
+    }
 
-/// Parse AND expressions
-fn parse_and_expression(input: &str) -> Result<ClaimFilter, FilterError> {
-    let parts = split_top_level(input, '&');
+    fn parse_or(&mut self) -> Result<ClaimFilter, FilterError> {
+        let mut filters = vec![self.parse_and()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            filters.push(self.parse_and()?);
+        }
+        Ok(if filters.len() == 1 {
+            filters.pop().unwrap()
+        } else {
+            ClaimFilter::Or(filters)
+        })
+    }
 
-    if parts.len() == 1 {
-        return parse_atomic(&parts[0]);
+    fn parse_and(&mut self) -> Result<ClaimFilter, FilterError> {
+        let mut filters = vec![self.parse_unary()?];
+        while self.peek() == Some('&') {
+            self.pos += 1;
+            filters.push(self.parse_unary()?);
+        }
+        Ok(if filters.len() == 1 {
+            filters.pop().unwrap()
+        } else {
+            ClaimFilter::And(filters)
+        })
     }
 
-    let mut filters = Vec::new();
-    for part in parts {
-        filters.push(parse_atomic(&part)?);
+    fn parse_unary(&mut self) -> Result<ClaimFilter, FilterError> {
+        match self.peek() {
+            Some('~') => {
+                self.pos += 1;
+                Ok(ClaimFilter::Not(Box::new(self.parse_unary()?)))
+            }
+            Some('(') => {
+                self.pos += 1;
+                let filter = self.parse_or()?;
+                if self.peek() != Some(')') {
+                    return Err(self.error("Expected ')'"));
+                }
+                self.pos += 1;
+                Ok(filter)
+            }
+            _ => self.parse_atom(),
+        }
     }
 
-    Ok(ClaimFilter::And(filters))
+    fn parse_atom(&mut self) -> Result<ClaimFilter, FilterError> {
+        let rest = &self.input[self.pos..];
+        // Operators inside {qualifier} and [ref] conditions and "patterns" do
+        // not end the atom, nor does ~ before a pattern
+        let mut depth = 0;
+        let (mut quoted, mut escaped) = (false, false);
+        let len = rest
+            .char_indices()
+            .find(|&(i, c)| {
+                if quoted {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => quoted = false,
+                        _ => {}
+                    }
+                    return false;
+                }
+                match c {
+                    '"' => {
+                        quoted = true;
+                        false
+                    }
+                    '{' | '[' => {
+                        depth += 1;
+                        false
+                    }
+                    '}' | ']' => {
+                        depth -= 1;
+                        false
+                    }
+                    '~' => depth == 0 && !rest[i + 1..].trim_start().starts_with('"'),
+                    '|' | '&' | '(' | ')' => depth == 0,
+                    _ => false,
+                }
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        if rest[..len].trim().is_empty() {
+            return Err(self.error("Expected a property"));
+        }
+        if quoted {
+            return Err(self.error("Expected '\"'"));
+        }
+        if depth > 0 {
+            return Err(self.error("Expected '}' or ']'"));
+        }
+        self.pos += len;
+        parse_property_filter(&rest[..len])
+    }
 }
 
-/// Parse atomic expressions (possibly negated)
-fn parse_atomic(input: &str) -> Result<ClaimFilter, FilterError> {
+/// Parse a single property filter like "P31:Q5,Q6256" or "P18", optionally
+/// with ranks like "P1082@preferred" and followed by qualifier conditions
+/// like "{P580>=1990}", reference conditions like "[ref:P248:Q36578]" and
+/// units like "[unit:Q11573]"
+fn parse_property_filter(input: &str) -> Result<ClaimFilter, FilterError> {
     let input = input.trim();
+    // Ranks follow the property, before any values: P31@preferred:Q5
+    let head_end =
+        find_unquoted(input, &[':', '<', '>', '=', '~', '{', '[']).unwrap_or(input.len());
+    let (input, ranks) = match input[..head_end].split_once('@') {
+        Some((property, ranks)) => (
+            Cow::Owned(format!("{}{}", property, &input[head_end..])),
+            Some(RankSet::parse(ranks)?),
+        ),
+        None => (Cow::Borrowed(input), None),
+    };
+    let start = find_unquoted(&input, &['{', '[']).unwrap_or(input.len());
+    let filter = parse_value_filter(&input[..start])?;
+    if start == input.len() && ranks.is_none() {
+        return Ok(filter);
+    }
+    let mut statement = filter.into_statement().ok_or_else(|| {
+        FilterError::InvalidClaim(format!(
+            "Ranks, qualifier, reference and unit conditions are not supported with transitive values or property paths: {}",
+            input
+        ))
+    })?;
+    statement.ranks = ranks;
+    parse_statement_conditions(&input[start..], &mut statement)?;
+    // P2048:*[unit:Q11573] asks for any quantity in the unit, not an entity
+    if !statement.units.is_empty() && matches!(statement.value, ValueTest::AnyEntity(None)) {
+        statement.value = ValueTest::Any;
+    }
+    Ok(ClaimFilter::Statement(statement))
+}
 
-    // Handle NOT operator
-    if input.starts_with('~') {
-        let inner = &input[1..];
-        return Ok(ClaimFilter::Not(Box::new(parse_atomic(inner)?)));
+/// Parse "{P580>=1990}{P642:Q5}[ref:P248:Q36578][unit:Q11573]": conditions
+/// one statement's qualifiers, references and unit must all meet
+fn parse_statement_conditions(
+    input: &str,
+    statement: &mut StatementFilter,
+) -> Result<(), FilterError> {
+    let mut rest = input.trim();
+    while !rest.is_empty() {
+        let group = |open: char, close: char| {
+            let inner = rest.strip_prefix(open)?;
+            let end = find_unquoted(inner, &[close, '{', '['])?;
+            inner[end..]
+                .starts_with(close)
+                .then(|| (&inner[..end], &inner[end + 1..]))
+        };
+        if let Some((inner, after)) = group('{', '}') {
+            statement.qualifiers.push(parse_snak_condition(inner)?);
+            rest = after.trim_start();
+        } else if let Some((units, after)) = group('[', ']')
+            .and_then(|(inner, after)| Some((inner.trim().strip_prefix("unit:")?, after)))
+        {
+            for unit in units.split(',').map(str::trim) {
+                if unit != "1" && !is_valid_entity_id(unit) {
+                    return Err(FilterError::InvalidClaim(format!(
+                        "Invalid unit '{}', expected an item ID like Q11573 or 1",
+                        unit
+                    )));
+                }
+                statement.units.insert(unit.to_string());
+            }
+            rest = after.trim_start();
+        } else if let Some((inner, after)) = group('[', ']') {
+            match inner.trim().strip_prefix("ref") {
+                Some("") => statement.referenced = true,
+                Some(condition) if condition.starts_with(':') => {
+                    statement.referenced = true;
+                    statement
+                        .references
+                        .push(parse_snak_condition(&condition[1..])?);
+                }
+                _ => {
+                    return Err(FilterError::InvalidClaim(format!(
+                    "Expected [ref] or a reference condition like [ref:P248:Q36578], found '[{}]'",
+                    inner
+                )))
+                }
+            }
+            rest = after.trim_start();
+        } else {
+            return Err(FilterError::InvalidClaim(format!(
+                "Expected conditions like {{P580>=1990}}, [ref:P248:Q36578] or [unit:Q11573], found '{}'",
+                rest
+            )));
+        }
     }
+    Ok(())
+}
 
-    // Handle parentheses (for future expansion)
-    if input.starts_with('(') && input.ends_with(')') {
-        return parse_or_expression(&input[1..input.len() - 1]);
+/// Parse a qualifier or reference condition: "P580" (present), "P642:Q5,Q6",
+/// "P642:*", a range such as "P580:1990..2000" or a comparison with a number
+/// or date such as "P580>=1990"
+fn parse_snak_condition(input: &str) -> Result<SnakCondition, FilterError> {
+    let input = input.trim();
+    let end = input
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| !c.is_ascii_digit())
+        .map_or(input.len(), |(i, _)| i);
+    let property = input[..end].to_string();
+    if !is_valid_property_id(&property) || property.len() < 2 {
+        return Err(FilterError::InvalidClaim(format!(
+            "Invalid qualifier condition: {}",
+            input
+        )));
     }
+    let rest = input[end..].trim();
+    let test = if rest.is_empty() {
+        ValueTest::Any
+    } else if let Some(values) = rest.strip_prefix(':') {
+        match parse_value_keyword(values)? {
+            Some(test) => test,
+            None => {
+                let values: HashSet<String> = values
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                if values.is_empty() || !values.iter().all(|v| is_valid_entity_id(v)) {
+                    return Err(FilterError::InvalidClaim(format!(
+                        "Invalid values in qualifier condition: {}",
+                        input
+                    )));
+                }
+                ValueTest::Entities(values)
+            }
+        }
+    } else if let Some(pattern) = rest.strip_prefix('~') {
+        ValueTest::Pattern(Pattern::new(parse_quoted(pattern)?, false)?)
+    } else if let Some((op, literal)) = Comparison::split(rest) {
+        ValueTest::Compare(op, Literal::parse(literal)?)
+    } else {
+        return Err(FilterError::InvalidClaim(format!(
+            "Invalid qualifier condition: {}",
+            input
+        )));
+    };
+    Ok(SnakCondition { property, test })
+}
 
-    // Parse property[:values] expression
-    parse_property_filter(input)
+/// The test of a value that stands alone: a wildcard (* or Q!, P!, L!), an
+/// unknown or no value (somevalue, novalue), a date or number, a range of them
+/// (1900..1950, open as 1900.. or ..1950) or a "pattern" for the whole value;
+/// `None` for a list of values
+fn parse_value_keyword(values: &str) -> Result<Option<ValueTest>, FilterError> {
+    let wildcard = values.trim();
+    if wildcard.starts_with('"') {
+        let pattern = Pattern::new(parse_quoted(wildcard)?, true)?;
+        return Ok(Some(ValueTest::Pattern(pattern)));
+    }
+    // A range, unlike a value file path such as @../ids.txt
+    if let Some((low, high)) = wildcard
+        .split_once("..")
+        .filter(|_| !wildcard.starts_with('@'))
+    {
+        // An open range compares its one end
+        match (low.trim(), high.trim()) {
+            ("", "") => {
+                return Err(FilterError::InvalidClaim(
+                    "A range needs at least one end, as in 1900.. or ..1950".to_string(),
+                ))
+            }
+            (low, "") => {
+                return Ok(Some(ValueTest::Compare(
+                    Comparison::Ge,
+                    Literal::parse(low)?,
+                )))
+            }
+            ("", high) => {
+                return Ok(Some(ValueTest::Compare(
+                    Comparison::Le,
+                    Literal::parse(high)?,
+                )))
+            }
+            _ => {}
+        }
+        let (low, high) = (Literal::parse(low)?, Literal::parse(high)?);
+        let dates = low.date.is_some() && high.date.is_some();
+        if !dates && (low.number.is_none() || high.number.is_none()) {
+            return Err(FilterError::InvalidClaim(format!(
+                "Both ends of the range {} must be dates or numbers",
+                wildcard
+            )));
+        }
+        return Ok(Some(ValueTest::Range(low, high)));
+    }
+    match wildcard {
+        "*" => return Ok(Some(ValueTest::AnyEntity(None))),
+        "somevalue" => return Ok(Some(ValueTest::SomeValue)),
+        "novalue" => return Ok(Some(ValueTest::NoValue)),
+        _ => {}
+    }
+    // A date or number alone, which no entity ID is: P569:1952 is the year
+    if let Some(literal) = Literal::parse(wildcard)
+        .ok()
+        .filter(|literal| literal.unit.is_none())
+    {
+        return Ok(Some(ValueTest::Compare(Comparison::Eq, literal)));
+    }
+    match wildcard.strip_suffix('!') {
+        Some(letter @ ("Q" | "P" | "L")) => Ok(Some(ValueTest::AnyEntity(letter.chars().next()))),
+        Some(_) => Err(FilterError::InvalidClaim(format!(
+            "Invalid wildcard {}, expected *, Q!, P! or L!",
+            wildcard
+        ))),
+        None => Ok(None),
+    }
 }
 
-/// Parse a single property filter like "P31:Q5,Q6256" or "P18"
-fn parse_property_filter(input: &str) -> Result<ClaimFilter, FilterError> {
+/// Parse a property filter without qualifier conditions
+fn parse_value_filter(input: &str) -> Result<ClaimFilter, FilterError> {
     let input = input.trim();
 
     if input.is_empty() {
         return Err(FilterError::InvalidClaim("Empty claim filter".to_string()));
     }
 
-    if let Some(colon_pos) = input.find(':') {
-        let property = input[..colon_pos].trim().to_string();
-        let values_str = &input[colon_pos + 1..];
-
-        // Validate property ID
-        if !is_valid_property_id(&property) {
+    // A comparison or pattern of the main value: P1082>1000000, P856~"\.de/"
+    let colon = find_unquoted(input, &[':']).unwrap_or(input.len());
+    if let Some(op_pos) = find_unquoted(&input[..colon], &['<', '>', '=', '~']) {
+        let property = input[..op_pos].trim();
+        if property.contains('/') {
+            return Err(FilterError::InvalidClaim(format!(
+                "Comparisons and patterns are not supported in the property path {}",
+                property
+            )));
+        }
+        if !is_valid_property_id(property) {
             return Err(FilterError::InvalidClaim(format!(
                 "Invalid property ID: {}",
                 property
             )));
         }
+        let test = match input[op_pos..].strip_prefix('~') {
+            Some(pattern) => ValueTest::Pattern(Pattern::new(parse_quoted(pattern)?, false)?),
+            None => {
+                let (op, literal) =
+                    Comparison::split(&input[op_pos..]).expect("starts with an operator");
+                ValueTest::Compare(op, Literal::parse(literal)?)
+            }
+        };
+        return Ok(ClaimFilter::Statement(StatementFilter::new(
+            property.to_string(),
+            test,
+        )));
+    }
+
+    if let Some(colon_pos) = input.find(':') {
+        let properties: Vec<String> = input[..colon_pos]
+            .split('/')
+            .map(|p| p.trim().to_string())
+            .collect();
+        let values_str = &input[colon_pos + 1..];
 
-        // Parse values (comma-separated)
-        let values: HashSet<String> = values_str
+        // Validate property IDs
+        for property in &properties {
+            if !is_valid_property_id(property) {
+                return Err(FilterError::InvalidClaim(format!(
+                    "Invalid property ID: {}",
+                    property
+                )));
+            }
+        }
+        let property = properties[0].clone();
+
+        // A wildcard stands alone: * for any entity value, Q! for any item,
+        // and so do somevalue, novalue, dates, numbers and ranges
+        if let Some(test) = parse_value_keyword(values_str)? {
+            if properties.len() > 1 {
+                return Err(FilterError::InvalidClaim(format!(
+                    "Wildcards, special values, dates and ranges are not supported in the property path {}",
+                    properties.join("/")
+                )));
+            }
+            return Ok(match test {
+                ValueTest::AnyEntity(letter) => ClaimFilter::AnyValue { property, letter },
+                test => ClaimFilter::Statement(StatementFilter::new(property, test)),
+            });
+        }
+
+        // Parse values (comma-separated), transitive ones marked Q5* or <Q5,
+        // and @file for the IDs of a file
+        let mut values = HashSet::new();
+        let mut roots = HashSet::new();
+        for value in values_str
             .split(',')
-            .map(|v| v.trim().to_string())
+            .map(str::trim)
             .filter(|v| !v.is_empty())
-            .collect();
+        {
+            if let Some(path) = value.strip_prefix('@') {
+                let ids: HashSet<String> = read_id_file(path.trim())?;
+                if ids.is_empty() {
+                    return Err(FilterError::InvalidClaim(format!(
+                        "No values in {} for property {}",
+                        path, property
+                    )));
+                }
+                values.extend(ids);
+                continue;
+            }
+            match value.strip_suffix('*').or_else(|| value.strip_prefix('<')) {
+                Some(root) => roots.insert(root.trim().to_string()),
+                None => values.insert(value.to_string()),
+            };
+        }
 
-        if values.is_empty() {
+        if values.is_empty() && roots.is_empty() {
             return Err(FilterError::InvalidClaim(format!(
                 "No values specified for property {}",
                 property
@@ -120,7 +533,7 @@ fn parse_property_filter(input: &str) -> Result<ClaimFilter, FilterError> {
         }
 
         // Validate entity IDs
-        for value in &values {
+        for value in values.iter().chain(&roots) {
             if !is_valid_entity_id(value) {
                 return Err(FilterError::InvalidClaim(format!(
                     "Invalid entity ID: {}",
@@ -129,7 +542,35 @@ fn parse_property_filter(input: &str) -> Result<ClaimFilter, FilterError> {
             }
         }
 
-        Ok(ClaimFilter::PropertyValue(property, values))
+        if properties.len() > 1 {
+            if !roots.is_empty() {
+                return Err(FilterError::InvalidClaim(format!(
+                    "Transitive values are not supported in the property path {}",
+                    properties.join("/")
+                )));
+            }
+            return Ok(ClaimFilter::Path {
+                properties,
+                values,
+                starts: HashSet::new(),
+            });
+        }
+        if roots.is_empty() {
+            return Ok(ClaimFilter::PropertyValue(property, values));
+        }
+        let subclasses = ClaimFilter::Subclasses {
+            property: property.clone(),
+            classes: roots.clone(),
+            roots,
+        };
+        if values.is_empty() {
+            Ok(subclasses)
+        } else {
+            Ok(ClaimFilter::Or(vec![
+                ClaimFilter::PropertyValue(property, values),
+                subclasses,
+            ]))
+        }
     } else {
         // Just a property (check for existence)
         let property = input.to_string();
@@ -145,37 +586,36 @@ fn parse_property_filter(input: &str) -> Result<ClaimFilter, FilterError> {
     }
 }
 
-/// Split string by delimiter at top level (not inside parentheses)
-fn split_top_level(input: &str, delimiter: char) -> Vec<String> {
-    let mut parts = Vec::new();
-    let mut current = String::new();
-    let mut paren_depth = 0;
-
-    for ch in input.chars() {
-        match ch {
-            '(' => {
-                paren_depth += 1;
-                current.push(ch);
-            }
-            ')' => {
-                paren_depth -= 1;
-                current.push(ch);
-            }
-            c if c == delimiter && paren_depth == 0 => {
-                if !current.is_empty() {
-                    parts.push(current.trim().to_string());
-                    current = String::new();
-                }
-            }
-            c => current.push(c),
-        }
-    }
-
-    if !current.is_empty() {
-        parts.push(current.trim().to_string());
-    }
+/// The regular expression between the double quotes of `"..."`, as written
+fn parse_quoted(input: &str) -> Result<&str, FilterError> {
+    let input = input.trim();
+    input
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .ok_or_else(|| {
+            FilterError::InvalidClaim(format!(
+                "Expected a pattern in double quotes, found '{}'",
+                input
+            ))
+        })
+}
 
-    parts
+/// Byte position of the first of `chars` outside of "patterns", in which a
+/// backslash escapes the next character
+fn find_unquoted(input: &str, chars: &[char]) -> Option<usize> {
+    let (mut quoted, mut escaped) = (false, false);
+    input
+        .char_indices()
+        .find(|&(_, c)| {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                c => return !quoted && chars.contains(&c),
+            }
+            false
+        })
+        .map(|(i, _)| i)
 }
 
 /// Validate property ID format (P followed by digits)
@@ -198,6 +638,9 @@ fn is_valid_entity_id(id: &str) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hierarchy::Hierarchy;
+    use crate::test_util::TempDir;
+    use std::collections::{BTreeSet, HashMap};
 
     #[test]
     fn test_parse_simple_property() {
@@ -269,9 +712,7 @@ mod tests {
 
     #[test]
     fn test_parse_complex_expression() {
-        // P31:Q5&P18|P279 should parse as (P31:Q5 AND P18) OR P279
-        // But per wikibase-dump-filter docs, | has lower precedence
-        // so A&B|C = A AND (B OR C)
+        // P31:Q5&P18|P279 parses as (P31:Q5 AND P18) OR P279
         let filter = parse_claim_filter("P31:Q5&P18|P279").unwrap();
         match filter {
             ClaimFilter::Or(_) => {
@@ -281,6 +722,403 @@ mod tests {
         }
     }
 
+    /// Compact structure of a parsed filter, e.g. "|(&(P31:Q5 P18) ~P279)"
+    fn shape(filter: &ClaimFilter) -> String {
+        let list =
+            |filters: &[ClaimFilter]| filters.iter().map(shape).collect::<Vec<_>>().join(" ");
+        match filter {
+            ClaimFilter::HasProperty(p) => p.clone(),
+            ClaimFilter::PropertyValue(p, values) => {
+                let mut values: Vec<&str> = values.iter().map(String::as_str).collect();
+                values.sort_unstable();
+                format!("{}:{}", p, values.join(","))
+            }
+            ClaimFilter::And(filters) => format!("&({})", list(filters)),
+            ClaimFilter::Or(filters) => format!("|({})", list(filters)),
+            ClaimFilter::Not(inner) => format!("~{}", shape(inner)),
+            ClaimFilter::Statement(statement) => statement.to_string(),
+            other => other.tree(),
+        }
+    }
+
+    fn parse_shape(input: &str) -> String {
+        shape(&parse_claim_filter(input).unwrap())
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        assert_eq!(parse_shape("P31:Q5&P18|P279"), "|(&(P31:Q5 P18) P279)");
+        assert_eq!(parse_shape("P279|P31:Q5&P18"), "|(P279 &(P31:Q5 P18))");
+        assert_eq!(parse_shape("~P31&P18"), "&(~P31 P18)");
+        assert_eq!(parse_shape("P1|P2|P3"), "|(P1 P2 P3)");
+        assert_eq!(parse_shape("P1&P2&P3|P4&P5"), "|(&(P1 P2 P3) &(P4 P5))");
+        assert_eq!(parse_shape(" P31 : Q5 , Q6 & P18 "), "&(P31:Q5,Q6 P18)");
+    }
+
+    #[test]
+    fn test_parse_parentheses() {
+        assert_eq!(
+            parse_shape("(P31:Q5|P31:Q6256)&P18"),
+            "&(|(P31:Q5 P31:Q6256) P18)"
+        );
+        assert_eq!(
+            parse_shape("P18&(P31:Q5|P31:Q6256)"),
+            "&(P18 |(P31:Q5 P31:Q6256))"
+        );
+        assert_eq!(parse_shape("((P18))"), "P18");
+        assert_eq!(parse_shape("(P1|P2)&(P3|P4)"), "&(|(P1 P2) |(P3 P4))");
+        assert_eq!(parse_shape("P1&(P2|(P3&P4))"), "&(P1 |(P2 &(P3 P4)))");
+        assert_eq!(parse_shape("(P1)|(P2)"), "|(P1 P2)");
+    }
+
+    #[test]
+    fn test_parse_nested_not() {
+        assert_eq!(
+            parse_shape("~(P31:Q5&P106:Q82955)"),
+            "~&(P31:Q5 P106:Q82955)"
+        );
+        assert_eq!(parse_shape("~~P18"), "~~P18");
+        assert_eq!(parse_shape("~(~P18|P31)"), "~|(~P18 P31)");
+        assert_eq!(parse_shape("P31:Q5&~(P18|P19)"), "&(P31:Q5 ~|(P18 P19))");
+        assert_eq!(parse_shape("~ ( P18 )"), "~P18");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for input in [
+            "", "(P31", "P31)", "(P31))", "P31&", "&P31", "P31|", "|P31", "P31||P18", "P31&&P18",
+            "()", "~", "P31~P18", "P31(P18)", "(P31)P18", "(P31|)", "P31 P18",
+        ] {
+            assert!(parse_claim_filter(input).is_err(), "{:?}", input);
+        }
+        let error = parse_claim_filter("(P31:Q5|P18").unwrap_err().to_string();
+        assert!(error.contains("Expected ')' at position 12"), "{}", error);
+        let error = parse_claim_filter("P31&").unwrap_err().to_string();
+        assert!(
+            error.contains("Expected a property at position 5"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_parse_wildcards() {
+        let shapes = [
+            ("P1343:*", "P1343 = * (any entity)\n"),
+            ("P31:Q!", "P31 = Q! (any item)\n"),
+        ];
+        for (input, tree) in shapes {
+            assert_eq!(parse_claim_filter(input).unwrap().tree(), tree);
+        }
+        assert!(matches!(
+            parse_claim_filter(" P31 : P! ").unwrap(),
+            ClaimFilter::AnyValue {
+                letter: Some('P'),
+                ..
+            }
+        ));
+        for input in [
+            "P31:X!",
+            "P31:Q5!",
+            "P31:Q5,*",
+            "P31:*,Q5",
+            "P31/P279:*",
+            "P31:!",
+        ] {
+            assert!(parse_claim_filter(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_qualifier_conditions() {
+        let trees = [
+            ("P39:Q11696{P580>=1990}", "P39 = Q11696 {P580 >= 1990}\n"),
+            (
+                "P39{P580>=1990}{P582 < 2000-06}",
+                "P39 (any value) {P580 >= 1990} {P582 < 2000-06}\n",
+            ),
+            ("P31:Q!{P642:Q5,Q6}", "P31 = Q! {P642 = Q5 | Q6}\n"),
+            ("P31{P642}", "P31 (any value) {P642 (any value)}\n"),
+        ];
+        for (input, tree) in trees {
+            assert_eq!(parse_claim_filter(input).unwrap().tree(), tree, "{}", input);
+        }
+        assert_eq!(
+            parse_shape("P39:Q11696{P580>=1990}&~P18"),
+            "&(P39 = Q11696 {P580 >= 1990} ~P18)"
+        );
+        assert!(parse_claim_filter("(P39{P580>1990})|~P18").is_ok());
+        for input in [
+            "P39{P580>=1990",
+            "P39{P580>=1990}x",
+            "P39{}",
+            "P39{Q5}",
+            "P39{P580>=soon}",
+            "P39{P580:}",
+            "P39{P580=1990{P1}}",
+            "P39{P580>=1990|P580<1900}",
+            "P39/P279{P580}",
+            "P39:Q5+{P580}",
+        ] {
+            assert!(parse_claim_filter(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_reference_conditions() {
+        let trees = [
+            ("P569[ref]", "P569 (any value) [ref]\n"),
+            (
+                "P569[ref:P248:Q36578]",
+                "P569 (any value) [ref P248 = Q36578]\n",
+            ),
+            (
+                "P39:Q11696{P580>=1990}[ref:P813>=2020]",
+                "P39 = Q11696 {P580 >= 1990} [ref P813 >= 2020]\n",
+            ),
+        ];
+        for (input, tree) in trees {
+            assert_eq!(parse_claim_filter(input).unwrap().tree(), tree, "{}", input);
+        }
+        assert_eq!(
+            parse_shape("P569[ref]&~P570[ref:P248]"),
+            "&(P569 (any value) [ref] ~P570 (any value) [ref P248 (any value)])"
+        );
+        for input in [
+            "P569[ref",
+            "P569[source:P248]",
+            "P569[ref:]",
+            "P569[refP248]",
+            "P31:Q5*[ref]",
+        ] {
+            assert!(parse_claim_filter(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_ranks() {
+        let trees = [
+            ("P1082@preferred", "P1082@preferred (any value)\n"),
+            (
+                "P31@normal, deprecated:Q5,Q6",
+                "P31@normal,deprecated = Q5 | Q6\n",
+            ),
+            (
+                "P39@deprecated{P580}[ref]",
+                "P39@deprecated (any value) {P580 (any value)} [ref]\n",
+            ),
+        ];
+        for (input, tree) in trees {
+            assert_eq!(parse_claim_filter(input).unwrap().tree(), tree, "{}", input);
+        }
+        // @ after the colon still reads values from a file
+        assert!(parse_claim_filter("P31:@/nonexistent/values.txt").is_err());
+        for input in [
+            "P31@",
+            "P31@best",
+            "P31@preferred:Q5*",
+            "P31/P279@preferred:Q5",
+        ] {
+            assert!(parse_claim_filter(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_comparisons() {
+        let trees = [
+            ("P1082>1000000", "P1082 > 1000000\n"),
+            ("P2046 <= 50 Q712226", "P2046 <= 50 Q712226\n"),
+            ("P1082@preferred>=1e6", "P1082@preferred >= 1e6\n"),
+            (
+                "P1082>1000000{P585>=2020}&P31:Q515",
+                "AND\n  P1082 > 1000000 {P585 >= 2020}\n  P31 = Q515\n",
+            ),
+        ];
+        for (input, tree) in trees {
+            assert_eq!(parse_claim_filter(input).unwrap().tree(), tree, "{}", input);
+        }
+        for input in [
+            "P1082>",
+            "P1082>many",
+            "P31/P1082>5",
+            "Q5>3",
+            "P2046<=50 km",
+        ] {
+            assert!(parse_claim_filter(input).is_err(), "{}", input);
+        }
+        // A transitive value after the colon is not a comparison
+        assert!(parse_claim_expression("P31:<Q5").is_ok());
+    }
+
+    #[test]
+    fn test_parse_units() {
+        let trees = [
+            ("P2048:*[unit:Q11573]", "P2048 (any value) [unit Q11573]\n"),
+            (
+                "P2048>2[unit: Q11573, Q174728]",
+                "P2048 > 2 [unit Q11573 | Q174728]\n",
+            ),
+            ("P1082[unit:1]", "P1082 (any value) [unit 1]\n"),
+            (
+                "P2048@preferred:*{P585>=2020}[ref][unit:Q11573]",
+                "P2048@preferred (any value) {P585 >= 2020} [ref] [unit Q11573]\n",
+            ),
+        ];
+        for (input, tree) in trees {
+            assert_eq!(parse_claim_filter(input).unwrap().tree(), tree, "{}", input);
+        }
+        for input in ["P2048[unit:]", "P2048[unit:metre]", "P2048[unit:Q11573"] {
+            assert!(parse_claim_filter(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_ranges() {
+        let trees = [
+            ("P569:1900..1950", "P569 in 1900..1950\n"),
+            ("P585:2020-01..2020-06-30", "P585 in 2020-01..2020-06-30\n"),
+            ("P1082:1e5..1e6", "P1082 in 1e5..1e6\n"),
+            (
+                "P39{P580:1990..2000}",
+                "P39 (any value) {P580 in 1990..2000}\n",
+            ),
+            ("P569:1900..", "P569 >= 1900\n"),
+            ("P569: ..1950-06", "P569 <= 1950-06\n"),
+            ("P39{P580:1990..}", "P39 (any value) {P580 >= 1990}\n"),
+            // A date or number alone matches it exactly
+            ("P569:1952", "P569 = 1952\n"),
+            ("P569:-0500-03", "P569 = -0500-03\n"),
+            ("P1082:1e5", "P1082 = 1e5\n"),
+            ("P39{P580:1990}", "P39 (any value) {P580 = 1990}\n"),
+        ];
+        for (input, tree) in trees {
+            assert_eq!(parse_claim_filter(input).unwrap().tree(), tree, "{}", input);
+        }
+        for input in [
+            "P569:..",
+            "P569:1900..x",
+            "P569:1900-05..1.5",
+            "P31/P569:1900..1950",
+            "P31/P569:1952",
+            "P569:1952,Q5",
+            "P2046:50 Q712226",
+        ] {
+            assert!(parse_claim_filter(input).is_err(), "{}", input);
+        }
+        // A value file in a parent directory is not a range
+        assert!(parse_claim_filter("P106:@../nonexistent.txt")
+            .unwrap_err()
+            .to_string()
+            .contains("nonexistent"));
+    }
+
+    #[test]
+    fn test_parse_patterns() {
+        let trees = [
+            (r#"P212:"978-3-.*""#, "P212 = \"978-3-.*\"\n"),
+            (r#"P856~"\.de/""#, "P856 ~ \"\\.de/\"\n"),
+            (
+                r#"P856 ~ "a|b(c)&~d{2}"&~P18"#,
+                "AND\n  P856 ~ \"a|b(c)&~d{2}\"\n  NOT\n    P18 (any value)\n",
+            ),
+            (
+                r#"P1343{P1545:"1[0-9]"}[ref:P854~"http://[^/]*\.gov/"]"#,
+                "P1343 (any value) {P1545 = \"1[0-9]\"} [ref P854 ~ \"http://[^/]*\\.gov/\"]\n",
+            ),
+            (r#"P1476~"say \"hi\"""#, "P1476 ~ \"say \\\"hi\\\"\"\n"),
+        ];
+        for (input, tree) in trees {
+            assert_eq!(parse_claim_filter(input).unwrap().tree(), tree, "{}", input);
+        }
+        for input in [
+            r#"P212:"978"#,
+            r#"P856~\.de"#,
+            r#"P856~"(""#,
+            r#"P31/P856~"x""#,
+        ] {
+            assert!(parse_claim_filter(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_special_values() {
+        let trees = [
+            ("P570:somevalue", "P570 = somevalue\n"),
+            ("P570@preferred:novalue", "P570@preferred = novalue\n"),
+            ("P39{P582:novalue}", "P39 (any value) {P582 = novalue}\n"),
+        ];
+        for (input, tree) in trees {
+            assert_eq!(parse_claim_filter(input).unwrap().tree(), tree, "{}", input);
+        }
+        for input in ["P31/P279:somevalue", "P570:novalue,Q5", "P570:unknown"] {
+            assert!(parse_claim_filter(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_value_file() {
+        let dir = TempDir::new("values");
+        let path = dir.join("values.txt");
+        std::fs::write(&path, "# occupations\nQ82955\nQ36180\n").unwrap();
+        let input = format!("P106:Q1,@{}&P18", path.display());
+        assert_eq!(parse_shape(&input), "&(P106:Q1,Q36180,Q82955 P18)");
+        std::fs::write(&path, "Q82955\nfoo\n").unwrap();
+        assert!(parse_claim_filter(&input).is_err());
+        std::fs::write(&path, "").unwrap();
+        assert!(parse_claim_filter(&input).is_err());
+        std::fs::remove_file(&path).ok();
+        assert!(parse_claim_filter(&input).is_err());
+    }
+
+    /// Parse and resolve against the P279 edges Q2 -> Q1 and Q3 -> Q2
+    fn parse_resolved(input: &str) -> Result<ClaimFilter, FilterError> {
+        let hierarchy = Hierarchy::read("Q2\tQ1\nQ3\tQ2\n".as_bytes(), "test").unwrap();
+        let index = ClaimIndex {
+            hierarchy: Some(hierarchy.clone()),
+            edges: HashMap::from([("P279".to_string(), hierarchy)]),
+        };
+        let mut filter = parse_claim_expression(input)?;
+        filter.resolve(&index)?;
+        Ok(filter)
+    }
+
+    #[test]
+    fn test_parse_transitive_values() {
+        let filter = parse_resolved("P31:Q1*").unwrap();
+        match filter {
+            ClaimFilter::Subclasses { roots, classes, .. } => {
+                assert_eq!(roots, HashSet::from(["Q1".to_string()]));
+                assert_eq!(classes.len(), 3);
+            }
+            _ => panic!("Expected Subclasses"),
+        }
+        let filter = parse_resolved("P31:Q5,<Q2").unwrap();
+        assert_eq!(filter.tree(), "OR\n  P31 = Q5\n  P31 = Q2* (2 classes)\n");
+        assert!(parse_claim_filter("P31:Q1*").is_err());
+        assert!(parse_resolved("P31:X1*").is_err());
+    }
+
+    #[test]
+    fn test_parse_property_path() {
+        let filter = parse_resolved("P31/P279:Q1").unwrap();
+        assert_eq!(
+            filter.path_properties(),
+            BTreeSet::from(["P279".to_string()])
+        );
+        match filter {
+            ClaimFilter::Path { starts, .. } => {
+                assert_eq!(starts, HashSet::from(["Q2".to_string()]))
+            }
+            _ => panic!("Expected Path"),
+        }
+        let filter = parse_resolved("P31/P279/P279:Q1").unwrap();
+        assert_eq!(filter.tree(), "P31/P279/P279 = Q1 (1 values of P31)\n");
+        assert!(parse_resolved("P31/P17:Q1").is_err());
+        assert!(parse_resolved("P31/P279:Q1*").is_err());
+        assert!(parse_resolved("P31/Q279:Q1").is_err());
+        assert!(parse_claim_filter("P31/P279:Q1").is_err());
+    }
+
     #[test]
     fn test_invalid_property() {
         assert!(parse_claim_filter("Q31").is_err());
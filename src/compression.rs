@@ -1,24 +1,42 @@
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use bzip2::read::BzDecoder;
-use flate2::read::GzDecoder;
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
 use flate2::write::GzEncoder;
 use lz4_flex::frame::{FrameDecoder as Lz4Decoder, FrameEncoder as Lz4Encoder};
 
-/// Default output buffer size (8 MB)
+use crate::tar;
+
+/// Default output buffer size (8 MB, --write-buffer)
 pub const OUTPUT_BUFFER_SIZE: usize = 8 * 1024 * 1024;
 
+/// Default input buffer size (8 KB like std's BufReader, --read-buffer)
+pub const INPUT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// `path` without a .bz2, .gz or .lz4 extension
+pub fn strip_compression_suffix(path: &str) -> &str {
+    path.strip_suffix(".bz2")
+        .or_else(|| path.strip_suffix(".gz"))
+        .or_else(|| path.strip_suffix(".lz4"))
+        .unwrap_or(path)
+}
+
 /// Detect input format from file path
 pub fn detect_format_from_path(path: &str) -> String {
     let path_lower = path.to_lowercase();
-    // Remove compression extensions first
-    let path_without_compression = path_lower
-        .strip_suffix(".bz2")
-        .or_else(|| path_lower.strip_suffix(".gz"))
-        .or_else(|| path_lower.strip_suffix(".lz4"))
-        .unwrap_or(&path_lower);
-
-    if path_without_compression.ends_with(".nt") || path_without_compression.contains("truthy") {
+    // Remove compression and tar extensions first
+    let path_without_compression = strip_compression_suffix(&path_lower);
+    let path_without_compression = path_without_compression
+        .strip_suffix(".tar")
+        .unwrap_or(path_without_compression);
+
+    if path_without_compression.ends_with(".ttl") {
+        "turtle".to_string()
+    } else if path_without_compression.ends_with(".nt")
+        || path_without_compression.contains("truthy")
+    {
         "rdf".to_string()
     } else if path_without_compression.ends_with(".json")
         || path_without_compression.ends_with(".ndjson")
@@ -29,6 +47,19 @@ pub fn detect_format_from_path(path: &str) -> String {
     }
 }
 
+/// Compression of an input file, from its extension
+pub fn input_compression(path: &str) -> &'static str {
+    if path.ends_with(".bz2") {
+        "bzip2"
+    } else if path.ends_with(".gz") {
+        "gzip"
+    } else if path.ends_with(".lz4") {
+        "lz4"
+    } else {
+        "none"
+    }
+}
+
 /// Determine output compression from CLI option or output file extension
 pub fn determine_compression(compress_arg: &str, output_path: Option<&str>) -> String {
     // If --compress is explicitly set to something other than "none", use it
@@ -49,41 +80,312 @@ pub fn determine_compression(compress_arg: &str, output_path: Option<&str>) -> S
     "none".to_string()
 }
 
+/// A writer whose stream has to be ended, such as by the gzip trailer or the
+/// LZ4 frame end mark
+pub trait FinishWrite: Write {
+    /// End the stream and flush the writer beneath, reporting any error
+    fn finish(self: Box<Self>) -> std::io::Result<()>;
+}
+
+/// Flush a buffered output and end its stream; outputs written under a
+/// temporary name are only committed once this succeeded
+pub fn finish_output(output: BufWriter<Box<dyn FinishWrite>>) -> std::io::Result<()> {
+    output
+        .into_inner()
+        .map_err(std::io::IntoInnerError::into_error)?
+        .finish()
+}
+
+/// Uncompressed output, which only needs flushing
+struct Uncompressed<W: Write>(W);
+
+impl<W: Write> Write for Uncompressed<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Write> FinishWrite for Uncompressed<W> {
+    fn finish(mut self: Box<Self>) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Write> FinishWrite for GzEncoder<W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        GzEncoder::finish(*self)?.flush()
+    }
+}
+
+/// LZ4 frame writer; dropped without `finish`, as on errors, it still tries
+/// to write the frame end mark
+struct Lz4Writer<W: Write>(Option<Lz4Encoder<W>>);
+
+impl<W: Write> FinishWrite for Lz4Writer<W> {
+    fn finish(mut self: Box<Self>) -> std::io::Result<()> {
+        let encoder = self.0.take().expect("LZ4 writer used after finish");
+        encoder.finish().map_err(std::io::Error::other)?.flush()
+    }
+}
+
+impl<W: Write> Write for Lz4Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .as_mut()
+            .expect("LZ4 writer used after finish")
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0
+            .as_mut()
+            .expect("LZ4 writer used after finish")
+            .flush()
+    }
+}
+
+impl<W: Write> Drop for Lz4Writer<W> {
+    fn drop(&mut self) {
+        // Like GzEncoder, finish the stream on drop so the output stays decodable
+        if let Some(encoder) = self.0.take() {
+            let _ = encoder.finish();
+        }
+    }
+}
+
 /// Create a writer with optional compression
+///
+/// `finish` ends compressed streams and reports their errors; a dropped
+/// writer ends them as well as it can.
 pub fn create_compressed_writer<W: Write + 'static>(
     writer: W,
     compression: &str,
-) -> Box<dyn Write> {
+) -> Box<dyn FinishWrite> {
     match compression {
-        "lz4" => Box::new(Lz4Encoder::new(writer)),
+        "lz4" => Box::new(Lz4Writer(Some(Lz4Encoder::new(writer)))),
         "gzip" | "gz" => Box::new(GzEncoder::new(writer, flate2::Compression::default())),
-        _ => Box::new(writer),
+        _ => Box::new(Uncompressed(writer)),
+    }
+}
+
+/// Ends the current member of a member writer at its next flush, so the
+/// output written so far is complete on its own (checkpoints)
+#[derive(Debug, Clone, Default)]
+pub struct MemberBoundary(Arc<AtomicBool>);
+
+impl MemberBoundary {
+    pub fn request(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Create a writer that compresses into gzip members or LZ4 frames (--append)
+///
+/// A member ends at the first flush after `boundary` was requested, and when
+/// the writer is finished. The output then ends at a complete member, so a
+/// resumed run can truncate it there and add new members; readers decode the
+/// members as one stream.
+pub fn create_member_writer<W: Write + 'static>(
+    writer: W,
+    compression: &str,
+    boundary: MemberBoundary,
+) -> Box<dyn FinishWrite> {
+    match compression {
+        "lz4" | "gzip" | "gz" => Box::new(MemberWriter {
+            member: Some(Member::Idle(writer)),
+            lz4: compression == "lz4",
+            boundary,
+        }),
+        _ => Box::new(Uncompressed(writer)),
+    }
+}
+
+/// Compressed writer that ends the current member at requested boundaries;
+/// dropped without `finish`, as on errors, it still tries to end the member
+struct MemberWriter<W: Write> {
+    member: Option<Member<W>>,
+    lz4: bool,
+    boundary: MemberBoundary,
+}
+
+enum Member<W: Write> {
+    /// Between members
+    Idle(W),
+    Gzip(GzEncoder<W>),
+    Lz4(Lz4Encoder<W>),
+}
+
+impl<W: Write> MemberWriter<W> {
+    /// Finish the current member, returning the underlying writer
+    fn end_member(&mut self) -> std::io::Result<&mut W> {
+        let writer = match self
+            .member
+            .take()
+            .expect("member writer used after a failed finish")
+        {
+            Member::Idle(writer) => writer,
+            Member::Gzip(encoder) => encoder.finish()?,
+            Member::Lz4(encoder) => encoder.finish().map_err(std::io::Error::other)?,
+        };
+        match self.member.insert(Member::Idle(writer)) {
+            Member::Idle(writer) => Ok(writer),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<W: Write> Write for MemberWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let member = self
+            .member
+            .as_mut()
+            .expect("member writer used after a failed finish");
+        if let Member::Idle(_) = member {
+            let writer = match self.member.take() {
+                Some(Member::Idle(writer)) => writer,
+                _ => unreachable!(),
+            };
+            self.member = Some(if self.lz4 {
+                Member::Lz4(Lz4Encoder::new(writer))
+            } else {
+                Member::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+            });
+        }
+        match self.member.as_mut() {
+            Some(Member::Gzip(encoder)) => encoder.write(buf),
+            Some(Member::Lz4(encoder)) => encoder.write(buf),
+            _ => unreachable!(),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.boundary.0.swap(false, Ordering::Relaxed) {
+            return self.end_member()?.flush();
+        }
+        match self
+            .member
+            .as_mut()
+            .expect("member writer used after a failed finish")
+        {
+            Member::Idle(writer) => writer.flush(),
+            Member::Gzip(encoder) => encoder.flush(),
+            Member::Lz4(encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl<W: Write> FinishWrite for MemberWriter<W> {
+    fn finish(mut self: Box<Self>) -> std::io::Result<()> {
+        self.end_member()?.flush()
     }
 }
 
+impl<W: Write> Drop for MemberWriter<W> {
+    fn drop(&mut self) {
+        if self.member.is_some() {
+            let _ = self.end_member();
+        }
+    }
+}
+
+/// Open the output file, or stdout if None
+pub fn open_output(path: Option<&str>) -> std::io::Result<Box<dyn Write>> {
+    match path {
+        Some(path) => Ok(Box::new(std::fs::File::create(path)?)),
+        None => Ok(Box::new(std::io::stdout())),
+    }
+}
+
+/// Create the output writer for a file path (stdout if None) with optional compression
+pub fn create_output_writer(
+    path: Option<&str>,
+    compression: &str,
+) -> std::io::Result<Box<dyn FinishWrite>> {
+    Ok(create_compressed_writer(open_output(path)?, compression))
+}
+
 /// Create a reader for the input file with optional decompression
+///
+/// `buffer_size` is the size of the buffer holding decompressed input.
 pub fn create_input_reader(
     path: &str,
     format_arg: &str,
+    buffer_size: usize,
 ) -> std::io::Result<(Box<dyn BufRead + Send>, String)> {
     let file = std::fs::File::open(path)?;
+    Ok(create_input_reader_from(
+        file,
+        path,
+        format_arg,
+        buffer_size,
+    ))
+}
+
+/// LZ4 reader that continues with the next frame of concatenated frames
+struct MultiLz4Decoder<R: Read>(Lz4Decoder<BufReader<R>>);
+
+impl<R: Read> Read for MultiLz4Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let read = self.0.read(buf)?;
+            // The frame decoder reports the end of each frame as the end of input
+            if read > 0 || buf.is_empty() || self.0.get_mut().fill_buf()?.is_empty() {
+                return Ok(read);
+            }
+        }
+    }
+}
+
+/// Like `create_input_reader`, reading the stored bytes of `path` from `source`
+pub fn create_input_reader_from<S: Read + Send + 'static>(
+    source: S,
+    path: &str,
+    format_arg: &str,
+    buffer_size: usize,
+) -> (Box<dyn BufRead + Send>, String) {
     let format = if format_arg == "auto" {
         detect_format_from_path(path)
     } else {
         format_arg.to_string()
     };
 
+    // Decoders read the stored bytes in pieces of their own buffer size, so
+    // compressed input is read in pieces of `buffer_size` too
+    let mut decoder = if input_compression(path) == "none" {
+        Box::new(source)
+    } else {
+        decompressing_reader(BufReader::with_capacity(buffer_size, source), path)
+    };
+    // Tar archives are streamed member by member
+    if tar::is_tar(path) {
+        decoder = Box::new(tar::TarReader::new(decoder));
+    }
+    (
+        Box::new(BufReader::with_capacity(buffer_size, decoder)),
+        format,
+    )
+}
+
+/// `source` decompressed according to the extension of `path`
+pub fn decompressing_reader<S: Read + Send + 'static>(
+    source: S,
+    path: &str,
+) -> Box<dyn Read + Send> {
     if path.ends_with(".bz2") {
-        let decoder = BzDecoder::new(file);
-        Ok((Box::new(BufReader::new(decoder)), format))
+        Box::new(MultiBzDecoder::new(source))
     } else if path.ends_with(".gz") {
-        let decoder = GzDecoder::new(file);
-        Ok((Box::new(BufReader::new(decoder)), format))
+        Box::new(MultiGzDecoder::new(source))
     } else if path.ends_with(".lz4") {
-        let decoder = Lz4Decoder::new(file);
-        Ok((Box::new(BufReader::new(decoder)), format))
+        Box::new(MultiLz4Decoder(Lz4Decoder::new(BufReader::new(source))))
     } else {
-        Ok((Box::new(BufReader::new(file)), format))
+        Box::new(source)
     }
 }
 
@@ -144,6 +446,8 @@ mod tests {
         assert_eq!(detect_format_from_path("data.nt.gz"), "rdf");
         assert_eq!(detect_format_from_path("data.json.bz2"), "json");
         assert_eq!(detect_format_from_path("data.json.gz"), "json");
+        assert_eq!(detect_format_from_path("data.ttl.bz2"), "turtle");
+        assert_eq!(detect_format_from_path("latest-truthy.ttl.gz"), "turtle");
     }
 
     #[test]
@@ -196,6 +500,91 @@ mod tests {
         assert_eq!(decompressed, test_data);
     }
 
+    /// Writer into a buffer that stays readable after the writer is dropped
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_compressed_writers_finish_on_drop() {
+        let test_data = b"Hello, this is test data that must survive the drop!\n";
+        for compression in ["lz4", "gzip"] {
+            let buffer = SharedBuffer::default();
+            let mut writer = create_compressed_writer(buffer.clone(), compression);
+            writer.write_all(test_data).unwrap();
+            drop(writer);
+
+            let compressed = buffer.0.lock().unwrap().clone();
+            let mut decompressed = Vec::new();
+            if compression == "lz4" {
+                Lz4Decoder::new(&compressed[..])
+                    .read_to_end(&mut decompressed)
+                    .unwrap();
+                // The frame end mark is written
+                assert_eq!(&compressed[compressed.len() - 4..], &[0, 0, 0, 0]);
+            } else {
+                MultiGzDecoder::new(&compressed[..])
+                    .read_to_end(&mut decompressed)
+                    .unwrap();
+            }
+            assert_eq!(decompressed, test_data, "{}", compression);
+        }
+    }
+
+    #[test]
+    fn test_member_writer_ends_members_at_boundaries() {
+        for (path, compression) in [("out.nt.gz", "gzip"), ("out.nt.lz4", "lz4")] {
+            let read = |data: &[u8]| -> std::io::Result<Vec<String>> {
+                let (reader, _) = create_input_reader_from(
+                    std::io::Cursor::new(data.to_vec()),
+                    path,
+                    "auto",
+                    1024,
+                );
+                reader.lines().collect()
+            };
+            let buffer = SharedBuffer::default();
+            let boundary = MemberBoundary::default();
+            let mut writer = create_member_writer(buffer.clone(), compression, boundary.clone());
+            writer.write_all(b"first\n").unwrap();
+            writer.flush().unwrap();
+            let flushed = buffer.0.lock().unwrap().len();
+
+            boundary.request();
+            writer.flush().unwrap();
+            writer.flush().unwrap();
+            let first_member = buffer.0.lock().unwrap().len();
+            // Only the requested boundary adds the gzip trailer or LZ4 end mark
+            assert!(first_member > flushed, "{}", compression);
+            writer.write_all(b"second\n").unwrap();
+            writer.finish().unwrap();
+
+            let stored = buffer.0.lock().unwrap().clone();
+            // The output up to a boundary is complete on its own
+            assert_eq!(
+                read(&stored[..first_member]).unwrap(),
+                ["first"],
+                "{}",
+                compression
+            );
+            assert_eq!(
+                read(&stored).unwrap(),
+                ["first", "second"],
+                "{}",
+                compression
+            );
+        }
+    }
+
     #[test]
     fn test_gzip_roundtrip() {
         use flate2::read::GzDecoder as GzDecoderRead;
@@ -301,4 +690,62 @@ mod tests {
         assert!(std::mem::size_of_val(&writer_none) > 0);
         drop(writer_none);
     }
+
+    #[test]
+    fn test_reads_concatenated_streams() {
+        // bgzip, pigz and pbzip2 write several compressed streams back to back
+        for (path, compression) in [("dump.nt.gz", "gzip"), ("dump.nt.bz2", "bzip2")] {
+            let mut stored = Vec::new();
+            for part in ["first\n", "second\n"] {
+                let mut compressed = Vec::new();
+                if compression == "gzip" {
+                    let mut encoder = GzEncoder::new(&mut compressed, flate2::Compression::fast());
+                    encoder.write_all(part.as_bytes()).unwrap();
+                    encoder.finish().unwrap();
+                } else {
+                    let mut encoder =
+                        bzip2::write::BzEncoder::new(&mut compressed, bzip2::Compression::fast());
+                    encoder.write_all(part.as_bytes()).unwrap();
+                    encoder.finish().unwrap();
+                }
+                stored.extend(compressed);
+            }
+            let (reader, _) =
+                create_input_reader_from(std::io::Cursor::new(stored), path, "auto", 1024);
+            let lines: Vec<String> = reader.lines().map(Result::unwrap).collect();
+            assert_eq!(lines, ["first", "second"], "{}", compression);
+        }
+    }
+
+    #[test]
+    fn test_compressed_input_read_in_buffer_size() {
+        use std::sync::atomic::AtomicUsize;
+
+        /// Records the largest read asked of it
+        struct Source(std::io::Cursor<Vec<u8>>, Arc<AtomicUsize>);
+        impl Read for Source {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.1.fetch_max(buf.len(), Ordering::Relaxed);
+                self.0.read(buf)
+            }
+        }
+
+        let text: String = (0..20_000).map(|i| format!("line {}\n", i)).collect();
+        let mut gzip = GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gzip.write_all(text.as_bytes()).unwrap();
+        let mut lz4 = Lz4Encoder::new(Vec::new());
+        lz4.write_all(text.as_bytes()).unwrap();
+        for (path, stored) in [
+            ("dump.nt.gz", gzip.finish().unwrap()),
+            ("dump.nt.lz4", lz4.finish().unwrap()),
+        ] {
+            let largest = Arc::new(AtomicUsize::new(0));
+            let source = Source(std::io::Cursor::new(stored), largest.clone());
+            let (mut reader, _) = create_input_reader_from(source, path, "auto", 1 << 20);
+            let mut read = String::new();
+            reader.read_to_string(&mut read).unwrap();
+            assert_eq!(read, text);
+            assert_eq!(largest.load(Ordering::Relaxed), 1 << 20, "{}", path);
+        }
+    }
 }
@@ -0,0 +1,202 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::compression::{create_input_reader_from, decompressing_reader, INPUT_BUFFER_SIZE};
+use crate::diff::EntitySink;
+use crate::filter::EntityFilter;
+use crate::http;
+use crate::rdf::{OutputFormat, ProcessingOptions};
+use crate::{run_filter, FilterError};
+
+/// Property datatypes as named in JSON dumps
+pub const DATATYPES: &[&str] = &[
+    "wikibase-item",
+    "wikibase-property",
+    "wikibase-lexeme",
+    "wikibase-form",
+    "wikibase-sense",
+    "string",
+    "external-id",
+    "url",
+    "commonsMedia",
+    "geo-shape",
+    "tabular-data",
+    "monolingualtext",
+    "quantity",
+    "time",
+    "globe-coordinate",
+    "math",
+    "musical-notation",
+    "entity-schema",
+];
+
+const ONTOLOGY_PREFIX: &str = "<http://wikiba.se/ontology#";
+
+/// Properties whose claims are written, by datatype (--property-datatype,
+/// --omit-property-datatype)
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatatypeFilter {
+    pub datatypes: HashSet<String>,
+    /// Drop the claims of these datatypes instead of keeping only them
+    pub omit: bool,
+    /// Datatype of each property, for snaks that do not name theirs (RDF)
+    pub properties: HashMap<String, String>,
+}
+
+impl DatatypeFilter {
+    /// Parse comma-separated datatypes such as `external-id,url`
+    pub fn parse(list: &str, omit: bool) -> Result<Self, FilterError> {
+        let datatypes: HashSet<String> = list
+            .split(',')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(str::to_string)
+            .collect();
+        if datatypes.is_empty() {
+            return Err(FilterError::Parse(
+                "No property datatypes given".to_string(),
+            ));
+        }
+        if let Some(unknown) = datatypes.iter().find(|d| !DATATYPES.contains(&d.as_str())) {
+            return Err(FilterError::Parse(format!(
+                "Unknown property datatype '{}', expected one of {}",
+                unknown,
+                DATATYPES.join(", ")
+            )));
+        }
+        Ok(DatatypeFilter {
+            datatypes,
+            omit,
+            properties: HashMap::new(),
+        })
+    }
+
+    /// Whether the claims of a property are written, by the datatype its
+    /// snaks name or else the one of the property; properties of unknown
+    /// datatype are dropped when keeping and kept when omitting
+    pub fn keeps(&self, property: &str, datatype: Option<&str>) -> bool {
+        match datatype.or_else(|| self.properties.get(property).map(String::as_str)) {
+            Some(datatype) => self.datatypes.contains(datatype) != self.omit,
+            None => self.omit,
+        }
+    }
+}
+
+/// JSON name of an RDF property type such as `<http://wikiba.se/ontology#ExternalId>`
+pub fn from_rdf(object: &str) -> Option<String> {
+    let name = object.strip_prefix(ONTOLOGY_PREFIX)?.strip_suffix('>')?;
+    if name == "CommonsMedia" {
+        return Some("commonsMedia".to_string());
+    }
+    let mut datatype = String::new();
+    for (i, c) in name.char_indices() {
+        if c.is_ascii_uppercase() && i > 0 {
+            datatype.push('-');
+        }
+        datatype.push(c.to_ascii_lowercase());
+    }
+    Some(datatype)
+}
+
+/// Read property datatypes from a file of `P212 external-id` lines,
+/// decompressed by its extension; blank lines and `#` comments are skipped
+pub fn read_datatype_file(path: &str) -> Result<HashMap<String, String>, FilterError> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| FilterError::Parse(format!("Cannot open datatype file '{}': {}", path, e)))?;
+    let mut properties = HashMap::new();
+    for line in BufReader::new(decompressing_reader(file, path)).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [property, datatype] => {
+                properties.insert(property.to_string(), datatype.to_string());
+            }
+            _ => {
+                return Err(FilterError::Parse(format!(
+                    "Expected 'P212 external-id' in {}, found '{}'",
+                    path, line
+                )))
+            }
+        }
+    }
+    Ok(properties)
+}
+
+/// Read the datatypes of the property entities of a dump in one pass
+pub fn scan(
+    input: &str,
+    format: &str,
+    show_progress: bool,
+    http_retries: u32,
+) -> Result<HashMap<String, String>, FilterError> {
+    let filter = Arc::new(EntityFilter {
+        entity_type: "property".to_string(),
+        keep_attributes: Some(HashSet::from(["id".to_string(), "datatype".to_string()])),
+        ..Default::default()
+    });
+    let options = ProcessingOptions {
+        show_progress,
+        batch_size: 0,
+        output_format: OutputFormat::Json,
+        ..Default::default()
+    };
+    let mut properties = HashMap::new();
+    let source = http::open_source(input, 0, http_retries)?;
+    let (reader, format) = create_input_reader_from(source, input, format, INPUT_BUFFER_SIZE);
+    let mut sink = EntitySink::new(|entity| {
+        let id = entity.get("id").and_then(Value::as_str);
+        let datatype = entity.get("datatype").and_then(Value::as_str);
+        if let (Some(id), Some(datatype)) = (id, datatype) {
+            properties.insert(id.to_string(), datatype.to_string());
+        }
+        Ok(())
+    });
+    run_filter(reader, &format, &mut sink, &mut [], &filter, &options)?;
+    Ok(properties)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_rdf() {
+        for (name, datatype) in [
+            ("ExternalId", "external-id"),
+            ("WikibaseItem", "wikibase-item"),
+            ("Url", "url"),
+            ("CommonsMedia", "commonsMedia"),
+            ("Monolingualtext", "monolingualtext"),
+            ("GlobeCoordinate", "globe-coordinate"),
+        ] {
+            let object = format!("{}{}>", ONTOLOGY_PREFIX, name);
+            assert_eq!(from_rdf(&object).as_deref(), Some(datatype));
+            assert!(DATATYPES.contains(&datatype));
+        }
+        assert_eq!(from_rdf("<http://example.org/Url>"), None);
+    }
+
+    #[test]
+    fn test_datatype_filter() {
+        let mut keep = DatatypeFilter::parse("external-id, url", false).unwrap();
+        keep.properties
+            .insert("P856".to_string(), "url".to_string());
+        assert!(keep.keeps("P212", Some("external-id")));
+        assert!(!keep.keeps("P31", Some("wikibase-item")));
+        assert!(keep.keeps("P856", None));
+        assert!(!keep.keeps("P18", None));
+
+        let omit = DatatypeFilter::parse("external-id", true).unwrap();
+        assert!(!omit.keeps("P212", Some("external-id")));
+        assert!(omit.keeps("P31", Some("wikibase-item")) && omit.keeps("P18", None));
+
+        assert!(DatatypeFilter::parse("external_id", false).is_err());
+        assert!(DatatypeFilter::parse(" , ", false).is_err());
+    }
+}
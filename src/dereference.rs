@@ -0,0 +1,378 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::compression::{create_input_reader_from, INPUT_BUFFER_SIZE};
+use crate::diff::EntitySink;
+use crate::filter::EntityFilter;
+use crate::http;
+use crate::idset::IdSet;
+use crate::rdf::{OutputFormat, ProcessingOptions};
+use crate::{run_filter, FilterError};
+
+/// Entity IDs kept in a temporary file between passes, one per line
+///
+/// Only the IDs of the current step are held in memory while the input is
+/// read; the whole set is loaded to drop known IDs and for the last pass.
+pub struct IdFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+    len: u64,
+}
+
+impl IdFile {
+    pub fn create(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(format!("wikidata-werkzeug-ids-{}.txt", std::process::id()));
+        let file = BufWriter::new(
+            File::options()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&path)?,
+        );
+        Ok(Self { path, file, len: 0 })
+    }
+
+    pub fn append<'a>(&mut self, ids: impl IntoIterator<Item = &'a String>) -> io::Result<()> {
+        for id in ids {
+            writeln!(self.file, "{}", id)?;
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// All IDs written so far
+    pub fn load<S: FromIterator<String>>(&mut self) -> io::Result<S> {
+        self.file.flush()?;
+        let mut file = self.file.get_ref();
+        file.seek(SeekFrom::Start(0))?;
+        let ids = BufReader::new(file).lines().collect();
+        self.file.get_mut().seek(SeekFrom::End(0))?;
+        ids
+    }
+}
+
+impl Drop for IdFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Entity IDs the claims of an entity point to
+pub fn referenced_ids(entity: &Value) -> HashSet<String> {
+    EntityFilter::default()
+        .extract_json_claims(entity)
+        .into_values()
+        .flatten()
+        .collect()
+}
+
+/// Properties of the statements, qualifiers and references of an entity
+pub fn used_properties(entity: &Value) -> HashSet<String> {
+    let mut properties = HashSet::new();
+    let claims = entity.get("claims").and_then(Value::as_object);
+    for (property, statements) in claims.into_iter().flatten() {
+        properties.insert(property.clone());
+        for statement in statements.as_array().into_iter().flatten() {
+            let qualifiers = statement.get("qualifiers").and_then(Value::as_object);
+            properties.extend(qualifiers.into_iter().flatten().map(|(p, _)| p.clone()));
+            let references = statement.get("references").and_then(Value::as_array);
+            for reference in references.into_iter().flatten() {
+                let snaks = reference.get("snaks").and_then(Value::as_object);
+                properties.extend(snaks.into_iter().flatten().map(|(p, _)| p.clone()));
+            }
+        }
+    }
+    properties
+}
+
+/// Passes over one input that only need the IDs and claims of entities
+pub struct Passes<'a> {
+    pub input: &'a str,
+    pub format: &'a str,
+    /// Also write the properties the written entities use (--include-properties-used)
+    pub include_properties: bool,
+    pub show_progress: bool,
+    pub http_retries: u32,
+}
+
+impl Passes<'_> {
+    /// Read the input through `filter`, handing each match to `handle`
+    fn run(
+        &self,
+        filter: EntityFilter,
+        mut handle: impl FnMut(&Value) -> io::Result<()>,
+    ) -> Result<(), FilterError> {
+        let filter = Arc::new(EntityFilter {
+            keep_attributes: Some(HashSet::from(["id".to_string(), "claims".to_string()])),
+            omit_attributes: None,
+            ..filter
+        });
+        let options = ProcessingOptions {
+            show_progress: self.show_progress,
+            batch_size: 0,
+            output_format: OutputFormat::Json,
+            ..Default::default()
+        };
+        let source = http::open_source(self.input, 0, self.http_retries)?;
+        let (reader, format) =
+            create_input_reader_from(source, self.input, self.format, INPUT_BUFFER_SIZE);
+        let mut sink = EntitySink::new(|entity| handle(&entity));
+        run_filter(reader, &format, &mut sink, &mut [], &filter, &options)
+    }
+
+    /// The filter of the output pass: the entities matching `base` and those
+    /// their claims point to, up to `depth` steps (--dereference-depth), and
+    /// the properties all of these use (--include-properties-used)
+    ///
+    /// The first pass selects the matching entities, each further pass reads
+    /// the entities referenced in the step before to follow their claims.
+    /// Referenced entities are written whatever the claim, subject, shard,
+    /// references and type filters; the language and attribute filters still
+    /// shape them.
+    pub fn dereference(
+        &self,
+        base: &EntityFilter,
+        depth: u32,
+    ) -> Result<EntityFilter, FilterError> {
+        let mut ids = IdFile::create(&std::env::temp_dir())?;
+        let mut referenced = HashSet::new();
+        let mut properties = HashSet::new();
+        if self.show_progress {
+            eprintln!("Pass 1: selecting entities...");
+        }
+        self.run(base.clone(), |entity| {
+            if let Some(id) = entity.get("id").and_then(Value::as_str) {
+                ids.append([&id.to_string()])?;
+                if depth > 0 {
+                    referenced.extend(referenced_ids(entity));
+                }
+                if self.include_properties {
+                    properties.extend(used_properties(entity));
+                }
+            }
+            Ok(())
+        })?;
+
+        for step in 1..=depth {
+            let known: IdSet = ids.load()?;
+            referenced.retain(|id| !known.contains(id));
+            drop(known);
+            if referenced.is_empty() {
+                break;
+            }
+            ids.append(&referenced)?;
+            if self.show_progress {
+                eprintln!(
+                    "{} more entities referenced at depth {}",
+                    referenced.len(),
+                    step
+                );
+            }
+            // The entities of the last step are read only for their properties
+            let follow = step < depth;
+            if follow || self.include_properties {
+                if self.show_progress {
+                    eprintln!("Pass {}: reading referenced entities...", step + 1);
+                }
+                let filter = EntityFilter {
+                    subject_filter: Some(std::mem::take(&mut referenced).into()),
+                    property_filter: base.property_filter.clone(),
+                    ..Default::default()
+                };
+                self.run(filter, |entity| {
+                    if follow {
+                        referenced.extend(referenced_ids(entity));
+                    }
+                    if self.include_properties {
+                        properties.extend(used_properties(entity));
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+
+        if !properties.is_empty() {
+            let known: IdSet = ids.load()?;
+            properties.retain(|id| !known.contains(id));
+            drop(known);
+            ids.append(&properties)?;
+            if self.show_progress {
+                eprintln!("{} more properties used", properties.len());
+            }
+        }
+
+        if self.show_progress {
+            eprintln!("Writing {} entities...", ids.len());
+        }
+        Ok(EntityFilter {
+            claim_filter: None,
+            subject_filter: Some(ids.load()?),
+            shard: None,
+            reference_filter: None,
+            term_filter: None,
+            badge_filter: None,
+            sitelink_sites: None,
+            min_sitelinks: None,
+            modified_since: None,
+            min_statements: None,
+            max_statements: None,
+            entity_type: EntityFilter::default().entity_type,
+            invert_match: false,
+            ..base.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::claim_parser::parse_claim_filter;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_id_file() {
+        let dir = TempDir::new("ids");
+        let mut ids = IdFile::create(dir.path()).unwrap();
+        ids.append(&["Q1".to_string(), "Q2".to_string()]).unwrap();
+        assert_eq!(ids.load::<Vec<String>>().unwrap().len(), 2);
+        ids.append(&["Q3".to_string()]).unwrap();
+        assert_eq!(
+            ids.load::<HashSet<String>>().unwrap(),
+            HashSet::from(["Q1".to_string(), "Q2".to_string(), "Q3".to_string()])
+        );
+        assert_eq!(ids.len(), 3);
+        drop(ids);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    fn link(property: &str, id: &str) -> String {
+        format!(
+            r#""{}":[{{"mainsnak":{{"datavalue":{{"value":{{"entity-type":"item","id":"{}"}},"type":"wikibase-entityid"}}}}}}]"#,
+            property, id
+        )
+    }
+
+    #[test]
+    fn test_used_properties() {
+        let entity: Value = serde_json::from_str(
+            r#"{"id":"Q42","claims":{"P69":[{"mainsnak":{},
+                "qualifiers":{"P580":[],"P582":[]},
+                "references":[{"snaks":{"P248":[],"P813":[]}}]}],"P18":[]}}"#,
+        )
+        .unwrap();
+        let mut properties: Vec<String> = used_properties(&entity).into_iter().collect();
+        properties.sort();
+        assert_eq!(properties, ["P18", "P248", "P580", "P582", "P69", "P813"]);
+    }
+
+    #[test]
+    fn test_dereference_depth() {
+        let dir = TempDir::new("dereference");
+        let path = dir.join("dump.ndjson");
+        std::fs::write(
+            &path,
+            format!(
+                "{{\"id\":\"Q42\",\"type\":\"item\",\"claims\":{{{},{}}}}}\n\
+                 {{\"id\":\"Q5\",\"type\":\"item\",\"claims\":{{{}}}}}\n\
+                 {{\"id\":\"Q145\",\"type\":\"item\",\"claims\":{{{}}}}}\n\
+                 {{\"id\":\"Q215627\",\"type\":\"item\",\"claims\":{{}}}}\n\
+                 {{\"id\":\"Q46\",\"type\":\"item\",\"claims\":{{}}}}\n\
+                 {{\"id\":\"Q64\",\"type\":\"item\",\"claims\":{{}}}}\n",
+                link("P31", "Q5"),
+                link("P27", "Q145"),
+                link("P279", "Q215627"),
+                link("P30", "Q46"),
+            ),
+        )
+        .unwrap();
+        let input = path.to_string_lossy();
+        let mut passes = Passes {
+            input: &input,
+            format: "auto",
+            include_properties: false,
+            show_progress: false,
+            http_retries: 0,
+        };
+        let base = EntityFilter {
+            claim_filter: Some(parse_claim_filter("P31:Q5").unwrap()),
+            ..Default::default()
+        };
+        let ids = |passes: &Passes, depth| {
+            let mut ids: Vec<String> = passes
+                .dereference(&base, depth)
+                .unwrap()
+                .subject_filter
+                .unwrap()
+                .iter()
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(&passes, 0), ["Q42"]);
+        assert_eq!(ids(&passes, 1), ["Q145", "Q42", "Q5"]);
+        assert_eq!(ids(&passes, 2), ["Q145", "Q215627", "Q42", "Q46", "Q5"]);
+        assert_eq!(ids(&passes, 5), ids(&passes, 2));
+        passes.include_properties = true;
+        assert_eq!(ids(&passes, 0), ["P27", "P31", "Q42"]);
+        assert_eq!(
+            ids(&passes, 1),
+            ["P27", "P279", "P30", "P31", "Q145", "Q42", "Q5"]
+        );
+    }
+
+    #[test]
+    fn test_include_properties_used_rdf() {
+        let dir = TempDir::new("dereference-rdf");
+        let path = dir.join("dump.nt");
+        std::fs::write(
+            &path,
+            "\
+<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .
+<http://www.wikidata.org/entity/Q5> <http://www.w3.org/2000/01/rdf-schema#label> \"human\"@en .
+<http://www.wikidata.org/entity/P31> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://wikiba.se/ontology#Property> .
+<http://www.wikidata.org/entity/P31> <http://www.w3.org/2000/01/rdf-schema#label> \"instance of\"@en .
+<http://www.wikidata.org/entity/P279> <http://www.w3.org/2000/01/rdf-schema#label> \"subclass of\"@en .
+",
+        )
+        .unwrap();
+        let input = path.to_string_lossy();
+        let passes = Passes {
+            input: &input,
+            format: "auto",
+            include_properties: true,
+            show_progress: false,
+            http_retries: 0,
+        };
+        let base = EntityFilter {
+            claim_filter: Some(parse_claim_filter("P31:Q5").unwrap()),
+            ..Default::default()
+        };
+        let filter = Arc::new(passes.dereference(&base, 0).unwrap());
+
+        let (reader, format) = create_input_reader_from(
+            File::open(&path).unwrap(),
+            &input,
+            "auto",
+            INPUT_BUFFER_SIZE,
+        );
+        let mut output = Vec::new();
+        let options = ProcessingOptions::default();
+        run_filter(reader, &format, &mut output, &mut [], &filter, &options).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("<http://www.wikidata.org/entity/Q42> "));
+        assert!(output.contains(
+            "<http://www.wikidata.org/entity/P31> <http://www.w3.org/2000/01/rdf-schema#label> \"instance of\"@en ."
+        ));
+        assert!(!output.contains("human") && !output.contains("subclass of"));
+    }
+}
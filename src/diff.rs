@@ -0,0 +1,346 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use crate::claim_parser::parse_claim_filter;
+use crate::compression::{
+    create_input_reader, create_output_writer, determine_compression, finish_output,
+    INPUT_BUFFER_SIZE, OUTPUT_BUFFER_SIZE,
+};
+use crate::filter::EntityFilter;
+use crate::idset::IdSet;
+use crate::rdf::{OutputFormat, ProcessingOptions};
+use crate::sort::EntityKey;
+use crate::{run_filter, FilterError};
+
+/// Arguments for the `diff` subcommand
+#[derive(clap::Args, Debug)]
+pub struct DiffArgs {
+    /// Older dump or extract (supports .bz2, .gz, .lz4)
+    #[arg()]
+    old: String,
+
+    /// Newer dump or extract
+    #[arg()]
+    new: String,
+
+    /// Input format of both: auto, rdf, turtle, json
+    #[arg(short = 'f', long, default_value = "auto")]
+    format: String,
+
+    /// Output file for the change records (stdout if not provided)
+    #[arg(long)]
+    output: Option<String>,
+
+    /// Filter by claim (e.g., P31:Q5, P31:Q5,Q6256, P31:Q5&P18)
+    #[arg(short, long)]
+    claim: Option<String>,
+
+    /// Entity type to filter: item, property, or both
+    #[arg(short = 't', long, default_value = "both")]
+    r#type: String,
+
+    /// Keep only specified subject entity IDs (comma-separated, e.g., Q31,Q42)
+    #[arg(long)]
+    subject: Option<String>,
+
+    /// Compare only labels/descriptions/aliases in these languages (comma-separated)
+    #[arg(short = 'l', long)]
+    languages: Option<String>,
+
+    /// Leave the entities out of the change records
+    #[arg(long)]
+    ids_only: bool,
+
+    /// Show progress info on stderr
+    #[arg(short = 'p', long)]
+    progress: bool,
+}
+
+/// Revision metadata, which changes with every edit and is not compared
+const METADATA: [&str; 5] = ["lastrevid", "modified", "pageid", "ns", "title"];
+
+/// Top-level fields compared per language, property or site
+const KEYED_FIELDS: [&str; 5] = ["labels", "descriptions", "aliases", "claims", "sitelinks"];
+
+/// Hashes of the parts of an entity (`labels.en`, `claims.P31`, `type`), sorted by name
+type Fingerprint = Vec<(String, u64)>;
+
+fn hash_value(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Fingerprint of a canonical JSON entity
+fn fingerprint(entity: &Value) -> Fingerprint {
+    let mut parts = Vec::new();
+    for (key, value) in entity.as_object().into_iter().flatten() {
+        if METADATA.contains(&key.as_str()) {
+            continue;
+        }
+        match value.as_object() {
+            Some(map) if KEYED_FIELDS.contains(&key.as_str()) => {
+                for (sub_key, sub_value) in map {
+                    parts.push((format!("{}.{}", key, sub_key), hash_value(sub_value)));
+                }
+            }
+            _ => parts.push((key.clone(), hash_value(value))),
+        }
+    }
+    parts.sort_unstable();
+    parts
+}
+
+/// Names of the parts that differ between two fingerprints
+fn changed_fields(old: &Fingerprint, new: &Fingerprint) -> Vec<String> {
+    let old_parts: HashMap<&str, u64> = old.iter().map(|(k, h)| (k.as_str(), *h)).collect();
+    let new_parts: HashMap<&str, u64> = new.iter().map(|(k, h)| (k.as_str(), *h)).collect();
+    let mut fields: Vec<String> = old_parts
+        .keys()
+        .chain(new_parts.keys())
+        .filter(|key| old_parts.get(*key) != new_parts.get(*key))
+        .map(|key| key.to_string())
+        .collect();
+    fields.sort_unstable();
+    fields.dedup();
+    fields
+}
+
+/// Counts of the change records written
+#[derive(Default, Debug, PartialEq)]
+struct Summary {
+    added: u64,
+    removed: u64,
+    changed: u64,
+    unchanged: u64,
+}
+
+/// Compares the entities of the newer input against the fingerprints of the
+/// older one, writing a change record for each difference
+struct Comparison<W: Write> {
+    old: HashMap<String, Fingerprint>,
+    seen: HashSet<String>,
+    output: W,
+    ids_only: bool,
+    summary: Summary,
+}
+
+impl<W: Write> Comparison<W> {
+    fn record(&mut self, record: Value) -> io::Result<()> {
+        serde_json::to_writer(&mut self.output, &record)?;
+        self.output.write_all(b"\n")
+    }
+
+    /// Compare one entity of the newer input
+    fn compare(&mut self, entity: Value) -> io::Result<()> {
+        let Some(id) = entity.get("id").and_then(Value::as_str).map(str::to_string) else {
+            return Ok(());
+        };
+        let new = fingerprint(&entity);
+        let entity = if self.ids_only { None } else { Some(entity) };
+        match self.old.get(&id) {
+            None => {
+                self.summary.added += 1;
+                let mut record = json!({"id": id, "change": "added"});
+                if let Some(entity) = entity {
+                    record["entity"] = entity;
+                }
+                self.record(record)?;
+            }
+            Some(old) => {
+                let fields = changed_fields(old, &new);
+                if fields.is_empty() {
+                    self.summary.unchanged += 1;
+                } else {
+                    self.summary.changed += 1;
+                    let mut record = json!({"id": id, "change": "changed", "fields": fields});
+                    if let Some(entity) = entity {
+                        record["entity"] = entity;
+                    }
+                    self.record(record)?;
+                }
+            }
+        }
+        self.seen.insert(id);
+        Ok(())
+    }
+
+    /// Write the entities of the older input that are missing from the newer
+    /// one, in ID order
+    fn finish(&mut self) -> io::Result<()> {
+        let mut removed: Vec<&String> = self
+            .old
+            .keys()
+            .filter(|id| !self.seen.contains(*id))
+            .collect();
+        removed.sort_by_cached_key(|id| EntityKey::new(id));
+        let records: Vec<Value> = removed
+            .into_iter()
+            .map(|id| json!({"id": id, "change": "removed"}))
+            .collect();
+        for record in records {
+            self.summary.removed += 1;
+            self.record(record)?;
+        }
+        self.output.flush()
+    }
+}
+
+/// Write sink that hands each NDJSON line it receives to `handle`
+pub struct EntitySink<F: FnMut(Value) -> io::Result<()>> {
+    pending: Vec<u8>,
+    handle: F,
+}
+
+impl<F: FnMut(Value) -> io::Result<()>> EntitySink<F> {
+    pub fn new(handle: F) -> Self {
+        Self {
+            pending: Vec::new(),
+            handle,
+        }
+    }
+}
+
+impl<F: FnMut(Value) -> io::Result<()>> Write for EntitySink<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let mut start = 0;
+        while let Some(pos) = memchr::memchr(b'\n', &self.pending[start..]) {
+            let line = &self.pending[start..start + pos];
+            if !line.is_empty() {
+                (self.handle)(serde_json::from_slice(line)?)?;
+            }
+            start += pos + 1;
+        }
+        self.pending.drain(..start);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Run the `diff` subcommand
+///
+/// Both inputs go through the filter and are converted to canonical JSON, so
+/// dumps in different formats and statement order can be compared. The older
+/// input is held in memory as per-field hashes.
+pub fn run(args: DiffArgs) -> Result<(), FilterError> {
+    let claim_filter = args.claim.as_deref().map(parse_claim_filter).transpose()?;
+    let split_ids =
+        |s: &String| -> HashSet<String> { s.split(',').map(|id| id.trim().to_string()).collect() };
+    let filter = Arc::new(EntityFilter {
+        claim_filter,
+        subject_filter: args.subject.as_ref().map(split_ids).map(IdSet::from),
+        language_filter: args.languages.as_ref().map(split_ids),
+        entity_type: args.r#type.clone(),
+        ..Default::default()
+    });
+    let options = ProcessingOptions {
+        show_progress: args.progress,
+        batch_size: 0,
+        output_format: OutputFormat::Json,
+        canonical: true,
+        ..Default::default()
+    };
+
+    let mut old = HashMap::new();
+    let (reader, format) = create_input_reader(&args.old, &args.format, INPUT_BUFFER_SIZE)?;
+    let mut sink = EntitySink::new(|entity: Value| {
+        if let Some(id) = entity.get("id").and_then(Value::as_str) {
+            old.insert(id.to_string(), fingerprint(&entity));
+        }
+        Ok(())
+    });
+    run_filter(reader, &format, &mut sink, &mut [], &filter, &options)?;
+
+    let compression = determine_compression("none", args.output.as_deref());
+    let output = BufWriter::with_capacity(
+        OUTPUT_BUFFER_SIZE,
+        create_output_writer(args.output.as_deref(), &compression)?,
+    );
+    let mut comparison = Comparison {
+        old,
+        seen: HashSet::new(),
+        output,
+        ids_only: args.ids_only,
+        summary: Summary::default(),
+    };
+    let (reader, format) = create_input_reader(&args.new, &args.format, INPUT_BUFFER_SIZE)?;
+    let mut sink = EntitySink::new(|entity| comparison.compare(entity));
+    run_filter(reader, &format, &mut sink, &mut [], &filter, &options)?;
+    comparison.finish()?;
+    finish_output(comparison.output)?;
+
+    let summary = comparison.summary;
+    eprintln!(
+        "{} added, {} removed, {} changed, {} unchanged entities",
+        summary.added, summary.removed, summary.changed, summary.unchanged
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comparison() {
+        let entity = |json: &str| -> Value { serde_json::from_str(json).unwrap() };
+        let mut old = HashMap::new();
+        for json in [
+            r#"{"id":"Q1","type":"item","labels":{"en":{"language":"en","value":"one"}},"lastrevid":1}"#,
+            r#"{"id":"Q2","type":"item","labels":{"en":{"language":"en","value":"two"}},"claims":{"P31":[]}}"#,
+            r#"{"id":"Q3","type":"item"}"#,
+            r#"{"id":"Q10","type":"item"}"#,
+        ] {
+            let entity = entity(json);
+            old.insert(
+                entity["id"].as_str().unwrap().to_string(),
+                fingerprint(&entity),
+            );
+        }
+        let mut comparison = Comparison {
+            old,
+            seen: HashSet::new(),
+            output: Vec::new(),
+            ids_only: true,
+            summary: Summary::default(),
+        };
+        // Only the revision changed
+        comparison
+            .compare(entity(r#"{"id":"Q1","type":"item","labels":{"en":{"language":"en","value":"one"}},"lastrevid":2}"#))
+            .unwrap();
+        comparison
+            .compare(entity(r#"{"id":"Q2","type":"item","labels":{"en":{"language":"en","value":"zwei"}},"aliases":{"en":[]}}"#))
+            .unwrap();
+        comparison
+            .compare(entity(r#"{"id":"Q4","type":"item"}"#))
+            .unwrap();
+        comparison.finish().unwrap();
+
+        assert_eq!(
+            comparison.summary,
+            Summary {
+                added: 1,
+                removed: 2,
+                changed: 1,
+                unchanged: 1
+            }
+        );
+        assert_eq!(
+            String::from_utf8(comparison.output).unwrap(),
+            r#"{"change":"changed","fields":["aliases.en","claims.P31","labels.en"],"id":"Q2"}
+{"change":"added","id":"Q4"}
+{"change":"removed","id":"Q3"}
+{"change":"removed","id":"Q10"}
+"#
+        );
+    }
+}
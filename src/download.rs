@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::http;
+use crate::FilterError;
+
+/// Where the status of the current Wikidata entity dumps is published
+pub const DEFAULT_STATUS_URL: &str =
+    "https://dumps.wikimedia.org/wikidatawiki/entities/dumpstatus.json";
+
+/// Arguments for the `download` subcommand
+#[derive(clap::Args, Debug)]
+pub struct DownloadArgs {
+    /// Dump to download: latest-all, latest-truthy, latest-lexemes, optionally
+    /// with extensions (latest-all.json.bz2), or a file name from the status
+    #[arg()]
+    dump: String,
+
+    /// Directory to download into (created if missing)
+    #[arg(long, default_value = ".")]
+    dir: String,
+
+    /// dumpstatus.json listing the files of the current dumps (URL or file)
+    #[arg(long, default_value = DEFAULT_STATUS_URL)]
+    status_url: String,
+
+    /// Reconnects with a Range request after transient network errors
+    #[arg(long, default_value_t = http::DEFAULT_RETRIES)]
+    http_retries: u32,
+}
+
+/// dumpstatus.json: jobs with the files they produced
+#[derive(Deserialize, Debug)]
+struct DumpStatus {
+    jobs: BTreeMap<String, Job>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Job {
+    status: String,
+    #[serde(default)]
+    files: BTreeMap<String, DumpFile>,
+}
+
+/// A published dump file and its checksums
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct DumpFile {
+    url: String,
+    size: Option<u64>,
+    md5: Option<String>,
+    sha1: Option<String>,
+}
+
+/// Default extensions of the dumps when only their name is given
+fn default_extensions(kind: &str) -> &'static str {
+    match kind {
+        "truthy" => "nt.gz",
+        _ => "json.gz",
+    }
+}
+
+/// Kind and extensions of a dated dump file name, with its date:
+/// `wikidata-20261012-truthy-BETA.nt.gz` is ("20261012", "truthy.nt.gz")
+fn dump_kind(name: &str) -> Option<(&str, String)> {
+    let mut rest = name;
+    loop {
+        let (segment, tail) = rest.split_once('-')?;
+        if segment.len() == 8 && segment.bytes().all(|b| b.is_ascii_digit()) {
+            return Some((segment, tail.replacen("-BETA", "", 1)));
+        }
+        rest = tail;
+    }
+}
+
+/// The newest finished file matching `dump`, with its name
+fn resolve(status: &DumpStatus, dump: &str) -> Option<(String, DumpFile)> {
+    let finished = || {
+        status
+            .jobs
+            .values()
+            .filter(|job| job.status == "done")
+            .flat_map(|job| job.files.iter())
+    };
+    if let Some((name, file)) = finished().find(|(name, _)| name.as_str() == dump) {
+        return Some((name.clone(), file.clone()));
+    }
+    let kind = dump.strip_prefix("latest-").unwrap_or(dump);
+    let wanted = match kind.split_once('.') {
+        Some(_) => kind.to_string(),
+        None => format!("{}.{}", kind, default_extensions(kind)),
+    };
+    finished()
+        .filter_map(|(name, file)| {
+            let (date, kind) = dump_kind(name)?;
+            (kind == wanted).then_some((date, name, file))
+        })
+        .max_by_key(|(date, name, _)| (*date, name.as_str()))
+        .map(|(_, name, file)| (name.clone(), file.clone()))
+}
+
+/// Absolute URL of a file listed in the status at `status_url`
+fn file_url(status_url: &str, url: &str) -> String {
+    if http::is_url(url) {
+        return url.to_string();
+    }
+    if let Some(path) = url.strip_prefix('/') {
+        // Origin of the status URL: scheme and host
+        let origin_end = status_url
+            .find("://")
+            .and_then(|scheme| status_url[scheme + 3..].find('/').map(|i| i + scheme + 3))
+            .unwrap_or(status_url.len());
+        return format!("{}/{}", &status_url[..origin_end], path);
+    }
+    let dir = status_url.rfind('/').map_or("", |i| &status_url[..=i]);
+    format!("{}{}", dir, url)
+}
+
+/// Problem with a downloaded file's size or checksums, if any
+fn verify(path: &Path, file: &DumpFile) -> io::Result<Option<String>> {
+    use md5::{Digest, Md5};
+    use sha1::Sha1;
+
+    let size = fs::metadata(path)?.len();
+    if let Some(expected) = file.size.filter(|&expected| expected != size) {
+        return Ok(Some(format!(
+            "size is {} bytes, expected {}",
+            size, expected
+        )));
+    }
+    let (mut md5, mut sha1) = (Md5::new(), Sha1::new());
+    let mut input = fs::File::open(path)?;
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let read = input.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        md5.update(&buf[..read]);
+        sha1.update(&buf[..read]);
+    }
+    let checks = [
+        ("md5", &file.md5, hex(&md5.finalize())),
+        ("sha1", &file.sha1, hex(&sha1.finalize())),
+    ];
+    for (name, expected, actual) in checks {
+        if let Some(expected) = expected {
+            if !expected.eq_ignore_ascii_case(&actual) {
+                return Ok(Some(format!(
+                    "{} is {}, published {}",
+                    name, actual, expected
+                )));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Download `url` into `part`, continuing after the bytes already in it
+fn fetch(url: &str, part: &Path, size: Option<u64>, retries: u32) -> io::Result<()> {
+    let mut offset = fs::metadata(part).map_or(0, |metadata| metadata.len());
+    if size.is_some_and(|size| offset > size) {
+        offset = 0;
+    }
+    if size == Some(offset) {
+        return Ok(());
+    }
+    if offset > 0 {
+        eprintln!("Resuming {} at byte {}", part.display(), offset);
+    }
+    let mut input = http::open_source(url, offset, retries)?;
+    let mut output = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(offset == 0)
+        .append(offset > 0)
+        .open(part)?;
+    let mut buf = vec![0u8; 1 << 20];
+    let mut last_report = Instant::now();
+    loop {
+        let read = match input.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        output.write_all(&buf[..read])?;
+        offset += read as u64;
+        if last_report.elapsed() >= Duration::from_secs(10) {
+            last_report = Instant::now();
+            match size {
+                Some(size) => eprintln!(
+                    "Downloaded {} of {} MiB ({:.1}%)",
+                    offset >> 20,
+                    size >> 20,
+                    offset as f64 * 100.0 / size.max(1) as f64
+                ),
+                None => eprintln!("Downloaded {} MiB", offset >> 20),
+            }
+        }
+    }
+    output.flush()
+}
+
+/// Download the current file of a dump into `--dir` and verify its checksums;
+/// prints the path of the verified file
+pub fn run(args: DownloadArgs) -> Result<(), FilterError> {
+    let status: DumpStatus =
+        serde_json::from_reader(http::open_source(&args.status_url, 0, args.http_retries)?)?;
+    let (name, file) = resolve(&status, &args.dump).ok_or_else(|| {
+        FilterError::Parse(format!(
+            "no finished dump matching '{}' in {}",
+            args.dump, args.status_url
+        ))
+    })?;
+    let url = file_url(&args.status_url, &file.url);
+
+    fs::create_dir_all(&args.dir)?;
+    let path = Path::new(&args.dir).join(&name);
+    let part = PathBuf::from(format!("{}.part", path.display()));
+    let downloaded = fs::metadata(&path).ok().map(|metadata| metadata.len());
+    if downloaded.is_some_and(|len| file.size.is_none_or(|size| len == size)) {
+        eprintln!("{} is already downloaded", path.display());
+        println!("{}", path.display());
+        return Ok(());
+    }
+
+    eprintln!(
+        "Downloading {}{} to {}",
+        url,
+        file.size
+            .map_or(String::new(), |size| format!(" ({} MiB)", size >> 20)),
+        path.display()
+    );
+    fetch(&url, &part, file.size, args.http_retries)?;
+    eprintln!("Verifying {}", part.display());
+    if let Some(problem) = verify(&part, &file)? {
+        fs::remove_file(&part)?;
+        return Err(FilterError::Parse(format!(
+            "{} is corrupt ({}); removed it, run again to download it anew",
+            name, problem
+        )));
+    }
+    fs::rename(&part, &path)?;
+    eprintln!("Verified {}", path.display());
+    println!("{}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    const STATUS: &str = r#"{
+        "jobs": {
+            "json": {"status": "done", "files": {
+                "wikidata-20261005-all.json.gz": {"url": "/wikidatawiki/entities/20261005/wikidata-20261005-all.json.gz", "size": 10, "md5": "a"},
+                "wikidata-20261012-all.json.gz": {"url": "/wikidatawiki/entities/20261012/wikidata-20261012-all.json.gz", "size": 12, "md5": "b"},
+                "wikidata-20261012-all.json.bz2": {"url": "20261012/wikidata-20261012-all.json.bz2"}
+            }},
+            "truthy": {"status": "done", "files": {
+                "wikidata-20261012-truthy-BETA.nt.gz": {"url": "/x/wikidata-20261012-truthy-BETA.nt.gz"}
+            }},
+            "lexemes": {"status": "in-progress", "files": {
+                "wikidata-20261012-lexemes.json.gz": {"url": "/x/wikidata-20261012-lexemes.json.gz"}
+            }}
+        },
+        "version": "0.8"
+    }"#;
+
+    #[test]
+    fn test_resolve() {
+        let status: DumpStatus = serde_json::from_str(STATUS).unwrap();
+        let name = |dump| resolve(&status, dump).map(|(name, _)| name);
+        assert_eq!(
+            name("latest-all").as_deref(),
+            Some("wikidata-20261012-all.json.gz")
+        );
+        assert_eq!(
+            name("latest-all.json.bz2").as_deref(),
+            Some("wikidata-20261012-all.json.bz2")
+        );
+        assert_eq!(
+            name("latest-truthy").as_deref(),
+            Some("wikidata-20261012-truthy-BETA.nt.gz")
+        );
+        assert_eq!(
+            name("wikidata-20261005-all.json.gz").as_deref(),
+            Some("wikidata-20261005-all.json.gz")
+        );
+        // Unfinished jobs are left out
+        assert_eq!(name("latest-lexemes"), None);
+    }
+
+    #[test]
+    fn test_file_url() {
+        let status = "https://dumps.wikimedia.org/wikidatawiki/entities/dumpstatus.json";
+        assert_eq!(
+            file_url(status, "/wikidatawiki/entities/20261012/a.json.gz"),
+            "https://dumps.wikimedia.org/wikidatawiki/entities/20261012/a.json.gz"
+        );
+        assert_eq!(
+            file_url(status, "20261012/a.json.gz"),
+            "https://dumps.wikimedia.org/wikidatawiki/entities/20261012/a.json.gz"
+        );
+        assert_eq!(
+            file_url(status, "https://mirror.example/a.json.gz"),
+            "https://mirror.example/a.json.gz"
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let dir = TempDir::new("download");
+        let path = dir.join("dump");
+        fs::write(&path, "hello\n").unwrap();
+        let mut file = DumpFile {
+            url: String::new(),
+            size: Some(6),
+            md5: Some("b1946ac92492d2347c6235b4d2611184".to_string()),
+            sha1: Some("F572D396FAE9206628714FB2CE00F72E94F2258F".to_string()),
+        };
+        assert_eq!(verify(&path, &file).unwrap(), None);
+        file.md5 = Some("00000000000000000000000000000000".to_string());
+        assert!(verify(&path, &file).unwrap().unwrap().starts_with("md5"));
+        file.size = Some(7);
+        assert!(verify(&path, &file).unwrap().unwrap().starts_with("size"));
+    }
+}
@@ -0,0 +1,318 @@
+use crate::compression::determine_compression;
+use crate::filter::{ClaimFilter, EntityFilter};
+use crate::idset::IdSet;
+use crate::rdf::ProcessingOptions;
+use crate::route::RouteSpec;
+
+/// Input and output of a run as resolved from the arguments
+pub struct RunPlan<'a> {
+    pub input: Option<&'a str>,
+    pub input_format: &'a str,
+    pub input_compression: &'a str,
+    pub output: Option<&'a str>,
+    pub compression: &'a str,
+    /// Path and compression of --output-rejected
+    pub rejected: Option<(&'a str, &'a str)>,
+    pub routes: &'a [RouteSpec],
+}
+
+/// Most set items listed before the rest is summarized by its count
+const MAX_LISTED: usize = 20;
+
+/// Sorted, comma-separated set, or `all` when there is no filter
+fn set_list<S: AsRef<str>>(set: Option<impl IntoIterator<Item = S>>) -> String {
+    match set {
+        Some(set) => {
+            let mut items: Vec<S> = set.into_iter().collect();
+            items.sort_unstable_by(|a, b| a.as_ref().cmp(b.as_ref()));
+            let items: Vec<&str> = items.iter().map(AsRef::as_ref).collect();
+            if items.len() > MAX_LISTED {
+                format!(
+                    "{}, ... ({} in total)",
+                    items[..MAX_LISTED].join(", "),
+                    items.len()
+                )
+            } else {
+                items.join(", ")
+            }
+        }
+        None => "all".to_string(),
+    }
+}
+
+/// Append a claim filter tree, one indented node per line
+fn push_claim_filter(description: &mut String, label: &str, claim_filter: Option<&ClaimFilter>) {
+    match claim_filter {
+        Some(claim_filter) => {
+            description.push_str(&format!("{}:\n", label));
+            for line in claim_filter.tree().lines() {
+                description.push_str(&format!("  {}\n", line));
+            }
+        }
+        None => description.push_str(&format!("{}: none\n", label)),
+    }
+}
+
+/// Describe what a run would do, for --dry-run
+pub fn describe(plan: &RunPlan, filter: &EntityFilter, options: &ProcessingOptions) -> String {
+    let format = options.output_format.name();
+    let mut lines = vec![format!(
+        "Input:       {} ({}, compression: {})",
+        plan.input.unwrap_or("stdin"),
+        plan.input_format,
+        plan.input_compression
+    )];
+    match options.partition {
+        Some(ref partition) => lines.push(format!(
+            "Partition:   {} into {} ({}, compression: {})",
+            partition.describe(),
+            partition.template,
+            format,
+            plan.compression
+        )),
+        None if plan.routes.is_empty() => lines.push(format!(
+            "Output:      {} ({}, compression: {})",
+            plan.output.unwrap_or("stdout"),
+            format,
+            plan.compression
+        )),
+        None => {}
+    }
+    for route in plan.routes {
+        lines.push(format!(
+            "Route:       {} -> {} ({}, compression: {})",
+            route.name,
+            route.path,
+            format,
+            determine_compression("none", Some(&route.path))
+        ));
+    }
+    if let Some((path, compression)) = plan.rejected {
+        lines.push(format!(
+            "Rejected:    {} ({}, compression: {})",
+            path, format, compression
+        ));
+    }
+    lines.extend([
+        format!("Type:        {}", filter.entity_type),
+        format!(
+            "Subjects:    {}",
+            set_list(filter.subject_filter.as_ref().map(IdSet::iter))
+        ),
+    ]);
+    if let Some(shard) = filter.shard {
+        lines.push(format!("Shard:       {}/{}", shard.index, shard.count));
+    }
+    if let Some(ref since) = filter.modified_since {
+        lines.push(format!("Modified:    {} or later", since));
+    }
+    if let Some(ref values) = filter.reference_filter {
+        lines.push(format!("References:  {}", set_list(Some(values))));
+    }
+    if let Some(ref terms) = filter.term_filter {
+        lines.push(format!("Terms:       {}", terms));
+    }
+    if let Some(ref badges) = filter.badge_filter {
+        let sites = match badges.sites {
+            Some(ref sites) => format!(" on {}", set_list(Some(sites))),
+            None => String::new(),
+        };
+        lines.push(format!(
+            "Badges:      {}{}",
+            set_list(Some(&badges.badges)),
+            sites
+        ));
+    }
+    if let Some(ref sites) = filter.sitelink_sites {
+        lines.push(format!("Sitelinks:   on {}", set_list(Some(sites))));
+    }
+    if let Some(min) = filter.min_sitelinks {
+        lines.push(format!("Sitelinks:   at least {}", min));
+    }
+    if let Some(bounds) = filter.statement_bounds() {
+        lines.push(format!("Statements:  {} per entity", bounds));
+    }
+    lines.push(format!(
+        "Properties:  {}",
+        set_list(filter.property_filter.as_ref())
+    ));
+    if let Some(ref datatypes) = filter.datatype_filter {
+        lines.push(format!(
+            "Datatypes:   {}{}",
+            if datatypes.omit { "all but " } else { "" },
+            set_list(Some(&datatypes.datatypes))
+        ));
+    }
+    let subvariants = match filter.language_filter {
+        Some(_) if filter.language_include_subvariants => " (and subvariants)",
+        _ => "",
+    };
+    let mul = match filter.language_filter {
+        Some(_) if filter.materialize_mul => ", mul copied into each",
+        Some(_) if filter.include_mul => ", and mul",
+        _ => "",
+    };
+    lines.push(format!(
+        "Languages:   {}{}{}",
+        set_list(filter.language_filter.as_ref()),
+        subvariants,
+        mul
+    ));
+    if filter.keep_attributes.is_some() {
+        lines.push(format!(
+            "Keep:        {}",
+            set_list(filter.keep_attributes.as_ref())
+        ));
+    }
+    if filter.omit_attributes.is_some() {
+        lines.push(format!(
+            "Omit:        {}",
+            set_list(filter.omit_attributes.as_ref())
+        ));
+    }
+    if filter.best_rank_only {
+        lines.push("Statements:  best rank per property".to_string());
+    }
+    if filter.exclude_special_values {
+        lines.push("Statements:  without unknown and no values".to_string());
+    }
+    if let Some(ref conversions) = filter.unit_conversions {
+        lines.push(format!(
+            "Units:       {} conversion factors",
+            conversions.factors.len()
+        ));
+    }
+    if let Some(form) = filter.unicode_form {
+        lines.push(format!("Unicode:     {}", form.name()));
+    }
+    let omitted: Vec<&str> = [
+        (filter.omit_qualifiers, "qualifiers"),
+        (filter.omit_references, "references"),
+    ]
+    .into_iter()
+    .filter_map(|(omit, part)| omit.then_some(part))
+    .collect();
+    if !omitted.is_empty() {
+        lines.push(format!("Statements:  without {}", omitted.join(" and ")));
+    }
+    if filter.claims_only {
+        lines.push("Triples:     claims and terms only".to_string());
+    }
+    if let Some(ref properties) = filter.keep_qualifiers {
+        lines.push(format!("Qualifiers:  {}", set_list(Some(properties))));
+    }
+
+    let mut limits = Vec::new();
+    if options.skip_lines > 0 {
+        limits.push(format!("skip {} lines", options.skip_lines));
+    }
+    if options.max_lines != u64::MAX {
+        limits.push(format!("at most {} lines", options.max_lines));
+    }
+    if options.skip_entities > 0 {
+        limits.push(format!("skip {} entities", options.skip_entities));
+    }
+    if options.max_entities != u64::MAX {
+        limits.push(format!("at most {} matches", options.max_entities));
+    }
+    if !limits.is_empty() {
+        lines.push(format!("Limits:      {}", limits.join(", ")));
+    }
+    if filter.invert_match {
+        lines.push("Invert:      writing the entities the filters reject".to_string());
+    }
+    if let Some(ref sort) = options.sort {
+        lines.push(format!(
+            "Sort:        by entity ID, runs of {} MiB in {}",
+            sort.memory >> 20,
+            sort.dir.display()
+        ));
+    }
+
+    let mut description = lines.join("\n") + "\n";
+    if plan.routes.is_empty() {
+        push_claim_filter(
+            &mut description,
+            "Claim filter",
+            filter.claim_filter.as_ref(),
+        );
+    }
+    for route in plan.routes {
+        let label = format!("Route {}", route.name);
+        push_claim_filter(&mut description, &label, Some(&route.claim_filter));
+    }
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::claim_parser::parse_claim_filter;
+    use crate::partition::Partition;
+    use crate::rdf::OutputFormat;
+
+    #[test]
+    fn test_describe() {
+        let filter = EntityFilter {
+            claim_filter: Some(parse_claim_filter("P31:Q5&~P570").unwrap()),
+            language_filter: Some(["en".to_string(), "de".to_string()].into()),
+            omit_attributes: Some(["sitelinks".to_string()].into()),
+            entity_type: "item".to_string(),
+            ..Default::default()
+        };
+        let options = ProcessingOptions {
+            output_format: OutputFormat::Json,
+            max_entities: 10,
+            ..Default::default()
+        };
+        let plan = RunPlan {
+            input: Some("latest-all.json.bz2"),
+            input_format: "json",
+            input_compression: "bzip2",
+            output: None,
+            compression: "none",
+            rejected: Some(("rest.ndjson.gz", "gzip")),
+            routes: &[],
+        };
+        assert_eq!(
+            describe(&plan, &filter, &options),
+            "Input:       latest-all.json.bz2 (json, compression: bzip2)\n\
+             Output:      stdout (json, compression: none)\n\
+             Rejected:    rest.ndjson.gz (json, compression: gzip)\n\
+             Type:        item\n\
+             Subjects:    all\n\
+             Properties:  all\n\
+             Languages:   de, en (and subvariants)\n\
+             Omit:        sitelinks\n\
+             Limits:      at most 10 matches\n\
+             Claim filter:\n\
+             \x20 AND\n\
+             \x20   P31 = Q5\n\
+             \x20   NOT\n\
+             \x20     P570 (any value)\n"
+        );
+    }
+
+    #[test]
+    fn test_describe_partition() {
+        let options = ProcessingOptions {
+            partition: Some(Partition::new("P31", "out/{value}.nt.gz", 1024).unwrap()),
+            ..Default::default()
+        };
+        let plan = RunPlan {
+            input: None,
+            input_format: "rdf",
+            input_compression: "none",
+            output: Some("out/{value}.nt.gz"),
+            compression: "gzip",
+            rejected: None,
+            routes: &[],
+        };
+        let description = describe(&plan, &EntityFilter::default(), &options);
+        assert!(description.starts_with(
+            "Input:       stdin (rdf, compression: none)\n\
+             Partition:   by P31 into out/{value}.nt.gz (ntriples, compression: gzip)\n\
+             Type:"
+        ));
+    }
+}
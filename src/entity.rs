@@ -0,0 +1,554 @@
+use std::borrow::{Borrow, Cow};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+
+use crate::statement::{RankSet, SnakValue, StatementFilter, StatementView, TruthyValue};
+
+/// A string borrowed from the input line if possible
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(transparent)]
+pub struct Str<'a>(#[serde(borrow)] pub Cow<'a, str>);
+
+impl Str<'_> {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Str<'_> {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Deserialize a JSON object, accepting `[]` for an empty one
+///
+/// Wikibase serializes empty maps (claims, qualifiers, ...) as empty arrays.
+fn map_or_empty_array<'de, D, K, V>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+where
+    D: Deserializer<'de>,
+    K: Deserialize<'de> + Ord,
+    V: Deserialize<'de>,
+{
+    struct MapVisitor<K, V>(PhantomData<(K, V)>);
+
+    impl<'de, K, V> Visitor<'de> for MapVisitor<K, V>
+    where
+        K: Deserialize<'de> + Ord,
+        V: Deserialize<'de>,
+    {
+        type Value = BTreeMap<K, V>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an object or an empty array")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut result = BTreeMap::new();
+            while let Some((key, value)) = map.next_entry()? {
+                result.insert(key, value);
+            }
+            Ok(result)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            if seq.next_element::<IgnoredAny>()?.is_some() {
+                return Err(de::Error::custom("expected an empty array"));
+            }
+            Ok(BTreeMap::new())
+        }
+    }
+
+    deserializer.deserialize_any(MapVisitor(PhantomData))
+}
+
+/// Typed view of a Wikidata JSON entity (item, property, lexeme, ...) for filtering
+///
+/// Strings are borrowed from the input line where possible, and fields no
+/// filter looks at are skipped. Output is still written from the
+/// `serde_json::Value`, so it stays lossless.
+#[derive(Debug, Default, Deserialize)]
+pub struct Entity<'a> {
+    #[serde(borrow, default)]
+    pub id: Option<Str<'a>>,
+    #[serde(rename = "type", borrow, default)]
+    pub entity_type: Option<Str<'a>>,
+    /// Time of the last edit, e.g. "2024-01-15T10:00:00Z"
+    #[serde(borrow, default)]
+    pub modified: Option<Str<'a>>,
+    /// Statements grouped by property ID
+    #[serde(borrow, default, deserialize_with = "map_or_empty_array")]
+    pub claims: BTreeMap<Str<'a>, Vec<Claim<'a>>>,
+    /// Labels by language code
+    #[serde(borrow, default, deserialize_with = "map_or_empty_array")]
+    pub labels: BTreeMap<Str<'a>, Term<'a>>,
+    /// Descriptions by language code
+    #[serde(borrow, default, deserialize_with = "map_or_empty_array")]
+    pub descriptions: BTreeMap<Str<'a>, Term<'a>>,
+    /// Sitelinks by site (e.g., "enwiki"), read for their badges only
+    #[serde(borrow, default, deserialize_with = "map_or_empty_array")]
+    pub sitelinks: BTreeMap<Str<'a>, Sitelink<'a>>,
+}
+
+/// The badges of a sitelink, such as featured article (Q17437796)
+#[derive(Debug, Deserialize)]
+pub struct Sitelink<'a> {
+    #[serde(borrow, default)]
+    pub badges: Vec<Str<'a>>,
+}
+
+/// A label or description in one language
+#[derive(Debug, Deserialize)]
+pub struct Term<'a> {
+    #[serde(borrow)]
+    pub value: Str<'a>,
+}
+
+/// A statement: main snak plus qualifiers, references and rank
+#[derive(Debug, Deserialize)]
+pub struct Claim<'a> {
+    #[serde(borrow, default)]
+    pub id: Option<Str<'a>>,
+    #[serde(borrow, default)]
+    pub mainsnak: Snak<'a>,
+    #[serde(borrow, default)]
+    pub rank: Option<Str<'a>>,
+    #[serde(borrow, default, deserialize_with = "map_or_empty_array")]
+    pub qualifiers: BTreeMap<Str<'a>, Vec<Snak<'a>>>,
+    #[serde(borrow, default)]
+    pub references: Vec<Reference<'a>>,
+}
+
+/// A reference of a statement
+#[derive(Debug, Deserialize)]
+pub struct Reference<'a> {
+    #[serde(borrow, default)]
+    pub hash: Option<Str<'a>>,
+    #[serde(borrow, default, deserialize_with = "map_or_empty_array")]
+    pub snaks: BTreeMap<Str<'a>, Vec<Snak<'a>>>,
+}
+
+/// A property-value pair (`value`, `somevalue` or `novalue`)
+#[derive(Debug, Default, Deserialize)]
+pub struct Snak<'a> {
+    #[serde(borrow, default)]
+    pub snaktype: Option<Str<'a>>,
+    #[serde(borrow, default)]
+    pub property: Option<Str<'a>>,
+    #[serde(borrow, default)]
+    pub datatype: Option<Str<'a>>,
+    #[serde(borrow, default)]
+    pub datavalue: Option<DataValue<'a>>,
+}
+
+/// The value of a snak, by datavalue type
+#[derive(Debug)]
+pub enum DataValue<'a> {
+    EntityId(EntityIdValue<'a>),
+    String(Str<'a>),
+    MonolingualText(MonolingualTextValue<'a>),
+    Time(TimeValue<'a>),
+    Quantity(QuantityValue<'a>),
+    GlobeCoordinate(GlobeCoordinateValue<'a>),
+    /// Datavalue types this tool does not interpret
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "value")]
+enum KnownDataValue<'a> {
+    #[serde(rename = "wikibase-entityid", borrow)]
+    EntityId(EntityIdValue<'a>),
+    #[serde(rename = "string", borrow)]
+    String(Str<'a>),
+    #[serde(rename = "monolingualtext", borrow)]
+    MonolingualText(MonolingualTextValue<'a>),
+    #[serde(rename = "time", borrow)]
+    Time(TimeValue<'a>),
+    #[serde(rename = "quantity", borrow)]
+    Quantity(QuantityValue<'a>),
+    #[serde(rename = "globecoordinate", borrow)]
+    GlobeCoordinate(GlobeCoordinateValue<'a>),
+}
+
+/// Falls back to `Other` for unknown datavalue types instead of failing the entity
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DataValueRepr<'a> {
+    #[serde(borrow)]
+    Known(KnownDataValue<'a>),
+    Other(IgnoredAny),
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for DataValue<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        DataValueRepr::deserialize(deserializer).map(DataValue::from)
+    }
+}
+
+impl<'a> From<DataValueRepr<'a>> for DataValue<'a> {
+    fn from(repr: DataValueRepr<'a>) -> Self {
+        match repr {
+            DataValueRepr::Known(KnownDataValue::EntityId(v)) => DataValue::EntityId(v),
+            DataValueRepr::Known(KnownDataValue::String(v)) => DataValue::String(v),
+            DataValueRepr::Known(KnownDataValue::MonolingualText(v)) => {
+                DataValue::MonolingualText(v)
+            }
+            DataValueRepr::Known(KnownDataValue::Time(v)) => DataValue::Time(v),
+            DataValueRepr::Known(KnownDataValue::Quantity(v)) => DataValue::Quantity(v),
+            DataValueRepr::Known(KnownDataValue::GlobeCoordinate(v)) => {
+                DataValue::GlobeCoordinate(v)
+            }
+            DataValueRepr::Other(_) => DataValue::Other,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EntityIdValue<'a> {
+    #[serde(borrow, default)]
+    pub id: Option<Str<'a>>,
+    #[serde(rename = "numeric-id", default)]
+    pub numeric_id: Option<u64>,
+    #[serde(rename = "entity-type", borrow, default)]
+    pub entity_type: Option<Str<'a>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MonolingualTextValue<'a> {
+    #[serde(borrow)]
+    pub text: Str<'a>,
+    #[serde(borrow)]
+    pub language: Str<'a>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimeValue<'a> {
+    #[serde(borrow)]
+    pub time: Str<'a>,
+    #[serde(default)]
+    pub precision: Option<u8>,
+    #[serde(borrow, default)]
+    pub calendarmodel: Option<Str<'a>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuantityValue<'a> {
+    #[serde(borrow)]
+    pub amount: Str<'a>,
+    #[serde(borrow, default)]
+    pub unit: Option<Str<'a>>,
+    #[serde(rename = "upperBound", borrow, default)]
+    pub upper_bound: Option<Str<'a>>,
+    #[serde(rename = "lowerBound", borrow, default)]
+    pub lower_bound: Option<Str<'a>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GlobeCoordinateValue<'a> {
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default)]
+    pub precision: Option<f64>,
+    #[serde(borrow, default)]
+    pub globe: Option<Str<'a>>,
+}
+
+impl EntityIdValue<'_> {
+    /// The entity ID, built from `numeric-id` for the older format
+    pub fn entity_id(&self) -> Option<Cow<'_, str>> {
+        if let Some(ref id) = self.id {
+            return Some(Cow::Borrowed(id.as_str()));
+        }
+        let numeric_id = self.numeric_id?;
+        let prefix = match self.entity_type.as_ref().map(Str::as_str) {
+            Some("property") => "P",
+            _ => "Q",
+        };
+        Some(Cow::Owned(format!("{}{}", prefix, numeric_id)))
+    }
+}
+
+impl Snak<'_> {
+    /// The entity ID this snak points to, if it has an entity value
+    pub fn entity_id(&self) -> Option<Cow<'_, str>> {
+        match self.datavalue {
+            Some(DataValue::EntityId(ref value)) => value.entity_id(),
+            _ => None,
+        }
+    }
+}
+
+impl Entity<'_> {
+    pub fn id(&self) -> &str {
+        self.id.as_ref().map(Str::as_str).unwrap_or_default()
+    }
+
+    /// Entity type, `item` if missing
+    pub fn entity_type(&self) -> &str {
+        self.entity_type.as_ref().map(Str::as_str).unwrap_or("item")
+    }
+
+    /// Entity IDs of the main snaks per property, as used by the RDF claim maps
+    pub fn claim_values(&self) -> HashMap<String, HashSet<String>> {
+        self.claims
+            .iter()
+            .map(|(prop, claims)| {
+                let values = claims
+                    .iter()
+                    .filter_map(|claim| claim.mainsnak.entity_id())
+                    .map(Cow::into_owned)
+                    .collect();
+                (prop.as_str().to_string(), values)
+            })
+            .collect()
+    }
+
+    /// Statements of the property that plain claim tests count: preferred and
+    /// normal ones, as in the truthy dumps
+    fn counted_claims(&self, prop: &str) -> impl Iterator<Item = &Claim<'_>> {
+        self.claims
+            .get(prop)
+            .into_iter()
+            .flatten()
+            .filter(|claim| RankSet::default().contains(claim.rank()))
+    }
+}
+
+/// Claim data a `ClaimFilter` can be evaluated against
+pub trait ClaimLookup {
+    /// Whether the entity has any statement for the property
+    fn has_property(&self, prop: &str) -> bool;
+    /// Whether any main snak of the property has one of the entity values
+    fn has_any_value(&self, prop: &str, values: &HashSet<String>) -> bool;
+    /// Whether any main snak, of any property, has one of the entity values
+    fn references_any(&self, values: &HashSet<String>) -> bool;
+    /// Whether any main snak of the property has an entity value, with an
+    /// ID starting with `letter` if given
+    fn has_entity_value(&self, prop: &str, letter: Option<char>) -> bool;
+    /// Whether any statement of the filter's property passes it
+    fn any_statement(&self, filter: &StatementFilter) -> bool;
+    /// Labels as (language, text) pairs, for --label-regex; none if not known
+    fn labels(&self) -> Vec<(&str, &str)> {
+        Vec::new()
+    }
+    /// Descriptions as (language, text) pairs, for --description-regex
+    fn descriptions(&self) -> Vec<(&str, &str)> {
+        Vec::new()
+    }
+    /// Sitelink badges as (site, badge) pairs, for --badge; none if not known
+    fn badges(&self) -> Vec<(&str, &str)> {
+        Vec::new()
+    }
+    /// Sites the entity has sitelinks on, for --sitelink; none if not known
+    fn sitelink_sites(&self) -> Vec<&str> {
+        Vec::new()
+    }
+    /// Number of sitelinks, for --min-sitelinks; `None` if not known
+    fn sitelink_count(&self) -> Option<usize> {
+        None
+    }
+    /// Number of statements, for --min-statements; `None` if not known
+    fn statement_count(&self) -> Option<usize> {
+        None
+    }
+    /// Time of the last edit as an ISO 8601 string, for --modified-since
+    fn modified(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl ClaimLookup for HashMap<String, HashSet<String>> {
+    fn has_property(&self, prop: &str) -> bool {
+        self.contains_key(prop)
+    }
+
+    fn has_any_value(&self, prop: &str, values: &HashSet<String>) -> bool {
+        match self.get(prop) {
+            Some(claim_values) => values.iter().any(|v| claim_values.contains(v)),
+            None => false,
+        }
+    }
+
+    fn references_any(&self, values: &HashSet<String>) -> bool {
+        self.values()
+            .any(|claim_values| claim_values.iter().any(|v| values.contains(v)))
+    }
+
+    fn has_entity_value(&self, prop: &str, letter: Option<char>) -> bool {
+        self.get(prop).is_some_and(|claim_values| {
+            claim_values
+                .iter()
+                .any(|v| letter.is_none_or(|letter| v.starts_with(letter)))
+        })
+    }
+
+    fn any_statement(&self, filter: &StatementFilter) -> bool {
+        self.get(&filter.property).is_some_and(|claim_values| {
+            // Literal and unknown values are not kept, only the property
+            if claim_values.is_empty() {
+                return filter.matches(&TruthyValue(SnakValue::Other));
+            }
+            claim_values
+                .iter()
+                .any(|v| filter.matches(&TruthyValue(SnakValue::Entity(Cow::Borrowed(v)))))
+        })
+    }
+}
+
+impl ClaimLookup for Entity<'_> {
+    fn has_property(&self, prop: &str) -> bool {
+        self.counted_claims(prop).next().is_some()
+    }
+
+    fn has_any_value(&self, prop: &str, values: &HashSet<String>) -> bool {
+        self.counted_claims(prop).any(|claim| {
+            claim
+                .mainsnak
+                .entity_id()
+                .is_some_and(|id| values.contains(id.as_ref()))
+        })
+    }
+
+    fn references_any(&self, values: &HashSet<String>) -> bool {
+        self.claims.values().flatten().any(|claim| {
+            claim
+                .mainsnak
+                .entity_id()
+                .is_some_and(|id| values.contains(id.as_ref()))
+        })
+    }
+
+    fn has_entity_value(&self, prop: &str, letter: Option<char>) -> bool {
+        self.counted_claims(prop).any(|claim| {
+            claim
+                .mainsnak
+                .entity_id()
+                .is_some_and(|id| letter.is_none_or(|letter| id.starts_with(letter)))
+        })
+    }
+
+    fn any_statement(&self, filter: &StatementFilter) -> bool {
+        self.claims
+            .get(filter.property.as_str())
+            .is_some_and(|claims| claims.iter().any(|claim| filter.matches(claim)))
+    }
+
+    fn labels(&self) -> Vec<(&str, &str)> {
+        terms(&self.labels)
+    }
+
+    fn descriptions(&self) -> Vec<(&str, &str)> {
+        terms(&self.descriptions)
+    }
+
+    fn badges(&self) -> Vec<(&str, &str)> {
+        self.sitelinks
+            .iter()
+            .flat_map(|(site, sitelink)| {
+                sitelink
+                    .badges
+                    .iter()
+                    .map(move |badge| (site.as_str(), badge.as_str()))
+            })
+            .collect()
+    }
+
+    fn sitelink_sites(&self) -> Vec<&str> {
+        self.sitelinks.keys().map(Str::as_str).collect()
+    }
+
+    fn sitelink_count(&self) -> Option<usize> {
+        Some(self.sitelinks.len())
+    }
+
+    fn statement_count(&self) -> Option<usize> {
+        Some(self.claims.values().map(Vec::len).sum())
+    }
+
+    fn modified(&self) -> Option<&str> {
+        self.modified.as_ref().map(Str::as_str)
+    }
+}
+
+fn terms<'a>(terms: &'a BTreeMap<Str, Term>) -> Vec<(&'a str, &'a str)> {
+    terms
+        .iter()
+        .map(|(lang, term)| (lang.as_str(), term.value.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_entity() {
+        let line = r#"{"type":"item","id":"Q42","labels":{"en":{"language":"en","value":"Douglas Adams"}},
+            "claims":{"P31":[{"mainsnak":{"snaktype":"value","property":"P31","datatype":"wikibase-item",
+                "datavalue":{"value":{"entity-type":"item","numeric-id":5,"id":"Q5"},"type":"wikibase-entityid"}},
+                "type":"statement","id":"Q42$1","rank":"normal","qualifiers":[],
+                "references":[{"hash":"abc","snaks":{"P248":[{"snaktype":"value","property":"P248",
+                    "datavalue":{"value":{"id":"Q36578"},"type":"wikibase-entityid"}}]},"snaks-order":["P248"]}]}],
+              "P569":[{"mainsnak":{"snaktype":"value","property":"P569",
+                "datavalue":{"value":{"time":"+1952-03-11T00:00:00Z","timezone":0,"before":0,"after":0,"precision":11,
+                    "calendarmodel":"http://www.wikidata.org/entity/Q1985727"},"type":"time"}},"rank":"normal"}],
+              "P1":[{"mainsnak":{"snaktype":"novalue","property":"P1"},"rank":"normal"}],
+              "P2":[{"mainsnak":{"snaktype":"value","property":"P2","datavalue":{"value":{},"type":"musical-notation"}}}]},
+            "sitelinks":[]}"#;
+        let entity: Entity = serde_json::from_str(line).unwrap();
+
+        assert_eq!(entity.id(), "Q42");
+        assert_eq!(entity.entity_type(), "item");
+        let p31 = &entity.claims["P31"][0];
+        assert_eq!(p31.mainsnak.entity_id().as_deref(), Some("Q5"));
+        // Strings are borrowed from the line, even inside buffered datavalues
+        assert!(matches!(entity.id, Some(Str(Cow::Borrowed(_)))));
+        assert!(matches!(
+            p31.mainsnak.datavalue,
+            Some(DataValue::EntityId(EntityIdValue {
+                id: Some(Str(Cow::Borrowed(_))),
+                ..
+            }))
+        ));
+        assert_eq!(
+            p31.references[0].snaks["P248"][0].entity_id().as_deref(),
+            Some("Q36578")
+        );
+        assert!(matches!(
+            entity.claims["P569"][0].mainsnak.datavalue,
+            Some(DataValue::Time(TimeValue {
+                precision: Some(11),
+                ..
+            }))
+        ));
+        assert!(entity.claims["P1"][0].mainsnak.datavalue.is_none());
+        assert!(matches!(
+            entity.claims["P2"][0].mainsnak.datavalue,
+            Some(DataValue::Other)
+        ));
+
+        assert!(entity.has_property("P1"));
+        assert!(entity.has_any_value("P31", &HashSet::from(["Q5".to_string()])));
+        assert!(!entity.has_any_value("P31", &HashSet::from(["Q6256".to_string()])));
+    }
+
+    #[test]
+    fn test_numeric_id_and_empty_claims() {
+        let entity: Entity = serde_json::from_str(
+            r#"{"id":"Q1","claims":{"P1":[{"mainsnak":{"datavalue":{"type":"wikibase-entityid","value":{"entity-type":"property","numeric-id":31}}}}]}}"#,
+        )
+        .unwrap();
+        let values = entity.claim_values();
+        assert_eq!(values["P1"], HashSet::from(["P31".to_string()]));
+
+        let empty: Entity =
+            serde_json::from_str(r#"{"id":"Q2","type":"property","claims":[]}"#).unwrap();
+        assert!(empty.claims.is_empty());
+        assert_eq!(empty.entity_type(), "property");
+    }
+}
@@ -1,9 +1,23 @@
+use serde::Deserialize;
 use serde_json::Value;
-use std::collections::{HashMap, HashSet};
-
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{BufRead, BufReader};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::compression::decompressing_reader;
+use crate::datatype::DatatypeFilter;
+use crate::entity::{ClaimLookup, Entity};
+use crate::hierarchy::ClaimIndex;
+use crate::idset::IdSet;
+use crate::statement::{RankSet, StatementFilter, ValueTest};
+use crate::terms::TermFilter;
+use crate::truthify::Rank;
+use crate::unicode::NormalizationForm;
+use crate::units::UnitConversions;
 use crate::FilterError;
 
-/// Valid entity attributes that can be filtered with --keep/--omit
+/// Valid entity attributes that can be filtered with --keep/--omit; other
+/// fields are written unless --keep is given
 pub const VALID_ATTRIBUTES: &[&str] = &[
     "id",
     "type",
@@ -12,14 +26,31 @@ pub const VALID_ATTRIBUTES: &[&str] = &[
     "aliases",
     "claims",
     "sitelinks",
+    "lastrevid",
+    "modified",
+    "pageid",
+    "ns",
+    "title",
 ];
 
+/// Attributes whose entries --keep/--omit can select by key, as in
+/// `claims.P31`, `labels.en` or `sitelinks.enwiki`
+pub const NESTED_ATTRIBUTES: &[&str] =
+    &["labels", "descriptions", "aliases", "claims", "sitelinks"];
+
+/// Language code of the terms that hold in all languages, such as the
+/// names of people in Latin script
+pub const MUL_LANGUAGE: &str = "mul";
+
+/// Optional set of attribute names
+type AttributeSet = Option<HashSet<String>>;
+
 /// Parse --keep and --omit attribute filters
 /// Returns (keep_attributes, omit_attributes)
 pub fn parse_attribute_filters(
     keep: Option<&str>,
     omit: Option<&str>,
-) -> Result<(Option<HashSet<String>>, Option<HashSet<String>>), FilterError> {
+) -> Result<(AttributeSet, AttributeSet), FilterError> {
     // Validate that keep and omit are not both specified
     if keep.is_some() && omit.is_some() {
         return Err(FilterError::Parse(
@@ -28,15 +59,28 @@ pub fn parse_attribute_filters(
     }
 
     let parse_attrs = |s: &str| -> Result<HashSet<String>, FilterError> {
+        // Keys of map attributes (`claims.P31`) keep their case
         let attrs: HashSet<String> = s
             .split(',')
-            .map(|a| a.trim().to_lowercase())
+            .map(str::trim)
             .filter(|a| !a.is_empty())
+            .map(|a| match a.split_once('.') {
+                Some((attr, key)) => format!("{}.{}", attr.to_lowercase(), key),
+                None => a.to_lowercase(),
+            })
             .collect();
 
         // Validate all attributes
         for attr in &attrs {
-            if !VALID_ATTRIBUTES.contains(&attr.as_str()) {
+            if let Some((name, key)) = attr.split_once('.') {
+                if !NESTED_ATTRIBUTES.contains(&name) || key.is_empty() || key.contains('.') {
+                    return Err(FilterError::Parse(format!(
+                        "Invalid attribute path '{}'. Paths select a key of: {}",
+                        attr,
+                        NESTED_ATTRIBUTES.join(", ")
+                    )));
+                }
+            } else if !VALID_ATTRIBUTES.contains(&attr.as_str()) {
                 return Err(FilterError::Parse(format!(
                     "Invalid attribute '{}'. Valid attributes: {}",
                     attr,
@@ -54,6 +98,62 @@ pub fn parse_attribute_filters(
     Ok((keep_attrs, omit_attrs))
 }
 
+/// Read entity IDs from a file, one per line, decompressed by its extension
+///
+/// Blank lines and lines starting with `#` are skipped.
+pub fn read_id_file<S: Default + Extend<String>>(path: &str) -> Result<S, FilterError> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| FilterError::Parse(format!("Cannot open ID file '{}': {}", path, e)))?;
+    let reader = BufReader::new(decompressing_reader(file, path));
+    let mut ids = S::default();
+    for line in reader.lines() {
+        let line = line?;
+        let id = line.trim();
+        if !id.is_empty() && !id.starts_with('#') {
+            ids.extend([id.to_string()]);
+        }
+    }
+    Ok(ids)
+}
+
+/// Parse entity IDs given comma-separated, or one per line in a file
+pub fn parse_id_list(value: &str) -> Result<HashSet<String>, FilterError> {
+    if std::path::Path::new(value).is_file() {
+        return read_id_file(value);
+    }
+    let ids: HashSet<String> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect();
+    if ids.is_empty() {
+        return Err(FilterError::Parse(format!("No entity IDs in '{}'", value)));
+    }
+    Ok(ids)
+}
+
+/// Check a --modified-since timestamp, `2024-01-01` or `2024-01-01T12:00:00Z`
+///
+/// Modification times are UTC ISO 8601 strings in both dump formats, so they
+/// compare as strings; a date alone sorts before the times of that day.
+pub fn parse_timestamp(value: &str) -> Result<String, FilterError> {
+    let value = value.trim();
+    let template = "0000-00-00T00:00:00Z";
+    let valid = (value.len() == 10 || value.len() == template.len())
+        && value.bytes().zip(template.bytes()).all(|(c, t)| match t {
+            b'0' => c.is_ascii_digit(),
+            _ => c == t,
+        });
+    if !valid {
+        return Err(FilterError::Parse(format!(
+            "Invalid timestamp '{}', expected 2024-01-01 or 2024-01-01T12:00:00Z",
+            value
+        )));
+    }
+    Ok(value.to_string())
+}
+
 /// Represents a claim filter condition
 #[derive(Debug, Clone)]
 pub enum ClaimFilter {
@@ -61,6 +161,29 @@ pub enum ClaimFilter {
     HasProperty(String),
     /// Property has specific value(s) (e.g., P31:Q5 or P31:Q5,Q6256)
     PropertyValue(String, HashSet<String>),
+    /// Property has one of the classes or a transitive subclass (e.g., P31:Q5*);
+    /// `classes` holds the roots until resolved against a `--hierarchy`
+    Subclasses {
+        property: String,
+        roots: HashSet<String>,
+        classes: HashSet<String>,
+    },
+    /// A value reachable through a path of properties (e.g., P31/P279:Q5);
+    /// `starts` holds the values of the first property that lead to one of
+    /// `values`, once resolved against the edges of the other properties
+    Path {
+        properties: Vec<String>,
+        values: HashSet<String>,
+        starts: HashSet<String>,
+    },
+    /// Property has an entity value, of the entity type with this ID letter
+    /// if given (e.g., P1343:* or P31:Q!)
+    AnyValue {
+        property: String,
+        letter: Option<char>,
+    },
+    /// A statement whose value and qualifiers pass the tests (e.g., P39:Q11696{P580>=1990})
+    Statement(StatementFilter),
     /// AND of multiple filters (e.g., P31:Q5&P18)
     And(Vec<ClaimFilter>),
     /// OR of multiple filters (e.g., P31:Q5|P31:Q6256)
@@ -71,19 +194,27 @@ pub enum ClaimFilter {
 
 impl ClaimFilter {
     /// Check if the filter matches the given claims
-    pub fn matches(&self, claims: &HashMap<String, HashSet<String>>) -> bool {
+    pub fn matches<C: ClaimLookup + ?Sized>(&self, claims: &C) -> bool {
         match self {
-            ClaimFilter::HasProperty(prop) => claims.contains_key(prop),
+            ClaimFilter::HasProperty(prop) => claims.has_property(prop),
 
-            ClaimFilter::PropertyValue(prop, values) => {
-                if let Some(claim_values) = claims.get(prop) {
-                    // Check if any of the required values are in the claim values
-                    values.iter().any(|v| claim_values.contains(v))
-                } else {
-                    false
-                }
+            // Check if any of the required values are in the claim values
+            ClaimFilter::PropertyValue(prop, values) => claims.has_any_value(prop, values),
+
+            ClaimFilter::Subclasses {
+                property, classes, ..
+            } => claims.has_any_value(property, classes),
+
+            ClaimFilter::Path {
+                properties, starts, ..
+            } => claims.has_any_value(&properties[0], starts),
+
+            ClaimFilter::AnyValue { property, letter } => {
+                claims.has_entity_value(property, *letter)
             }
 
+            ClaimFilter::Statement(filter) => claims.any_statement(filter),
+
             ClaimFilter::And(filters) => filters.iter().all(|f| f.matches(claims)),
 
             ClaimFilter::Or(filters) => filters.iter().any(|f| f.matches(claims)),
@@ -91,28 +222,669 @@ impl ClaimFilter {
             ClaimFilter::Not(filter) => !filter.matches(claims),
         }
     }
+
+    /// Properties whose edges the property paths of the filter follow
+    pub fn path_properties(&self) -> BTreeSet<String> {
+        match self {
+            ClaimFilter::Path { properties, .. } => properties[1..].iter().cloned().collect(),
+            ClaimFilter::And(filters) | ClaimFilter::Or(filters) => filters
+                .iter()
+                .flat_map(ClaimFilter::path_properties)
+                .collect(),
+            ClaimFilter::Not(filter) => filter.path_properties(),
+            _ => BTreeSet::new(),
+        }
+    }
+
+    /// Resolve transitive values and property paths against `index`
+    ///
+    /// Fails if the filter has transitive values and the index no hierarchy,
+    /// or a property path whose edges the index lacks.
+    pub fn resolve(&mut self, index: &ClaimIndex) -> Result<(), FilterError> {
+        match self {
+            ClaimFilter::Subclasses {
+                property,
+                roots,
+                classes,
+            } => {
+                let hierarchy = index.hierarchy.as_ref().ok_or_else(|| {
+                    FilterError::InvalidClaim(format!(
+                        "Transitive values of {} need a subclass file (--hierarchy)",
+                        property
+                    ))
+                })?;
+                *classes = hierarchy.subclasses(roots);
+                Ok(())
+            }
+            ClaimFilter::Path {
+                properties,
+                values,
+                starts,
+            } => {
+                *starts = values.clone();
+                for property in properties[1..].iter().rev() {
+                    let edges = index.edges.get(property).ok_or_else(|| {
+                        FilterError::InvalidClaim(format!(
+                            "The property path {} needs a first pass over a dump file",
+                            properties.join("/")
+                        ))
+                    })?;
+                    *starts = edges.subjects(starts);
+                }
+                Ok(())
+            }
+            ClaimFilter::And(filters) | ClaimFilter::Or(filters) => filters
+                .iter_mut()
+                .try_for_each(|filter| filter.resolve(index)),
+            ClaimFilter::Not(filter) => filter.resolve(index),
+            ClaimFilter::HasProperty(_)
+            | ClaimFilter::PropertyValue(..)
+            | ClaimFilter::AnyValue { .. }
+            | ClaimFilter::Statement(_) => Ok(()),
+        }
+    }
+
+    /// The statement filter testing the same main value, for a single
+    /// property with plain or wildcard values
+    pub fn into_statement(self) -> Option<StatementFilter> {
+        match self {
+            ClaimFilter::HasProperty(property) => {
+                Some(StatementFilter::new(property, ValueTest::Any))
+            }
+            ClaimFilter::PropertyValue(property, values) => {
+                Some(StatementFilter::new(property, ValueTest::Entities(values)))
+            }
+            ClaimFilter::AnyValue { property, letter } => {
+                Some(StatementFilter::new(property, ValueTest::AnyEntity(letter)))
+            }
+            ClaimFilter::Statement(filter) => Some(filter),
+            _ => None,
+        }
+    }
+
+    /// The filter with only referenced statements counting (--require-reference)
+    pub fn require_reference(self) -> Result<Self, FilterError> {
+        self.map_statements("--require-reference", &|statement| {
+            statement.referenced = true
+        })
+    }
+
+    /// The filter with unknown and no values not counting where any value
+    /// would (--exclude-special-values); entity values, transitive values
+    /// and property paths are left as they are
+    pub fn exclude_special_values(self) -> Self {
+        let values_only = |test: &mut ValueTest| {
+            if matches!(test, ValueTest::Any) {
+                *test = ValueTest::Value;
+            }
+        };
+        match self {
+            ClaimFilter::And(filters) => ClaimFilter::And(
+                filters
+                    .into_iter()
+                    .map(ClaimFilter::exclude_special_values)
+                    .collect(),
+            ),
+            ClaimFilter::Or(filters) => ClaimFilter::Or(
+                filters
+                    .into_iter()
+                    .map(ClaimFilter::exclude_special_values)
+                    .collect(),
+            ),
+            ClaimFilter::Not(filter) => ClaimFilter::Not(Box::new(filter.exclude_special_values())),
+            ClaimFilter::HasProperty(property) => {
+                ClaimFilter::Statement(StatementFilter::new(property, ValueTest::Value))
+            }
+            ClaimFilter::Statement(mut statement) => {
+                values_only(&mut statement.value);
+                for condition in statement
+                    .qualifiers
+                    .iter_mut()
+                    .chain(statement.references.iter_mut())
+                {
+                    values_only(&mut condition.test);
+                }
+                ClaimFilter::Statement(statement)
+            }
+            filter => filter,
+        }
+    }
+
+    /// The filter counting statements of these ranks where no rank is given (--ranks)
+    pub fn with_ranks(self, ranks: RankSet) -> Result<Self, FilterError> {
+        self.map_statements("--ranks", &|statement| {
+            statement.ranks.get_or_insert(ranks);
+        })
+    }
+
+    /// Turn every property test into a statement filter and change it with
+    /// `update`; `option` names the option for transitive values and paths,
+    /// which have no statement filter
+    fn map_statements(
+        self,
+        option: &str,
+        update: &dyn Fn(&mut StatementFilter),
+    ) -> Result<Self, FilterError> {
+        let map_all = |filters: Vec<ClaimFilter>| {
+            filters
+                .into_iter()
+                .map(|filter| filter.map_statements(option, update))
+                .collect::<Result<_, _>>()
+        };
+        match self {
+            ClaimFilter::And(filters) => Ok(ClaimFilter::And(map_all(filters)?)),
+            ClaimFilter::Or(filters) => Ok(ClaimFilter::Or(map_all(filters)?)),
+            ClaimFilter::Not(filter) => Ok(ClaimFilter::Not(Box::new(
+                filter.map_statements(option, update)?,
+            ))),
+            filter => {
+                let tree = filter.tree();
+                let mut statement = filter.into_statement().ok_or_else(|| {
+                    FilterError::InvalidClaim(format!(
+                        "{} is not supported with transitive values or property paths: {}",
+                        option,
+                        tree.trim_end()
+                    ))
+                })?;
+                update(&mut statement);
+                Ok(ClaimFilter::Statement(statement))
+            }
+        }
+    }
+
+    /// The filter as an indented tree, one node per line (--dry-run)
+    pub fn tree(&self) -> String {
+        let mut tree = String::new();
+        self.push_tree(&mut tree, 0, &|_| "");
+        tree
+    }
+
+    /// The filter tree with each node marked as matching the claims or not (--explain)
+    pub fn explain<C: ClaimLookup + ?Sized>(&self, claims: &C) -> String {
+        let mut tree = String::new();
+        self.push_tree(&mut tree, 0, &|filter| match_mark(filter.matches(claims)));
+        tree
+    }
+
+    fn push_tree(&self, tree: &mut String, depth: usize, mark: &dyn Fn(&Self) -> &'static str) {
+        tree.push_str(&"  ".repeat(depth));
+        tree.push_str(mark(self));
+        match self {
+            ClaimFilter::HasProperty(prop) => tree.push_str(&format!("{} (any value)\n", prop)),
+            ClaimFilter::Statement(filter) => tree.push_str(&format!("{}\n", filter)),
+            ClaimFilter::AnyValue { property, letter } => {
+                let kind = match letter {
+                    Some('Q') => "Q! (any item)",
+                    Some('P') => "P! (any property)",
+                    Some(_) => "L! (any lexeme)",
+                    None => "* (any entity)",
+                };
+                tree.push_str(&format!("{} = {}\n", property, kind));
+            }
+            ClaimFilter::PropertyValue(prop, values) => {
+                let mut values: Vec<&str> = values.iter().map(String::as_str).collect();
+                values.sort_unstable();
+                tree.push_str(&format!("{} = {}\n", prop, values.join(" | ")));
+            }
+            ClaimFilter::Subclasses {
+                property,
+                roots,
+                classes,
+            } => {
+                let mut roots: Vec<String> =
+                    roots.iter().map(|root| format!("{}*", root)).collect();
+                roots.sort_unstable();
+                tree.push_str(&format!(
+                    "{} = {} ({} classes)\n",
+                    property,
+                    roots.join(" | "),
+                    classes.len()
+                ));
+            }
+            ClaimFilter::Path {
+                properties,
+                values,
+                starts,
+            } => {
+                let mut values: Vec<&str> = values.iter().map(String::as_str).collect();
+                values.sort_unstable();
+                tree.push_str(&format!(
+                    "{} = {} ({} values of {})\n",
+                    properties.join("/"),
+                    values.join(" | "),
+                    starts.len(),
+                    properties[0]
+                ));
+            }
+            ClaimFilter::And(filters) | ClaimFilter::Or(filters) => {
+                let name = if matches!(self, ClaimFilter::And(_)) {
+                    "AND"
+                } else {
+                    "OR"
+                };
+                tree.push_str(name);
+                tree.push('\n');
+                for filter in filters {
+                    filter.push_tree(tree, depth + 1, mark);
+                }
+            }
+            ClaimFilter::Not(filter) => {
+                tree.push_str("NOT\n");
+                filter.push_tree(tree, depth + 1, mark);
+            }
+        }
+    }
+}
+
+/// Keep the statements of the best non-deprecated rank of each property, and
+/// drop properties with deprecated statements only (--best-rank-only)
+fn keep_best_rank(claims: &mut serde_json::Map<String, Value>) {
+    let rank = |statement: &Value| {
+        statement
+            .get("rank")
+            .and_then(Value::as_str)
+            .and_then(Rank::from_name)
+            .unwrap_or(Rank::Normal)
+    };
+    claims.retain(|_, statements| {
+        let Some(statements) = statements.as_array_mut() else {
+            return true;
+        };
+        let best = statements
+            .iter()
+            .map(rank)
+            .filter(|r| *r != Rank::Deprecated)
+            .max();
+        match best {
+            Some(best) => {
+                statements.retain(|statement| rank(statement) == best);
+                true
+            }
+            None => false,
+        }
+    });
+}
+
+/// Drop statements and qualifiers with an unknown or no value, and the
+/// properties left without statements (--exclude-special-values)
+fn drop_special_values(claims: &mut serde_json::Map<String, Value>) {
+    let is_value = |snak: &Value| {
+        snak.get("snaktype")
+            .and_then(Value::as_str)
+            .is_none_or(|snaktype| snaktype == "value")
+    };
+    claims.retain(|_, statements| {
+        let Some(statements) = statements.as_array_mut() else {
+            return true;
+        };
+        statements.retain(|statement| statement.get("mainsnak").is_none_or(is_value));
+        for statement in statements.iter_mut() {
+            let Some(qualifiers) = statement
+                .get_mut("qualifiers")
+                .and_then(Value::as_object_mut)
+            else {
+                continue;
+            };
+            qualifiers.retain(|_, snaks| match snaks.as_array_mut() {
+                Some(snaks) => {
+                    snaks.retain(is_value);
+                    !snaks.is_empty()
+                }
+                None => true,
+            });
+            retain_qualifier_order(statement);
+        }
+        !statements.is_empty()
+    });
+}
+
+/// Move the `mul` terms of a labels, descriptions or aliases map into the
+/// languages without their own (--materialize-mul)
+fn materialize_mul(terms: &mut serde_json::Map<String, Value>, languages: &HashSet<String>) {
+    let Some(mul) = terms.remove(MUL_LANGUAGE) else {
+        return;
+    };
+    let set_language = |term: &mut Value, lang: &str| {
+        if let Some(term) = term.as_object_mut() {
+            term.insert("language".to_string(), Value::from(lang));
+        }
+    };
+    for lang in languages {
+        if terms.contains_key(lang) {
+            continue;
+        }
+        // Labels and descriptions are single terms, aliases lists of them
+        let mut copy = mul.clone();
+        match copy.as_array_mut() {
+            Some(aliases) => aliases
+                .iter_mut()
+                .for_each(|alias| set_language(alias, lang)),
+            None => set_language(&mut copy, lang),
+        }
+        terms.insert(lang.clone(), copy);
+    }
+}
+
+/// Keys of an attribute named by `attribute.key` paths of --keep or --omit
+fn attribute_keys<'a>(paths: &'a HashSet<String>, attr: &'a str) -> impl Iterator<Item = &'a str> {
+    paths
+        .iter()
+        .filter_map(move |path| path.strip_prefix(attr)?.strip_prefix('.'))
+}
+
+/// Main snak entity values by property, read from the JSON value for
+/// entities the typed model rejects
+fn value_claims(entity: &Value) -> HashMap<String, HashSet<String>> {
+    let mut claims: HashMap<String, HashSet<String>> = HashMap::new();
+    let properties = entity.get("claims").and_then(Value::as_object);
+    for (property, statements) in properties.into_iter().flatten() {
+        let values = claims.entry(property.clone()).or_default();
+        for statement in statements.as_array().into_iter().flatten() {
+            let Some(value) = statement.pointer("/mainsnak/datavalue/value") else {
+                continue;
+            };
+            if let Some(id) = value.get("id").and_then(Value::as_str) {
+                values.insert(id.to_string());
+            } else if let Some(numeric_id) = value.get("numeric-id").and_then(Value::as_u64) {
+                // Older serializations have the numeric ID only
+                let prefix = match value.get("entity-type").and_then(Value::as_str) {
+                    Some("property") => "P",
+                    _ => "Q",
+                };
+                values.insert(format!("{}{}", prefix, numeric_id));
+            }
+        }
+    }
+    claims
+}
+
+/// Drop the properties missing from the qualifiers of a statement from its
+/// `qualifiers-order`
+fn retain_qualifier_order(statement: &mut Value) {
+    let kept: Vec<String> = match statement.get("qualifiers").and_then(Value::as_object) {
+        Some(qualifiers) => qualifiers.keys().cloned().collect(),
+        None => return,
+    };
+    if let Some(order) = statement
+        .get_mut("qualifiers-order")
+        .and_then(Value::as_array_mut)
+    {
+        order.retain(|p| p.as_str().is_some_and(|p| kept.iter().any(|k| k == p)));
+    }
+}
+
+/// Keep only the qualifiers of the given properties (--keep-qualifiers)
+fn keep_qualifiers(claims: &mut serde_json::Map<String, Value>, properties: &HashSet<String>) {
+    for statement in claims
+        .values_mut()
+        .filter_map(Value::as_array_mut)
+        .flatten()
+    {
+        let Some(qualifiers) = statement
+            .get_mut("qualifiers")
+            .and_then(Value::as_object_mut)
+        else {
+            continue;
+        };
+        qualifiers.retain(|property, _| properties.contains(property));
+        retain_qualifier_order(statement);
+    }
+}
+
+/// Remove the qualifiers and/or references of every statement
+/// (--omit-qualifiers, --omit-references)
+fn omit_statement_parts(
+    claims: &mut serde_json::Map<String, Value>,
+    qualifiers: bool,
+    references: bool,
+) {
+    let statements = claims
+        .values_mut()
+        .filter_map(Value::as_array_mut)
+        .flatten()
+        .filter_map(Value::as_object_mut);
+    for statement in statements {
+        if qualifiers {
+            statement.remove("qualifiers");
+            statement.remove("qualifiers-order");
+        }
+        if references {
+            statement.remove("references");
+        }
+    }
+}
+
+/// Marker for a filter node that matched or did not match (--explain)
+fn match_mark(matched: bool) -> &'static str {
+    if matched {
+        "✓ "
+    } else {
+        "✗ "
+    }
+}
+
+/// One of the entity filters, checked in declaration order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FilterStage {
+    /// --subject
+    Subject,
+    /// --shard
+    Shard,
+    /// --type
+    Type,
+    /// --modified-since
+    Modified,
+    /// --references
+    References,
+    /// --label-contains, --label-regex, --description-regex, --require-label
+    Terms,
+    /// --badge
+    Badges,
+    /// --sitelink, --min-sitelinks
+    Sitelinks,
+    /// --min-statements, --max-statements
+    Statements,
+    /// --claim
+    Claim,
+}
+
+impl FilterStage {
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterStage::Subject => "subject",
+            FilterStage::Shard => "shard",
+            FilterStage::Type => "type",
+            FilterStage::Modified => "modified",
+            FilterStage::References => "references",
+            FilterStage::Terms => "terms",
+            FilterStage::Badges => "badges",
+            FilterStage::Sitelinks => "sitelinks",
+            FilterStage::Statements => "statements",
+            FilterStage::Claim => "claim",
+        }
+    }
+}
+
+/// One of `count` disjoint shards of the entities by ID number (--shard K/N)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: u64,
+    pub count: u64,
+}
+
+impl Shard {
+    /// Parse `K/N` with K < N
+    pub fn parse(s: &str) -> Result<Self, FilterError> {
+        let error = || FilterError::Parse(format!("--shard must be K/N with K < N, not '{}'", s));
+        let (index, count) = s.split_once('/').ok_or_else(error)?;
+        let shard = Self {
+            index: index.trim().parse().map_err(|_| error())?,
+            count: count.trim().parse().map_err(|_| error())?,
+        };
+        if shard.index >= shard.count {
+            return Err(error());
+        }
+        Ok(shard)
+    }
+
+    /// Number of an entity ID (42 for Q42, 1 for L1-F1)
+    fn number(id: &str) -> Option<u64> {
+        let digits = id.get(1..)?;
+        let end = digits
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(digits.len());
+        digits[..end].parse().ok()
+    }
+
+    /// Whether an entity belongs to this shard; IDs without a number are in shard 0
+    pub fn contains(&self, id: &str) -> bool {
+        Self::number(id).map_or(0, |number| number % self.count) == self.index
+    }
+}
+
+/// Sitelink badges an entity needs on one of its sitelinks, such as featured
+/// (Q17437796) or good article (Q17437798) (--badge, --badge-site)
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadgeFilter {
+    pub badges: HashSet<String>,
+    /// Sites the badged sitelink is on (e.g., enwiki); any if `None`
+    pub sites: Option<HashSet<String>>,
+}
+
+impl BadgeFilter {
+    /// Parse comma-separated badge IDs and optionally sites
+    pub fn parse(badges: &str, sites: Option<&str>) -> Result<Self, FilterError> {
+        let list = |value: &str| -> HashSet<String> {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let filter = BadgeFilter {
+            badges: list(badges),
+            sites: sites.map(list),
+        };
+        if filter.badges.is_empty() {
+            return Err(FilterError::Parse(format!("No badge IDs in '{}'", badges)));
+        }
+        if filter.sites.as_ref().is_some_and(HashSet::is_empty) {
+            return Err(FilterError::Parse(
+                "No sites given for --badge-site".to_string(),
+            ));
+        }
+        Ok(filter)
+    }
+
+    /// Whether some sitelink of the entity, on one of the sites, has one of the badges
+    pub fn matches<C: ClaimLookup + ?Sized>(&self, entity: &C) -> bool {
+        entity.badges().into_iter().any(|(site, badge)| {
+            self.badges.contains(badge) && self.sites.as_ref().is_none_or(|s| s.contains(site))
+        })
+    }
 }
 
 /// Main entity filter configuration
 #[derive(Debug, Clone)]
 pub struct EntityFilter {
     pub claim_filter: Option<ClaimFilter>,
-    pub subject_filter: Option<HashSet<String>>,
+    pub subject_filter: Option<IdSet>,
+    pub shard: Option<Shard>,
+    /// Keep entities with a statement whose value is one of these IDs (--references)
+    pub reference_filter: Option<HashSet<String>>,
+    /// Tests of labels and descriptions in the selected languages (--label-regex)
+    pub term_filter: Option<TermFilter>,
+    /// Keep entities with a sitelink badge (--badge)
+    pub badge_filter: Option<BadgeFilter>,
+    /// Keep entities with a sitelink on one of these sites (--sitelink)
+    pub sitelink_sites: Option<HashSet<String>>,
+    /// Keep entities with at least this many sitelinks (--min-sitelinks)
+    pub min_sitelinks: Option<usize>,
+    /// Keep entities modified at or after this timestamp (--modified-since)
+    pub modified_since: Option<String>,
+    /// Keep entities with at least this many statements (--min-statements)
+    pub min_statements: Option<usize>,
+    /// Keep entities with at most this many statements (--max-statements)
+    pub max_statements: Option<usize>,
     pub property_filter: Option<HashSet<String>>,
     pub language_filter: Option<HashSet<String>>,
     pub language_include_subvariants: bool,
+    /// Count `mul` terms as in every selected language (--include-mul)
+    pub include_mul: bool,
+    /// Copy `mul` terms into the selected languages without their own (--materialize-mul)
+    pub materialize_mul: bool,
     pub entity_type: String,
     /// Attributes to keep (if Some, only these attributes are kept)
     pub keep_attributes: Option<HashSet<String>>,
     /// Attributes to omit (if Some, these attributes are removed)
     pub omit_attributes: Option<HashSet<String>>,
+    /// Keep only the statements of the best rank per property (--best-rank-only)
+    pub best_rank_only: bool,
+    /// Drop unknown and no value statements and qualifiers (--exclude-special-values)
+    pub exclude_special_values: bool,
+    /// Keep or drop the claims of properties by datatype (--property-datatype)
+    pub datatype_filter: Option<DatatypeFilter>,
+    /// Convert quantities to other units (--normalize-units)
+    pub unit_conversions: Option<UnitConversions>,
+    /// Normalization form of the written terms and string values (--normalize-unicode)
+    pub unicode_form: Option<NormalizationForm>,
+    /// Drop the qualifiers of statements from the output (--omit-qualifiers)
+    pub omit_qualifiers: bool,
+    /// Keep only the qualifiers of these properties in the output (--keep-qualifiers)
+    pub keep_qualifiers: Option<HashSet<String>>,
+    /// Drop the references of statements from the output (--omit-references)
+    pub omit_references: bool,
+    /// Write only the claim and term triples of RDF output (--claims-only)
+    pub claims_only: bool,
+    /// Select the entities the filters reject instead (--invert-match)
+    pub invert_match: bool,
+}
+
+impl Default for EntityFilter {
+    /// A filter that matches every entity and keeps all data
+    fn default() -> Self {
+        Self {
+            claim_filter: None,
+            subject_filter: None,
+            shard: None,
+            reference_filter: None,
+            term_filter: None,
+            badge_filter: None,
+            sitelink_sites: None,
+            min_sitelinks: None,
+            modified_since: None,
+            min_statements: None,
+            max_statements: None,
+            property_filter: None,
+            language_filter: None,
+            language_include_subvariants: true,
+            include_mul: false,
+            materialize_mul: false,
+            entity_type: "both".to_string(),
+            keep_attributes: None,
+            omit_attributes: None,
+            best_rank_only: false,
+            exclude_special_values: false,
+            datatype_filter: None,
+            unit_conversions: None,
+            unicode_form: None,
+            omit_qualifiers: false,
+            keep_qualifiers: None,
+            omit_references: false,
+            claims_only: false,
+            invert_match: false,
+        }
+    }
 }
 
 impl EntityFilter {
     /// Check if a language tag matches the language filter
     pub fn matches_language(&self, lang_tag: &str) -> bool {
         if let Some(ref lang_filter) = self.language_filter {
-            if self.language_include_subvariants {
+            if self.include_mul && lang_tag == MUL_LANGUAGE {
+                true
+            } else if self.language_include_subvariants {
                 // Extract base language (e.g., "de" from "de-ch")
                 let base_lang = lang_tag.split('-').next().unwrap_or(lang_tag);
                 lang_filter.contains(lang_tag) || lang_filter.contains(base_lang)
@@ -124,132 +896,419 @@ impl EntityFilter {
         }
     }
 
-    /// Check if an RDF entity matches all filters
-    pub fn matches(
+    /// The first label or description test an entity fails, if any
+    fn term_rejection<C: ClaimLookup + ?Sized>(&self, entity: &C) -> Option<String> {
+        self.term_filter.as_ref()?.rejection(
+            entity,
+            |lang| self.matches_language(lang),
+            self.include_mul,
+        )
+    }
+
+    /// Whether the entity has a sitelink on one of the --sitelink sites, if given
+    fn has_listed_sitelink<C: ClaimLookup + ?Sized>(&self, claims: &C) -> bool {
+        self.sitelink_sites.as_ref().is_none_or(|sites| {
+            claims
+                .sitelink_sites()
+                .into_iter()
+                .any(|site| sites.contains(site))
+        })
+    }
+
+    /// Whether --min-statements or --max-statements is given
+    fn filters_statement_count(&self) -> bool {
+        self.min_statements.is_some() || self.max_statements.is_some()
+    }
+
+    /// The --min-statements and --max-statements bounds, e.g. "at least 5"
+    pub fn statement_bounds(&self) -> Option<String> {
+        match (self.min_statements, self.max_statements) {
+            (Some(min), Some(max)) => Some(format!("{} to {}", min, max)),
+            (Some(min), None) => Some(format!("at least {}", min)),
+            (None, Some(max)) => Some(format!("at most {}", max)),
+            (None, None) => None,
+        }
+    }
+
+    /// Whether a statement count is within --min-statements and --max-statements
+    fn statement_count_matches(&self, count: usize) -> bool {
+        self.min_statements.is_none_or(|min| count >= min)
+            && self.max_statements.is_none_or(|max| count <= max)
+    }
+
+    /// Whether an entity with this filter result is written, honoring --invert-match
+    pub fn selects(&self, rejection: Option<FilterStage>) -> bool {
+        rejection.is_none() != self.invert_match
+    }
+
+    /// Check if an RDF entity is selected by the filters
+    pub fn matches<C: ClaimLookup + ?Sized>(
         &self,
         entity_id: &str,
-        claims: &HashMap<String, HashSet<String>>,
+        claims: &C,
         entity_type: Option<&str>,
     ) -> bool {
+        self.selects(self.rejection(entity_id, claims, entity_type))
+    }
+
+    /// The first filter an RDF entity fails, if any
+    pub fn rejection<C: ClaimLookup + ?Sized>(
+        &self,
+        entity_id: &str,
+        claims: &C,
+        entity_type: Option<&str>,
+    ) -> Option<FilterStage> {
         // Check subject filter
         if let Some(ref subjects) = self.subject_filter {
             if !subjects.contains(entity_id) {
-                return false;
+                return Some(FilterStage::Subject);
             }
         }
+        if self.shard.is_some_and(|shard| !shard.contains(entity_id)) {
+            return Some(FilterStage::Shard);
+        }
 
         // Check entity type filter
         if self.entity_type != "both" {
             if let Some(etype) = entity_type {
                 if etype != self.entity_type {
-                    return false;
+                    return Some(FilterStage::Type);
                 }
             }
         }
 
+        self.content_rejection(claims)
+    }
+
+    /// The first filter on the modification date, claims, terms and sitelinks
+    /// of an entity it fails, if any
+    fn content_rejection<C: ClaimLookup + ?Sized>(&self, claims: &C) -> Option<FilterStage> {
+        if let Some(ref since) = self.modified_since {
+            if claims
+                .modified()
+                .is_none_or(|modified| modified < since.as_str())
+            {
+                return Some(FilterStage::Modified);
+            }
+        }
+        if let Some(ref values) = self.reference_filter {
+            if !claims.references_any(values) {
+                return Some(FilterStage::References);
+            }
+        }
+        if self.term_rejection(claims).is_some() {
+            return Some(FilterStage::Terms);
+        }
+        if self
+            .badge_filter
+            .as_ref()
+            .is_some_and(|badges| !badges.matches(claims))
+        {
+            return Some(FilterStage::Badges);
+        }
+        if !self.has_listed_sitelink(claims)
+            || self
+                .min_sitelinks
+                .is_some_and(|min| claims.sitelink_count().unwrap_or(0) < min)
+        {
+            return Some(FilterStage::Sitelinks);
+        }
+        if self.filters_statement_count()
+            && !self.statement_count_matches(claims.statement_count().unwrap_or(0))
+        {
+            return Some(FilterStage::Statements);
+        }
+
         // Check claim filter
         if let Some(ref filter) = self.claim_filter {
             if !filter.matches(claims) {
-                return false;
+                return Some(FilterStage::Claim);
             }
         }
 
-        true
+        None
     }
 
-    /// Check if a JSON entity matches all filters
-    pub fn matches_json(&self, entity: &Value) -> bool {
-        // Get entity ID
-        let entity_id = entity.get("id").and_then(|v| v.as_str()).unwrap_or("");
+    /// Why an entity matched or was rejected, with a line per filter (--explain)
+    ///
+    /// Every filter is shown, also those after the first one that rejects.
+    pub fn explain<C: ClaimLookup + ?Sized>(
+        &self,
+        entity_id: &str,
+        claims: &C,
+        entity_type: Option<&str>,
+    ) -> String {
+        let mut checks = String::new();
+        let mut rejection = None;
+        let mut check = |stage: FilterStage, matched: bool, detail: String| {
+            if !matched && rejection.is_none() {
+                rejection = Some(stage);
+            }
+            checks.push_str(&format!("  {}{}", match_mark(matched), stage.name()));
+            if !detail.is_empty() {
+                checks.push_str(": ");
+                checks.push_str(&detail);
+            }
+            checks.push('\n');
+        };
 
-        // Check subject filter
         if let Some(ref subjects) = self.subject_filter {
-            if !subjects.contains(entity_id) {
-                return false;
+            let listed = subjects.contains(entity_id);
+            let detail = if listed { "listed" } else { "not listed" };
+            check(FilterStage::Subject, listed, detail.to_string());
+        }
+        if let Some(shard) = self.shard {
+            check(
+                FilterStage::Shard,
+                shard.contains(entity_id),
+                format!("want {}/{}", shard.index, shard.count),
+            );
+        }
+        if self.entity_type != "both" {
+            match entity_type {
+                Some(etype) => check(
+                    FilterStage::Type,
+                    etype == self.entity_type,
+                    format!("{} (want {})", etype, self.entity_type),
+                ),
+                None => check(FilterStage::Type, true, "unknown, not checked".to_string()),
+            }
+        }
+        if let Some(ref since) = self.modified_since {
+            let (matched, detail) = match claims.modified() {
+                Some(modified) => (
+                    modified >= since.as_str(),
+                    format!("{} (want {} or later)", modified, since),
+                ),
+                None => (false, format!("unknown (want {} or later)", since)),
+            };
+            check(FilterStage::Modified, matched, detail);
+        }
+        if let Some(ref values) = self.reference_filter {
+            let referenced = claims.references_any(values);
+            let detail = if referenced {
+                "references a listed entity"
+            } else {
+                "references none of the listed entities"
+            };
+            check(FilterStage::References, referenced, detail.to_string());
+        }
+        if let Some(ref terms) = self.term_filter {
+            match self.term_rejection(claims) {
+                Some(detail) => check(FilterStage::Terms, false, detail),
+                None => check(FilterStage::Terms, true, terms.to_string()),
+            }
+        }
+        if let Some(ref badges) = self.badge_filter {
+            let matched = badges.matches(claims);
+            let detail = if matched {
+                "has a listed badge"
+            } else {
+                "has none of the listed badges"
+            };
+            check(FilterStage::Badges, matched, detail.to_string());
+        }
+        if let Some(ref sites) = self.sitelink_sites {
+            let linked: Vec<&str> = claims
+                .sitelink_sites()
+                .into_iter()
+                .filter(|site| sites.contains(*site))
+                .collect();
+            let detail = if linked.is_empty() {
+                "none on the listed sites".to_string()
+            } else {
+                format!("on {}", linked.join(", "))
+            };
+            check(FilterStage::Sitelinks, !linked.is_empty(), detail);
+        }
+        if let Some(min) = self.min_sitelinks {
+            let (matched, detail) = match claims.sitelink_count() {
+                Some(count) => (count >= min, format!("{} (want at least {})", count, min)),
+                None => (false, format!("unknown (want at least {})", min)),
+            };
+            check(FilterStage::Sitelinks, matched, detail);
+        }
+        if let Some(bounds) = self.statement_bounds() {
+            let (matched, detail) = match claims.statement_count() {
+                Some(count) => (
+                    self.statement_count_matches(count),
+                    format!("{} (want {})", count, bounds),
+                ),
+                None => (
+                    self.statement_count_matches(0),
+                    format!("unknown (want {})", bounds),
+                ),
+            };
+            check(FilterStage::Statements, matched, detail);
+        }
+        if let Some(ref filter) = self.claim_filter {
+            check(FilterStage::Claim, filter.matches(claims), String::new());
+            for line in filter.explain(claims).lines() {
+                checks.push_str(&format!("    {}\n", line));
             }
         }
 
-        // Check entity type
+        let mut verdict = match rejection {
+            Some(stage) => format!("rejected by {}", stage.name()),
+            None => "matched".to_string(),
+        };
+        if self.invert_match {
+            let written = if self.selects(rejection) {
+                "written"
+            } else {
+                "dropped"
+            };
+            verdict.push_str(&format!(" (--invert-match: {})", written));
+        }
+        format!("{}: {}\n{}", entity_id, verdict, checks)
+    }
+
+    /// Whether any filter needs to look at the entity at all
+    pub fn filters_entities(&self) -> bool {
+        self.subject_filter.is_some()
+            || self.shard.is_some()
+            || self.entity_type != "both"
+            || self.modified_since.is_some()
+            || self.reference_filter.is_some()
+            || self.term_filter.is_some()
+            || self.badge_filter.is_some()
+            || self.sitelink_sites.is_some()
+            || self.min_sitelinks.is_some()
+            || self.filters_statement_count()
+            || self.claim_filter.is_some()
+            || self.invert_match
+    }
+
+    /// The configured filters, in the order they are checked
+    pub fn active_stages(&self) -> Vec<FilterStage> {
+        let mut stages = Vec::new();
+        if self.subject_filter.is_some() {
+            stages.push(FilterStage::Subject);
+        }
+        if self.shard.is_some() {
+            stages.push(FilterStage::Shard);
+        }
         if self.entity_type != "both" {
-            let etype = entity
-                .get("type")
-                .and_then(|v| v.as_str())
-                .unwrap_or("item");
-            if etype != self.entity_type {
-                return false;
-            }
+            stages.push(FilterStage::Type);
+        }
+        if self.modified_since.is_some() {
+            stages.push(FilterStage::Modified);
+        }
+        if self.reference_filter.is_some() {
+            stages.push(FilterStage::References);
+        }
+        if self.term_filter.is_some() {
+            stages.push(FilterStage::Terms);
+        }
+        if self.badge_filter.is_some() {
+            stages.push(FilterStage::Badges);
+        }
+        if self.sitelink_sites.is_some() || self.min_sitelinks.is_some() {
+            stages.push(FilterStage::Sitelinks);
         }
+        if self.filters_statement_count() {
+            stages.push(FilterStage::Statements);
+        }
+        if self.claim_filter.is_some() {
+            stages.push(FilterStage::Claim);
+        }
+        stages
+    }
 
-        // Check claim filter
-        if let Some(ref filter) = self.claim_filter {
-            let claims = self.extract_json_claims(entity);
-            if !filter.matches(&claims) {
-                return false;
+    /// Check if a typed JSON entity is selected by the filters
+    pub fn matches_entity(&self, entity: &Entity) -> bool {
+        self.selects(self.entity_rejection(entity))
+    }
+
+    /// The first filter a typed JSON entity fails, if any
+    pub fn entity_rejection(&self, entity: &Entity) -> Option<FilterStage> {
+        // Check subject filter
+        if let Some(ref subjects) = self.subject_filter {
+            if !subjects.contains(entity.id()) {
+                return Some(FilterStage::Subject);
             }
         }
+        if self.shard.is_some_and(|shard| !shard.contains(entity.id())) {
+            return Some(FilterStage::Shard);
+        }
 
-        true
+        // Check entity type
+        if self.entity_type != "both" && entity.entity_type() != self.entity_type {
+            return Some(FilterStage::Type);
+        }
+
+        self.content_rejection(entity)
     }
 
-    /// Extract claims from a JSON entity into the same format used for RDF
-    fn extract_json_claims(&self, entity: &Value) -> HashMap<String, HashSet<String>> {
-        let mut claims: HashMap<String, HashSet<String>> = HashMap::new();
-
-        if let Some(claims_obj) = entity.get("claims").and_then(|c| c.as_object()) {
-            for (prop_id, statements) in claims_obj {
-                let mut values = HashSet::new();
-
-                if let Some(statements_arr) = statements.as_array() {
-                    for statement in statements_arr {
-                        // Get the main snak value
-                        if let Some(mainsnak) = statement.get("mainsnak") {
-                            if let Some(datavalue) = mainsnak.get("datavalue") {
-                                if let Some(value_obj) = datavalue.get("value") {
-                                    // Entity reference
-                                    if let Some(entity_id) =
-                                        value_obj.get("id").and_then(|v| v.as_str())
-                                    {
-                                        values.insert(entity_id.to_string());
-                                    }
-                                    // Numeric ID (older format)
-                                    else if let Some(numeric_id) =
-                                        value_obj.get("numeric-id").and_then(|v| v.as_u64())
-                                    {
-                                        let entity_type = value_obj
-                                            .get("entity-type")
-                                            .and_then(|v| v.as_str())
-                                            .unwrap_or("item");
-                                        let prefix =
-                                            if entity_type == "property" { "P" } else { "Q" };
-                                        values.insert(format!("{}{}", prefix, numeric_id));
-                                    }
-                                }
-                            }
-                        }
-                    }
+    /// Check if a JSON entity matches all filters
+    ///
+    /// An entity that does not fit the typed model cannot be filtered and
+    /// does not match; the first one is reported on stderr.
+    pub fn matches_json(&self, entity: &Value) -> bool {
+        match Entity::deserialize(entity) {
+            Ok(typed) => self.matches_entity(&typed),
+            Err(e) => {
+                static WARNED: AtomicBool = AtomicBool::new(false);
+                if !WARNED.swap(true, Ordering::Relaxed) {
+                    eprintln!(
+                        "Warning: skipping malformed entity {}: {}",
+                        entity
+                            .get("id")
+                            .and_then(Value::as_str)
+                            .unwrap_or("without ID"),
+                        e
+                    );
                 }
-
-                claims.insert(prop_id.clone(), values);
+                false
             }
         }
+    }
 
-        claims
+    /// Extract claims from a JSON entity into the same format used for RDF
+    pub fn extract_json_claims(&self, entity: &Value) -> HashMap<String, HashSet<String>> {
+        match Entity::deserialize(entity) {
+            Ok(typed) => typed.claim_values(),
+            // Read what can be read from an entity the typed model rejects
+            Err(_) => value_claims(entity),
+        }
     }
 
-    /// Check if an attribute should be included in the output
-    fn should_include_attribute(&self, attr: &str) -> bool {
+    /// Check if an attribute, or with `key` one of its entries, should be
+    /// included in the output
+    pub fn should_include_attribute(&self, attr: &str, key: Option<&str>) -> bool {
         if let Some(ref keep) = self.keep_attributes {
-            // If keep is specified, only include listed attributes
+            // If keep is specified, only include listed attributes and keys;
+            // an attribute is included for any of its listed keys
             keep.contains(attr)
+                || attribute_keys(keep, attr).any(|listed| key.is_none_or(|key| key == listed))
         } else if let Some(ref omit) = self.omit_attributes {
-            // If omit is specified, exclude listed attributes
+            // If omit is specified, exclude listed attributes and keys
             !omit.contains(attr)
+                && !key.is_some_and(|key| attribute_keys(omit, attr).any(|listed| listed == key))
         } else {
             // No filter, include everything
             true
         }
     }
 
+    /// Apply the `attribute.key` paths of --keep and --omit to the entries
+    /// of an attribute
+    fn select_attribute_keys(&self, attr: &str, value: &mut Value) {
+        let Some(entries) = value.as_object_mut() else {
+            return;
+        };
+        if let Some(ref keep) = self.keep_attributes {
+            if !keep.contains(attr) {
+                let keys: HashSet<&str> = attribute_keys(keep, attr).collect();
+                entries.retain(|key, _| keys.contains(key.as_str()));
+            }
+        } else if let Some(ref omit) = self.omit_attributes {
+            for key in attribute_keys(omit, attr) {
+                entries.remove(key);
+            }
+        }
+    }
+
     /// Filter a JSON entity to keep only requested data
     pub fn filter_json_entity(&self, entity: &Value) -> Value {
         let obj = match entity.as_object() {
@@ -261,18 +1320,24 @@ impl EntityFilter {
 
         // Process each attribute based on keep/omit filters
         for (key, value) in obj {
-            if !self.should_include_attribute(key) {
+            if !self.should_include_attribute(key, None) {
                 continue;
             }
 
             let mut filtered_value = value.clone();
+            self.select_attribute_keys(key, &mut filtered_value);
 
             // Apply language filter to language-specific attributes
             if let Some(ref langs) = self.language_filter {
                 match key.as_str() {
                     "labels" | "descriptions" | "aliases" => {
                         if let Some(lang_map) = filtered_value.as_object_mut() {
-                            lang_map.retain(|k, _| langs.contains(k));
+                            if self.materialize_mul {
+                                materialize_mul(lang_map, langs);
+                            }
+                            lang_map.retain(|k, _| {
+                                langs.contains(k) || (self.include_mul && k == MUL_LANGUAGE)
+                            });
                         }
                     }
                     "sitelinks" => {
@@ -282,13 +1347,51 @@ impl EntityFilter {
                     _ => {}
                 }
             }
+            if let (Some(form), "labels" | "descriptions" | "aliases") =
+                (self.unicode_form, key.as_str())
+            {
+                if let Some(terms) = filtered_value.as_object_mut() {
+                    form.normalize_terms(terms);
+                }
+            }
 
             // Apply property filter to claims
             if key == "claims" {
-                if let Some(ref props) = self.property_filter {
-                    if let Some(claims_map) = filtered_value.as_object_mut() {
+                if let Some(claims_map) = filtered_value.as_object_mut() {
+                    if let Some(ref props) = self.property_filter {
                         claims_map.retain(|k, _| props.contains(k));
                     }
+                    if let Some(ref datatypes) = self.datatype_filter {
+                        claims_map.retain(|property, statements| {
+                            let datatype = statements
+                                .get(0)
+                                .and_then(|statement| statement["mainsnak"].get("datatype"))
+                                .and_then(Value::as_str);
+                            datatypes.keeps(property, datatype)
+                        });
+                    }
+                    if self.best_rank_only {
+                        keep_best_rank(claims_map);
+                    }
+                    if self.exclude_special_values {
+                        drop_special_values(claims_map);
+                    }
+                    if let Some(ref conversions) = self.unit_conversions {
+                        conversions.normalize(claims_map);
+                    }
+                    if let Some(form) = self.unicode_form {
+                        form.normalize_claims(claims_map);
+                    }
+                    if let Some(ref properties) = self.keep_qualifiers {
+                        keep_qualifiers(claims_map, properties);
+                    }
+                    if self.omit_qualifiers || self.omit_references {
+                        omit_statement_parts(
+                            claims_map,
+                            self.omit_qualifiers,
+                            self.omit_references,
+                        );
+                    }
                 }
             }
 
@@ -302,6 +1405,7 @@ impl EntityFilter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::TempDir;
 
     #[test]
     fn test_has_property_filter() {
@@ -367,6 +1471,41 @@ mod tests {
         assert!(!filter.matches(&claims));
     }
 
+    #[test]
+    fn test_any_value_filter() {
+        let mut claims = HashMap::new();
+        // P1343 with only literal values still has a (valueless) entry
+        claims.insert("P1343".to_string(), HashSet::new());
+        claims.insert("P31".to_string(), HashSet::from(["Q5".to_string()]));
+        let any = |property: &str, letter| ClaimFilter::AnyValue {
+            property: property.to_string(),
+            letter,
+        };
+        assert!(ClaimFilter::HasProperty("P1343".to_string()).matches(&claims));
+        assert!(!any("P1343", None).matches(&claims));
+        assert!(any("P31", None).matches(&claims));
+        assert!(any("P31", Some('Q')).matches(&claims));
+        assert!(!any("P31", Some('P')).matches(&claims));
+    }
+
+    #[test]
+    fn test_require_reference() {
+        let filter = crate::claim_parser::parse_claim_filter("P31:Q5&~P18|P569{P580}")
+            .unwrap()
+            .require_reference()
+            .unwrap();
+        assert_eq!(
+            filter.tree(),
+            "OR\n  AND\n    P31 = Q5 [ref]\n    NOT\n      P18 (any value) [ref]\n  P569 (any value) {P580 (any value)} [ref]\n"
+        );
+        // Truthy claims have no references
+        let claims = HashMap::from([("P31".to_string(), HashSet::from(["Q5".to_string()]))]);
+        assert!(!filter.matches(&claims));
+
+        let path = crate::claim_parser::parse_claim_expression("P31/P279:Q5").unwrap();
+        assert!(path.require_reference().is_err());
+    }
+
     #[test]
     fn test_not_filter() {
         let filter = ClaimFilter::Not(Box::new(ClaimFilter::PropertyValue(
@@ -382,17 +1521,111 @@ mod tests {
         assert!(filter.matches(&claims));
     }
 
+    #[test]
+    fn test_claim_filter_tree() {
+        let filter = crate::claim_parser::parse_claim_filter("P31:Q6256,Q5&(P18|~P576)").unwrap();
+        assert_eq!(
+            filter.tree(),
+            "AND\n  P31 = Q5 | Q6256\n  OR\n    P18 (any value)\n    NOT\n      P576 (any value)\n"
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let filter = EntityFilter {
+            claim_filter: Some(
+                crate::claim_parser::parse_claim_filter("P31:Q5&~P570|P106:Q82955").unwrap(),
+            ),
+            entity_type: "item".to_string(),
+            ..Default::default()
+        };
+        let mut claims = HashMap::new();
+        claims.insert("P31".to_string(), HashSet::from(["Q5".to_string()]));
+        claims.insert("P570".to_string(), HashSet::new());
+
+        assert_eq!(
+            filter.explain("Q42", &claims, Some("item")),
+            "Q42: rejected by claim\n  \
+             ✓ type: item (want item)\n  \
+             ✗ claim\n    \
+             ✗ OR\n      \
+             ✗ AND\n        \
+             ✓ P31 = Q5\n        \
+             ✗ NOT\n          \
+             ✓ P570 (any value)\n      \
+             ✗ P106 = Q82955\n"
+        );
+        claims.remove("P570");
+        assert!(filter
+            .explain("Q42", &claims, Some("item"))
+            .starts_with("Q42: matched\n"));
+    }
+
+    #[test]
+    fn test_shard() {
+        let shard = Shard::parse("3/16").unwrap();
+        assert!(shard.contains("Q35") && shard.contains("P19") && shard.contains("L3-F2"));
+        assert!(!shard.contains("Q36"));
+        assert!(Shard::parse("0/1").unwrap().contains("foo"));
+        for invalid in ["16/16", "3", "a/16", "1/0"] {
+            assert!(Shard::parse(invalid).is_err(), "{}", invalid);
+        }
+
+        let filter = EntityFilter {
+            shard: Some(shard),
+            ..Default::default()
+        };
+        let claims = HashMap::new();
+        assert_eq!(
+            filter.rejection("Q36", &claims, None),
+            Some(FilterStage::Shard)
+        );
+        assert!(filter.matches("Q19", &claims, None));
+    }
+
+    #[test]
+    fn test_reference_filter() {
+        let filter = EntityFilter {
+            reference_filter: Some(parse_id_list("Q937, Q5").unwrap()),
+            ..Default::default()
+        };
+        let mut claims = HashMap::new();
+        claims.insert("P31".to_string(), HashSet::from(["Q5".to_string()]));
+        assert!(filter.matches("Q42", &claims, None));
+        claims.insert("P31".to_string(), HashSet::from(["Q6".to_string()]));
+        assert_eq!(
+            filter.rejection("Q42", &claims, None),
+            Some(FilterStage::References)
+        );
+        assert!(parse_id_list(",").is_err());
+    }
+
+    #[test]
+    fn test_read_id_file_gz() {
+        use std::io::Write;
+        let dir = TempDir::new("ids");
+        let path = dir.join("ids.txt.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&path).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder
+            .write_all(b"# from SPARQL\nQ42\n\n Q5 \nQ42\n")
+            .unwrap();
+        encoder.finish().unwrap();
+        let ids: HashSet<String> = read_id_file(&path.to_string_lossy()).unwrap();
+        assert_eq!(ids, HashSet::from(["Q42".to_string(), "Q5".to_string()]));
+        std::fs::remove_file(&path).ok();
+        assert!(read_id_file::<IdSet>(&path.to_string_lossy()).is_err());
+    }
+
     #[test]
     fn test_language_filter_exact_match() {
         let filter = EntityFilter {
-            claim_filter: None,
-            subject_filter: None,
-            property_filter: None,
             language_filter: Some(HashSet::from(["de".to_string(), "en".to_string()])),
             language_include_subvariants: false,
             entity_type: "item".to_string(),
-            keep_attributes: None,
-            omit_attributes: None,
+            ..Default::default()
         };
 
         // Exact matches
@@ -413,14 +1646,9 @@ mod tests {
     #[test]
     fn test_language_filter_with_subvariants() {
         let filter = EntityFilter {
-            claim_filter: None,
-            subject_filter: None,
-            property_filter: None,
             language_filter: Some(HashSet::from(["de".to_string(), "en".to_string()])),
-            language_include_subvariants: true,
             entity_type: "item".to_string(),
-            keep_attributes: None,
-            omit_attributes: None,
+            ..Default::default()
         };
 
         // Exact matches
@@ -442,14 +1670,8 @@ mod tests {
     #[test]
     fn test_language_filter_none() {
         let filter = EntityFilter {
-            claim_filter: None,
-            subject_filter: None,
-            property_filter: None,
-            language_filter: None,
-            language_include_subvariants: true,
             entity_type: "item".to_string(),
-            keep_attributes: None,
-            omit_attributes: None,
+            ..Default::default()
         };
 
         // Without language filter, everything matches
@@ -459,17 +1681,54 @@ mod tests {
         assert!(filter.matches_language("de-ch"));
     }
 
+    #[test]
+    fn test_mul_terms() {
+        let entity: Value = serde_json::from_str(
+            r#"{"id":"Q1","labels":{"mul":{"language":"mul","value":"Douglas Adams"},"de":{"language":"de","value":"Douglas Adams (Autor)"}},
+                "aliases":{"mul":[{"language":"mul","value":"DNA"}]}}"#,
+        )
+        .unwrap();
+        let filter = |include_mul, materialize_mul| EntityFilter {
+            language_filter: Some(HashSet::from(["en".to_string(), "de".to_string()])),
+            include_mul,
+            materialize_mul,
+            ..Default::default()
+        };
+        let labels = |filter: EntityFilter| filter.filter_json_entity(&entity)["labels"].clone();
+
+        assert_eq!(labels(filter(false, false)).as_object().unwrap().len(), 1);
+        assert!(filter(true, false).matches_language("mul"));
+        assert_eq!(labels(filter(true, false))["mul"]["value"], "Douglas Adams");
+
+        let filtered = filter(true, true).filter_json_entity(&entity);
+        assert_eq!(
+            filtered["labels"],
+            serde_json::json!({
+                "en": {"language": "en", "value": "Douglas Adams"},
+                "de": {"language": "de", "value": "Douglas Adams (Autor)"}
+            })
+        );
+        assert_eq!(filtered["aliases"]["de"][0]["language"], "de");
+        assert_eq!(filtered["aliases"]["en"][0]["value"], "DNA");
+        assert!(filtered["aliases"].get("mul").is_none());
+
+        // A mul label stands in for any required one
+        let require = |include_mul| EntityFilter {
+            include_mul,
+            term_filter: TermFilter::new(None, None, None, Some("fr"), "all").unwrap(),
+            ..Default::default()
+        };
+        let typed = Entity::deserialize(&entity).unwrap();
+        assert!(require(true).term_rejection(&typed).is_none());
+        assert!(require(false).term_rejection(&typed).is_some());
+    }
+
     #[test]
     fn test_keep_attributes() {
         let filter = EntityFilter {
-            claim_filter: None,
-            subject_filter: None,
-            property_filter: None,
-            language_filter: None,
-            language_include_subvariants: true,
             entity_type: "item".to_string(),
             keep_attributes: Some(HashSet::from(["id".to_string(), "labels".to_string()])),
-            omit_attributes: None,
+            ..Default::default()
         };
 
         let entity: Value = serde_json::from_str(
@@ -496,17 +1755,12 @@ mod tests {
     #[test]
     fn test_omit_attributes() {
         let filter = EntityFilter {
-            claim_filter: None,
-            subject_filter: None,
-            property_filter: None,
-            language_filter: None,
-            language_include_subvariants: true,
             entity_type: "item".to_string(),
-            keep_attributes: None,
             omit_attributes: Some(HashSet::from([
                 "claims".to_string(),
                 "sitelinks".to_string(),
             ])),
+            ..Default::default()
         };
 
         let entity: Value = serde_json::from_str(
@@ -515,7 +1769,9 @@ mod tests {
             "type": "item",
             "labels": {"en": {"language": "en", "value": "Douglas Adams"}},
             "claims": {},
-            "sitelinks": {}
+            "sitelinks": {},
+            "lastrevid": 2245066389,
+            "future": true
         }"#,
         )
         .unwrap();
@@ -528,6 +1784,313 @@ mod tests {
         assert!(obj.contains_key("labels"));
         assert!(!obj.contains_key("claims"));
         assert!(!obj.contains_key("sitelinks"));
+        // Fields unknown to --keep/--omit pass through
+        assert!(obj.contains_key("lastrevid") && obj.contains_key("future"));
+
+        let filter = EntityFilter {
+            omit_attributes: parse_attribute_filters(
+                None,
+                Some("lastrevid,modified,pageid,ns,title"),
+            )
+            .unwrap()
+            .1,
+            ..Default::default()
+        };
+        let filtered = filter.filter_json_entity(&entity);
+        assert!(!filtered.as_object().unwrap().contains_key("lastrevid"));
+    }
+
+    #[test]
+    fn test_best_rank_only() {
+        let filter = EntityFilter {
+            best_rank_only: true,
+            ..Default::default()
+        };
+        let entity: Value = serde_json::from_str(
+            r#"{"id":"Q1","claims":{
+                "P1082":[{"id":"a","rank":"normal"},{"id":"b","rank":"preferred"},{"id":"c","rank":"preferred"}],
+                "P31":[{"id":"d","rank":"deprecated"},{"id":"e","rank":"normal"},{"id":"f"}],
+                "P18":[{"id":"g","rank":"deprecated"}]}}"#,
+        )
+        .unwrap();
+        let filtered = filter.filter_json_entity(&entity);
+        let ids = |property: &str| -> Vec<&str> {
+            filtered["claims"][property]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|statement| statement["id"].as_str().unwrap())
+                .collect()
+        };
+        assert_eq!(ids("P1082"), ["b", "c"]);
+        assert_eq!(ids("P31"), ["e", "f"]);
+        assert!(filtered["claims"].get("P18").is_none());
+    }
+
+    #[test]
+    fn test_exclude_special_values() {
+        let filter = crate::claim_parser::parse_claim_filter("P570|~P40&P39{P582}")
+            .unwrap()
+            .exclude_special_values();
+        assert_eq!(
+            filter.tree(),
+            "OR\n  P570 (specific value)\n  AND\n    NOT\n      P40 (specific value)\n    P39 (specific value) {P582 (specific value)}\n"
+        );
+
+        let filter = EntityFilter {
+            exclude_special_values: true,
+            ..Default::default()
+        };
+        let entity: Value = serde_json::from_str(
+            r#"{"id":"Q1","claims":{
+                "P570":[{"id":"a","mainsnak":{"snaktype":"somevalue"}}],
+                "P40":[{"id":"b","mainsnak":{"snaktype":"novalue"}},{"id":"c","mainsnak":{"snaktype":"value"}}],
+                "P39":[{"id":"d","mainsnak":{"snaktype":"value"},
+                    "qualifiers":{"P580":[{"snaktype":"value"}],"P582":[{"snaktype":"novalue"}]},
+                    "qualifiers-order":["P580","P582"]}]}}"#,
+        )
+        .unwrap();
+        let filtered = filter.filter_json_entity(&entity);
+        assert!(filtered["claims"].get("P570").is_none());
+        assert_eq!(filtered["claims"]["P40"].as_array().unwrap().len(), 1);
+        assert_eq!(filtered["claims"]["P40"][0]["id"], "c");
+        let statement = &filtered["claims"]["P39"][0];
+        assert!(statement["qualifiers"].get("P582").is_none());
+        assert_eq!(statement["qualifiers-order"], serde_json::json!(["P580"]));
+    }
+
+    #[test]
+    fn test_omit_statement_parts() {
+        let entity: Value = serde_json::from_str(
+            r#"{"id":"Q1","claims":{"P39":[{"id":"a","mainsnak":{"snaktype":"value"},
+                "qualifiers":{"P580":[{"snaktype":"value"}]},"qualifiers-order":["P580"],
+                "references":[{"snaks":{"P248":[{"snaktype":"value"}]}}]}]}}"#,
+        )
+        .unwrap();
+        let statement = |omit_qualifiers, omit_references| {
+            let filter = EntityFilter {
+                omit_qualifiers,
+                omit_references,
+                ..Default::default()
+            };
+            filter.filter_json_entity(&entity)["claims"]["P39"][0].clone()
+        };
+        let stripped = statement(true, false);
+        assert!(stripped.get("qualifiers").is_none() && stripped.get("qualifiers-order").is_none());
+        assert!(stripped.get("references").is_some());
+        let stripped = statement(false, true);
+        assert!(stripped.get("qualifiers").is_some() && stripped.get("references").is_none());
+        let stripped = statement(true, true);
+        assert_eq!(
+            stripped,
+            serde_json::json!({"id": "a", "mainsnak": {"snaktype": "value"}})
+        );
+    }
+
+    #[test]
+    fn test_keep_qualifiers() {
+        let filter = EntityFilter {
+            keep_qualifiers: Some(HashSet::from(["P580".to_string(), "P582".to_string()])),
+            ..Default::default()
+        };
+        let entity: Value = serde_json::from_str(
+            r#"{"id":"Q1","claims":{"P39":[
+                {"id":"a","qualifiers":{"P580":[{"snaktype":"value"}],"P1365":[{"snaktype":"value"}]},
+                 "qualifiers-order":["P1365","P580"]},
+                {"id":"b","qualifiers":{"P642":[{"snaktype":"value"}]},"qualifiers-order":["P642"]},
+                {"id":"c"}]}}"#,
+        )
+        .unwrap();
+        let filtered = filter.filter_json_entity(&entity);
+        let statements = &filtered["claims"]["P39"];
+        assert_eq!(
+            statements[0]["qualifiers"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            ["P580"]
+        );
+        assert_eq!(
+            statements[0]["qualifiers-order"],
+            serde_json::json!(["P580"])
+        );
+        assert_eq!(statements[1]["qualifiers"], serde_json::json!({}));
+        assert_eq!(statements[1]["qualifiers-order"], serde_json::json!([]));
+        assert!(statements[2].get("qualifiers").is_none());
+    }
+
+    #[test]
+    fn test_badge_filter() {
+        let entity: Value = serde_json::from_str(
+            r#"{"id":"Q64","sitelinks":{
+                "enwiki":{"site":"enwiki","title":"Berlin","badges":["Q17437798"]},
+                "dewiki":{"site":"dewiki","title":"Berlin","badges":[]}}}"#,
+        )
+        .unwrap();
+        let filter = |badges, sites| EntityFilter {
+            badge_filter: Some(BadgeFilter::parse(badges, sites).unwrap()),
+            ..Default::default()
+        };
+        assert!(filter("Q17437796,Q17437798", None).matches_json(&entity));
+        assert!(filter("Q17437798", Some("enwiki")).matches_json(&entity));
+        assert!(!filter("Q17437798", Some("dewiki,frwiki")).matches_json(&entity));
+        let featured = filter("Q17437796", None);
+        assert!(!featured.matches_json(&entity));
+        assert_eq!(
+            featured.rejection("Q64", &HashMap::new(), None),
+            Some(FilterStage::Badges)
+        );
+        assert!(BadgeFilter::parse(" , ", None).is_err());
+        assert!(BadgeFilter::parse("Q17437796", Some("")).is_err());
+    }
+
+    #[test]
+    fn test_min_sitelinks() {
+        let filter = EntityFilter {
+            min_sitelinks: Some(2),
+            ..Default::default()
+        };
+        let entity = |sitelinks: &str| -> Value {
+            serde_json::from_str(&format!(r#"{{"id":"Q1","sitelinks":{}}}"#, sitelinks)).unwrap()
+        };
+        assert!(filter.matches_json(&entity(
+            r#"{"enwiki":{"site":"enwiki","title":"A"},"dewiki":{"site":"dewiki","title":"A"}}"#
+        )));
+        assert!(!filter.matches_json(&entity(r#"{"enwiki":{"site":"enwiki","title":"A"}}"#)));
+        assert!(!filter.matches_json(&entity("[]")));
+        // Without a count, as for RDF entities without EntityData, nothing passes
+        assert_eq!(
+            filter.rejection("Q1", &HashMap::new(), None),
+            Some(FilterStage::Sitelinks)
+        );
+    }
+
+    #[test]
+    fn test_sitelink_sites() {
+        let filter = EntityFilter {
+            sitelink_sites: Some(HashSet::from(["dewiki".to_string(), "frwiki".to_string()])),
+            ..Default::default()
+        };
+        let entity = |sitelinks: &str| -> Value {
+            serde_json::from_str(&format!(r#"{{"id":"Q1","sitelinks":{}}}"#, sitelinks)).unwrap()
+        };
+        let berlin = entity(r#"{"enwiki":{"site":"enwiki"},"dewiki":{"site":"dewiki"}}"#);
+        assert!(filter.matches_json(&berlin));
+        assert!(!filter.matches_json(&entity(r#"{"enwiki":{"site":"enwiki"}}"#)));
+        let typed = Entity::deserialize(&berlin).unwrap();
+        assert!(filter
+            .explain("Q1", &typed, None)
+            .contains("✓ sitelinks: on dewiki"));
+    }
+
+    #[test]
+    fn test_statement_count() {
+        let entity: Value = serde_json::from_str(
+            r#"{"id":"Q1","claims":{"P31":[{"mainsnak":{}}],"P18":[{"mainsnak":{}},{"mainsnak":{}}]}}"#,
+        )
+        .unwrap();
+        let filter = |min_statements, max_statements| EntityFilter {
+            min_statements,
+            max_statements,
+            ..Default::default()
+        };
+        assert!(filter(Some(3), None).matches_json(&entity));
+        assert!(!filter(Some(4), None).matches_json(&entity));
+        assert!(filter(Some(1), Some(3)).matches_json(&entity));
+        assert!(!filter(None, Some(2)).matches_json(&entity));
+        assert_eq!(
+            filter(Some(1), None).rejection("Q1", &HashMap::new(), None),
+            Some(FilterStage::Statements)
+        );
+        assert_eq!(
+            filter(None, Some(2)).rejection("Q1", &HashMap::new(), None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_modified_since() {
+        let filter = EntityFilter {
+            modified_since: Some(parse_timestamp("2024-01-01").unwrap()),
+            ..Default::default()
+        };
+        let entity = |modified: &str| -> Value {
+            serde_json::from_str(&format!(r#"{{"id":"Q1","modified":"{}"}}"#, modified)).unwrap()
+        };
+        assert!(filter.matches_json(&entity("2024-01-01T00:00:00Z")));
+        assert!(filter.matches_json(&entity("2025-06-30T12:00:00Z")));
+        assert!(!filter.matches_json(&entity("2023-12-31T23:59:59Z")));
+        assert!(!filter.matches_json(&serde_json::json!({"id": "Q1"})));
+
+        assert!(parse_timestamp("2024-01-01T12:00:00Z").is_ok());
+        for invalid in ["2024", "2024-1-1", "yesterday", "2024-01-01 12:00"] {
+            assert!(parse_timestamp(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_claims_of_malformed_entity() {
+        // A label without a string value does not fit the typed model
+        let entity = serde_json::json!({
+            "id": "Q1",
+            "labels": {"en": {"language": "en", "value": 1}},
+            "claims": {"P31": [
+                {"mainsnak": {"datavalue": {"value": {"id": "Q5"}}}},
+                {"mainsnak": {"datavalue": {"value": {"entity-type": "item", "numeric-id": 42}}}}
+            ]}
+        });
+        let claims = EntityFilter::default().extract_json_claims(&entity);
+        assert_eq!(
+            claims["P31"],
+            HashSet::from(["Q5".to_string(), "Q42".to_string()])
+        );
+        assert!(!EntityFilter::default().matches_json(&entity));
+    }
+
+    #[test]
+    fn test_datatype_filter() {
+        let filter = EntityFilter {
+            datatype_filter: Some(DatatypeFilter::parse("external-id,url", false).unwrap()),
+            unit_conversions: None,
+            unicode_form: None,
+            omit_qualifiers: false,
+            keep_qualifiers: None,
+            omit_references: false,
+            claims_only: false,
+            ..Default::default()
+        };
+        let entity: Value = serde_json::from_str(
+            r#"{"id":"Q1","claims":{
+                "P212":[{"mainsnak":{"datatype":"external-id"}}],
+                "P856":[{"mainsnak":{"datatype":"url"}}],
+                "P31":[{"mainsnak":{"datatype":"wikibase-item"}}],
+                "P18":[{"mainsnak":{}}]}}"#,
+        )
+        .unwrap();
+        let properties = |filter: &EntityFilter| {
+            let filtered = filter.filter_json_entity(&entity);
+            let mut properties: Vec<String> = filtered["claims"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect();
+            properties.sort();
+            properties
+        };
+        assert_eq!(properties(&filter), ["P212", "P856"]);
+        let filter = EntityFilter {
+            datatype_filter: Some(DatatypeFilter::parse("external-id", true).unwrap()),
+            unit_conversions: None,
+            unicode_form: None,
+            omit_qualifiers: false,
+            keep_qualifiers: None,
+            omit_references: false,
+            claims_only: false,
+            ..Default::default()
+        };
+        assert_eq!(properties(&filter), ["P18", "P31", "P856"]);
     }
 
     #[test]
@@ -547,6 +2110,59 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_parse_attribute_paths() {
+        let (keep, _) =
+            parse_attribute_filters(Some("id,Claims.P31,sitelinks.enwiki"), None).unwrap();
+        let keep = keep.unwrap();
+        assert!(keep.contains("claims.P31") && keep.contains("sitelinks.enwiki"));
+        assert!(parse_attribute_filters(Some("id.x"), None).is_err());
+        assert!(parse_attribute_filters(None, Some("labels.")).is_err());
+        assert!(parse_attribute_filters(None, Some("claims.P31.x")).is_err());
+    }
+
+    #[test]
+    fn test_attribute_paths() {
+        let entity: Value = serde_json::from_str(
+            r#"{"id":"Q64","type":"item",
+                "labels":{"en":{"language":"en","value":"Berlin"},"de":{"language":"de","value":"Berlin"}},
+                "claims":{"P31":[],"P17":[]},
+                "sitelinks":{"enwiki":{"site":"enwiki","title":"Berlin"}}}"#,
+        )
+        .unwrap();
+        let keys = |value: &Value| {
+            value
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let filter = |keep: Option<&str>, omit: Option<&str>| {
+            let (keep_attributes, omit_attributes) = parse_attribute_filters(keep, omit).unwrap();
+            EntityFilter {
+                keep_attributes,
+                omit_attributes,
+                ..Default::default()
+            }
+        };
+
+        let filtered = filter(Some("id,claims.P31,labels.en"), None).filter_json_entity(&entity);
+        assert_eq!(keys(&filtered), ["claims", "id", "labels"]);
+        assert_eq!(keys(&filtered["claims"]), ["P31"]);
+        assert_eq!(keys(&filtered["labels"]), ["en"]);
+
+        // A whole attribute wins over its paths
+        let filtered = filter(Some("labels,labels.en"), None).filter_json_entity(&entity);
+        assert_eq!(keys(&filtered["labels"]), ["de", "en"]);
+
+        let filtered =
+            filter(None, Some("claims.P17,sitelinks.enwiki")).filter_json_entity(&entity);
+        assert_eq!(keys(&filtered["claims"]), ["P31"]);
+        assert_eq!(filtered["sitelinks"], serde_json::json!({}));
+        assert_eq!(keys(&filtered["labels"]), ["de", "en"]);
+    }
+
     #[test]
     fn test_parse_attribute_filters_both_error() {
         let result = parse_attribute_filters(Some("id"), Some("claims"));
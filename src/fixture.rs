@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+use std::io::BufWriter;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::compression::{
+    create_input_reader, create_output_writer, determine_compression, finish_output,
+    INPUT_BUFFER_SIZE, OUTPUT_BUFFER_SIZE,
+};
+use crate::filter::EntityFilter;
+use crate::rdf::{OutputFormat, ProcessingOptions};
+use crate::{run_filter, FilterError};
+
+/// Arguments for the `make-fixture` subcommand
+#[derive(clap::Args, Debug)]
+pub struct FixtureArgs {
+    /// Entity IDs to extract (comma-separated, e.g., Q42,Q64)
+    #[arg(long)]
+    ids: String,
+
+    /// Input dump file (read twice, so stdin is not supported)
+    #[arg()]
+    input: String,
+
+    /// Input format: auto, rdf, turtle, json
+    #[arg(short = 'f', long, default_value = "auto")]
+    format: String,
+
+    /// Output file (stdout if not provided). Extension determines compression (.gz, .lz4)
+    #[arg(long)]
+    output: Option<String>,
+
+    /// Output compression: none, gzip, lz4 (auto-detected from --output extension)
+    #[arg(long, default_value = "none")]
+    compress: String,
+
+    /// Filter languages for labels/descriptions (comma-separated, e.g., en,de,fr)
+    #[arg(short = 'l', long)]
+    languages: Option<String>,
+
+    /// Only extract the given entities, not the entities and properties they reference
+    #[arg(long)]
+    no_dependencies: bool,
+
+    /// Show progress info on stderr
+    #[arg(short = 'p', long)]
+    progress: bool,
+}
+
+/// Collect the properties and entity values referenced by an entity's claims
+pub fn collect_dependencies(entity: &Value) -> HashSet<String> {
+    let claims = EntityFilter::default().extract_json_claims(entity);
+    let mut dependencies = HashSet::new();
+    for (prop_id, values) in claims {
+        dependencies.insert(prop_id);
+        dependencies.extend(values);
+    }
+    dependencies
+}
+
+/// Run the `make-fixture` subcommand
+///
+/// The first pass extracts the requested entities to find their dependencies,
+/// the second pass writes requested entities plus dependencies in the input format.
+pub fn run(args: FixtureArgs) -> Result<(), FilterError> {
+    let ids: HashSet<String> = args
+        .ids
+        .split(',')
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect();
+    if ids.is_empty() {
+        return Err(FilterError::Parse("--ids must not be empty".to_string()));
+    }
+
+    let language_filter: Option<HashSet<String>> = args
+        .languages
+        .as_ref()
+        .map(|s| s.split(',').map(|l| l.trim().to_string()).collect());
+
+    let mut subjects = ids.clone();
+
+    if !args.no_dependencies {
+        if args.progress {
+            eprintln!(
+                "Pass 1: collecting dependencies of {} entities...",
+                ids.len()
+            );
+        }
+
+        let (reader, format) = create_input_reader(&args.input, &args.format, INPUT_BUFFER_SIZE)?;
+        let filter = Arc::new(EntityFilter {
+            subject_filter: Some(ids.iter().cloned().collect()),
+            ..Default::default()
+        });
+        let options = ProcessingOptions {
+            output_format: OutputFormat::Json,
+            batch_size: 0,
+            ..Default::default()
+        };
+
+        let mut found = Vec::new();
+        run_filter(reader, &format, &mut found, &mut [], &filter, &options)?;
+
+        let mut found_ids = HashSet::new();
+        for line in String::from_utf8_lossy(&found).lines() {
+            let entity: Value = serde_json::from_str(line)?;
+            if let Some(id) = entity.get("id").and_then(|v| v.as_str()) {
+                found_ids.insert(id.to_string());
+            }
+            subjects.extend(collect_dependencies(&entity));
+        }
+
+        let mut missing: Vec<&String> = ids.difference(&found_ids).collect();
+        missing.sort();
+        if !missing.is_empty() {
+            eprintln!(
+                "Warning: entities not found in {}: {}",
+                args.input,
+                missing
+                    .iter()
+                    .map(|s| s.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
+
+    if args.progress {
+        eprintln!(
+            "Pass 2: writing {} entities ({} dependencies)...",
+            subjects.len(),
+            subjects.len() - ids.len()
+        );
+    }
+
+    let (reader, format) = create_input_reader(&args.input, &args.format, INPUT_BUFFER_SIZE)?;
+    let filter = Arc::new(EntityFilter {
+        subject_filter: Some(subjects.into()),
+        language_filter,
+        ..Default::default()
+    });
+    let output_format = match format.as_str() {
+        "json" | "ndjson" => OutputFormat::Json,
+        _ => OutputFormat::NTriples,
+    };
+    let options = ProcessingOptions {
+        show_progress: args.progress,
+        batch_size: 0,
+        output_format,
+        ..Default::default()
+    };
+
+    let compression = determine_compression(&args.compress, args.output.as_deref());
+    let output_writer = create_output_writer(args.output.as_deref(), &compression)?;
+    let mut output = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, output_writer);
+
+    run_filter(reader, &format, &mut output, &mut [], &filter, &options)?;
+    finish_output(output)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_collect_dependencies() {
+        let entity: Value = serde_json::from_str(
+            r#"{"id":"Q42","claims":{
+                "P31":[{"mainsnak":{"datavalue":{"value":{"entity-type":"item","id":"Q5"},"type":"wikibase-entityid"}}}],
+                "P18":[{"mainsnak":{"datavalue":{"value":"Douglas adams portrait.jpg","type":"string"}}}]
+            }}"#,
+        )
+        .unwrap();
+
+        let deps = collect_dependencies(&entity);
+        assert_eq!(
+            deps,
+            HashSet::from(["P31".to_string(), "Q5".to_string(), "P18".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_make_fixture_json() {
+        let dir = TempDir::new("fixture");
+        let input = dir.join("dump.json");
+        let output = dir.join("fixture.json");
+        std::fs::write(
+            &input,
+            r#"[
+{"id":"Q5","type":"item","claims":{}},
+{"id":"Q42","type":"item","claims":{"P31":[{"mainsnak":{"datavalue":{"value":{"entity-type":"item","id":"Q5"},"type":"wikibase-entityid"}}}]}},
+{"id":"Q64","type":"item","claims":{}},
+{"id":"P31","type":"property","claims":{}}
+]
+"#,
+        )
+        .unwrap();
+
+        run(FixtureArgs {
+            ids: "Q42".to_string(),
+            input: input.to_string_lossy().to_string(),
+            format: "auto".to_string(),
+            output: Some(output.to_string_lossy().to_string()),
+            compress: "none".to_string(),
+            languages: None,
+            no_dependencies: false,
+            progress: false,
+        })
+        .unwrap();
+
+        let written = std::fs::read_to_string(&output).unwrap();
+        let ids: Vec<String> = written
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap()["id"].to_string())
+            .collect();
+        assert_eq!(ids, vec!["\"Q5\"", "\"Q42\"", "\"P31\""]);
+    }
+}
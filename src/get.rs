@@ -0,0 +1,210 @@
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+
+use crate::atomic::PendingFile;
+use crate::compression::{
+    create_compressed_writer, determine_compression, finish_output, OUTPUT_BUFFER_SIZE,
+};
+use crate::filter::EntityFilter;
+use crate::index::Index;
+use crate::rdf::{OutputFormat, ProcessingOptions};
+use crate::{http, run_filter, FilterError};
+
+/// Arguments for the `get` subcommand
+#[derive(clap::Args, Debug)]
+pub struct GetArgs {
+    /// Entity IDs to print, in this order
+    #[arg(required = true)]
+    ids: Vec<String>,
+
+    /// Indexed dump (file or URL)
+    #[arg(long)]
+    input: String,
+
+    /// Index written by the index subcommand (INPUT.idx if not provided)
+    #[arg(long)]
+    index: Option<String>,
+
+    /// Output format: json, ntriples (the format of the dump if not provided)
+    #[arg(short = 'o', long)]
+    output_format: Option<String>,
+
+    /// Output file (stdout if not provided). Extension determines compression (.gz, .lz4)
+    #[arg(long)]
+    output: Option<String>,
+
+    /// Reconnects with a Range request after transient network errors
+    #[arg(long, default_value_t = http::DEFAULT_RETRIES)]
+    http_retries: u32,
+}
+
+/// Write the lines of one entity, converting them if the dump is in another
+/// format; returns false if nothing was written, as for lines the conversion
+/// reads no entity from
+fn write_entity<W: Write>(
+    output: &mut W,
+    data: &[u8],
+    dump_json: bool,
+    json: bool,
+) -> Result<bool, FilterError> {
+    if dump_json == json {
+        output.write_all(data)?;
+        output.write_all(b"\n")?;
+        return Ok(!data.is_empty());
+    }
+    let options = ProcessingOptions {
+        batch_size: 0,
+        output_format: if json {
+            OutputFormat::Json
+        } else {
+            OutputFormat::NTriples
+        },
+        ..Default::default()
+    };
+    let format = if dump_json { "json" } else { "rdf" };
+    let filter = Arc::new(EntityFilter::default());
+    let reader = io::Cursor::new([data, b"\n"].concat());
+    let mut converted = Vec::new();
+    run_filter(reader, format, &mut converted, &mut [], &filter, &options)?;
+    output.write_all(&converted)?;
+    Ok(!converted.is_empty())
+}
+
+/// Run the `get` subcommand
+pub fn run(args: GetArgs) -> Result<(), FilterError> {
+    let index_path = args
+        .index
+        .clone()
+        .unwrap_or_else(|| format!("{}.idx", args.input));
+    let mut index = Index::open(&index_path)?;
+    if !http::is_url(&args.input) && std::fs::metadata(&args.input)?.len() != index.input_size {
+        return Err(FilterError::Parse(format!(
+            "{} changed since {} was written, index it again",
+            args.input, index_path
+        )));
+    }
+    let json = match args.output_format.as_deref() {
+        None => index.json,
+        Some("json") => true,
+        Some("ntriples") => false,
+        Some(other) => {
+            return Err(FilterError::Parse(format!(
+                "get writes json or ntriples, not {}",
+                other
+            )))
+        }
+    };
+
+    let mut pending = args
+        .output
+        .as_deref()
+        .map(|path| PendingFile::new(path, false, http::DEFAULT_RETRIES))
+        .transpose()?;
+    let file: Box<dyn Write> = match &mut pending {
+        Some(pending) => pending.create()?,
+        None => Box::new(io::stdout()),
+    };
+    let compression = determine_compression("none", args.output.as_deref());
+    let mut output = BufWriter::with_capacity(
+        OUTPUT_BUFFER_SIZE,
+        create_compressed_writer(file, &compression),
+    );
+    let mut missing = 0;
+    for id in &args.ids {
+        match index.lookup(id)? {
+            Some(entry) => {
+                let data = index.read_entity(&args.input, entry, args.http_retries)?;
+                if !write_entity(&mut output, &data, index.json, json)? {
+                    eprintln!(
+                        "{} is in {}, but no entity was read from its lines",
+                        id, index_path
+                    );
+                    missing += 1;
+                }
+            }
+            None => {
+                eprintln!("{} is not in {}", id, index_path);
+                missing += 1;
+            }
+        }
+    }
+    finish_output(output)?;
+    if let Some(pending) = pending {
+        pending.commit()?;
+    }
+
+    if missing > 0 {
+        return Err(FilterError::Parse(format!(
+            "{} of {} entities not found or not written",
+            missing,
+            args.ids.len()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Entry;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_write_entity_converts() {
+        let triples = "<http://www.wikidata.org/entity/Q1> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://wikiba.se/ontology#Item> .\n<http://www.wikidata.org/entity/Q1> <http://www.w3.org/2000/01/rdf-schema#label> \"one\"@en .";
+        let mut output = Vec::new();
+        write_entity(&mut output, triples.as_bytes(), false, false).unwrap();
+        assert_eq!(output, format!("{}\n", triples).as_bytes());
+
+        let mut output = Vec::new();
+        write_entity(&mut output, triples.as_bytes(), false, true).unwrap();
+        let entity: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(entity["id"], "Q1");
+        assert_eq!(entity["labels"]["en"]["value"], "one");
+
+        // Property entities convert like items
+        let property = "<http://www.wikidata.org/entity/P31> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://wikiba.se/ontology#Property> .\n<http://www.wikidata.org/entity/P31> <http://www.w3.org/2000/01/rdf-schema#label> \"instance of\"@en .";
+        let mut output = Vec::new();
+        assert!(write_entity(&mut output, property.as_bytes(), false, true).unwrap());
+        let entity: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(entity["id"], "P31");
+        assert_eq!(entity["type"], "property");
+
+        // Lines without an entity write nothing
+        let mut output = Vec::new();
+        let other = "<http://example.org/x> <http://example.org/y> \"z\" .";
+        assert!(!write_entity(&mut output, other.as_bytes(), false, true).unwrap());
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_unwritten_entity_fails() {
+        let dir = TempDir::new("get");
+        let (dump, index) = (dir.file("dump.nt"), dir.file("dump.nt.idx"));
+        let data = "<http://example.org/x> <http://example.org/y> \"z\" .\n";
+        std::fs::write(&dump, data).unwrap();
+        // An index that places P31 on the line of another subject
+        let entry = Entry {
+            key: (b'P', 31),
+            offset: 0,
+            length: data.len() as u32 - 1,
+        };
+        let mut bytes = crate::index::MAGIC.to_vec();
+        bytes.extend([b'n', 0]);
+        bytes.extend((data.len() as u64).to_le_bytes());
+        bytes.extend(1u64.to_le_bytes());
+        bytes.extend(entry.to_bytes());
+        std::fs::write(&index, bytes).unwrap();
+
+        let args = GetArgs {
+            ids: vec!["P31".to_string()],
+            input: dump,
+            index: Some(index),
+            output_format: Some("json".to_string()),
+            output: Some(dir.file("out.ndjson")),
+            http_retries: 0,
+        };
+        let error = run(args).unwrap_err().to_string();
+        assert!(error.contains("1 of 1 entities"), "{}", error);
+    }
+}
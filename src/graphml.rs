@@ -0,0 +1,236 @@
+use std::collections::HashSet;
+use std::io::Write;
+
+use serde_json::Value;
+
+/// Escape text for XML attribute values and character data
+pub fn escape_xml(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Writes matched entities as GraphML nodes and their entity-valued claims as edges
+///
+/// Edge targets that are not matched themselves are added as bare nodes when
+/// the graph is finished, so the file stays valid for Gephi and other tools.
+pub struct GraphMlWriter {
+    languages: Vec<String>,
+    header_written: bool,
+    nodes: HashSet<String>,
+    targets: HashSet<String>,
+}
+
+impl GraphMlWriter {
+    /// Create a writer with one label attribute per language (default: en)
+    pub fn new(language_filter: Option<&HashSet<String>>) -> Self {
+        let mut languages: Vec<String> = match language_filter {
+            Some(langs) => langs.iter().cloned().collect(),
+            None => vec!["en".to_string()],
+        };
+        languages.sort();
+        Self {
+            languages,
+            header_written: false,
+            nodes: HashSet::new(),
+            targets: HashSet::new(),
+        }
+    }
+
+    fn push_header(&mut self, buffer: &mut String) {
+        if self.header_written {
+            return;
+        }
+        buffer.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        buffer.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        buffer.push_str(
+            "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+        );
+        for lang in &self.languages {
+            let lang = escape_xml(lang);
+            buffer.push_str(&format!(
+                "  <key id=\"label_{0}\" for=\"node\" attr.name=\"label_{0}\" attr.type=\"string\"/>\n",
+                lang
+            ));
+        }
+        buffer.push_str(
+            "  <key id=\"type\" for=\"node\" attr.name=\"type\" attr.type=\"string\"/>\n",
+        );
+        buffer.push_str(
+            "  <key id=\"property\" for=\"edge\" attr.name=\"property\" attr.type=\"string\"/>\n",
+        );
+        buffer.push_str("  <graph id=\"wikidata\" edgedefault=\"directed\">\n");
+        self.header_written = true;
+    }
+
+    /// Append the node and edges of one (filtered) JSON entity
+    pub fn push_entity(&mut self, buffer: &mut String, entity: &Value) {
+        self.push_header(buffer);
+
+        let id = match entity.get("id").and_then(|v| v.as_str()) {
+            Some(id) => id,
+            None => return,
+        };
+        if !self.nodes.insert(id.to_string()) {
+            return;
+        }
+
+        let label = |lang: &str| {
+            entity
+                .get("labels")
+                .and_then(|l| l.get(lang))
+                .and_then(|l| l.get("value"))
+                .and_then(|v| v.as_str())
+        };
+
+        buffer.push_str(&format!("    <node id=\"{}\">\n", escape_xml(id)));
+        let display = self
+            .languages
+            .iter()
+            .find_map(|lang| label(lang))
+            .unwrap_or(id);
+        buffer.push_str(&format!(
+            "      <data key=\"label\">{}</data>\n",
+            escape_xml(display)
+        ));
+        for lang in &self.languages {
+            if let Some(value) = label(lang) {
+                buffer.push_str(&format!(
+                    "      <data key=\"label_{}\">{}</data>\n",
+                    escape_xml(lang),
+                    escape_xml(value)
+                ));
+            }
+        }
+        if let Some(entity_type) = entity.get("type").and_then(|v| v.as_str()) {
+            buffer.push_str(&format!(
+                "      <data key=\"type\">{}</data>\n",
+                escape_xml(entity_type)
+            ));
+        }
+        buffer.push_str("    </node>\n");
+
+        let claims = match entity.get("claims").and_then(|c| c.as_object()) {
+            Some(claims) => claims,
+            None => return,
+        };
+        for (prop, statements) in claims {
+            let mut seen = HashSet::new();
+            for statement in statements.as_array().into_iter().flatten() {
+                let target = statement
+                    .get("mainsnak")
+                    .and_then(|m| m.get("datavalue"))
+                    .filter(|d| d.get("type").and_then(|t| t.as_str()) == Some("wikibase-entityid"))
+                    .and_then(|d| d.get("value"))
+                    .and_then(|v| v.get("id"))
+                    .and_then(|v| v.as_str());
+                let target = match target {
+                    Some(t) if seen.insert(t) => t,
+                    _ => continue,
+                };
+                buffer.push_str(&format!(
+                    "    <edge source=\"{}\" target=\"{}\">\n      <data key=\"property\">{}</data>\n    </edge>\n",
+                    escape_xml(id),
+                    escape_xml(target),
+                    escape_xml(prop)
+                ));
+                self.targets.insert(target.to_string());
+            }
+        }
+    }
+
+    /// Write a batch of (filtered) JSON entities
+    pub fn write_entities<W: Write>(
+        &mut self,
+        output: &mut W,
+        entities: &[Value],
+    ) -> std::io::Result<()> {
+        let mut buffer = String::new();
+        for entity in entities {
+            self.push_entity(&mut buffer, entity);
+        }
+        output.write_all(buffer.as_bytes())
+    }
+
+    /// Add nodes for unmatched edge targets and close the document
+    pub fn finish<W: Write>(mut self, output: &mut W) -> std::io::Result<()> {
+        let mut buffer = String::new();
+        self.push_header(&mut buffer);
+
+        let mut missing: Vec<&String> = self.targets.difference(&self.nodes).collect();
+        missing.sort();
+        for id in missing {
+            buffer.push_str(&format!("    <node id=\"{}\"/>\n", escape_xml(id)));
+        }
+
+        buffer.push_str("  </graph>\n</graphml>\n");
+        output.write_all(buffer.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!(escape_xml("a<b & \"c\""), "a&lt;b &amp; &quot;c&quot;");
+    }
+
+    #[test]
+    fn test_graphml_nodes_and_edges() {
+        let languages = HashSet::from(["en".to_string(), "de".to_string()]);
+        let mut writer = GraphMlWriter::new(Some(&languages));
+        let entities = vec![
+            json!({"id": "Q64", "type": "item",
+                "labels": {"de": {"language": "de", "value": "Berlin"}},
+                "claims": {"P17": [{"mainsnak": {"datavalue": {"type": "wikibase-entityid", "value": {"id": "Q183"}}}}],
+                           "P1082": [{"mainsnak": {"datavalue": {"type": "quantity", "value": {"amount": "+1"}}}}]}}),
+            json!({"id": "Q183", "type": "item", "labels": {"en": {"language": "en", "value": "Germany"}}}),
+        ];
+
+        let mut output = Vec::new();
+        writer.write_entities(&mut output, &entities).unwrap();
+        writer.finish(&mut output).unwrap();
+        let xml = String::from_utf8(output).unwrap();
+
+        assert!(xml.starts_with("<?xml"));
+        assert!(xml.contains("<key id=\"label_de\""));
+        assert!(xml.contains("<key id=\"label_en\""));
+        assert!(xml.contains("<node id=\"Q64\">\n      <data key=\"label\">Berlin</data>"));
+        assert!(xml.contains(
+            "<edge source=\"Q64\" target=\"Q183\">\n      <data key=\"property\">P17</data>"
+        ));
+        assert!(!xml.contains("P1082"));
+        // Q183 was matched, so no placeholder node is added
+        assert!(!xml.contains("<node id=\"Q183\"/>"));
+        assert!(xml.ends_with("</graph>\n</graphml>\n"));
+    }
+
+    #[test]
+    fn test_graphml_adds_missing_targets() {
+        let mut writer = GraphMlWriter::new(None);
+        let mut output = Vec::new();
+        writer
+            .write_entities(
+                &mut output,
+                &[json!({"id": "Q42", "claims": {"P31": [{"mainsnak": {"datavalue": {"type": "wikibase-entityid", "value": {"id": "Q5"}}}}]}})],
+            )
+            .unwrap();
+        writer.finish(&mut output).unwrap();
+        let xml = String::from_utf8(output).unwrap();
+
+        assert!(xml.contains("<data key=\"label\">Q42</data>"));
+        assert!(xml.contains("<node id=\"Q5\"/>"));
+    }
+}
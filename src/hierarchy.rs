@@ -0,0 +1,439 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use crate::compression::{
+    create_input_reader_from, create_output_writer, decompressing_reader, determine_compression,
+    finish_output, INPUT_BUFFER_SIZE, OUTPUT_BUFFER_SIZE,
+};
+use crate::diff::EntitySink;
+use crate::filter::{ClaimFilter, EntityFilter};
+use crate::http;
+use crate::rdf::{OutputFormat, ProcessingOptions};
+use crate::sort::EntityKey;
+use crate::{run_filter, FilterError};
+
+/// Arguments for the `extract-hierarchy` subcommand
+#[derive(clap::Args, Debug)]
+pub struct HierarchyArgs {
+    /// Dump or extract to read (supports .bz2, .gz, .lz4)
+    #[arg()]
+    input: String,
+
+    /// Classes to extract the tree under (comma-separated, e.g., Q5,Q16521)
+    #[arg(long)]
+    root: String,
+
+    /// Property linking a class to its parent class
+    #[arg(long, default_value = "P279")]
+    property: String,
+
+    /// Input format: auto, rdf, turtle, json
+    #[arg(short = 'f', long, default_value = "auto")]
+    format: String,
+
+    /// Output format: edges (subclass and class, tab-separated), json (nested tree)
+    #[arg(short = 'o', long, default_value = "edges")]
+    output_format: String,
+
+    /// Output file (stdout if not provided). Extension determines compression (.gz, .lz4)
+    #[arg(long)]
+    output: Option<String>,
+
+    /// Show progress info on stderr
+    #[arg(short = 'p', long)]
+    progress: bool,
+}
+
+/// Subclass graph of one property, from each class to its direct subclasses
+#[derive(Debug, Default, Clone)]
+pub struct Hierarchy {
+    children: HashMap<String, Vec<String>>,
+}
+
+impl Hierarchy {
+    /// Read the edges written by `extract-hierarchy` (optionally compressed)
+    ///
+    /// Blank lines and lines starting with `#` are skipped.
+    pub fn load(path: &str) -> Result<Self, FilterError> {
+        let file = std::fs::File::open(path)?;
+        Self::read(BufReader::new(decompressing_reader(file, path)), path)
+    }
+
+    /// Read edges from `reader`, naming `path` in errors
+    pub fn read<R: BufRead>(reader: R, path: &str) -> Result<Self, FilterError> {
+        let mut hierarchy = Self::default();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (child, parent) = line.split_once('\t').ok_or_else(|| {
+                FilterError::Parse(format!(
+                    "{} line {}: expected subclass and class separated by a tab",
+                    path,
+                    number + 1
+                ))
+            })?;
+            hierarchy.add_edge(parent.trim(), child.trim());
+        }
+        Ok(hierarchy)
+    }
+
+    /// Read the `property` edges of a whole dump or extract (file or URL)
+    pub fn scan(
+        input: &str,
+        format: &str,
+        property: &str,
+        show_progress: bool,
+        http_retries: u32,
+    ) -> Result<Self, FilterError> {
+        let property = property.trim().to_string();
+        let mut edges = scan_edges(
+            input,
+            format,
+            std::slice::from_ref(&property),
+            show_progress,
+            http_retries,
+        )?;
+        Ok(edges.remove(&property).unwrap_or_default())
+    }
+
+    /// Add the edges from the `property` values of an entity to the entity
+    pub fn add(&mut self, entity: &Value, property: &str) {
+        let Some(id) = entity.get("id").and_then(Value::as_str) else {
+            return;
+        };
+        let claims = EntityFilter::default().extract_json_claims(entity);
+        self.add_claims(id, &claims, property);
+    }
+
+    fn add_claims(&mut self, id: &str, claims: &HashMap<String, HashSet<String>>, property: &str) {
+        for parent in claims.get(property).into_iter().flatten() {
+            self.add_edge(parent, id);
+        }
+    }
+
+    fn add_edge(&mut self, parent: &str, child: &str) {
+        if parent != child {
+            self.children
+                .entry(parent.to_string())
+                .or_default()
+                .push(child.to_string());
+        }
+    }
+
+    /// The roots and all their transitive subclasses
+    pub fn subclasses(&self, roots: &HashSet<String>) -> HashSet<String> {
+        let mut classes = roots.clone();
+        let mut queue: Vec<&str> = roots.iter().map(String::as_str).collect();
+        while let Some(class) = queue.pop() {
+            for child in self.children.get(class).into_iter().flatten() {
+                if classes.insert(child.clone()) {
+                    queue.push(child);
+                }
+            }
+        }
+        classes
+    }
+
+    /// Entities with one of the values, one step back along the edges
+    pub fn subjects(&self, values: &HashSet<String>) -> HashSet<String> {
+        values
+            .iter()
+            .flat_map(|value| self.children.get(value).into_iter().flatten())
+            .cloned()
+            .collect()
+    }
+
+    /// Direct subclasses of a class, by ID
+    fn children_of(&self, class: &str) -> Vec<&String> {
+        let mut children: Vec<&String> = self.children.get(class).into_iter().flatten().collect();
+        children.sort_by_cached_key(|id| EntityKey::new(id));
+        children.dedup();
+        children
+    }
+
+    /// Subclass edges reachable from the roots, breadth first
+    ///
+    /// Each class is expanded once, so cycles and classes with several parents
+    /// under the roots do not repeat its subtree.
+    pub fn edges<'a>(&'a self, roots: &'a [String]) -> Vec<(&'a str, &'a str)> {
+        let mut seen: HashSet<&str> = roots.iter().map(String::as_str).collect();
+        let mut queue: VecDeque<&str> = roots.iter().map(String::as_str).collect();
+        let mut edges = Vec::new();
+        while let Some(class) = queue.pop_front() {
+            for child in self.children_of(class) {
+                edges.push((child.as_str(), class));
+                if seen.insert(child) {
+                    queue.push_back(child);
+                }
+            }
+        }
+        edges
+    }
+
+    /// Nested tree under the roots
+    ///
+    /// A class below several parents is expanded at its first place in depth
+    /// first order and only named (`"repeated": true`) at the others.
+    pub fn tree(&self, roots: &[String]) -> Value {
+        let mut expanded = HashSet::new();
+        Value::Array(
+            roots
+                .iter()
+                .map(|root| self.subtree(root, &mut expanded))
+                .collect(),
+        )
+    }
+
+    fn subtree<'a>(&'a self, class: &'a str, expanded: &mut HashSet<&'a str>) -> Value {
+        if !expanded.insert(class) {
+            return json!({"id": class, "repeated": true});
+        }
+        let children: Vec<Value> = self
+            .children_of(class)
+            .into_iter()
+            .map(|child| self.subtree(child, expanded))
+            .collect();
+        if children.is_empty() {
+            json!({"id": class})
+        } else {
+            json!({"id": class, "children": children})
+        }
+    }
+}
+
+/// What the transitive values and property paths of claim filters are
+/// resolved against
+#[derive(Debug, Default)]
+pub struct ClaimIndex {
+    /// Subclass graph for transitive values (--hierarchy, --closure-property)
+    pub hierarchy: Option<Hierarchy>,
+    /// Edges of the properties that paths follow, by property
+    pub edges: HashMap<String, Hierarchy>,
+}
+
+/// Read the edges of several properties in one pass over a dump or extract
+///
+/// Only entities with a statement for one of the properties pass the
+/// filter, and only their claims are converted.
+pub fn scan_edges(
+    input: &str,
+    format: &str,
+    properties: &[String],
+    show_progress: bool,
+    http_retries: u32,
+) -> Result<HashMap<String, Hierarchy>, FilterError> {
+    for property in properties {
+        if !property.starts_with('P') || property[1..].parse::<u64>().is_err() {
+            return Err(FilterError::Parse(format!(
+                "{} is not a property ID such as P279",
+                property
+            )));
+        }
+    }
+    let filter = Arc::new(EntityFilter {
+        claim_filter: Some(ClaimFilter::Or(
+            properties
+                .iter()
+                .cloned()
+                .map(ClaimFilter::HasProperty)
+                .collect(),
+        )),
+        keep_attributes: Some(HashSet::from(["id".to_string(), "claims".to_string()])),
+        ..Default::default()
+    });
+    let options = ProcessingOptions {
+        show_progress,
+        batch_size: 0,
+        output_format: OutputFormat::Json,
+        ..Default::default()
+    };
+
+    let mut edges: HashMap<String, Hierarchy> = properties
+        .iter()
+        .map(|property| (property.clone(), Hierarchy::default()))
+        .collect();
+    let source = http::open_source(input, 0, http_retries)?;
+    let (reader, format) = create_input_reader_from(source, input, format, INPUT_BUFFER_SIZE);
+    let mut sink = EntitySink::new(|entity| {
+        let Some(id) = entity.get("id").and_then(Value::as_str) else {
+            return Ok(());
+        };
+        let claims = EntityFilter::default().extract_json_claims(&entity);
+        for (property, hierarchy) in edges.iter_mut() {
+            hierarchy.add_claims(id, &claims, property);
+        }
+        Ok(())
+    });
+    run_filter(reader, &format, &mut sink, &mut [], &filter, &options)?;
+    Ok(edges)
+}
+
+/// Run the `extract-hierarchy` subcommand
+pub fn run(args: HierarchyArgs) -> Result<(), FilterError> {
+    let roots: Vec<String> = args
+        .root
+        .split(',')
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect();
+    if roots.is_empty() {
+        return Err(FilterError::Parse("--root must not be empty".to_string()));
+    }
+    let json_output = match args.output_format.as_str() {
+        "edges" => false,
+        "json" => true,
+        other => {
+            return Err(FilterError::Parse(format!(
+                "extract-hierarchy writes edges or json, not {}",
+                other
+            )))
+        }
+    };
+    let hierarchy = Hierarchy::scan(
+        &args.input,
+        &args.format,
+        &args.property,
+        args.progress,
+        http::DEFAULT_RETRIES,
+    )?;
+
+    let compression = determine_compression("none", args.output.as_deref());
+    let mut output = BufWriter::with_capacity(
+        OUTPUT_BUFFER_SIZE,
+        create_output_writer(args.output.as_deref(), &compression)?,
+    );
+    if json_output {
+        serde_json::to_writer(&mut output, &hierarchy.tree(&roots))?;
+        output.write_all(b"\n")?;
+    } else {
+        let edges = hierarchy.edges(&roots);
+        write_edges(&mut output, &edges)?;
+        if args.progress {
+            let classes: HashSet<&str> = edges.iter().map(|(child, _)| *child).collect();
+            eprintln!(
+                "{} subclasses of {} ({} edges)",
+                classes.len(),
+                roots.join(", "),
+                edges.len()
+            );
+        }
+    }
+    finish_output(output)?;
+    Ok(())
+}
+
+fn write_edges<W: Write>(output: &mut W, edges: &[(&str, &str)]) -> io::Result<()> {
+    for (child, parent) in edges {
+        writeln!(output, "{}\t{}", child, parent)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn class(id: &str, parents: &[&str]) -> Value {
+        let claims: Vec<Value> = parents
+            .iter()
+            .map(|parent| {
+                json!({"mainsnak": {"datavalue": {
+                    "value": {"entity-type": "item", "id": parent},
+                    "type": "wikibase-entityid"
+                }}})
+            })
+            .collect();
+        json!({"id": id, "type": "item", "claims": {"P279": claims}})
+    }
+
+    fn hierarchy() -> Hierarchy {
+        let mut hierarchy = Hierarchy::default();
+        for entity in [
+            class("Q2", &["Q1"]),
+            class("Q3", &["Q1"]),
+            class("Q4", &["Q2", "Q3"]),
+            class("Q1", &["Q4"]),
+            class("Q9", &["Q8"]),
+        ] {
+            hierarchy.add(&entity, "P279");
+        }
+        hierarchy
+    }
+
+    #[test]
+    fn test_edges_under_root() {
+        let hierarchy = hierarchy();
+        let roots = vec!["Q1".to_string()];
+        assert_eq!(
+            hierarchy.edges(&roots),
+            [
+                ("Q2", "Q1"),
+                ("Q3", "Q1"),
+                ("Q4", "Q2"),
+                ("Q4", "Q3"),
+                ("Q1", "Q4")
+            ]
+        );
+        assert!(hierarchy.edges(&["Q9".to_string()]).is_empty());
+    }
+
+    #[test]
+    fn test_subclasses() {
+        let hierarchy = hierarchy();
+        let roots = HashSet::from(["Q2".to_string()]);
+        let mut classes: Vec<String> = hierarchy.subclasses(&roots).into_iter().collect();
+        classes.sort();
+        assert_eq!(classes, ["Q1", "Q2", "Q3", "Q4"]);
+        let roots = HashSet::from(["Q8".to_string(), "Q7".to_string()]);
+        assert_eq!(hierarchy.subclasses(&roots).len(), 3);
+    }
+
+    #[test]
+    fn test_load_edges() {
+        let dir = TempDir::new("hierarchy");
+        let path = dir.join("edges.p279");
+        std::fs::write(&path, "# buildings\nQ2\tQ1\n\nQ3\tQ2\n").unwrap();
+        let hierarchy = Hierarchy::load(&path.to_string_lossy()).unwrap();
+        let classes = hierarchy.subclasses(&HashSet::from(["Q1".to_string()]));
+        assert_eq!(classes.len(), 3);
+        std::fs::write(&path, "Q2 Q1\n").unwrap();
+        assert!(Hierarchy::load(&path.to_string_lossy()).is_err());
+    }
+
+    #[test]
+    fn test_scan_ntriples() {
+        let dir = TempDir::new("hierarchy");
+        let path = dir.join("dump.nt");
+        std::fs::write(
+            &path,
+            "<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P279> <http://www.wikidata.org/entity/Q1> .\n\
+             <http://www.wikidata.org/entity/Q3> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q2> .\n",
+        )
+        .unwrap();
+        let input = path.to_string_lossy();
+        let hierarchy = Hierarchy::scan(&input, "auto", "P279", false, 0).unwrap();
+        assert_eq!(hierarchy.edges(&["Q1".to_string()]), [("Q2", "Q1")]);
+        assert!(Hierarchy::scan(&input, "auto", "Q279", false, 0).is_err());
+    }
+
+    #[test]
+    fn test_nested_tree() {
+        let tree = hierarchy().tree(&["Q2".to_string()]);
+        assert_eq!(
+            tree,
+            json!([{"id": "Q2", "children": [{"id": "Q4", "children": [
+                {"id": "Q1", "children": [
+                    {"id": "Q2", "repeated": true},
+                    {"id": "Q3", "children": [{"id": "Q4", "repeated": true}]}
+                ]}
+            ]}]}])
+        );
+    }
+}
@@ -0,0 +1,390 @@
+use std::io::{self, Read, Seek, SeekFrom};
+#[cfg(feature = "http")]
+use std::time::Duration;
+
+use crate::s3;
+
+/// Default number of reconnects after transient network errors (--http-retries)
+pub const DEFAULT_RETRIES: u32 = 5;
+
+/// Whether `path` is a URL (http, https, s3, gs) rather than a file
+pub fn is_url(path: &str) -> bool {
+    let lower = path.to_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://") || s3::is_object_url(path)
+}
+
+/// Open an input file or URL at byte `offset` of its stored data
+///
+/// URLs are read with a Range request from `offset` and reconnect up to
+/// `retries` times after transient errors.
+pub fn open_source(path: &str, offset: u64, retries: u32) -> io::Result<Box<dyn Read + Send>> {
+    if s3::is_object_url(path) {
+        return s3::open(path, offset, retries);
+    }
+    if is_url(path) {
+        return open_url(path, offset, retries);
+    }
+    let mut file = std::fs::File::open(path)?;
+    if offset > 0 {
+        file.seek(SeekFrom::Start(offset))?;
+    }
+    Ok(Box::new(file))
+}
+
+/// Read an http:// or https:// URL from byte `offset`
+#[cfg(feature = "http")]
+pub fn open_url(url: &str, offset: u64, retries: u32) -> io::Result<Box<dyn Read + Send>> {
+    open_named(url, url, offset, retries)
+}
+
+/// Like `open_url`, naming the input `name` in messages (presigned URLs)
+#[cfg(feature = "http")]
+pub fn open_named(
+    url: &str,
+    name: &str,
+    offset: u64,
+    retries: u32,
+) -> io::Result<Box<dyn Read + Send>> {
+    let mut reader = HttpReader::new(url, offset, retries, Duration::from_secs(1));
+    reader.name = name.to_string();
+    reader.connect()?;
+    Ok(Box::new(reader))
+}
+
+/// Client with the connect and read timeouts of all requests
+#[cfg(feature = "http")]
+pub fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(30))
+        .timeout_read(Duration::from_secs(60))
+        .build()
+}
+
+#[cfg(not(feature = "http"))]
+pub fn open_url(url: &str, _offset: u64, _retries: u32) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{} needs HTTP support, build with --features http", url),
+    ))
+}
+
+/// Open a server-sent event stream, continuing after `last_event_id` if given
+#[cfg(feature = "http")]
+pub fn open_event_stream(
+    url: &str,
+    last_event_id: Option<&str>,
+    retries: u32,
+) -> io::Result<Box<dyn Read + Send>> {
+    let agent = agent();
+    retry(url, retries, Duration::from_secs(1), || {
+        let mut request = agent.get(url).set("Accept", "text/event-stream");
+        if let Some(id) = last_event_id {
+            request = request.set("Last-Event-ID", id);
+        }
+        let response = request.call().map_err(request_error)?;
+        Ok(Box::new(response.into_reader()) as Box<dyn Read + Send>)
+    })
+}
+
+/// Longest wait between two attempts
+#[cfg(feature = "http")]
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Wait before retry number `attempt`: `initial_delay`, doubled for each further one
+#[cfg(feature = "http")]
+fn backoff(initial_delay: Duration, attempt: u32) -> Duration {
+    initial_delay
+        .saturating_mul(1 << (attempt - 1).min(16))
+        .min(MAX_DELAY)
+}
+
+/// Run `attempt` until it succeeds, retrying transient errors up to `retries`
+/// times with exponential backoff from `initial_delay`
+#[cfg(feature = "http")]
+pub fn retry<T>(
+    what: &str,
+    retries: u32,
+    initial_delay: Duration,
+    mut attempt: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut failures = 0;
+    loop {
+        match attempt() {
+            Ok(value) => return Ok(value),
+            Err(e) if failures < retries && is_transient(&e) => {
+                failures += 1;
+                let delay = backoff(initial_delay, failures);
+                eprintln!(
+                    "Warning: {}: {}; retrying in {:.1}s ({} of {})",
+                    what,
+                    e,
+                    delay.as_secs_f64(),
+                    failures,
+                    retries
+                );
+                std::thread::sleep(delay);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// An I/O error for a failed request; 408, 429 and 5xx responses and
+/// connection problems are transient
+#[cfg(feature = "http")]
+pub fn request_error(error: ureq::Error) -> io::Error {
+    match error {
+        ureq::Error::Status(status, response) => {
+            // Not Interrupted: readers retry that kind immediately, without a limit
+            let kind = if matches!(status, 408 | 429 | 500 | 502 | 503 | 504) {
+                io::ErrorKind::ConnectionAborted
+            } else {
+                io::ErrorKind::Other
+            };
+            io::Error::new(kind, format!("HTTP {} {}", status, response.status_text()))
+        }
+        ureq::Error::Transport(transport) => {
+            io::Error::new(io::ErrorKind::ConnectionAborted, transport)
+        }
+    }
+}
+
+/// Response body of a URL that reconnects with a Range request from the last
+/// byte read after a dropped connection or a transient server error
+#[cfg(feature = "http")]
+pub struct HttpReader {
+    url: String,
+    /// The input as given, for messages
+    name: String,
+    agent: ureq::Agent,
+    body: Option<Box<dyn Read + Send + Sync>>,
+    /// Stored bytes read so far, including the starting offset
+    offset: u64,
+    /// Total size, if the server sent it
+    length: Option<u64>,
+    /// Reconnects in a row without reading any data before giving up
+    retries: u32,
+    /// Wait before the first retry; doubled for each further one
+    initial_delay: Duration,
+}
+
+#[cfg(feature = "http")]
+impl HttpReader {
+    fn new(url: &str, offset: u64, retries: u32, initial_delay: Duration) -> Self {
+        Self {
+            url: url.to_string(),
+            name: url.to_string(),
+            agent: agent(),
+            body: None,
+            offset,
+            length: None,
+            retries,
+            initial_delay,
+        }
+    }
+
+    /// Request the body from the current offset, retrying with exponential backoff
+    fn connect(&mut self) -> io::Result<()> {
+        let what = format!("{} at byte {}", self.name, self.offset);
+        let (retries, initial_delay) = (self.retries, self.initial_delay);
+        retry(&what, retries, initial_delay, || self.request())
+    }
+
+    fn request(&mut self) -> io::Result<()> {
+        let mut request = self.agent.get(&self.url);
+        if self.offset > 0 {
+            request = request.set("Range", &format!("bytes={}-", self.offset));
+        }
+        let response = match request.call() {
+            Ok(response) => response,
+            // Nothing left after the offset
+            Err(ureq::Error::Status(416, _)) if self.offset > 0 => {
+                self.body = Some(Box::new(io::empty()));
+                return Ok(());
+            }
+            Err(e) => return Err(request_error(e)),
+        };
+        // Bytes before the offset, if the server ignored the Range header
+        let mut skip = if response.status() == 206 {
+            0
+        } else {
+            self.offset
+        };
+        self.length = match response.status() {
+            206 => response
+                .header("Content-Range")
+                .and_then(|range| range.rsplit('/').next())
+                .and_then(|total| total.parse().ok()),
+            _ => response
+                .header("Content-Length")
+                .and_then(|length| length.parse().ok()),
+        };
+        let mut reader = response.into_reader();
+        if skip > 0 {
+            eprintln!(
+                "Warning: {} does not support Range requests, skipping {} bytes",
+                self.name, skip
+            );
+            skip -= io::copy(&mut (&mut reader).take(skip), &mut io::sink())?;
+            if skip > 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed before the resume offset",
+                ));
+            }
+        }
+        self.body = Some(reader);
+        Ok(())
+    }
+}
+
+/// Dropped connections, timeouts and 5xx/429 responses (ConnectionAborted)
+/// are worth retrying
+#[cfg(feature = "http")]
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::WouldBlock
+    )
+}
+
+#[cfg(feature = "http")]
+impl Read for HttpReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut attempt = 0;
+        loop {
+            let result = match self.body.as_mut() {
+                Some(body) => body.read(buf),
+                None => Ok(0),
+            };
+            let error = match result {
+                Ok(0) if self.length.is_some_and(|length| self.offset < length) => {
+                    io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed early")
+                }
+                Ok(read) => {
+                    self.offset += read as u64;
+                    return Ok(read);
+                }
+                Err(e) => e,
+            };
+            if attempt >= self.retries || !is_transient(&error) {
+                return Err(error);
+            }
+            attempt += 1;
+            eprintln!(
+                "Warning: {} at byte {}: {}; reconnecting ({} of {})",
+                self.name, self.offset, error, attempt, self.retries
+            );
+            self.body = None;
+            std::thread::sleep(backoff(self.initial_delay, attempt));
+            self.connect()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url(
+            "https://dumps.wikimedia.org/wikidatawiki/entities/latest-all.json.gz"
+        ));
+        assert!(is_url("HTTP://example.org/dump.nt"));
+        assert!(!is_url("dumps/latest-all.json.gz"));
+    }
+
+    #[test]
+    fn test_open_source_seeks_files() {
+        let dir = TempDir::new("source");
+        let path = dir.join("source");
+        std::fs::write(&path, "0123456789").unwrap();
+        let mut data = String::new();
+        open_source(&path.to_string_lossy(), 4, 0)
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "456789");
+    }
+
+    /// Serve `body` with Range support; of the first `dropped` connections the
+    /// first is closed halfway and the others before any data
+    #[cfg(feature = "http")]
+    fn serve(body: &'static [u8], dropped: usize) -> String {
+        use std::io::{BufRead, BufReader, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for (i, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                let mut start = 0;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(range) = line.strip_prefix("Range: bytes=") {
+                        start = range.trim_end_matches('-').parse().unwrap();
+                    }
+                }
+                let rest = &body[start..];
+                let header = if start > 0 {
+                    format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                        rest.len(),
+                        start,
+                        body.len() - 1,
+                        body.len()
+                    )
+                } else {
+                    format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", rest.len())
+                };
+                stream.write_all(header.as_bytes()).unwrap();
+                let sent = match i {
+                    0 if dropped > 0 => rest.len() / 2,
+                    i if i < dropped => 0,
+                    _ => rest.len(),
+                };
+                stream.write_all(&rest[..sent]).unwrap();
+            }
+        });
+        format!("http://{}/dump.nt", address)
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_reconnects_with_range_requests() {
+        let body: &'static [u8] = b"<a> <b> <c> .\n<d> <e> <f> .\n<g> <h> <i> .\n";
+        let url = serve(body, 2);
+        let mut reader = HttpReader::new(&url, 0, 3, Duration::from_millis(1));
+        reader.connect().unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, body);
+
+        // Resuming at an offset
+        let url = serve(body, 0);
+        let mut reader = HttpReader::new(&url, 14, 0, Duration::from_millis(1));
+        reader.connect().unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, &body[14..]);
+
+        // Giving up once the retries are used
+        let url = serve(body, 5);
+        let mut reader = HttpReader::new(&url, 0, 1, Duration::from_millis(1));
+        reader.connect().unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+    }
+}
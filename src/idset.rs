@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+
+/// Most values a chunk keeps as a sorted array before switching to bits
+const ARRAY_LIMIT: usize = 4096;
+
+/// Values of one chunk of 65536 numbers: sorted while sparse, a bit per
+/// number once dense (8 KiB)
+#[derive(Debug, Clone, PartialEq)]
+enum Container {
+    Array(Vec<u16>),
+    Bits(Box<[u64; 1024]>),
+}
+
+impl Container {
+    fn contains(&self, low: u16) -> bool {
+        match self {
+            Container::Array(values) => values.binary_search(&low).is_ok(),
+            Container::Bits(bits) => bits[low as usize / 64] & (1 << (low % 64)) != 0,
+        }
+    }
+
+    fn insert(&mut self, low: u16) -> bool {
+        match self {
+            Container::Array(values) => {
+                let Err(position) = values.binary_search(&low) else {
+                    return false;
+                };
+                values.insert(position, low);
+                if values.len() > ARRAY_LIMIT {
+                    let mut bits = Box::new([0u64; 1024]);
+                    for value in values.iter() {
+                        bits[*value as usize / 64] |= 1 << (value % 64);
+                    }
+                    *self = Container::Bits(bits);
+                }
+                true
+            }
+            Container::Bits(bits) => {
+                let word = &mut bits[low as usize / 64];
+                let bit = 1 << (low % 64);
+                let added = *word & bit == 0;
+                *word |= bit;
+                added
+            }
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u16> + '_> {
+        match self {
+            Container::Array(values) => Box::new(values.iter().copied()),
+            Container::Bits(bits) => Box::new(
+                (0..=u16::MAX).filter(|low| bits[*low as usize / 64] & (1 << (low % 64)) != 0),
+            ),
+        }
+    }
+}
+
+/// Set of 32-bit numbers in roaring bitmap layout: chunks by the upper 16
+/// bits, each holding the lower 16 bits of its numbers
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bitmap {
+    chunks: Vec<(u16, Container)>,
+}
+
+impl Bitmap {
+    pub fn contains(&self, number: u32) -> bool {
+        let (high, low) = ((number >> 16) as u16, number as u16);
+        match self.chunks.binary_search_by_key(&high, |(key, _)| *key) {
+            Ok(index) => self.chunks[index].1.contains(low),
+            Err(_) => false,
+        }
+    }
+
+    /// Add a number, returning whether it was new
+    pub fn insert(&mut self, number: u32) -> bool {
+        let (high, low) = ((number >> 16) as u16, number as u16);
+        let index = match self.chunks.binary_search_by_key(&high, |(key, _)| *key) {
+            Ok(index) => index,
+            Err(index) => {
+                self.chunks
+                    .insert(index, (high, Container::Array(Vec::new())));
+                index
+            }
+        };
+        self.chunks[index].1.insert(low)
+    }
+
+    /// Numbers in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.chunks.iter().flat_map(|(high, container)| {
+            container
+                .iter()
+                .map(move |low| (*high as u32) << 16 | low as u32)
+        })
+    }
+}
+
+/// Entity type letters whose IDs are kept as numbers
+const LETTERS: [char; 3] = ['Q', 'P', 'L'];
+
+/// Set of entity IDs for --subject and --subject-file
+///
+/// Item, property and lexeme IDs are kept as numbers in a bitmap per letter,
+/// a few bytes per ID for the millions of IDs of a SPARQL result instead of
+/// a string each. Other IDs (forms, senses, IDs with leading zeros) are kept
+/// as strings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IdSet {
+    numbers: [Bitmap; 3],
+    other: HashSet<String>,
+    len: usize,
+}
+
+/// Bitmap index and number of an ID such as Q42, if it has that form
+fn split_id(id: &str) -> Option<(usize, u32)> {
+    let mut chars = id.chars();
+    let letter = LETTERS.iter().position(|l| Some(*l) == chars.next())?;
+    let digits = chars.as_str();
+    if digits.starts_with('0') || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((letter, digits.parse().ok()?))
+}
+
+impl IdSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        match split_id(id) {
+            Some((letter, number)) => self.numbers[letter].contains(number),
+            None => self.other.contains(id),
+        }
+    }
+
+    /// Add an ID, returning whether it was new
+    pub fn insert(&mut self, id: &str) -> bool {
+        let added = match split_id(id) {
+            Some((letter, number)) => self.numbers[letter].insert(number),
+            None => self.other.insert(id.to_string()),
+        };
+        self.len += added as usize;
+        added
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// All IDs, the numeric ones first by letter and number
+    pub fn iter(&self) -> impl Iterator<Item = String> + '_ {
+        LETTERS
+            .iter()
+            .zip(&self.numbers)
+            .flat_map(|(letter, bitmap)| bitmap.iter().map(move |n| format!("{}{}", letter, n)))
+            .chain(self.other.iter().cloned())
+    }
+}
+
+impl Extend<String> for IdSet {
+    fn extend<I: IntoIterator<Item = String>>(&mut self, ids: I) {
+        for id in ids {
+            self.insert(&id);
+        }
+    }
+}
+
+impl FromIterator<String> for IdSet {
+    fn from_iter<I: IntoIterator<Item = String>>(ids: I) -> Self {
+        let mut set = Self::new();
+        set.extend(ids);
+        set
+    }
+}
+
+impl From<HashSet<String>> for IdSet {
+    fn from(ids: HashSet<String>) -> Self {
+        ids.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitmap_array_to_bits() {
+        let mut bitmap = Bitmap::default();
+        for number in (0..20_000).map(|n| n * 3) {
+            assert!(bitmap.insert(number));
+        }
+        assert!(!bitmap.insert(3));
+        assert!(bitmap.insert(u32::MAX));
+        assert!(matches!(bitmap.chunks[0].1, Container::Bits(_)));
+        assert!(matches!(bitmap.chunks[1].1, Container::Array(_)));
+        assert!(bitmap.contains(59_997) && bitmap.contains(u32::MAX));
+        assert!(!bitmap.contains(59_998) && !bitmap.contains(70_000));
+        let numbers: Vec<u32> = bitmap.iter().collect();
+        assert_eq!(numbers.len(), 20_001);
+        assert!(numbers.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_id_set() {
+        let ids: IdSet = ["Q42", "P31", "L7", "L7-F1", "Q042", "Q42", "Q99999999999"]
+            .map(String::from)
+            .into_iter()
+            .collect();
+        assert_eq!(ids.len(), 6);
+        for id in ["Q42", "P31", "L7", "L7-F1", "Q042", "Q99999999999"] {
+            assert!(ids.contains(id), "{}", id);
+        }
+        for id in ["Q31", "P42", "Q", "L7-F2", "Q4x"] {
+            assert!(!ids.contains(id), "{}", id);
+        }
+        let mut listed: Vec<String> = ids.iter().collect();
+        listed.sort();
+        assert_eq!(
+            listed,
+            ["L7", "L7-F1", "P31", "Q042", "Q42", "Q99999999999"]
+        );
+    }
+}
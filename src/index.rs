@@ -0,0 +1,486 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::atomic::PendingFile;
+use crate::bgzf::{self, BgzfReader, BlockIndex};
+use crate::checkpoint;
+use crate::compression::{detect_format_from_path, input_compression, INPUT_BUFFER_SIZE};
+use crate::json::dump_array_elements;
+use crate::rdf::{ENTITY_DATA_PREFIX, ENTITY_PREFIX};
+use crate::{http, FilterError};
+
+/// Arguments for the `index` subcommand
+#[derive(clap::Args, Debug)]
+pub struct IndexArgs {
+    /// Uncompressed or BGZF-compressed dump (file or URL); plain gzip, bzip2
+    /// and LZ4 dumps must be recompressed with bgzip first
+    #[arg()]
+    input: String,
+
+    /// Index file to write (INPUT.idx if not provided)
+    #[arg(long)]
+    output: Option<String>,
+
+    /// Input format: auto, rdf, json
+    #[arg(short = 'f', long, default_value = "auto")]
+    format: String,
+
+    /// Reconnects with a Range request after transient network errors
+    #[arg(long, default_value_t = http::DEFAULT_RETRIES)]
+    http_retries: u32,
+}
+
+/// Start of every index file, followed by the format version
+pub const MAGIC: &[u8; 6] = b"WWIDX\x01";
+
+/// Size of the header: magic, format, compression, input size and entry count
+pub const HEADER_SIZE: u64 = 24;
+
+/// Size of one entry: ID letter, ID number, offset and length
+pub const ENTRY_SIZE: u64 = 17;
+
+/// Where an entity is in the dump
+///
+/// For BGZF input the offset is a virtual offset as used by htslib: the stored
+/// offset of the block shifted left by 16 bits, plus the position in the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Entry {
+    /// ID letter (Q, P, L) and number, the sort key of the entries
+    pub key: (u8, u32),
+    pub offset: u64,
+    /// Decompressed size of the entity's lines, without the final newline
+    pub length: u32,
+}
+
+impl Entry {
+    pub fn to_bytes(self) -> [u8; ENTRY_SIZE as usize] {
+        let mut bytes = [0u8; ENTRY_SIZE as usize];
+        bytes[0] = self.key.0;
+        bytes[1..5].copy_from_slice(&self.key.1.to_le_bytes());
+        bytes[5..13].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[13..17].copy_from_slice(&self.length.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; ENTRY_SIZE as usize]) -> Self {
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        Self {
+            key: (bytes[0], u32_at(1)),
+            offset: u64::from_le_bytes(bytes[5..13].try_into().unwrap()),
+            length: u32_at(13),
+        }
+    }
+}
+
+/// An index file opened for lookups
+pub struct Index {
+    file: File,
+    /// Whether the dump is JSON, or N-Triples
+    pub json: bool,
+    /// Whether offsets are BGZF virtual offsets
+    pub bgzf: bool,
+    /// Stored size of the dump when it was indexed
+    pub input_size: u64,
+    count: u64,
+}
+
+impl Index {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut header = [0u8; HEADER_SIZE as usize];
+        file.read_exact(&mut header)
+            .ok()
+            .filter(|()| header[..6] == *MAGIC && matches!(header[6], b'j' | b'n'))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is not an index written by this version", path),
+                )
+            })?;
+        let u64_at = |i: usize| u64::from_le_bytes(header[i..i + 8].try_into().unwrap());
+        Ok(Self {
+            file,
+            json: header[6] == b'j',
+            bgzf: header[7] == 1,
+            input_size: u64_at(8),
+            count: u64_at(16),
+        })
+    }
+
+    /// Location of an entity, by binary search over the sorted entries
+    pub fn lookup(&mut self, id: &str) -> io::Result<Option<Entry>> {
+        let Some(key) = id_key(id) else {
+            return Ok(None);
+        };
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let mut bytes = [0u8; ENTRY_SIZE as usize];
+            self.file
+                .seek(SeekFrom::Start(HEADER_SIZE + middle * ENTRY_SIZE))?;
+            self.file.read_exact(&mut bytes)?;
+            let entry = Entry::from_bytes(&bytes);
+            match entry.key.cmp(&key) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Ok(Some(entry)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read the lines of an entity from the dump file or URL
+    pub fn read_entity(&self, input: &str, entry: Entry, retries: u32) -> io::Result<Vec<u8>> {
+        let mut reader: Box<dyn Read> = if self.bgzf {
+            let (stored, skip) = (entry.offset >> 16, entry.offset & 0xffff);
+            let source = http::open_source(input, stored, retries)?;
+            let mut reader = BgzfReader::new(source, stored, 0, BlockIndex::default());
+            io::copy(&mut (&mut reader).take(skip), &mut io::sink())?;
+            Box::new(reader)
+        } else {
+            http::open_source(input, entry.offset, retries)?
+        };
+        let mut data = Vec::with_capacity(entry.length as usize);
+        reader
+            .by_ref()
+            .take(entry.length as u64)
+            .read_to_end(&mut data)?;
+        if data.len() < entry.length as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} ends before the indexed entity", input),
+            ));
+        }
+        Ok(data)
+    }
+}
+
+/// Sort key of an entity ID such as `Q42`, if it has one
+pub fn id_key(id: &str) -> Option<(u8, u32)> {
+    let (&letter, number) = id.as_bytes().split_first()?;
+    if !letter.is_ascii_uppercase() || !number.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some((letter, std::str::from_utf8(number).ok()?.parse().ok()?))
+}
+
+/// Subject entity of an N-Triples line, for items, properties and lexemes
+pub fn subject_id(line: &str) -> Option<&str> {
+    let rest = line
+        .strip_prefix(ENTITY_PREFIX)
+        .or_else(|| line.strip_prefix(ENTITY_DATA_PREFIX))?;
+    let end = rest.find('>')?;
+    id_key(&rest[..end]).map(|_| &rest[..end])
+}
+
+#[derive(Deserialize)]
+struct EntityId<'a> {
+    #[serde(borrow)]
+    id: Cow<'a, str>,
+}
+
+/// Entries found in a dump
+#[derive(Default, Debug)]
+struct Scan {
+    entries: Vec<Entry>,
+    /// Entities whose IDs have no number that fits the index
+    skipped: u64,
+    /// Later occurrences of IDs seen before, which are not indexed
+    duplicates: u64,
+}
+
+impl Scan {
+    /// Record an entity spanning `length` decompressed bytes from `start`
+    fn add(&mut self, id: &str, start: u64, length: u64, blocks: Option<&BlockIndex>) {
+        let (Some(key), Ok(length)) = (id_key(id), u32::try_from(length)) else {
+            self.skipped += 1;
+            return;
+        };
+        let offset = match blocks {
+            // Entity starts come in increasing order, as `locate` expects
+            Some(blocks) => match blocks.locate(start) {
+                Some((stored, skip)) => (stored << 16) | skip,
+                None => {
+                    self.skipped += 1;
+                    return;
+                }
+            },
+            None => start,
+        };
+        self.entries.push(Entry {
+            key,
+            offset,
+            length,
+        });
+    }
+
+    /// Sort the entries by ID, keeping the first occurrence of each
+    fn finish(&mut self) {
+        // The sort is stable, so the first occurrence stays in front
+        self.entries.sort_by_key(|entry| entry.key);
+        let before = self.entries.len();
+        self.entries.dedup_by_key(|entry| entry.key);
+        self.duplicates = (before - self.entries.len()) as u64;
+    }
+}
+
+/// Find the entities of a dump and where they are
+///
+/// N-Triples entities are runs of lines with the same subject entity, lines
+/// without one belong to the entity before them; JSON entities are lines.
+fn scan(
+    mut reader: impl BufRead,
+    json: bool,
+    blocks: Option<&BlockIndex>,
+) -> Result<Scan, FilterError> {
+    let mut result = Scan::default();
+    // N-Triples entity being read: ID, start and end offset
+    let mut current: Option<(String, u64, u64)> = None;
+    let mut line = Vec::new();
+    let mut offset = 0u64;
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)? as u64;
+        if read == 0 {
+            break;
+        }
+        let content = line.strip_suffix(b"\n").unwrap_or(&line);
+        let text = String::from_utf8_lossy(content);
+        if json {
+            for entry in dump_array_elements(&text) {
+                let EntityId { id } = serde_json::from_str(entry)?;
+                // Elements are slices of the line; the trailing comma is left out
+                let start = entry.as_ptr() as u64 - text.as_ptr() as u64;
+                result.add(&id, offset + start, entry.len() as u64, blocks);
+            }
+        } else {
+            match subject_id(&text) {
+                Some(id) if current.as_ref().is_some_and(|(current, ..)| current == id) => {}
+                Some(id) => {
+                    if let Some((id, start, end)) = current.take() {
+                        result.add(&id, start, end - start, blocks);
+                    }
+                    current = Some((id.to_string(), offset, offset));
+                }
+                None => {}
+            }
+            if let Some((_, _, end)) = &mut current {
+                *end = offset + content.len() as u64;
+            }
+        }
+        offset += read;
+    }
+    if let Some((id, start, end)) = current {
+        result.add(&id, start, end - start, blocks);
+    }
+    result.finish();
+    Ok(result)
+}
+
+/// Write an index file for `scan`
+fn write_index<W: Write>(
+    output: &mut W,
+    scan: &Scan,
+    json: bool,
+    bgzf: bool,
+    input_size: u64,
+) -> io::Result<()> {
+    output.write_all(MAGIC)?;
+    output.write_all(&[if json { b'j' } else { b'n' }, bgzf as u8])?;
+    output.write_all(&input_size.to_le_bytes())?;
+    output.write_all(&(scan.entries.len() as u64).to_le_bytes())?;
+    for entry in &scan.entries {
+        output.write_all(&entry.to_bytes())?;
+    }
+    output.flush()
+}
+
+/// Run the `index` subcommand
+///
+/// The entries are held in memory until the input is read, about 24 bytes per
+/// entity (2.5 GiB for a full dump of 110 million entities).
+pub fn run(args: IndexArgs) -> Result<(), FilterError> {
+    let bgzf = bgzf::is_bgzf(&args.input, args.http_retries)?;
+    // Plain gzip, bzip2 and LZ4 streams can only be decompressed from their
+    // start, so an offset into them would not spare the scan
+    let compression = input_compression(&args.input);
+    if !bgzf && compression != "none" {
+        let decompress = match compression {
+            "gzip" => "zcat",
+            "bzip2" => "bzcat",
+            _ => "lz4cat",
+        };
+        return Err(FilterError::Parse(format!(
+            "index needs uncompressed or BGZF input, not {}; recompress it with \
+             `{} {} | bgzip > DUMP.gz`",
+            compression, decompress, args.input
+        )));
+    }
+    let format = match args.format.as_str() {
+        "auto" => detect_format_from_path(&args.input),
+        other => other.to_string(),
+    };
+    let json = match format.as_str() {
+        "json" | "ndjson" => true,
+        "rdf" | "ntriples" | "nt" => false,
+        other => {
+            return Err(FilterError::Parse(format!(
+                "index supports N-Triples and JSON dumps, not {}",
+                other
+            )))
+        }
+    };
+
+    let blocks = bgzf.then(BlockIndex::default);
+    let bytes_read = Arc::new(AtomicU64::new(0));
+    let reader = checkpoint::open_input(
+        &args.input,
+        None,
+        blocks.as_ref(),
+        bytes_read.clone(),
+        INPUT_BUFFER_SIZE,
+        args.http_retries,
+    )?;
+    let scan = scan(reader, json, blocks.as_ref())?;
+
+    let path = args.output.unwrap_or_else(|| format!("{}.idx", args.input));
+    let mut pending = PendingFile::new(&path, false, http::DEFAULT_RETRIES)?;
+    let mut output = BufWriter::new(pending.create()?);
+    write_index(
+        &mut output,
+        &scan,
+        json,
+        bgzf,
+        bytes_read.load(Ordering::Relaxed),
+    )?;
+    drop(output.into_inner().map_err(|e| e.into_error())?);
+    pending.commit()?;
+
+    if scan.skipped > 0 {
+        eprintln!(
+            "Warning: {} entities with IDs that cannot be indexed were skipped",
+            scan.skipped
+        );
+    }
+    if scan.duplicates > 0 {
+        eprintln!(
+            "Warning: {} repeated entities were not indexed, lookups find the first",
+            scan.duplicates
+        );
+    }
+    eprintln!("Indexed {} entities in {}", scan.entries.len(), path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_id_key() {
+        assert_eq!(id_key("Q42"), Some((b'Q', 42)));
+        assert_eq!(id_key("P31"), Some((b'P', 31)));
+        assert_eq!(id_key("L1-F1"), None);
+        assert_eq!(id_key("Q99999999999"), None);
+        assert_eq!(id_key("q1"), None);
+    }
+
+    #[test]
+    fn test_scan_ntriples() {
+        let data = "# header\n\
+            <http://www.wikidata.org/entity/Q2> <http://schema.org/name> \"b\"@en .\n\
+            <http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .\n\
+            <http://www.wikidata.org/entity/P31> <http://schema.org/name> \"p\"@en .\n\
+            <http://www.wikidata.org/entity/Q1> <http://schema.org/name> \"a\"@en .\n";
+        let scan = scan(io::Cursor::new(data), false, None).unwrap();
+        let spans: Vec<(u8, u32, &str)> = scan
+            .entries
+            .iter()
+            .map(|entry| {
+                let start = entry.offset as usize;
+                let (letter, number) = entry.key;
+                (letter, number, &data[start..start + entry.length as usize])
+            })
+            .collect();
+        let q2 = &data[9..data.find("<http://www.wikidata.org/entity/P31> <").unwrap() - 1];
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[0], (b'P', 31, data.lines().nth(3).unwrap()));
+        assert_eq!(spans[1], (b'Q', 1, data.lines().nth(4).unwrap()));
+        assert_eq!(spans[2], (b'Q', 2, q2));
+    }
+
+    #[test]
+    fn test_scan_json() {
+        let data = "[\n{\"id\":\"Q3\"},\n{\"id\":\"Q1\"},\n{\"id\":\"Q3\",\"x\":1}\n]\n";
+        let scan = scan(io::Cursor::new(data), true, None).unwrap();
+        assert_eq!(scan.duplicates, 1);
+        let spans: Vec<&str> = scan
+            .entries
+            .iter()
+            .map(|entry| &data[entry.offset as usize..][..entry.length as usize])
+            .collect();
+        assert_eq!(spans, ["{\"id\":\"Q1\"}", "{\"id\":\"Q3\"}"]);
+
+        let mut output = Vec::new();
+        write_index(&mut output, &scan, true, false, data.len() as u64).unwrap();
+        assert_eq!(output.len() as u64, HEADER_SIZE + 2 * ENTRY_SIZE);
+        assert_eq!(&output[..8], b"WWIDX\x01j\x00");
+    }
+
+    #[test]
+    fn test_lookup() {
+        let dir = TempDir::new("index");
+        let (dump, index) = (dir.join("dump.json"), dir.join("dump.json.idx"));
+        let data: String = (1..=50)
+            .map(|n| format!("{{\"id\":\"Q{}\",\"n\":{}}}\n", n * 2, n))
+            .collect();
+        std::fs::write(&dump, &data).unwrap();
+        let scan = scan(io::Cursor::new(&data), true, None).unwrap();
+        let mut output = Vec::new();
+        write_index(&mut output, &scan, true, false, data.len() as u64).unwrap();
+        std::fs::write(&index, output).unwrap();
+
+        let mut index = Index::open(&index.to_string_lossy()).unwrap();
+        assert!(index.json && !index.bgzf);
+        assert_eq!(index.input_size, data.len() as u64);
+        let entry = index.lookup("Q84").unwrap().unwrap();
+        let entity = index
+            .read_entity(&dump.to_string_lossy(), entry, 0)
+            .unwrap();
+        assert_eq!(entity, b"{\"id\":\"Q84\",\"n\":42}");
+        assert_eq!(index.lookup("Q83").unwrap(), None);
+        assert_eq!(index.lookup("Q2").unwrap().unwrap().offset, 0);
+        assert_eq!(index.lookup("Q100").unwrap().unwrap().key, (b'Q', 100));
+    }
+
+    #[test]
+    fn test_plain_gzip_refused() {
+        let dir = TempDir::new("index-gzip");
+        let dump = dir.file("dump.json.gz");
+        let mut encoder =
+            flate2::write::GzEncoder::new(File::create(&dump).unwrap(), Default::default());
+        encoder.write_all(b"{\"id\":\"Q1\"}\n").unwrap();
+        encoder.finish().unwrap();
+
+        let args = IndexArgs {
+            input: dump.clone(),
+            output: None,
+            format: "auto".to_string(),
+            http_retries: 0,
+        };
+        let error = run(args).unwrap_err().to_string();
+        assert!(error.contains("not gzip"), "{}", error);
+        assert!(
+            error.contains(&format!("zcat {} | bgzip", dump)),
+            "{}",
+            error
+        );
+        assert!(!dir.join("dump.json.gz.idx").exists());
+    }
+}
@@ -0,0 +1,178 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::builder::{ListBuilder, StringBuilder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_ipc::writer::StreamEncoder;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+
+use crate::tabular::TableSpec;
+
+/// Build the Arrow schema for the selected columns
+///
+/// Single-valued columns are nullable strings, aliases and claims are
+/// lists of strings.
+pub fn table_schema(spec: &TableSpec) -> Schema {
+    let fields: Vec<Field> = spec
+        .names
+        .iter()
+        .zip(&spec.columns)
+        .map(|(name, column)| {
+            if column.is_multi_valued() {
+                Field::new(
+                    name,
+                    DataType::List(Arc::new(Field::new_list_field(DataType::Utf8, true))),
+                    true,
+                )
+            } else {
+                Field::new(name, DataType::Utf8, true)
+            }
+        })
+        .collect();
+    Schema::new(fields)
+}
+
+fn to_io_error(e: ArrowError) -> std::io::Error {
+    std::io::Error::other(e)
+}
+
+/// Writes entity rows as an Arrow IPC stream
+///
+/// The schema message is written with the first batch (or on finish for an
+/// empty stream), the end-of-stream marker on finish.
+pub struct ArrowStreamWriter {
+    schema: SchemaRef,
+    multi_valued: Vec<bool>,
+    encoder: StreamEncoder,
+}
+
+impl ArrowStreamWriter {
+    pub fn new(spec: &TableSpec) -> std::io::Result<Self> {
+        let schema = table_schema(spec);
+        let encoder = StreamEncoder::try_new(&schema).map_err(to_io_error)?;
+        Ok(Self {
+            schema: Arc::new(schema),
+            multi_valued: spec.columns.iter().map(|c| c.is_multi_valued()).collect(),
+            encoder,
+        })
+    }
+
+    /// Build a record batch from rows of cell values (see `TableSpec::json_cells`)
+    pub fn record_batch(&self, rows: &[Vec<Vec<String>>]) -> std::io::Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = self
+            .multi_valued
+            .iter()
+            .enumerate()
+            .map(|(i, &multi)| {
+                if multi {
+                    let mut builder = ListBuilder::new(StringBuilder::new());
+                    for row in rows {
+                        for value in &row[i] {
+                            builder.values().append_value(value);
+                        }
+                        builder.append(true);
+                    }
+                    Arc::new(builder.finish()) as ArrayRef
+                } else {
+                    let mut builder = StringBuilder::new();
+                    for row in rows {
+                        builder.append_option(row[i].first());
+                    }
+                    Arc::new(builder.finish()) as ArrayRef
+                }
+            })
+            .collect();
+        RecordBatch::try_new(self.schema.clone(), columns).map_err(to_io_error)
+    }
+
+    /// Encode and write one record batch
+    pub fn write_rows<W: Write>(
+        &mut self,
+        output: &mut W,
+        rows: &[Vec<Vec<String>>],
+    ) -> std::io::Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let batch = self.record_batch(rows)?;
+        for buffer in self.encoder.encode(&batch).map_err(to_io_error)? {
+            output.write_all(buffer.as_slice())?;
+        }
+        Ok(())
+    }
+
+    /// Write the end-of-stream marker
+    pub fn finish<W: Write>(self, output: &mut W) -> std::io::Result<()> {
+        for buffer in self.encoder.finish().map_err(to_io_error)? {
+            output.write_all(buffer.as_slice())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{Array, ListArray, StringArray};
+    use arrow_ipc::reader::StreamReader;
+
+    #[test]
+    fn test_arrow_stream_roundtrip() {
+        let spec = TableSpec::parse("id,labels.en,P31", "|").unwrap();
+        let mut writer = ArrowStreamWriter::new(&spec).unwrap();
+        let mut output = Vec::new();
+
+        writer
+            .write_rows(
+                &mut output,
+                &[
+                    vec![
+                        vec!["Q64".to_string()],
+                        vec!["Berlin".to_string()],
+                        vec!["Q515".to_string(), "Q1637706".to_string()],
+                    ],
+                    vec![vec!["Q1".to_string()], vec![], vec![]],
+                ],
+            )
+            .unwrap();
+        writer.finish(&mut output).unwrap();
+
+        let reader = StreamReader::try_new(output.as_slice(), None).unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+
+        let labels = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(labels.value(0), "Berlin");
+        assert!(labels.is_null(1));
+
+        let claims = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        let values = claims.value(0);
+        let values = values.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(values.value(1), "Q1637706");
+        assert_eq!(claims.value(1).len(), 0);
+    }
+
+    #[test]
+    fn test_empty_arrow_stream_has_schema() {
+        let spec = TableSpec::default();
+        let mut output = Vec::new();
+        ArrowStreamWriter::new(&spec)
+            .unwrap()
+            .finish(&mut output)
+            .unwrap();
+
+        let reader = StreamReader::try_new(output.as_slice(), None).unwrap();
+        assert_eq!(reader.schema().fields().len(), 3);
+        assert_eq!(reader.count(), 0);
+    }
+}
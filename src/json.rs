@@ -1,11 +1,30 @@
+use std::collections::BTreeMap;
+use std::fs::File;
 use std::io::{BufRead, Write};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
-use rayon::prelude::*;
-
-use crate::filter::EntityFilter;
-use crate::rdf::OutputFormat;
+use crate::canonical::canonicalize_json;
+use crate::checkpoint::Checkpointer;
+use crate::compression::create_input_reader_from;
+use crate::entity::Entity;
+use crate::filter::{EntityFilter, FilterStage};
+use crate::graphml::GraphMlWriter;
+use crate::ipc::ArrowStreamWriter;
+use crate::malformed::{MalformedLine, MalformedSummary};
+use crate::memory::MemoryAction;
+use crate::mmap;
+use crate::ntriples::escape_literal;
+use crate::partition::{self, EntityChunks, Partition, PartitionWriter, SplitState};
+use crate::pipeline::{self, Emitter};
+use crate::quads;
+use crate::rdf::{OutputFormat, ProcessingOptions};
+use crate::route::{self, BatchOutputs, Route};
+use crate::signal;
+use crate::sort::{EntityKey, ExternalSorter};
+use crate::stats::{Counting, Counts, ResultTypes};
+use crate::tabular;
 use crate::FilterError;
 
 /// Convert a JSON entity to N-Triples format
@@ -36,7 +55,7 @@ pub fn json_entity_to_ntriples(entity: &serde_json::Value) -> Vec<String> {
     if let Some(labels) = entity.get("labels").and_then(|v| v.as_object()) {
         for (lang, label_obj) in labels {
             if let Some(value) = label_obj.get("value").and_then(|v| v.as_str()) {
-                let escaped = escape_ntriples_string(value);
+                let escaped = escape_literal(value);
                 triples.push(format!(
                     "{} <http://www.w3.org/2000/01/rdf-schema#label> \"{}\"@{} .",
                     subject, escaped, lang
@@ -49,7 +68,7 @@ pub fn json_entity_to_ntriples(entity: &serde_json::Value) -> Vec<String> {
     if let Some(descriptions) = entity.get("descriptions").and_then(|v| v.as_object()) {
         for (lang, desc_obj) in descriptions {
             if let Some(value) = desc_obj.get("value").and_then(|v| v.as_str()) {
-                let escaped = escape_ntriples_string(value);
+                let escaped = escape_literal(value);
                 triples.push(format!(
                     "{} <http://schema.org/description> \"{}\"@{} .",
                     subject, escaped, lang
@@ -64,7 +83,7 @@ pub fn json_entity_to_ntriples(entity: &serde_json::Value) -> Vec<String> {
             if let Some(arr) = alias_arr.as_array() {
                 for alias_obj in arr {
                     if let Some(value) = alias_obj.get("value").and_then(|v| v.as_str()) {
-                        let escaped = escape_ntriples_string(value);
+                        let escaped = escape_literal(value);
                         triples.push(format!(
                             "{} <http://www.w3.org/2004/02/skos/core#altLabel> \"{}\"@{} .",
                             subject, escaped, lang
@@ -102,7 +121,7 @@ pub fn json_entity_to_ntriples(entity: &serde_json::Value) -> Vec<String> {
                                     }
                                     Some("string") => {
                                         if let Some(s) = value_obj.as_str() {
-                                            let escaped = escape_ntriples_string(s);
+                                            let escaped = escape_literal(s);
                                             triples.push(format!(
                                                 "{} {} \"{}\" .",
                                                 subject, predicate, escaped
@@ -117,7 +136,7 @@ pub fn json_entity_to_ntriples(entity: &serde_json::Value) -> Vec<String> {
                                                 .get("language")
                                                 .and_then(|v| v.as_str())
                                                 .unwrap_or("en");
-                                            let escaped = escape_ntriples_string(text);
+                                            let escaped = escape_literal(text);
                                             triples.push(format!(
                                                 "{} {} \"{}\"@{} .",
                                                 subject, predicate, escaped, lang
@@ -158,7 +177,7 @@ pub fn json_entity_to_ntriples(entity: &serde_json::Value) -> Vec<String> {
                                     _ => {
                                         // For other types, try to serialize as string
                                         if let Some(s) = value_obj.as_str() {
-                                            let escaped = escape_ntriples_string(s);
+                                            let escaped = escape_literal(s);
                                             triples.push(format!(
                                                 "{} {} \"{}\" .",
                                                 subject, predicate, escaped
@@ -177,22 +196,6 @@ pub fn json_entity_to_ntriples(entity: &serde_json::Value) -> Vec<String> {
     triples
 }
 
-/// Escape special characters for N-Triples string literals
-pub fn escape_ntriples_string(s: &str) -> String {
-    let mut result = String::with_capacity(s.len());
-    for c in s.chars() {
-        match c {
-            '\\' => result.push_str("\\\\"),
-            '"' => result.push_str("\\\""),
-            '\n' => result.push_str("\\n"),
-            '\r' => result.push_str("\\r"),
-            '\t' => result.push_str("\\t"),
-            _ => result.push(c),
-        }
-    }
-    result
-}
-
 /// Write JSON entities efficiently using batch writes
 fn write_json_batch<W: Write>(output: &mut W, json_lines: &[String]) -> std::io::Result<()> {
     if json_lines.is_empty() {
@@ -211,79 +214,1275 @@ fn write_json_batch<W: Write>(output: &mut W, json_lines: &[String]) -> std::io:
     output.write_all(buffer.as_bytes())
 }
 
-/// Process a batch of JSON lines in parallel
-fn process_json_batch_parallel(
-    batch: &[String],
-    filter: &Arc<EntityFilter>,
-    show_progress: bool,
-    output_format: OutputFormat,
-) -> Vec<String> {
-    batch
-        .par_iter()
-        .filter_map(
-            |line| match serde_json::from_str::<serde_json::Value>(line) {
-                Ok(entity) => {
-                    if filter.matches_json(&entity) {
-                        let filtered_entity = filter.filter_json_entity(&entity);
-                        match output_format {
-                            OutputFormat::Json => serde_json::to_string(&filtered_entity).ok(),
-                            OutputFormat::NTriples => {
-                                let triples = json_entity_to_ntriples(&filtered_entity);
-                                if triples.is_empty() {
-                                    None
-                                } else {
-                                    Some(triples.join("\n"))
-                                }
-                            }
-                        }
-                    } else {
-                        None
-                    }
+/// Split a top-level array fragment into the JSON objects it contains
+///
+/// Tracks brace depth outside of strings, so `},{` inside values is left alone.
+fn split_top_level_objects(s: &str) -> Vec<&str> {
+    let mut objects = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, b) in s.bytes().enumerate() {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'{' => {
+                if depth == 0 {
+                    start = i;
                 }
-                Err(e) => {
-                    if show_progress {
-                        eprintln!("Warning: Failed to parse JSON: {}", e);
-                    }
-                    None
+                depth += 1;
+            }
+            b'}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    objects.push(&s[start..=i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        // Unbalanced: pass the rest through so the parse error is reported
+        objects.push(&s[start..]);
+    }
+    objects
+}
+
+/// Extract the entity objects from one input line
+///
+/// Accepts NDJSON as well as the official array-style dump (`[`, entities
+/// separated by `,\n`, `]`), including a byte order mark and brackets that
+/// share a line with an entity, e.g. `[{...},` or a whole array on one line.
+pub fn dump_array_elements(line: &str) -> Vec<&str> {
+    let mut trimmed = line.trim_start_matches('\u{feff}').trim();
+    let mut framed = false;
+    if let Some(rest) = trimmed.strip_prefix('[') {
+        trimmed = rest.trim_start();
+        framed = true;
+    }
+    trimmed = trimmed.trim_end_matches(',').trim_end();
+    if let Some(rest) = trimmed.strip_suffix(']') {
+        trimmed = rest.trim_end().trim_end_matches(',').trim_end();
+        framed = true;
+    }
+    if trimmed.is_empty() {
+        Vec::new()
+    } else if framed {
+        split_top_level_objects(trimmed)
+    } else {
+        vec![trimmed]
+    }
+}
+
+/// Layout of JSON output
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum JsonStyle {
+    /// One compact entity per line
+    NdJson,
+    /// Array with one compact entity per line, like the official dumps
+    Array,
+    /// Pretty-printed array
+    Pretty,
+}
+
+impl JsonStyle {
+    /// Parse a `--json-style` value
+    pub fn parse(s: &str) -> Result<Self, FilterError> {
+        match s {
+            "ndjson" => Ok(JsonStyle::NdJson),
+            "array" => Ok(JsonStyle::Array),
+            "pretty" => Ok(JsonStyle::Pretty),
+            _ => Err(FilterError::Parse(format!(
+                "Invalid JSON style '{}'. Valid styles: ndjson, array, pretty",
+                s
+            ))),
+        }
+    }
+
+    /// Serialize an entity in this style
+    pub fn serialize(self, entity: &serde_json::Value) -> Option<String> {
+        match self {
+            // Indented one level as an array element
+            JsonStyle::Pretty => serde_json::to_string_pretty(entity).ok().map(|json| {
+                json.lines()
+                    .map(|line| format!("  {}", line))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }),
+            JsonStyle::NdJson | JsonStyle::Array => serde_json::to_string(entity).ok(),
+        }
+    }
+}
+
+/// Parser used for JSON entity lines
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum JsonParser {
+    /// serde_json (always available)
+    Serde,
+    /// simd-json (requires the `simd` feature, falls back to serde_json otherwise)
+    Simd,
+}
+
+impl JsonParser {
+    /// Parse a `--parser` value
+    pub fn parse(s: &str) -> Result<Self, FilterError> {
+        match s {
+            "serde" => Ok(JsonParser::Serde),
+            "simd" => Ok(JsonParser::Simd),
+            _ => Err(FilterError::Parse(format!(
+                "Invalid JSON parser '{}'. Valid parsers: serde, simd",
+                s
+            ))),
+        }
+    }
+
+    /// Whether this parser is compiled into the binary
+    pub fn is_available(self) -> bool {
+        match self {
+            JsonParser::Serde => true,
+            JsonParser::Simd => cfg!(feature = "simd"),
+        }
+    }
+
+    /// Parse one entity line into a JSON value
+    pub fn parse_entity(self, line: &str) -> Result<serde_json::Value, String> {
+        match self {
+            #[cfg(feature = "simd")]
+            JsonParser::Simd => {
+                // simd-json parses in place, so it needs its own copy of the line
+                let mut bytes = line.as_bytes().to_vec();
+                simd_json::serde::from_slice(&mut bytes).map_err(|e| e.to_string())
+            }
+            _ => serde_json::from_str(line).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Parse one entity line into the typed model and return the first filter it fails
+    pub fn line_rejection(
+        self,
+        line: &str,
+        filter: &EntityFilter,
+    ) -> Result<Option<FilterStage>, String> {
+        self.with_typed_entity(line, |entity| filter.entity_rejection(entity))
+    }
+
+    /// Parse one entity line into the typed model and apply `f` to it
+    pub fn with_typed_entity<T>(
+        self,
+        line: &str,
+        f: impl FnOnce(&Entity) -> T,
+    ) -> Result<T, String> {
+        match self {
+            #[cfg(feature = "simd")]
+            JsonParser::Simd => {
+                let mut bytes = line.as_bytes().to_vec();
+                let entity: Entity =
+                    simd_json::serde::from_slice(&mut bytes).map_err(|e| e.to_string())?;
+                Ok(f(&entity))
+            }
+            _ => {
+                let entity: Entity = serde_json::from_str(line).map_err(|e| e.to_string())?;
+                Ok(f(&entity))
+            }
+        }
+    }
+}
+
+/// Wraps serialized entities in `[`, `,` and `]` across batches
+#[derive(Default)]
+pub struct JsonArrayWriter {
+    entities_written: u64,
+}
+
+impl JsonArrayWriter {
+    /// Write serialized entities as array elements
+    pub fn write_entities<W: Write>(
+        &mut self,
+        output: &mut W,
+        json_entities: &[String],
+    ) -> std::io::Result<()> {
+        let mut buffer = String::new();
+        for entity in json_entities {
+            buffer.push_str(if self.entities_written == 0 {
+                "[\n"
+            } else {
+                ",\n"
+            });
+            buffer.push_str(entity);
+            self.entities_written += 1;
+        }
+        output.write_all(buffer.as_bytes())
+    }
+
+    /// Close the array
+    pub fn finish<W: Write>(self, output: &mut W) -> std::io::Result<()> {
+        if self.entities_written == 0 {
+            output.write_all(b"[\n]\n")
+        } else {
+            output.write_all(b"\n]\n")
+        }
+    }
+}
+
+/// Writers of the --route outputs, which are not limited by --max-entities
+struct JsonRouteOutputs<'a> {
+    outputs: Vec<(JsonStreamWriters, &'a mut dyn Write)>,
+}
+
+impl<'a> JsonRouteOutputs<'a> {
+    fn new(
+        outputs: Vec<&'a mut dyn Write>,
+        filter: &EntityFilter,
+        options: &ProcessingOptions,
+    ) -> std::io::Result<Self> {
+        let outputs = outputs
+            .into_iter()
+            .map(|output| {
+                Ok((
+                    JsonStreamWriters::with_limit(filter, options, u64::MAX)?,
+                    output,
+                ))
+            })
+            .collect::<std::io::Result<_>>()?;
+        Ok(Self { outputs })
+    }
+
+    /// Write the route results of one batch
+    fn write(&mut self, results: Vec<JsonBatchResult>, drain: bool) -> std::io::Result<()> {
+        for ((writers, output), results) in self.outputs.iter_mut().zip(results) {
+            writers.write_results(output, results)?;
+            if drain {
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        for (writers, mut output) in self.outputs {
+            writers.finish(&mut output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Files of a --partition-by or --shards run
+///
+/// Partition files are opened when their value first has a match, shards
+/// all at the start.
+struct JsonPartitionOutputs<'a> {
+    outputs: BTreeMap<String, (JsonStreamWriters, PartitionWriter)>,
+    /// Entities dealt to round-robin shards so far
+    dealt: usize,
+    /// Current file of a split output
+    split: SplitState,
+    filter: &'a EntityFilter,
+    options: &'a ProcessingOptions,
+}
+
+impl<'a> JsonPartitionOutputs<'a> {
+    fn new(filter: &'a EntityFilter, options: &'a ProcessingOptions) -> std::io::Result<Self> {
+        let mut outputs = Self {
+            outputs: BTreeMap::new(),
+            dealt: 0,
+            split: SplitState::default(),
+            filter,
+            options,
+        };
+        if let Some(ref partition) = options.partition {
+            for value in partition.fixed_values() {
+                outputs.output(partition, value)?;
+            }
+        }
+        Ok(outputs)
+    }
+
+    /// Writers and file of a partition value, opening the file if needed
+    fn output(
+        &mut self,
+        partition: &Partition,
+        value: String,
+    ) -> std::io::Result<&mut (JsonStreamWriters, PartitionWriter)> {
+        Ok(match self.outputs.entry(value) {
+            std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::btree_map::Entry::Vacant(entry) => {
+                let output = partition.open(entry.key(), self.options)?;
+                let writers = JsonStreamWriters::with_limit(self.filter, self.options, u64::MAX)?;
+                entry.insert((writers, output))
+            }
+        })
+    }
+
+    /// End a file that gets no more entities
+    fn close(&mut self, value: &str) -> std::io::Result<()> {
+        if let Some((writers, mut output)) = self.outputs.remove(value) {
+            writers.finish(&mut output)?;
+            output.close(self.options.interrupted.load(Ordering::Relaxed))?;
+        }
+        Ok(())
+    }
+
+    /// Write the partition results of one batch, returning the number of matches
+    ///
+    /// With round-robin shards, the main results are dealt to the shards; a
+    /// split output cuts them into its files.
+    fn write(
+        &mut self,
+        results: &mut BatchOutputs<JsonBatchResult>,
+        drain: bool,
+    ) -> std::io::Result<u64> {
+        let options = self.options;
+        let Some(ref partition) = options.partition else {
+            return Ok(0);
+        };
+        let mut partitions = std::mem::take(&mut results.partitions);
+        if let Some(shards) = partition.round_robin() {
+            let dealt = results.main.len();
+            for (shard, result) in results
+                .main
+                .deal(shards, self.dealt)
+                .into_iter()
+                .enumerate()
+            {
+                partitions.insert(partition.shard_name(shard), result);
+            }
+            self.dealt += dealt;
+        }
+        let mut matched = 0;
+        if let Some(limits) = partition.split_limits() {
+            while let Some((chunk, finished)) = self.split.next_chunk(limits, &mut results.main) {
+                if let Some(part) = finished {
+                    self.close(&partition.shard_name(part))?;
+                }
+                let (writers, output) =
+                    self.output(partition, partition.shard_name(self.split.part))?;
+                matched += writers.write_results(output, chunk)?;
+            }
+        }
+        for (value, results) in partitions {
+            let (writers, output) = self.output(partition, value)?;
+            matched += writers.write_results(output, results)?;
+        }
+        if drain {
+            for (_, output) in self.outputs.values_mut() {
+                output.flush()?;
+            }
+        }
+        Ok(matched)
+    }
+
+    /// End and close every partition file
+    fn finish(self) -> std::io::Result<()> {
+        let interrupted = self.options.interrupted.load(Ordering::Relaxed);
+        for (writers, mut output) in self.outputs.into_values() {
+            writers.finish(&mut output)?;
+            output.close(interrupted)?;
+        }
+        Ok(())
+    }
+}
+
+/// Output writers that keep state across batches
+#[derive(Default)]
+struct JsonStreamWriters {
+    arrow: Option<ArrowStreamWriter>,
+    graphml: Option<GraphMlWriter>,
+    json_array: Option<JsonArrayWriter>,
+    sorter: Option<ExternalSorter>,
+    /// Entities that may still be written (--max-entities)
+    remaining: u64,
+}
+
+impl JsonStreamWriters {
+    fn new(filter: &EntityFilter, options: &ProcessingOptions) -> std::io::Result<Self> {
+        Self::with_limit(filter, options, options.max_entities)
+    }
+
+    /// Writers that stop after `max_entities`; the rejected output has no limit
+    fn with_limit(
+        filter: &EntityFilter,
+        options: &ProcessingOptions,
+        max_entities: u64,
+    ) -> std::io::Result<Self> {
+        let mut writers = Self {
+            sorter: options.sort.clone().map(ExternalSorter::new),
+            remaining: max_entities,
+            ..Default::default()
+        };
+        match options.output_format {
+            OutputFormat::Arrow => writers.arrow = Some(ArrowStreamWriter::new(&options.table)?),
+            OutputFormat::GraphMl => {
+                writers.graphml = Some(GraphMlWriter::new(filter.language_filter.as_ref()))
+            }
+            OutputFormat::Json if options.json_style != JsonStyle::NdJson => {
+                writers.json_array = Some(JsonArrayWriter::default())
+            }
+            _ => {}
+        }
+        Ok(writers)
+    }
+
+    /// Write the sorted entities and the end of the framed outputs
+    fn finish<W: Write>(self, output: &mut W) -> std::io::Result<()> {
+        if let Some(sorter) = self.sorter {
+            sorter.finish(output)?;
+        }
+        if let Some(arrow) = self.arrow {
+            arrow.finish(output)?;
+        }
+        if let Some(graphml) = self.graphml {
+            graphml.finish(output)?;
+        }
+        if let Some(json_array) = self.json_array {
+            json_array.finish(output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a JSON line and return the filtered entity with the outputs it goes to
+///
+/// The destinations say whether the entity is written to the main output and
+/// to each route; entities without any destination are not returned. With
+/// --partition-by, the partition values of selected entities come last. Lines
+/// that fail to parse are added to `malformed` with their line number.
+fn parse_matching_entity(
+    (number, line): (u64, &str),
+    filter: &EntityFilter,
+    routes: &[Arc<EntityFilter>],
+    options: &ProcessingOptions,
+    counts: &mut Counts,
+    malformed: &mut Vec<MalformedLine>,
+) -> Option<(serde_json::Value, Vec<bool>, Vec<String>)> {
+    let parser = options.json_parser;
+    if options.explain {
+        if let Ok(entity) = serde_json::from_str::<Entity>(line) {
+            eprint!(
+                "{}",
+                filter.explain(entity.id(), &entity, Some(entity.entity_type()))
+            );
+        }
+    }
+    // Non-matching lines are only parsed into the typed model, which borrows
+    // from the line; the full value is built for matches only
+    let parsed = if filter.filters_entities() || !routes.is_empty() {
+        parser
+            .with_typed_entity(line, |entity| {
+                let rejection = filter.entity_rejection(entity);
+                let mut destinations = vec![!options.routes_only && filter.selects(rejection)];
+                destinations.extend(routes.iter().map(|route| route.matches_entity(entity)));
+                (rejection, destinations)
+            })
+            .and_then(|(rejection, destinations)| {
+                if destinations.contains(&true) {
+                    parser
+                        .parse_entity(line)
+                        .map(|entity| Some((entity, rejection, destinations)))
+                } else {
+                    counts.record(rejection, false, None);
+                    Ok(None)
                 }
+            })
+    } else {
+        parser
+            .parse_entity(line)
+            .map(|entity| Some((entity, None, vec![true])))
+    };
+
+    match parsed {
+        Ok(Some((entity, rejection, destinations))) => {
+            let entity_type = entity.get("type").and_then(|t| t.as_str());
+            counts.record(rejection, destinations[0], entity_type);
+            // Values are read before the property filter can drop the claim
+            let values = match options.partition {
+                Some(ref partition) if destinations[0] => partition.json_values(&entity),
+                _ => Vec::new(),
+            };
+            let mut filtered_entity = filter.filter_json_entity(&entity);
+            if options.canonical {
+                canonicalize_json(&mut filtered_entity);
+            }
+            Some((filtered_entity, destinations, values))
+        }
+        Ok(None) => None,
+        Err(e) => {
+            malformed.push(MalformedLine::new(number, e.to_string(), line));
+            None
+        }
+    }
+}
+
+/// Serialize a filtered entity as one output record
+///
+/// Returns None for entities without output, such as items without triples
+/// in N-Triples output.
+fn json_record(
+    filtered_entity: &serde_json::Value,
+    options: &ProcessingOptions,
+    counts: &mut Counts,
+) -> Option<String> {
+    let output_format = options.output_format;
+    match output_format {
+        OutputFormat::Json => options.json_style.serialize(filtered_entity),
+        OutputFormat::Csv | OutputFormat::Tsv => Some(tabular::format_record(
+            &options.table.json_row(filtered_entity),
+            output_format,
+        )),
+        OutputFormat::Arrow | OutputFormat::GraphMl => {
+            unreachable!("Arrow and GraphML batches are filtered by filter_json_batch")
+        }
+        OutputFormat::EsBulk => {
+            let mut buffer = String::new();
+            options.bulk.push_entity(&mut buffer, filtered_entity);
+            // The batch writer adds the final newline
+            buffer.pop();
+            Some(buffer)
+        }
+        OutputFormat::NTriples => {
+            let triples = json_entity_to_ntriples(filtered_entity);
+            counts.triples_written += triples.len() as u64;
+            if triples.is_empty() {
+                None
+            } else {
+                Some(triples.join("\n"))
+            }
+        }
+        OutputFormat::NQuads | OutputFormat::TriG => {
+            let triples = json_entity_to_ntriples(filtered_entity);
+            counts.triples_written += triples.len() as u64;
+            let entity_id = filtered_entity
+                .get("id")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+            if triples.is_empty() {
+                None
+            } else {
+                let mut buffer = String::new();
+                quads::push_entity_graph(
+                    &mut buffer,
+                    entity_id,
+                    triples.iter().map(String::as_str),
+                    output_format,
+                );
+                // The batch writer adds the final newline
+                buffer.pop();
+                Some(buffer)
+            }
+        }
+    }
+}
+
+/// Filter a batch of JSON lines, converting the entities with `convert`
+///
+/// Triples of entities that only go to routes are not added to `counts`.
+fn process_json_batch<S: AsRef<str>, T: Clone>(
+    batch: &[(u64, S)],
+    filter: &EntityFilter,
+    routes: &[Arc<EntityFilter>],
+    options: &ProcessingOptions,
+    counts: &mut Counts,
+    malformed: &mut Vec<MalformedLine>,
+    mut convert: impl FnMut(serde_json::Value, &mut Counts) -> Option<T>,
+) -> BatchOutputs<Vec<T>> {
+    let mut results: BatchOutputs<Vec<T>> = BatchOutputs::new(routes.len());
+    for (number, line) in batch {
+        let parsed = parse_matching_entity(
+            (*number, line.as_ref()),
+            filter,
+            routes,
+            options,
+            counts,
+            malformed,
+        );
+        let Some((entity, destinations, values)) = parsed else {
+            continue;
+        };
+        // Round-robin shards are dealt from the main output when writing
+        let main = destinations[0] && values.is_empty();
+        if main {
+            let entity_type = entity.get("type").and_then(serde_json::Value::as_str);
+            results.main_types.push(entity_type);
+        }
+        let converted = if destinations[0] {
+            convert(entity, counts)
+        } else {
+            convert(entity, &mut Counts::default())
+        };
+        let Some(converted) = converted else {
+            if main {
+                results.main_types.pop();
+            }
+            continue;
+        };
+        let mut targets = usize::from(main)
+            + destinations[1..].iter().filter(|&&write| write).count()
+            + values.len();
+        // The last output gets the record itself, the others a copy
+        let mut converted = Some(converted);
+        let mut record = || {
+            targets -= 1;
+            match targets {
+                0 => converted.take(),
+                _ => converted.clone(),
+            }
+            .expect("one record per target")
+        };
+        if main {
+            results.main.push(record());
+        }
+        for (route, &write) in results.routes.iter_mut().zip(&destinations[1..]) {
+            if write {
+                route.push(record());
+            }
+        }
+        for value in values {
+            results.partitions.entry(value).or_default().push(record());
+        }
+    }
+    results
+}
+
+/// A batch of entity lines handed from the reader thread to the workers
+struct JsonBatch {
+    /// Entity lines with their input line numbers
+    lines: Vec<(u64, String)>,
+    /// Flush the output after writing this batch (memory watchdog)
+    drain: bool,
+    /// Line number and byte offset of the first input line after the batch
+    position: (u64, u64),
+}
+
+/// Filtered results of one batch, in the shape the output writer needs
+enum JsonBatchResult {
+    Records(Vec<String>),
+    Rows(Vec<Vec<Vec<String>>>),
+    Entities(Vec<serde_json::Value>),
+    /// Records with the sort keys of their entities (--sort-by-id)
+    Keyed(Vec<(EntityKey, String)>),
+}
+
+impl EntityChunks for JsonBatchResult {
+    fn entity_count(&self) -> usize {
+        self.len()
+    }
+
+    fn byte_sizes(&self) -> Option<Vec<u64>> {
+        match self {
+            JsonBatchResult::Records(records) => {
+                Some(records.iter().map(|r| r.len() as u64 + 1).collect())
+            }
+            JsonBatchResult::Keyed(records) => {
+                Some(records.iter().map(|(_, r)| r.len() as u64 + 1).collect())
+            }
+            JsonBatchResult::Rows(_) | JsonBatchResult::Entities(_) => None,
+        }
+    }
+
+    fn split_to(&mut self, n: usize) -> Self {
+        match self {
+            JsonBatchResult::Records(records) => {
+                JsonBatchResult::Records(records.drain(..n).collect())
+            }
+            JsonBatchResult::Rows(rows) => JsonBatchResult::Rows(rows.drain(..n).collect()),
+            JsonBatchResult::Entities(entities) => {
+                JsonBatchResult::Entities(entities.drain(..n).collect())
+            }
+            JsonBatchResult::Keyed(records) => JsonBatchResult::Keyed(records.drain(..n).collect()),
+        }
+    }
+}
+
+impl JsonBatchResult {
+    /// Number of matched entities
+    fn len(&self) -> usize {
+        match self {
+            JsonBatchResult::Records(records) => records.len(),
+            JsonBatchResult::Rows(rows) => rows.len(),
+            JsonBatchResult::Entities(entities) => entities.len(),
+            JsonBatchResult::Keyed(records) => records.len(),
+        }
+    }
+
+    /// Move the entities to `n` results, entity i to result (start + i) % n
+    /// (round-robin shards)
+    fn deal(&mut self, n: usize, start: usize) -> Vec<JsonBatchResult> {
+        match self {
+            JsonBatchResult::Records(records) => partition::deal(std::mem::take(records), n, start)
+                .into_iter()
+                .map(JsonBatchResult::Records)
+                .collect(),
+            JsonBatchResult::Rows(rows) => partition::deal(std::mem::take(rows), n, start)
+                .into_iter()
+                .map(JsonBatchResult::Rows)
+                .collect(),
+            JsonBatchResult::Entities(entities) => {
+                partition::deal(std::mem::take(entities), n, start)
+                    .into_iter()
+                    .map(JsonBatchResult::Entities)
+                    .collect()
+            }
+            JsonBatchResult::Keyed(records) => partition::deal(std::mem::take(records), n, start)
+                .into_iter()
+                .map(JsonBatchResult::Keyed)
+                .collect(),
+        }
+    }
+
+    /// Keep only the first `n` entities (--max-entities)
+    fn truncate(&mut self, n: usize) {
+        match self {
+            JsonBatchResult::Records(records) => records.truncate(n),
+            JsonBatchResult::Rows(rows) => rows.truncate(n),
+            JsonBatchResult::Entities(entities) => entities.truncate(n),
+            JsonBatchResult::Keyed(records) => records.truncate(n),
+        }
+    }
+}
+
+/// Filter one batch on a worker thread
+///
+/// Returns the results of each output and the malformed lines.
+fn filter_json_batch<S: AsRef<str>>(
+    batch: &[(u64, S)],
+    filter: &Arc<EntityFilter>,
+    routes: &[Arc<EntityFilter>],
+    options: &ProcessingOptions,
+) -> (BatchOutputs<JsonBatchResult>, Vec<MalformedLine>) {
+    let mut counts = Counts::default();
+    let mut malformed = Vec::new();
+    let (counts_ref, malformed_ref) = (&mut counts, &mut malformed);
+    let results = match options.output_format {
+        OutputFormat::Arrow => process_json_batch(
+            batch,
+            filter,
+            routes,
+            options,
+            counts_ref,
+            malformed_ref,
+            |entity, _| Some(options.table.json_cells(&entity)),
+        )
+        .map(JsonBatchResult::Rows),
+        OutputFormat::GraphMl => process_json_batch(
+            batch,
+            filter,
+            routes,
+            options,
+            counts_ref,
+            malformed_ref,
+            |entity, _| Some(entity),
+        )
+        .map(JsonBatchResult::Entities),
+        _ if options.sort.is_some() => process_json_batch(
+            batch,
+            filter,
+            routes,
+            options,
+            counts_ref,
+            malformed_ref,
+            |entity, counts| {
+                let key = EntityKey::new(entity["id"].as_str().unwrap_or_default());
+                json_record(&entity, options, counts).map(|record| (key, record))
             },
         )
-        .collect()
+        .map(JsonBatchResult::Keyed),
+        _ => process_json_batch(
+            batch,
+            filter,
+            routes,
+            options,
+            counts_ref,
+            malformed_ref,
+            |entity, counts| json_record(&entity, options, counts),
+        )
+        .map(JsonBatchResult::Records),
+    };
+    counts.malformed_lines = malformed.len() as u64;
+    if let Some(ref stats) = options.stats {
+        stats.add(counts);
+    }
+    (results, malformed)
+}
+
+impl JsonStreamWriters {
+    /// Write the main results of one batch, returning the number of matches;
+    /// the matches --max-entities cuts off are taken back from the statistics
+    fn write_main<W: Write>(
+        &mut self,
+        output: &mut W,
+        results: JsonBatchResult,
+        types: &ResultTypes,
+        options: &ProcessingOptions,
+    ) -> std::io::Result<u64> {
+        let total = results.len() as u64;
+        let matched = self.write_results(output, results)?;
+        if let Some(ref stats) = options.stats {
+            stats.unmatch(types, total - matched);
+        }
+        Ok(matched)
+    }
+
+    /// Write the results of one batch, returning the number of matches
+    fn write_results<W: Write>(
+        &mut self,
+        output: &mut W,
+        mut results: JsonBatchResult,
+    ) -> std::io::Result<u64> {
+        results.truncate(self.remaining.min(usize::MAX as u64) as usize);
+        let matched = results.len() as u64;
+        self.remaining -= matched;
+        match results {
+            JsonBatchResult::Rows(rows) => {
+                if let Some(ref mut arrow) = self.arrow {
+                    arrow.write_rows(output, &rows)?;
+                }
+            }
+            JsonBatchResult::Entities(entities) => {
+                if let Some(ref mut graphml) = self.graphml {
+                    graphml.write_entities(output, &entities)?;
+                }
+            }
+            JsonBatchResult::Records(records) => match self.json_array {
+                Some(ref mut json_array) => json_array.write_entities(output, &records)?,
+                None => write_json_batch(output, &records)?,
+            },
+            JsonBatchResult::Keyed(records) => {
+                for (key, mut record) in records {
+                    record.push('\n');
+                    match self.sorter {
+                        Some(ref mut sorter) => sorter.push(key, record.into_bytes())?,
+                        None => output.write_all(record.as_bytes())?,
+                    }
+                }
+            }
+        }
+        Ok(matched)
+    }
 }
 
 /// Main JSON filtering function with parallel processing
-pub fn filter_json_parallel<R: BufRead, W: Write>(
+///
+/// A reader thread splits the input into batches of entity lines, worker
+/// threads filter them, and the calling thread writes the results in order.
+/// Each route gets the entities its own filter selects.
+pub fn filter_json_parallel<R: BufRead + Send, W: Write>(
     reader: R,
     output: &mut W,
+    routes: &mut [Route],
     filter: &Arc<EntityFilter>,
-    show_progress: bool,
-    batch_size: usize,
-    skip_lines: u64,
-    max_lines: u64,
-    output_format: OutputFormat,
+    options: &ProcessingOptions,
 ) -> Result<(), FilterError> {
+    let show_progress = options.show_progress;
+    let (route_filters, route_outputs) = route::split(routes);
+    let mut writers = JsonStreamWriters::new(filter, options)?;
+    let mut route_outputs = JsonRouteOutputs::new(route_outputs, filter, options)?;
+    let mut partition_outputs = JsonPartitionOutputs::new(filter, options)?;
+    let mut checkpointer = options.checkpoint.clone().map(Checkpointer::new);
+    let mut malformed = MalformedSummary::new(options)?;
+    let lines_processed = AtomicU64::new(options.resume_position.0);
+    let entities_matched = AtomicU64::new(0);
+
+    pipeline::run(
+        options.pipeline,
+        |emitter| {
+            read_json_batches(
+                reader,
+                emitter,
+                options,
+                &lines_processed,
+                &entities_matched,
+            )
+        },
+        |batch: JsonBatch| {
+            (
+                filter_json_batch(&batch.lines, filter, &route_filters, options),
+                batch.drain,
+                batch.position,
+            )
+        },
+        |((mut results, lines), drain, position)| -> Result<(), FilterError> {
+            malformed.add(lines)?;
+            let mut matched = partition_outputs.write(&mut results, drain)?;
+            matched += writers.write_main(output, results.main, &results.main_types, options)?;
+            entities_matched.fetch_add(matched, Ordering::Relaxed);
+            route_outputs.write(results.routes, drain)?;
+            if drain {
+                output.flush()?;
+            }
+            if let Some(ref mut checkpointer) = checkpointer {
+                checkpointer.record(output, position)?;
+            }
+            Ok(())
+        },
+    )?;
+
+    writers.finish(output)?;
+    route_outputs.finish()?;
+    partition_outputs.finish()?;
+    if let Some(ref mut checkpointer) = checkpointer {
+        checkpointer.finish(output)?;
+    }
+    malformed.finish()?;
+
+    if let Some(ref stats) = options.stats {
+        stats.add(Counts {
+            lines_read: lines_processed.load(Ordering::Relaxed) - options.resume_position.0,
+            ..Default::default()
+        });
+    }
+
+    if show_progress {
+        eprintln!(
+            "Done! Processed {} lines, matched {} entities",
+            lines_processed.load(Ordering::Relaxed),
+            entities_matched.load(Ordering::Relaxed)
+        );
+    }
+
+    Ok(())
+}
+
+/// JSON filtering of a memory-mapped dump (--mmap)
+///
+/// The reader thread only cuts the mapping into line-aligned byte ranges;
+/// splitting lines and filtering happens on the workers.
+pub fn filter_json_mmap<W: Write>(
+    data: &[u8],
+    output: &mut W,
+    routes: &mut [Route],
+    filter: &Arc<EntityFilter>,
+    options: &ProcessingOptions,
+) -> Result<(), FilterError> {
+    let show_progress = options.show_progress;
+    let (route_filters, route_outputs) = route::split(routes);
+    let mut writers = JsonStreamWriters::new(filter, options)?;
+    let mut route_outputs = JsonRouteOutputs::new(route_outputs, filter, options)?;
+    let mut partition_outputs = JsonPartitionOutputs::new(filter, options)?;
+    let mut malformed = MalformedSummary::new(options)?;
+    let lines_processed = AtomicU64::new(0);
+    let entities_matched = AtomicU64::new(0);
+    let started = Instant::now();
+
+    pipeline::run(
+        options.pipeline,
+        |emitter| -> Result<(), FilterError> {
+            let mut first_line = 1;
+            for range in mmap::chunk_ranges(data, mmap::CHUNK_SIZE, mmap::next_line_start) {
+                if options.interrupted.load(Ordering::Relaxed) {
+                    signal::report_interrupt(None, range.start as u64);
+                    break;
+                }
+                if entities_matched.load(Ordering::Relaxed) >= options.max_entities {
+                    break;
+                }
+                if options.snapshot_requested.swap(false, Ordering::Relaxed) {
+                    signal::report_snapshot(
+                        Some(lines_processed.load(Ordering::Relaxed)),
+                        range.start as u64,
+                        entities_matched.load(Ordering::Relaxed),
+                        started.elapsed(),
+                    );
+                }
+                if show_progress {
+                    eprintln!(
+                        "Read {} of {} MiB, matched {} entities",
+                        range.start >> 20,
+                        data.len() >> 20,
+                        entities_matched.load(Ordering::Relaxed)
+                    );
+                }
+                let lines = memchr::memchr_iter(b'\n', &data[range.clone()]).count() as u64;
+                if !emitter.emit((range, first_line)) {
+                    break;
+                }
+                first_line += lines;
+            }
+            Ok(())
+        },
+        |(range, first_line): (std::ops::Range<usize>, u64)| -> Result<_, FilterError> {
+            let chunk = mmap::chunk_str(&data[range])?;
+            let mut lines = 0;
+            let elements: Vec<(u64, &str)> = chunk
+                .lines()
+                .zip(first_line..)
+                .inspect(|_| lines += 1)
+                .flat_map(|(line, number)| {
+                    dump_array_elements(line)
+                        .into_iter()
+                        .map(move |element| (number, element))
+                })
+                .collect();
+            lines_processed.fetch_add(lines, Ordering::Relaxed);
+            Ok(filter_json_batch(
+                &elements,
+                filter,
+                &route_filters,
+                options,
+            ))
+        },
+        |results| {
+            let (mut results, lines) = results?;
+            malformed.add(lines)?;
+            let mut matched = partition_outputs.write(&mut results, false)?;
+            matched += writers.write_main(output, results.main, &results.main_types, options)?;
+            entities_matched.fetch_add(matched, Ordering::Relaxed);
+            route_outputs.write(results.routes, false)?;
+            Ok(())
+        },
+    )?;
+
+    writers.finish(output)?;
+    route_outputs.finish()?;
+    partition_outputs.finish()?;
+    malformed.finish()?;
+
+    if let Some(ref stats) = options.stats {
+        stats.add(Counts {
+            lines_read: lines_processed.load(Ordering::Relaxed),
+            ..Default::default()
+        });
+    }
+
+    if show_progress {
+        eprintln!(
+            "Done! Processed {} lines, matched {} entities",
+            lines_processed.load(Ordering::Relaxed),
+            entities_matched.load(Ordering::Relaxed)
+        );
+    }
+
+    Ok(())
+}
+
+/// JSON filtering of a directory of dump parts
+///
+/// Up to one reader thread per worker takes whole files, so decompression
+/// scales across cores too. Entities of one part stay in order, but the
+/// parts are interleaved in the output; with --stable-order, a single reader
+/// takes the parts one after the other.
+pub fn filter_json_files<W: Write>(
+    parts: &[String],
+    buffer_size: usize,
+    bytes_read: Arc<AtomicU64>,
+    output: &mut W,
+    routes: &mut [Route],
+    filter: &Arc<EntityFilter>,
+    options: &ProcessingOptions,
+) -> Result<(), FilterError> {
+    let show_progress = options.show_progress;
+    let (route_filters, route_outputs) = route::split(routes);
+    let mut writers = JsonStreamWriters::new(filter, options)?;
+    let mut route_outputs = JsonRouteOutputs::new(route_outputs, filter, options)?;
+    let mut partition_outputs = JsonPartitionOutputs::new(filter, options)?;
+    let mut malformed = MalformedSummary::new(options)?;
     let lines_processed = AtomicU64::new(0);
-    let lines_skipped = AtomicU64::new(0);
     let entities_matched = AtomicU64::new(0);
+    let next_part = AtomicUsize::new(0);
+    let readers = match options.stable_order {
+        true => 1,
+        false => options.pipeline.workers.min(parts.len()).max(1),
+    };
+
+    pipeline::run(
+        options.pipeline,
+        |emitter| -> Result<(), FilterError> {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = (0..readers)
+                    .map(|_| {
+                        let mut emitter = emitter.clone();
+                        let (next_part, bytes_read) = (&next_part, &bytes_read);
+                        let (lines_processed, entities_matched) =
+                            (&lines_processed, &entities_matched);
+                        scope.spawn(move || -> Result<(), FilterError> {
+                            loop {
+                                let index = next_part.fetch_add(1, Ordering::Relaxed);
+                                let Some(path) = parts.get(index) else {
+                                    return Ok(());
+                                };
+                                if show_progress {
+                                    eprintln!(
+                                        "Reading part {} of {}: {}",
+                                        index + 1,
+                                        parts.len(),
+                                        path
+                                    );
+                                }
+                                let file = Counting::new(File::open(path)?, bytes_read.clone());
+                                let (reader, _) =
+                                    create_input_reader_from(file, path, "json", buffer_size);
+                                if !read_json_part(
+                                    reader,
+                                    &mut emitter,
+                                    options,
+                                    lines_processed,
+                                    entities_matched,
+                                )? {
+                                    return Ok(());
+                                }
+                            }
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .try_for_each(|handle| handle.join().expect("dump part reader panicked"))
+            })
+        },
+        |batch: JsonBatch| filter_json_batch(&batch.lines, filter, &route_filters, options),
+        |(mut results, lines)| -> Result<(), FilterError> {
+            malformed.add(lines)?;
+            let mut matched = partition_outputs.write(&mut results, false)?;
+            matched += writers.write_main(output, results.main, &results.main_types, options)?;
+            entities_matched.fetch_add(matched, Ordering::Relaxed);
+            route_outputs.write(results.routes, false)?;
+            Ok(())
+        },
+    )?;
+    if options.interrupted.load(Ordering::Relaxed) {
+        eprintln!(
+            "Interrupted after {} lines; the output holds the entities read so far",
+            lines_processed.load(Ordering::Relaxed)
+        );
+    }
+
+    writers.finish(output)?;
+    route_outputs.finish()?;
+    partition_outputs.finish()?;
+    malformed.finish()?;
+
+    if let Some(ref stats) = options.stats {
+        stats.add(Counts {
+            lines_read: lines_processed.load(Ordering::Relaxed),
+            ..Default::default()
+        });
+    }
+
+    if show_progress {
+        eprintln!(
+            "Done! Processed {} lines in {} parts, matched {} entities",
+            lines_processed.load(Ordering::Relaxed),
+            parts.len(),
+            entities_matched.load(Ordering::Relaxed)
+        );
+    }
+
+    Ok(())
+}
+
+/// Reader thread of `filter_json_files`: cut one part into batches
+///
+/// Returns false if reading should stop (Ctrl-C, --max-entities or a
+/// stopped writer). Line numbers count from the start of the part.
+fn read_json_part<R: BufRead>(
+    reader: R,
+    emitter: &mut Emitter<JsonBatch>,
+    options: &ProcessingOptions,
+    lines_processed: &AtomicU64,
+    entities_matched: &AtomicU64,
+) -> Result<bool, FilterError> {
+    let batch_size = options.batch_size;
+    let mut line_batch: Vec<(u64, String)> = Vec::with_capacity(batch_size);
+    let mut batch_bytes = 0;
+    for (line_result, line_number) in reader.lines().zip(1..) {
+        let line = line_result?;
+        if options.interrupted.load(Ordering::Relaxed)
+            || entities_matched.load(Ordering::Relaxed) >= options.max_entities
+        {
+            return Ok(false);
+        }
+        lines_processed.fetch_add(1, Ordering::Relaxed);
+        batch_bytes += line.len() as u64;
+        line_batch.extend(
+            dump_array_elements(&line)
+                .into_iter()
+                .map(|element| (line_number, element.to_string())),
+        );
+        if line_batch.len() >= batch_size || batch_bytes >= emitter.batch_bytes() {
+            let lines = std::mem::replace(&mut line_batch, Vec::with_capacity(batch_size));
+            let batch = JsonBatch {
+                lines,
+                drain: false,
+                position: (0, 0),
+            };
+            if !emitter.emit_sized(batch, std::mem::take(&mut batch_bytes)) {
+                return Ok(false);
+            }
+        }
+    }
+    let batch = JsonBatch {
+        lines: line_batch,
+        drain: false,
+        position: (0, 0),
+    };
+    Ok(batch.lines.is_empty() || emitter.emit_sized(batch, batch_bytes))
+}
+
+/// Reader thread of `filter_json_parallel`: skip/limit lines and cut them into batches
+fn read_json_batches<R: BufRead>(
+    reader: R,
+    emitter: &mut Emitter<JsonBatch>,
+    options: &ProcessingOptions,
+    lines_processed: &AtomicU64,
+    entities_matched: &AtomicU64,
+) -> Result<(), FilterError> {
+    let show_progress = options.show_progress;
+    let started = Instant::now();
+    let batch_size = options.batch_size;
+    let skip_lines = options.skip_lines;
+    let max_lines = options.max_lines;
+    let mut watchdog = options.memory_watchdog();
+    let mut lines_skipped: u64 = 0;
     let mut skip_mode = skip_lines > 0;
+    let mut entities_to_skip = options.skip_entities;
 
-    let mut line_batch: Vec<String> = Vec::with_capacity(batch_size);
+    let mut line_batch: Vec<(u64, String)> = Vec::with_capacity(batch_size);
+    let mut batch_bytes = 0;
 
+    let mut offset = options.resume_position.1;
+    let mut stopped_at = None;
     for line_result in reader.lines() {
         let line = line_result?;
+        // On Ctrl-C, stop before this line; every line read so far is in a batch
+        if options.interrupted.load(Ordering::Relaxed) {
+            signal::report_interrupt(Some(lines_processed.load(Ordering::Relaxed)), offset);
+            break;
+        }
+        // The writer has all the entities it needs
+        if entities_matched.load(Ordering::Relaxed) >= options.max_entities {
+            break;
+        }
+        if options.snapshot_requested.swap(false, Ordering::Relaxed) {
+            signal::report_snapshot(
+                Some(lines_processed.load(Ordering::Relaxed)),
+                offset,
+                entities_matched.load(Ordering::Relaxed),
+                started.elapsed(),
+            );
+        }
+        let line_offset = offset;
+        offset += line.len() as u64 + 1;
         let current_line = lines_processed.fetch_add(1, Ordering::Relaxed) + 1;
 
         // Check max_lines limit
-        if current_line > skip_lines + max_lines {
+        if current_line - options.resume_position.0 > skip_lines + max_lines {
             if show_progress {
                 eprintln!("Reached max_lines limit ({}), stopping.", max_lines);
             }
+            stopped_at = Some((current_line, line_offset));
             break;
         }
 
         // Skip lines if needed
         if skip_mode {
-            lines_skipped.fetch_add(1, Ordering::Relaxed);
+            lines_skipped += 1;
             if current_line >= skip_lines {
                 skip_mode = false;
                 if show_progress {
@@ -296,49 +1495,70 @@ pub fn filter_json_parallel<R: BufRead, W: Write>(
             continue;
         }
 
-        if show_progress && (current_line - skip_lines) % 10000 == 0 {
+        if show_progress && (current_line - skip_lines).is_multiple_of(10000) {
             eprintln!(
                 "Processed {} lines (skipped {}), matched {} entities",
                 current_line,
-                lines_skipped.load(Ordering::Relaxed),
+                lines_skipped,
                 entities_matched.load(Ordering::Relaxed)
             );
         }
 
         // Skip empty lines and array brackets
-        let trimmed = line.trim().trim_end_matches(',');
-        if trimmed.is_empty() || trimmed == "[" || trimmed == "]" {
+        let mut elements = dump_array_elements(&line);
+        if entities_to_skip > 0 {
+            let skipped = elements.len().min(entities_to_skip as usize);
+            elements.drain(..skipped);
+            entities_to_skip -= skipped as u64;
+        }
+        if elements.is_empty() {
             continue;
         }
 
-        line_batch.push(trimmed.to_string());
+        batch_bytes += line.len() as u64;
+        line_batch.extend(
+            elements
+                .into_iter()
+                .map(|element| (current_line, element.to_string())),
+        );
 
-        // Process batch when full
-        if line_batch.len() >= batch_size {
-            let results =
-                process_json_batch_parallel(&line_batch, filter, show_progress, output_format);
-            entities_matched.fetch_add(results.len() as u64, Ordering::Relaxed);
+        // Shrink the batch or drain early when memory gets tight
+        let mut drain = false;
+        let mut effective_batch_size = batch_size;
+        if let Some(ref mut watchdog) = watchdog {
+            drain = watchdog.poll() == MemoryAction::Drain;
+            effective_batch_size = watchdog.batch_size();
+        }
 
-            write_json_batch(output, &results)?;
-            line_batch.clear();
+        // Hand the batch to the workers when full
+        if drain || line_batch.len() >= effective_batch_size || batch_bytes >= emitter.batch_bytes()
+        {
+            let lines = std::mem::replace(&mut line_batch, Vec::with_capacity(batch_size));
+            let position = (current_line + 1, offset);
+            let batch = JsonBatch {
+                lines,
+                drain,
+                position,
+            };
+            if !emitter.emit_sized(batch, std::mem::take(&mut batch_bytes)) {
+                return Ok(());
+            }
+            // Wait for pending batches to be written before reading more
+            if drain && !emitter.wait_idle() {
+                return Ok(());
+            }
         }
     }
 
-    // Process remaining batch
+    // Hand over the remaining batch
     if !line_batch.is_empty() {
-        let results =
-            process_json_batch_parallel(&line_batch, filter, show_progress, output_format);
-        entities_matched.fetch_add(results.len() as u64, Ordering::Relaxed);
-
-        write_json_batch(output, &results)?;
-    }
-
-    if show_progress {
-        eprintln!(
-            "Done! Processed {} lines, matched {} entities",
-            lines_processed.load(Ordering::Relaxed),
-            entities_matched.load(Ordering::Relaxed)
-        );
+        let lines = lines_processed.load(Ordering::Relaxed);
+        let batch = JsonBatch {
+            lines: line_batch,
+            drain: false,
+            position: stopped_at.unwrap_or((lines + 1, offset)),
+        };
+        emitter.emit_sized(batch, batch_bytes);
     }
 
     Ok(())
@@ -347,6 +1567,7 @@ pub fn filter_json_parallel<R: BufRead, W: Write>(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::TempDir;
 
     #[test]
     fn test_json_entity_to_ntriples_basic() {
@@ -409,11 +1630,498 @@ mod tests {
     }
 
     #[test]
-    fn test_escape_ntriples_string() {
-        assert_eq!(escape_ntriples_string("hello"), "hello");
-        assert_eq!(escape_ntriples_string("hello\"world"), "hello\\\"world");
-        assert_eq!(escape_ntriples_string("line1\nline2"), "line1\\nline2");
-        assert_eq!(escape_ntriples_string("path\\file"), "path\\\\file");
-        assert_eq!(escape_ntriples_string("tab\there"), "tab\\there");
+    fn test_interrupt_stops_reading() {
+        let options = ProcessingOptions {
+            output_format: OutputFormat::Json,
+            ..Default::default()
+        };
+        options
+            .interrupted
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+        let mut output = Vec::new();
+        filter_json_parallel(
+            "{\"id\":\"Q1\",\"type\":\"item\"}\n".as_bytes(),
+            &mut output,
+            &mut [],
+            &Arc::new(EntityFilter::default()),
+            &options,
+        )
+        .unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_stable_order_with_parallel_batches() {
+        // Every 7th entity is much larger, so its batch takes longer to filter
+        let input: String = (1..=300)
+            .map(|n| {
+                let aliases = if n % 7 == 0 { 500 } else { 1 };
+                let aliases: Vec<String> = (0..aliases)
+                    .map(|a| format!(r#"{{"language":"en","value":"alias {a}"}}"#))
+                    .collect();
+                format!(
+                    r#"{{"id":"Q{n}","type":"item","aliases":{{"en":[{}]}}}}"#,
+                    aliases.join(",")
+                ) + "\n"
+            })
+            .collect();
+        let options = ProcessingOptions {
+            batch_size: 1,
+            output_format: OutputFormat::Json,
+            pipeline: crate::pipeline::PipelineConfig {
+                workers: 8,
+                queue_depth: 32,
+                ordered: true,
+                max_bytes: None,
+            },
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        filter_json_parallel(
+            input.as_bytes(),
+            &mut output,
+            &mut [],
+            &Arc::new(EntityFilter::default()),
+            &options,
+        )
+        .unwrap();
+
+        let ids: Vec<String> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["id"].to_string())
+            .collect();
+        let expected: Vec<String> = (1..=300).map(|n| format!("\"Q{n}\"")).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_skip_and_max_entities() {
+        // Two entities share the first line, as in a one-line array dump
+        let input = concat!(
+            r#"[{"id":"Q1","type":"item"},{"id":"Q2","type":"item"},"#,
+            "\n",
+            r#"{"id":"Q3","type":"item"},"#,
+            "\n",
+            r#"{"id":"Q4","type":"item"},"#,
+            "\n",
+            r#"{"id":"Q5","type":"item"}]"#,
+            "\n",
+        );
+        let options = ProcessingOptions {
+            batch_size: 2,
+            output_format: OutputFormat::Json,
+            skip_entities: 1,
+            max_entities: 3,
+            stats: Some(Arc::new(crate::stats::RunStats::default())),
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        filter_json_parallel(
+            input.as_bytes(),
+            &mut output,
+            &mut [],
+            &Arc::new(EntityFilter::default()),
+            &options,
+        )
+        .unwrap();
+
+        let ids: Vec<String> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["id"].to_string())
+            .collect();
+        assert_eq!(ids, ["\"Q2\"", "\"Q3\"", "\"Q4\""]);
+        // Matches past the limit are not counted
+        let report = options
+            .stats
+            .unwrap()
+            .report(&EntityFilter::default(), std::time::Duration::ZERO);
+        assert_eq!(report["entities_matched"], 3);
+    }
+
+    #[test]
+    fn test_invert_match() {
+        let input = concat!(
+            r#"{"id":"Q1","type":"item"}"#,
+            "\n",
+            r#"{"id":"P31","type":"property"}"#,
+            "\n",
+        );
+        let run = |entity_type: &str| {
+            let filter = Arc::new(EntityFilter {
+                entity_type: entity_type.to_string(),
+                invert_match: true,
+                ..Default::default()
+            });
+            let options = ProcessingOptions {
+                output_format: OutputFormat::Json,
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            filter_json_parallel(input.as_bytes(), &mut output, &mut [], &filter, &options)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(run("item"), "{\"id\":\"P31\",\"type\":\"property\"}\n");
+        // Without entity filters every entity matches, so nothing is left
+        assert_eq!(run("both"), "");
+    }
+
+    #[test]
+    fn test_routes() {
+        let human = r#"{"id":"Q1","type":"item","claims":{"P31":[{"mainsnak":{"snaktype":"value","property":"P31","datavalue":{"value":{"entity-type":"item","id":"Q5"},"type":"wikibase-entityid"}}}]}}"#;
+        let input = format!(
+            "[\n{},\n{{\"id\":\"P31\",\"type\":\"property\"}},\n{{\"id\":\"Q2\",\"type\":\"item\"}}\n]\n",
+            human
+        );
+        let filter = EntityFilter {
+            entity_type: "item".to_string(),
+            ..Default::default()
+        };
+        let humans = EntityFilter {
+            claim_filter: Some(crate::claim_parser::parse_claim_filter("P31:Q5").unwrap()),
+            ..filter.clone()
+        };
+        let rejected = EntityFilter {
+            invert_match: true,
+            ..filter.clone()
+        };
+        let options = ProcessingOptions {
+            output_format: OutputFormat::Json,
+            json_style: JsonStyle::Array,
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        let (mut humans_output, mut rejected_output) = (Vec::new(), Vec::new());
+        filter_json_parallel(
+            input.as_bytes(),
+            &mut output,
+            &mut [
+                Route {
+                    filter: Arc::new(humans),
+                    output: &mut humans_output,
+                },
+                Route {
+                    filter: Arc::new(rejected),
+                    output: &mut rejected_output,
+                },
+            ],
+            &Arc::new(filter),
+            &options,
+        )
+        .unwrap();
+
+        let ids = |output: Vec<u8>| {
+            let entities: Vec<serde_json::Value> = serde_json::from_slice(&output).unwrap();
+            entities
+                .iter()
+                .map(|e| e["id"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(output), ["Q1", "Q2"]);
+        assert_eq!(ids(humans_output), ["Q1"]);
+        assert_eq!(ids(rejected_output), ["P31"]);
+    }
+
+    #[test]
+    fn test_partition_by_claim_value() {
+        let human = r#"{"id":"Q1","type":"item","claims":{"P31":[{"mainsnak":{"snaktype":"value","property":"P31","datavalue":{"value":{"entity-type":"item","id":"Q5"},"type":"wikibase-entityid"}}}]}}"#;
+        let input = format!("{}\n{{\"id\":\"Q2\",\"type\":\"item\"}}\n", human);
+        let dir = TempDir::new("partition-json");
+        let template = dir.file("{value}.csv");
+        let options = ProcessingOptions {
+            output_format: OutputFormat::Csv,
+            table: tabular::TableSpec::parse("id", "|").unwrap(),
+            // The property filter drops P31 after the partition value is read
+            partition: Some(Partition::new("P31", &template, 1024).unwrap()),
+            ..Default::default()
+        };
+        let filter = EntityFilter {
+            property_filter: Some(["P18".to_string()].into()),
+            ..Default::default()
+        };
+        filter_json_parallel(
+            input.as_bytes(),
+            &mut std::io::sink(),
+            &mut [],
+            &Arc::new(filter),
+            &options,
+        )
+        .unwrap();
+
+        let read =
+            |value: &str| std::fs::read_to_string(dir.join(format!("{}.csv", value))).unwrap();
+        assert_eq!(read("Q5"), "id\nQ1\n");
+        assert_eq!(read("none"), "id\nQ2\n");
+    }
+
+    #[test]
+    fn test_hash_shards() {
+        let input = "{\"id\":\"Q1\"}\n{\"id\":\"Q2\"}\n{\"id\":\"Q3\"}\n";
+        let dir = TempDir::new("shards-json");
+        let template = dir.file("part-{shard}.ndjson");
+        let shards = Partition::shards(8, partition::ShardBy::Hash, &template, 1024).unwrap();
+        let options = ProcessingOptions {
+            output_format: OutputFormat::Json,
+            partition: Some(shards.clone()),
+            ..Default::default()
+        };
+        filter_json_parallel(
+            input.as_bytes(),
+            &mut std::io::sink(),
+            &mut [],
+            &Arc::new(EntityFilter::default()),
+            &options,
+        )
+        .unwrap();
+
+        // Every shard file exists, each entity is in the shard of its ID
+        let read = |shard: &str| std::fs::read_to_string(shards.path(shard)).unwrap();
+        let total: usize = shards
+            .fixed_values()
+            .iter()
+            .map(|s| read(s).lines().count())
+            .sum();
+        assert_eq!(total, 3);
+        for line in input.lines() {
+            let entity: serde_json::Value = serde_json::from_str(line).unwrap();
+            let shard = &shards.json_values(&entity)[0];
+            assert!(read(shard).contains(line));
+        }
+    }
+
+    #[test]
+    fn test_sort_by_id() {
+        let input = "{\"id\":\"Q20\"}\n{\"id\":\"P31\"}\n{\"id\":\"Q3\"}\n";
+        let dir = TempDir::new("sort-json");
+        let options = ProcessingOptions {
+            output_format: OutputFormat::Json,
+            sort: Some(crate::sort::SortConfig {
+                memory: 1 << 20,
+                dir: dir.path().to_path_buf(),
+            }),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        filter_json_parallel(
+            input.as_bytes(),
+            &mut output,
+            &mut [],
+            &Arc::new(EntityFilter::default()),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"id\":\"P31\"}\n{\"id\":\"Q3\"}\n{\"id\":\"Q20\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_filter_json_files() {
+        let dir = TempDir::new("json-parts");
+        let plain = dir.join("part-1.json");
+        std::fs::write(&plain, "[\n{\"id\":\"Q1\"},\n{\"id\":\"Q2\"}\n]\n").unwrap();
+        let gzipped = dir.join("part-2.json.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&gzipped).unwrap(),
+            flate2::Compression::fast(),
+        );
+        encoder
+            .write_all(b"{\"id\":\"Q3\"}\n{\"id\":\"Q4\"}\n")
+            .unwrap();
+        encoder.finish().unwrap();
+        let parts = [plain, gzipped].map(|path| path.to_string_lossy().into_owned());
+
+        let bytes_read = Arc::new(AtomicU64::new(0));
+        let run = |stable_order| {
+            let options = ProcessingOptions {
+                output_format: OutputFormat::Json,
+                pipeline: pipeline::PipelineConfig::new(Some(2), None),
+                stable_order,
+                batch_size: 1,
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            filter_json_files(
+                &parts,
+                1 << 16,
+                bytes_read.clone(),
+                &mut output,
+                &mut [],
+                &Arc::new(EntityFilter::default()),
+                &options,
+            )
+            .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let expected = [
+            "{\"id\":\"Q1\"}",
+            "{\"id\":\"Q2\"}",
+            "{\"id\":\"Q3\"}",
+            "{\"id\":\"Q4\"}",
+        ];
+        let output = run(false);
+        let mut lines: Vec<&str> = output.lines().collect();
+        lines.sort();
+        assert_eq!(lines, expected);
+        assert!(bytes_read.load(Ordering::Relaxed) > 0);
+        // The parts follow each other in name order
+        assert_eq!(run(true).lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_strict_reports_malformed_line() {
+        let input = "{\"id\":\"Q1\",\"type\":\"item\"}\n{\"id\":\"Q2\",\n{\"id\":\"Q3\",\"type\":\"item\"}\n";
+        let run = |strict| {
+            let options = ProcessingOptions {
+                output_format: OutputFormat::Json,
+                strict,
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            filter_json_parallel(
+                input.as_bytes(),
+                &mut output,
+                &mut [],
+                &Arc::new(EntityFilter::default()),
+                &options,
+            )
+            .map(|()| output)
+        };
+
+        assert_eq!(run(false).unwrap().split(|&b| b == b'\n').count(), 3);
+        let error = run(true).unwrap_err().to_string();
+        assert!(error.contains("line 2"), "{error}");
+        assert!(error.contains(r#"{"id":"Q2""#), "{error}");
+    }
+
+    fn run_json_style(style: JsonStyle, input: &str) -> String {
+        let filter = Arc::new(EntityFilter::default());
+        let options = ProcessingOptions {
+            batch_size: 1,
+            output_format: OutputFormat::Json,
+            json_style: style,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        filter_json_parallel(input.as_bytes(), &mut output, &mut [], &filter, &options).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_json_style_array() {
+        let input = "[\n{\"id\":\"Q1\",\"type\":\"item\"},\n{\"id\":\"Q2\",\"type\":\"item\"}\n]\n";
+        let output = run_json_style(JsonStyle::Array, input);
+        assert_eq!(
+            output,
+            "[\n{\"id\":\"Q1\",\"type\":\"item\"},\n{\"id\":\"Q2\",\"type\":\"item\"}\n]\n"
+        );
+
+        // Array output can be read back as input
+        assert_eq!(run_json_style(JsonStyle::Array, &output), output);
+
+        assert_eq!(run_json_style(JsonStyle::Array, ""), "[\n]\n");
+    }
+
+    #[test]
+    fn test_dump_array_elements() {
+        assert_eq!(
+            dump_array_elements("{\"id\":\"Q1\"},"),
+            vec!["{\"id\":\"Q1\"}"]
+        );
+        assert_eq!(dump_array_elements("\u{feff}[\r"), Vec::<&str>::new());
+        assert_eq!(dump_array_elements("]"), Vec::<&str>::new());
+        assert_eq!(
+            dump_array_elements("[{\"id\":\"Q1\"},"),
+            vec!["{\"id\":\"Q1\"}"]
+        );
+        assert_eq!(
+            dump_array_elements("{\"id\":\"Q2\"}]"),
+            vec!["{\"id\":\"Q2\"}"]
+        );
+        assert_eq!(
+            dump_array_elements("[{\"id\":\"Q1\",\"v\":\"},{\\\"\"}, {\"id\":\"Q2\"}]"),
+            vec!["{\"id\":\"Q1\",\"v\":\"},{\\\"\"}", "{\"id\":\"Q2\"}"]
+        );
+    }
+
+    #[test]
+    fn test_single_line_array_dump() {
+        let output = run_json_style(
+            JsonStyle::NdJson,
+            "[{\"id\":\"Q1\",\"type\":\"item\"},{\"id\":\"Q2\",\"type\":\"item\"}]",
+        );
+        assert_eq!(
+            output,
+            "{\"id\":\"Q1\",\"type\":\"item\"}\n{\"id\":\"Q2\",\"type\":\"item\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_serde_parser() {
+        let line = r#"{"id":"Q1","labels":{"en":{"language":"en","value":"Universe \u00e9"}},"claims":{"P1":[{"rank":"normal"}]}}"#;
+        let entity = JsonParser::Serde.parse_entity(line).unwrap();
+        assert_eq!(entity["labels"]["en"]["value"], "Universe \u{e9}");
+        assert!(JsonParser::Serde.parse_entity("{\"id\":").is_err());
+        assert!(JsonParser::Serde.is_available());
+        assert_eq!(JsonParser::Simd.is_available(), cfg!(feature = "simd"));
+
+        let filter = EntityFilter {
+            subject_filter: Some(["Q1".to_string()].into_iter().collect()),
+            ..Default::default()
+        };
+        assert_eq!(
+            JsonParser::Serde.line_rejection(line, &filter).unwrap(),
+            None
+        );
+        assert_eq!(
+            JsonParser::Serde
+                .line_rejection(r#"{"id":"Q2","claims":[]}"#, &filter)
+                .unwrap(),
+            Some(FilterStage::Subject)
+        );
+        assert!(JsonParser::parse("sonic").is_err());
+    }
+
+    // Without the simd feature `JsonParser::Simd` falls back to serde_json,
+    // so the comparison is only meaningful with simd-json compiled in
+    #[cfg(feature = "simd")]
+    #[test]
+    fn test_json_parsers_agree() {
+        let line = r#"{"id":"Q1","labels":{"en":{"language":"en","value":"Universe \u00e9"}},"claims":{"P1":[{"rank":"normal"}]}}"#;
+        let serde = JsonParser::Serde.parse_entity(line).unwrap();
+        assert_eq!(JsonParser::Simd.parse_entity(line).unwrap(), serde);
+        assert!(JsonParser::Simd.parse_entity("{\"id\":").is_err());
+
+        let filter = EntityFilter {
+            subject_filter: Some(["Q1".to_string()].into_iter().collect()),
+            ..Default::default()
+        };
+        assert_eq!(
+            JsonParser::Simd.line_rejection(line, &filter).unwrap(),
+            None
+        );
+        assert_eq!(
+            JsonParser::Simd
+                .line_rejection(r#"{"id":"Q2","claims":[]}"#, &filter)
+                .unwrap(),
+            Some(FilterStage::Subject)
+        );
+    }
+
+    #[test]
+    fn test_json_style_pretty() {
+        let output = run_json_style(JsonStyle::Pretty, "{\"id\":\"Q1\",\"type\":\"item\"}\n");
+        let parsed: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(parsed[0]["id"], "Q1");
+        assert!(output.contains("\n    \"id\": \"Q1\""));
+
+        assert!(JsonStyle::parse("yaml").is_err());
     }
 }
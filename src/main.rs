@@ -1,28 +1,140 @@
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::sync::Arc;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use thiserror::Error;
 
+mod apply_diff;
+mod atomic;
+mod bgzf;
+mod bulk;
+mod canonical;
+mod checkpoint;
 mod claim_parser;
 mod compression;
+mod datatype;
+mod dereference;
+mod diff;
+#[cfg(feature = "http")]
+mod download;
+mod dry_run;
+mod entity;
 mod filter;
+mod fixture;
+mod get;
+mod graphml;
+mod hierarchy;
+mod http;
+mod idset;
+mod index;
+mod ipc;
 mod json;
+mod malformed;
+mod memory;
+mod merge;
+mod mmap;
+mod naming;
 mod ntriples;
+mod partition;
+mod parts;
+mod pipeline;
+mod quads;
 mod rdf;
+mod reports;
+mod route;
+mod s3;
+mod sample;
+mod set_ops;
+mod signal;
+mod sort;
+mod split;
+mod sqlite;
+mod statement;
+mod stats;
+mod tabular;
+mod tar;
+mod terms;
+#[cfg(test)]
+mod test_util;
+mod truthify;
+mod turtle;
+mod unicode;
+mod unicode_tables;
+mod units;
+#[cfg(feature = "http")]
+mod update;
+mod validate;
 
 use compression::{
-    create_compressed_writer, create_input_reader, determine_compression, OUTPUT_BUFFER_SIZE,
+    create_compressed_writer, create_input_reader_from, determine_compression, open_output,
+    INPUT_BUFFER_SIZE, OUTPUT_BUFFER_SIZE,
 };
 use filter::EntityFilter;
 use json::filter_json_parallel;
-use rdf::{filter_rdf_parallel, OutputFormat};
+use rdf::{filter_rdf_parallel, OutputFormat, ProcessingOptions};
+
+/// Options that do not combine with --route/--route-file
+const ROUTE_CONFLICTS: [&str; 7] = [
+    "claim",
+    "claim_file",
+    "output",
+    "output_rejected",
+    "max_entities",
+    "checkpoint",
+    "resume",
+];
+
+/// Options that do not combine with --append
+const APPEND_CONFLICTS: [&str; 5] = [
+    "no_clobber",
+    "partition_by",
+    "shards",
+    "split_size",
+    "split_entities",
+];
+
+/// Options that do not combine with --sort-by-id
+const SORT_CONFLICTS: [&str; 10] = [
+    "stable_order",
+    "route",
+    "route_file",
+    "output_rejected",
+    "partition_by",
+    "shards",
+    "split_size",
+    "split_entities",
+    "checkpoint",
+    "resume",
+];
+
+/// Options that do not combine with --dereference-depth
+const DEREFERENCE_CONFLICTS: [&str; 6] = [
+    "route",
+    "route_file",
+    "output_rejected",
+    "invert_match",
+    "checkpoint",
+    "resume",
+];
+
+/// Options that do not combine with --split-size/--split-entities
+const SPLIT_CONFLICTS: [&str; 5] = [
+    "partition_by",
+    "shards",
+    "max_entities",
+    "checkpoint",
+    "resume",
+];
 
 #[derive(Parser, Debug)]
 #[command(name = "wikidata-werkzeug")]
 #[command(author, version, about = "Filter Wikidata dumps (RDF truthy and JSON formats)", long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Filter by claim (e.g., P31:Q5, P31:Q5,Q6256, P31:Q5&P18)
     /// Supports: AND (&), OR (|, or comma for values), NOT (~)
     #[arg(short, long)]
@@ -32,15 +144,39 @@ struct Args {
     #[arg(long)]
     claim_file: Option<String>,
 
+    /// Subclass edges from extract-hierarchy, for transitive claim values (P31:Q5*)
+    #[arg(long)]
+    hierarchy: Option<String>,
+
+    /// Build the subclass graph of this property (e.g. P279) for transitive
+    /// claim values in a first pass over the input, instead of --hierarchy
+    #[arg(long, requires = "input", conflicts_with = "hierarchy")]
+    closure_property: Option<String>,
+
+    /// Count only statements with at least one reference in claim filters
+    /// (JSON input, or full RDF dumps with --truthify)
+    #[arg(long)]
+    require_reference: bool,
+
+    /// Statement ranks claim filters count, comma-separated (preferred,
+    /// normal, deprecated); `P1082@preferred` sets them for one property
+    #[arg(long, default_value = "preferred,normal")]
+    ranks: String,
+
+    /// Do not count unknown (somevalue) and no values (novalue) as values in
+    /// claim filters, and drop such statements and qualifiers from the output
+    #[arg(long)]
+    exclude_special_values: bool,
+
     /// Entity type to filter: item, property, or both
     #[arg(short = 't', long, default_value = "both")]
     r#type: String,
 
-    /// Input format: auto, rdf, json (auto-detects from extension/content)
+    /// Input format: auto, rdf, turtle, json (auto-detects from extension/content)
     #[arg(short = 'f', long, default_value = "auto")]
     format: String,
 
-    /// Output format: same (preserve input format), ntriples, json
+    /// Output format: same (preserve input format), ntriples, nquads, trig, json, csv, tsv, arrow, es-bulk, graphml
     #[arg(short = 'o', long, default_value = "same")]
     output_format: String,
 
@@ -52,7 +188,17 @@ struct Args {
     #[arg(long, default_value = "false")]
     language_exact_match: bool,
 
-    /// Input file (stdin if not provided, supports .bz2, .gz, .lz4)
+    /// Keep `mul` labels, descriptions and aliases with --languages, and
+    /// count them as in every language for --require-label
+    #[arg(long)]
+    include_mul: bool,
+
+    /// Copy `mul` terms into each of --languages that has none of its own
+    #[arg(long, requires = "languages")]
+    materialize_mul: bool,
+
+    /// Input file, directory of dump parts or http(s):// URL (stdin if not
+    /// provided, supports .bz2, .gz, .lz4, .tar)
     #[arg()]
     input: Option<String>,
 
@@ -64,6 +210,70 @@ struct Args {
     #[arg(long, default_value = "none")]
     compress: String,
 
+    /// Fail instead of replacing existing output files
+    #[arg(long)]
+    no_clobber: bool,
+
+    /// Add to an existing --output file instead of replacing it; gzip/lz4
+    /// output gets new members, which lets --checkpoint/--resume continue it
+    #[arg(long, requires = "output", conflicts_with_all = APPEND_CONFLICTS)]
+    append: bool,
+
+    /// Also write the entities the filters reject to this file, in the same pass
+    #[arg(long, conflicts_with_all = ["checkpoint", "resume"])]
+    output_rejected: Option<String>,
+
+    /// Compression of --output-rejected: none, gzip, lz4 (auto-detected from its extension)
+    #[arg(long, default_value = "none")]
+    compress_rejected: String,
+
+    /// Write the entities matching a claim expression to their own file, as
+    /// NAME=EXPR=>FILE (e.g. 'humans=P31:Q5=>humans.ndjson.gz'). Repeat for
+    /// several outputs from one pass; the other filters and the output format
+    /// apply to every route, and a FILE extension naming another format is refused
+    #[arg(long, conflicts_with_all = ROUTE_CONFLICTS)]
+    route: Vec<String>,
+
+    /// File with one NAME=EXPR=>FILE route per line (# starts a comment)
+    #[arg(long, conflicts_with_all = ROUTE_CONFLICTS)]
+    route_file: Option<String>,
+
+    /// Write each entity to one file per value of this property (e.g. P31),
+    /// named by replacing {value} in --output (e.g. 'out/{value}.ndjson.gz')
+    #[arg(long, requires = "output", conflicts_with_all = ["max_entities", "checkpoint", "resume"])]
+    partition_by: Option<String>,
+
+    /// Distribute the entities over N files, named by replacing {shard} in
+    /// --output (e.g. 'out/part-{shard}.nt.gz')
+    #[arg(long, requires = "output", conflicts_with_all = ["partition_by", "max_entities", "checkpoint", "resume"])]
+    shards: Option<usize>,
+
+    /// How --shards assigns entities: round-robin, or hash (of the entity ID, stable across runs)
+    #[arg(long, default_value = "round-robin", requires = "shards")]
+    shard_by: String,
+
+    /// Roll over to a new numbered --output file before this size (e.g. 1G,
+    /// before compression); {part} in --output places the number
+    #[arg(long, requires = "output", conflicts_with_all = SPLIT_CONFLICTS)]
+    split_size: Option<String>,
+
+    /// Roll over to a new numbered --output file after this many entities
+    #[arg(long, requires = "output", conflicts_with_all = SPLIT_CONFLICTS)]
+    split_entities: Option<u64>,
+
+    /// Write the entities sorted by numeric ID (L, then P, then Q), spilling
+    /// sorted runs to temporary files and merging them at the end
+    #[arg(long, conflicts_with_all = SORT_CONFLICTS)]
+    sort_by_id: bool,
+
+    /// Entity output --sort-by-id holds in memory before spilling a run (e.g. 512M)
+    #[arg(long, default_value = "1G", requires = "sort_by_id")]
+    sort_memory: String,
+
+    /// Directory for the --sort-by-id runs (default: the system temporary directory)
+    #[arg(long, requires = "sort_by_id")]
+    sort_dir: Option<String>,
+
     /// Show progress info on stderr
     #[arg(short = 'p', long)]
     progress: bool,
@@ -72,21 +282,139 @@ struct Args {
     #[arg(long)]
     subject: Option<String>,
 
+    /// Keep only the subject entity IDs listed in a file, one per line
+    /// (may be .gz, .bz2 or .lz4 compressed)
+    #[arg(long, conflicts_with = "subject")]
+    subject_file: Option<String>,
+
+    /// Also write the entities the claims of matching entities point to, up
+    /// to N steps away, found in extra passes over the input
+    #[arg(long, requires = "input", conflicts_with_all = DEREFERENCE_CONFLICTS)]
+    dereference_depth: Option<u32>,
+
+    /// Also write the property entities the written entities use in their
+    /// statements, qualifiers and references, found in an extra pass
+    #[arg(long, requires = "input", conflicts_with_all = DEREFERENCE_CONFLICTS)]
+    include_properties_used: bool,
+
+    /// Keep only entities whose ID number is K modulo N (e.g., 3/16), one of N disjoint shards
+    #[arg(long)]
+    shard: Option<String>,
+
+    /// Keep only entities with a statement pointing to one of these entities
+    /// (comma-separated, e.g., Q937, or a file with one ID per line)
+    #[arg(long)]
+    references: Option<String>,
+
+    /// Keep only entities with a label containing this text, in the languages
+    /// of --languages if given
+    #[arg(long)]
+    label_contains: Option<String>,
+
+    /// Keep only entities with a label the regular expression matches, in the
+    /// languages of --languages if given
+    #[arg(long)]
+    label_regex: Option<String>,
+
+    /// Keep only entities with a description the regular expression matches,
+    /// in the languages of --languages if given
+    #[arg(long)]
+    description_regex: Option<String>,
+
+    /// Keep only entities with a label in these languages (comma-separated,
+    /// e.g., en,de); see --require-label-mode
+    #[arg(long)]
+    require_label: Option<String>,
+
+    /// Whether --require-label needs a label in any of the languages or in all
+    #[arg(long, default_value = "any", requires = "require_label")]
+    require_label_mode: String,
+
+    /// Keep only entities with a sitelink that has one of these badges
+    /// (comma-separated, e.g., Q17437796,Q17437798 for featured and good
+    /// articles; JSON input only)
+    #[arg(long)]
+    badge: Option<String>,
+
+    /// Sites the --badge sitelink must be on (comma-separated, e.g., enwiki)
+    #[arg(long, requires = "badge")]
+    badge_site: Option<String>,
+
+    /// Keep only entities with a sitelink on one of these sites
+    /// (comma-separated, e.g., enwiki,dewiki)
+    #[arg(long)]
+    sitelink: Option<String>,
+
+    /// Keep only entities with at least this many sitelinks, a cheap
+    /// notability proxy (RDF: the wikibase:sitelinks count of EntityData)
+    #[arg(long)]
+    min_sitelinks: Option<usize>,
+
+    /// Keep only entities last modified at or after this date or time (e.g.,
+    /// 2024-01-01 or 2024-01-01T12:00:00Z; RDF: schema:dateModified of EntityData)
+    #[arg(long)]
+    modified_since: Option<String>,
+
+    /// Keep only entities with at least this many statements, to drop stubs
+    /// (RDF: the wikibase:statements count of EntityData)
+    #[arg(long)]
+    min_statements: Option<usize>,
+
+    /// Keep only entities with at most this many statements, e.g. to isolate
+    /// huge entities
+    #[arg(long)]
+    max_statements: Option<usize>,
+
     /// Keep only triples with specified properties (comma-separated, e.g., P31,P279)
     #[arg(long)]
     property: Option<String>,
 
-    /// Number of threads for parallel processing (default: number of CPUs)
+    /// Keep only the properties listed in a file, one per line (may be compressed)
+    #[arg(long, conflicts_with = "property")]
+    property_file: Option<String>,
+
+    /// Keep only the claims of properties of these datatypes (comma-separated,
+    /// e.g., external-id,url)
+    #[arg(long)]
+    property_datatype: Option<String>,
+
+    /// Drop the claims of properties of these datatypes (comma-separated)
+    #[arg(long, conflicts_with = "property_datatype")]
+    omit_property_datatype: Option<String>,
+
+    /// File of `P212 external-id` lines giving the property datatypes for RDF
+    /// input, instead of reading them in a first pass
+    #[arg(long)]
+    property_datatypes: Option<String>,
+
+    /// Number of filter worker threads (default: number of CPUs)
     #[arg(long)]
     threads: Option<usize>,
 
+    /// Maximum number of batches queued between reader and writer (default: 2 x threads)
+    #[arg(long)]
+    queue_depth: Option<usize>,
+
+    /// Write batches as soon as a worker finishes them instead of in input order
+    #[arg(long)]
+    unordered: bool,
+
+    /// Guarantee that entities are written in exactly the input order; the
+    /// parts of a dump directory are then read one after the other
+    #[arg(long, conflicts_with = "unordered")]
+    stable_order: bool,
+
     /// Keep only specified entity attributes (comma-separated)
-    /// Valid attributes: id, type, labels, descriptions, aliases, claims, sitelinks
+    /// Valid attributes: id, type, labels, descriptions, aliases, claims, sitelinks,
+    /// lastrevid, modified, pageid, ns, title, or paths to keys such as
+    /// claims.P31, labels.en, sitelinks.enwiki
     #[arg(long)]
     keep: Option<String>,
 
     /// Omit specified entity attributes (comma-separated)
-    /// Valid attributes: id, type, labels, descriptions, aliases, claims, sitelinks
+    /// Valid attributes: id, type, labels, descriptions, aliases, claims, sitelinks,
+    /// lastrevid, modified, pageid, ns, title, or paths to keys such as
+    /// claims.P31, labels.en, sitelinks.enwiki
     #[arg(long)]
     omit: Option<String>,
 
@@ -94,6 +422,16 @@ struct Args {
     #[arg(long)]
     batch_size: Option<usize>,
 
+    /// Save the input position and output length to this file every 30 seconds
+    /// and at the end, for --resume (uncompressed or BGZF input, uncompressed output)
+    #[arg(long, conflicts_with = "unordered")]
+    checkpoint: Option<String>,
+
+    /// Continue from a checkpoint file: seek the input to its position and append
+    /// to the output. Keeps updating the file unless --checkpoint names another
+    #[arg(long, conflicts_with_all = ["unordered", "skip_lines", "skip_entities"])]
+    resume: Option<String>,
+
     /// Skip the first N lines before processing (useful for resuming interrupted jobs)
     #[arg(long, default_value = "0")]
     skip_lines: u64,
@@ -101,6 +439,186 @@ struct Args {
     /// Stop processing after N lines (0 = no limit)
     #[arg(long, default_value = "0")]
     max_lines: u64,
+
+    /// Skip the first N entities of the input before filtering
+    #[arg(long, default_value = "0")]
+    skip_entities: u64,
+
+    /// Stop after writing N matching entities (0 = no limit)
+    #[arg(long, default_value = "0")]
+    max_entities: u64,
+
+    /// Abort on the first malformed input line, naming its line number and
+    /// content. By default malformed lines are dropped and counted
+    #[arg(long, conflicts_with = "skip_invalid")]
+    strict: bool,
+
+    /// Skip malformed input lines and keep going. This is the default; the
+    /// flag makes it explicit and conflicts with --strict
+    #[arg(long)]
+    skip_invalid: bool,
+
+    /// Write every skipped malformed line to this file, one per line as
+    /// line number, error and content separated by tabs
+    #[arg(long, conflicts_with = "strict")]
+    errors_output: Option<String>,
+
+    /// Output the entities the filters reject instead of those they match
+    /// (like grep -v)
+    #[arg(short = 'v', long)]
+    invert_match: bool,
+
+    /// Print to stderr why each entity matched or was rejected, clause by
+    /// clause. Runs a single worker so explanations come in input order; meant
+    /// for --subject or small inputs
+    #[arg(long)]
+    explain: bool,
+
+    /// Print the parsed filter tree, the detected formats and compression,
+    /// then exit without reading the input
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Soft memory (RSS) budget, e.g. 4G or 512M. When approached, batches shrink
+    /// and pending output is drained before reading more input
+    #[arg(long)]
+    memory_budget: Option<String>,
+
+    /// Upper bound for resident memory, e.g. 2G. Batch sizes, queue depth and
+    /// the write buffer are sized to fit, the reader waits while too many
+    /// input bytes are in flight, and output is flushed early when RSS
+    /// approaches the limit
+    #[arg(long)]
+    max_memory: Option<String>,
+
+    /// Memory-map uncompressed N-Triples/JSON input and split it into byte
+    /// ranges for the worker threads instead of reading it line by line
+    #[arg(long)]
+    mmap: bool,
+
+    /// Write run statistics (lines, entities per type, per-filter matches,
+    /// triples, bytes, wall time) as JSON to this file
+    #[arg(long)]
+    stats_output: Option<String>,
+
+    /// Reconnects with a Range request after transient network errors on
+    /// URL input, waiting 1s, 2s, 4s, ... in between
+    #[arg(long, default_value_t = http::DEFAULT_RETRIES)]
+    http_retries: u32,
+
+    /// Input buffer size, e.g. 1M or 64k (default: 8k)
+    #[arg(long)]
+    read_buffer: Option<String>,
+
+    /// Output buffer size, e.g. 64M or 256k (default: 8M)
+    #[arg(long)]
+    write_buffer: Option<String>,
+
+    /// Collapse full-dump statement nodes into truthy wdt:/wdtn: triples, keeping
+    /// only the best non-deprecated rank per property (RDF input only)
+    #[arg(long)]
+    truthify: bool,
+
+    /// Write only the claim and label, description and alias triples of RDF
+    /// output, dropping EntityData metadata, sitelinks, types and the dump header
+    #[arg(long, alias = "strip-meta")]
+    claims_only: bool,
+
+    /// Keep only the statements of the best rank per property: preferred ones
+    /// where there are any, else normal ones, as in the truthy dumps (JSON input only)
+    #[arg(long)]
+    best_rank_only: bool,
+
+    /// Convert quantities to other units by the factors in a file of
+    /// `Q174728 0.01 Q11573` lines (JSON input only)
+    #[arg(long)]
+    normalize_units: Option<String>,
+
+    /// Write labels, descriptions, aliases and string values in a Unicode
+    /// normalization form: nfc or nfd
+    #[arg(long)]
+    normalize_unicode: Option<String>,
+
+    /// Drop the qualifiers of every statement from the output (JSON input only)
+    #[arg(long)]
+    omit_qualifiers: bool,
+
+    /// Keep only the qualifiers of these comma-separated properties, e.g.
+    /// P580,P582 (JSON input only)
+    #[arg(long, conflicts_with = "omit_qualifiers")]
+    keep_qualifiers: Option<String>,
+
+    /// Drop the references of every statement from the output (JSON input only)
+    #[arg(long)]
+    omit_references: bool,
+
+    /// Columns for csv/tsv/arrow output (comma-separated, e.g., id,labels.en,descriptions.en,P31,P625)
+    #[arg(long)]
+    columns: Option<String>,
+
+    /// Separator for multi-valued csv/tsv cells (claims, aliases)
+    #[arg(long, default_value = "|")]
+    value_separator: String,
+
+    /// Layout of JSON output: ndjson (one entity per line), array (like the official
+    /// dump), pretty (pretty-printed array)
+    #[arg(long, default_value = "ndjson")]
+    json_style: String,
+
+    /// JSON parser: serde, or simd (needs a build with the `simd` feature)
+    #[arg(long, default_value = "serde")]
+    parser: String,
+
+    /// Deterministic output for diffing: sorted statements and aliases (JSON),
+    /// sorted triples per entity (RDF)
+    #[arg(long)]
+    canonical: bool,
+
+    /// Index name for es-bulk output (placeholders: {id}, {type})
+    #[arg(long, default_value = "wikidata")]
+    es_index: String,
+
+    /// Document ID for es-bulk output (placeholders: {id}, {type})
+    #[arg(long, default_value = "{id}")]
+    es_id: String,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Extract entities plus their immediate dependencies into a small fixture dump
+    MakeFixture(fixture::FixtureArgs),
+    /// Write filtered entities into a queryable SQLite database
+    ToSqlite(sqlite::SqliteArgs),
+    /// Check a dump for malformed lines, interleaved entities and truncation
+    /// without writing any output
+    Validate(validate::ValidateArgs),
+    /// Download the current file of a dump and verify its published checksums
+    #[cfg(feature = "http")]
+    Download(download::DownloadArgs),
+    /// Follow the Wikimedia EventStreams change feed and append the new
+    /// revisions of matching entities to an extract
+    #[cfg(feature = "http")]
+    Update(update::UpdateArgs),
+    /// Merge incremental dumps into a filtered NDJSON extract
+    ApplyDiff(apply_diff::ApplyDiffArgs),
+    /// Report added, removed and changed entities between two dumps or extracts
+    Diff(diff::DiffArgs),
+    /// Combine extracts into one, keeping one version of each entity
+    Merge(merge::MergeArgs),
+    /// Union, intersection or difference of two extracts by entity ID
+    Set(set_ops::SetArgs),
+    /// Write a sidecar index of the byte offset of every entity in a dump
+    Index(index::IndexArgs),
+    /// Print single entities of an indexed dump without scanning it
+    Get(get::GetArgs),
+    /// Cut a dump into parts at entity boundaries, each with the dump header
+    Split(split::SplitArgs),
+    /// Write a reproducible random sample of the matching entities
+    Sample(sample::SampleArgs),
+    /// Summary reports of a dump, such as its property usage
+    Stats(reports::StatsArgs),
+    /// Write the subclass tree under given classes, as edges or nested JSON
+    ExtractHierarchy(hierarchy::HierarchyArgs),
 }
 
 #[derive(Error, Debug)]
@@ -113,48 +631,325 @@ pub enum FilterError {
     InvalidClaim(String),
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// Default batch size for an input format (1000 for JSON, 100 for RDF)
+pub fn default_batch_size(format: &str) -> usize {
+    match format {
+        "json" | "ndjson" => 1000,
+        _ => 100,
+    }
+}
+
+/// Run the filter loop matching the input format
+///
+/// A batch size of 0 in `options` selects the default for the format. Each
+/// route gets the entities its own filter selects.
+pub fn run_filter<R: BufRead + Send, W: Write>(
+    reader: R,
+    format: &str,
+    output: &mut W,
+    routes: &mut [route::Route],
+    filter: &Arc<EntityFilter>,
+    options: &ProcessingOptions,
+) -> Result<(), FilterError> {
+    let mut options = options.clone();
+    if options.batch_size == 0 {
+        options.batch_size = default_batch_size(format);
+    }
+
+    match format {
+        "rdf" | "ntriples" | "nt" => filter_rdf_parallel(reader, output, routes, filter, &options),
+        "turtle" | "ttl" => {
+            // Turtle is converted to N-Triples on the fly
+            let reader = BufReader::new(turtle::TurtleReader::new(reader));
+            filter_rdf_parallel(reader, output, routes, filter, &options)
+        }
+        "json" | "ndjson" => filter_json_parallel(reader, output, routes, filter, &options),
+        _ => {
+            eprintln!("Unknown format: {}, assuming RDF", format);
+            filter_rdf_parallel(reader, output, routes, filter, &options)
+        }
+    }
+}
+
+/// Run the filter on a memory-mapped N-Triples or JSON dump
+pub fn run_filter_mmap<W: Write>(
+    data: &[u8],
+    format: &str,
+    output: &mut W,
+    routes: &mut [route::Route],
+    filter: &Arc<EntityFilter>,
+    options: &ProcessingOptions,
+) -> Result<(), FilterError> {
+    let mut options = options.clone();
+    if options.batch_size == 0 {
+        options.batch_size = default_batch_size(format);
+    }
+
+    match format {
+        "json" | "ndjson" => json::filter_json_mmap(data, output, routes, filter, &options),
+        _ => rdf::filter_rdf_mmap(data, output, routes, filter, &options),
+    }
+}
+
+/// Run the filter on the JSON parts of a dump directory, one reader thread per part
+pub fn run_filter_parts<W: Write>(
+    parts: &[String],
+    buffer_size: usize,
+    bytes_read: Arc<std::sync::atomic::AtomicU64>,
+    output: &mut W,
+    routes: &mut [route::Route],
+    filter: &Arc<EntityFilter>,
+    options: &ProcessingOptions,
+) -> Result<(), FilterError> {
+    let mut options = options.clone();
+    if options.batch_size == 0 {
+        options.batch_size = default_batch_size("json");
+    }
+    json::filter_json_files(
+        parts,
+        buffer_size,
+        bytes_read,
+        output,
+        routes,
+        filter,
+        &options,
+    )
+}
+
+/// The subclass graph and path edges the claim filters are resolved against
+///
+/// `--hierarchy` is read from its file; the `--closure-property` graph and the
+/// edges of property paths come from one pass over the input, which is
+/// skipped with `--dry-run`.
+fn build_claim_index<'a>(
+    args: &Args,
+    filters: impl Iterator<Item = &'a filter::ClaimFilter>,
+) -> Result<hierarchy::ClaimIndex, FilterError> {
+    let path_properties: BTreeSet<String> = filters.flat_map(|f| f.path_properties()).collect();
+    let closure_property = args.closure_property.as_deref().map(str::trim);
+    let mut properties = path_properties.clone();
+    properties.extend(closure_property.map(str::to_string));
+    let properties: Vec<String> = properties.into_iter().collect();
+
+    let mut edges = if properties.is_empty() || args.dry_run {
+        properties
+            .iter()
+            .map(|property| (property.clone(), hierarchy::Hierarchy::default()))
+            .collect()
+    } else {
+        let input = match args.input.as_deref() {
+            Some(input) if !parts::is_dump_directory(input) => input,
+            _ => {
+                return Err(FilterError::Parse(
+                    "Property paths and --closure-property read a dump file or URL twice"
+                        .to_string(),
+                ))
+            }
+        };
+        if args.progress {
+            eprintln!("Pass 1: collecting {} edges...", properties.join(", "));
+        }
+        let edges = hierarchy::scan_edges(
+            input,
+            &args.format,
+            &properties,
+            args.progress,
+            args.http_retries,
+        )?;
+        if args.progress {
+            eprintln!("Pass 2: filtering...");
+        }
+        edges
+    };
+
+    let hierarchy = match (&args.hierarchy, closure_property) {
+        (Some(path), _) => Some(hierarchy::Hierarchy::load(path)?),
+        (None, Some(property)) if path_properties.contains(property) => {
+            edges.get(property).cloned()
+        }
+        (None, Some(property)) => edges.remove(property),
+        (None, None) => None,
+    };
+    Ok(hierarchy::ClaimIndex { hierarchy, edges })
+}
+
+/// The datatypes of the properties for --property-datatype on RDF input
+///
+/// They come from --property-datatypes, or from the property entities in a
+/// first pass over the input, which is skipped with `--dry-run`. JSON snaks
+/// name their datatype, so JSON input needs neither.
+fn property_datatypes(args: &Args) -> Result<HashMap<String, String>, FilterError> {
+    if let Some(ref path) = args.property_datatypes {
+        return datatype::read_datatype_file(path);
+    }
+    let format = match (args.format.as_str(), args.input.as_deref()) {
+        ("auto", Some(input)) => compression::detect_format_from_path(input),
+        (format, _) => format.to_string(),
+    };
+    if matches!(format.as_str(), "json" | "ndjson") || args.dry_run {
+        return Ok(HashMap::new());
+    }
+    let input = match args.input.as_deref() {
+        Some(input) if !parts::is_dump_directory(input) => input,
+        _ => {
+            return Err(FilterError::Parse(
+                "--property-datatype on RDF input reads a dump file or URL twice, or needs --property-datatypes"
+                    .to_string(),
+            ))
+        }
+    };
+    if args.progress {
+        eprintln!("Pass 1: collecting property datatypes...");
+    }
+    let properties = datatype::scan(input, &args.format, args.progress, args.http_retries)?;
+    if args.progress {
+        eprintln!("Pass 2: filtering...");
+    }
+    Ok(properties)
 }
 
 fn main() -> Result<(), FilterError> {
-    let args = Args::parse();
+    let mut args = Args::parse();
 
-    // Configure rayon thread pool if specified
-    if let Some(threads) = args.threads {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(threads)
-            .build_global()
-            .ok();
+    if let Some(command) = args.command {
+        return match command {
+            Command::MakeFixture(fixture_args) => fixture::run(fixture_args),
+            Command::ToSqlite(sqlite_args) => sqlite::run(sqlite_args),
+            Command::Validate(validate_args) => validate::run(validate_args),
+            #[cfg(feature = "http")]
+            Command::Download(download_args) => download::run(download_args),
+            #[cfg(feature = "http")]
+            Command::Update(update_args) => update::run(update_args),
+            Command::ApplyDiff(apply_diff_args) => apply_diff::run(apply_diff_args),
+            Command::Diff(diff_args) => diff::run(diff_args),
+            Command::Merge(merge_args) => merge::run(merge_args),
+            Command::Set(set_args) => set_ops::run(set_args),
+            Command::Index(index_args) => index::run(index_args),
+            Command::Get(get_args) => get::run(get_args),
+            Command::Split(split_args) => split::run(split_args),
+            Command::Sample(sample_args) => sample::run(sample_args),
+            Command::Stats(stats_args) => reports::run(stats_args),
+            Command::ExtractHierarchy(hierarchy_args) => hierarchy::run(hierarchy_args),
+        };
     }
+    let started = std::time::Instant::now();
+    let interrupted = signal::install_interrupt_handler()?;
+    let snapshot_requested = signal::install_snapshot_handler()?;
 
     // Build filters
-    let claim_filter = match (&args.claim, &args.claim_file) {
+    let mut claim_filter = match (&args.claim, &args.claim_file) {
         (Some(_), Some(_)) => {
             return Err(FilterError::InvalidClaim(
                 "Cannot specify both --claim and --claim-file".to_string(),
             ));
         }
-        (Some(ref claim_str), None) => Some(claim_parser::parse_claim_filter(claim_str)?),
+        (Some(ref claim_str), None) => Some(claim_parser::parse_claim_expression(claim_str)?),
         (None, Some(ref path)) => {
-            let claim_str = std::fs::read_to_string(path).map_err(|e| FilterError::Io(e))?;
+            let claim_str = std::fs::read_to_string(path).map_err(FilterError::Io)?;
             let claim_str = claim_str.trim();
             if claim_str.is_empty() {
                 None
             } else {
-                Some(claim_parser::parse_claim_filter(claim_str)?)
+                Some(claim_parser::parse_claim_expression(claim_str)?)
             }
         }
         (None, None) => None,
     };
 
-    let subject_filter: Option<HashSet<String>> = args
-        .subject
-        .as_ref()
-        .map(|s| s.split(',').map(|id| id.trim().to_string()).collect());
+    let mut route_specs = route::parse_routes(&args.route, args.route_file.as_deref())?;
 
-    let property_filter: Option<HashSet<String>> = args
-        .property
-        .as_ref()
-        .map(|s| s.split(',').map(|id| id.trim().to_string()).collect());
+    // Transitive values and property paths may need a first pass over the input
+    let claim_index = build_claim_index(
+        &args,
+        claim_filter
+            .iter()
+            .chain(route_specs.iter().map(|spec| &spec.claim_filter)),
+    )?;
+    if let Some(ref mut filter) = claim_filter {
+        filter.resolve(&claim_index)?;
+    }
+    for spec in &mut route_specs {
+        spec.claim_filter.resolve(&claim_index)?;
+    }
+    drop(claim_index);
+
+    let ranks = statement::RankSet::parse(&args.ranks)?;
+    if ranks != statement::RankSet::default() {
+        claim_filter = claim_filter.map(|f| f.with_ranks(ranks)).transpose()?;
+        for spec in &mut route_specs {
+            spec.claim_filter = spec.claim_filter.clone().with_ranks(ranks)?;
+        }
+    }
+
+    if args.exclude_special_values {
+        claim_filter = claim_filter.map(filter::ClaimFilter::exclude_special_values);
+        for spec in &mut route_specs {
+            spec.claim_filter = spec.claim_filter.clone().exclude_special_values();
+        }
+    }
+
+    if args.require_reference {
+        if claim_filter.is_none() && route_specs.is_empty() {
+            return Err(FilterError::InvalidClaim(
+                "--require-reference needs --claim, --claim-file or --route".to_string(),
+            ));
+        }
+        claim_filter = claim_filter
+            .map(filter::ClaimFilter::require_reference)
+            .transpose()?;
+        for spec in &mut route_specs {
+            spec.claim_filter = spec.claim_filter.clone().require_reference()?;
+        }
+    }
+
+    // Fill in {date}, {filter} and {type}; a route's {filter} is its name
+    let names = naming::OutputNames::new(
+        args.claim.as_deref(),
+        args.claim_file.as_deref(),
+        &args.r#type,
+    );
+    args.output = args.output.map(|path| names.expand(&path, None));
+    args.output_rejected = args.output_rejected.map(|path| names.expand(&path, None));
+    for spec in &mut route_specs {
+        spec.path = names.expand(&spec.path, Some(&spec.name));
+        partition::check_placeholders(&spec.path)?;
+    }
+    if let Some(ref path) = args.output_rejected {
+        partition::check_placeholders(path)?;
+    }
+
+    let subject_filter: Option<idset::IdSet> = match args.subject_file {
+        Some(ref path) => Some(filter::read_id_file(path)?),
+        None => args
+            .subject
+            .as_ref()
+            .map(|s| s.split(',').map(|id| id.trim().to_string()).collect()),
+    };
+
+    let property_filter: Option<HashSet<String>> = match args.property_file {
+        Some(ref path) => Some(filter::read_id_file(path)?),
+        None => args
+            .property
+            .as_ref()
+            .map(|s| s.split(',').map(|id| id.trim().to_string()).collect()),
+    };
+
+    let datatype_filter = match (&args.property_datatype, &args.omit_property_datatype) {
+        (Some(list), _) => Some(datatype::DatatypeFilter::parse(list, false)?),
+        (None, Some(list)) => Some(datatype::DatatypeFilter::parse(list, true)?),
+        (None, None) => None,
+    };
+    let datatype_filter = match datatype_filter {
+        Some(mut filter) => {
+            filter.properties = property_datatypes(&args)?;
+            Some(filter)
+        }
+        None => None,
+    };
 
     let language_filter: Option<HashSet<String>> = args
         .languages
@@ -165,33 +960,239 @@ fn main() -> Result<(), FilterError> {
     let (keep_attributes, omit_attributes) =
         filter::parse_attribute_filters(args.keep.as_deref(), args.omit.as_deref())?;
 
+    let table = tabular::TableSpec::parse(
+        args.columns.as_deref().unwrap_or(tabular::DEFAULT_COLUMNS),
+        &args.value_separator,
+    )?;
+
+    let json_style = json::JsonStyle::parse(&args.json_style)?;
+
+    let mut json_parser = json::JsonParser::parse(&args.parser)?;
+    if !json_parser.is_available() {
+        eprintln!(
+            "Warning: --parser {} is not available in this build, using serde",
+            args.parser
+        );
+        json_parser = json::JsonParser::Serde;
+    }
+
+    let memory_budget = args
+        .memory_budget
+        .as_deref()
+        .map(memory::parse_byte_size)
+        .transpose()?;
+
+    let read_buffer = args
+        .read_buffer
+        .as_deref()
+        .map(memory::parse_buffer_size)
+        .transpose()?
+        .unwrap_or(INPUT_BUFFER_SIZE);
+    let mut write_buffer = args
+        .write_buffer
+        .as_deref()
+        .map(memory::parse_buffer_size)
+        .transpose()?
+        .unwrap_or(OUTPUT_BUFFER_SIZE);
+    let max_memory = args
+        .max_memory
+        .as_deref()
+        .map(memory::parse_byte_size)
+        .transpose()?;
+
     let entity_filter = Arc::new(EntityFilter {
         claim_filter,
         subject_filter,
+        shard: args
+            .shard
+            .as_deref()
+            .map(filter::Shard::parse)
+            .transpose()?,
+        reference_filter: args
+            .references
+            .as_deref()
+            .map(filter::parse_id_list)
+            .transpose()?,
+        term_filter: terms::TermFilter::new(
+            args.label_contains.as_deref(),
+            args.label_regex.as_deref(),
+            args.description_regex.as_deref(),
+            args.require_label.as_deref(),
+            &args.require_label_mode,
+        )?,
+        badge_filter: args
+            .badge
+            .as_deref()
+            .map(|badges| filter::BadgeFilter::parse(badges, args.badge_site.as_deref()))
+            .transpose()?,
+        sitelink_sites: args
+            .sitelink
+            .as_ref()
+            .map(|s| s.split(',').map(|site| site.trim().to_string()).collect()),
+        min_sitelinks: args.min_sitelinks,
+        modified_since: args
+            .modified_since
+            .as_deref()
+            .map(filter::parse_timestamp)
+            .transpose()?,
+        min_statements: args.min_statements,
+        max_statements: args.max_statements,
         property_filter,
         language_filter,
         language_include_subvariants: !args.language_exact_match,
+        include_mul: args.include_mul || args.materialize_mul,
+        materialize_mul: args.materialize_mul,
         entity_type: args.r#type.clone(),
         keep_attributes,
         omit_attributes,
+        best_rank_only: args.best_rank_only,
+        exclude_special_values: args.exclude_special_values,
+        datatype_filter,
+        unit_conversions: args
+            .normalize_units
+            .as_deref()
+            .map(units::UnitConversions::read)
+            .transpose()?,
+        unicode_form: args
+            .normalize_unicode
+            .as_deref()
+            .map(unicode::NormalizationForm::parse)
+            .transpose()?,
+        omit_qualifiers: args.omit_qualifiers,
+        keep_qualifiers: args
+            .keep_qualifiers
+            .as_ref()
+            .map(|s| s.split(',').map(|id| id.trim().to_string()).collect()),
+        omit_references: args.omit_references,
+        claims_only: args.claims_only,
+        invert_match: args.invert_match,
     });
 
+    // The output pass writes the matches and the entities they reference
+    let entity_filter = match args.input.as_deref() {
+        Some(input)
+            if (args.dereference_depth.is_some() || args.include_properties_used)
+                && !args.dry_run =>
+        {
+            if parts::is_dump_directory(input) {
+                return Err(FilterError::Parse(
+                    "Referenced entities are found by reading a dump file or URL several times"
+                        .to_string(),
+                ));
+            }
+            let passes = dereference::Passes {
+                input,
+                format: &args.format,
+                include_properties: args.include_properties_used,
+                show_progress: args.progress,
+                http_retries: args.http_retries,
+            };
+            Arc::new(passes.dereference(&entity_filter, args.dereference_depth.unwrap_or(0))?)
+        }
+        _ => entity_filter,
+    };
+
+    let resume = args
+        .resume
+        .as_deref()
+        .map(checkpoint::Checkpoint::load)
+        .transpose()?;
+    if resume.as_ref().is_some_and(|c| c.complete) {
+        eprintln!(
+            "The run of checkpoint {} is already complete",
+            args.resume.as_deref().unwrap_or_default()
+        );
+        return Ok(());
+    }
+    // The file the run writes: the temporary one, or the output itself with --append
+    let written_path = |path: &str| {
+        if args.append {
+            path.to_string()
+        } else {
+            atomic::pending_path(path)
+        }
+    };
+    if let Some(ref resume) = resume {
+        resume.check_paths(
+            args.input.as_deref().unwrap_or("-"),
+            &args.output.as_deref().map_or("-".to_string(), written_path),
+        )?;
+    }
+    let checkpoint_path = args.checkpoint.clone().or(args.resume.clone());
+    // Checkpoints end the gzip/LZ4 member of an --append output
+    let member_boundary = compression::MemberBoundary::default();
+    // A directory holds the parts of a pre-chunked dump
+    let parts = args
+        .input
+        .as_deref()
+        .filter(|path| parts::is_dump_directory(path))
+        .map(parts::list_parts)
+        .transpose()?;
+    let bgzf_blocks = match args.input.as_deref() {
+        Some(path) if checkpoint_path.is_some() && path.to_lowercase().ends_with(".gz") => {
+            if !bgzf::is_bgzf(path, args.http_retries)? {
+                return Err(FilterError::Parse(format!(
+                    "--checkpoint needs uncompressed or BGZF input, {} is plain gzip",
+                    path
+                )));
+            }
+            Some(bgzf::BlockIndex::default())
+        }
+        _ => None,
+    };
+
     // Determine input format and create reader
+    let stats = Arc::new(stats::RunStats::default());
     let (reader, detected_format): (Box<dyn BufRead + Send>, String) = match &args.input {
-        Some(path) => create_input_reader(path, &args.format)?,
+        Some(_) if parts.is_some() => (
+            Box::new(io::empty()),
+            parts::format(&args.format, parts.as_deref().unwrap_or_default()),
+        ),
+        Some(path) if checkpoint_path.is_some() => {
+            let reader = checkpoint::open_input(
+                path,
+                resume.as_ref(),
+                bgzf_blocks.as_ref(),
+                stats.bytes_read.clone(),
+                read_buffer,
+                args.http_retries,
+            )?;
+            let format = if args.format == "auto" {
+                compression::detect_format_from_path(path)
+            } else {
+                args.format.clone()
+            };
+            (reader, format)
+        }
+        Some(path) => {
+            let file = stats::Counting::new(
+                http::open_source(path, 0, args.http_retries)?,
+                stats.bytes_read.clone(),
+            );
+            create_input_reader_from(file, path, &args.format, read_buffer)
+        }
         None => {
-            let stdin = io::stdin();
+            let stdin = stats::Counting::new(io::stdin(), stats.bytes_read.clone());
             let format = if args.format == "auto" {
                 "rdf".to_string()
             } else {
                 args.format.clone()
             };
-            (Box::new(BufReader::new(stdin)), format)
+            (
+                Box::new(BufReader::with_capacity(read_buffer, stdin)),
+                format,
+            )
         }
     };
 
+    if args.truthify && matches!(detected_format.as_str(), "json" | "ndjson") {
+        eprintln!("Warning: --truthify only applies to RDF input, ignoring it for JSON");
+    }
+
     // Determine compression from --compress or output file extension
     let compression = determine_compression(&args.compress, args.output.as_deref());
+    let rejected_compression =
+        determine_compression(&args.compress_rejected, args.output_rejected.as_deref());
 
     let skip_lines = args.skip_lines;
     let max_lines = if args.max_lines == 0 {
@@ -200,6 +1201,12 @@ fn main() -> Result<(), FilterError> {
         args.max_lines
     };
 
+    let max_entities = if args.max_entities == 0 {
+        u64::MAX
+    } else {
+        args.max_entities
+    };
+
     if skip_lines > 0 && args.progress {
         eprintln!("Skipping first {} lines...", skip_lines);
     }
@@ -208,6 +1215,13 @@ fn main() -> Result<(), FilterError> {
     let output_format = match args.output_format.as_str() {
         "json" => OutputFormat::Json,
         "ntriples" => OutputFormat::NTriples,
+        "nquads" => OutputFormat::NQuads,
+        "trig" => OutputFormat::TriG,
+        "csv" => OutputFormat::Csv,
+        "tsv" => OutputFormat::Tsv,
+        "arrow" => OutputFormat::Arrow,
+        "es-bulk" => OutputFormat::EsBulk,
+        "graphml" => OutputFormat::GraphMl,
         "same" => {
             // Preserve input format
             match detected_format.as_str() {
@@ -218,69 +1232,373 @@ fn main() -> Result<(), FilterError> {
         _ => OutputFormat::NTriples,
     };
 
-    // Create output writer with optional compression
-    let output_writer: Box<dyn Write> = match &args.output {
-        Some(path) => {
-            let file = std::fs::File::create(path)?;
-            create_compressed_writer(file, &compression)
+    let mut pipeline = pipeline::PipelineConfig::new(args.threads, args.queue_depth);
+    // --stable-order conflicts with --unordered and --sort-by-id
+    pipeline.ordered = !args.unordered;
+    if args.explain {
+        pipeline.workers = 1;
+    }
+    let mut batch_size = args.batch_size.unwrap_or(0);
+    if let Some(limit) = max_memory {
+        let plan = memory::MemoryPlan::new(
+            limit,
+            args.batch_size
+                .unwrap_or_else(|| default_batch_size(&detected_format)),
+            pipeline.workers,
+            pipeline.queue_depth,
+            read_buffer,
+            write_buffer,
+        )?;
+        if args.progress {
+            eprintln!(
+                "Memory limit {} MiB: batch size {}, queue depth {}, write buffer {} KiB, \
+                 {} MiB of input in flight",
+                limit >> 20,
+                plan.batch_size,
+                plan.queue_depth,
+                plan.write_buffer >> 10,
+                plan.in_flight_bytes >> 20
+            );
         }
-        None => {
-            let stdout = io::stdout();
-            create_compressed_writer(stdout, &compression)
+        batch_size = plan.batch_size;
+        pipeline.queue_depth = plan.queue_depth;
+        pipeline.max_bytes = Some(plan.in_flight_bytes);
+        write_buffer = plan.write_buffer;
+    }
+
+    let partition = match (&args.partition_by, args.shards, &args.output) {
+        (Some(property), _, Some(template)) => {
+            Some(partition::Partition::new(property, template, write_buffer)?)
+        }
+        (None, Some(count), Some(template)) => Some(partition::Partition::shards(
+            count,
+            partition::ShardBy::parse(&args.shard_by)?,
+            template,
+            write_buffer,
+        )?),
+        (None, None, Some(output))
+            if args.split_size.is_some() || args.split_entities.is_some() =>
+        {
+            let max_bytes = match args.split_size {
+                Some(_) if matches!(output_format, OutputFormat::Arrow | OutputFormat::GraphMl) => {
+                    return Err(FilterError::Parse(format!(
+                        "--split-size cannot cut {} output, use --split-entities",
+                        output_format.name()
+                    )));
+                }
+                Some(ref size) => memory::parse_byte_size(size)?,
+                None => u64::MAX,
+            };
+            let max_entities = args.split_entities.unwrap_or(u64::MAX);
+            if max_bytes == 0 || max_entities == 0 {
+                return Err(FilterError::Parse(
+                    "--split-size and --split-entities must be positive".to_string(),
+                ));
+            }
+            let limits = partition::SplitLimits {
+                max_bytes,
+                max_entities,
+            };
+            Some(partition::Partition::split(limits, output, write_buffer))
+        }
+        (None, None, Some(output)) => {
+            partition::check_placeholders(output)?;
+            None
         }
+        _ => None,
     };
 
-    let mut output = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, output_writer);
+    let sort = if args.sort_by_id {
+        Some(sort::SortConfig {
+            memory: memory::parse_byte_size(&args.sort_memory)?,
+            dir: args
+                .sort_dir
+                .as_ref()
+                .map_or_else(std::env::temp_dir, std::path::PathBuf::from),
+        })
+    } else {
+        None
+    };
 
-    match detected_format.as_str() {
-        "rdf" | "ntriples" | "nt" => {
-            let batch_size = args.batch_size.unwrap_or(100);
-            filter_rdf_parallel(
-                reader,
-                &mut output,
-                &entity_filter,
-                args.progress,
-                batch_size,
-                skip_lines,
-                max_lines,
-                output_format,
-            )?;
+    let options = ProcessingOptions {
+        show_progress: args.progress,
+        batch_size,
+        skip_lines,
+        max_lines,
+        skip_entities: args.skip_entities,
+        max_entities,
+        strict: args.strict,
+        errors_output: args.errors_output.clone(),
+        explain: args.explain,
+        routes_only: !route_specs.is_empty(),
+        partition,
+        no_clobber: args.no_clobber,
+        http_retries: args.http_retries,
+        output_format,
+        // The RSS watchdog flushes early when the hard limit is approached
+        memory_budget: memory_budget.or(max_memory),
+        truthify: args.truthify,
+        json_style,
+        canonical: args.canonical,
+        sort,
+        json_parser,
+        pipeline,
+        stable_order: args.stable_order,
+        stats: args.stats_output.is_some().then(|| stats.clone()),
+        interrupted: interrupted.clone(),
+        snapshot_requested,
+        checkpoint: checkpoint_path.map(|path| checkpoint::CheckpointConfig {
+            path,
+            input: args.input.clone().unwrap_or_default(),
+            output: args.output.as_deref().map(written_path).unwrap_or_default(),
+            interval: checkpoint::CHECKPOINT_INTERVAL,
+            blocks: bgzf_blocks,
+            member_boundary: member_boundary.clone(),
+        }),
+        resume_position: resume.as_ref().map_or((0, 0), |c| (c.lines, c.offset)),
+        table,
+        bulk: bulk::BulkTemplate {
+            index: args.es_index.clone(),
+            id: args.es_id.clone(),
+        },
+    };
+
+    if parts.is_some() {
+        if let Some(reason) = parts::unsupported_reason(&detected_format, &options) {
+            return Err(FilterError::Parse(format!("A dump directory {}", reason)));
+        }
+    }
+    if options.checkpoint.is_some() {
+        if let Some(reason) = checkpoint::unsupported_reason(
+            args.input.as_deref(),
+            args.output.as_deref(),
+            &detected_format,
+            &compression,
+            args.append,
+            &options,
+        ) {
+            return Err(FilterError::Parse(format!("--checkpoint {}", reason)));
+        }
+    }
+    if options.sort.is_some() && checkpoint::framed_output(&options) {
+        return Err(FilterError::Parse(
+            "--sort-by-id does not support arrow, graphml or array-style JSON output".to_string(),
+        ));
+    }
+    if args.append && args.output.as_deref().is_some_and(s3::is_object_url) {
+        return Err(FilterError::Parse(
+            "--append needs a local output file".to_string(),
+        ));
+    }
+    if args.append && checkpoint::framed_output(&options) {
+        return Err(FilterError::Parse(
+            "--append does not support arrow, graphml or array-style JSON output".to_string(),
+        ));
+    }
+
+    if args.dry_run {
+        let input_compression = match args.input.as_deref() {
+            Some(_) if parts.is_some() => "per part",
+            Some(path) if path.ends_with(".gz") && bgzf::is_bgzf(path, args.http_retries)? => {
+                "gzip (BGZF)"
+            }
+            Some(path) => compression::input_compression(path),
+            None => "none",
+        };
+        let plan = dry_run::RunPlan {
+            input: args.input.as_deref(),
+            input_format: &detected_format,
+            input_compression,
+            output: args.output.as_deref(),
+            compression: &compression,
+            rejected: args
+                .output_rejected
+                .as_deref()
+                .map(|path| (path, rejected_compression.as_str())),
+            routes: &route_specs,
+        };
+        print!("{}", dry_run::describe(&plan, &entity_filter, &options));
+        return Ok(());
+    }
+
+    // Output files are written under a temporary name and renamed once complete;
+    // --append writes to the output file in place
+    let mut pending_outputs = Vec::new();
+    let mut continued = resume.is_some();
+    let output_file: Box<dyn Write> = match (&resume, &args.output) {
+        // With --route, --partition-by, --shards or a split output, everything goes to the files they name
+        _ if !route_specs.is_empty() || options.partition.is_some() => Box::new(io::sink()),
+        // A resumed run appends to the file of the interrupted one
+        (Some(resume), Some(path)) => {
+            if !args.append {
+                pending_outputs.push(
+                    atomic::PendingFile::new(path, args.no_clobber, args.http_retries)?
+                        .keep_on_failure(),
+                );
+            }
+            Box::new(checkpoint::open_output(resume)?)
+        }
+        (None, Some(path)) if args.append => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            // The header or prefixes are already in a non-empty file
+            continued = file.metadata()?.len() > 0;
+            Box::new(file)
+        }
+        (None, Some(path)) => {
+            let mut pending = atomic::PendingFile::new(path, args.no_clobber, args.http_retries)?;
+            if options.checkpoint.is_some() {
+                pending = pending.keep_on_failure();
+            }
+            let file = pending.create()?;
+            pending_outputs.push(pending);
+            Box::new(file)
+        }
+        (_, None) => open_output(None)?,
+    };
+    let output_file = stats::Counting::new(output_file, stats.bytes_written.clone());
+    let output_writer = if args.append {
+        compression::create_member_writer(output_file, &compression, member_boundary)
+    } else {
+        create_compressed_writer(output_file, &compression)
+    };
+
+    let mut output = BufWriter::with_capacity(write_buffer, output_writer);
+
+    // Extra outputs with their own filters: the complement, or one per route
+    let mut route_targets = Vec::new();
+    if let Some(ref path) = args.output_rejected {
+        let inverted = EntityFilter {
+            invert_match: !entity_filter.invert_match,
+            ..(*entity_filter).clone()
+        };
+        route_targets.push((inverted, path.as_str(), rejected_compression.clone()));
+    }
+    for spec in &route_specs {
+        spec.check_format(options.output_format)?;
+        let compression = determine_compression("none", Some(&spec.path));
+        route_targets.push((spec.filter(&entity_filter), spec.path.as_str(), compression));
+    }
+    let mut route_outputs = Vec::new();
+    let mut route_filters = Vec::new();
+    for (filter, path, compression) in route_targets {
+        let mut pending = atomic::PendingFile::new(path, args.no_clobber, args.http_retries)?;
+        route_outputs.push(BufWriter::with_capacity(
+            write_buffer,
+            create_compressed_writer(pending.create()?, &compression),
+        ));
+        pending_outputs.push(pending);
+        route_filters.push(Arc::new(filter));
+    }
+
+    // Partition files get their preamble when they are opened, a continued
+    // output already has it
+    let outputs = std::iter::once(&mut output).chain(route_outputs.iter_mut());
+    let outputs = outputs.skip(usize::from(
+        continued || !route_specs.is_empty() || options.partition.is_some(),
+    ));
+    for output in outputs {
+        partition::write_preamble(output, &options)?;
+    }
+
+    let mut routes: Vec<route::Route> = route_filters
+        .into_iter()
+        .zip(route_outputs.iter_mut())
+        .map(|(filter, output)| route::Route { filter, output })
+        .collect();
+
+    let mapped = match mmap::unsupported_reason(args.input.as_deref(), &detected_format, &options) {
+        _ if !args.mmap => None,
+        _ if parts.is_some() => {
+            eprintln!(
+                "Warning: --mmap only applies to single files, reading the dump parts as streams"
+            );
+            None
+        }
+        None => args.input.as_deref().map(mmap::map_file).transpose()?,
+        Some(reason) => {
+            eprintln!("Warning: --mmap {}, reading the input as a stream", reason);
+            None
         }
-        "json" | "ndjson" => {
-            let batch_size = args.batch_size.unwrap_or(1000);
-            filter_json_parallel(
-                reader,
+    };
+
+    match (mapped, parts) {
+        (_, Some(parts)) => {
+            drop(reader);
+            run_filter_parts(
+                &parts,
+                read_buffer,
+                stats.bytes_read.clone(),
                 &mut output,
+                &mut routes,
                 &entity_filter,
-                args.progress,
-                batch_size,
-                skip_lines,
-                max_lines,
-                output_format,
-            )?;
+                &options,
+            )?
         }
-        _ => {
-            eprintln!("Unknown format: {}, assuming RDF", detected_format);
-            let batch_size = args.batch_size.unwrap_or(100);
-            filter_rdf_parallel(
-                reader,
+        (Some(data), None) => {
+            drop(reader);
+            stats
+                .bytes_read
+                .store(data.len() as u64, std::sync::atomic::Ordering::Relaxed);
+            run_filter_mmap(
+                &data,
+                &detected_format,
                 &mut output,
+                &mut routes,
                 &entity_filter,
-                args.progress,
-                batch_size,
-                skip_lines,
-                max_lines,
-                output_format,
-            )?;
+                &options,
+            )?
+        }
+        (None, None) => run_filter(
+            reader,
+            &detected_format,
+            &mut output,
+            &mut routes,
+            &entity_filter,
+            &options,
+        )?,
+    }
+
+    // Finish the LZ4/gzip streams (frame end mark, trailer); an output that
+    // cannot be finished is not committed
+    drop(routes);
+    compression::finish_output(output)?;
+    for route_output in route_outputs {
+        compression::finish_output(route_output)?;
+    }
+
+    let interrupted = interrupted.load(std::sync::atomic::Ordering::Relaxed);
+    for pending in pending_outputs {
+        if interrupted {
+            let path = pending.path().to_string();
+            match pending.keep() {
+                Some(partial) => eprintln!("Partial output left in {}", partial),
+                None => eprintln!("Upload of {} aborted", path),
+            }
+        } else {
+            pending.commit()?;
         }
     }
+    // A resume of the finished run would look for the renamed temporary output
+    if let (false, Some(checkpoint)) = (interrupted, &options.checkpoint) {
+        checkpoint::Checkpoint::mark_complete(&checkpoint.path)?;
+    }
 
-    // Flush the buffered writer
-    output.flush()?;
+    if let Some(ref path) = args.stats_output {
+        stats.write_report(path, &entity_filter, started.elapsed())?;
+    }
 
-    // For LZ4, we need to finish the encoder to write the frame footer
-    // This is handled by dropping the writer, but we should explicitly flush
-    drop(output);
+    if interrupted {
+        if let Some(ref checkpoint) = options.checkpoint {
+            eprintln!(
+                "Checkpoint saved, continue with --resume {}",
+                checkpoint.path
+            );
+        }
+        std::process::exit(signal::INTERRUPTED_EXIT_CODE);
+    }
 
     Ok(())
 }
@@ -0,0 +1,191 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+
+use crate::rdf::ProcessingOptions;
+use crate::FilterError;
+
+/// Longest part of a malformed line shown in messages
+const MAX_EXCERPT: usize = 200;
+
+/// An input line that could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MalformedLine {
+    /// 1-based input line number
+    pub line: u64,
+    pub error: String,
+    pub content: String,
+}
+
+impl MalformedLine {
+    pub fn new(line: u64, error: impl Into<String>, content: &str) -> Self {
+        Self {
+            line,
+            error: error.into(),
+            content: content.to_string(),
+        }
+    }
+
+    /// Start of the line, cut at a character boundary
+    pub fn excerpt(&self) -> String {
+        if self.content.len() <= MAX_EXCERPT {
+            return self.content.clone();
+        }
+        let mut end = MAX_EXCERPT;
+        while !self.content.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}...", &self.content[..end])
+    }
+
+    /// Error aborting a --strict run
+    pub fn into_error(self) -> FilterError {
+        FilterError::Parse(format!(
+            "Malformed input at line {}: {}: {}",
+            self.line,
+            self.error,
+            self.excerpt()
+        ))
+    }
+}
+
+/// Malformed lines of a run: how many, and the first one
+#[derive(Debug, Default)]
+pub struct MalformedSummary {
+    count: u64,
+    first: Option<MalformedLine>,
+    strict: bool,
+    /// Path and writer of the --errors-output file
+    errors: Option<(String, BufWriter<File>)>,
+}
+
+impl MalformedSummary {
+    /// Create the summary of a run, opening its --errors-output file
+    ///
+    /// A resumed run appends to the file of the interrupted one.
+    pub fn new(options: &ProcessingOptions) -> io::Result<Self> {
+        let errors = match options.errors_output {
+            Some(ref path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(options.resume_position != (0, 0))
+                    .truncate(options.resume_position == (0, 0))
+                    .open(path)?;
+                Some((path.clone(), BufWriter::new(file)))
+            }
+            None => None,
+        };
+        Ok(Self {
+            strict: options.strict,
+            errors,
+            ..Default::default()
+        })
+    }
+
+    /// Add the malformed lines of a batch; with --strict, the first one is an error
+    pub fn add(&mut self, lines: Vec<MalformedLine>) -> Result<(), FilterError> {
+        if let Some((_, ref mut errors)) = self.errors {
+            for line in &lines {
+                writeln!(errors, "{}\t{}\t{}", line.line, line.error, line.content)?;
+            }
+        }
+        let mut lines = lines.into_iter();
+        if let Some(first) = lines.next() {
+            if self.strict {
+                return Err(first.into_error());
+            }
+            self.count += 1 + lines.len() as u64;
+            self.first.get_or_insert(first);
+        }
+        Ok(())
+    }
+
+    /// Flush the --errors-output file and print a summary of dropped lines to stderr
+    pub fn finish(mut self) -> io::Result<()> {
+        let listed = match self.errors {
+            Some((ref path, ref mut errors)) => {
+                errors.flush()?;
+                format!("all listed in {}", path)
+            }
+            None => "use --strict to abort on the first".to_string(),
+        };
+        if let Some(ref first) = self.first {
+            eprintln!(
+                "Warning: dropped {} malformed lines ({}), first at line {}: {}: {}",
+                self.count,
+                listed,
+                first.line,
+                first.error,
+                first.excerpt()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_summary_keeps_first_line() {
+        let mut summary = MalformedSummary::default();
+        summary
+            .add(vec![MalformedLine::new(7, "bad", "x")])
+            .unwrap();
+        summary
+            .add(vec![
+                MalformedLine::new(9, "bad", "y"),
+                MalformedLine::new(12, "bad", "z"),
+            ])
+            .unwrap();
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.first.as_ref().unwrap().line, 7);
+    }
+
+    #[test]
+    fn test_strict_error_names_line() {
+        let mut summary = MalformedSummary {
+            strict: true,
+            ..Default::default()
+        };
+        let error = summary
+            .add(vec![MalformedLine::new(42, "expected '.'", "<a> <b> <c>")])
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Parse error: Malformed input at line 42: expected '.': <a> <b> <c>"
+        );
+    }
+
+    #[test]
+    fn test_errors_output_lists_every_line() {
+        let dir = TempDir::new("errors");
+        let path = dir.join("errors.log");
+        let options = ProcessingOptions {
+            errors_output: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        let mut summary = MalformedSummary::new(&options).unwrap();
+        summary
+            .add(vec![
+                MalformedLine::new(3, "bad", "x y"),
+                MalformedLine::new(8, "worse", "z"),
+            ])
+            .unwrap();
+        summary.finish().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "3\tbad\tx y\n8\tworse\tz\n"
+        );
+    }
+
+    #[test]
+    fn test_excerpt_is_cut_at_char_boundary() {
+        let line = MalformedLine::new(1, "bad", &"ä".repeat(200));
+        let excerpt = line.excerpt();
+        assert!(excerpt.ends_with("..."));
+        assert!(excerpt.len() <= MAX_EXCERPT + 3);
+    }
+}
@@ -0,0 +1,333 @@
+use std::time::{Duration, Instant};
+
+use crate::FilterError;
+
+/// Fraction of the budget at which the watchdog starts applying backpressure
+const PRESSURE_THRESHOLD: f64 = 0.9;
+
+/// Fraction of the budget below which batch sizes are allowed to grow again
+const RELAX_THRESHOLD: f64 = 0.7;
+
+/// Minimum time between two RSS measurements
+const CHECK_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Memory assumed for the binary, allocator and filter state under --max-memory
+const BASE_OVERHEAD: u64 = 32 << 20;
+
+/// Memory per input byte in flight: raw lines, parsed form and serialized output
+const INPUT_MEMORY_FACTOR: u64 = 3;
+
+/// Assumed memory per in-flight entity, for an entity of 16 KiB
+const ENTITY_MEMORY_ESTIMATE: u64 = INPUT_MEMORY_FACTOR * (16 << 10);
+
+/// Smallest accepted --max-memory
+const MIN_MAX_MEMORY: u64 = 64 << 20;
+
+/// Smallest write buffer chosen under --max-memory
+const MIN_WRITE_BUFFER: usize = 64 << 10;
+
+/// Parse a human-readable byte size like "512M", "4G", "64k" or "1048576"
+///
+/// Suffixes are binary (k = 1024) and case-insensitive, an optional trailing
+/// "B" or "iB" is accepted.
+pub fn parse_byte_size(input: &str) -> Result<u64, FilterError> {
+    let s = input.trim().to_lowercase();
+    let s = s
+        .strip_suffix("ib")
+        .or_else(|| s.strip_suffix('b'))
+        .unwrap_or(&s);
+
+    let (number, multiplier) = match s.chars().last() {
+        Some('k') => (&s[..s.len() - 1], 1u64 << 10),
+        Some('m') => (&s[..s.len() - 1], 1u64 << 20),
+        Some('g') => (&s[..s.len() - 1], 1u64 << 30),
+        Some('t') => (&s[..s.len() - 1], 1u64 << 40),
+        _ => (s, 1),
+    };
+
+    let value: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| FilterError::Parse(format!("Invalid size '{}'", input)))?;
+    if value < 0.0 || !value.is_finite() {
+        return Err(FilterError::Parse(format!("Invalid size '{}'", input)));
+    }
+
+    Ok((value * multiplier as f64) as u64)
+}
+
+/// Parse an I/O buffer size like "64k" or "16M", rejecting zero
+pub fn parse_buffer_size(input: &str) -> Result<usize, FilterError> {
+    match parse_byte_size(input)? {
+        0 => Err(FilterError::Parse(format!(
+            "Buffer size must be positive, got '{}'",
+            input
+        ))),
+        size => Ok(size as usize),
+    }
+}
+
+/// Batch, queue and buffer sizes that keep the pipeline under --max-memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryPlan {
+    pub batch_size: usize,
+    pub queue_depth: usize,
+    pub write_buffer: usize,
+    /// Most input bytes of the batches in flight, which the pipeline holds
+    /// the reader at, so that dense matches of large entities also fit
+    pub in_flight_bytes: u64,
+}
+
+impl MemoryPlan {
+    /// Shrink the configured sizes until the estimated peak fits into `limit`
+    ///
+    /// At most `queue_depth` batches are in flight plus the one being read.
+    /// The queue is cut down to one batch per worker first, then batches get
+    /// smaller. The write buffer is capped at 1/16 of the limit. The counts
+    /// assume entities of 16 KiB; `in_flight_bytes` bounds the real sizes.
+    pub fn new(
+        limit: u64,
+        batch_size: usize,
+        workers: usize,
+        queue_depth: usize,
+        read_buffer: usize,
+        write_buffer: usize,
+    ) -> Result<Self, FilterError> {
+        if limit < MIN_MAX_MEMORY {
+            return Err(FilterError::Parse(format!(
+                "--max-memory must be at least {} MiB",
+                MIN_MAX_MEMORY >> 20
+            )));
+        }
+
+        let write_buffer = write_buffer
+            .min((limit / 16) as usize)
+            .max(MIN_WRITE_BUFFER);
+        let available =
+            limit.saturating_sub(BASE_OVERHEAD + read_buffer as u64 + write_buffer as u64);
+        let entities = ((available / ENTITY_MEMORY_ESTIMATE) as usize).max(2);
+
+        let fits =
+            |queue_depth: usize, batch_size: usize| (queue_depth + 1) * batch_size <= entities;
+        let mut queue_depth = queue_depth.max(1);
+        let mut batch_size = batch_size.max(1);
+        if !fits(queue_depth, batch_size) {
+            queue_depth = queue_depth.min(workers).max(1);
+        }
+        if !fits(queue_depth, batch_size) {
+            batch_size = (entities / (queue_depth + 1)).max(1);
+        }
+        if !fits(queue_depth, batch_size) {
+            queue_depth = entities - 1;
+        }
+
+        Ok(Self {
+            batch_size,
+            queue_depth,
+            write_buffer,
+            in_flight_bytes: (available / INPUT_MEMORY_FACTOR).max(1),
+        })
+    }
+}
+
+/// Read the resident set size of the current process in bytes
+///
+/// Returns None on platforms without /proc.
+pub fn current_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find(|l| l.starts_with("VmRSS:"))
+        .and_then(|l| l.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+}
+
+/// Action requested by the watchdog
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryAction {
+    /// Keep going
+    Continue,
+    /// Memory is tight: process pending work and flush output before reading more
+    Drain,
+}
+
+/// Soft memory watchdog that shrinks batch sizes when an RSS budget is approached
+///
+/// The watchdog only samples RSS every few hundred milliseconds. When usage
+/// exceeds 90% of the budget it halves the effective batch size and asks the
+/// caller to drain; when usage drops below 70% the batch size grows back
+/// towards the configured value.
+#[derive(Debug)]
+pub struct MemoryWatchdog {
+    budget: u64,
+    configured_batch_size: usize,
+    batch_size: usize,
+    last_check: Option<Instant>,
+    warned_over_budget: bool,
+    show_progress: bool,
+}
+
+impl MemoryWatchdog {
+    pub fn new(budget: u64, configured_batch_size: usize, show_progress: bool) -> Self {
+        Self {
+            budget,
+            configured_batch_size: configured_batch_size.max(1),
+            batch_size: configured_batch_size.max(1),
+            last_check: None,
+            warned_over_budget: false,
+            show_progress,
+        }
+    }
+
+    /// Current effective batch size
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Sample RSS if the check interval has elapsed and adjust the batch size
+    pub fn poll(&mut self) -> MemoryAction {
+        let now = Instant::now();
+        if let Some(last) = self.last_check {
+            if now.duration_since(last) < CHECK_INTERVAL {
+                return MemoryAction::Continue;
+            }
+        }
+        self.last_check = Some(now);
+
+        match current_rss() {
+            Some(rss) => self.observe(rss),
+            None => MemoryAction::Continue,
+        }
+    }
+
+    /// Adjust the batch size for a measured RSS value
+    pub fn observe(&mut self, rss: u64) -> MemoryAction {
+        let usage = rss as f64 / self.budget as f64;
+
+        if usage >= PRESSURE_THRESHOLD {
+            let new_size = (self.batch_size / 2).max(1);
+            if self.show_progress && new_size != self.batch_size {
+                eprintln!(
+                    "Memory: RSS {} MiB near budget {} MiB, reducing batch size to {}",
+                    rss >> 20,
+                    self.budget >> 20,
+                    new_size
+                );
+            }
+            self.batch_size = new_size;
+
+            if usage >= 1.0 && !self.warned_over_budget {
+                eprintln!(
+                    "Warning: RSS {} MiB exceeds memory budget of {} MiB",
+                    rss >> 20,
+                    self.budget >> 20
+                );
+                self.warned_over_budget = true;
+            }
+            MemoryAction::Drain
+        } else {
+            if usage < RELAX_THRESHOLD && self.batch_size < self.configured_batch_size {
+                self.batch_size = (self.batch_size * 2).min(self.configured_batch_size);
+            }
+            MemoryAction::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("1024").unwrap(), 1024);
+        assert_eq!(parse_byte_size("64k").unwrap(), 64 * 1024);
+        assert_eq!(parse_byte_size("512M").unwrap(), 512 << 20);
+        assert_eq!(parse_byte_size("4G").unwrap(), 4 << 30);
+        assert_eq!(parse_byte_size("4GiB").unwrap(), 4 << 30);
+        assert_eq!(parse_byte_size("1.5g").unwrap(), 3 << 29);
+        assert!(parse_byte_size("lots").is_err());
+        assert!(parse_byte_size("-1M").is_err());
+    }
+
+    #[test]
+    fn test_memory_plan() {
+        let mib = |n: u64| n << 20;
+
+        // Plenty of memory: nothing changes
+        let plan = MemoryPlan::new(mib(16 << 10), 1000, 8, 16, 8 << 10, 8 << 20).unwrap();
+        assert_eq!(
+            (plan.batch_size, plan.queue_depth, plan.write_buffer),
+            (1000, 16, 8 << 20)
+        );
+
+        let estimate = |plan: &MemoryPlan| {
+            BASE_OVERHEAD
+                + (8 << 10)
+                + plan.write_buffer as u64
+                + (plan.queue_depth as u64 + 1) * plan.batch_size as u64 * ENTITY_MEMORY_ESTIMATE
+        };
+
+        // Tight limit: queue shrinks to one batch per worker, then batches shrink
+        let plan = MemoryPlan::new(mib(256), 1000, 8, 16, 8 << 10, 8 << 20).unwrap();
+        assert_eq!(plan.queue_depth, 8);
+        assert_eq!(plan.write_buffer, 8 << 20);
+        assert!(plan.batch_size < 1000);
+        assert!(estimate(&plan) <= mib(256));
+        // In-flight input of any entity size fits too
+        assert!(
+            BASE_OVERHEAD
+                + (8 << 10)
+                + plan.write_buffer as u64
+                + plan.in_flight_bytes * INPUT_MEMORY_FACTOR
+                <= mib(256)
+        );
+
+        // Write buffer is capped too
+        let plan = MemoryPlan::new(mib(64), 100, 64, 128, 8 << 10, 8 << 20).unwrap();
+        assert_eq!(plan.write_buffer, 4 << 20);
+        assert!(estimate(&plan) <= mib(64));
+        assert!(MemoryPlan::new(mib(16), 100, 4, 8, 8 << 10, 8 << 20).is_err());
+    }
+
+    #[test]
+    fn test_parse_buffer_size() {
+        assert_eq!(parse_buffer_size("64k").unwrap(), 64 * 1024);
+        assert!(parse_buffer_size("0").is_err());
+        assert!(parse_buffer_size("0.1").is_err());
+    }
+
+    #[test]
+    fn test_watchdog_shrinks_and_recovers() {
+        let budget = 1000 << 20;
+        let mut watchdog = MemoryWatchdog::new(budget, 100, false);
+
+        assert_eq!(watchdog.observe(500 << 20), MemoryAction::Continue);
+        assert_eq!(watchdog.batch_size(), 100);
+
+        // Approaching the budget halves the batch size and requests a drain
+        assert_eq!(watchdog.observe(950 << 20), MemoryAction::Drain);
+        assert_eq!(watchdog.batch_size(), 50);
+        assert_eq!(watchdog.observe(950 << 20), MemoryAction::Drain);
+        assert_eq!(watchdog.batch_size(), 25);
+
+        // Between thresholds nothing changes
+        assert_eq!(watchdog.observe(800 << 20), MemoryAction::Continue);
+        assert_eq!(watchdog.batch_size(), 25);
+
+        // Low usage grows back up to the configured size
+        watchdog.observe(100 << 20);
+        assert_eq!(watchdog.batch_size(), 50);
+        watchdog.observe(100 << 20);
+        watchdog.observe(100 << 20);
+        assert_eq!(watchdog.batch_size(), 100);
+    }
+
+    #[test]
+    fn test_watchdog_batch_size_never_zero() {
+        let mut watchdog = MemoryWatchdog::new(1 << 20, 1, false);
+        watchdog.observe(2 << 20);
+        assert_eq!(watchdog.batch_size(), 1);
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::atomic::PendingFile;
+use crate::compression::{
+    create_compressed_writer, create_input_reader, determine_compression, finish_output,
+    INPUT_BUFFER_SIZE, OUTPUT_BUFFER_SIZE,
+};
+use crate::diff::EntitySink;
+use crate::filter::EntityFilter;
+use crate::json::json_entity_to_ntriples;
+use crate::rdf::{OutputFormat, ProcessingOptions};
+use crate::{http, run_filter, FilterError};
+
+/// Arguments for the `merge` subcommand
+#[derive(clap::Args, Debug)]
+pub struct MergeArgs {
+    /// Extracts to combine (read twice, so stdin is not supported)
+    #[arg(required = true, num_args = 2..)]
+    inputs: Vec<String>,
+
+    /// Input format of the extracts: auto, rdf, turtle, json
+    #[arg(short = 'f', long, default_value = "auto")]
+    format: String,
+
+    /// Which version of an entity in several extracts is kept: newest (by
+    /// lastrevid, then modified), first or last (in the order of the inputs)
+    #[arg(long, default_value = "newest")]
+    prefer: String,
+
+    /// Output format: json, ntriples
+    #[arg(short = 'o', long, default_value = "json")]
+    output_format: String,
+
+    /// Output file (stdout if not provided). Extension determines compression (.gz, .lz4)
+    #[arg(long)]
+    output: Option<String>,
+}
+
+/// Which duplicate wins
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Prefer {
+    Newest,
+    First,
+    Last,
+}
+
+impl Prefer {
+    fn parse(s: &str) -> Result<Self, FilterError> {
+        match s {
+            "newest" => Ok(Prefer::Newest),
+            "first" => Ok(Prefer::First),
+            "last" => Ok(Prefer::Last),
+            _ => Err(FilterError::Parse(format!(
+                "--prefer must be newest, first or last, not '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Where a version of an entity is: input number and position among its entities
+type Location = (usize, u64);
+
+/// Revision of an entity version; versions without one are the oldest
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+struct Revision {
+    lastrevid: Option<u64>,
+    modified: Option<String>,
+}
+
+impl Revision {
+    fn of(entity: &Value) -> Self {
+        Self {
+            lastrevid: entity.get("lastrevid").and_then(Value::as_u64),
+            modified: entity
+                .get("modified")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        }
+    }
+}
+
+/// The version of every entity ID that is kept
+struct Winners {
+    prefer: Prefer,
+    versions: HashMap<String, (Revision, Location)>,
+}
+
+impl Winners {
+    /// Consider the version of `entity` at `location`; inputs are offered in order
+    fn offer(&mut self, entity: &Value, location: Location) {
+        let Some(id) = entity.get("id").and_then(Value::as_str) else {
+            return;
+        };
+        let revision = match self.prefer {
+            Prefer::Newest => Revision::of(entity),
+            _ => Revision {
+                lastrevid: None,
+                modified: None,
+            },
+        };
+        match self.versions.get_mut(id) {
+            None => {
+                self.versions.insert(id.to_string(), (revision, location));
+            }
+            Some(kept) => {
+                // Later versions win ties, except with --prefer first
+                let replace = match self.prefer {
+                    Prefer::First => false,
+                    Prefer::Last => true,
+                    Prefer::Newest => revision >= kept.0,
+                };
+                if replace {
+                    *kept = (revision, location);
+                }
+            }
+        }
+    }
+
+    fn is_kept(&self, entity: &Value, location: Location) -> bool {
+        entity
+            .get("id")
+            .and_then(Value::as_str)
+            .and_then(|id| self.versions.get(id))
+            .is_some_and(|(_, kept)| *kept == location)
+    }
+}
+
+/// Run `handle` on every entity of an input, converted to JSON, with its position
+fn for_each_entity(
+    path: &str,
+    format: &str,
+    mut handle: impl FnMut(Value, u64) -> std::io::Result<()>,
+) -> Result<(), FilterError> {
+    let (reader, format) = create_input_reader(path, format, INPUT_BUFFER_SIZE)?;
+    let options = ProcessingOptions {
+        batch_size: 0,
+        output_format: OutputFormat::Json,
+        ..Default::default()
+    };
+    let mut position = 0;
+    let mut sink = EntitySink::new(|entity| {
+        position += 1;
+        handle(entity, position - 1)
+    });
+    let filter = Arc::new(EntityFilter::default());
+    run_filter(reader, &format, &mut sink, &mut [], &filter, &options)?;
+    Ok(())
+}
+
+/// Run the `merge` subcommand
+///
+/// The first pass finds the version of each entity to keep, the second writes
+/// the kept versions in input order.
+pub fn run(args: MergeArgs) -> Result<(), FilterError> {
+    let prefer = Prefer::parse(&args.prefer)?;
+    let ntriples = match args.output_format.as_str() {
+        "json" => false,
+        "ntriples" => true,
+        other => {
+            return Err(FilterError::Parse(format!(
+                "merge writes json or ntriples, not {}",
+                other
+            )))
+        }
+    };
+
+    let mut winners = Winners {
+        prefer,
+        versions: HashMap::new(),
+    };
+    let mut versions = 0u64;
+    for (input, path) in args.inputs.iter().enumerate() {
+        for_each_entity(path, &args.format, |entity, position| {
+            versions += 1;
+            winners.offer(&entity, (input, position));
+            Ok(())
+        })?;
+    }
+
+    let mut pending = args
+        .output
+        .as_deref()
+        .map(|path| PendingFile::new(path, false, http::DEFAULT_RETRIES))
+        .transpose()?;
+    let file: Box<dyn Write> = match &mut pending {
+        Some(pending) => pending.create()?,
+        None => Box::new(std::io::stdout()),
+    };
+    let compression = determine_compression("none", args.output.as_deref());
+    let mut output = BufWriter::with_capacity(
+        OUTPUT_BUFFER_SIZE,
+        create_compressed_writer(file, &compression),
+    );
+    for (input, path) in args.inputs.iter().enumerate() {
+        for_each_entity(path, &args.format, |entity, position| {
+            if !winners.is_kept(&entity, (input, position)) {
+                return Ok(());
+            }
+            if ntriples {
+                for triple in json_entity_to_ntriples(&entity) {
+                    writeln!(output, "{}", triple)?;
+                }
+            } else {
+                serde_json::to_writer(&mut output, &entity)?;
+                output.write_all(b"\n")?;
+            }
+            Ok(())
+        })?;
+    }
+    finish_output(output)?;
+    if let Some(pending) = pending {
+        pending.commit()?;
+    }
+
+    eprintln!(
+        "Merged {} entity versions from {} inputs into {} entities",
+        versions,
+        args.inputs.len(),
+        winners.versions.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_winners() {
+        let entity = |json: &str| -> Value { serde_json::from_str(json).unwrap() };
+        let versions = [
+            (entity(r#"{"id":"Q1","lastrevid":5}"#), (0, 0)),
+            (
+                entity(r#"{"id":"Q2","modified":"2026-10-02T00:00:00Z"}"#),
+                (0, 1),
+            ),
+            (entity(r#"{"id":"Q1","lastrevid":3}"#), (1, 0)),
+            (
+                entity(r#"{"id":"Q2","modified":"2026-10-01T00:00:00Z"}"#),
+                (1, 1),
+            ),
+            (entity(r#"{"id":"Q3"}"#), (1, 2)),
+            (entity(r#"{"id":"Q3"}"#), (1, 3)),
+        ];
+        let kept = |prefer| {
+            let mut winners = Winners {
+                prefer,
+                versions: HashMap::new(),
+            };
+            for (entity, location) in &versions {
+                winners.offer(entity, *location);
+            }
+            let mut kept: Vec<Location> = versions
+                .iter()
+                .filter(|(entity, location)| winners.is_kept(entity, *location))
+                .map(|(_, location)| *location)
+                .collect();
+            kept.sort();
+            kept
+        };
+        assert_eq!(kept(Prefer::Newest), [(0, 0), (0, 1), (1, 3)]);
+        assert_eq!(kept(Prefer::First), [(0, 0), (0, 1), (1, 2)]);
+        assert_eq!(kept(Prefer::Last), [(1, 0), (1, 1), (1, 3)]);
+        assert!(Prefer::parse("oldest").is_err());
+    }
+}
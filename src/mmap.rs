@@ -0,0 +1,141 @@
+use std::ops::Range;
+
+use memmap2::Mmap;
+
+use crate::http;
+use crate::rdf::ProcessingOptions;
+use crate::tar;
+
+/// Approximate size of the byte ranges handed to the workers
+pub const CHUNK_SIZE: usize = 4 << 20;
+
+/// Why `--mmap` cannot be used for an input, if it cannot
+pub fn unsupported_reason(
+    path: Option<&str>,
+    format: &str,
+    options: &ProcessingOptions,
+) -> Option<&'static str> {
+    let path = match path {
+        Some(path) => path.to_lowercase(),
+        None => return Some("needs an input file"),
+    };
+    if [".bz2", ".gz", ".lz4"]
+        .iter()
+        .any(|ext| path.ends_with(ext))
+    {
+        return Some("only applies to uncompressed files");
+    }
+    if http::is_url(&path) {
+        return Some("only applies to local files");
+    }
+    if tar::is_tar(&path) {
+        return Some("does not apply to tar archives");
+    }
+    if !matches!(format, "rdf" | "ntriples" | "nt" | "json" | "ndjson") {
+        return Some("only applies to N-Triples and JSON input");
+    }
+    if options.skip_lines > 0 || options.max_lines < u64::MAX {
+        return Some("does not support --skip-lines/--max-lines");
+    }
+    if options.skip_entities > 0 {
+        return Some("does not support --skip-entities");
+    }
+    if options.checkpoint.is_some() || options.resume_position != (0, 0) {
+        return Some("does not support --checkpoint/--resume");
+    }
+    None
+}
+
+/// Map an input file into memory
+pub fn map_file(path: &str) -> std::io::Result<Mmap> {
+    let file = std::fs::File::open(path)?;
+    // Safety: the dump must not be truncated or modified while it is filtered
+    let map = unsafe { Mmap::map(&file)? };
+    #[cfg(unix)]
+    map.advise(memmap2::Advice::Sequential)?;
+    Ok(map)
+}
+
+/// Start of the first line at or after `pos`
+pub fn next_line_start(data: &[u8], pos: usize) -> usize {
+    if pos == 0 || pos >= data.len() || data[pos - 1] == b'\n' {
+        return pos.min(data.len());
+    }
+    memchr::memchr(b'\n', &data[pos..]).map_or(data.len(), |i| pos + i + 1)
+}
+
+/// Split `data` into ranges of about `chunk_size` bytes
+///
+/// `next_boundary(data, pos)` returns the first record boundary at or after
+/// `pos`, so that no record is split between two ranges.
+pub fn chunk_ranges<F>(
+    data: &[u8],
+    chunk_size: usize,
+    next_boundary: F,
+) -> impl Iterator<Item = Range<usize>> + '_
+where
+    F: Fn(&[u8], usize) -> usize + 'static,
+{
+    let chunk_size = chunk_size.max(1);
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        if start >= data.len() {
+            return None;
+        }
+        let end = if data.len() - start <= chunk_size {
+            data.len()
+        } else {
+            next_boundary(data, start + chunk_size).max(start + 1)
+        };
+        let range = start..end;
+        start = end;
+        Some(range)
+    })
+}
+
+/// View a byte range as text, failing like `BufRead::lines` on invalid UTF-8
+pub fn chunk_str(chunk: &[u8]) -> std::io::Result<&str> {
+    std::str::from_utf8(chunk).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "stream did not contain valid UTF-8",
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_line_start() {
+        let data = b"ab\ncd\nef";
+        assert_eq!(next_line_start(data, 0), 0);
+        assert_eq!(next_line_start(data, 1), 3);
+        assert_eq!(next_line_start(data, 3), 3);
+        assert_eq!(next_line_start(data, 7), 8);
+    }
+
+    #[test]
+    fn test_chunk_ranges_cover_input_on_lines() {
+        let data = b"line one\nline two\nline three\nfour\n";
+        let ranges: Vec<_> = chunk_ranges(data, 5, next_line_start).collect();
+        assert_eq!(ranges.first().unwrap().start, 0);
+        assert_eq!(ranges.last().unwrap().end, data.len());
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+            assert_eq!(data[pair[0].end - 1], b'\n');
+        }
+        assert_eq!(ranges.len(), 4);
+    }
+
+    #[test]
+    fn test_unsupported_reason() {
+        let options = ProcessingOptions::default();
+        assert_eq!(unsupported_reason(Some("dump.nt"), "rdf", &options), None);
+        assert_eq!(unsupported_reason(Some("d.json"), "json", &options), None);
+        assert!(unsupported_reason(Some("dump.nt.gz"), "rdf", &options).is_some());
+        assert!(unsupported_reason(Some("dump.ttl"), "turtle", &options).is_some());
+        assert!(unsupported_reason(None, "rdf", &options).is_some());
+    }
+}
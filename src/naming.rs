@@ -0,0 +1,125 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Placeholder for the date of the run (UTC, YYYY-MM-DD)
+pub const DATE_PLACEHOLDER: &str = "{date}";
+
+/// Placeholder for the name of the filter a file gets
+pub const FILTER_PLACEHOLDER: &str = "{filter}";
+
+/// Placeholder for the --type entity type
+pub const TYPE_PLACEHOLDER: &str = "{type}";
+
+/// Filter name of a run without a claim filter
+pub const NO_FILTER: &str = "all";
+
+/// Values of the placeholders in --output, --output-rejected and route file names
+#[derive(Debug, Clone)]
+pub struct OutputNames {
+    pub date: String,
+    /// Name of the main claim filter
+    pub filter: String,
+    pub entity_type: String,
+}
+
+impl OutputNames {
+    /// Names for a run with the --claim expression or --claim-file
+    pub fn new(claim: Option<&str>, claim_file: Option<&str>, entity_type: &str) -> Self {
+        let filter = match (claim, claim_file) {
+            (Some(expression), _) => sanitize(expression.trim()),
+            (None, Some(path)) => Path::new(path).file_stem().map_or_else(
+                || NO_FILTER.to_string(),
+                |stem| sanitize(&stem.to_string_lossy()),
+            ),
+            (None, None) => NO_FILTER.to_string(),
+        };
+        Self {
+            date: today(),
+            filter,
+            entity_type: entity_type.to_string(),
+        }
+    }
+
+    /// Replace the placeholders of `template`; `filter` overrides the main
+    /// filter name (the name of a route)
+    pub fn expand(&self, template: &str, filter: Option<&str>) -> String {
+        let filter = filter.map_or_else(|| self.filter.clone(), sanitize);
+        template
+            .replace(DATE_PLACEHOLDER, &self.date)
+            .replace(FILTER_PLACEHOLDER, &filter)
+            .replace(TYPE_PLACEHOLDER, &self.entity_type)
+    }
+}
+
+/// Make a value safe as part of a file name, keeping it from reaching outside
+/// the template's directory
+pub fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Today's date in UTC as YYYY-MM-DD
+fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (year, month, day) = civil_date((seconds / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Year, month and day of a day number counted from 1970-01-01
+pub fn civil_date(days: i64) -> (i64, u32, u32) {
+    // Howard Hinnant's days_from_civil inverse, with years starting in March
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_civil_date() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(11_016), (2000, 2, 29));
+        assert_eq!(civil_date(20_740), (2026, 10, 14));
+        assert_eq!(civil_date(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn test_expand_placeholders() {
+        let names = OutputNames {
+            date: "2026-10-14".to_string(),
+            ..OutputNames::new(Some("P31:Q5&P17:Q183"), None, "item")
+        };
+        assert_eq!(
+            names.expand("out/{type}-{filter}-{date}.nt.gz", None),
+            "out/item-P31_Q5_P17_Q183-2026-10-14.nt.gz"
+        );
+        assert_eq!(names.expand("{filter}.nt", Some("humans")), "humans.nt");
+        // Placeholders of partitioned outputs are left for them
+        assert_eq!(names.expand("{value}.nt", None), "{value}.nt");
+
+        let names = OutputNames::new(None, Some("filters/humans.txt"), "both");
+        assert_eq!(names.filter, "humans");
+        assert_eq!(OutputNames::new(None, None, "both").filter, NO_FILTER);
+    }
+}
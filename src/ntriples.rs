@@ -1,98 +1,220 @@
-/// Represents an N-Triples line (subject predicate object .)
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
-pub struct NTriple {
-    pub subject: String,
-    pub predicate: String,
-    pub object: String,
-    pub raw: String,
+use std::borrow::Cow;
+
+use memchr::{memchr, memchr2};
+
+/// A borrowed N-Triples statement, split in a single pass over the line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Triple<'a> {
+    pub subject: &'a str,
+    pub predicate: &'a str,
+    /// Whole object term, including quotes and language tag or datatype
+    pub object: &'a str,
+    /// Lexical form of a literal object (still escaped)
+    pub lexical: Option<&'a str>,
+    /// Language tag of a literal object
+    pub language: Option<&'a str>,
 }
 
-#[allow(dead_code)]
-impl NTriple {
-    /// Parse an N-Triples line
-    pub fn parse(line: &str) -> Option<Self> {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with('#') {
+/// Length of an IRI (`<...>`) or blank node (`_:...`) term at the start of `s`
+fn term_len(s: &[u8]) -> Option<usize> {
+    match s.first()? {
+        b'<' => memchr(b'>', s).map(|end| end + 1),
+        b'_' => Some(
+            s.iter()
+                .position(|b| b.is_ascii_whitespace())
+                .unwrap_or(s.len()),
+        ),
+        _ => None,
+    }
+}
+
+/// Position of the closing quote of a literal whose opening quote is at 0
+fn literal_end(s: &[u8]) -> Option<usize> {
+    let mut pos = 1;
+    loop {
+        pos += memchr2(b'"', b'\\', s.get(pos..)?)?;
+        if s[pos] == b'"' {
+            return Some(pos);
+        }
+        // Skip the escaped character
+        pos += 2;
+    }
+}
+
+fn skip_whitespace(s: &[u8], pos: usize) -> usize {
+    pos + s[pos..]
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(s.len() - pos)
+}
+
+impl<'a> Triple<'a> {
+    /// Split an N-Triples line into its terms
+    ///
+    /// Returns None for blank lines, comments and malformed lines.
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let bytes = line.as_bytes();
+        let start = skip_whitespace(bytes, 0);
+        if start == bytes.len() || bytes[start] == b'#' {
             return None;
         }
 
-        // N-Triples format: <subject> <predicate> <object> .
-        // or: <subject> <predicate> "literal"^^<type> .
-        // or: <subject> <predicate> "literal"@lang .
-
-        let mut parts: Vec<&str> = Vec::new();
-        let mut current_start = 0;
-        let mut in_uri = false;
-        let mut in_literal = false;
-        let mut escape_next = false;
-
-        let chars: Vec<char> = line.chars().collect();
-        for (i, &ch) in chars.iter().enumerate() {
-            if escape_next {
-                escape_next = false;
-                continue;
-            }
+        let subject_end = start + term_len(&bytes[start..])?;
+        let predicate_start = skip_whitespace(bytes, subject_end);
+        if bytes.get(predicate_start) != Some(&b'<') {
+            return None;
+        }
+        let predicate_end = predicate_start + term_len(&bytes[predicate_start..])?;
+        let object_start = skip_whitespace(bytes, predicate_end);
 
-            match ch {
-                '\\' => escape_next = true,
-                '<' if !in_literal => in_uri = true,
-                '>' if !in_literal && in_uri => {
-                    in_uri = false;
-                    if current_start < i + 1 {
-                        parts.push(&line[current_start..=i]);
-                        current_start = i + 1;
+        let mut lexical = None;
+        let mut language = None;
+        let object_end = if bytes.get(object_start) == Some(&b'"') {
+            let quote = object_start + literal_end(&bytes[object_start..])?;
+            lexical = Some(&line[object_start + 1..quote]);
+            match bytes.get(quote + 1) {
+                Some(b'@') => {
+                    let tag_start = quote + 2;
+                    let tag_len = bytes[tag_start..]
+                        .iter()
+                        .position(|&b| !(b.is_ascii_alphanumeric() || b == b'-'))
+                        .unwrap_or(bytes.len() - tag_start);
+                    if tag_len == 0 {
+                        return None;
                     }
+                    language = Some(&line[tag_start..tag_start + tag_len]);
+                    tag_start + tag_len
                 }
-                '"' if !in_uri => {
-                    if !in_literal {
-                        in_literal = true;
-                        if current_start < i {
-                            current_start = i;
-                        }
-                    } else {
-                        in_literal = false;
-                        // Include potential type/lang suffix
-                    }
-                }
-                ' ' | '\t' if !in_uri && !in_literal => {
-                    if current_start < i {
-                        let part = &line[current_start..i];
-                        if !part.trim().is_empty() && part.trim() != "." {
-                            parts.push(part.trim());
-                        }
-                    }
-                    current_start = i + 1;
+                Some(b'^') if bytes.get(quote + 2) == Some(&b'^') => {
+                    quote + 3 + term_len(&bytes[quote + 3..])?
                 }
-                _ => {}
+                _ => quote + 1,
             }
+        } else {
+            object_start + term_len(bytes.get(object_start..)?)?
+        };
+
+        let end = skip_whitespace(bytes, object_end);
+        if bytes.get(end) != Some(&b'.') {
+            return None;
         }
 
-        // Add remaining part
-        if current_start < line.len() {
-            let part = &line[current_start..];
-            let part = part.trim().trim_end_matches('.');
-            if !part.trim().is_empty() {
-                parts.push(part.trim());
+        Some(Triple {
+            subject: &line[start..subject_end],
+            predicate: &line[predicate_start..predicate_end],
+            object: &line[object_start..object_end],
+            lexical,
+            language,
+        })
+    }
+}
+
+/// Escape text for the lexical form of an N-Triples literal: quotes,
+/// backslashes and control characters
+pub fn escape_literal(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '"' => result.push_str("\\\""),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            '\u{8}' => result.push_str("\\b"),
+            '\u{c}' => result.push_str("\\f"),
+            c if c.is_control() && (c as u32) < 0x80 => {
+                result.push_str(&format!("\\u{:04X}", c as u32));
             }
+            _ => result.push(c),
         }
+    }
+    result
+}
 
-        if parts.len() >= 3 {
-            Some(NTriple {
-                subject: parts[0].to_string(),
-                predicate: parts[1].to_string(),
-                object: parts[2..]
-                    .join(" ")
-                    .trim_end_matches(" .")
-                    .trim_end_matches('.')
-                    .trim()
-                    .to_string(),
-                raw: line.to_string(),
-            })
-        } else {
-            None
+/// Text of the lexical form of an N-Triples literal, with its escapes
+/// resolved; invalid escapes are kept as they are
+pub fn unescape_literal(lexical: &str) -> Cow<'_, str> {
+    if !lexical.contains('\\') {
+        return Cow::Borrowed(lexical);
+    }
+    let mut text = String::with_capacity(lexical.len());
+    let mut rest = lexical;
+    while let Some(pos) = rest.find('\\') {
+        text.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let (c, len) = match rest.as_bytes().get(1) {
+            Some(b't') => (Some('\t'), 2),
+            Some(b'b') => (Some('\u{8}'), 2),
+            Some(b'n') => (Some('\n'), 2),
+            Some(b'r') => (Some('\r'), 2),
+            Some(b'f') => (Some('\u{c}'), 2),
+            Some(&b) if matches!(b, b'"' | b'\'' | b'\\') => (Some(b as char), 2),
+            Some(b'u') => unescape_code_point(rest),
+            Some(b'U') => (hex_char(rest.get(2..10)), 10),
+            _ => (None, 1),
+        };
+        match c {
+            Some(c) => {
+                text.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                text.push('\\');
+                rest = &rest[1..];
+            }
         }
     }
+    text.push_str(rest);
+    Cow::Owned(text)
+}
+
+/// The character of a `\uXXXX` escape and its length, joining a UTF-16
+/// surrogate pair as some writers use for characters outside the BMP
+fn unescape_code_point(escape: &str) -> (Option<char>, usize) {
+    let Some(high) = hex_value(escape.get(2..6)) else {
+        return (None, 6);
+    };
+    if (0xD800..0xDC00).contains(&high) && escape.get(6..8) == Some("\\u") {
+        if let Some(low) = hex_value(escape.get(8..12)).filter(|low| (0xDC00..0xE000).contains(low))
+        {
+            let c = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+            return (char::from_u32(c), 12);
+        }
+    }
+    (char::from_u32(high), 6)
+}
+
+fn hex_value(hex: Option<&str>) -> Option<u32> {
+    let hex = hex.filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))?;
+    u32::from_str_radix(hex, 16).ok()
+}
+
+fn hex_char(hex: Option<&str>) -> Option<char> {
+    hex_value(hex).and_then(char::from_u32)
+}
+
+/// Represents an N-Triples line (subject predicate object .)
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct NTriple {
+    pub subject: String,
+    pub predicate: String,
+    pub object: String,
+    pub raw: String,
+}
+
+#[allow(dead_code)]
+impl NTriple {
+    /// Parse an N-Triples line
+    pub fn parse(line: &str) -> Option<Self> {
+        let triple = Triple::parse(line)?;
+        Some(NTriple {
+            subject: triple.subject.to_string(),
+            predicate: triple.predicate.to_string(),
+            object: triple.object.to_string(),
+            raw: line.trim().to_string(),
+        })
+    }
 
     /// Extract entity ID from a Wikidata URI (e.g., Q31 from <http://www.wikidata.org/entity/Q31>)
     pub fn extract_entity_id(uri: &str) -> Option<String> {
@@ -177,6 +299,91 @@ mod tests {
         assert!(triple.object.contains("11825551"));
     }
 
+    #[test]
+    fn test_triple_terms() {
+        let line = r#"<http://www.wikidata.org/entity/Q183> <http://www.w3.org/2000/01/rdf-schema#label> "Deutschland"@de-ch ."#;
+        let triple = Triple::parse(line).unwrap();
+        assert_eq!(triple.subject, "<http://www.wikidata.org/entity/Q183>");
+        assert_eq!(
+            triple.predicate,
+            "<http://www.w3.org/2000/01/rdf-schema#label>"
+        );
+        assert_eq!(triple.object, r#""Deutschland"@de-ch"#);
+        assert_eq!(triple.lexical, Some("Deutschland"));
+        assert_eq!(triple.language, Some("de-ch"));
+
+        let line = r#"_:b1 <http://schema.org/name> "say \"hi\" @ \\"^^<http://www.w3.org/2001/XMLSchema#string>."#;
+        let triple = Triple::parse(line).unwrap();
+        assert_eq!(triple.subject, "_:b1");
+        assert_eq!(triple.lexical, Some(r#"say \"hi\" @ \\"#));
+        assert_eq!(triple.language, None);
+        assert!(triple.object.ends_with("#string>"));
+
+        let triple = Triple::parse("  <a> <b> _:c .  ").unwrap();
+        assert_eq!(triple.object, "_:c");
+        assert_eq!(triple.lexical, None);
+    }
+
+    #[test]
+    fn test_escape_literal() {
+        assert_eq!(escape_literal("hello"), "hello");
+        assert_eq!(escape_literal("hello\"world"), "hello\\\"world");
+        assert_eq!(escape_literal("line1\nline2"), "line1\\nline2");
+        assert_eq!(escape_literal("path\\file"), "path\\\\file");
+        assert_eq!(escape_literal("tab\there"), "tab\\there");
+        assert_eq!(escape_literal("bell\u{7}"), "bell\\u0007");
+        assert_eq!(escape_literal("Zürich 😀"), "Zürich 😀");
+    }
+
+    #[test]
+    fn test_unescape_literal() {
+        assert!(matches!(
+            unescape_literal("Zürich"),
+            Cow::Borrowed("Zürich")
+        ));
+        assert_eq!(
+            unescape_literal(r#"say \"hi\" \\ bye"#),
+            r#"say "hi" \ bye"#
+        );
+        assert_eq!(unescape_literal(r"line1\nline2\r\t"), "line1\nline2\r\t");
+        assert_eq!(unescape_literal(r"Z\u00FCrich"), "Zürich");
+        // Characters outside the BMP, as N-Triples writes them and as surrogate pairs
+        assert_eq!(unescape_literal(r"\U0001F600"), "😀");
+        assert_eq!(unescape_literal(r"\uD83D\uDE00!"), "😀!");
+        // Invalid escapes are left alone
+        assert_eq!(unescape_literal(r"\x \u12 \uD83D"), r"\x \u12 \uD83D");
+        assert_eq!(unescape_literal("trailing \\"), "trailing \\");
+
+        for text in ["say \"hi\"\n\\", "😀\u{7}\u{8}\u{c}", "'quoted'"] {
+            assert_eq!(unescape_literal(&escape_literal(text)), text);
+        }
+    }
+
+    #[test]
+    fn test_triple_language() {
+        let language = |object: &str| {
+            Triple::parse(&format!("<s> <p> {} .", object))
+                .and_then(|t| t.language)
+                .map(str::to_string)
+        };
+        assert_eq!(language(r#""Germany"@en"#), Some("en".to_string()));
+        assert_eq!(language(r#""Deutschland"@de"#), Some("de".to_string()));
+        assert_eq!(language(r#""Schweiz"@de-ch"#), Some("de-ch".to_string()));
+        assert_eq!(language("<http://example.org/thing>"), None);
+        assert_eq!(language(r#""a@b.org""#), None);
+    }
+
+    #[test]
+    fn test_triple_rejects_malformed() {
+        assert_eq!(Triple::parse(""), None);
+        assert_eq!(Triple::parse("# comment"), None);
+        assert_eq!(Triple::parse("<a> <b> <c>"), None);
+        assert_eq!(Triple::parse(r#"<a> <b> "open ."#), None);
+        assert_eq!(Triple::parse(r#"<a> "b" <c> ."#), None);
+        assert_eq!(Triple::parse(r#"<a> <b> "x"@ ."#), None);
+        assert_eq!(Triple::parse(r#"<a> <b> "x\"#), None);
+    }
+
     #[test]
     fn test_extract_entity_id() {
         assert_eq!(
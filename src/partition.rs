@@ -0,0 +1,603 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufWriter, Write};
+
+use crate::atomic::PendingFile;
+use crate::compression::{
+    create_compressed_writer, determine_compression, finish_output, FinishWrite,
+};
+use crate::naming::sanitize;
+use crate::quads;
+use crate::rdf::{OutputFormat, ProcessingOptions, RdfEntity};
+use crate::tabular;
+use crate::FilterError;
+
+/// Placeholder for the partition value in the --output template
+pub const VALUE_PLACEHOLDER: &str = "{value}";
+
+/// Placeholder for the shard number in the --output template
+pub const SHARD_PLACEHOLDER: &str = "{shard}";
+
+/// Placeholder for the file number of a split output
+pub const PART_PLACEHOLDER: &str = "{part}";
+
+/// Partition value of entities without an entity value for the property
+pub const NO_VALUE: &str = "none";
+
+/// Reject the placeholders of partitioned outputs in a single file's name
+pub fn check_placeholders(path: &str) -> Result<(), FilterError> {
+    let options = [
+        (VALUE_PLACEHOLDER, "--partition-by"),
+        (SHARD_PLACEHOLDER, "--shards"),
+        (PART_PLACEHOLDER, "--split-size or --split-entities"),
+    ];
+    match options
+        .iter()
+        .find(|(placeholder, _)| path.contains(placeholder))
+    {
+        Some((placeholder, option)) => Err(FilterError::Parse(format!(
+            "{} in '{}' needs {}",
+            placeholder, path, option
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Written file of a partitioned run, renamed to its path when closed
+pub struct PartitionWriter {
+    writer: BufWriter<Box<dyn FinishWrite>>,
+    pending: PendingFile,
+}
+
+impl PartitionWriter {
+    /// Finish the file; an interrupted run leaves it under its temporary name
+    pub fn close(self, interrupted: bool) -> io::Result<()> {
+        let Self { writer, pending } = self;
+        // A file whose compressed stream cannot be ended is not committed
+        finish_output(writer)?;
+        if interrupted {
+            pending.keep();
+            return Ok(());
+        }
+        pending.commit()
+    }
+}
+
+impl Write for PartitionWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// How entities are assigned to shards (--shard-by)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardBy {
+    /// Matched entities in turn, on the writer thread
+    RoundRobin,
+    /// Hash of the entity ID, the same shard in every run
+    Hash,
+}
+
+impl ShardBy {
+    pub fn parse(s: &str) -> Result<Self, FilterError> {
+        match s {
+            "round-robin" => Ok(ShardBy::RoundRobin),
+            "hash" => Ok(ShardBy::Hash),
+            _ => Err(FilterError::Parse(format!(
+                "Invalid --shard-by '{}'. Valid: round-robin, hash",
+                s
+            ))),
+        }
+    }
+}
+
+/// What names the file an entity is written to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionKey {
+    /// Entity values of a property (--partition-by)
+    Property(String),
+    /// One of a fixed number of files (--shards)
+    Shards { count: usize, by: ShardBy },
+    /// Numbered files of limited size, written one after another
+    Split(SplitLimits),
+}
+
+/// Largest file of a split output (--split-size, --split-entities)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitLimits {
+    /// Bytes of entities before compression (u64::MAX = no limit)
+    pub max_bytes: u64,
+    /// Entities (u64::MAX = no limit)
+    pub max_entities: u64,
+}
+
+/// Several output files named by a template (--partition-by, --shards,
+/// --split-size, --split-entities)
+#[derive(Debug, Clone)]
+pub struct Partition {
+    pub key: PartitionKey,
+    /// Output path with a `{value}`, `{shard}` or `{part}` placeholder
+    pub template: String,
+    /// Buffer size of each file
+    pub buffer_size: usize,
+}
+
+impl Partition {
+    /// Partition by `property` into files named by `template`
+    pub fn new(property: &str, template: &str, buffer_size: usize) -> Result<Self, FilterError> {
+        let property = property.trim();
+        let valid = property.len() > 1
+            && property.starts_with('P')
+            && property[1..].bytes().all(|b| b.is_ascii_digit());
+        if !valid {
+            return Err(FilterError::Parse(format!(
+                "Invalid --partition-by property '{}', expected e.g. P31",
+                property
+            )));
+        }
+        if !template.contains(VALUE_PLACEHOLDER) {
+            return Err(FilterError::Parse(format!(
+                "--partition-by needs an --output template with {}, e.g. out/{}.nt.gz",
+                VALUE_PLACEHOLDER, VALUE_PLACEHOLDER
+            )));
+        }
+        Ok(Self {
+            key: PartitionKey::Property(property.to_string()),
+            template: template.to_string(),
+            buffer_size,
+        })
+    }
+
+    /// `count` shards named by `template`
+    pub fn shards(
+        count: usize,
+        by: ShardBy,
+        template: &str,
+        buffer_size: usize,
+    ) -> Result<Self, FilterError> {
+        if count == 0 {
+            return Err(FilterError::Parse(
+                "--shards must be at least 1".to_string(),
+            ));
+        }
+        if !template.contains(SHARD_PLACEHOLDER) {
+            return Err(FilterError::Parse(format!(
+                "--shards needs an --output template with {}, e.g. out/part-{}.nt.gz",
+                SHARD_PLACEHOLDER, SHARD_PLACEHOLDER
+            )));
+        }
+        Ok(Self {
+            key: PartitionKey::Shards { count, by },
+            template: template.to_string(),
+            buffer_size,
+        })
+    }
+
+    /// Numbered files of at most `limits`, named by the `{part}` in `output`
+    ///
+    /// Without the placeholder, the number goes before the extensions:
+    /// `dump.nt.gz` becomes `dump-00000.nt.gz`, `dump-00001.nt.gz`, ...
+    pub fn split(limits: SplitLimits, output: &str, buffer_size: usize) -> Self {
+        let template = if output.contains(PART_PLACEHOLDER) {
+            output.to_string()
+        } else {
+            let name_start = output.rfind('/').map_or(0, |i| i + 1);
+            let stem_end = output[name_start..]
+                .find('.')
+                .map_or(output.len(), |i| name_start + i);
+            format!(
+                "{}-{}{}",
+                &output[..stem_end],
+                PART_PLACEHOLDER,
+                &output[stem_end..]
+            )
+        };
+        Self {
+            key: PartitionKey::Split(limits),
+            template,
+            buffer_size,
+        }
+    }
+
+    /// Short description for --dry-run, e.g. `by P31` or `4 shards by hash`
+    pub fn describe(&self) -> String {
+        match self.key {
+            PartitionKey::Property(ref property) => format!("by {}", property),
+            PartitionKey::Shards { count, by } => {
+                let by = match by {
+                    ShardBy::RoundRobin => "round-robin",
+                    ShardBy::Hash => "hash",
+                };
+                format!("{} shards by {}", count, by)
+            }
+            PartitionKey::Split(limits) => {
+                let mut parts = Vec::new();
+                if limits.max_bytes != u64::MAX {
+                    parts.push(format!("{} bytes", limits.max_bytes));
+                }
+                if limits.max_entities != u64::MAX {
+                    parts.push(format!("{} entities", limits.max_entities));
+                }
+                format!("files of at most {}", parts.join(" and "))
+            }
+        }
+    }
+
+    /// Limits of a split output
+    pub fn split_limits(&self) -> Option<SplitLimits> {
+        match self.key {
+            PartitionKey::Split(limits) => Some(limits),
+            _ => None,
+        }
+    }
+
+    /// Number of shards dealt in turn on the writer thread (round-robin)
+    pub fn round_robin(&self) -> Option<usize> {
+        match self.key {
+            PartitionKey::Shards {
+                count,
+                by: ShardBy::RoundRobin,
+            } => Some(count),
+            _ => None,
+        }
+    }
+
+    /// Names of the files that exist whether or not entities go to them
+    pub fn fixed_values(&self) -> Vec<String> {
+        match self.key {
+            PartitionKey::Shards { count, .. } => (0..count).map(|i| self.shard_name(i)).collect(),
+            PartitionKey::Split(_) => vec![self.shard_name(0)],
+            PartitionKey::Property(_) => Vec::new(),
+        }
+    }
+
+    /// Shard number padded to the width of the largest, so the files sort
+    ///
+    /// The number of split files is not known in advance, they get 5 digits.
+    pub fn shard_name(&self, shard: usize) -> String {
+        let count = match self.key {
+            PartitionKey::Shards { count, .. } => count,
+            PartitionKey::Split(_) => 100_000,
+            PartitionKey::Property(_) => 1,
+        };
+        let width = (count - 1).to_string().len();
+        format!("{:0width$}", shard, width = width)
+    }
+
+    /// Shard of an entity ID (FNV-1a, stable across runs and platforms)
+    fn hash_shard(&self, id: &str, count: usize) -> String {
+        let hash = id.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+        });
+        self.shard_name((hash % count as u64) as usize)
+    }
+
+    /// Partition values of an RDF entity, sorted, or `none`
+    ///
+    /// Empty for round-robin shards and split files, which are assigned when writing.
+    pub fn rdf_values(&self, entity: &RdfEntity) -> Vec<String> {
+        let property = match self.key {
+            PartitionKey::Property(ref property) => property,
+            PartitionKey::Shards {
+                count,
+                by: ShardBy::Hash,
+            } => return vec![self.hash_shard(&entity.id, count)],
+            PartitionKey::Shards { .. } | PartitionKey::Split(_) => return Vec::new(),
+        };
+        match entity.claims.get(property) {
+            Some(values) if !values.is_empty() => values
+                .iter()
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .cloned()
+                .collect(),
+            _ => vec![NO_VALUE.to_string()],
+        }
+    }
+
+    /// Partition values of a JSON entity (entity IDs of its main snaks), sorted, or `none`
+    ///
+    /// Empty for round-robin shards and split files, which are assigned when writing.
+    pub fn json_values(&self, entity: &serde_json::Value) -> Vec<String> {
+        let property = match self.key {
+            PartitionKey::Property(ref property) => property,
+            PartitionKey::Shards {
+                count,
+                by: ShardBy::Hash,
+            } => {
+                let id = entity.get("id").and_then(|id| id.as_str());
+                return vec![self.hash_shard(id.unwrap_or_default(), count)];
+            }
+            PartitionKey::Shards { .. } | PartitionKey::Split(_) => return Vec::new(),
+        };
+        let statements = entity
+            .get("claims")
+            .and_then(|claims| claims.get(property))
+            .and_then(|statements| statements.as_array());
+        let values: BTreeSet<&str> = statements
+            .into_iter()
+            .flatten()
+            .filter_map(|statement| statement.pointer("/mainsnak/datavalue/value/id")?.as_str())
+            .collect();
+        if values.is_empty() {
+            return vec![NO_VALUE.to_string()];
+        }
+        values.into_iter().map(str::to_string).collect()
+    }
+
+    /// Path of the file for a partition value
+    pub fn path(&self, value: &str) -> String {
+        let value = sanitize(value);
+        let placeholder = match self.key {
+            PartitionKey::Property(_) => VALUE_PLACEHOLDER,
+            PartitionKey::Shards { .. } => SHARD_PLACEHOLDER,
+            PartitionKey::Split(_) => PART_PLACEHOLDER,
+        };
+        self.template.replace(placeholder, &value)
+    }
+
+    /// Create the file of a partition value with its directory and preamble
+    pub fn open(&self, value: &str, options: &ProcessingOptions) -> io::Result<PartitionWriter> {
+        let path = self.path(value);
+        let compression = determine_compression("none", Some(&path));
+        let opened = PendingFile::new(&path, options.no_clobber, options.http_retries).and_then(
+            |mut pending| {
+                let file = pending.create()?;
+                Ok((create_compressed_writer(file, &compression), pending))
+            },
+        );
+        let (writer, pending) = opened.map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Cannot create partition file {}: {}", path, e),
+            )
+        })?;
+        let mut writer = PartitionWriter {
+            writer: BufWriter::with_capacity(self.buffer_size, writer),
+            pending,
+        };
+        write_preamble(&mut writer, options)?;
+        Ok(writer)
+    }
+}
+
+/// Move `items` to `n` lists, item i to list (start + i) % n (round-robin shards)
+pub fn deal<T>(items: Vec<T>, n: usize, start: usize) -> Vec<Vec<T>> {
+    let mut dealt: Vec<Vec<T>> = (0..n)
+        .map(|_| Vec::with_capacity(items.len() / n + 1))
+        .collect();
+    for (i, item) in items.into_iter().enumerate() {
+        dealt[(start + i) % n].push(item);
+    }
+    dealt
+}
+
+/// Batch results that can be cut at entity boundaries (split output)
+pub trait EntityChunks: Sized {
+    fn entity_count(&self) -> usize;
+
+    /// Bytes of the entities, None if only known once written (Arrow, GraphML)
+    fn byte_sizes(&self) -> Option<Vec<u64>>;
+
+    /// Remove and return the first `n` entities
+    fn split_to(&mut self, n: usize) -> Self;
+}
+
+/// Position of a split output: current file and what it holds
+#[derive(Debug, Default)]
+pub struct SplitState {
+    pub part: usize,
+    entities: u64,
+    bytes: u64,
+}
+
+impl SplitState {
+    /// Cut the entities of `results` that go to the current file
+    ///
+    /// When the current file is full, it moves on to the next one and
+    /// returns the number of the finished file along with the chunk. A single
+    /// entity larger than the size limit gets a file of its own.
+    pub fn next_chunk<R: EntityChunks>(
+        &mut self,
+        limits: SplitLimits,
+        results: &mut R,
+    ) -> Option<(R, Option<usize>)> {
+        let mut finished = None;
+        loop {
+            let count = results.entity_count();
+            if count == 0 {
+                return None;
+            }
+            let remaining = limits.max_entities - self.entities;
+            let mut n = count.min(remaining.min(usize::MAX as u64) as usize);
+            let sizes = results
+                .byte_sizes()
+                .filter(|_| limits.max_bytes != u64::MAX);
+            if let Some(ref sizes) = sizes {
+                let budget = limits.max_bytes.saturating_sub(self.bytes);
+                let mut total = 0;
+                let fitting = sizes
+                    .iter()
+                    .take_while(|&&size| {
+                        total += size;
+                        total <= budget
+                    })
+                    .count();
+                n = n.min(fitting);
+            }
+            if n == 0 && self.entities == 0 {
+                n = 1;
+            }
+            if n == 0 {
+                finished = Some(self.part);
+                self.part += 1;
+                self.entities = 0;
+                self.bytes = 0;
+                continue;
+            }
+            self.entities += n as u64;
+            self.bytes += sizes.map_or(0, |sizes| sizes[..n].iter().sum());
+            return Some((results.split_to(n), finished));
+        }
+    }
+}
+
+/// Write what precedes the entities of an output file (TriG prefixes, CSV/TSV header)
+pub fn write_preamble<W: Write>(output: &mut W, options: &ProcessingOptions) -> io::Result<()> {
+    match options.output_format {
+        OutputFormat::TriG => quads::write_trig_prefixes(output),
+        format @ (OutputFormat::Csv | OutputFormat::Tsv) => {
+            tabular::write_header(output, &options.table, format)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_partition_values() {
+        let partition = Partition::new("P31", "out/{value}.nt", 1024).unwrap();
+        let mut entity = RdfEntity::new("Q1");
+        assert_eq!(partition.rdf_values(&entity), ["none"]);
+        entity.claims.insert(
+            "P31".to_string(),
+            ["Q5".to_string(), "Q15632617".to_string()].into(),
+        );
+        assert_eq!(partition.rdf_values(&entity), ["Q15632617", "Q5"]);
+
+        let entity = serde_json::json!({
+            "id": "Q2",
+            "claims": {"P31": [
+                {"mainsnak": {"datavalue": {"value": {"entity-type": "item", "id": "Q6256"}}}},
+                {"mainsnak": {"snaktype": "somevalue"}}
+            ]}
+        });
+        assert_eq!(partition.json_values(&entity), ["Q6256"]);
+        assert_eq!(
+            partition.json_values(&serde_json::json!({"id": "Q3"})),
+            ["none"]
+        );
+    }
+
+    #[test]
+    fn test_partition_path() {
+        let partition = Partition::new("P31", "out/{value}.ndjson.gz", 1024).unwrap();
+        assert_eq!(partition.path("Q5"), "out/Q5.ndjson.gz");
+        assert_eq!(partition.path("../x"), "out/___x.ndjson.gz");
+        assert!(Partition::new("P31", "out.nt", 1024).is_err());
+        assert!(Partition::new("31", "{value}.nt", 1024).is_err());
+        assert!(check_placeholders("out/{shard}.nt").is_err());
+        assert!(check_placeholders("out/{date}.nt").is_ok());
+    }
+
+    #[test]
+    fn test_shards() {
+        let shards = Partition::shards(12, ShardBy::Hash, "out/part-{shard}.nt", 1024).unwrap();
+        assert_eq!(shards.fixed_values().first().unwrap(), "00");
+        assert_eq!(shards.path("11"), "out/part-11.nt");
+        let entity = RdfEntity::new("Q42");
+        let json = serde_json::json!({"id": "Q42"});
+        assert_eq!(shards.rdf_values(&entity), shards.json_values(&json));
+        assert!(Partition::shards(4, ShardBy::Hash, "out/{value}.nt", 1024).is_err());
+
+        let round_robin = Partition::shards(3, ShardBy::RoundRobin, "{shard}.nt", 1024).unwrap();
+        assert!(round_robin.rdf_values(&entity).is_empty());
+        assert_eq!(deal(vec![1, 2, 3, 4], 3, 2), [vec![2], vec![3], vec![1, 4]]);
+    }
+
+    impl EntityChunks for Vec<u64> {
+        fn entity_count(&self) -> usize {
+            self.len()
+        }
+
+        fn byte_sizes(&self) -> Option<Vec<u64>> {
+            Some(self.clone())
+        }
+
+        fn split_to(&mut self, n: usize) -> Self {
+            self.drain(..n).collect()
+        }
+    }
+
+    #[test]
+    fn test_split_chunks() {
+        let limits = SplitLimits {
+            max_bytes: 10,
+            max_entities: 3,
+        };
+        let mut state = SplitState::default();
+        let mut chunks = Vec::new();
+        for mut batch in [vec![4, 4, 4], vec![20, 1, 1, 1, 1]] {
+            while let Some(chunk) = state.next_chunk(limits, &mut batch) {
+                chunks.push(chunk);
+            }
+        }
+        // Cut by size, by count, and an oversized entity alone
+        assert_eq!(
+            chunks,
+            [
+                (vec![4, 4], None),
+                (vec![4], Some(0)),
+                (vec![20], Some(1)),
+                (vec![1, 1, 1], Some(2)),
+                (vec![1], Some(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_template() {
+        let limits = SplitLimits {
+            max_bytes: u64::MAX,
+            max_entities: 10,
+        };
+        let split = Partition::split(limits, "out/dump.nt.gz", 1024);
+        assert_eq!(split.path(&split.shard_name(3)), "out/dump-00003.nt.gz");
+        let split = Partition::split(limits, "./out/part{part}.nt", 1024);
+        assert_eq!(split.path(&split.shard_name(0)), "./out/part00000.nt");
+    }
+
+    /// File writer that fails once the compressed stream is finished
+    struct FailingFlush(Box<dyn Write>);
+
+    impl Write for FailingFlush {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::other("disk full"))
+        }
+    }
+
+    #[test]
+    fn test_failed_finish_leaves_target() {
+        let dir = TempDir::new("partition-finish");
+        let path = dir.file("out.nt.gz");
+        std::fs::write(&path, "previous\n").unwrap();
+
+        let mut pending = PendingFile::new(&path, false, 0).unwrap();
+        let file = FailingFlush(pending.create().unwrap());
+        let mut writer = PartitionWriter {
+            writer: BufWriter::new(create_compressed_writer(file, "gzip")),
+            pending,
+        };
+        writer.write_all(b"<a> <b> <c> .\n").unwrap();
+        assert!(writer.close(false).is_err());
+
+        // The old file stays in place and the partial one is removed
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "previous\n");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
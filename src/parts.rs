@@ -0,0 +1,107 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::compression::{detect_format_from_path, strip_compression_suffix};
+use crate::rdf::ProcessingOptions;
+
+/// JSON dump files in a directory of dump parts, in name order
+///
+/// Other files (checksums, index pages) are left out.
+pub fn list_parts(dir: &str) -> io::Result<Vec<String>> {
+    let mut parts = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || !entry.file_type()?.is_file() {
+            continue;
+        }
+        let lower = name.to_lowercase();
+        let stem = strip_compression_suffix(&lower);
+        if stem.ends_with(".json") || stem.ends_with(".ndjson") {
+            parts.push(entry.path().to_string_lossy().into_owned());
+        }
+    }
+    if parts.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no .json or .ndjson dump parts in {}", dir),
+        ));
+    }
+    parts.sort();
+    Ok(parts)
+}
+
+/// Input format of a dump directory: that of its parts unless --format is given
+pub fn format(format_arg: &str, parts: &[String]) -> String {
+    if format_arg == "auto" {
+        parts
+            .first()
+            .map_or_else(|| "json".to_string(), |part| detect_format_from_path(part))
+    } else {
+        format_arg.to_string()
+    }
+}
+
+/// Why a dump directory cannot be read with `options`, if it cannot
+///
+/// The parts are read in parallel, so nothing that depends on a single
+/// position in the input applies.
+pub fn unsupported_reason(format: &str, options: &ProcessingOptions) -> Option<&'static str> {
+    if !matches!(format, "json" | "ndjson") {
+        return Some("only holds JSON dump parts");
+    }
+    if options.skip_lines > 0 || options.max_lines < u64::MAX {
+        return Some("does not support --skip-lines/--max-lines");
+    }
+    if options.skip_entities > 0 {
+        return Some("does not support --skip-entities");
+    }
+    if options.checkpoint.is_some() || options.resume_position != (0, 0) {
+        return Some("does not support --checkpoint/--resume");
+    }
+    None
+}
+
+/// Whether `path` is a directory of dump parts rather than a single input
+pub fn is_dump_directory(path: &str) -> bool {
+    Path::new(path).is_dir()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_list_parts() {
+        let dir = TempDir::new("parts");
+        fs::create_dir_all(dir.join("nested.json")).unwrap();
+        for name in [
+            "b.json.gz",
+            "a.ndjson",
+            "c.json.bz2",
+            "md5sums.txt",
+            ".hidden.json",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let dir_path = dir.path().to_string_lossy().into_owned();
+        let names: Vec<String> = list_parts(&dir_path)
+            .unwrap()
+            .iter()
+            .map(|part| {
+                Path::new(part)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        assert_eq!(names, ["a.ndjson", "b.json.gz", "c.json.bz2"]);
+        assert!(is_dump_directory(&dir_path));
+
+        let empty = TempDir::new("parts");
+        assert!(list_parts(&empty.path().to_string_lossy()).is_err());
+    }
+}
@@ -0,0 +1,511 @@
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/// Worker and queue settings for the reader/worker/writer pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineConfig {
+    /// Number of filter worker threads
+    pub workers: usize,
+    /// Maximum number of batches between reader and writer
+    pub queue_depth: usize,
+    /// Write results in input order; otherwise as soon as a worker finishes them
+    pub ordered: bool,
+    /// Most input bytes of batches between reader and writer (--max-memory)
+    pub max_bytes: Option<u64>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        let workers = thread::available_parallelism().map_or(4, |n| n.get());
+        Self {
+            workers,
+            queue_depth: 2 * workers,
+            ordered: true,
+            max_bytes: None,
+        }
+    }
+}
+
+impl PipelineConfig {
+    /// Settings from `--threads` and `--queue-depth`, defaulting to one worker per CPU
+    pub fn new(workers: Option<usize>, queue_depth: Option<usize>) -> Self {
+        let workers = workers
+            .filter(|&n| n > 0)
+            .unwrap_or_else(|| Self::default().workers);
+        Self {
+            workers,
+            queue_depth: queue_depth.filter(|&n| n > 0).unwrap_or(2 * workers),
+            ordered: true,
+            max_bytes: None,
+        }
+    }
+}
+
+/// Batches and their input bytes between reader and writer
+#[derive(Default)]
+struct State {
+    batches: usize,
+    bytes: u64,
+    stopped: bool,
+}
+
+/// Counts the batches between reader and writer
+#[derive(Default)]
+struct InFlight {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl InFlight {
+    /// Wait until `ready(count)` holds; returns false once the writer has stopped
+    fn wait_for(&self, ready: impl Fn(usize) -> bool) -> bool {
+        let mut state = self.state.lock().expect("pipeline state poisoned");
+        while !ready(state.batches) && !state.stopped {
+            state = self.changed.wait(state).expect("pipeline state poisoned");
+        }
+        !state.stopped
+    }
+
+    /// Wait for room below `depth` batches and `max_bytes` and take it;
+    /// returns false once the writer has stopped. A batch larger than
+    /// `max_bytes` is let in alone.
+    fn acquire(&self, depth: usize, bytes: u64, max_bytes: u64) -> bool {
+        let mut state = self.state.lock().expect("pipeline state poisoned");
+        while (state.batches >= depth
+            || (state.batches > 0 && state.bytes.saturating_add(bytes) > max_bytes))
+            && !state.stopped
+        {
+            state = self.changed.wait(state).expect("pipeline state poisoned");
+        }
+        if state.stopped {
+            return false;
+        }
+        state.batches += 1;
+        state.bytes += bytes;
+        true
+    }
+
+    /// Free the room of a written batch
+    fn release(&self, bytes: u64) {
+        self.update(|state| {
+            state.batches -= 1;
+            state.bytes -= bytes;
+        });
+    }
+
+    fn stop(&self) {
+        self.update(|state| state.stopped = true);
+    }
+
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        f(&mut self.state.lock().expect("pipeline state poisoned"));
+        self.changed.notify_all();
+    }
+}
+
+/// Hands batches from the reader thread to the workers
+///
+/// Clones share the queue, so several reader threads can emit batches;
+/// each batch is written in the order it was emitted.
+pub struct Emitter<'a, T> {
+    in_flight: &'a InFlight,
+    depth: usize,
+    max_bytes: u64,
+    work: SyncSender<(u64, u64, T)>,
+    next_seq: &'a AtomicU64,
+}
+
+impl<T> Clone for Emitter<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            in_flight: self.in_flight,
+            depth: self.depth,
+            max_bytes: self.max_bytes,
+            work: self.work.clone(),
+            next_seq: self.next_seq,
+        }
+    }
+}
+
+impl<T> Emitter<'_, T> {
+    /// Queue a batch, blocking while `queue_depth` batches are in flight.
+    /// Returns false if the writer has stopped and reading should end.
+    pub fn emit(&mut self, batch: T) -> bool {
+        self.emit_sized(batch, 0)
+    }
+
+    /// Queue a batch of `bytes` input bytes, blocking also while the batches
+    /// in flight hold `max_bytes`
+    pub fn emit_sized(&mut self, batch: T, bytes: u64) -> bool {
+        if !self.in_flight.acquire(self.depth, bytes, self.max_bytes) {
+            return false;
+        }
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        self.work.send((seq, bytes, batch)).is_ok()
+    }
+
+    /// Input bytes at which a reader should cut a batch, so that a full
+    /// queue fits into `max_bytes`
+    pub fn batch_bytes(&self) -> u64 {
+        self.max_bytes / (self.depth as u64 + 1)
+    }
+
+    /// Block until every queued batch has been written
+    pub fn wait_idle(&self) -> bool {
+        self.in_flight.wait_for(|n| n == 0)
+    }
+}
+
+/// Run `produce` on a reader thread, `work` on the worker threads and
+/// `consume` on the calling thread, which receives results in input order
+/// (or in completion order if `config.ordered` is false)
+///
+/// Backpressure: the reader blocks once `queue_depth` batches, or batches of
+/// `max_bytes` input bytes, are queued, being filtered or waiting to be
+/// written. If `consume` fails, the reader
+/// is stopped and the error returned; otherwise a reader error is returned.
+/// A panic in `work` stops the pipeline and is resumed on the calling thread.
+pub fn run<T, U, E, P, F, C>(
+    config: PipelineConfig,
+    produce: P,
+    work: F,
+    mut consume: C,
+) -> Result<(), E>
+where
+    T: Send,
+    U: Send,
+    E: Send,
+    P: FnOnce(&mut Emitter<T>) -> Result<(), E> + Send,
+    F: Fn(T) -> U + Sync,
+    C: FnMut(U) -> Result<(), E>,
+{
+    let depth = config.queue_depth.max(1);
+    let max_bytes = config.max_bytes.unwrap_or(u64::MAX);
+    let in_flight = InFlight::default();
+    let next_seq = AtomicU64::new(0);
+    let (work_tx, work_rx) = mpsc::sync_channel::<(u64, u64, T)>(depth);
+    let (result_tx, result_rx) = mpsc::sync_channel::<(u64, u64, U)>(depth);
+    let work_rx = Mutex::new(work_rx);
+
+    thread::scope(|scope| {
+        let in_flight = &in_flight;
+        let next_seq = &next_seq;
+        let reader = scope.spawn(move || {
+            let mut emitter = Emitter {
+                in_flight,
+                depth,
+                max_bytes,
+                work: work_tx,
+                next_seq,
+            };
+            produce(&mut emitter)
+        });
+
+        for _ in 0..config.workers.max(1) {
+            let result_tx = result_tx.clone();
+            let work_rx = &work_rx;
+            let work = &work;
+            scope.spawn(move || loop {
+                let next = work_rx.lock().map(|rx| rx.recv());
+                let (seq, bytes, batch) = match next {
+                    Ok(Ok(item)) => item,
+                    _ => break,
+                };
+                // A panicking worker never sends its result; stop the reader
+                // and writer waiting for it before passing the panic on
+                let result = match panic::catch_unwind(AssertUnwindSafe(|| work(batch))) {
+                    Ok(result) => result,
+                    Err(payload) => {
+                        in_flight.stop();
+                        panic::resume_unwind(payload);
+                    }
+                };
+                if result_tx.send((seq, bytes, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_tx);
+
+        let written = if config.ordered {
+            write_in_order(&result_rx, in_flight, &mut consume)
+        } else {
+            write_unordered(&result_rx, in_flight, &mut consume)
+        };
+        // Unblock and stop the reader and workers if writing failed early
+        in_flight.stop();
+        drop(result_rx);
+        let read = reader.join().expect("pipeline reader thread panicked");
+        written.and(read)
+    })
+}
+
+/// Pass results to `consume` by sequence number
+fn write_in_order<U, E>(
+    results: &Receiver<(u64, u64, U)>,
+    in_flight: &InFlight,
+    consume: &mut impl FnMut(U) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut pending = BTreeMap::new();
+    let mut next_seq = 0;
+    for (seq, bytes, result) in results {
+        pending.insert(seq, (bytes, result));
+        while let Some((bytes, result)) = pending.remove(&next_seq) {
+            consume(result)?;
+            next_seq += 1;
+            in_flight.release(bytes);
+        }
+    }
+    Ok(())
+}
+
+/// Pass results to `consume` as they arrive
+fn write_unordered<U, E>(
+    results: &Receiver<(u64, u64, U)>,
+    in_flight: &InFlight,
+    consume: &mut impl FnMut(U) -> Result<(), E>,
+) -> Result<(), E> {
+    for (_, bytes, result) in results {
+        consume(result)?;
+        in_flight.release(bytes);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_results_in_input_order() {
+        let config = PipelineConfig {
+            workers: 4,
+            queue_depth: 3,
+            ordered: true,
+            max_bytes: None,
+        };
+        let mut output = Vec::new();
+        run(
+            config,
+            |emitter: &mut Emitter<u64>| -> Result<(), String> {
+                for i in 0..50 {
+                    assert!(emitter.emit(i));
+                }
+                Ok(())
+            },
+            |i| {
+                // Later batches finish first
+                thread::sleep(Duration::from_micros(50 - i));
+                i * 2
+            },
+            |result| {
+                output.push(result);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(output, (0..50).map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_unordered_results() {
+        let config = PipelineConfig {
+            workers: 4,
+            queue_depth: 8,
+            ordered: false,
+            max_bytes: None,
+        };
+        let mut output = Vec::new();
+        run(
+            config,
+            |emitter: &mut Emitter<u64>| -> Result<(), String> {
+                for i in 0..50 {
+                    assert!(emitter.emit(i));
+                }
+                Ok(())
+            },
+            |i| i * 2,
+            |result| {
+                output.push(result);
+                Ok(())
+            },
+        )
+        .unwrap();
+        output.sort();
+        assert_eq!(output, (0..50).map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_writer_error_stops_reader() {
+        let config = PipelineConfig {
+            workers: 2,
+            queue_depth: 2,
+            ordered: true,
+            max_bytes: None,
+        };
+        let mut emitted = 0;
+        let result = run(
+            config,
+            |emitter: &mut Emitter<u32>| -> Result<(), String> {
+                for i in 0..1_000_000 {
+                    if !emitter.emit(i) {
+                        break;
+                    }
+                    emitted += 1;
+                }
+                Ok(())
+            },
+            |i| i,
+            |i| {
+                if i == 5 {
+                    Err("disk full".to_string())
+                } else {
+                    Ok(())
+                }
+            },
+        );
+        assert_eq!(result, Err("disk full".to_string()));
+        assert!(emitted < 100);
+    }
+
+    #[test]
+    fn test_worker_panic_stops_pipeline() {
+        let config = PipelineConfig {
+            workers: 2,
+            queue_depth: 2,
+            ordered: true,
+            max_bytes: None,
+        };
+        let result = panic::catch_unwind(|| {
+            run(
+                config,
+                |emitter: &mut Emitter<u32>| -> Result<(), String> {
+                    for i in 0..1_000_000 {
+                        if !emitter.emit(i) {
+                            break;
+                        }
+                    }
+                    Ok(())
+                },
+                |i| {
+                    assert_ne!(i, 5, "bad batch");
+                    i
+                },
+                |_| Ok(()),
+            )
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_byte_limit() {
+        use std::sync::atomic::AtomicUsize;
+        let config = PipelineConfig {
+            workers: 4,
+            queue_depth: 100,
+            ordered: true,
+            max_bytes: Some(100),
+        };
+        let (emitted, written) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        run(
+            config,
+            |emitter: &mut Emitter<usize>| -> Result<(), String> {
+                for i in 0..50 {
+                    assert!(emitter.emit_sized(i, 40));
+                    let emitted = emitted.fetch_add(1, Ordering::SeqCst) + 1;
+                    // At most two batches of 40 bytes are in flight
+                    assert!(emitted - written.load(Ordering::SeqCst) <= 2);
+                }
+                // A batch over the limit is let in alone
+                assert!(emitter.emit_sized(50, 500));
+                Ok(())
+            },
+            |i| i,
+            |_| {
+                thread::sleep(Duration::from_micros(100));
+                written.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(written.load(Ordering::SeqCst), 51);
+    }
+
+    #[test]
+    fn test_wait_idle() {
+        let written = std::sync::atomic::AtomicUsize::new(0);
+        run(
+            PipelineConfig::new(Some(2), Some(8)),
+            |emitter: &mut Emitter<usize>| -> Result<(), String> {
+                for i in 0..5 {
+                    emitter.emit(i);
+                }
+                assert!(emitter.wait_idle());
+                assert_eq!(written.load(std::sync::atomic::Ordering::SeqCst), 5);
+                Ok(())
+            },
+            |i| i,
+            |_| {
+                written.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Ok(())
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_cloned_emitters() {
+        let mut output = Vec::new();
+        run(
+            PipelineConfig::new(Some(2), Some(4)),
+            |emitter: &mut Emitter<u32>| -> Result<(), String> {
+                thread::scope(|scope| {
+                    for reader in 0..3 {
+                        let mut emitter = emitter.clone();
+                        scope.spawn(move || {
+                            for i in 0..20 {
+                                assert!(emitter.emit(reader * 100 + i));
+                            }
+                        });
+                    }
+                });
+                Ok(())
+            },
+            |i| i,
+            |i| {
+                output.push(i);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(output.len(), 60);
+        // Each reader's batches stay in its own order
+        for reader in 0..3 {
+            let own: Vec<u32> = output
+                .iter()
+                .copied()
+                .filter(|i| i / 100 == reader)
+                .collect();
+            assert_eq!(own, (0..20).map(|i| reader * 100 + i).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_reader_error_is_returned() {
+        let result = run(
+            PipelineConfig::default(),
+            |emitter: &mut Emitter<u32>| {
+                emitter.emit(1);
+                Err("bad input".to_string())
+            },
+            |i| i,
+            |_| Ok(()),
+        );
+        assert_eq!(result, Err("bad input".to_string()));
+    }
+}
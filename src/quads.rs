@@ -0,0 +1,232 @@
+use std::io::Write;
+
+use crate::ntriples::NTriple;
+use crate::rdf::OutputFormat;
+
+/// Namespace prefixes declared at the top of TriG output
+pub const TRIG_PREFIXES: &[(&str, &str)] = &[
+    ("wd", "http://www.wikidata.org/entity/"),
+    ("wdt", "http://www.wikidata.org/prop/direct/"),
+    ("wdtn", "http://www.wikidata.org/prop/direct-normalized/"),
+    ("wdata", "https://www.wikidata.org/wiki/Special:EntityData/"),
+    ("wikibase", "http://wikiba.se/ontology#"),
+    ("rdf", "http://www.w3.org/1999/02/22-rdf-syntax-ns#"),
+    ("rdfs", "http://www.w3.org/2000/01/rdf-schema#"),
+    ("schema", "http://schema.org/"),
+    ("skos", "http://www.w3.org/2004/02/skos/core#"),
+    ("xsd", "http://www.w3.org/2001/XMLSchema#"),
+    ("geo", "http://www.opengis.net/ont/geosparql#"),
+];
+
+/// Named graph that holds all triples of an entity
+pub fn entity_graph_iri(entity_id: &str) -> String {
+    format!(
+        "<https://www.wikidata.org/wiki/Special:EntityData/{}>",
+        entity_id
+    )
+}
+
+/// Write the TriG prefix declarations
+pub fn write_trig_prefixes<W: Write>(output: &mut W) -> std::io::Result<()> {
+    let mut buffer = String::new();
+    for (prefix, namespace) in TRIG_PREFIXES {
+        buffer.push_str(&format!("@prefix {}: <{}> .\n", prefix, namespace));
+    }
+    buffer.push('\n');
+    output.write_all(buffer.as_bytes())
+}
+
+/// Append the triples of one entity to `buffer` as a per-entity graph
+///
+/// N-Quads output gets the graph IRI as fourth term on every line, TriG output
+/// gets one `graph { ... }` block per entity. Every written line ends with '\n'.
+pub fn push_entity_graph<'a, I>(
+    buffer: &mut String,
+    entity_id: &str,
+    lines: I,
+    format: OutputFormat,
+) where
+    I: IntoIterator<Item = &'a str>,
+{
+    let graph = entity_graph_iri(entity_id);
+    match format {
+        OutputFormat::TriG => push_trig_graph(buffer, &graph, lines),
+        _ => push_nquads(buffer, &graph, lines),
+    }
+}
+
+/// Append N-Triples lines as N-Quads in the given graph
+fn push_nquads<'a, I>(buffer: &mut String, graph: &str, lines: I)
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    for line in lines {
+        let statement = line.trim_end();
+        let statement = statement.strip_suffix('.').unwrap_or(statement).trim_end();
+        if statement.is_empty() {
+            continue;
+        }
+        buffer.push_str(statement);
+        buffer.push(' ');
+        buffer.push_str(graph);
+        buffer.push_str(" .\n");
+    }
+}
+
+/// Append N-Triples lines as a TriG graph block, grouping consecutive
+/// triples with the same subject using `;`
+fn push_trig_graph<'a, I>(buffer: &mut String, graph: &str, lines: I)
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    buffer.push_str(&abbreviate_term(graph));
+    buffer.push_str(" {\n");
+
+    let mut previous_subject: Option<String> = None;
+    for line in lines {
+        let triple = match NTriple::parse(line) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        let predicate = abbreviate_term(&triple.predicate);
+        let object = abbreviate_term(&triple.object);
+
+        if previous_subject.as_deref() == Some(triple.subject.as_str()) {
+            buffer.push_str(" ;\n        ");
+        } else {
+            if previous_subject.is_some() {
+                buffer.push_str(" .\n");
+            }
+            buffer.push_str("    ");
+            buffer.push_str(&abbreviate_term(&triple.subject));
+            buffer.push(' ');
+            previous_subject = Some(triple.subject.clone());
+        }
+        buffer.push_str(&predicate);
+        buffer.push(' ');
+        buffer.push_str(&object);
+    }
+
+    if previous_subject.is_some() {
+        buffer.push_str(" .\n");
+    }
+    buffer.push_str("}\n");
+}
+
+/// Shorten an IRI term (or the datatype of a typed literal) to a prefixed name
+///
+/// Terms that cannot be expressed safely as a prefixed name are returned unchanged.
+pub fn abbreviate_term(term: &str) -> String {
+    if let Some(iri) = term.strip_prefix('<').and_then(|t| t.strip_suffix('>')) {
+        return abbreviate_iri(iri).unwrap_or_else(|| term.to_string());
+    }
+
+    // Typed literal: "value"^^<datatype>
+    if term.starts_with('"') && term.ends_with('>') {
+        if let Some(pos) = term.rfind("\"^^<") {
+            let datatype = &term[pos + 4..term.len() - 1];
+            if let Some(short) = abbreviate_iri(datatype) {
+                return format!("{}^^{}", &term[..pos + 1], short);
+            }
+        }
+    }
+
+    term.to_string()
+}
+
+/// Find a prefixed name for an IRI
+fn abbreviate_iri(iri: &str) -> Option<String> {
+    TRIG_PREFIXES.iter().find_map(|(prefix, namespace)| {
+        let local = iri.strip_prefix(namespace)?;
+        if is_safe_local_name(local) {
+            Some(format!("{}:{}", prefix, local))
+        } else {
+            None
+        }
+    })
+}
+
+/// Conservative check for Turtle PN_LOCAL names that need no escaping
+fn is_safe_local_name(local: &str) -> bool {
+    !local.is_empty()
+        && !local.starts_with('-')
+        && local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPE_LINE: &str = "<http://www.wikidata.org/entity/Q42> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://wikiba.se/ontology#Item> .";
+    const LABEL_LINE: &str = r#"<http://www.wikidata.org/entity/Q42> <http://www.w3.org/2000/01/rdf-schema#label> "Douglas Adams"@en ."#;
+
+    #[test]
+    fn test_abbreviate_term() {
+        assert_eq!(
+            abbreviate_term("<http://www.wikidata.org/entity/Q42>"),
+            "wd:Q42"
+        );
+        assert_eq!(
+            abbreviate_term("<http://www.wikidata.org/prop/direct/P31>"),
+            "wdt:P31"
+        );
+        assert_eq!(
+            abbreviate_term(r#""42"^^<http://www.w3.org/2001/XMLSchema#decimal>"#),
+            r#""42"^^xsd:decimal"#
+        );
+        // Literals and unknown namespaces are kept as they are
+        assert_eq!(
+            abbreviate_term(r#""Douglas Adams"@en"#),
+            r#""Douglas Adams"@en"#
+        );
+        assert_eq!(
+            abbreviate_term("<https://en.wikipedia.org/wiki/Douglas_Adams>"),
+            "<https://en.wikipedia.org/wiki/Douglas_Adams>"
+        );
+    }
+
+    #[test]
+    fn test_push_entity_graph_nquads() {
+        let mut buffer = String::new();
+        push_entity_graph(
+            &mut buffer,
+            "Q42",
+            [TYPE_LINE, LABEL_LINE],
+            OutputFormat::NQuads,
+        );
+
+        let lines: Vec<&str> = buffer.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].ends_with(
+            r#""Douglas Adams"@en <https://www.wikidata.org/wiki/Special:EntityData/Q42> ."#
+        ));
+    }
+
+    #[test]
+    fn test_push_entity_graph_trig() {
+        let mut buffer = String::new();
+        push_entity_graph(
+            &mut buffer,
+            "Q42",
+            [TYPE_LINE, LABEL_LINE],
+            OutputFormat::TriG,
+        );
+
+        assert_eq!(
+            buffer,
+            "wdata:Q42 {\n    wd:Q42 rdf:type wikibase:Item ;\n        rdfs:label \"Douglas Adams\"@en .\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_write_trig_prefixes() {
+        let mut output = Vec::new();
+        write_trig_prefixes(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("@prefix wd: <http://www.wikidata.org/entity/> ."));
+        assert!(output.ends_with("\n\n"));
+    }
+}
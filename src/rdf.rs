@@ -1,12 +1,34 @@
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::{BufRead, Write};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-
-use rayon::prelude::*;
-use regex::Regex;
-
-use crate::filter::EntityFilter;
+use std::time::Instant;
+
+use crate::bulk::BulkTemplate;
+use crate::canonical::canonicalize_rdf_entity;
+use crate::checkpoint::{CheckpointConfig, Checkpointer};
+use crate::datatype;
+use crate::entity::ClaimLookup;
+use crate::filter::{EntityFilter, MUL_LANGUAGE};
+use crate::graphml::GraphMlWriter;
+use crate::ipc::ArrowStreamWriter;
+use crate::json::{JsonArrayWriter, JsonParser, JsonStyle};
+use crate::malformed::{MalformedLine, MalformedSummary};
+use crate::memory::{MemoryAction, MemoryWatchdog};
+use crate::mmap;
+use crate::ntriples::{escape_literal, unescape_literal, Triple};
+use crate::partition::{self, EntityChunks, Partition, PartitionWriter, SplitState};
+use crate::pipeline::{self, Emitter, PipelineConfig};
+use crate::quads;
+use crate::route::{self, BatchOutputs, Route};
+use crate::signal;
+use crate::sort::{EntityKey, ExternalSorter, SortConfig};
+use crate::statement::{SnakValue, StatementFilter, TruthyValue};
+use crate::stats::{Counts, ResultTypes, RunStats};
+use crate::tabular::{self, TableSpec};
+use crate::truthify::{Statement, Truthifier};
+use crate::unicode::NormalizationForm;
 use crate::FilterError;
 
 /// Output format for processing
@@ -14,6 +36,152 @@ use crate::FilterError;
 pub enum OutputFormat {
     NTriples,
     Json,
+    /// N-Triples with a per-entity named graph
+    NQuads,
+    /// TriG with prefixes and one graph block per entity
+    TriG,
+    /// Comma-separated values, one row per entity
+    Csv,
+    /// Tab-separated values, one row per entity
+    Tsv,
+    /// Arrow IPC stream with the CSV/TSV columns
+    Arrow,
+    /// Elasticsearch/OpenSearch `_bulk` action and source line pairs
+    EsBulk,
+    /// GraphML document with entities as nodes and entity-valued claims as edges
+    GraphMl,
+}
+
+impl OutputFormat {
+    /// The `--output-format` value selecting this format
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::NTriples => "ntriples",
+            OutputFormat::Json => "json",
+            OutputFormat::NQuads => "nquads",
+            OutputFormat::TriG => "trig",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::Arrow => "arrow",
+            OutputFormat::EsBulk => "es-bulk",
+            OutputFormat::GraphMl => "graphml",
+        }
+    }
+
+    /// Check if the format is an RDF serialization
+    pub fn is_rdf(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::NTriples | OutputFormat::NQuads | OutputFormat::TriG
+        )
+    }
+}
+
+/// Settings shared by the RDF and JSON processing loops
+#[derive(Clone, Debug)]
+pub struct ProcessingOptions {
+    /// Show progress info on stderr
+    pub show_progress: bool,
+    /// Number of entities (RDF) or lines (JSON) per parallel batch
+    pub batch_size: usize,
+    /// Number of input lines to skip before processing
+    pub skip_lines: u64,
+    /// Maximum number of lines to process after skipping (u64::MAX = no limit)
+    pub max_lines: u64,
+    /// Number of input entities to skip before filtering
+    pub skip_entities: u64,
+    /// Stop after writing this many matching entities (u64::MAX = no limit)
+    pub max_entities: u64,
+    /// Abort on the first malformed input line instead of counting it
+    pub strict: bool,
+    /// File listing every malformed line with its line number and error
+    pub errors_output: Option<String>,
+    /// Print why each entity matched or was rejected to stderr
+    pub explain: bool,
+    /// Write only the --route outputs, nothing to the main output
+    pub routes_only: bool,
+    /// Fail instead of replacing existing output files
+    pub no_clobber: bool,
+    /// Request retries of s3:// and gs:// uploads (--http-retries)
+    pub http_retries: u32,
+    /// Write the selected entities to one file per claim value (--partition-by)
+    pub partition: Option<Partition>,
+    pub output_format: OutputFormat,
+    /// Soft RSS budget in bytes for the memory watchdog
+    pub memory_budget: Option<u64>,
+    /// Collapse full-dump statement nodes into truthy direct triples (RDF input only)
+    pub truthify: bool,
+    /// Columns for CSV/TSV and Arrow output
+    pub table: TableSpec,
+    /// Index and ID templates for `_bulk` output
+    pub bulk: BulkTemplate,
+    /// Layout of JSON output
+    pub json_style: JsonStyle,
+    /// Deterministic output: sorted triples, statements and aliases
+    pub canonical: bool,
+    /// Write the entities sorted by ID through an external sort (--sort-by-id)
+    pub sort: Option<SortConfig>,
+    /// Parser for JSON entity lines
+    pub json_parser: JsonParser,
+    /// Worker threads and queue depth
+    pub pipeline: PipelineConfig,
+    /// Write the entities in exactly the input order, also where the input
+    /// could be read in parallel (--stable-order)
+    pub stable_order: bool,
+    /// Counters for the --stats-output report
+    pub stats: Option<Arc<RunStats>>,
+    /// Set on Ctrl-C: stop reading and finish the output
+    pub interrupted: Arc<AtomicBool>,
+    /// Set on SIGUSR1: print a progress snapshot
+    pub snapshot_requested: Arc<AtomicBool>,
+    /// Save progress for --resume while writing
+    pub checkpoint: Option<CheckpointConfig>,
+    /// Lines and decompressed bytes of input before the reader's start (--resume)
+    pub resume_position: (u64, u64),
+}
+
+impl Default for ProcessingOptions {
+    fn default() -> Self {
+        Self {
+            show_progress: false,
+            batch_size: 100,
+            skip_lines: 0,
+            max_lines: u64::MAX,
+            skip_entities: 0,
+            max_entities: u64::MAX,
+            strict: false,
+            errors_output: None,
+            explain: false,
+            routes_only: false,
+            no_clobber: false,
+            http_retries: crate::http::DEFAULT_RETRIES,
+            partition: None,
+            output_format: OutputFormat::NTriples,
+            memory_budget: None,
+            truthify: false,
+            table: TableSpec::default(),
+            bulk: BulkTemplate::default(),
+            json_style: JsonStyle::NdJson,
+            canonical: false,
+            sort: None,
+            json_parser: JsonParser::Serde,
+            pipeline: PipelineConfig::default(),
+            stable_order: false,
+            stats: None,
+            interrupted: Arc::new(AtomicBool::new(false)),
+            snapshot_requested: Arc::new(AtomicBool::new(false)),
+            checkpoint: None,
+            resume_position: (0, 0),
+        }
+    }
+}
+
+impl ProcessingOptions {
+    /// Create the memory watchdog if a budget is configured
+    pub fn memory_watchdog(&self) -> Option<MemoryWatchdog> {
+        self.memory_budget
+            .map(|budget| MemoryWatchdog::new(budget, self.batch_size, self.show_progress))
+    }
 }
 
 /// Represents a parsed RDF entity with all its data
@@ -22,111 +190,401 @@ pub struct RdfEntity {
     pub id: String,
     pub metadata: Vec<String>,
     pub triples: Vec<String>,
+    /// Input line numbers of the raw `triples`, until the workers parse them
+    pub lines: Vec<u64>,
     pub claims: HashMap<String, HashSet<String>>,
+    /// Literal and URL objects of truthy claims (strings, times, quantities)
+    /// by property, for comparisons and patterns
+    pub literals: Vec<(String, String)>,
+    /// Properties with an unknown (somevalue) or no value (novalue) truthy claim
+    pub special_values: Vec<(String, SnakValue<'static>)>,
+    /// Statement nodes of a full dump read with --truthify
+    pub statements: Vec<Statement>,
     pub entity_type: Option<String>,
+    /// Datatype of a property entity, as named in JSON (e.g., "external-id")
+    pub datatype: Option<String>,
     /// Labels by language code (e.g., "de" -> "Deutschland")
     pub labels: HashMap<String, String>,
     /// Descriptions by language code
     pub descriptions: HashMap<String, String>,
     /// Aliases by language code (multiple per language)
     pub aliases: HashMap<String, Vec<String>>,
+    /// Sitelinks by site ID (e.g., "enwiki")
+    pub sitelinks: BTreeMap<String, RdfSitelink>,
 }
 
-/// Thread-safe regex container for RDF parsing
-pub struct RdfRegexes {
-    pub entity_re: Regex,
-    pub entity_data_re: Regex,
-    pub prop_direct_re: Regex,
-    pub entity_value_re: Regex,
-    pub type_re: Regex,
-    /// Matches rdfs:label predicate
-    pub label_re: Regex,
-    /// Matches schema:description predicate
-    pub description_re: Regex,
-    /// Matches skos:altLabel predicate
-    pub alias_re: Regex,
-    /// Extracts language-tagged literal: "value"@lang
-    pub lang_literal_re: Regex,
-}
-
-impl RdfRegexes {
-    pub fn new() -> Self {
-        Self {
-            entity_re: Regex::new(r"^<http://www\.wikidata\.org/entity/(Q\d+)>").unwrap(),
-            entity_data_re: Regex::new(
-                r"^<https://www\.wikidata\.org/wiki/Special:EntityData/(Q\d+)>",
-            )
-            .unwrap(),
-            prop_direct_re: Regex::new(
-                r"<http://www\.wikidata\.org/prop/direct(?:-normalized)?/(P\d+)>",
-            )
-            .unwrap(),
-            entity_value_re: Regex::new(r"<http://www\.wikidata\.org/entity/(Q\d+)>\s*\.$")
-                .unwrap(),
-            type_re: Regex::new(r"<http://wikiba\.se/ontology#(Item|Property)>").unwrap(),
-            label_re: Regex::new(r"<http://www\.w3\.org/2000/01/rdf-schema#label>").unwrap(),
-            description_re: Regex::new(r"<http://schema\.org/description>").unwrap(),
-            alias_re: Regex::new(r"<http://www\.w3\.org/2004/02/skos/core#altLabel>").unwrap(),
-            lang_literal_re: Regex::new(r#""(.*)"\s*@([a-zA-Z0-9-]+)\s*\.\s*$"#).unwrap(),
+/// A sitelink, read from the triples of its article
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RdfSitelink {
+    /// Page title, the `schema:name` of the article
+    pub title: String,
+    /// Badge item IDs, the `wikibase:badge` objects of the article
+    pub badges: Vec<String>,
+    /// Whether --keep and --omit let the sitelink through to the output
+    pub written: bool,
+}
+
+pub const ENTITY_PREFIX: &str = "<http://www.wikidata.org/entity/";
+pub const ENTITY_DATA_PREFIX: &str = "<https://www.wikidata.org/wiki/Special:EntityData/";
+const PROP_DIRECT_PREFIX: &str = "<http://www.wikidata.org/prop/direct/";
+const PROP_DIRECT_NORMALIZED_PREFIX: &str = "<http://www.wikidata.org/prop/direct-normalized/";
+const RDF_TYPE: &str = "<http://www.w3.org/1999/02/22-rdf-syntax-ns#type>";
+const PROP_NOVALUE_PREFIX: &str = "<http://www.wikidata.org/prop/novalue/";
+const PROPERTY_TYPE_PREDICATE: &str = "<http://wikiba.se/ontology#propertyType>";
+const LABEL_PREDICATE: &str = "<http://www.w3.org/2000/01/rdf-schema#label>";
+const DESCRIPTION_PREDICATE: &str = "<http://schema.org/description>";
+const ALIAS_PREDICATE: &str = "<http://www.w3.org/2004/02/skos/core#altLabel>";
+/// Predicates that repeat each label
+const LABEL_COPY_PREDICATES: &[&str] = &[
+    "<http://www.w3.org/2004/02/skos/core#prefLabel>",
+    "<http://schema.org/name>",
+];
+/// Namespace of the `p:` predicates linking an entity to its statement nodes
+const PROP_PREFIX: &str = "<http://www.wikidata.org/prop/";
+const SITELINKS_PREDICATE: &str = "<http://wikiba.se/ontology#sitelinks>";
+const STATEMENTS_PREDICATE: &str = "<http://wikiba.se/ontology#statements>";
+const DATE_MODIFIED_PREDICATE: &str = "<http://schema.org/dateModified>";
+const ARTICLE_NAME_PREDICATE: &str = "<http://schema.org/name>";
+const BADGE_PREDICATE: &str = "<http://wikiba.se/ontology#badge>";
+/// Sister projects whose site IDs end in the project name, e.g. `dewikivoyage`
+const SITE_PROJECTS: &[&str] = &[
+    "wiktionary",
+    "wikibooks",
+    "wikinews",
+    "wikiquote",
+    "wikisource",
+    "wikiversity",
+    "wikivoyage",
+];
+
+/// Reported for lines that are not `subject predicate object .`
+const NTRIPLES_ERROR: &str = "not a valid N-Triples statement";
+
+/// ID with a single-letter prefix (e.g. `Q42` for prefix `Q`) at the start of
+/// `s`, followed by `>`
+fn prefixed_id(s: &str, prefix: u8) -> Option<&str> {
+    let bytes = s.as_bytes();
+    if bytes.first() != Some(&prefix) {
+        return None;
+    }
+    let digits = bytes[1..].iter().take_while(|b| b.is_ascii_digit()).count();
+    if digits == 0 || bytes.get(1 + digits) != Some(&b'>') {
+        return None;
+    }
+    Some(&s[..1 + digits])
+}
+
+/// Item, property or lexeme ID of an entity IRI at the start of `s`
+pub fn entity_value_id(s: &str) -> Option<&str> {
+    let local = s.strip_prefix(ENTITY_PREFIX)?;
+    [b'Q', b'P', b'L']
+        .into_iter()
+        .find_map(|prefix| prefixed_id(local, prefix))
+}
+
+/// Site ID of a Wikimedia page or site IRI, such as `enwiki` for
+/// `<https://en.wikipedia.org/wiki/Berlin>` or `dewikivoyage` for
+/// `<https://de.wikivoyage.org/>`
+pub fn sitelink_site(iri: &str) -> Option<String> {
+    let host = iri.strip_prefix("<https://")?.split(['/', '>']).next()?;
+    match host {
+        "www.wikidata.org" => return Some("wikidatawiki".to_string()),
+        "www.mediawiki.org" => return Some("mediawikiwiki".to_string()),
+        "wikisource.org" => return Some("sourceswiki".to_string()),
+        _ => {}
+    }
+    let (subdomain, domain) = host.split_once('.')?;
+    // Language codes such as zh-min-nan are written with underscores
+    let subdomain = subdomain.replace('-', "_");
+    match domain {
+        "wikipedia.org" | "wikimedia.org" => Some(format!("{}wiki", subdomain)),
+        _ => {
+            let project = domain.strip_suffix(".org")?;
+            SITE_PROJECTS
+                .contains(&project)
+                .then(|| format!("{}{}", subdomain, project))
         }
     }
 }
 
-impl Default for RdfRegexes {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-/// Helper to create RdfEntity and reset state
-fn create_entity(
-    id: &str,
-    metadata: &mut Vec<String>,
-    triples: &mut Vec<String>,
-    claims: &mut HashMap<String, HashSet<String>>,
-    entity_type: &mut Option<String>,
-    labels: &mut HashMap<String, String>,
-    descriptions: &mut HashMap<String, String>,
-    aliases: &mut HashMap<String, Vec<String>>,
-) -> RdfEntity {
-    RdfEntity {
-        id: id.to_string(),
-        metadata: std::mem::take(metadata),
-        triples: std::mem::take(triples),
-        claims: std::mem::take(claims),
-        entity_type: entity_type.take(),
-        labels: std::mem::take(labels),
-        descriptions: std::mem::take(descriptions),
-        aliases: std::mem::take(aliases),
-    }
-}
-
-/// Extract language tag from an RDF line
-pub fn extract_language_tag(line: &str) -> Option<String> {
-    // Look for language tag like "text"@en
-    if let Some(at_pos) = line.rfind("@") {
-        let after_at = &line[at_pos + 1..];
-        // Find end of language tag (space or end of line)
-        let end_pos = after_at
-            .find(|c: char| c.is_whitespace() || c == '.')
-            .unwrap_or(after_at.len());
-        let lang = &after_at[..end_pos];
-        if !lang.is_empty() && lang.chars().all(|c| c.is_alphanumeric() || c == '-') {
-            return Some(lang.to_string());
-        }
-    }
-    None
-}
-
-/// Process a batch of RDF entities in parallel
-fn process_rdf_batch_parallel(batch: &[RdfEntity], filter: &Arc<EntityFilter>) -> Vec<RdfEntity> {
-    batch
-        .par_iter()
-        .filter(|entity| filter.matches(&entity.id, &entity.claims, entity.entity_type.as_deref()))
-        .cloned()
+/// Item, property or lexeme ID of an EntityData metadata line
+/// (`<https://www.wikidata.org/wiki/Special:EntityData/Q...>`)
+pub fn entity_data_id(line: &str) -> Option<&str> {
+    let local = line.strip_prefix(ENTITY_DATA_PREFIX)?;
+    [b'Q', b'P', b'L']
+        .into_iter()
+        .find_map(|prefix| prefixed_id(local, prefix))
+}
+
+/// Property ID of a direct (wdt:) or direct-normalized (wdtn:) predicate
+pub fn direct_property_id(predicate: &str) -> Option<&str> {
+    let local = predicate
+        .strip_prefix(PROP_DIRECT_PREFIX)
+        .or_else(|| predicate.strip_prefix(PROP_DIRECT_NORMALIZED_PREFIX))?;
+    prefixed_id(local, b'P')
+}
+
+/// Truthy claims, and the statements of full dumps for statement-level filters
+impl ClaimLookup for RdfEntity {
+    fn has_property(&self, prop: &str) -> bool {
+        self.claims.has_property(prop) || self.special_values.iter().any(|(p, _)| p == prop)
+    }
+
+    fn has_any_value(&self, prop: &str, values: &HashSet<String>) -> bool {
+        self.claims.has_any_value(prop, values)
+    }
+
+    fn references_any(&self, values: &HashSet<String>) -> bool {
+        self.claims.references_any(values)
+    }
+
+    fn has_entity_value(&self, prop: &str, letter: Option<char>) -> bool {
+        self.claims.has_entity_value(prop, letter)
+    }
+
+    fn any_statement(&self, filter: &StatementFilter) -> bool {
+        if self.statements.is_empty() {
+            return self.claims.any_statement(filter)
+                || self.literals.iter().any(|(property, object)| {
+                    *property == filter.property
+                        && filter.matches(&TruthyValue(SnakValue::of_rdf(object)))
+                })
+                || self.special_values.iter().any(|(property, value)| {
+                    *property == filter.property && filter.matches(&TruthyValue(value.clone()))
+                });
+        }
+        self.statements.iter().any(|statement| {
+            statement.property.as_deref() == Some(filter.property.as_str())
+                && filter.matches(statement)
+        })
+    }
+
+    fn labels(&self) -> Vec<(&str, &str)> {
+        terms(&self.labels)
+    }
+
+    fn descriptions(&self) -> Vec<(&str, &str)> {
+        terms(&self.descriptions)
+    }
+
+    fn badges(&self) -> Vec<(&str, &str)> {
+        self.sitelinks
+            .iter()
+            .flat_map(|(site, sitelink)| {
+                sitelink
+                    .badges
+                    .iter()
+                    .map(move |badge| (site.as_str(), badge.as_str()))
+            })
+            .collect()
+    }
+
+    fn sitelink_sites(&self) -> Vec<&str> {
+        self.sitelinks.keys().map(String::as_str).collect()
+    }
+
+    fn sitelink_count(&self) -> Option<usize> {
+        match self.metadata_value(SITELINKS_PREDICATE) {
+            Some(count) => count.parse().ok(),
+            // Sitelink articles of the dump
+            None => (!self.sitelinks.is_empty()).then_some(self.sitelinks.len()),
+        }
+    }
+
+    fn statement_count(&self) -> Option<usize> {
+        match self.metadata_value(STATEMENTS_PREDICATE) {
+            Some(count) => count.parse().ok(),
+            // Statement nodes of a full dump read with --truthify
+            None => (!self.statements.is_empty()).then_some(self.statements.len()),
+        }
+    }
+
+    fn modified(&self) -> Option<&str> {
+        self.metadata_value(DATE_MODIFIED_PREDICATE)
+    }
+}
+
+fn terms(terms: &HashMap<String, String>) -> Vec<(&str, &str)> {
+    terms
+        .iter()
+        .map(|(lang, text)| (lang.as_str(), text.as_str()))
         .collect()
 }
 
+impl RdfEntity {
+    /// Create an empty entity with the given ID
+    pub fn new(id: &str) -> Self {
+        RdfEntity {
+            id: id.to_string(),
+            metadata: Vec::new(),
+            triples: Vec::new(),
+            lines: Vec::new(),
+            claims: HashMap::new(),
+            literals: Vec::new(),
+            special_values: Vec::new(),
+            statements: Vec::new(),
+            entity_type: None,
+            datatype: None,
+            labels: HashMap::new(),
+            descriptions: HashMap::new(),
+            aliases: HashMap::new(),
+            sitelinks: BTreeMap::new(),
+        }
+    }
+
+    /// Lexical form of the object of an EntityData metadata triple, such as
+    /// the `wikibase:sitelinks` count
+    pub fn metadata_value(&self, predicate: &str) -> Option<&str> {
+        self.metadata
+            .iter()
+            .filter_map(|line| Triple::parse(line))
+            .find(|triple| triple.predicate == predicate)?
+            .lexical
+    }
+}
+
+/// Maximum number of distinct unrecognized subject patterns tracked individually
+const MAX_UNRECOGNIZED_PATTERNS: usize = 100;
+
+/// Subjects of full-dump lines that belong to an entity without naming it:
+/// statement, reference and value nodes, and the `prop/` namespace
+const NODE_PREFIXES: &[&str] = &[
+    "<http://www.wikidata.org/entity/statement/",
+    "<http://www.wikidata.org/reference/",
+    "<http://www.wikidata.org/value/",
+    PROP_PREFIX,
+];
+
+/// Whether the subject of a line is an item, property or lexeme, or one of
+/// their nodes, which are dropped by design rather than for being unknown
+fn is_known_subject(line: &str) -> bool {
+    entity_value_id(line).is_some() || NODE_PREFIXES.iter().any(|p| line.starts_with(p))
+}
+
+/// Counts lines that were dropped because their subject does not match a known
+/// entity ID scheme, grouped by subject pattern
+#[derive(Debug, Default)]
+pub struct UnrecognizedSubjects {
+    counts: HashMap<String, u64>,
+    /// Lines whose pattern did not fit into the tracked patterns
+    other: u64,
+}
+
+impl UnrecognizedSubjects {
+    /// Record a dropped line. Returns the pattern if it was seen for the first
+    /// time; lines of known subjects are not counted.
+    pub fn record(&mut self, line: &str) -> Option<String> {
+        if is_known_subject(line) {
+            return None;
+        }
+        let pattern = subject_pattern(line);
+        if let Some(count) = self.counts.get_mut(&pattern) {
+            *count += 1;
+            None
+        } else if self.counts.len() < MAX_UNRECOGNIZED_PATTERNS {
+            self.counts.insert(pattern.clone(), 1);
+            Some(pattern)
+        } else {
+            self.other += 1;
+            None
+        }
+    }
+
+    /// Add the counts of another tracker, e.g. from a worker thread
+    pub fn merge(&mut self, other: UnrecognizedSubjects) {
+        for (pattern, count) in other.counts {
+            if let Some(total) = self.counts.get_mut(&pattern) {
+                *total += count;
+            } else if self.counts.len() < MAX_UNRECOGNIZED_PATTERNS {
+                self.counts.insert(pattern, count);
+            } else {
+                self.other += count;
+            }
+        }
+        self.other += other.other;
+    }
+
+    /// Total number of dropped lines
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum::<u64>() + self.other
+    }
+
+    /// Patterns with their counts, most frequent first
+    pub fn patterns(&self) -> Vec<(&str, u64)> {
+        let mut patterns: Vec<(&str, u64)> =
+            self.counts.iter().map(|(p, c)| (p.as_str(), *c)).collect();
+        patterns.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        patterns
+    }
+
+    /// Print a summary of dropped lines to stderr
+    pub fn report(&self) {
+        let total = self.total();
+        if total == 0 {
+            return;
+        }
+        eprintln!(
+            "Warning: dropped {} lines with unrecognized subjects:",
+            total
+        );
+        for (pattern, count) in self.patterns() {
+            eprintln!("  {:>12}  {}", count, pattern);
+        }
+        if self.other > 0 {
+            eprintln!("  {:>12}  (other patterns)", self.other);
+        }
+    }
+}
+
+/// Reduce the subject of an N-Triples line to a pattern describing its ID scheme
+///
+/// IRIs are split into namespace and local name. Local names that look like IDs
+/// (uppercase prefix followed by digits, e.g. "P31" or "L7-F1") keep their shape
+/// with digit runs replaced by "N"; other local names are replaced by "*".
+pub fn subject_pattern(line: &str) -> String {
+    let subject = line.split_whitespace().next().unwrap_or("");
+
+    if subject.starts_with("_:") {
+        return "_:*".to_string();
+    }
+
+    let iri = match subject.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+        Some(iri) => iri,
+        None => return "<non-IRI subject>".to_string(),
+    };
+
+    let split_pos = iri.rfind(['/', '#']).map(|p| p + 1).unwrap_or(0);
+    let (namespace, local) = iri.split_at(split_pos);
+
+    let prefix_len = local
+        .find(|c: char| !c.is_ascii_uppercase())
+        .unwrap_or(local.len());
+    let looks_like_id = prefix_len > 0
+        && local[prefix_len..].starts_with(|c: char| c.is_ascii_digit())
+        && local.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+
+    let local_shape = if looks_like_id {
+        let mut shape = String::with_capacity(local.len());
+        let mut in_digits = false;
+        for c in local.chars() {
+            if c.is_ascii_digit() {
+                if !in_digits {
+                    shape.push('N');
+                }
+                in_digits = true;
+            } else {
+                shape.push(c);
+                in_digits = false;
+            }
+        }
+        shape
+    } else {
+        "*".to_string()
+    };
+
+    format!("<{}{}>", namespace, local_shape)
+}
+
 /// Write header lines efficiently
 fn write_header_batch<W: Write>(output: &mut W, headers: &[String]) -> std::io::Result<u64> {
     if headers.is_empty() {
@@ -190,8 +648,19 @@ pub fn rdf_entity_to_json(entity: &RdfEntity) -> serde_json::Value {
     obj.insert("id".to_string(), serde_json::json!(entity.id));
 
     // type
-    let etype = entity.entity_type.as_deref().unwrap_or("item");
+    // Entities without a type triple are typed by their ID
+    let etype = entity
+        .entity_type
+        .as_deref()
+        .unwrap_or(match entity.id.as_bytes().first() {
+            Some(b'P') => "property",
+            Some(b'L') => "lexeme",
+            _ => "item",
+        });
     obj.insert("type".to_string(), serde_json::json!(etype));
+    if let Some(ref datatype) = entity.datatype {
+        obj.insert("datatype".to_string(), serde_json::json!(datatype));
+    }
 
     // labels - Wikidata format: {"en": {"language": "en", "value": "Germany"}}
     if !entity.labels.is_empty() {
@@ -256,8 +725,11 @@ pub fn rdf_entity_to_json(entity: &RdfEntity) -> serde_json::Value {
             if values.is_empty() {
                 continue;
             }
+            // Sorted so that output does not depend on hash set iteration order
+            let mut values: Vec<&String> = values.iter().collect();
+            values.sort();
             let statements: Vec<serde_json::Value> = values
-                .iter()
+                .into_iter()
                 .map(|value_id| {
                     serde_json::json!({
                         "mainsnak": {
@@ -283,6 +755,27 @@ pub fn rdf_entity_to_json(entity: &RdfEntity) -> serde_json::Value {
         }
     }
 
+    // sitelinks - Wikidata format: {"enwiki": {"site": "enwiki", "title": "Berlin", "badges": []}}
+    let sitelinks: serde_json::Map<String, serde_json::Value> = entity
+        .sitelinks
+        .iter()
+        .filter(|(_, sitelink)| sitelink.written)
+        .map(|(site, sitelink)| {
+            let value = serde_json::json!({
+                "site": site,
+                "title": sitelink.title,
+                "badges": sitelink.badges
+            });
+            (site.clone(), value)
+        })
+        .collect();
+    if !sitelinks.is_empty() {
+        obj.insert(
+            "sitelinks".to_string(),
+            serde_json::Value::Object(sitelinks),
+        );
+    }
+
     serde_json::Value::Object(obj)
 }
 
@@ -310,63 +803,1576 @@ fn write_rdf_entities_as_json_batch<W: Write>(
     Ok((entities.len() as u64, entities.len() as u64))
 }
 
+/// Write RDF entities with one named graph per entity (N-Quads or TriG)
+fn write_rdf_entities_as_graphs_batch<W: Write>(
+    output: &mut W,
+    entities: &[RdfEntity],
+    format: OutputFormat,
+) -> std::io::Result<(u64, u64)> {
+    if entities.is_empty() {
+        return Ok((0, 0));
+    }
+
+    let mut buffer = String::new();
+    let mut triples_count: u64 = 0;
+
+    for entity in entities {
+        let lines = entity.metadata.iter().chain(&entity.triples);
+        triples_count += (entity.metadata.len() + entity.triples.len()) as u64;
+        quads::push_entity_graph(&mut buffer, &entity.id, lines.map(String::as_str), format);
+    }
+
+    output.write_all(buffer.as_bytes())?;
+
+    Ok((entities.len() as u64, triples_count))
+}
+
+/// Write RDF entities as CSV/TSV rows
+fn write_rdf_entities_as_table_batch<W: Write>(
+    output: &mut W,
+    entities: &[RdfEntity],
+    format: OutputFormat,
+    table: &TableSpec,
+) -> std::io::Result<(u64, u64)> {
+    if entities.is_empty() {
+        return Ok((0, 0));
+    }
+
+    let mut buffer = String::new();
+
+    for entity in entities {
+        buffer.push_str(&tabular::format_record(&table.rdf_row(entity), format));
+        buffer.push('\n');
+    }
+
+    output.write_all(buffer.as_bytes())?;
+
+    Ok((entities.len() as u64, 0))
+}
+
+/// Write RDF entities as `_bulk` action/source line pairs
+fn write_rdf_entities_as_bulk_batch<W: Write>(
+    output: &mut W,
+    entities: &[RdfEntity],
+    bulk: &BulkTemplate,
+) -> std::io::Result<(u64, u64)> {
+    if entities.is_empty() {
+        return Ok((0, 0));
+    }
+
+    let mut buffer = String::new();
+
+    for entity in entities {
+        bulk.push_entity(&mut buffer, &rdf_entity_to_json(entity));
+    }
+
+    output.write_all(buffer.as_bytes())?;
+
+    Ok((entities.len() as u64, 0))
+}
+
 /// Write RDF entities to output in the specified format
 fn write_rdf_output_batch<W: Write>(
     output: &mut W,
     entities: &[RdfEntity],
     format: OutputFormat,
+    table: &TableSpec,
+    bulk: &BulkTemplate,
 ) -> std::io::Result<(u64, u64)> {
     match format {
+        OutputFormat::EsBulk => write_rdf_entities_as_bulk_batch(output, entities, bulk),
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            write_rdf_entities_as_table_batch(output, entities, format, table)
+        }
+        OutputFormat::Arrow | OutputFormat::GraphMl => {
+            unreachable!("Arrow and GraphML output is written by RdfOutput")
+        }
         OutputFormat::NTriples => write_rdf_entities_batch(output, entities),
         OutputFormat::Json => write_rdf_entities_as_json_batch(output, entities),
+        OutputFormat::NQuads | OutputFormat::TriG => {
+            write_rdf_entities_as_graphs_batch(output, entities, format)
+        }
+    }
+}
+
+/// A batch of entities handed from the reader thread to the workers
+///
+/// The entities still hold their raw lines in `triples`; workers parse them.
+struct RdfBatch {
+    entities: Vec<RdfEntity>,
+    /// Dump header lines seen since the previous batch
+    header_lines: Vec<String>,
+    /// Lines the reader could not assign to an entity because they do not parse
+    malformed: Vec<MalformedLine>,
+    /// Flush the output after writing this batch (memory watchdog)
+    drain: bool,
+    /// Line number and byte offset of the first input line after the batch
+    position: (u64, u64),
+}
+
+/// Filtered and serialized results of one batch
+enum RdfBatchResult {
+    /// Output of the stateless formats with the end offset and running triple
+    /// count of each entity
+    Bytes(Vec<u8>, Vec<(usize, u64)>),
+    Rows(Vec<Vec<Vec<String>>>),
+    Entities(Vec<serde_json::Value>),
+    Records(Vec<String>),
+    /// Output of each entity with its sort key and triple count (--sort-by-id)
+    Keyed(Vec<(EntityKey, Vec<u8>, u64)>),
+}
+
+impl RdfBatchResult {
+    /// Number of matched entities
+    fn len(&self) -> usize {
+        match self {
+            RdfBatchResult::Bytes(_, ends) => ends.len(),
+            RdfBatchResult::Rows(rows) => rows.len(),
+            RdfBatchResult::Entities(entities) => entities.len(),
+            RdfBatchResult::Records(records) => records.len(),
+            RdfBatchResult::Keyed(entities) => entities.len(),
+        }
+    }
+
+    /// Move the entities to `n` results, entity i to result (start + i) % n
+    /// (round-robin shards)
+    fn deal(&mut self, n: usize, start: usize) -> Vec<RdfBatchResult> {
+        match self {
+            RdfBatchResult::Bytes(buffer, ends) => {
+                let mut dealt = vec![(Vec::new(), Vec::new()); n];
+                let (mut begin, mut triples) = (0, 0);
+                for (i, &(end, total)) in ends.iter().enumerate() {
+                    let (shard_buffer, shard_ends) = &mut dealt[(start + i) % n];
+                    shard_buffer.extend_from_slice(&buffer[begin..end]);
+                    let shard_triples = shard_ends.last().map_or(0, |&(_, t)| t);
+                    shard_ends.push((shard_buffer.len(), shard_triples + total - triples));
+                    (begin, triples) = (end, total);
+                }
+                buffer.clear();
+                ends.clear();
+                dealt
+                    .into_iter()
+                    .map(|(buffer, ends)| RdfBatchResult::Bytes(buffer, ends))
+                    .collect()
+            }
+            RdfBatchResult::Rows(rows) => partition::deal(std::mem::take(rows), n, start)
+                .into_iter()
+                .map(RdfBatchResult::Rows)
+                .collect(),
+            RdfBatchResult::Entities(entities) => {
+                partition::deal(std::mem::take(entities), n, start)
+                    .into_iter()
+                    .map(RdfBatchResult::Entities)
+                    .collect()
+            }
+            RdfBatchResult::Records(records) => partition::deal(std::mem::take(records), n, start)
+                .into_iter()
+                .map(RdfBatchResult::Records)
+                .collect(),
+            RdfBatchResult::Keyed(entities) => partition::deal(std::mem::take(entities), n, start)
+                .into_iter()
+                .map(RdfBatchResult::Keyed)
+                .collect(),
+        }
     }
+
+    /// Keep only the first `n` entities (--max-entities)
+    fn truncate(&mut self, n: usize) {
+        match self {
+            RdfBatchResult::Bytes(buffer, ends) => {
+                ends.truncate(n);
+                buffer.truncate(ends.last().map_or(0, |&(end, _)| end));
+            }
+            RdfBatchResult::Rows(rows) => rows.truncate(n),
+            RdfBatchResult::Entities(entities) => entities.truncate(n),
+            RdfBatchResult::Records(records) => records.truncate(n),
+            RdfBatchResult::Keyed(entities) => entities.truncate(n),
+        }
+    }
+}
+
+impl EntityChunks for RdfBatchResult {
+    fn entity_count(&self) -> usize {
+        self.len()
+    }
+
+    fn byte_sizes(&self) -> Option<Vec<u64>> {
+        match self {
+            RdfBatchResult::Bytes(_, ends) => {
+                let mut start = 0;
+                Some(
+                    ends.iter()
+                        .map(|&(end, _)| (end - std::mem::replace(&mut start, end)) as u64)
+                        .collect(),
+                )
+            }
+            RdfBatchResult::Records(records) => {
+                Some(records.iter().map(|r| r.len() as u64 + 1).collect())
+            }
+            RdfBatchResult::Keyed(entities) => Some(
+                entities
+                    .iter()
+                    .map(|(_, data, _)| data.len() as u64)
+                    .collect(),
+            ),
+            RdfBatchResult::Rows(_) | RdfBatchResult::Entities(_) => None,
+        }
+    }
+
+    fn split_to(&mut self, n: usize) -> Self {
+        match self {
+            RdfBatchResult::Bytes(buffer, ends) => {
+                let (end, triples) = ends[n - 1];
+                let head: Vec<(usize, u64)> = ends.drain(..n).collect();
+                for rest in ends.iter_mut() {
+                    *rest = (rest.0 - end, rest.1 - triples);
+                }
+                RdfBatchResult::Bytes(buffer.drain(..end).collect(), head)
+            }
+            RdfBatchResult::Rows(rows) => RdfBatchResult::Rows(rows.drain(..n).collect()),
+            RdfBatchResult::Entities(entities) => {
+                RdfBatchResult::Entities(entities.drain(..n).collect())
+            }
+            RdfBatchResult::Records(records) => {
+                RdfBatchResult::Records(records.drain(..n).collect())
+            }
+            RdfBatchResult::Keyed(entities) => RdfBatchResult::Keyed(entities.drain(..n).collect()),
+        }
+    }
+}
+
+/// Parse, filter and serialize one batch on a worker thread
+///
+/// With --partition-by, the selected entities go to the partitions of their
+/// values instead of the main output.
+fn process_rdf_batch(
+    batch: Vec<RdfEntity>,
+    filter: &EntityFilter,
+    routes: &[Arc<EntityFilter>],
+    options: &ProcessingOptions,
+    malformed: &mut Vec<MalformedLine>,
+) -> std::io::Result<BatchOutputs<RdfBatchResult>> {
+    let mut counts = Counts::default();
+    // Entities written anywhere, and the indices of those of each output
+    let mut kept = Vec::new();
+    let mut destinations: BatchOutputs<Vec<usize>> = BatchOutputs::new(routes.len());
+    for mut entity in batch {
+        let lines = std::mem::take(&mut entity.lines);
+        for (i, line) in std::mem::take(&mut entity.triples).into_iter().enumerate() {
+            if let Err(line) = add_triple(&mut entity, line, filter) {
+                // Truthy triples synthesized from full dumps have no line number
+                let number = lines.get(i).copied().unwrap_or(0);
+                malformed.push(MalformedLine::new(number, NTRIPLES_ERROR, &line));
+            }
+        }
+        if let (true, Some(languages)) = (filter.materialize_mul, &filter.language_filter) {
+            materialize_mul(&mut entity, languages);
+        }
+        let entity_type = entity.entity_type.as_deref();
+        let rejection = filter.rejection(&entity.id, &entity, entity_type);
+        let selected = !options.routes_only && filter.selects(rejection);
+        counts.record(rejection, selected, entity_type);
+        if options.explain {
+            eprint!("{}", filter.explain(&entity.id, &entity, entity_type));
+        }
+        if filter.claims_only {
+            entity.metadata.clear();
+        }
+        let index = kept.len();
+        let mut written = false;
+        if selected {
+            let values = match options.partition {
+                Some(ref partition) => partition.rdf_values(&entity),
+                None => Vec::new(),
+            };
+            // Entities without values go to the main output, round-robin shards
+            // are dealt from it when writing
+            if values.is_empty() {
+                destinations.main.push(index);
+            }
+            for value in values {
+                destinations
+                    .partitions
+                    .entry(value)
+                    .or_default()
+                    .push(index);
+            }
+            written = true;
+        }
+        for (route, indices) in routes.iter().zip(&mut destinations.routes) {
+            if route.matches(&entity.id, &entity, entity_type) {
+                indices.push(index);
+                written = true;
+            }
+        }
+        if written {
+            if options.canonical {
+                canonicalize_rdf_entity(&mut entity);
+            }
+            kept.push(entity);
+        }
+    }
+    counts.malformed_lines = malformed.len() as u64;
+    if let Some(ref stats) = options.stats {
+        stats.add(counts);
+    }
+
+    let serialize = |indices: Vec<usize>, types: &mut ResultTypes| {
+        let entities: Vec<&RdfEntity> = indices.iter().map(|&i| &kept[i]).collect();
+        serialize_rdf_entities(&entities, options, types)
+    };
+    let main = std::mem::take(&mut destinations.main);
+    let mut outputs =
+        destinations.try_map(|indices| serialize(indices, &mut ResultTypes::default()))?;
+    outputs.main = serialize(main, &mut outputs.main_types)?;
+    Ok(outputs)
+}
+
+/// Serialize filtered entities in the shape the output writer needs, adding
+/// the types of the entities with output to `types`
+fn serialize_rdf_entities(
+    results: &[&RdfEntity],
+    options: &ProcessingOptions,
+    types: &mut ResultTypes,
+) -> std::io::Result<RdfBatchResult> {
+    let mut output_of = |entity: &RdfEntity| types.push(entity.entity_type.as_deref());
+    Ok(match options.output_format {
+        OutputFormat::Arrow => RdfBatchResult::Rows(
+            results
+                .iter()
+                .inspect(|e| output_of(e))
+                .map(|e| options.table.rdf_cells(e))
+                .collect(),
+        ),
+        OutputFormat::GraphMl => RdfBatchResult::Entities(
+            results
+                .iter()
+                .inspect(|e| output_of(e))
+                .map(|e| rdf_entity_to_json(e))
+                .collect(),
+        ),
+        OutputFormat::Json if options.json_style != JsonStyle::NdJson => {
+            let style = options.json_style;
+            RdfBatchResult::Records(
+                results
+                    .iter()
+                    .filter_map(|e| {
+                        let record = style.serialize(&rdf_entity_to_json(e))?;
+                        output_of(e);
+                        Some(record)
+                    })
+                    .collect(),
+            )
+        }
+        format if options.sort.is_some() => {
+            let mut keyed = Vec::with_capacity(results.len());
+            for entity in results {
+                let mut buffer = Vec::new();
+                let (written, triples) = write_rdf_output_batch(
+                    &mut buffer,
+                    std::slice::from_ref(*entity),
+                    format,
+                    &options.table,
+                    &options.bulk,
+                )?;
+                if written > 0 {
+                    output_of(entity);
+                    keyed.push((EntityKey::new(&entity.id), buffer, triples));
+                }
+            }
+            RdfBatchResult::Keyed(keyed)
+        }
+        format => {
+            let mut buffer = Vec::new();
+            let mut ends = Vec::with_capacity(results.len());
+            let mut triples = 0;
+            for entity in results {
+                let (written, entity_triples) = write_rdf_output_batch(
+                    &mut buffer,
+                    std::slice::from_ref(*entity),
+                    format,
+                    &options.table,
+                    &options.bulk,
+                )?;
+                triples += entity_triples;
+                if written > 0 {
+                    output_of(entity);
+                    ends.push((buffer.len(), triples));
+                }
+            }
+            RdfBatchResult::Bytes(buffer, ends)
+        }
+    })
+}
+
+/// Receives the entities, header and malformed lines grouped by `RdfLineContext`
+trait EntitySink {
+    fn push_entity(&mut self, entity: RdfEntity);
+    fn push_header(&mut self, line: String);
+    fn push_malformed(&mut self, line: MalformedLine);
+}
+
+/// Entities of one memory-mapped chunk, grouped on a worker thread
+#[derive(Default)]
+struct RdfChunk {
+    entities: Vec<RdfEntity>,
+    header_lines: Vec<String>,
+    malformed: Vec<MalformedLine>,
+}
+
+impl EntitySink for RdfChunk {
+    fn push_entity(&mut self, entity: RdfEntity) {
+        self.entities.push(entity);
+    }
+
+    fn push_header(&mut self, line: String) {
+        self.header_lines.push(line);
+    }
+
+    fn push_malformed(&mut self, line: MalformedLine) {
+        self.malformed.push(line);
+    }
+}
+
+/// Collects finished entities into batches on the reader thread
+struct RdfBatcher<'e, 'a> {
+    emitter: &'e mut Emitter<'a, RdfBatch>,
+    batch_size: usize,
+    watchdog: Option<MemoryWatchdog>,
+    batch: Vec<RdfEntity>,
+    /// Input bytes of the triples in `batch`
+    batch_bytes: u64,
+    header_lines: Vec<String>,
+    malformed: Vec<MalformedLine>,
+    stopped: bool,
+    /// Position of the line being read, where the next batch starts
+    position: (u64, u64),
+    /// Entities still to drop before batching (--skip-entities)
+    skip_entities: u64,
+}
+
+impl EntitySink for RdfBatcher<'_, '_> {
+    /// Add a finished entity, handing the batch to the workers when it is full
+    fn push_entity(&mut self, entity: RdfEntity) {
+        if self.skip_entities > 0 {
+            self.skip_entities -= 1;
+            return;
+        }
+        self.batch_bytes += entity
+            .triples
+            .iter()
+            .map(|line| line.len() as u64)
+            .sum::<u64>();
+        self.batch.push(entity);
+
+        let mut batch_size = self.batch_size;
+        if let Some(ref mut watchdog) = self.watchdog {
+            if watchdog.poll() == MemoryAction::Drain {
+                // Drain pending work before reading more input
+                self.emit(true);
+                if !self.emitter.wait_idle() {
+                    self.stopped = true;
+                }
+                return;
+            }
+            batch_size = watchdog.batch_size();
+        }
+
+        if self.batch.len() >= batch_size || self.batch_bytes >= self.emitter.batch_bytes() {
+            self.emit(false);
+        }
+    }
+
+    fn push_header(&mut self, line: String) {
+        self.header_lines.push(line);
+    }
+
+    fn push_malformed(&mut self, line: MalformedLine) {
+        self.malformed.push(line);
+    }
+}
+
+impl RdfBatcher<'_, '_> {
+    /// Hand all pending entities, header and malformed lines to the workers
+    fn emit(&mut self, drain: bool) {
+        if self.batch.is_empty() && self.header_lines.is_empty() && self.malformed.is_empty() {
+            return;
+        }
+        let batch = RdfBatch {
+            entities: std::mem::replace(&mut self.batch, Vec::with_capacity(self.batch_size)),
+            header_lines: std::mem::take(&mut self.header_lines),
+            malformed: std::mem::take(&mut self.malformed),
+            drain,
+            position: self.position,
+        };
+        if !self
+            .emitter
+            .emit_sized(batch, std::mem::take(&mut self.batch_bytes))
+        {
+            self.stopped = true;
+        }
+    }
+}
+
+/// Writes batch results in order on the calling thread
+struct RdfOutput<'a, W: Write> {
+    output: W,
+    /// Whether the dump header lines are written (RDF output without --claims-only)
+    write_header: bool,
+    arrow: Option<ArrowStreamWriter>,
+    graphml: Option<GraphMlWriter>,
+    json_array: Option<JsonArrayWriter>,
+    header_lines: Vec<String>,
+    any_matched: bool,
+    entities_matched: &'a AtomicU64,
+    triples_output: &'a AtomicU64,
+    /// Write at most this many entities (--max-entities)
+    max_entities: u64,
+    checkpointer: Option<Checkpointer>,
+    sorter: Option<ExternalSorter>,
+}
+
+impl<'a, W: Write> RdfOutput<'a, W> {
+    fn new(
+        output: W,
+        filter: &EntityFilter,
+        options: &ProcessingOptions,
+        entities_matched: &'a AtomicU64,
+        triples_output: &'a AtomicU64,
+    ) -> std::io::Result<Self> {
+        let arrow = match options.output_format {
+            OutputFormat::Arrow => Some(ArrowStreamWriter::new(&options.table)?),
+            _ => None,
+        };
+        let graphml = match options.output_format {
+            OutputFormat::GraphMl => Some(GraphMlWriter::new(filter.language_filter.as_ref())),
+            _ => None,
+        };
+        let json_array = match options.output_format {
+            OutputFormat::Json if options.json_style != JsonStyle::NdJson => {
+                Some(JsonArrayWriter::default())
+            }
+            _ => None,
+        };
+        Ok(Self {
+            output,
+            write_header: options.output_format.is_rdf() && !filter.claims_only,
+            arrow,
+            graphml,
+            json_array,
+            header_lines: Vec::new(),
+            any_matched: false,
+            entities_matched,
+            triples_output,
+            max_entities: options.max_entities,
+            checkpointer: options.checkpoint.clone().map(Checkpointer::new),
+            sorter: options.sort.clone().map(ExternalSorter::new),
+        })
+    }
+
+    /// Writer of a --route or partition output: no limit, no checkpoints
+    fn route(
+        output: W,
+        filter: &EntityFilter,
+        options: &ProcessingOptions,
+        entities_written: &'a AtomicU64,
+        triples_output: &'a AtomicU64,
+    ) -> std::io::Result<Self> {
+        let mut writer = Self::new(output, filter, options, entities_written, triples_output)?;
+        writer.max_entities = u64::MAX;
+        writer.checkpointer = None;
+        Ok(writer)
+    }
+
+    /// Write the main results of one batch; the matches --max-entities cuts
+    /// off are taken back from the statistics
+    fn write_main(
+        &mut self,
+        header_lines: Vec<String>,
+        result: RdfBatchResult,
+        types: &ResultTypes,
+        options: &ProcessingOptions,
+        drain: bool,
+    ) -> std::io::Result<()> {
+        let total = result.len();
+        let matched = self.write(header_lines, result, drain)?;
+        if let Some(ref stats) = options.stats {
+            stats.unmatch(types, (total - matched) as u64);
+        }
+        Ok(())
+    }
+
+    /// Write the results of one batch, returning the number of matches
+    fn write(
+        &mut self,
+        header_lines: Vec<String>,
+        mut result: RdfBatchResult,
+        drain: bool,
+    ) -> std::io::Result<usize> {
+        self.header_lines.extend(header_lines);
+
+        let written = self.entities_matched.load(Ordering::Relaxed);
+        let remaining = self.max_entities.saturating_sub(written);
+        if (result.len() as u64) > remaining {
+            result.truncate(remaining as usize);
+        }
+        let matched = result.len();
+
+        // Write header lines once something matched (only for RDF output, header
+        // triples go to the default graph). With --unordered, the batch holding
+        // them may arrive after other matches.
+        self.any_matched |= matched > 0;
+        if self.write_header && self.any_matched && !self.header_lines.is_empty() {
+            let lines =
+                write_header_batch(&mut self.output, &std::mem::take(&mut self.header_lines))?;
+            self.triples_output.fetch_add(lines, Ordering::Relaxed);
+        }
+
+        match result {
+            RdfBatchResult::Bytes(buffer, ends) => {
+                self.output.write_all(&buffer)?;
+                let triples = ends.last().map_or(0, |&(_, triples)| triples);
+                self.triples_output.fetch_add(triples, Ordering::Relaxed);
+            }
+            RdfBatchResult::Rows(rows) => {
+                if let Some(ref mut arrow) = self.arrow {
+                    arrow.write_rows(&mut self.output, &rows)?;
+                }
+            }
+            RdfBatchResult::Entities(entities) => {
+                if let Some(ref mut graphml) = self.graphml {
+                    graphml.write_entities(&mut self.output, &entities)?;
+                }
+            }
+            RdfBatchResult::Records(records) => {
+                if let Some(ref mut json_array) = self.json_array {
+                    json_array.write_entities(&mut self.output, &records)?;
+                }
+            }
+            RdfBatchResult::Keyed(entities) => {
+                for (key, data, triples) in entities {
+                    self.triples_output.fetch_add(triples, Ordering::Relaxed);
+                    match self.sorter {
+                        Some(ref mut sorter) => sorter.push(key, data)?,
+                        None => self.output.write_all(&data)?,
+                    }
+                }
+            }
+        }
+        self.entities_matched
+            .fetch_add(matched as u64, Ordering::Relaxed);
+
+        if drain {
+            self.output.flush()?;
+        }
+        Ok(matched)
+    }
+
+    /// Note that the input before `position` is written (--checkpoint)
+    fn checkpoint(&mut self, position: (u64, u64)) -> std::io::Result<()> {
+        // Header lines held back until the first match would be lost on resume
+        let pending_header = self.write_header && !self.header_lines.is_empty();
+        match self.checkpointer {
+            Some(ref mut checkpointer) if !pending_header => {
+                checkpointer.record(&mut self.output, position)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// End the output stream
+    fn finish(&mut self) -> std::io::Result<()> {
+        if let Some(sorter) = self.sorter.take() {
+            sorter.finish(&mut self.output)?;
+        }
+        if let Some(arrow) = self.arrow.take() {
+            arrow.finish(&mut self.output)?;
+        }
+        if let Some(graphml) = self.graphml.take() {
+            graphml.finish(&mut self.output)?;
+        }
+        if let Some(json_array) = self.json_array.take() {
+            json_array.finish(&mut self.output)?;
+        }
+        if let Some(ref mut checkpointer) = self.checkpointer {
+            checkpointer.finish(&mut self.output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Extract labels, claims and type from an entity triple and keep it if it passes
+/// the property and language filters. Returns the line if it is malformed.
+fn add_triple(entity: &mut RdfEntity, line: String, filter: &EntityFilter) -> Result<(), String> {
+    let line = match filter.unicode_form {
+        Some(form) => normalize_literal(line, form),
+        None => line,
+    };
+    let triple = match Triple::parse(&line) {
+        Some(triple) => triple,
+        None => return Err(line),
+    };
+
+    // Sitelink articles and their sites
+    if !triple.subject.starts_with(ENTITY_PREFIX) {
+        let site = sitelink_site(triple.subject);
+        let written =
+            !filter.claims_only && filter.should_include_attribute("sitelinks", site.as_deref());
+        // Site lines name no article
+        if let Some(site) = site.filter(|_| triple.subject.contains("/wiki/")) {
+            let sitelink = entity.sitelinks.entry(site).or_default();
+            sitelink.written = written;
+            match triple.predicate {
+                ARTICLE_NAME_PREDICATE => {
+                    sitelink.title =
+                        unescape_literal(triple.lexical.unwrap_or_default()).into_owned();
+                }
+                BADGE_PREDICATE => {
+                    if let Some(badge) = entity_value_id(triple.object) {
+                        sitelink.badges.push(badge.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+        if written {
+            entity.triples.push(line);
+        }
+        return Ok(());
+    }
+
+    // Extract labels, descriptions, aliases
+    if let (Some(lexical), Some(lang)) = (triple.lexical, triple.language) {
+        if filter.language_filter.is_none() || filter.matches_language(lang) {
+            let value = unescape_literal(lexical);
+            match triple.predicate {
+                LABEL_PREDICATE => {
+                    entity.labels.insert(lang.to_string(), value.to_string());
+                }
+                DESCRIPTION_PREDICATE => {
+                    entity
+                        .descriptions
+                        .insert(lang.to_string(), value.to_string());
+                }
+                ALIAS_PREDICATE => {
+                    entity
+                        .aliases
+                        .entry(lang.to_string())
+                        .or_default()
+                        .push(value.to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Extract claims, keeping unknown values apart
+    let mut prop_id = direct_property_id(triple.predicate);
+    if let Some(prop_id) = prop_id {
+        if SnakValue::of_rdf(triple.object) == SnakValue::SomeValue {
+            entity
+                .special_values
+                .push((prop_id.to_string(), SnakValue::SomeValue));
+            if filter.exclude_special_values {
+                return Ok(());
+            }
+        } else {
+            let values = entity.claims.entry(prop_id.to_string()).or_default();
+            if let Some(value_id) = entity_value_id(triple.object) {
+                values.insert(value_id.to_string());
+            } else {
+                entity
+                    .literals
+                    .push((prop_id.to_string(), triple.object.to_string()));
+            }
+        }
+    }
+
+    // No values are typed with the wdno: class of their property
+    if triple.predicate == RDF_TYPE {
+        if let Some(id) = triple
+            .object
+            .strip_prefix(PROP_NOVALUE_PREFIX)
+            .and_then(|local| prefixed_id(local, b'P'))
+        {
+            entity
+                .special_values
+                .push((id.to_string(), SnakValue::NoValue));
+            if filter.exclude_special_values {
+                return Ok(());
+            }
+            prop_id = Some(id);
+        }
+    }
+
+    // Extract entity type
+    if triple.predicate == RDF_TYPE {
+        match triple.object {
+            "<http://wikiba.se/ontology#Item>" => entity.entity_type = Some("item".to_string()),
+            "<http://wikiba.se/ontology#Property>" => {
+                entity.entity_type = Some("property".to_string())
+            }
+            "<http://www.w3.org/ns/lemon/ontolex#LexicalEntry>" => {
+                entity.entity_type = Some("lexeme".to_string())
+            }
+            _ => {}
+        }
+    }
+
+    if triple.predicate == PROPERTY_TYPE_PREDICATE {
+        entity.datatype = datatype::from_rdf(triple.object);
+    }
+
+    // Apply property and datatype filters
+    if let (Some(prop_filter), Some(prop_id)) = (&filter.property_filter, prop_id) {
+        if !prop_filter.contains(prop_id) {
+            return Ok(());
+        }
+    }
+    if let (Some(datatypes), Some(prop_id)) = (&filter.datatype_filter, prop_id) {
+        if !datatypes.keeps(prop_id, None) {
+            return Ok(());
+        }
+    }
+
+    // Apply --keep and --omit to the triples of entity attributes
+    let attribute = triple_attribute(&triple, prop_id);
+    if let Some((attr, key)) = attribute {
+        if !filter.should_include_attribute(attr, key) {
+            return Ok(());
+        }
+    }
+
+    // Keep only claims and terms (--claims-only)
+    if filter.claims_only {
+        let term = matches!(
+            triple.predicate,
+            LABEL_PREDICATE | DESCRIPTION_PREDICATE | ALIAS_PREDICATE
+        );
+        if !term && !matches!(attribute, Some(("claims", _))) {
+            return Ok(());
+        }
+    }
+
+    // Apply language filter to any triple with a language tag
+    if let Some(lang) = triple.language {
+        if !filter.matches_language(lang) {
+            return Ok(());
+        }
+    }
+
+    entity.triples.push(line);
+    Ok(())
+}
+
+/// Write the literal of an entity triple in a normalization form
+/// (--normalize-unicode)
+fn normalize_literal(line: String, form: NormalizationForm) -> String {
+    let normalized = match Triple::parse(&line) {
+        Some(triple) if triple.subject.starts_with(ENTITY_PREFIX) => {
+            // Escapes such as \u0308 hide combining marks, so the text is
+            // normalized and escaped again
+            triple
+                .lexical
+                .and_then(|lexical| match form.normalize(&unescape_literal(lexical)) {
+                    Cow::Owned(normalized) => {
+                        let start = lexical.as_ptr() as usize - line.as_ptr() as usize;
+                        let end = start + lexical.len();
+                        Some(format!(
+                            "{}{}{}",
+                            &line[..start],
+                            escape_literal(&normalized),
+                            &line[end..]
+                        ))
+                    }
+                    Cow::Borrowed(_) => None,
+                })
+        }
+        _ => None,
+    };
+    normalized.unwrap_or(line)
+}
+
+/// Replace the `mul` terms of an entity by copies in the languages without
+/// their own (--materialize-mul)
+fn materialize_mul(entity: &mut RdfEntity, languages: &HashSet<String>) {
+    let missing = |has: &dyn Fn(&str) -> bool| -> Vec<String> {
+        let mut missing: Vec<String> = languages
+            .iter()
+            .filter(|lang| !has(lang))
+            .cloned()
+            .collect();
+        missing.sort();
+        missing
+    };
+    let labels = missing(&|lang| entity.labels.contains_key(lang));
+    let descriptions = missing(&|lang| entity.descriptions.contains_key(lang));
+    let aliases = missing(&|lang| entity.aliases.contains_key(lang));
+
+    let mut triples = Vec::with_capacity(entity.triples.len());
+    for line in std::mem::take(&mut entity.triples) {
+        let tag = match Triple::parse(&line) {
+            Some(triple) if triple.language == Some(MUL_LANGUAGE) => {
+                // Claims of monolingual text keep their `mul` values
+                let targets = match triple.predicate {
+                    LABEL_PREDICATE => Some(&labels),
+                    predicate if LABEL_COPY_PREDICATES.contains(&predicate) => Some(&labels),
+                    DESCRIPTION_PREDICATE => Some(&descriptions),
+                    ALIAS_PREDICATE => Some(&aliases),
+                    _ => None,
+                };
+                let start = line.rfind("\"@mul").map(|start| start + 2);
+                start.zip(targets)
+            }
+            _ => None,
+        };
+        match tag {
+            Some((start, targets)) => {
+                let end = start + MUL_LANGUAGE.len();
+                for lang in targets {
+                    triples.push(format!("{}{}{}", &line[..start], lang, &line[end..]));
+                }
+            }
+            None => triples.push(line),
+        }
+    }
+    entity.triples = triples;
+
+    if let Some(label) = entity.labels.remove(MUL_LANGUAGE) {
+        for lang in labels {
+            entity.labels.insert(lang, label.clone());
+        }
+    }
+    if let Some(description) = entity.descriptions.remove(MUL_LANGUAGE) {
+        for lang in descriptions {
+            entity.descriptions.insert(lang, description.clone());
+        }
+    }
+    if let Some(values) = entity.aliases.remove(MUL_LANGUAGE) {
+        for lang in aliases {
+            entity.aliases.insert(lang, values.clone());
+        }
+    }
+}
+
+/// The entity attribute a triple belongs to for --keep and --omit, with its
+/// key: the language of terms, the property of claims
+fn triple_attribute<'a>(
+    triple: &Triple<'a>,
+    prop_id: Option<&'a str>,
+) -> Option<(&'static str, Option<&'a str>)> {
+    let attr = match triple.predicate {
+        LABEL_PREDICATE => "labels",
+        predicate if LABEL_COPY_PREDICATES.contains(&predicate) => "labels",
+        DESCRIPTION_PREDICATE => "descriptions",
+        ALIAS_PREDICATE => "aliases",
+        _ => {
+            let statement_property = triple
+                .predicate
+                .strip_prefix(PROP_PREFIX)
+                .and_then(|local| prefixed_id(local, b'P'));
+            if let Some(property) = prop_id.or(statement_property) {
+                return Some(("claims", Some(property)));
+            }
+            if triple.predicate == RDF_TYPE {
+                return Some(("type", None));
+            }
+            return None;
+        }
+    };
+    Some((attr, triple.language))
+}
+
+/// Groups dump lines into entities
+struct RdfLineContext {
+    truthifier: Option<Truthifier>,
+    current: Option<RdfEntity>,
+    unrecognized: UnrecognizedSubjects,
+    /// Line number and byte offset of the line being read
+    position: (u64, u64),
+    /// Position of the first line of the current entity
+    current_start: (u64, u64),
+}
+
+impl RdfLineContext {
+    fn new(options: &ProcessingOptions) -> Self {
+        Self {
+            truthifier: options.truthify.then(Truthifier::default),
+            current: None,
+            unrecognized: UnrecognizedSubjects::default(),
+            position: (0, 0),
+            current_start: (0, 0),
+        }
+    }
+
+    /// Drop the unfinished current entity, returning the position of its first line
+    fn abandon_entity(&mut self) -> Option<(u64, u64)> {
+        self.current.take().map(|_| self.current_start)
+    }
+
+    /// Append the truthy triples synthesized from the entity's statement nodes
+    fn finish_entity(&mut self, mut entity: RdfEntity, sink: &mut impl EntitySink) {
+        if let Some(ref mut truthifier) = self.truthifier {
+            let (triples, statements) = truthifier.finish(&entity.id);
+            entity.triples.extend(triples);
+            entity.statements = statements;
+        }
+        sink.push_entity(entity);
+    }
+
+    /// Make `entity_id` the current entity, handing the previous one to the sink
+    fn enter_entity(&mut self, entity_id: &str, sink: &mut impl EntitySink) -> &mut RdfEntity {
+        if self.current.as_ref().map(|e| e.id.as_str()) != Some(entity_id) {
+            if let Some(prev) = self.current.take() {
+                self.finish_entity(prev, sink);
+            }
+            self.current = Some(RdfEntity::new(entity_id));
+            self.current_start = self.position;
+        }
+        self.current.as_mut().expect("current entity was just set")
+    }
+
+    /// Assign a line to its entity. Returns the subject pattern of a dropped
+    /// line if it is the first one with that pattern.
+    fn read_line(&mut self, line: String, sink: &mut impl EntitySink) -> Option<String> {
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        if line.contains("wikiba.se/ontology#Dump") {
+            sink.push_header(line);
+            return None;
+        }
+
+        // Check for EntityData metadata line
+        if let Some(entity_id) = entity_data_id(&line) {
+            self.enter_entity(entity_id, sink).metadata.push(line);
+            return None;
+        }
+
+        // Statement, reference and value nodes of full dumps are collapsed
+        if let Some(ref mut truthifier) = self.truthifier {
+            if truthifier.handle_node_line(&line) {
+                return None;
+            }
+        }
+
+        // Parse triple to extract subject entity
+        let entity_id = match entity_value_id(&line) {
+            Some(id) => id.to_string(),
+            None if Triple::parse(&line).is_none() => {
+                sink.push_malformed(MalformedLine::new(self.position.0, NTRIPLES_ERROR, &line));
+                return None;
+            }
+            None => match self.current.as_mut() {
+                // Sitelink articles and their sites follow their entity
+                Some(entity)
+                    if !line.starts_with(ENTITY_DATA_PREFIX) && sitelink_site(&line).is_some() =>
+                {
+                    entity.triples.push(line);
+                    entity.lines.push(self.position.0);
+                    return None;
+                }
+                // Unknown subject shape (e.g. a new entity type): count instead
+                // of dropping silently
+                _ => return self.unrecognized.record(&line),
+            },
+        };
+
+        let truthify = self.truthifier.is_some();
+        let line_number = self.position.0;
+        let entity = self.enter_entity(&entity_id, sink);
+        if truthify && Truthifier::is_replaced_entity_line(&line) {
+            return None;
+        }
+
+        // Parsed by the workers
+        entity.triples.push(line);
+        entity.lines.push(line_number);
+        None
+    }
+
+    /// Hand the last entity to the sink
+    fn finish(&mut self, sink: &mut impl EntitySink) {
+        if let Some(entity) = self.current.take() {
+            self.finish_entity(entity, sink);
+        }
+    }
+}
+
+/// Entity a dump line belongs to (EntityData metadata or entity subject)
+pub fn line_entity_id(line: &str) -> Option<&str> {
+    entity_data_id(line).or_else(|| entity_value_id(line))
+}
+
+/// Start of the first entity beginning at or after `pos` in N-Triples data
+///
+/// Entity lines are contiguous in the dumps, so a boundary is a line whose
+/// subject entity differs from the one of the lines before it.
+fn next_entity_start(data: &[u8], pos: usize) -> usize {
+    let line_id = |line: &[u8]| {
+        let line = std::str::from_utf8(line).ok()?;
+        line_entity_id(line).map(str::to_string)
+    };
+
+    let mut start = mmap::next_line_start(data, pos);
+    let mut previous = start.checked_sub(1).and_then(|end| {
+        let line_start = memchr::memrchr(b'\n', &data[..end]).map_or(0, |i| i + 1);
+        line_id(&data[line_start..end])
+    });
+    while start < data.len() {
+        let end = memchr::memchr(b'\n', &data[start..]).map_or(data.len(), |i| start + i);
+        if let Some(id) = line_id(&data[start..end]) {
+            match previous {
+                Some(ref prev) if *prev != id => return start,
+                _ => previous = Some(id),
+            }
+        }
+        start = end + 1;
+    }
+    data.len()
+}
+
+/// Entity and triple counters of each --route output
+fn route_counters(routes: usize) -> Vec<(AtomicU64, AtomicU64)> {
+    (0..routes).map(|_| Default::default()).collect()
+}
+
+/// Writers of the --route outputs
+fn route_writers<'a>(
+    outputs: Vec<&'a mut dyn Write>,
+    counters: &'a [(AtomicU64, AtomicU64)],
+    filter: &EntityFilter,
+    options: &ProcessingOptions,
+) -> std::io::Result<Vec<RdfOutput<'a, &'a mut dyn Write>>> {
+    outputs
+        .into_iter()
+        .zip(counters)
+        .map(|(output, (entities, triples))| {
+            RdfOutput::route(output, filter, options, entities, triples)
+        })
+        .collect()
+}
+
+/// Files of a --partition-by or --shards run
+///
+/// Partition files are opened when their value first has a match, shards
+/// all at the start.
+struct RdfPartitionOutputs<'a> {
+    writers: BTreeMap<String, RdfOutput<'a, PartitionWriter>>,
+    /// Header lines seen so far, for the partitions opened later
+    header_lines: Vec<String>,
+    /// Entities dealt to round-robin shards so far
+    dealt: usize,
+    /// Current file of a split output
+    split: SplitState,
+    filter: &'a EntityFilter,
+    options: &'a ProcessingOptions,
+    entities_matched: &'a AtomicU64,
+    triples_output: &'a AtomicU64,
+}
+
+impl<'a> RdfPartitionOutputs<'a> {
+    fn new(
+        filter: &'a EntityFilter,
+        options: &'a ProcessingOptions,
+        entities_matched: &'a AtomicU64,
+        triples_output: &'a AtomicU64,
+    ) -> std::io::Result<Self> {
+        let mut outputs = Self {
+            writers: BTreeMap::new(),
+            header_lines: Vec::new(),
+            dealt: 0,
+            split: SplitState::default(),
+            filter,
+            options,
+            entities_matched,
+            triples_output,
+        };
+        if let Some(ref partition) = options.partition {
+            for value in partition.fixed_values() {
+                outputs.writer(partition, value)?;
+            }
+        }
+        Ok(outputs)
+    }
+
+    /// Writer of a partition value, opening its file if needed
+    fn writer(
+        &mut self,
+        partition: &Partition,
+        value: String,
+    ) -> std::io::Result<&mut RdfOutput<'a, PartitionWriter>> {
+        Ok(match self.writers.entry(value) {
+            std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::btree_map::Entry::Vacant(entry) => {
+                let output = partition.open(entry.key(), self.options)?;
+                let mut writer = RdfOutput::route(
+                    output,
+                    self.filter,
+                    self.options,
+                    self.entities_matched,
+                    self.triples_output,
+                )?;
+                writer.header_lines = self.header_lines.clone();
+                entry.insert(writer)
+            }
+        })
+    }
+
+    /// End a file that gets no more entities
+    fn close(&mut self, value: &str) -> std::io::Result<()> {
+        if let Some(mut writer) = self.writers.remove(value) {
+            writer.finish()?;
+            let interrupted = self.options.interrupted.load(Ordering::Relaxed);
+            writer.output.close(interrupted)?;
+        }
+        Ok(())
+    }
+
+    /// Write the partition results of one batch
+    ///
+    /// With round-robin shards, the main results are dealt to the shards; a
+    /// split output cuts them into its files.
+    fn write(
+        &mut self,
+        header_lines: Vec<String>,
+        results: &mut BatchOutputs<RdfBatchResult>,
+        drain: bool,
+    ) -> std::io::Result<()> {
+        let options = self.options;
+        let Some(ref partition) = options.partition else {
+            return Ok(());
+        };
+        for writer in self.writers.values_mut() {
+            writer.header_lines.extend(header_lines.iter().cloned());
+        }
+        self.header_lines.extend(header_lines);
+        let mut partitions = std::mem::take(&mut results.partitions);
+        if let Some(shards) = partition.round_robin() {
+            let dealt = results.main.len();
+            for (shard, result) in results
+                .main
+                .deal(shards, self.dealt)
+                .into_iter()
+                .enumerate()
+            {
+                partitions.insert(partition.shard_name(shard), result);
+            }
+            self.dealt += dealt;
+        }
+        if let Some(limits) = partition.split_limits() {
+            while let Some((chunk, finished)) = self.split.next_chunk(limits, &mut results.main) {
+                if let Some(part) = finished {
+                    self.close(&partition.shard_name(part))?;
+                }
+                let part = partition.shard_name(self.split.part);
+                self.writer(partition, part)?
+                    .write(Vec::new(), chunk, drain)?;
+            }
+        }
+        for (value, result) in partitions {
+            self.writer(partition, value)?
+                .write(Vec::new(), result, drain)?;
+        }
+        Ok(())
+    }
+
+    /// End and close every partition file
+    fn finish(self) -> std::io::Result<()> {
+        let interrupted = self.options.interrupted.load(Ordering::Relaxed);
+        for mut writer in self.writers.into_values() {
+            writer.finish()?;
+            writer.output.close(interrupted)?;
+        }
+        Ok(())
+    }
+}
+
+/// Main RDF filtering function with parallel processing
+///
+/// A reader thread groups lines by entity, worker threads parse, filter and
+/// serialize batches of entities, and the calling thread writes them in order.
+/// Each route gets the entities its own filter selects.
+pub fn filter_rdf_parallel<R: BufRead + Send, W: Write>(
+    reader: R,
+    output: &mut W,
+    routes: &mut [Route],
+    filter: &Arc<EntityFilter>,
+    options: &ProcessingOptions,
+) -> Result<(), FilterError> {
+    let entities_matched = AtomicU64::new(0);
+    let triples_output = AtomicU64::new(0);
+    let (route_filters, route_outputs) = route::split(routes);
+    let route_counters = route_counters(route_outputs.len());
+    let mut writer = RdfOutput::new(output, filter, options, &entities_matched, &triples_output)?;
+    let mut route_writers = route_writers(route_outputs, &route_counters, filter, options)?;
+    let mut partition_writers =
+        RdfPartitionOutputs::new(filter, options, &entities_matched, &triples_output)?;
+    let mut malformed = MalformedSummary::new(options)?;
+
+    pipeline::run(
+        options.pipeline,
+        |emitter| read_rdf_entities(reader, emitter, options, &entities_matched, &triples_output),
+        |mut batch: RdfBatch| {
+            let result = process_rdf_batch(
+                batch.entities,
+                filter,
+                &route_filters,
+                options,
+                &mut batch.malformed,
+            );
+            batch.malformed.sort_by_key(|line| line.line);
+            (
+                batch.header_lines,
+                result,
+                batch.malformed,
+                batch.drain,
+                batch.position,
+            )
+        },
+        |(header_lines, result, lines, drain, position)| -> Result<(), FilterError> {
+            malformed.add(lines)?;
+            let mut results = result?;
+            partition_writers.write(header_lines.clone(), &mut results, drain)?;
+            for (route_writer, result) in route_writers.iter_mut().zip(results.routes) {
+                route_writer.write(header_lines.clone(), result, drain)?;
+            }
+            writer.write_main(
+                header_lines,
+                results.main,
+                &results.main_types,
+                options,
+                drain,
+            )?;
+            writer.checkpoint(position)?;
+            Ok(())
+        },
+    )?;
+    writer.finish()?;
+    for route_writer in &mut route_writers {
+        route_writer.finish()?;
+    }
+    partition_writers.finish()?;
+    malformed.finish()?;
+    if let Some(ref stats) = options.stats {
+        stats.add(Counts {
+            triples_written: triples_output.load(Ordering::Relaxed),
+            ..Default::default()
+        });
+    }
+
+    Ok(())
+}
+
+/// RDF filtering of a memory-mapped N-Triples dump (--mmap)
+///
+/// The reader thread only cuts the mapping into byte ranges at entity
+/// boundaries; workers group, parse, filter and serialize each range.
+pub fn filter_rdf_mmap<W: Write>(
+    data: &[u8],
+    output: &mut W,
+    routes: &mut [Route],
+    filter: &Arc<EntityFilter>,
+    options: &ProcessingOptions,
+) -> Result<(), FilterError> {
+    let show_progress = options.show_progress;
+    let entities_matched = AtomicU64::new(0);
+    let triples_output = AtomicU64::new(0);
+    let (route_filters, route_outputs) = route::split(routes);
+    let route_counters = route_counters(route_outputs.len());
+    let mut writer = RdfOutput::new(output, filter, options, &entities_matched, &triples_output)?;
+    let mut route_writers = route_writers(route_outputs, &route_counters, filter, options)?;
+    let mut partition_writers =
+        RdfPartitionOutputs::new(filter, options, &entities_matched, &triples_output)?;
+    let mut unrecognized = UnrecognizedSubjects::default();
+    let mut malformed = MalformedSummary::new(options)?;
+    let started = Instant::now();
+
+    pipeline::run(
+        options.pipeline,
+        |emitter| -> Result<(), FilterError> {
+            let mut first_line = 1;
+            for range in mmap::chunk_ranges(data, mmap::CHUNK_SIZE, next_entity_start) {
+                if options.interrupted.load(Ordering::Relaxed) {
+                    signal::report_interrupt(None, range.start as u64);
+                    break;
+                }
+                if entities_matched.load(Ordering::Relaxed) >= options.max_entities {
+                    break;
+                }
+                if options.snapshot_requested.swap(false, Ordering::Relaxed) {
+                    signal::report_snapshot(
+                        None,
+                        range.start as u64,
+                        entities_matched.load(Ordering::Relaxed),
+                        started.elapsed(),
+                    );
+                }
+                if show_progress {
+                    eprintln!(
+                        "Read {} of {} MiB, matched {} entities, output {} triples",
+                        range.start >> 20,
+                        data.len() >> 20,
+                        entities_matched.load(Ordering::Relaxed),
+                        triples_output.load(Ordering::Relaxed)
+                    );
+                }
+                let lines = memchr::memchr_iter(b'\n', &data[range.clone()]).count() as u64;
+                if !emitter.emit((range, first_line)) {
+                    break;
+                }
+                first_line += lines;
+            }
+            Ok(())
+        },
+        |(range, first_line): (std::ops::Range<usize>, u64)| -> Result<_, FilterError> {
+            let mut ctx = RdfLineContext::new(options);
+            let mut chunk = RdfChunk::default();
+            let mut lines_read = 0;
+            for line in mmap::chunk_str(&data[range])?.lines() {
+                ctx.position = (first_line + lines_read, 0);
+                lines_read += 1;
+                ctx.read_line(line.to_string(), &mut chunk);
+            }
+            if let Some(ref stats) = options.stats {
+                stats.add(Counts {
+                    lines_read,
+                    ..Default::default()
+                });
+            }
+            ctx.finish(&mut chunk);
+            let result = process_rdf_batch(
+                chunk.entities,
+                filter,
+                &route_filters,
+                options,
+                &mut chunk.malformed,
+            )?;
+            chunk.malformed.sort_by_key(|line| line.line);
+            Ok((
+                chunk.header_lines,
+                result,
+                chunk.malformed,
+                ctx.unrecognized,
+            ))
+        },
+        |chunk| {
+            let (header_lines, mut results, lines, chunk_unrecognized) = chunk?;
+            malformed.add(lines)?;
+            partition_writers.write(header_lines.clone(), &mut results, false)?;
+            for (route_writer, result) in route_writers.iter_mut().zip(results.routes) {
+                route_writer.write(header_lines.clone(), result, false)?;
+            }
+            writer.write_main(
+                header_lines,
+                results.main,
+                &results.main_types,
+                options,
+                false,
+            )?;
+            unrecognized.merge(chunk_unrecognized);
+            Ok(())
+        },
+    )?;
+    writer.finish()?;
+    for route_writer in &mut route_writers {
+        route_writer.finish()?;
+    }
+    partition_writers.finish()?;
+    malformed.finish()?;
+    if let Some(ref stats) = options.stats {
+        stats.add(Counts {
+            triples_written: triples_output.load(Ordering::Relaxed),
+            ..Default::default()
+        });
+    }
+
+    if show_progress {
+        eprintln!(
+            "Done! Matched {} entities, output {} triples",
+            entities_matched.load(Ordering::Relaxed),
+            triples_output.load(Ordering::Relaxed)
+        );
+    }
+
+    unrecognized.report();
+
+    Ok(())
 }
 
-/// Main RDF filtering function with parallel processing
-pub fn filter_rdf_parallel<R: BufRead, W: Write>(
+/// Reader thread of `filter_rdf_parallel`: group lines into entities and batch them
+fn read_rdf_entities<R: BufRead>(
     reader: R,
-    output: &mut W,
-    filter: &Arc<EntityFilter>,
-    show_progress: bool,
-    batch_size: usize,
-    skip_lines: u64,
-    max_lines: u64,
-    output_format: OutputFormat,
+    emitter: &mut Emitter<RdfBatch>,
+    options: &ProcessingOptions,
+    entities_matched: &AtomicU64,
+    triples_output: &AtomicU64,
 ) -> Result<(), FilterError> {
-    let regexes = RdfRegexes::new();
-
-    let mut current_entity: Option<String> = None;
-    let mut current_triples: Vec<String> = Vec::new();
-    let mut current_metadata: Vec<String> = Vec::new();
-    let mut entity_claims: HashMap<String, HashSet<String>> = HashMap::new();
-    let mut entity_type: Option<String> = None;
-    let mut entity_labels: HashMap<String, String> = HashMap::new();
-    let mut entity_descriptions: HashMap<String, String> = HashMap::new();
-    let mut entity_aliases: HashMap<String, Vec<String>> = HashMap::new();
-
-    let lines_processed = AtomicU64::new(0);
-    let lines_skipped = AtomicU64::new(0);
-    let entities_matched = AtomicU64::new(0);
-    let triples_output = AtomicU64::new(0);
-    let mut header_written = false;
+    let mut ctx = RdfLineContext::new(options);
+    let show_progress = options.show_progress;
+    let started = Instant::now();
+    let skip_lines = options.skip_lines;
+    let max_lines = options.max_lines;
+
+    let mut batcher = RdfBatcher {
+        emitter,
+        batch_size: options.batch_size,
+        watchdog: options.memory_watchdog(),
+        batch: Vec::with_capacity(options.batch_size),
+        batch_bytes: 0,
+        header_lines: Vec::new(),
+        malformed: Vec::new(),
+        stopped: false,
+        position: (0, 0),
+        skip_entities: options.skip_entities,
+    };
+
+    let mut lines_skipped: u64 = 0;
     let mut skip_mode = skip_lines > 0;
     // After skipping, wait for next entity boundary to avoid partial entities
     let mut waiting_for_entity_boundary = skip_lines > 0;
-
-    let mut header_lines: Vec<String> = Vec::new();
-    let mut entity_batch: Vec<RdfEntity> = Vec::with_capacity(batch_size);
+    // Entity of the last skipped line, to find where the next one starts
+    let mut skipped_entity: Option<String> = None;
 
     let mut lines_actually_processed: u64 = 0;
 
-    for line_result in reader.lines() {
+    let (mut lines_read, mut offset) = options.resume_position;
+    let mut interrupted_at = None;
+    let mut stopped_at = None;
+    for (current_line, line_result) in (lines_read + 1..).zip(reader.lines()) {
         let line = line_result?;
-        let current_line = lines_processed.fetch_add(1, Ordering::Relaxed) + 1;
+        if batcher.stopped {
+            return Ok(());
+        }
+        // On Ctrl-C, stop before this line and drop the unfinished entity
+        if options.interrupted.load(Ordering::Relaxed) {
+            interrupted_at = Some(ctx.abandon_entity().unwrap_or((current_line, offset)));
+            break;
+        }
+        // The writer has all the entities it needs
+        if entities_matched.load(Ordering::Relaxed) >= options.max_entities {
+            stopped_at = ctx.abandon_entity().or(Some((current_line, offset)));
+            break;
+        }
+        if options.snapshot_requested.swap(false, Ordering::Relaxed) {
+            signal::report_snapshot(
+                Some(current_line - 1),
+                offset,
+                entities_matched.load(Ordering::Relaxed),
+                started.elapsed(),
+            );
+        }
+        lines_read = current_line;
+        let line_offset = offset;
+        offset += line.len() as u64 + 1;
 
         // Skip lines if needed
         if skip_mode {
-            lines_skipped.fetch_add(1, Ordering::Relaxed);
+            lines_skipped += 1;
             if current_line >= skip_lines {
                 skip_mode = false;
+                skipped_entity = line_entity_id(&line).map(str::to_string);
                 if show_progress {
                     eprintln!(
                         "Skipped {} lines, waiting for next entity boundary...",
@@ -377,10 +2383,20 @@ pub fn filter_rdf_parallel<R: BufRead, W: Write>(
             continue;
         }
 
-        // After skipping, wait until we hit a new entity (EntityData line)
+        // After skipping, wait until we hit a new entity (EntityData line or a
+        // subject other than the one of the last skipped line)
         if waiting_for_entity_boundary {
-            lines_skipped.fetch_add(1, Ordering::Relaxed);
-            if regexes.entity_data_re.is_match(&line) {
+            lines_skipped += 1;
+            let boundary = match (line_entity_id(&line), skipped_entity.as_deref()) {
+                _ if entity_data_id(&line).is_some() => true,
+                (Some(id), Some(skipped)) => id != skipped,
+                (Some(id), None) => {
+                    skipped_entity = Some(id.to_string());
+                    false
+                }
+                (None, _) => false,
+            };
+            if boundary {
                 waiting_for_entity_boundary = false;
                 if show_progress {
                     eprintln!(
@@ -402,252 +2418,73 @@ pub fn filter_rdf_parallel<R: BufRead, W: Write>(
             if show_progress {
                 eprintln!("Reached max_lines limit ({}), stopping.", max_lines);
             }
+            stopped_at = Some((current_line, line_offset));
             break;
         }
 
-        if show_progress && lines_actually_processed % 100000 == 0 {
+        if show_progress && lines_actually_processed.is_multiple_of(100000) {
             eprintln!(
                 "Line {} (skipped {}), processed {}, matched {} entities, output {} triples",
                 current_line,
-                lines_skipped.load(Ordering::Relaxed),
+                lines_skipped,
                 lines_actually_processed,
                 entities_matched.load(Ordering::Relaxed),
                 triples_output.load(Ordering::Relaxed)
             );
         }
 
-        if line.is_empty() || line.starts_with('#') {
-            continue;
-        }
-
-        if line.contains("wikiba.se/ontology#Dump") {
-            header_lines.push(line);
-            continue;
-        }
-
-        // Check for EntityData metadata line
-        if let Some(caps) = regexes.entity_data_re.captures(&line) {
-            let entity_id = caps[1].to_string();
-
-            if current_entity.as_ref() != Some(&entity_id) {
-                // Save previous entity to batch
-                if let Some(ref prev_entity) = current_entity {
-                    entity_batch.push(create_entity(
-                        prev_entity,
-                        &mut current_metadata,
-                        &mut current_triples,
-                        &mut entity_claims,
-                        &mut entity_type,
-                        &mut entity_labels,
-                        &mut entity_descriptions,
-                        &mut entity_aliases,
-                    ));
-
-                    // Process batch when full
-                    if entity_batch.len() >= batch_size {
-                        let results = process_rdf_batch_parallel(&entity_batch, filter);
-
-                        // Write header once (only for NTriples output)
-                        if output_format == OutputFormat::NTriples
-                            && !header_written
-                            && !results.is_empty()
-                        {
-                            let header_count = write_header_batch(output, &header_lines)?;
-                            triples_output.fetch_add(header_count, Ordering::Relaxed);
-                            header_written = true;
-                        }
-
-                        // Write results using batch write
-                        let (ent_count, triple_count) =
-                            write_rdf_output_batch(output, &results, output_format)?;
-                        entities_matched.fetch_add(ent_count, Ordering::Relaxed);
-                        triples_output.fetch_add(triple_count, Ordering::Relaxed);
-                        entity_batch.clear();
-                    }
-                }
-
-                current_entity = Some(entity_id);
-                entity_claims = HashMap::new();
-                entity_type = None;
-                entity_labels = HashMap::new();
-                entity_descriptions = HashMap::new();
-                entity_aliases = HashMap::new();
-            }
-
-            current_metadata.push(line);
-            continue;
-        }
-
-        // Parse triple to extract subject entity
-        let subject_entity = regexes
-            .entity_re
-            .captures(&line)
-            .map(|caps| caps[1].to_string());
-
-        if let Some(ref entity_id) = subject_entity {
-            if current_entity.as_ref() != Some(entity_id) {
-                // Save previous entity to batch
-                if let Some(ref prev_entity) = current_entity {
-                    entity_batch.push(create_entity(
-                        prev_entity,
-                        &mut current_metadata,
-                        &mut current_triples,
-                        &mut entity_claims,
-                        &mut entity_type,
-                        &mut entity_labels,
-                        &mut entity_descriptions,
-                        &mut entity_aliases,
-                    ));
-
-                    // Process batch when full
-                    if entity_batch.len() >= batch_size {
-                        let results = process_rdf_batch_parallel(&entity_batch, filter);
-
-                        if output_format == OutputFormat::NTriples
-                            && !header_written
-                            && !results.is_empty()
-                        {
-                            let header_count = write_header_batch(output, &header_lines)?;
-                            triples_output.fetch_add(header_count, Ordering::Relaxed);
-                            header_written = true;
-                        }
-
-                        let (ent_count, triple_count) =
-                            write_rdf_output_batch(output, &results, output_format)?;
-                        entities_matched.fetch_add(ent_count, Ordering::Relaxed);
-                        triples_output.fetch_add(triple_count, Ordering::Relaxed);
-                        entity_batch.clear();
-                    }
-                }
-
-                current_entity = Some(entity_id.clone());
-                entity_claims = HashMap::new();
-                entity_type = None;
-                entity_labels = HashMap::new();
-                entity_descriptions = HashMap::new();
-                entity_aliases = HashMap::new();
-            }
-
-            // Extract labels, descriptions, aliases
-            if regexes.label_re.is_match(&line) {
-                if let Some(caps) = regexes.lang_literal_re.captures(&line) {
-                    let value = caps[1].to_string();
-                    let lang = caps[2].to_string();
-                    // Apply language filter
-                    if filter.language_filter.is_none() || filter.matches_language(&lang) {
-                        entity_labels.insert(lang, value);
-                    }
-                }
-            } else if regexes.description_re.is_match(&line) {
-                if let Some(caps) = regexes.lang_literal_re.captures(&line) {
-                    let value = caps[1].to_string();
-                    let lang = caps[2].to_string();
-                    if filter.language_filter.is_none() || filter.matches_language(&lang) {
-                        entity_descriptions.insert(lang, value);
-                    }
-                }
-            } else if regexes.alias_re.is_match(&line) {
-                if let Some(caps) = regexes.lang_literal_re.captures(&line) {
-                    let value = caps[1].to_string();
-                    let lang = caps[2].to_string();
-                    if filter.language_filter.is_none() || filter.matches_language(&lang) {
-                        entity_aliases
-                            .entry(lang)
-                            .or_insert_with(Vec::new)
-                            .push(value);
-                    }
-                }
-            }
-
-            // Extract claims
-            if let Some(prop_caps) = regexes.prop_direct_re.captures(&line) {
-                let prop_id = prop_caps[1].to_string();
-                if let Some(val_caps) = regexes.entity_value_re.captures(&line) {
-                    let value_id = val_caps[1].to_string();
-                    entity_claims
-                        .entry(prop_id.clone())
-                        .or_insert_with(HashSet::new)
-                        .insert(value_id);
-                } else {
-                    entity_claims.entry(prop_id).or_insert_with(HashSet::new);
-                }
-            }
-
-            // Extract entity type
-            if line.contains("rdf-syntax-ns#type") {
-                if let Some(type_caps) = regexes.type_re.captures(&line) {
-                    entity_type = Some(type_caps[1].to_string().to_lowercase());
-                }
-            }
-
-            // Apply property filter
-            if let Some(ref prop_filter) = filter.property_filter {
-                if let Some(prop_caps) = regexes.prop_direct_re.captures(&line) {
-                    let prop_id = &prop_caps[1];
-                    if !prop_filter.contains(prop_id) && !line.contains("rdf-syntax-ns#type") {
-                        continue;
-                    }
-                }
-            }
-
-            // Apply language filter to any triple with a language tag
-            if filter.language_filter.is_some() {
-                if let Some(lang_match) = extract_language_tag(&line) {
-                    if !filter.matches_language(&lang_match) {
-                        continue;
-                    }
-                }
+        ctx.position = (current_line, line_offset);
+        batcher.position = ctx.position;
+        if let Some(pattern) = ctx.read_line(line, &mut batcher) {
+            if show_progress {
+                eprintln!(
+                    "Warning: line {} has unrecognized subject pattern {}, dropping such lines",
+                    current_line, pattern
+                );
             }
-
-            current_triples.push(line);
         }
     }
 
-    // Add last entity to batch
-    if let Some(ref entity_id) = current_entity {
-        entity_batch.push(create_entity(
-            entity_id,
-            &mut current_metadata,
-            &mut current_triples,
-            &mut entity_claims,
-            &mut entity_type,
-            &mut entity_labels,
-            &mut entity_descriptions,
-            &mut entity_aliases,
-        ));
-    }
-
-    // Process remaining batch
-    if !entity_batch.is_empty() {
-        let results = process_rdf_batch_parallel(&entity_batch, filter);
-
-        if output_format == OutputFormat::NTriples && !header_written && !results.is_empty() {
-            let header_count = write_header_batch(output, &header_lines)?;
-            triples_output.fetch_add(header_count, Ordering::Relaxed);
-        }
-
-        let (ent_count, triple_count) = write_rdf_output_batch(output, &results, output_format)?;
-        entities_matched.fetch_add(ent_count, Ordering::Relaxed);
-        triples_output.fetch_add(triple_count, Ordering::Relaxed);
+    // Add last entity to batch and process remaining batch
+    batcher.position = interrupted_at
+        .or(stopped_at)
+        .unwrap_or((lines_read + 1, offset));
+    ctx.finish(&mut batcher);
+    batcher.emit(false);
+    if !batcher.emitter.wait_idle() {
+        return Ok(());
     }
 
     if show_progress {
         eprintln!(
-            "Done! Total {} lines, skipped {}, processed {}, matched {} entities, output {} triples",
-            lines_processed.load(Ordering::Relaxed),
-            lines_skipped.load(Ordering::Relaxed),
+            "Done! Processed {} lines, matched {} entities, output {} triples",
             lines_actually_processed,
             entities_matched.load(Ordering::Relaxed),
             triples_output.load(Ordering::Relaxed)
         );
     }
 
+    if let Some(ref stats) = options.stats {
+        stats.add(Counts {
+            lines_read: lines_read - options.resume_position.0,
+            ..Default::default()
+        });
+    }
+
+    ctx.unrecognized.report();
+
+    if let Some((line, offset)) = interrupted_at {
+        signal::report_interrupt(Some(line - 1), offset);
+    }
+
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::datatype::DatatypeFilter;
+    use crate::test_util::TempDir;
 
     fn create_test_entity() -> RdfEntity {
         let mut claims = HashMap::new();
@@ -679,11 +2516,17 @@ mod tests {
             id: "Q183".to_string(),
             metadata: vec![],
             triples: vec![],
+            lines: vec![],
             claims,
+            literals: vec![],
+            special_values: vec![],
+            statements: vec![],
             entity_type: Some("item".to_string()),
+            datatype: None,
             labels,
             descriptions,
             aliases,
+            sitelinks: BTreeMap::new(),
         }
     }
 
@@ -807,10 +2650,12 @@ mod tests {
             .claims
             .insert("P1628".to_string(), HashSet::from(["P279".to_string()]));
 
+        entity.datatype = Some("wikibase-item".to_string());
         let json = rdf_entity_to_json(&entity);
 
         assert_eq!(json["id"], "P31");
         assert_eq!(json["type"], "property");
+        assert_eq!(json["datatype"], "wikibase-item");
 
         // Check that property values have correct entity-type
         let claims = json.get("claims").unwrap();
@@ -847,63 +2692,1085 @@ mod tests {
         assert_ne!(OutputFormat::NTriples, OutputFormat::Json);
     }
 
+    fn entity_from_lines(lines: &[&str]) -> RdfEntity {
+        let filter = EntityFilter::default();
+        let mut entity = RdfEntity::new("Q183");
+        for line in lines {
+            add_triple(&mut entity, line.to_string(), &filter).unwrap();
+        }
+        entity
+    }
+
+    #[test]
+    fn test_add_triple_label() {
+        let label_line = r#"<http://www.wikidata.org/entity/Q183> <http://www.w3.org/2000/01/rdf-schema#label> "Germany"@en ."#;
+        let non_label_line = r#"<http://www.wikidata.org/entity/Q183> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q6256> ."#;
+        let entity = entity_from_lines(&[label_line, non_label_line]);
+
+        assert_eq!(entity.labels.len(), 1);
+        assert_eq!(entity.labels["en"], "Germany");
+        assert_eq!(entity.triples.len(), 2);
+    }
+
+    #[test]
+    fn test_add_triple_unescapes_terms() {
+        let lines = [
+            r#"<http://www.wikidata.org/entity/Q183> <http://www.w3.org/2000/01/rdf-schema#label> "Deutschland \"BRD\""@de ."#,
+            r#"<http://www.wikidata.org/entity/Q183> <http://schema.org/description> "Staat in Mitteleuropa\nund \u00DCberblick \U0001F1E9"@de ."#,
+            r#"<http://www.wikidata.org/entity/Q183> <http://www.w3.org/2004/02/skos/core#altLabel> "Back\\slash"@de ."#,
+        ];
+        let entity = entity_from_lines(&lines);
+        assert_eq!(entity.labels["de"], r#"Deutschland "BRD""#);
+        assert_eq!(
+            entity.descriptions["de"],
+            "Staat in Mitteleuropa\nund Überblick \u{1F1E9}"
+        );
+        assert_eq!(entity.aliases["de"], vec![r"Back\slash"]);
+        // The triples are written as they were read
+        assert_eq!(entity.triples, lines);
+
+        let json = rdf_entity_to_json(&entity).to_string();
+        assert!(json.contains(r#""value":"Deutschland \"BRD\"""#));
+        assert!(json.contains(r#""value":"Back\\slash""#));
+    }
+
+    #[test]
+    fn test_add_triple_description() {
+        let desc_line = r#"<http://www.wikidata.org/entity/Q183> <http://schema.org/description> "country in Central Europe"@en ."#;
+        let entity = entity_from_lines(&[desc_line]);
+        assert_eq!(entity.descriptions["en"], "country in Central Europe");
+        assert!(entity.labels.is_empty());
+    }
+
+    #[test]
+    fn test_add_triple_alias() {
+        let alias_line = r#"<http://www.wikidata.org/entity/Q183> <http://www.w3.org/2004/02/skos/core#altLabel> "Federal Republic of Germany"@en ."#;
+        let entity = entity_from_lines(&[alias_line, alias_line]);
+        assert_eq!(entity.aliases["en"].len(), 2);
+    }
+
+    #[test]
+    fn test_add_triple_claims_and_type() {
+        let entity = entity_from_lines(&[
+            "<http://www.wikidata.org/entity/Q183> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q6256> .",
+            r#"<http://www.wikidata.org/entity/Q183> <http://www.wikidata.org/prop/direct/P1082> "+83"^^<http://www.w3.org/2001/XMLSchema#decimal> ."#,
+            "<http://www.wikidata.org/entity/Q183> <http://www.wikidata.org/prop/direct-normalized/P227> <https://d-nb.info/gnd/4011882-4> .",
+            "<http://www.wikidata.org/entity/Q183> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://wikiba.se/ontology#Item> .",
+        ]);
+        assert_eq!(entity.claims["P31"], HashSet::from(["Q6256".to_string()]));
+        assert!(entity.claims["P1082"].is_empty());
+        assert!(entity.claims.contains_key("P227"));
+        assert_eq!(entity.entity_type.as_deref(), Some("item"));
+        let matches = |input: &str| {
+            crate::claim_parser::parse_claim_filter(input)
+                .unwrap()
+                .matches(&entity)
+        };
+        assert!(matches("P1082>80") && !matches("P1082>83"));
+        // Truthy values do not carry their unit
+        assert!(!matches("P1082>80 1"));
+        assert!(matches(r#"P227~"/gnd/4011""#) && !matches(r#"P227:"4011882-4""#));
+        assert!(!matches(r#"P31~"Q6256""#));
+    }
+
+    #[test]
+    fn test_add_triple_attribute_filters() {
+        let lines = [
+            r#"<http://www.wikidata.org/entity/Q183> <http://www.w3.org/2000/01/rdf-schema#label> "Germany"@en ."#,
+            r#"<http://www.wikidata.org/entity/Q183> <http://schema.org/name> "Deutschland"@de ."#,
+            r#"<http://www.wikidata.org/entity/Q183> <http://schema.org/description> "country"@en ."#,
+            "<http://www.wikidata.org/entity/Q183> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q6256> .",
+            "<http://www.wikidata.org/entity/Q183> <http://www.wikidata.org/prop/P17> <http://www.wikidata.org/entity/statement/Q183-1> .",
+            "<http://www.wikidata.org/entity/Q183> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://wikiba.se/ontology#Item> .",
+            r#"<http://www.wikidata.org/entity/Q183> <http://schema.org/version> "2245066389"^^<http://www.w3.org/2001/XMLSchema#integer> ."#,
+        ];
+        let kept = |keep: Option<&str>, omit: Option<&str>| {
+            let (keep_attributes, omit_attributes) =
+                crate::filter::parse_attribute_filters(keep, omit).unwrap();
+            let filter = EntityFilter {
+                keep_attributes,
+                omit_attributes,
+                ..Default::default()
+            };
+            let mut entity = RdfEntity::new("Q183");
+            for line in lines {
+                add_triple(&mut entity, line.to_string(), &filter).unwrap();
+            }
+            let kept: Vec<usize> = lines
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| entity.triples.iter().any(|t| t == *line))
+                .map(|(i, _)| i)
+                .collect();
+            (kept, entity)
+        };
+        // Other triples, such as the version, are kept
+        let (triples, entity) = kept(Some("labels,claims.P31"), None);
+        assert_eq!(triples, [0, 1, 3, 6]);
+        // The filters still see the dropped data
+        assert_eq!(entity.descriptions["en"], "country");
+        assert_eq!(kept(Some("labels.de,type"), None).0, [1, 5, 6]);
+        assert_eq!(kept(None, Some("claims,labels.en")).0, [1, 2, 5, 6]);
+    }
+
+    #[test]
+    fn test_add_triple_datatypes() {
+        let entity = entity_from_lines(&[
+            "<http://www.wikidata.org/entity/P212> <http://wikiba.se/ontology#propertyType> <http://wikiba.se/ontology#ExternalId> .",
+        ]);
+        assert_eq!(entity.datatype.as_deref(), Some("external-id"));
+
+        let mut datatypes = DatatypeFilter::parse("external-id", false).unwrap();
+        datatypes
+            .properties
+            .insert("P212".to_string(), "external-id".to_string());
+        let lines = [
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P212> "978-3-16-148410-0" ."#,
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q571> .",
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.w3.org/2000/01/rdf-schema#label> "Buch"@de ."#,
+        ];
+        let written = |datatypes: DatatypeFilter| {
+            let filter = EntityFilter {
+                datatype_filter: Some(datatypes),
+                unit_conversions: None,
+                unicode_form: None,
+                ..Default::default()
+            };
+            let mut entity = RdfEntity::new("Q1");
+            for line in lines {
+                add_triple(&mut entity, line.to_string(), &filter).unwrap();
+            }
+            // Claims are still matched on
+            assert!(entity.claims.contains_key("P31"));
+            entity.triples
+        };
+        assert_eq!(written(datatypes.clone()), [lines[0], lines[2]]);
+        datatypes.omit = true;
+        assert_eq!(written(datatypes), [lines[1], lines[2]]);
+    }
+
+    #[test]
+    fn test_add_triple_special_values() {
+        let lines = [
+            "<http://www.wikidata.org/entity/Q183> <http://www.wikidata.org/prop/direct/P570> <http://www.wikidata.org/.well-known/genid/0a1b> .",
+            "<http://www.wikidata.org/entity/Q183> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://www.wikidata.org/prop/novalue/P40> .",
+            "<http://www.wikidata.org/entity/Q183> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q6256> .",
+        ];
+        let entity = entity_from_lines(&lines);
+        assert!(!entity.claims.contains_key("P570"));
+        assert_eq!(entity.triples.len(), 3);
+        let matches = |input: &str| {
+            crate::claim_parser::parse_claim_filter(input)
+                .unwrap()
+                .matches(&entity)
+        };
+        assert!(matches("P570") && matches("P570:somevalue") && !matches("P570:*"));
+        assert!(matches("P40:novalue") && !matches("P31:novalue"));
+        let values_only = |input: &str| {
+            crate::claim_parser::parse_claim_filter(input)
+                .unwrap()
+                .exclude_special_values()
+                .matches(&entity)
+        };
+        assert!(!values_only("P570") && !values_only("P40") && values_only("P31"));
+
+        let filter = EntityFilter {
+            exclude_special_values: true,
+            ..Default::default()
+        };
+        let mut entity = RdfEntity::new("Q183");
+        for line in lines {
+            add_triple(&mut entity, line.to_string(), &filter).unwrap();
+        }
+        assert_eq!(entity.triples, [lines[2]]);
+    }
+
     #[test]
-    fn test_extract_language_tag() {
+    fn test_entity_ids() {
         assert_eq!(
-            extract_language_tag(r#""Germany"@en ."#),
-            Some("en".to_string())
+            entity_value_id("<http://www.wikidata.org/entity/Q42> <p> <o> ."),
+            Some("Q42")
         );
         assert_eq!(
-            extract_language_tag(r#""Deutschland"@de ."#),
-            Some("de".to_string())
+            entity_value_id("<http://www.wikidata.org/entity/Q42-F1> <p> <o> ."),
+            None
+        );
+        assert_eq!(
+            entity_value_id("<http://www.wikidata.org/entity/P31>"),
+            Some("P31")
+        );
+        assert_eq!(
+            entity_value_id("<http://www.wikidata.org/entity/L7-F1>"),
+            None
+        );
+        assert_eq!(
+            entity_data_id("<https://www.wikidata.org/wiki/Special:EntityData/Q1> <p> <o> ."),
+            Some("Q1")
         );
         assert_eq!(
-            extract_language_tag(r#""Schweiz"@de-ch ."#),
-            Some("de-ch".to_string())
+            entity_data_id("<https://www.wikidata.org/wiki/Special:EntityData/P31> <p> <o> ."),
+            Some("P31")
         );
         assert_eq!(
-            extract_language_tag(r#"<http://example.org/thing> ."#),
+            direct_property_id("<http://www.wikidata.org/prop/direct/P31>"),
+            Some("P31")
+        );
+        assert_eq!(
+            direct_property_id("<http://www.wikidata.org/prop/P31>"),
             None
         );
     }
 
     #[test]
-    fn test_rdf_regexes_label() {
-        let regexes = RdfRegexes::new();
+    fn test_next_entity_start() {
+        let data = concat!(
+            "<https://www.wikidata.org/wiki/Special:EntityData/Q1> <p> <o> .\n",
+            "<http://www.wikidata.org/entity/Q1> <p> \"a\" .\n",
+            "<http://www.wikidata.org/entity/statement/Q1-X> <p> <o> .\n",
+            "<http://www.wikidata.org/entity/Q1> <p> \"b\" .\n",
+            "<https://www.wikidata.org/wiki/Special:EntityData/Q2> <p> <o> .\n",
+            "<http://www.wikidata.org/entity/Q2> <p> \"c\" .\n",
+        )
+        .as_bytes();
+        let q2 = data
+            .windows(9)
+            .position(|w| w == b"/Q2> <p> ")
+            .map(|i| i - "<https://www.wikidata.org/wiki/Special:EntityData".len())
+            .unwrap();
+
+        // Every split inside Q1 moves to the first Q2 line
+        for pos in 1..q2 {
+            assert_eq!(next_entity_start(data, pos), q2);
+        }
+        assert_eq!(next_entity_start(data, q2 + 1), data.len());
+        let ranges: Vec<_> = mmap::chunk_ranges(data, 10, next_entity_start).collect();
+        assert_eq!(ranges, vec![0..q2, q2..data.len()]);
+    }
 
-        let label_line = r#"<http://www.wikidata.org/entity/Q183> <http://www.w3.org/2000/01/rdf-schema#label> "Germany"@en ."#;
-        assert!(regexes.label_re.is_match(label_line));
+    #[test]
+    fn test_subject_pattern() {
+        assert_eq!(
+            subject_pattern(
+                r#"<http://www.wikidata.org/entity/P31> <http://schema.org/name> "x"@en ."#
+            ),
+            "<http://www.wikidata.org/entity/PN>"
+        );
+        assert_eq!(
+            subject_pattern("<http://www.wikidata.org/entity/L7-F1> <http://x> <http://y> ."),
+            "<http://www.wikidata.org/entity/LN-FN>"
+        );
+        assert_eq!(
+            subject_pattern(
+                "<https://de.wikipedia.org/wiki/Berlin> <http://schema.org/about> <http://www.wikidata.org/entity/Q64> ."
+            ),
+            "<https://de.wikipedia.org/wiki/*>"
+        );
+        assert_eq!(subject_pattern("_:b0 <http://x> <http://y> ."), "_:*");
+        assert_eq!(subject_pattern("garbage"), "<non-IRI subject>");
+    }
 
-        let non_label_line = r#"<http://www.wikidata.org/entity/Q183> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q6256> ."#;
-        assert!(!regexes.label_re.is_match(non_label_line));
+    #[test]
+    fn test_unrecognized_subjects_counting() {
+        let mut unrecognized = UnrecognizedSubjects::default();
+
+        // Properties, lexemes and the nodes of full dumps are known
+        for line in [
+            "<http://www.wikidata.org/entity/P31> <http://x> <http://y> .",
+            "<http://www.wikidata.org/entity/L1> <http://x> <http://y> .",
+            "<http://www.wikidata.org/entity/statement/P31-abc> <http://x> <http://y> .",
+            "<http://www.wikidata.org/value/0123abc> <http://x> <http://y> .",
+            "<http://www.wikidata.org/prop/P31> <http://x> <http://y> .",
+        ] {
+            assert_eq!(unrecognized.record(line), None);
+        }
+        assert_eq!(unrecognized.total(), 0);
+
+        let e_line = "<http://www.wikidata.org/entity/E1> <http://x> <http://y> .";
+        let e_line2 = "<http://www.wikidata.org/entity/E22> <http://x> <http://y> .";
+        let other_line = "<http://example.org/thing> <http://x> <http://y> .";
+
+        // First occurrence of a pattern is reported, later ones are only counted
+        assert_eq!(
+            unrecognized.record(e_line).as_deref(),
+            Some("<http://www.wikidata.org/entity/EN>")
+        );
+        assert_eq!(unrecognized.record(e_line2), None);
+        assert!(unrecognized.record(other_line).is_some());
+
+        assert_eq!(unrecognized.total(), 3);
+        let patterns = unrecognized.patterns();
+        assert_eq!(patterns[0], ("<http://www.wikidata.org/entity/EN>", 2));
+        assert_eq!(patterns[1], ("<http://example.org/*>", 1));
     }
 
     #[test]
-    fn test_rdf_regexes_description() {
-        let regexes = RdfRegexes::new();
+    fn test_filter_rdf_keeps_processing_after_unrecognized_subject() {
+        let input = "\
+<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .
+<http://www.wikidata.org/entity/E1> <http://www.w3.org/2000/01/rdf-schema#label> \"new entity type\"@en .
+<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .
+";
+        let filter = Arc::new(EntityFilter::default());
+        let options = ProcessingOptions {
+            batch_size: 1,
+            ..Default::default()
+        };
 
-        let desc_line = r#"<http://www.wikidata.org/entity/Q183> <http://schema.org/description> "country in Central Europe"@en ."#;
-        assert!(regexes.description_re.is_match(desc_line));
+        let mut output = Vec::new();
+        filter_rdf_parallel(input.as_bytes(), &mut output, &mut [], &filter, &options).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("entity/Q1>"));
+        assert!(output.contains("entity/Q2>"));
+        assert!(!output.contains("new entity type"));
     }
 
     #[test]
-    fn test_rdf_regexes_alias() {
-        let regexes = RdfRegexes::new();
+    fn test_filter_rdf_entity_data() {
+        let input = "\
+<https://www.wikidata.org/wiki/Special:EntityData/Q64> <http://wikiba.se/ontology#sitelinks> \"250\"^^<http://www.w3.org/2001/XMLSchema#integer> .
+<https://www.wikidata.org/wiki/Special:EntityData/Q64> <http://schema.org/dateModified> \"2024-03-01T08:00:00Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .
+<http://www.wikidata.org/entity/Q64> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q515> .
+<https://www.wikidata.org/wiki/Special:EntityData/Q1> <http://wikiba.se/ontology#sitelinks> \"3\"^^<http://www.w3.org/2001/XMLSchema#integer> .
+<https://www.wikidata.org/wiki/Special:EntityData/Q1> <http://schema.org/dateModified> \"2023-12-31T23:59:59Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .
+<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q515> .
+<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q515> .
+";
+        let written = |filter: EntityFilter| {
+            let mut output = Vec::new();
+            filter_rdf_parallel(
+                input.as_bytes(),
+                &mut output,
+                &mut [],
+                &Arc::new(filter),
+                &ProcessingOptions::default(),
+            )
+            .unwrap();
+            let output = String::from_utf8(output).unwrap();
+            ["Q64", "Q1", "Q2"]
+                .into_iter()
+                .filter(|id| output.contains(&format!("entity/{}>", id)))
+                .collect::<Vec<_>>()
+        };
+        let min_sitelinks = EntityFilter {
+            min_sitelinks: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(written(min_sitelinks), ["Q64"]);
+        let modified_since = EntityFilter {
+            modified_since: Some("2024-01-01".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(written(modified_since), ["Q64"]);
+    }
 
-        let alias_line = r#"<http://www.wikidata.org/entity/Q183> <http://www.w3.org/2004/02/skos/core#altLabel> "Federal Republic of Germany"@en ."#;
-        assert!(regexes.alias_re.is_match(alias_line));
+    #[test]
+    fn test_filter_rdf_claims_only() {
+        let input = "\
+<http://wikiba.se/ontology#Dump> <http://schema.org/dateModified> \"2024-03-01T00:00:00Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .
+<https://www.wikidata.org/wiki/Special:EntityData/Q64> <http://wikiba.se/ontology#statements> \"1\"^^<http://www.w3.org/2001/XMLSchema#integer> .
+<http://www.wikidata.org/entity/Q64> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://wikiba.se/ontology#Item> .
+<http://www.wikidata.org/entity/Q64> <http://schema.org/version> \"2245066389\"^^<http://www.w3.org/2001/XMLSchema#integer> .
+<http://www.wikidata.org/entity/Q64> <http://www.w3.org/2000/01/rdf-schema#label> \"Berlin\"@en .
+<http://www.wikidata.org/entity/Q64> <http://schema.org/name> \"Berlin\"@en .
+<http://www.wikidata.org/entity/Q64> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q515> .
+<https://en.wikipedia.org/wiki/Berlin> <http://schema.org/about> <http://www.wikidata.org/entity/Q64> .
+";
+        let filter = EntityFilter {
+            claims_only: true,
+            min_statements: Some(1),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        filter_rdf_parallel(
+            input.as_bytes(),
+            &mut output,
+            &mut [],
+            &Arc::new(filter),
+            &ProcessingOptions::default(),
+        )
+        .unwrap();
+        // The metadata still counts for the filters
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<http://www.wikidata.org/entity/Q64> <http://www.w3.org/2000/01/rdf-schema#label> \"Berlin\"@en .\n\
+             <http://www.wikidata.org/entity/Q64> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q515> .\n"
+        );
+    }
+
+    #[test]
+    fn test_filter_rdf_materialize_mul() {
+        let input = "\
+<http://www.wikidata.org/entity/Q42> <http://www.w3.org/2000/01/rdf-schema#label> \"Douglas Adams\"@mul .
+<http://www.wikidata.org/entity/Q42> <http://www.w3.org/2000/01/rdf-schema#label> \"Douglas Adams (Autor)\"@de .
+<http://www.wikidata.org/entity/Q42> <http://schema.org/description> \"writer\"@en .
+<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P1559> \"Douglas Adams\"@mul .
+";
+        let filter = EntityFilter {
+            language_filter: Some(HashSet::from(["en".to_string(), "de".to_string()])),
+            include_mul: true,
+            materialize_mul: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        filter_rdf_parallel(
+            input.as_bytes(),
+            &mut output,
+            &mut [],
+            &Arc::new(filter),
+            &ProcessingOptions::default(),
+        )
+        .unwrap();
+        // Claims of monolingual text keep their language
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<http://www.wikidata.org/entity/Q42> <http://www.w3.org/2000/01/rdf-schema#label> \"Douglas Adams\"@en .\n\
+             <http://www.wikidata.org/entity/Q42> <http://www.w3.org/2000/01/rdf-schema#label> \"Douglas Adams (Autor)\"@de .\n\
+             <http://www.wikidata.org/entity/Q42> <http://schema.org/description> \"writer\"@en .\n\
+             <http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P1559> \"Douglas Adams\"@mul .\n"
+        );
+    }
+
+    #[test]
+    fn test_normalize_literal() {
+        let line = "<http://www.wikidata.org/entity/Q72> <http://www.w3.org/2000/01/rdf-schema#label> \"Zu\u{308}rich\"@de .";
+        assert_eq!(
+            normalize_literal(line.to_string(), NormalizationForm::Nfc),
+            "<http://www.wikidata.org/entity/Q72> <http://www.w3.org/2000/01/rdf-schema#label> \"Zürich\"@de ."
+        );
+        let nfc = "<http://www.wikidata.org/entity/Q72> <http://www.wikidata.org/prop/direct/P1448> \"Zürich\"@de .";
+        assert_eq!(
+            normalize_literal(nfc.to_string(), NormalizationForm::Nfc),
+            nfc
+        );
+        // Escaped marks are composed, and other escapes kept valid
+        let escaped = "<http://www.wikidata.org/entity/Q72> <http://www.w3.org/2000/01/rdf-schema#label> \"\\\"Zu\\u0308rich\\\"\"@de .";
+        assert_eq!(
+            normalize_literal(escaped.to_string(), NormalizationForm::Nfc),
+            "<http://www.wikidata.org/entity/Q72> <http://www.w3.org/2000/01/rdf-schema#label> \"\\\"Zürich\\\"\"@de ."
+        );
+        // Sitelink articles are left as they are
+        let article = "<https://de.wikipedia.org/wiki/Z%C3%BCrich> <http://schema.org/name> \"Zu\u{308}rich\"@de .";
+        assert_eq!(
+            normalize_literal(article.to_string(), NormalizationForm::Nfc),
+            article
+        );
+    }
+
+    #[test]
+    fn test_sitelink_site() {
+        for (iri, site) in [
+            ("<https://en.wikipedia.org/wiki/Berlin>", "enwiki"),
+            ("<https://zh-min-nan.wikipedia.org/>", "zh_min_nanwiki"),
+            ("<https://de.wikivoyage.org/wiki/Berlin>", "dewikivoyage"),
+            (
+                "<https://commons.wikimedia.org/wiki/Category:Berlin>",
+                "commonswiki",
+            ),
+            ("<https://www.wikidata.org/wiki/Q64>", "wikidatawiki"),
+        ] {
+            assert_eq!(sitelink_site(iri).as_deref(), Some(site));
+        }
+        assert_eq!(sitelink_site("<https://example.org/wiki/Berlin>"), None);
+        assert_eq!(sitelink_site("<http://www.wikidata.org/entity/Q64>"), None);
+    }
+
+    #[test]
+    fn test_filter_rdf_sitelinks() {
+        let input = "\
+<http://www.wikidata.org/entity/Q64> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q515> .
+<https://en.wikipedia.org/wiki/Berlin> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://schema.org/Article> .
+<https://en.wikipedia.org/wiki/Berlin> <http://schema.org/about> <http://www.wikidata.org/entity/Q64> .
+<https://en.wikipedia.org/wiki/Berlin> <http://schema.org/name> \"Berlin\"@en .
+<https://en.wikipedia.org/wiki/Berlin> <http://wikiba.se/ontology#badge> <http://www.wikidata.org/entity/Q17437798> .
+<https://en.wikipedia.org/> <http://wikiba.se/ontology#wikiGroup> \"wikipedia\" .
+<https://de.wikipedia.org/wiki/Berlin> <http://schema.org/about> <http://www.wikidata.org/entity/Q64> .
+<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q515> .
+";
+        let run = |filter: EntityFilter, output_format: OutputFormat| {
+            let options = ProcessingOptions {
+                output_format,
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            filter_rdf_parallel(
+                input.as_bytes(),
+                &mut output,
+                &mut [],
+                &Arc::new(filter),
+                &options,
+            )
+            .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let sites = |sites: &[&str]| EntityFilter {
+            sitelink_sites: Some(sites.iter().map(|s| s.to_string()).collect()),
+            ..Default::default()
+        };
+        let output = run(sites(&["dewiki", "frwiki"]), OutputFormat::NTriples);
+        assert_eq!(output.lines().count(), 7);
+        assert!(!output.contains("entity/Q1>"));
+        assert_eq!(run(sites(&["frwiki"]), OutputFormat::NTriples), "");
+
+        let badge = EntityFilter {
+            badge_filter: Some(
+                crate::filter::BadgeFilter::parse("Q17437798", Some("enwiki")).unwrap(),
+            ),
+            min_sitelinks: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(run(badge, OutputFormat::NTriples).lines().count(), 7);
+
+        let omit = EntityFilter {
+            omit_attributes: Some(HashSet::from(["sitelinks.enwiki".to_string()])),
+            ..Default::default()
+        };
+        let output = run(omit, OutputFormat::Json);
+        let json: serde_json::Value = serde_json::from_str(output.lines().next().unwrap()).unwrap();
+        assert_eq!(
+            json["sitelinks"],
+            serde_json::json!({"dewiki": {"site": "dewiki", "title": "", "badges": []}})
+        );
+        let output = run(sites(&["enwiki"]), OutputFormat::Json);
+        let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(json["sitelinks"]["enwiki"]["title"], "Berlin");
+        assert_eq!(
+            json["sitelinks"]["enwiki"]["badges"],
+            serde_json::json!(["Q17437798"])
+        );
+    }
+
+    /// Returns one line per read and sets `flag` once `after` lines were read
+    struct InterruptAfter {
+        lines: Vec<&'static str>,
+        after: usize,
+        flag: Arc<AtomicBool>,
+    }
+
+    impl std::io::Read for InterruptAfter {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.lines.is_empty() {
+                return Ok(0);
+            }
+            let line = self.lines.remove(0);
+            buf[..line.len()].copy_from_slice(line.as_bytes());
+            self.after = self.after.saturating_sub(1);
+            if self.after == 0 {
+                self.flag.store(true, Ordering::Relaxed);
+            }
+            Ok(line.len())
+        }
+    }
+
+    #[test]
+    fn test_interrupt_drops_unfinished_entity() {
+        let options = ProcessingOptions {
+            batch_size: 1,
+            ..Default::default()
+        };
+        let reader = std::io::BufReader::new(InterruptAfter {
+            lines: vec![
+                "<http://www.wikidata.org/entity/Q1> <p> \"a\" .\n",
+                "<http://www.wikidata.org/entity/Q1> <p> \"b\" .\n",
+                "<http://www.wikidata.org/entity/Q2> <p> \"c\" .\n",
+                "<http://www.wikidata.org/entity/Q2> <p> \"d\" .\n",
+                "<http://www.wikidata.org/entity/Q3> <p> \"e\" .\n",
+            ],
+            after: 4,
+            flag: options.interrupted.clone(),
+        });
+
+        let mut output = Vec::new();
+        filter_rdf_parallel(
+            reader,
+            &mut output,
+            &mut [],
+            &Arc::new(EntityFilter::default()),
+            &options,
+        )
+        .unwrap();
+
+        // Q2 was cut off, so only Q1 is written
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.lines().all(|l| l.contains("/Q1>")));
     }
 
     #[test]
-    fn test_rdf_regexes_lang_literal() {
-        let regexes = RdfRegexes::new();
+    fn test_checkpoint_and_resume() {
+        let lines = vec![
+            "<http://www.wikidata.org/entity/Q1> <p> \"a\" .\n",
+            "<http://www.wikidata.org/entity/Q1> <p> \"b\" .\n",
+            "<http://www.wikidata.org/entity/Q2> <p> \"c\" .\n",
+            "<http://www.wikidata.org/entity/Q2> <p> \"d\" .\n",
+            "<http://www.wikidata.org/entity/Q3> <p> \"e\" .\n",
+        ];
+        let dir = TempDir::new("rdf-resume");
+        let path = |name: &str| dir.file(name);
+        let config = CheckpointConfig {
+            path: path("run.checkpoint"),
+            input: "dump.nt".to_string(),
+            output: path("out.nt"),
+            interval: std::time::Duration::from_secs(3600),
+            blocks: None,
+            member_boundary: Default::default(),
+        };
+        let options = ProcessingOptions {
+            batch_size: 1,
+            checkpoint: Some(config.clone()),
+            ..Default::default()
+        };
+        let reader = std::io::BufReader::new(InterruptAfter {
+            lines: lines.clone(),
+            after: 4,
+            flag: options.interrupted.clone(),
+        });
+        let mut output = std::fs::File::create(&config.output).unwrap();
+        let filter = Arc::new(EntityFilter::default());
+        filter_rdf_parallel(reader, &mut output, &mut [], &filter, &options).unwrap();
+
+        // Q2 was cut off, so the checkpoint points at its first line
+        let checkpoint = crate::checkpoint::Checkpoint::load(&config.path).unwrap();
+        assert_eq!(checkpoint.lines, 2);
+        assert_eq!(checkpoint.offset as usize, lines[0].len() + lines[1].len());
+
+        let options = ProcessingOptions {
+            batch_size: 1,
+            checkpoint: Some(config.clone()),
+            resume_position: (checkpoint.lines, checkpoint.offset),
+            ..Default::default()
+        };
+        let rest = lines[2..].concat();
+        filter_rdf_parallel(rest.as_bytes(), &mut output, &mut [], &filter, &options).unwrap();
 
-        let line = r#"<http://www.wikidata.org/entity/Q183> <http://www.w3.org/2000/01/rdf-schema#label> "Deutschland"@de ."#;
-        let caps = regexes.lang_literal_re.captures(line);
-        assert!(caps.is_some());
+        assert_eq!(
+            std::fs::read_to_string(&config.output).unwrap(),
+            lines.concat()
+        );
+        let checkpoint = crate::checkpoint::Checkpoint::load(&config.path).unwrap();
+        assert_eq!(checkpoint.lines, 5);
+    }
+
+    #[test]
+    fn test_skip_lines_resumes_at_next_subject() {
+        // No EntityData lines, as in a resumed truthy dump
+        let input = "\
+<http://www.wikidata.org/entity/Q1> <p> \"a\" .
+<http://www.wikidata.org/entity/Q1> <p> \"b\" .
+<http://www.wikidata.org/entity/Q2> <p> \"c\" .
+<http://www.wikidata.org/entity/Q2> <p> \"d\" .
+<http://www.wikidata.org/entity/Q3> <p> \"e\" .
+";
+        let options = ProcessingOptions {
+            skip_lines: 3,
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        filter_rdf_parallel(
+            input.as_bytes(),
+            &mut output,
+            &mut [],
+            &Arc::new(EntityFilter::default()),
+            &options,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.contains("/Q3>"));
+    }
+
+    #[test]
+    fn test_skip_and_max_entities() {
+        let input: String = (1..=10)
+            .map(|n| format!("<http://www.wikidata.org/entity/Q{n}> <p> \"{n}\" .\n"))
+            .collect();
+        let options = ProcessingOptions {
+            batch_size: 3,
+            skip_entities: 2,
+            max_entities: 4,
+            stats: Some(Arc::new(crate::stats::RunStats::default())),
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        filter_rdf_parallel(
+            input.as_bytes(),
+            &mut output,
+            &mut [],
+            &Arc::new(EntityFilter::default()),
+            &options,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let ids: Vec<&str> = output.lines().filter_map(entity_value_id).collect();
+        assert_eq!(ids, ["Q3", "Q4", "Q5", "Q6"]);
+        // Matches past the limit are not counted
+        let report = options
+            .stats
+            .unwrap()
+            .report(&EntityFilter::default(), std::time::Duration::ZERO);
+        assert_eq!(report["entities_matched"], 4);
+    }
+
+    #[test]
+    fn test_invert_match() {
+        let input = concat!(
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .\n",
+            "<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q13442814> .\n",
+            "<http://www.wikidata.org/entity/Q3> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q515> .\n",
+        );
+        let filter = Arc::new(EntityFilter {
+            claim_filter: Some(
+                crate::claim_parser::parse_claim_filter("P31:Q5,Q13442814").unwrap(),
+            ),
+            invert_match: true,
+            ..Default::default()
+        });
+
+        let mut output = Vec::new();
+        filter_rdf_parallel(
+            input.as_bytes(),
+            &mut output,
+            &mut [],
+            &filter,
+            &ProcessingOptions::default(),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let ids: Vec<&str> = output.lines().filter_map(entity_value_id).collect();
+        assert_eq!(ids, ["Q3"]);
+    }
+
+    #[test]
+    fn test_output_rejected() {
+        let input = concat!(
+            "<http://wikiba.se/ontology#Dump> <http://schema.org/dateModified> \"2024-01-01\" .\n",
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .\n",
+            "<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q515> .\n",
+            "<http://www.wikidata.org/entity/Q3> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .\n",
+        );
+        let filter = Arc::new(EntityFilter {
+            claim_filter: Some(crate::claim_parser::parse_claim_filter("P31:Q5").unwrap()),
+            ..Default::default()
+        });
+        let options = ProcessingOptions {
+            max_entities: 1,
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        let mut rejected = Vec::new();
+        let inverted = EntityFilter {
+            invert_match: true,
+            ..(*filter).clone()
+        };
+        filter_rdf_parallel(
+            input.as_bytes(),
+            &mut output,
+            &mut [Route {
+                filter: Arc::new(inverted),
+                output: &mut rejected,
+            }],
+            &filter,
+            &options,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let rejected = String::from_utf8(rejected).unwrap();
+        // Both outputs get the dump header; --max-entities only limits matches
+        assert!(output.starts_with("<http://wikiba.se/ontology#Dump>"));
+        assert!(rejected.starts_with("<http://wikiba.se/ontology#Dump>"));
+        let ids = |s: &str| {
+            s.lines()
+                .filter_map(entity_value_id)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        assert_eq!(ids(&output), "Q1");
+        assert_eq!(ids(&rejected), "Q2");
+    }
+
+    #[test]
+    fn test_partition_by_claim_value() {
+        let input = concat!(
+            "<http://wikiba.se/ontology#Dump> <http://schema.org/dateModified> \"2024-01-01\" .\n",
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .\n",
+            "<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q515> .\n",
+            "<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .\n",
+            "<http://www.wikidata.org/entity/Q3> <http://www.wikidata.org/prop/direct/P18> \"x.jpg\" .\n",
+        );
+        let dir = TempDir::new("partition-rdf");
+        let template = dir.file("{value}.nt");
+        let options = ProcessingOptions {
+            partition: Some(Partition::new("P31", &template, 1024).unwrap()),
+            ..Default::default()
+        };
+        filter_rdf_parallel(
+            input.as_bytes(),
+            &mut std::io::sink(),
+            &mut [],
+            &Arc::new(EntityFilter::default()),
+            &options,
+        )
+        .unwrap();
+
+        let read =
+            |value: &str| std::fs::read_to_string(dir.join(format!("{}.nt", value))).unwrap();
+        let ids = |s: &str| {
+            s.lines()
+                .filter_map(entity_value_id)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        // Every file gets the dump header, entities go to each of their values
+        assert!(read("Q5").starts_with("<http://wikiba.se/ontology#Dump>"));
+        assert!(read("Q515").starts_with("<http://wikiba.se/ontology#Dump>"));
+        assert_eq!(ids(&read("Q5")), "Q1,Q2,Q2");
+        assert_eq!(ids(&read("Q515")), "Q2,Q2");
+        assert_eq!(ids(&read("none")), "Q3");
+    }
+
+    #[test]
+    fn test_sort_by_id() {
+        let input: String = [12, 3, 100, 7, 3]
+            .iter()
+            .map(|i| {
+                format!(
+                    "<http://www.wikidata.org/entity/Q{}> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .\n",
+                    i
+                )
+            })
+            .collect();
+        let dir = TempDir::new("sort-rdf");
+        let options = ProcessingOptions {
+            batch_size: 2,
+            sort: Some(SortConfig {
+                memory: 200,
+                dir: dir.path().to_path_buf(),
+            }),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        filter_rdf_parallel(
+            input.as_bytes(),
+            &mut output,
+            &mut [],
+            &Arc::new(EntityFilter::default()),
+            &options,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let ids: Vec<&str> = output.lines().filter_map(entity_value_id).collect();
+        assert_eq!(ids, ["Q3", "Q3", "Q7", "Q12", "Q100"]);
+    }
+
+    #[test]
+    fn test_round_robin_shards() {
+        let input: String = (1..=5)
+            .map(|i| {
+                format!(
+                    "<http://www.wikidata.org/entity/Q{}> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .\n",
+                    i
+                )
+            })
+            .collect();
+        let dir = TempDir::new("shards-rdf");
+        let template = dir.file("part-{shard}.nt");
+        let options = ProcessingOptions {
+            batch_size: 2,
+            partition: Some(
+                Partition::shards(3, partition::ShardBy::RoundRobin, &template, 1024).unwrap(),
+            ),
+            ..Default::default()
+        };
+        filter_rdf_parallel(
+            input.as_bytes(),
+            &mut std::io::sink(),
+            &mut [],
+            &Arc::new(EntityFilter::default()),
+            &options,
+        )
+        .unwrap();
+
+        let ids = |shard: &str| {
+            std::fs::read_to_string(dir.join(format!("part-{}.nt", shard)))
+                .unwrap()
+                .lines()
+                .filter_map(entity_value_id)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        // Matched entities are dealt in turn across batches
+        assert_eq!(ids("0"), "Q1,Q4");
+        assert_eq!(ids("1"), "Q2,Q5");
+        assert_eq!(ids("2"), "Q3");
+    }
+
+    #[test]
+    fn test_split_entities_into_compressed_files() {
+        let mut input =
+            "<http://wikiba.se/ontology#Dump> <http://schema.org/dateModified> \"2024-01-01\" .\n"
+                .to_string();
+        for i in 1..=5 {
+            input.push_str(&format!(
+                "<http://www.wikidata.org/entity/Q{}> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .\n",
+                i
+            ));
+        }
+        let dir = TempDir::new("split-rdf");
+        let output = dir.file("dump.nt.gz");
+        let limits = partition::SplitLimits {
+            max_bytes: u64::MAX,
+            max_entities: 2,
+        };
+        let options = ProcessingOptions {
+            partition: Some(Partition::split(limits, &output, 1024)),
+            ..Default::default()
+        };
+        filter_rdf_parallel(
+            input.as_bytes(),
+            &mut std::io::sink(),
+            &mut [],
+            &Arc::new(EntityFilter::default()),
+            &options,
+        )
+        .unwrap();
+
+        let read = |part: &str| {
+            let file = std::fs::File::open(dir.join(format!("dump-{}.nt.gz", part))).unwrap();
+            let mut text = String::new();
+            std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(file), &mut text)
+                .unwrap();
+            text
+        };
+        let ids = |s: &str| {
+            s.lines()
+                .filter_map(entity_value_id)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        // Every file is a complete gzip stream with the dump header
+        assert!(read("00002").starts_with("<http://wikiba.se/ontology#Dump>"));
+        assert_eq!(ids(&read("00000")), "Q1,Q2");
+        assert_eq!(ids(&read("00001")), "Q3,Q4");
+        assert_eq!(ids(&read("00002")), "Q5");
+        assert!(!dir.join("dump-00003.nt.gz").exists());
+    }
+
+    #[test]
+    fn test_malformed_lines_are_dropped_or_abort_with_strict() {
+        let input = concat!(
+            "<http://www.wikidata.org/entity/Q1> <p> \"1\" .\n",
+            "<http://www.wikidata.org/entity/Q1> <p> \"unterminated .\n",
+            "garbage\n",
+            "<http://www.wikidata.org/entity/Q2> <p> \"2\" .\n",
+        );
+        let run = |strict| {
+            let options = ProcessingOptions {
+                strict,
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            filter_rdf_parallel(
+                input.as_bytes(),
+                &mut output,
+                &mut [],
+                &Arc::new(EntityFilter::default()),
+                &options,
+            )
+            .map(|()| String::from_utf8(output).unwrap())
+        };
 
-        let caps = caps.unwrap();
-        assert_eq!(&caps[1], "Deutschland");
-        assert_eq!(&caps[2], "de");
+        let output = run(false).unwrap();
+        assert_eq!(output.lines().count(), 2);
+        assert!(!output.contains("unterminated") && !output.contains("garbage"));
+
+        let error = run(true).unwrap_err().to_string();
+        assert!(error.contains("line 2"), "{error}");
+        assert!(error.contains("unterminated"), "{error}");
+    }
+
+    #[test]
+    fn test_stable_order_with_parallel_batches() {
+        // Every 7th entity is much larger, so its batch takes longer to filter
+        let mut input = String::new();
+        for n in 1..=300 {
+            let triples = if n % 7 == 0 { 200 } else { 1 };
+            for t in 0..triples {
+                input.push_str(&format!(
+                    "<http://www.wikidata.org/entity/Q{n}> <http://schema.org/name> \"{t}\"@en .\n"
+                ));
+            }
+        }
+        let options = ProcessingOptions {
+            batch_size: 1,
+            pipeline: PipelineConfig {
+                workers: 8,
+                queue_depth: 32,
+                ordered: true,
+                max_bytes: None,
+            },
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        filter_rdf_parallel(
+            input.as_bytes(),
+            &mut output,
+            &mut [],
+            &Arc::new(EntityFilter::default()),
+            &options,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let mut ids: Vec<&str> = output.lines().filter_map(entity_value_id).collect();
+        ids.dedup();
+        let expected: Vec<String> = (1..=300).map(|n| format!("Q{n}")).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_filter_rdf_truthify_full_dump() {
+        let input = "\
+<http://www.wikidata.org/entity/Q42> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://wikiba.se/ontology#Item> .
+<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/P31> <http://www.wikidata.org/entity/statement/Q42-a> .
+<http://www.wikidata.org/entity/statement/Q42-a> <http://wikiba.se/ontology#rank> <http://wikiba.se/ontology#DeprecatedRank> .
+<http://www.wikidata.org/entity/statement/Q42-a> <http://www.wikidata.org/prop/statement/P31> <http://www.wikidata.org/entity/Q6256> .
+<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/P31> <http://www.wikidata.org/entity/statement/Q42-b> .
+<http://www.wikidata.org/entity/statement/Q42-b> <http://wikiba.se/ontology#rank> <http://wikiba.se/ontology#NormalRank> .
+<http://www.wikidata.org/entity/statement/Q42-b> <http://www.wikidata.org/prop/statement/P31> <http://www.wikidata.org/entity/Q5> .
+<http://www.wikidata.org/entity/statement/Q42-b> <http://www.wikidata.org/prop/qualifier/P580> \"2001\" .
+<http://www.wikidata.org/reference/r1> <http://www.wikidata.org/prop/reference/P248> <http://www.wikidata.org/entity/Q1> .
+<http://www.wikidata.org/entity/Q43> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://wikiba.se/ontology#Item> .
+<http://www.wikidata.org/entity/Q43> <http://www.wikidata.org/prop/P31> <http://www.wikidata.org/entity/statement/Q43-a> .
+<http://www.wikidata.org/entity/statement/Q43-a> <http://www.wikidata.org/prop/statement/P31> <http://www.wikidata.org/entity/Q6256> .
+";
+        let filter = Arc::new(EntityFilter {
+            claim_filter: Some(crate::claim_parser::parse_claim_filter("P31:Q5").unwrap()),
+            ..Default::default()
+        });
+        let options = ProcessingOptions {
+            batch_size: 1,
+            truthify: true,
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        filter_rdf_parallel(input.as_bytes(), &mut output, &mut [], &filter, &options).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "\
+<http://www.wikidata.org/entity/Q42> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://wikiba.se/ontology#Item> .
+<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .
+"
+        );
     }
 }
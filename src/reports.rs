@@ -0,0 +1,506 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use crate::claim_parser::parse_claim_filter;
+use crate::compression::{
+    create_input_reader, create_output_writer, determine_compression, finish_output,
+    INPUT_BUFFER_SIZE, OUTPUT_BUFFER_SIZE,
+};
+use crate::diff::EntitySink;
+use crate::filter::EntityFilter;
+use crate::idset::IdSet;
+use crate::rdf::{OutputFormat, ProcessingOptions};
+use crate::sort::EntityKey;
+use crate::{run_filter, FilterError};
+
+/// Arguments for the `stats` subcommand
+#[derive(clap::Args, Debug)]
+pub struct StatsArgs {
+    /// Dump or extract to analyze (supports .bz2, .gz, .lz4)
+    #[arg()]
+    input: String,
+
+    /// Report to compute: properties, labels, sizes
+    #[arg(long, default_value = "properties")]
+    report: String,
+
+    /// Input format: auto, rdf, turtle, json
+    #[arg(short = 'f', long, default_value = "auto")]
+    format: String,
+
+    /// Report format: tsv, json
+    #[arg(short = 'o', long, default_value = "tsv")]
+    output_format: String,
+
+    /// Output file (stdout if not provided)
+    #[arg(long)]
+    output: Option<String>,
+
+    /// Filter by claim (e.g., P31:Q5, P31:Q5,Q6256, P31:Q5&P18)
+    #[arg(short, long)]
+    claim: Option<String>,
+
+    /// Entity type to filter: item, property, or both
+    #[arg(short = 't', long, default_value = "both")]
+    r#type: String,
+
+    /// Keep only specified subject entity IDs (comma-separated, e.g., Q31,Q42)
+    #[arg(long)]
+    subject: Option<String>,
+
+    /// Languages to report (comma-separated, e.g., en,de,fr)
+    #[arg(short = 'l', long)]
+    languages: Option<String>,
+
+    /// Show progress info on stderr
+    #[arg(short = 'p', long)]
+    progress: bool,
+}
+
+/// Entities and statements using one property
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Usage {
+    entities: u64,
+    statements: u64,
+}
+
+/// Property usage histogram (--report properties)
+#[derive(Debug, Default)]
+struct PropertyReport {
+    entities: u64,
+    properties: HashMap<String, Usage>,
+}
+
+impl PropertyReport {
+    fn add(&mut self, entity: &Value) {
+        self.entities += 1;
+        let claims = entity.get("claims").and_then(Value::as_object);
+        for (property, statements) in claims.into_iter().flatten() {
+            let count = statements.as_array().map_or(0, Vec::len) as u64;
+            if count == 0 {
+                continue;
+            }
+            let usage = self.properties.entry(property.clone()).or_default();
+            usage.entities += 1;
+            usage.statements += count;
+        }
+    }
+
+    /// Properties by decreasing number of entities, then by ID
+    fn rows(&self) -> Vec<(&String, Usage)> {
+        let mut rows: Vec<(&String, Usage)> = self
+            .properties
+            .iter()
+            .map(|(property, usage)| (property, *usage))
+            .collect();
+        rows.sort_by_cached_key(|(property, usage)| {
+            (std::cmp::Reverse(usage.entities), EntityKey::new(property))
+        });
+        rows
+    }
+
+    fn write_tsv<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        writeln!(output, "property\tentities\tstatements")?;
+        for (property, usage) in self.rows() {
+            writeln!(
+                output,
+                "{}\t{}\t{}",
+                property, usage.entities, usage.statements
+            )?;
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> Value {
+        let properties: Vec<Value> = self
+            .rows()
+            .into_iter()
+            .map(|(property, usage)| {
+                json!({
+                    "property": property,
+                    "entities": usage.entities,
+                    "statements": usage.statements,
+                })
+            })
+            .collect();
+        json!({"entities": self.entities, "properties": properties})
+    }
+}
+
+/// Entities with a label, description and aliases in one language
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Coverage {
+    labels: u64,
+    descriptions: u64,
+    aliases: u64,
+}
+
+/// Label, description and alias coverage per language (--report labels)
+#[derive(Debug, Default)]
+struct LabelReport {
+    entities: u64,
+    languages: HashMap<String, Coverage>,
+}
+
+impl LabelReport {
+    fn add(&mut self, entity: &Value) {
+        self.entities += 1;
+        let languages = |field: &str| {
+            entity
+                .get(field)
+                .and_then(Value::as_object)
+                .into_iter()
+                .flatten()
+                .filter(|(_, value)| value.as_array().is_none_or(|aliases| !aliases.is_empty()))
+                .map(|(language, _)| language.clone())
+                .collect::<Vec<String>>()
+        };
+        for language in languages("labels") {
+            self.languages.entry(language).or_default().labels += 1;
+        }
+        for language in languages("descriptions") {
+            self.languages.entry(language).or_default().descriptions += 1;
+        }
+        for language in languages("aliases") {
+            self.languages.entry(language).or_default().aliases += 1;
+        }
+    }
+
+    /// Languages by decreasing number of labels, then by code
+    fn rows(&self) -> Vec<(&String, Coverage)> {
+        let mut rows: Vec<(&String, Coverage)> = self
+            .languages
+            .iter()
+            .map(|(language, coverage)| (language, *coverage))
+            .collect();
+        rows.sort_by(|a, b| b.1.labels.cmp(&a.1.labels).then_with(|| a.0.cmp(b.0)));
+        rows
+    }
+
+    fn write_tsv<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        writeln!(output, "language\tlabels\tdescriptions\taliases")?;
+        for (language, coverage) in self.rows() {
+            writeln!(
+                output,
+                "{}\t{}\t{}\t{}",
+                language, coverage.labels, coverage.descriptions, coverage.aliases
+            )?;
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> Value {
+        let languages: Vec<Value> = self
+            .rows()
+            .into_iter()
+            .map(|(language, coverage)| {
+                json!({
+                    "language": language,
+                    "labels": coverage.labels,
+                    "descriptions": coverage.descriptions,
+                    "aliases": coverage.aliases,
+                })
+            })
+            .collect();
+        json!({"entities": self.entities, "languages": languages})
+    }
+}
+
+/// Number of the largest entities listed in the sizes report
+const LARGEST: usize = 10;
+
+/// Distribution of one per-entity number in power-of-two buckets
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Entities with 0, 1, 2-3, 4-7, ... of it
+    buckets: Vec<u64>,
+    total: u64,
+    max: u64,
+    max_entity: String,
+}
+
+impl Histogram {
+    fn add(&mut self, value: u64, id: &str) {
+        let bucket = (u64::BITS - value.leading_zeros()) as usize;
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+        self.total += value;
+        if value > self.max || self.max_entity.is_empty() {
+            self.max = value;
+            self.max_entity = id.to_string();
+        }
+    }
+
+    /// Non-empty buckets: smallest and largest value, entities
+    fn rows(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, entities)| **entities > 0)
+            .map(|(bucket, entities)| match bucket {
+                0 => (0, 0, *entities),
+                _ => (1 << (bucket - 1), (1u64 << bucket) - 1, *entities),
+            })
+    }
+
+    fn to_json(&self) -> Value {
+        let histogram: Vec<Value> = self
+            .rows()
+            .map(|(from, to, entities)| json!({"from": from, "to": to, "entities": entities}))
+            .collect();
+        json!({
+            "total": self.total,
+            "max": self.max,
+            "max_entity": self.max_entity,
+            "histogram": histogram,
+        })
+    }
+}
+
+/// Statements, sitelinks and serialized size per entity (--report sizes)
+#[derive(Debug, Default)]
+struct SizeReport {
+    entities: u64,
+    statements: Histogram,
+    sitelinks: Histogram,
+    bytes: Histogram,
+    /// The largest entities by bytes, smallest first
+    largest: Vec<(u64, String)>,
+}
+
+impl SizeReport {
+    fn add(&mut self, entity: &Value) {
+        self.entities += 1;
+        let id = entity.get("id").and_then(Value::as_str).unwrap_or_default();
+        let statements = entity
+            .get("claims")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+            .map(|(_, statements)| statements.as_array().map_or(0, Vec::len) as u64)
+            .sum();
+        let sitelinks = entity
+            .get("sitelinks")
+            .and_then(Value::as_object)
+            .map_or(0, |sitelinks| sitelinks.len() as u64);
+        let bytes = entity.to_string().len() as u64;
+        self.statements.add(statements, id);
+        self.sitelinks.add(sitelinks, id);
+        self.bytes.add(bytes, id);
+
+        if self.largest.len() < LARGEST || bytes > self.largest[0].0 {
+            let at = self.largest.partition_point(|(size, _)| *size < bytes);
+            self.largest.insert(at, (bytes, id.to_string()));
+            if self.largest.len() > LARGEST {
+                self.largest.remove(0);
+            }
+        }
+    }
+
+    fn metrics(&self) -> [(&'static str, &Histogram); 3] {
+        [
+            ("statements", &self.statements),
+            ("sitelinks", &self.sitelinks),
+            ("bytes", &self.bytes),
+        ]
+    }
+
+    fn write_tsv<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        writeln!(output, "metric\tfrom\tto\tentities")?;
+        for (metric, histogram) in self.metrics() {
+            for (from, to, entities) in histogram.rows() {
+                writeln!(output, "{}\t{}\t{}\t{}", metric, from, to, entities)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> Value {
+        let mut report = json!({"entities": self.entities});
+        for (metric, histogram) in self.metrics() {
+            report[metric] = histogram.to_json();
+        }
+        report["largest"] = self
+            .largest
+            .iter()
+            .rev()
+            .map(|(bytes, id)| json!({"id": id, "bytes": bytes}))
+            .collect();
+        report
+    }
+}
+
+/// One of the reports of the `stats` subcommand
+enum Report {
+    Properties(PropertyReport),
+    Labels(LabelReport),
+    Sizes(SizeReport),
+}
+
+impl Report {
+    fn parse(s: &str) -> Result<Self, FilterError> {
+        match s {
+            "properties" => Ok(Report::Properties(PropertyReport::default())),
+            "labels" => Ok(Report::Labels(LabelReport::default())),
+            "sizes" => Ok(Report::Sizes(SizeReport::default())),
+            _ => Err(FilterError::Parse(format!(
+                "--report must be properties, labels or sizes, not '{}'",
+                s
+            ))),
+        }
+    }
+
+    fn add(&mut self, entity: &Value) {
+        match self {
+            Report::Properties(report) => report.add(entity),
+            Report::Labels(report) => report.add(entity),
+            Report::Sizes(report) => report.add(entity),
+        }
+    }
+
+    fn write_tsv<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        match self {
+            Report::Properties(report) => report.write_tsv(output),
+            Report::Labels(report) => report.write_tsv(output),
+            Report::Sizes(report) => report.write_tsv(output),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Report::Properties(report) => report.to_json(),
+            Report::Labels(report) => report.to_json(),
+            Report::Sizes(report) => report.to_json(),
+        }
+    }
+}
+
+/// Run the `stats` subcommand
+pub fn run(args: StatsArgs) -> Result<(), FilterError> {
+    let mut report = Report::parse(&args.report)?;
+    let json_output = match args.output_format.as_str() {
+        "tsv" => false,
+        "json" => true,
+        other => {
+            return Err(FilterError::Parse(format!(
+                "stats writes tsv or json, not {}",
+                other
+            )))
+        }
+    };
+    let claim_filter = args.claim.as_deref().map(parse_claim_filter).transpose()?;
+    let split_ids =
+        |s: &String| -> HashSet<String> { s.split(',').map(|id| id.trim().to_string()).collect() };
+    let filter = Arc::new(EntityFilter {
+        claim_filter,
+        subject_filter: args.subject.as_ref().map(split_ids).map(IdSet::from),
+        language_filter: args.languages.as_ref().map(split_ids),
+        entity_type: args.r#type.clone(),
+        ..Default::default()
+    });
+    let options = ProcessingOptions {
+        show_progress: args.progress,
+        batch_size: 0,
+        output_format: OutputFormat::Json,
+        ..Default::default()
+    };
+
+    let (reader, format) = create_input_reader(&args.input, &args.format, INPUT_BUFFER_SIZE)?;
+    let mut sink = EntitySink::new(|entity| {
+        report.add(&entity);
+        Ok(())
+    });
+    run_filter(reader, &format, &mut sink, &mut [], &filter, &options)?;
+
+    let compression = determine_compression("none", args.output.as_deref());
+    let mut output = BufWriter::with_capacity(
+        OUTPUT_BUFFER_SIZE,
+        create_output_writer(args.output.as_deref(), &compression)?,
+    );
+    if json_output {
+        serde_json::to_writer_pretty(&mut output, &report.to_json())?;
+        output.write_all(b"\n")?;
+    } else {
+        report.write_tsv(&mut output)?;
+    }
+    finish_output(output)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_property_report() {
+        let mut report = PropertyReport::default();
+        for entity in [
+            json!({"id": "Q1", "claims": {"P31": [{}, {}], "P18": [{}]}}),
+            json!({"id": "Q2", "claims": {"P31": [{}], "P5": []}}),
+            json!({"id": "Q3", "claims": {"P18": [{}]}}),
+            json!({"id": "Q4"}),
+        ] {
+            report.add(&entity);
+        }
+        let mut tsv = Vec::new();
+        report.write_tsv(&mut tsv).unwrap();
+        assert_eq!(
+            String::from_utf8(tsv).unwrap(),
+            "property\tentities\tstatements\nP18\t2\t2\nP31\t2\t3\n"
+        );
+        assert_eq!(report.to_json()["entities"], 4);
+        assert_eq!(report.to_json()["properties"][1]["statements"], 3);
+    }
+
+    #[test]
+    fn test_label_report() {
+        let mut report = LabelReport::default();
+        for entity in [
+            json!({"labels": {"en": {}, "de": {}}, "descriptions": {"en": {}}, "aliases": {"de": [{}], "fr": []}}),
+            json!({"labels": {"en": {}}, "aliases": {"en": [{}, {}]}}),
+            json!({"id": "Q3"}),
+        ] {
+            report.add(&entity);
+        }
+        let mut tsv = Vec::new();
+        report.write_tsv(&mut tsv).unwrap();
+        assert_eq!(
+            String::from_utf8(tsv).unwrap(),
+            "language\tlabels\tdescriptions\taliases\nen\t2\t1\t1\nde\t1\t0\t1\n"
+        );
+        assert_eq!(report.to_json()["entities"], 3);
+    }
+
+    #[test]
+    fn test_size_report() {
+        let mut report = SizeReport::default();
+        for n in 0..20u64 {
+            let claims: Vec<Value> = (0..n).map(|_| json!({})).collect();
+            report.add(&json!({"id": format!("Q{}", n), "claims": {"P31": claims}}));
+        }
+        let rows: Vec<(u64, u64, u64)> = report.statements.rows().collect();
+        assert_eq!(
+            rows,
+            [
+                (0, 0, 1),
+                (1, 1, 1),
+                (2, 3, 2),
+                (4, 7, 4),
+                (8, 15, 8),
+                (16, 31, 4)
+            ]
+        );
+        assert_eq!(report.statements.total, 190);
+        assert_eq!(report.statements.max_entity, "Q19");
+        assert_eq!(report.sitelinks.rows().collect::<Vec<_>>(), [(0, 0, 20)]);
+        let json = report.to_json();
+        assert_eq!(json["largest"].as_array().unwrap().len(), LARGEST);
+        assert_eq!(json["largest"][0]["id"], "Q19");
+        assert_eq!(json["largest"][9]["id"], "Q10");
+    }
+}
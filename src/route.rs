@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+
+use crate::claim_parser::parse_claim_expression;
+use crate::compression::strip_compression_suffix;
+use crate::filter::{ClaimFilter, EntityFilter};
+use crate::rdf::OutputFormat;
+use crate::stats::ResultTypes;
+use crate::FilterError;
+
+/// An additional output with its own entity filter (--route, --output-rejected)
+pub struct Route<'a> {
+    pub filter: Arc<EntityFilter>,
+    pub output: &'a mut dyn Write,
+}
+
+/// Split routes into their filters, for the workers, and outputs, for the writer
+pub fn split<'r>(routes: &'r mut [Route<'_>]) -> (Vec<Arc<EntityFilter>>, Vec<&'r mut dyn Write>) {
+    routes
+        .iter_mut()
+        .map(|route| (route.filter.clone(), &mut *route.output as &mut dyn Write))
+        .unzip()
+}
+
+/// Results of one batch for the main output, each route and each partition
+#[derive(Debug, Default)]
+pub struct BatchOutputs<T> {
+    pub main: T,
+    pub routes: Vec<T>,
+    /// Results of the --partition-by values with entities in the batch
+    pub partitions: BTreeMap<String, T>,
+    /// Entity types of the main results, for the run statistics
+    pub main_types: ResultTypes,
+}
+
+impl<T: Default> BatchOutputs<T> {
+    /// Empty results for the main output and `routes` routes
+    pub fn new(routes: usize) -> Self {
+        Self {
+            main: T::default(),
+            routes: std::iter::repeat_with(T::default).take(routes).collect(),
+            partitions: BTreeMap::new(),
+            main_types: ResultTypes::default(),
+        }
+    }
+}
+
+impl<T> BatchOutputs<T> {
+    /// Convert the results of every output
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> BatchOutputs<U> {
+        match self.try_map(|result| Ok::<_, std::convert::Infallible>(f(result))) {
+            Ok(outputs) => outputs,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Convert the results of every output, stopping at the first error
+    pub fn try_map<U, E>(self, mut f: impl FnMut(T) -> Result<U, E>) -> Result<BatchOutputs<U>, E> {
+        Ok(BatchOutputs {
+            main: f(self.main)?,
+            routes: self
+                .routes
+                .into_iter()
+                .map(&mut f)
+                .collect::<Result<_, _>>()?,
+            partitions: self
+                .partitions
+                .into_iter()
+                .map(|(value, result)| Ok((value, f(result)?)))
+                .collect::<Result<_, _>>()?,
+            main_types: self.main_types,
+        })
+    }
+}
+
+/// A named claim filter and the file it writes to, from `NAME=EXPR=>FILE`
+#[derive(Debug, Clone)]
+pub struct RouteSpec {
+    pub name: String,
+    pub claim_filter: ClaimFilter,
+    pub path: String,
+}
+
+impl RouteSpec {
+    /// Parse a `--route` value such as `humans=P31:Q5=>humans.ndjson.gz`
+    ///
+    /// Transitive values and property paths are left for `ClaimFilter::resolve`.
+    pub fn parse(spec: &str) -> Result<Self, FilterError> {
+        let invalid = || {
+            FilterError::Parse(format!(
+                "Invalid route '{}', expected NAME=EXPR=>FILE",
+                spec
+            ))
+        };
+        let (name, rest) = spec.split_once('=').ok_or_else(invalid)?;
+        let (expression, path) = rest.rsplit_once("=>").ok_or_else(invalid)?;
+        let (name, expression, path) = (name.trim(), expression.trim(), path.trim());
+        if name.is_empty() || expression.is_empty() || path.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            claim_filter: parse_claim_expression(expression)?,
+            path: path.to_string(),
+        })
+    }
+
+    /// Check that the file extension, if it names a format, names `format`
+    ///
+    /// Routes are written in the format of the run, so `humans.nt` with
+    /// `-o json` is refused rather than filled with JSON.
+    pub fn check_format(&self, format: OutputFormat) -> Result<(), FilterError> {
+        let path = self.path.to_lowercase();
+        let extension = strip_compression_suffix(&path).rsplit_once('.');
+        let formats: &[OutputFormat] = match extension.map(|(_, extension)| extension) {
+            Some("nt") => &[OutputFormat::NTriples],
+            Some("nq") => &[OutputFormat::NQuads],
+            Some("trig") => &[OutputFormat::TriG],
+            Some("json" | "ndjson" | "jsonl") => &[OutputFormat::Json, OutputFormat::EsBulk],
+            Some("csv") => &[OutputFormat::Csv],
+            Some("tsv") => &[OutputFormat::Tsv],
+            Some("arrow") => &[OutputFormat::Arrow],
+            Some("graphml") => &[OutputFormat::GraphMl],
+            _ => return Ok(()),
+        };
+        if formats.contains(&format) {
+            return Ok(());
+        }
+        Err(FilterError::Parse(format!(
+            "Route '{}' writes {} output, which does not fit the name {}; \
+             choose the format with -o",
+            self.name,
+            format.name(),
+            self.path
+        )))
+    }
+
+    /// The filter of this route: the shared filters with the route's claim filter
+    pub fn filter(&self, base: &EntityFilter) -> EntityFilter {
+        EntityFilter {
+            claim_filter: Some(self.claim_filter.clone()),
+            ..base.clone()
+        }
+    }
+}
+
+/// Parse the routes of `--route` values and a `--route-file`
+///
+/// The file holds one route per line; blank lines and lines starting with `#`
+/// are skipped. Route names must be unique.
+pub fn parse_routes(
+    specs: &[String],
+    route_file: Option<&str>,
+) -> Result<Vec<RouteSpec>, FilterError> {
+    let mut routes = specs
+        .iter()
+        .map(|spec| RouteSpec::parse(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(path) = route_file {
+        for line in std::fs::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                routes.push(RouteSpec::parse(line)?);
+            }
+        }
+    }
+    for (i, route) in routes.iter().enumerate() {
+        if routes[..i].iter().any(|r| r.name == route.name) {
+            return Err(FilterError::Parse(format!(
+                "Route '{}' is defined twice",
+                route.name
+            )));
+        }
+    }
+    Ok(routes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_route() {
+        let route = RouteSpec::parse("humans=P31:Q5&~P570=>out/humans.ndjson.gz").unwrap();
+        assert_eq!(route.name, "humans");
+        assert_eq!(route.path, "out/humans.ndjson.gz");
+        assert_eq!(
+            route.claim_filter.tree(),
+            "AND\n  P31 = Q5\n  NOT\n    P570 (any value)\n"
+        );
+
+        assert!(RouteSpec::parse("humans=P31:Q5").is_err());
+        assert!(RouteSpec::parse("P31:Q5=>humans.nt").is_err());
+        assert!(RouteSpec::parse("=P31:Q5=>humans.nt").is_err());
+        assert!(RouteSpec::parse("humans=P31:=>humans.nt").is_err());
+    }
+
+    #[test]
+    fn test_check_format() {
+        let route = |path: &str| RouteSpec::parse(&format!("r=P18=>{}", path)).unwrap();
+        assert!(route("x.nt").check_format(OutputFormat::NTriples).is_ok());
+        assert!(route("x.nt.gz")
+            .check_format(OutputFormat::NTriples)
+            .is_ok());
+        assert!(route("x.nt").check_format(OutputFormat::Json).is_err());
+        assert!(route("x.NDJSON.lz4")
+            .check_format(OutputFormat::Json)
+            .is_ok());
+        assert!(route("x.ndjson").check_format(OutputFormat::EsBulk).is_ok());
+        assert!(route("x.json").check_format(OutputFormat::Csv).is_err());
+        assert!(route("x.tsv").check_format(OutputFormat::Tsv).is_ok());
+        // Names without a known extension take any format
+        assert!(route("out/humans")
+            .check_format(OutputFormat::Arrow)
+            .is_ok());
+        assert!(route("x.txt").check_format(OutputFormat::Json).is_ok());
+    }
+
+    #[test]
+    fn test_parse_routes_rejects_duplicate_names() {
+        let specs = ["a=P31:Q5=>a.nt".to_string(), "a=P18=>b.nt".to_string()];
+        assert!(parse_routes(&specs, None).is_err());
+        assert_eq!(parse_routes(&specs[..1], None).unwrap().len(), 1);
+    }
+}
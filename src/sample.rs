@@ -0,0 +1,436 @@
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::atomic::PendingFile;
+use crate::claim_parser::parse_claim_filter;
+use crate::compression::{
+    create_compressed_writer, create_input_reader, determine_compression, finish_output,
+    INPUT_BUFFER_SIZE, OUTPUT_BUFFER_SIZE,
+};
+use crate::diff::EntitySink;
+use crate::filter::EntityFilter;
+use crate::idset::IdSet;
+use crate::json::json_entity_to_ntriples;
+use crate::rdf::{OutputFormat, ProcessingOptions};
+use crate::{http, run_filter, FilterError};
+
+/// Arguments for the `sample` subcommand
+#[derive(clap::Args, Debug)]
+pub struct SampleArgs {
+    /// Dump or extract to sample (supports .bz2, .gz, .lz4)
+    #[arg()]
+    input: String,
+
+    /// Keep each matching entity with this probability (e.g. 0.01)
+    #[arg(
+        long,
+        conflicts_with_all = ["count", "every_nth"],
+        required_unless_present_any = ["count", "every_nth"]
+    )]
+    rate: Option<f64>,
+
+    /// Keep exactly this many of the matching entities (or all, if fewer).
+    /// They are held in memory until the end; with --stratify-by, up to this
+    /// many per stratum, so memory grows with the number of strata
+    #[arg(long, conflicts_with = "every_nth")]
+    count: Option<usize>,
+
+    /// Keep every n-th matching entity, starting at the seed modulo n
+    #[arg(long)]
+    every_nth: Option<u64>,
+
+    /// Sample the entities of each value of this property (e.g. P31) on their
+    /// own, so the sample keeps their proportions
+    #[arg(long)]
+    stratify_by: Option<String>,
+
+    /// Seed of the selection; the same seed selects the same entities
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Input format: auto, rdf, turtle, json
+    #[arg(short = 'f', long, default_value = "auto")]
+    format: String,
+
+    /// Output format: json, ntriples
+    #[arg(short = 'o', long, default_value = "json")]
+    output_format: String,
+
+    /// Output file (stdout if not provided). Extension determines compression (.gz, .lz4)
+    #[arg(long)]
+    output: Option<String>,
+
+    /// Filter by claim (e.g., P31:Q5, P31:Q5,Q6256, P31:Q5&P18)
+    #[arg(short, long)]
+    claim: Option<String>,
+
+    /// Entity type to filter: item, property, or both
+    #[arg(short = 't', long, default_value = "both")]
+    r#type: String,
+
+    /// Keep only specified subject entity IDs (comma-separated, e.g., Q31,Q42)
+    #[arg(long)]
+    subject: Option<String>,
+
+    /// Filter languages for labels/descriptions (comma-separated, e.g., en,de,fr)
+    #[arg(short = 'l', long)]
+    languages: Option<String>,
+
+    /// Show progress info on stderr
+    #[arg(short = 'p', long)]
+    progress: bool,
+}
+
+/// Pseudo-random number of an entity ID for a seed, the same in every run
+///
+/// FNV-1a of the ID, mixed with the seed by the SplitMix64 finalizer.
+fn priority(seed: u64, id: &str) -> u64 {
+    let hash = id.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    });
+    let mut z = hash ^ seed.wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// How entities are selected
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    /// Entities whose priority is below this share of the range
+    Rate(f64),
+    /// The entities with the lowest priorities
+    Count(usize),
+    /// Every n-th entity, starting at the seed modulo n
+    EveryNth(u64),
+}
+
+/// Stratum of an entity: the sorted entity values of `property`, or `none`
+fn stratum(entity: &Value, property: &str) -> String {
+    let statements = entity
+        .get("claims")
+        .and_then(|claims| claims.get(property))
+        .and_then(Value::as_array);
+    let values: BTreeSet<&str> = statements
+        .into_iter()
+        .flatten()
+        .filter_map(|statement| statement.pointer("/mainsnak/datavalue/value/id")?.as_str())
+        .collect();
+    if values.is_empty() {
+        return "none".to_string();
+    }
+    values.into_iter().collect::<Vec<_>>().join(",")
+}
+
+/// Entities seen and kept in one stratum
+#[derive(Default)]
+struct Stratum {
+    seen: u64,
+    /// Lowest priorities with their position and entity, for `Mode::Count`;
+    /// each stratum holds up to the whole count, as its share is only known
+    /// once the input is read
+    kept: BinaryHeap<(u64, u64, String)>,
+}
+
+/// Selects entities by the priority of their IDs, so a sample does not
+/// depend on the order of the input and grows with the rate
+///
+/// With `stratify_by`, each stratum is sampled on its own, so the sample has
+/// the proportions of the input.
+struct Sampler<W: Write> {
+    mode: Mode,
+    seed: u64,
+    stratify_by: Option<String>,
+    output: W,
+    ntriples: bool,
+    strata: HashMap<String, Stratum>,
+    seen: u64,
+    written: u64,
+}
+
+impl<W: Write> Sampler<W> {
+    fn new(mode: Mode, seed: u64, output: W, ntriples: bool) -> Self {
+        Self {
+            mode,
+            seed,
+            stratify_by: None,
+            output,
+            ntriples,
+            strata: HashMap::new(),
+            seen: 0,
+            written: 0,
+        }
+    }
+
+    fn write(&mut self, entity: &Value) -> io::Result<()> {
+        self.written += 1;
+        if self.ntriples {
+            for triple in json_entity_to_ntriples(entity) {
+                writeln!(self.output, "{}", triple)?;
+            }
+        } else {
+            serde_json::to_writer(&mut self.output, entity)?;
+            self.output.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Offer one matching entity
+    fn offer(&mut self, entity: Value) -> io::Result<()> {
+        let Some(id) = entity.get("id").and_then(Value::as_str) else {
+            return Ok(());
+        };
+        let key = match &self.stratify_by {
+            Some(property) => stratum(&entity, property),
+            None => String::new(),
+        };
+        let offset = priority(self.seed, &key);
+        let priority = priority(self.seed, id);
+        let position = self.seen;
+        self.seen += 1;
+        let stratum = self.strata.entry(key).or_default();
+        let n = stratum.seen;
+        stratum.seen += 1;
+        let keep = match self.mode {
+            // Stratified rates take an exact share of each stratum, spread evenly
+            Mode::Rate(rate) if self.stratify_by.is_some() => {
+                let start = offset as f64 / u64::MAX as f64;
+                ((n + 1) as f64 * rate + start).floor() > (n as f64 * rate + start).floor()
+            }
+            Mode::Rate(rate) => (priority as f64) < rate * u64::MAX as f64,
+            Mode::EveryNth(every) => n % every == self.seed % every,
+            Mode::Count(count) => {
+                let kept = &mut stratum.kept;
+                if kept.len() < count || kept.peek().is_some_and(|(p, ..)| priority < *p) {
+                    kept.push((priority, position, entity.to_string()));
+                    if kept.len() > count {
+                        kept.pop();
+                    }
+                }
+                false
+            }
+        };
+        if keep {
+            self.write(&entity)?;
+        }
+        Ok(())
+    }
+
+    /// Write the entities kept for `Mode::Count`, in input order
+    ///
+    /// Strata get shares of the count by their size (largest remainder).
+    fn finish(&mut self) -> Result<(), FilterError> {
+        let Mode::Count(count) = self.mode else {
+            self.output.flush()?;
+            return Ok(());
+        };
+        let total = self.seen.max(1) as u128;
+        let count = (count as u64).min(self.seen);
+        let mut strata: Vec<(String, Stratum)> =
+            std::mem::take(&mut self.strata).into_iter().collect();
+        strata.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let shares: Vec<(u64, u128)> = strata
+            .iter()
+            .map(|(_, stratum)| {
+                let exact = count as u128 * stratum.seen as u128;
+                ((exact / total) as u64, exact % total)
+            })
+            .collect();
+        let mut quotas: Vec<u64> = shares.iter().map(|(quota, _)| *quota).collect();
+        let mut remaining = count - quotas.iter().sum::<u64>();
+        let mut by_remainder: Vec<usize> = (0..shares.len()).collect();
+        by_remainder.sort_by_key(|&i| std::cmp::Reverse(shares[i].1));
+        for i in by_remainder {
+            if remaining == 0 {
+                break;
+            }
+            quotas[i] += 1;
+            remaining -= 1;
+        }
+
+        let mut kept = Vec::new();
+        for ((_, stratum), quota) in strata.into_iter().zip(quotas) {
+            kept.extend(
+                stratum
+                    .kept
+                    .into_sorted_vec()
+                    .into_iter()
+                    .take(quota as usize),
+            );
+        }
+        kept.sort_unstable_by_key(|(_, position, _)| *position);
+        for (_, _, entity) in kept {
+            self.write(&serde_json::from_str(&entity)?)?;
+        }
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+/// Run the `sample` subcommand
+pub fn run(args: SampleArgs) -> Result<(), FilterError> {
+    let mode = match (args.rate, args.count, args.every_nth) {
+        (Some(rate), ..) if !(0.0..=1.0).contains(&rate) => {
+            return Err(FilterError::Parse(format!(
+                "--rate must be between 0 and 1, not {}",
+                rate
+            )))
+        }
+        (Some(rate), ..) => Mode::Rate(rate),
+        (_, Some(count), _) => Mode::Count(count),
+        (.., Some(0)) => {
+            return Err(FilterError::Parse(
+                "--every-nth must be at least 1".to_string(),
+            ))
+        }
+        (.., every) => Mode::EveryNth(every.unwrap_or(1)),
+    };
+    let ntriples = match args.output_format.as_str() {
+        "json" => false,
+        "ntriples" => true,
+        other => {
+            return Err(FilterError::Parse(format!(
+                "sample writes json or ntriples, not {}",
+                other
+            )))
+        }
+    };
+    let claim_filter = args.claim.as_deref().map(parse_claim_filter).transpose()?;
+    let split_ids =
+        |s: &String| -> HashSet<String> { s.split(',').map(|id| id.trim().to_string()).collect() };
+    let filter = Arc::new(EntityFilter {
+        claim_filter,
+        subject_filter: args.subject.as_ref().map(split_ids).map(IdSet::from),
+        language_filter: args.languages.as_ref().map(split_ids),
+        entity_type: args.r#type.clone(),
+        ..Default::default()
+    });
+    let options = ProcessingOptions {
+        show_progress: args.progress,
+        batch_size: 0,
+        output_format: OutputFormat::Json,
+        ..Default::default()
+    };
+
+    let mut pending = args
+        .output
+        .as_deref()
+        .map(|path| PendingFile::new(path, false, http::DEFAULT_RETRIES))
+        .transpose()?;
+    let file: Box<dyn Write> = match &mut pending {
+        Some(pending) => pending.create()?,
+        None => Box::new(io::stdout()),
+    };
+    let compression = determine_compression("none", args.output.as_deref());
+    let output = BufWriter::with_capacity(
+        OUTPUT_BUFFER_SIZE,
+        create_compressed_writer(file, &compression),
+    );
+    let mut sampler = Sampler::new(mode, args.seed, output, ntriples);
+    sampler.stratify_by = args.stratify_by;
+    let (reader, format) = create_input_reader(&args.input, &args.format, INPUT_BUFFER_SIZE)?;
+    let mut sink = EntitySink::new(|entity| sampler.offer(entity));
+    run_filter(reader, &format, &mut sink, &mut [], &filter, &options)?;
+    sampler.finish()?;
+    let (seen, written) = (sampler.seen, sampler.written);
+    finish_output(sampler.output)?;
+    if let Some(pending) = pending {
+        pending.commit()?;
+    }
+    eprintln!("Sampled {} of {} matching entities", written, seen);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(mode: Mode, seed: u64, ids: &[String]) -> Vec<String> {
+        let mut sampler = Sampler::new(mode, seed, Vec::new(), false);
+        for id in ids {
+            sampler.offer(serde_json::json!({ "id": id })).unwrap();
+        }
+        sampler.finish().unwrap();
+        String::from_utf8(sampler.output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap()["id"].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_rate_is_reproducible() {
+        let ids: Vec<String> = (0..10_000).map(|n| format!("Q{}", n)).collect();
+        let sampled = sample(Mode::Rate(0.1), 42, &ids);
+        assert!((900..1100).contains(&sampled.len()), "{}", sampled.len());
+        assert_eq!(sample(Mode::Rate(0.1), 42, &ids), sampled);
+        assert_ne!(sample(Mode::Rate(0.1), 43, &ids), sampled);
+        // A larger rate keeps a superset
+        let larger = sample(Mode::Rate(0.2), 42, &ids);
+        assert!(sampled.iter().all(|id| larger.contains(id)));
+    }
+
+    #[test]
+    fn test_count_keeps_input_order() {
+        let ids: Vec<String> = (0..1000).map(|n| format!("Q{}", n)).collect();
+        let sampled = sample(Mode::Count(10), 7, &ids);
+        assert_eq!(sampled.len(), 10);
+        let positions: Vec<usize> = sampled
+            .iter()
+            .map(|id| {
+                ids.iter()
+                    .position(|i| format!("\"{}\"", i) == *id)
+                    .unwrap()
+            })
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+        // The same entities whatever the order of the input
+        let mut reversed = ids.clone();
+        reversed.reverse();
+        let mut again = sample(Mode::Count(10), 7, &reversed);
+        again.reverse();
+        assert_eq!(again, sampled);
+        assert_eq!(sample(Mode::Count(10), 7, &ids[..5]).len(), 5);
+    }
+
+    #[test]
+    fn test_every_nth() {
+        let ids: Vec<String> = (0..10).map(|n| format!("Q{}", n)).collect();
+        assert_eq!(
+            sample(Mode::EveryNth(4), 1, &ids),
+            ["\"Q1\"", "\"Q5\"", "\"Q9\""]
+        );
+    }
+
+    #[test]
+    fn test_stratified() {
+        // 90 humans and 10 cities
+        let entities: Vec<Value> = (0..100)
+            .map(|n| {
+                let class = if n % 10 == 0 { "Q515" } else { "Q5" };
+                serde_json::json!({
+                    "id": format!("Q{}", 1000 + n),
+                    "claims": {"P31": [{"mainsnak": {"datavalue": {"value": {"id": class}}}}]}
+                })
+            })
+            .collect();
+        let classes = |mode| {
+            let mut sampler = Sampler::new(mode, 3, Vec::new(), false);
+            sampler.stratify_by = Some("P31".to_string());
+            for entity in &entities {
+                sampler.offer(entity.clone()).unwrap();
+            }
+            sampler.finish().unwrap();
+            let output = String::from_utf8(sampler.output).unwrap();
+            let cities = output.matches("Q515").count();
+            (output.lines().count() - cities, cities)
+        };
+        assert_eq!(classes(Mode::Count(10)), (9, 1));
+        assert_eq!(classes(Mode::Count(12)), (11, 1));
+        assert_eq!(classes(Mode::Rate(0.2)), (18, 2));
+        assert_eq!(classes(Mode::EveryNth(5)), (18, 2));
+    }
+}
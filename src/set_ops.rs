@@ -0,0 +1,262 @@
+use std::borrow::Cow;
+use std::io::{BufRead, BufWriter, Write};
+
+use serde::Deserialize;
+
+use crate::atomic::PendingFile;
+use crate::compression::{
+    create_compressed_writer, create_input_reader, determine_compression, finish_output,
+    INPUT_BUFFER_SIZE, OUTPUT_BUFFER_SIZE,
+};
+use crate::json::dump_array_elements;
+use crate::rdf::line_entity_id;
+use crate::validate::SeenIds;
+use crate::{http, FilterError};
+
+/// Arguments for the `set` subcommand
+#[derive(clap::Args, Debug)]
+pub struct SetArgs {
+    /// union, intersection or difference
+    #[arg()]
+    operation: String,
+
+    /// First extract; its entities are written for all operations
+    #[arg()]
+    first: String,
+
+    /// Second extract, in the same format
+    #[arg()]
+    second: String,
+
+    /// Input format of both: auto, rdf, json
+    #[arg(short = 'f', long, default_value = "auto")]
+    format: String,
+
+    /// Output file (stdout if not provided). Extension determines compression (.gz, .lz4)
+    #[arg(long)]
+    output: Option<String>,
+}
+
+/// Set operation on the entity IDs of two extracts
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operation {
+    /// Entities of the first, then those of the second that are not in the first
+    Union,
+    /// Entities of the first that are also in the second
+    Intersection,
+    /// Entities of the first that are not in the second
+    Difference,
+}
+
+impl Operation {
+    fn parse(s: &str) -> Result<Self, FilterError> {
+        match s {
+            "union" => Ok(Operation::Union),
+            "intersection" => Ok(Operation::Intersection),
+            "difference" => Ok(Operation::Difference),
+            _ => Err(FilterError::Parse(format!(
+                "set operation must be union, intersection or difference, not '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct EntityId<'a> {
+    #[serde(borrow)]
+    id: Cow<'a, str>,
+}
+
+/// Calls `handle` with the entity ID (if any) and data of every line or JSON
+/// entity of an extract
+fn for_each_record(
+    reader: impl BufRead,
+    json: bool,
+    mut handle: impl FnMut(Option<&str>, &str) -> Result<(), FilterError>,
+) -> Result<(), FilterError> {
+    for line in reader.lines() {
+        let line = line?;
+        if json {
+            for entry in dump_array_elements(&line) {
+                let EntityId { id } = serde_json::from_str(entry)?;
+                handle(Some(&id), entry)?;
+            }
+        } else if !line.trim().is_empty() {
+            handle(line_entity_id(&line), &line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether an input format is JSON, or N-Triples
+fn is_json(format: &str) -> Result<bool, FilterError> {
+    match format {
+        "json" | "ndjson" => Ok(true),
+        "rdf" | "ntriples" | "nt" => Ok(false),
+        _ => Err(FilterError::Parse(format!(
+            "set supports N-Triples and JSON extracts, not {}",
+            format
+        ))),
+    }
+}
+
+fn write_record<W: Write>(output: &mut W, data: &str) -> Result<(), FilterError> {
+    output.write_all(data.as_bytes())?;
+    output.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Write the result of `operation` on `first` and `second`
+///
+/// The IDs of one input and those written from the other are held, as bitsets
+/// with a bit per number up to the largest ID of each letter. Memory follows
+/// that number rather than the count of IDs: about 16 MiB per set for Q-IDs up
+/// to Q130000000, and up to 512 MiB for numbers near 2^32.
+fn apply<W: Write>(
+    operation: Operation,
+    first: impl BufRead,
+    second: impl BufRead,
+    json: bool,
+    output: &mut W,
+) -> Result<u64, FilterError> {
+    let mut ids = SeenIds::default();
+    let mut written = 0u64;
+    match operation {
+        Operation::Union => {
+            for_each_record(first, json, |id, data| {
+                if let Some(id) = id {
+                    if ids.insert(id) {
+                        written += 1;
+                    }
+                }
+                write_record(output, data)
+            })?;
+            let mut added = SeenIds::default();
+            for_each_record(second, json, |id, data| match id {
+                Some(id) if !ids.contains(id) => {
+                    if added.insert(id) {
+                        written += 1;
+                    }
+                    write_record(output, data)
+                }
+                // Lines outside entities (headers) come from the first input
+                _ => Ok(()),
+            })?;
+        }
+        Operation::Intersection | Operation::Difference => {
+            for_each_record(second, json, |id, _| {
+                if let Some(id) = id {
+                    ids.insert(id);
+                }
+                Ok(())
+            })?;
+            let keep_common = operation == Operation::Intersection;
+            let mut kept = SeenIds::default();
+            for_each_record(first, json, |id, data| match id {
+                Some(id) if ids.contains(id) != keep_common => Ok(()),
+                Some(id) => {
+                    if kept.insert(id) {
+                        written += 1;
+                    }
+                    write_record(output, data)
+                }
+                None => write_record(output, data),
+            })?;
+        }
+    }
+    Ok(written)
+}
+
+/// Run the `set` subcommand
+pub fn run(args: SetArgs) -> Result<(), FilterError> {
+    let operation = Operation::parse(&args.operation)?;
+    let (first, first_format) = create_input_reader(&args.first, &args.format, INPUT_BUFFER_SIZE)?;
+    let json = is_json(&first_format)?;
+    let (second, second_format) =
+        create_input_reader(&args.second, &args.format, INPUT_BUFFER_SIZE)?;
+    if is_json(&second_format)? != json {
+        return Err(FilterError::Parse(format!(
+            "{} and {} are in different formats",
+            args.first, args.second
+        )));
+    }
+
+    let mut pending = args
+        .output
+        .as_deref()
+        .map(|path| PendingFile::new(path, false, http::DEFAULT_RETRIES))
+        .transpose()?;
+    let file: Box<dyn Write> = match &mut pending {
+        Some(pending) => pending.create()?,
+        None => Box::new(std::io::stdout()),
+    };
+    let compression = determine_compression("none", args.output.as_deref());
+    let mut output = BufWriter::with_capacity(
+        OUTPUT_BUFFER_SIZE,
+        create_compressed_writer(file, &compression),
+    );
+    let written = apply(operation, first, second, json, &mut output)?;
+    finish_output(output)?;
+    if let Some(pending) = pending {
+        pending.commit()?;
+    }
+    eprintln!("Wrote {} entities ({})", written, args.operation);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_operations() {
+        let first = "{\"id\":\"Q1\"}\n{\"id\":\"Q2\"}\n{\"id\":\"Q3\"}\n";
+        let second = "[\n{\"id\":\"Q3\",\"x\":1},\n{\"id\":\"Q4\"},\n{\"id\":\"Q1\",\"x\":1}\n]\n";
+        let run = |operation| {
+            let mut output = Vec::new();
+            let written = apply(
+                operation,
+                io::Cursor::new(first),
+                io::Cursor::new(second),
+                true,
+                &mut output,
+            )
+            .unwrap();
+            (written, String::from_utf8(output).unwrap())
+        };
+        assert_eq!(
+            run(Operation::Union),
+            (
+                4,
+                "{\"id\":\"Q1\"}\n{\"id\":\"Q2\"}\n{\"id\":\"Q3\"}\n{\"id\":\"Q4\"}\n".to_string()
+            )
+        );
+        assert_eq!(
+            run(Operation::Intersection),
+            (2, "{\"id\":\"Q1\"}\n{\"id\":\"Q3\"}\n".to_string())
+        );
+        assert_eq!(
+            run(Operation::Difference),
+            (1, "{\"id\":\"Q2\"}\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_ntriples_operations() {
+        let first = "<http://www.wikidata.org/entity/Q1> <http://www.w3.org/2000/01/rdf-schema#label> \"a\"@en .\n<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .\n<http://www.wikidata.org/entity/Q2> <http://www.w3.org/2000/01/rdf-schema#label> \"b\"@en .\n";
+        let second = "<http://www.wikidata.org/entity/Q2> <http://www.w3.org/2000/01/rdf-schema#label> \"b\"@en .\n";
+        let mut output = Vec::new();
+        let written = apply(
+            Operation::Difference,
+            io::Cursor::new(first),
+            io::Cursor::new(second),
+            false,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(written, 1);
+        assert_eq!(String::from_utf8(output).unwrap().lines().count(), 2);
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use signal_hook::consts::SIGINT;
+
+/// Exit code after an interrupted run (128 + SIGINT)
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Install the Ctrl-C handler
+///
+/// The first SIGINT sets the returned flag, so reading stops and the batches
+/// already read are written and the output finalized. A second one exits
+/// immediately.
+pub fn install_interrupt_handler() -> std::io::Result<Arc<AtomicBool>> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register_conditional_shutdown(
+        SIGINT,
+        INTERRUPTED_EXIT_CODE,
+        interrupted.clone(),
+    )?;
+    signal_hook::flag::register(SIGINT, interrupted.clone())?;
+    Ok(interrupted)
+}
+
+/// Install the SIGUSR1 (and SIGINFO, where available) handler
+///
+/// The signal sets the returned flag; the reader prints a progress snapshot
+/// when it sees it and clears it again.
+pub fn install_snapshot_handler() -> std::io::Result<Arc<AtomicBool>> {
+    let requested = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGUSR1, requested.clone())?;
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "dragonfly"
+    ))]
+    signal_hook::flag::register(signal_hook::consts::SIGINFO, requested.clone())?;
+    Ok(requested)
+}
+
+/// Print a progress snapshot requested by SIGUSR1
+///
+/// `lines` is the number of input lines read, if known, `bytes` the input
+/// position and `matched` the entities written so far.
+pub fn report_snapshot(lines: Option<u64>, bytes: u64, matched: u64, elapsed: Duration) {
+    eprintln!("{}", snapshot_message(lines, bytes, matched, elapsed));
+}
+
+fn snapshot_message(lines: Option<u64>, bytes: u64, matched: u64, elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs_f64().max(1e-3);
+    let mib = bytes as f64 / (1 << 20) as f64;
+    let read = match lines {
+        Some(lines) => format!(
+            "{} lines ({:.1} MiB, {:.0} lines/s, {:.1} MiB/s)",
+            lines,
+            mib,
+            lines as f64 / seconds,
+            mib / seconds
+        ),
+        None => format!("{:.1} MiB ({:.1} MiB/s)", mib, mib / seconds),
+    };
+    format!(
+        "Progress after {:.1}s: read {}, matched {} entities",
+        elapsed.as_secs_f64(),
+        read,
+        matched
+    )
+}
+
+/// Tell the user where an interrupted run stopped
+///
+/// `lines` is the number of input lines whose entities were completely
+/// handed to the writer, `offset` the byte offset after them.
+pub fn report_interrupt(lines: Option<u64>, offset: u64) {
+    match lines {
+        Some(lines) => eprintln!(
+            "Interrupted: output is complete up to line {} (byte offset {}); resume with --skip-lines {}",
+            lines, offset, lines
+        ),
+        None => eprintln!(
+            "Interrupted: output is complete up to byte offset {}",
+            offset
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_message() {
+        assert_eq!(
+            snapshot_message(Some(2000), 4 << 20, 7, Duration::from_secs(2)),
+            "Progress after 2.0s: read 2000 lines (4.0 MiB, 1000 lines/s, 2.0 MiB/s), matched 7 entities"
+        );
+        assert_eq!(
+            snapshot_message(None, 1 << 20, 0, Duration::from_millis(500)),
+            "Progress after 0.5s: read 1.0 MiB (2.0 MiB/s), matched 0 entities"
+        );
+    }
+}
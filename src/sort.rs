@@ -0,0 +1,223 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+
+/// Position of an entity in --sort-by-id output: entity type letter, then
+/// numeric ID (L1 < P31 < Q5 < Q42)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EntityKey {
+    letter: u8,
+    number: u64,
+    /// Anything after the number, or the whole ID if it has no number
+    rest: String,
+}
+
+impl EntityKey {
+    /// Key of an entity ID such as Q42; IDs without a number sort last
+    pub fn new(id: &str) -> Self {
+        let bytes = id.as_bytes();
+        let digits = bytes
+            .iter()
+            .skip(1)
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        let number = match bytes.first() {
+            Some(letter) if letter.is_ascii_uppercase() => id[1..1 + digits].parse().ok(),
+            _ => None,
+        };
+        match number {
+            Some(number) => Self {
+                letter: bytes[0],
+                number,
+                rest: id[1 + digits..].to_string(),
+            },
+            None => Self {
+                letter: u8::MAX,
+                number: 0,
+                rest: id.to_string(),
+            },
+        }
+    }
+}
+
+/// Memory and temporary directory of the external sort (--sort-by-id)
+#[derive(Debug, Clone)]
+pub struct SortConfig {
+    /// Bytes of entity output held in memory before a sorted run is spilled
+    pub memory: u64,
+    pub dir: PathBuf,
+}
+
+/// Sorts serialized entities by ID, spilling sorted runs to temporary files
+/// and merging them when finished
+///
+/// Entities with the same ID keep their input order.
+pub struct ExternalSorter {
+    config: SortConfig,
+    records: Vec<(EntityKey, Vec<u8>)>,
+    buffered: u64,
+    runs: Vec<PathBuf>,
+}
+
+impl ExternalSorter {
+    pub fn new(config: SortConfig) -> Self {
+        Self {
+            config,
+            records: Vec::new(),
+            buffered: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Add the output of one entity
+    pub fn push(&mut self, key: EntityKey, data: Vec<u8>) -> io::Result<()> {
+        self.buffered += (data.len() + key.rest.len() + std::mem::size_of::<EntityKey>()) as u64;
+        self.records.push((key, data));
+        if self.buffered >= self.config.memory {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Write the buffered entities to a sorted run
+    fn spill(&mut self) -> io::Result<()> {
+        let path = self.config.dir.join(format!(
+            "wikidata-werkzeug-sort-{}-{}.run",
+            std::process::id(),
+            self.runs.len()
+        ));
+        // Removed in drop even if writing the run fails
+        self.runs.push(path.clone());
+        let mut run = BufWriter::new(File::create(&path)?);
+        self.records.sort_by(|a, b| a.0.cmp(&b.0));
+        for (key, data) in self.records.drain(..) {
+            write_record(&mut run, &key, &data)?;
+        }
+        run.flush()?;
+        self.buffered = 0;
+        Ok(())
+    }
+
+    /// Write all entities to `output` in ID order
+    pub fn finish<W: Write>(mut self, output: &mut W) -> io::Result<()> {
+        if self.runs.is_empty() {
+            self.records.sort_by(|a, b| a.0.cmp(&b.0));
+            for (_, data) in self.records.drain(..) {
+                output.write_all(&data)?;
+            }
+            return Ok(());
+        }
+        if !self.records.is_empty() {
+            self.spill()?;
+        }
+        let mut runs = self
+            .runs
+            .iter()
+            .map(|path| File::open(path).map(BufReader::new))
+            .collect::<io::Result<Vec<_>>>()?;
+        // The run index breaks ties, so equal IDs stay in input order
+        let mut heap = BinaryHeap::new();
+        let mut data = Vec::with_capacity(runs.len());
+        for (i, run) in runs.iter_mut().enumerate() {
+            let record = read_record(run)?;
+            if let Some((key, _)) = &record {
+                heap.push(Reverse((key.clone(), i)));
+            }
+            data.push(record.map(|(_, data)| data));
+        }
+        while let Some(Reverse((_, i))) = heap.pop() {
+            output.write_all(data[i].as_deref().unwrap_or_default())?;
+            let record = read_record(&mut runs[i])?;
+            if let Some((key, _)) = &record {
+                heap.push(Reverse((key.clone(), i)));
+            }
+            data[i] = record.map(|(_, data)| data);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ExternalSorter {
+    fn drop(&mut self) {
+        for run in &self.runs {
+            let _ = std::fs::remove_file(run);
+        }
+    }
+}
+
+fn write_record<W: Write>(run: &mut W, key: &EntityKey, data: &[u8]) -> io::Result<()> {
+    run.write_all(&[key.letter])?;
+    run.write_all(&key.number.to_le_bytes())?;
+    run.write_all(&(key.rest.len() as u32).to_le_bytes())?;
+    run.write_all(key.rest.as_bytes())?;
+    run.write_all(&(data.len() as u64).to_le_bytes())?;
+    run.write_all(data)
+}
+
+fn read_record<R: Read>(run: &mut R) -> io::Result<Option<(EntityKey, Vec<u8>)>> {
+    let mut letter = [0u8; 1];
+    if run.read(&mut letter)? == 0 {
+        return Ok(None);
+    }
+    let mut number = [0u8; 8];
+    run.read_exact(&mut number)?;
+    let mut length = [0u8; 4];
+    run.read_exact(&mut length)?;
+    let mut rest = vec![0u8; u32::from_le_bytes(length) as usize];
+    run.read_exact(&mut rest)?;
+    let mut length = [0u8; 8];
+    run.read_exact(&mut length)?;
+    let mut data = vec![0u8; u64::from_le_bytes(length) as usize];
+    run.read_exact(&mut data)?;
+    let key = EntityKey {
+        letter: letter[0],
+        number: u64::from_le_bytes(number),
+        rest: String::from_utf8(rest).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+    };
+    Ok(Some((key, data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_entity_key_order() {
+        let mut ids = vec!["Q42", "Q5", "P31", "L1-F2", "Q100", "L1", "sitelinks", "Q5"];
+        ids.sort_by_key(|id| EntityKey::new(id));
+        assert_eq!(
+            ids,
+            ["L1", "L1-F2", "P31", "Q5", "Q5", "Q42", "Q100", "sitelinks"]
+        );
+        assert_eq!(EntityKey::new("").letter, u8::MAX);
+    }
+
+    #[test]
+    fn test_external_sort_merges_runs() {
+        let dir = TempDir::new("sort");
+        // A tiny memory limit spills a run for every few entities
+        let mut sorter = ExternalSorter::new(SortConfig {
+            memory: 100,
+            dir: dir.path().to_path_buf(),
+        });
+        for (i, n) in [7, 3, 9, 1, 3, 8, 2, 6, 5, 4].into_iter().enumerate() {
+            let line = format!("Q{} {}\n", n, i);
+            sorter
+                .push(EntityKey::new(&format!("Q{}", n)), line.into_bytes())
+                .unwrap();
+        }
+        assert!(sorter.runs.len() > 1);
+        let mut output = Vec::new();
+        sorter.finish(&mut output).unwrap();
+        let lines: Vec<&str> = std::str::from_utf8(&output).unwrap().lines().collect();
+        assert_eq!(
+            lines,
+            ["Q1 3", "Q2 6", "Q3 1", "Q3 4", "Q4 9", "Q5 8", "Q6 7", "Q7 0", "Q8 5", "Q9 2"]
+        );
+        // The runs are removed
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,370 @@
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::compression::{
+    create_input_reader_from, input_compression, INPUT_BUFFER_SIZE, OUTPUT_BUFFER_SIZE,
+};
+use crate::index::subject_id;
+use crate::json::dump_array_elements;
+use crate::partition::{Partition, PartitionWriter, SplitLimits};
+use crate::rdf::ProcessingOptions;
+use crate::stats::Counting;
+use crate::FilterError;
+
+/// Arguments for the `split` subcommand
+#[derive(clap::Args, Debug)]
+pub struct SplitArgs {
+    /// Dump to split (supports .bz2, .gz, .lz4)
+    #[arg()]
+    input: String,
+
+    /// Output files: `{part}` is replaced by the part number, which otherwise
+    /// goes before the extensions. Extension determines compression (.gz, .lz4)
+    #[arg(long)]
+    output: String,
+
+    /// Number of parts of about the same share of the input
+    #[arg(
+        long,
+        conflicts_with = "entities_per_part",
+        required_unless_present = "entities_per_part"
+    )]
+    parts: Option<u64>,
+
+    /// Entities in each part but the last
+    #[arg(long)]
+    entities_per_part: Option<u64>,
+
+    /// Input format: auto, rdf, json
+    #[arg(short = 'f', long, default_value = "auto")]
+    format: String,
+}
+
+/// When a part is complete
+#[derive(Debug, Clone, Copy)]
+enum Limit {
+    /// After this many entities
+    Entities(u64),
+    /// When the position in the input reaches the part's share of its size
+    Parts { count: u64, input_size: u64 },
+}
+
+/// Copies the lines of a dump to numbered parts, starting a part only where
+/// an entity starts
+struct Splitter<O, C, W>
+where
+    O: FnMut(usize) -> io::Result<W>,
+    C: FnMut(W) -> io::Result<()>,
+    W: Write,
+{
+    open: O,
+    close: C,
+    limit: Limit,
+    json: bool,
+    /// Lines before the first entity, written at the start of every part
+    header: Vec<u8>,
+    /// JSON dumps that are one array get an array in every part
+    json_array: bool,
+    current: Option<W>,
+    parts: usize,
+    /// Entities in the current part
+    entities: u64,
+    total: u64,
+    /// Subject of the N-Triples entity being copied
+    subject: Option<String>,
+}
+
+impl<O, C, W> Splitter<O, C, W>
+where
+    O: FnMut(usize) -> io::Result<W>,
+    C: FnMut(W) -> io::Result<()>,
+    W: Write,
+{
+    fn new(open: O, close: C, limit: Limit, json: bool) -> Self {
+        Self {
+            open,
+            close,
+            limit,
+            json,
+            header: Vec::new(),
+            json_array: false,
+            current: None,
+            parts: 0,
+            entities: 0,
+            total: 0,
+            subject: None,
+        }
+    }
+
+    /// Whether the part being written is complete, `read` bytes into the input
+    fn is_full(&self, read: u64) -> bool {
+        match self.limit {
+            Limit::Entities(max) => self.entities >= max,
+            Limit::Parts { count, input_size } => {
+                (self.parts as u64) < count
+                    && read as u128 * count as u128 >= self.parts as u128 * input_size as u128
+            }
+        }
+    }
+
+    /// Start an entity, in a new part if the current one is complete;
+    /// returns the writer of its part
+    fn start_entity(&mut self, read: u64) -> io::Result<&mut W> {
+        if self.current.is_some() && self.is_full(read) {
+            self.finish_part()?;
+        }
+        let continued = self.current.is_some();
+        if !continued {
+            let mut writer = (self.open)(self.parts)?;
+            writer.write_all(&self.header)?;
+            self.current = Some(writer);
+            self.parts += 1;
+            self.entities = 0;
+        }
+        let writer = self.current.as_mut().expect("part opened above");
+        if continued && self.json_array {
+            writer.write_all(b",\n")?;
+        }
+        self.entities += 1;
+        self.total += 1;
+        Ok(writer)
+    }
+
+    /// Copy one line of input; `read` is the position in the input
+    fn line(&mut self, line: &str, read: u64) -> Result<(), FilterError> {
+        if self.json {
+            let elements = dump_array_elements(line);
+            if elements.is_empty() {
+                if self.total == 0 && line.trim() == "[" {
+                    self.json_array = true;
+                    self.header.extend_from_slice(b"[\n");
+                }
+                return Ok(());
+            }
+            let json_array = self.json_array;
+            for element in elements {
+                let output = self.start_entity(read)?;
+                output.write_all(element.as_bytes())?;
+                if !json_array {
+                    output.write_all(b"\n")?;
+                }
+            }
+            return Ok(());
+        }
+
+        let output = match subject_id(line) {
+            Some(id) if self.subject.as_deref() != Some(id) => {
+                self.subject = Some(id.to_string());
+                self.start_entity(read)?
+            }
+            _ => match &mut self.current {
+                Some(output) => output,
+                // Lines before the first entity are the header
+                None => {
+                    self.header.extend_from_slice(line.as_bytes());
+                    self.header.push(b'\n');
+                    return Ok(());
+                }
+            },
+        };
+        output.write_all(line.as_bytes())?;
+        output.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// End the current part
+    fn finish_part(&mut self) -> io::Result<()> {
+        let Some(mut writer) = self.current.take() else {
+            return Ok(());
+        };
+        if self.json_array {
+            writer.write_all(b"\n]\n")?;
+        }
+        (self.close)(writer)
+    }
+}
+
+/// Run the `split` subcommand
+pub fn run(args: SplitArgs) -> Result<(), FilterError> {
+    let file = std::fs::File::open(&args.input)?;
+    let input_size = file.metadata()?.len();
+    let read = Arc::new(AtomicU64::new(0));
+    let (mut reader, format) = create_input_reader_from(
+        Counting::new(file, read.clone()),
+        &args.input,
+        &args.format,
+        INPUT_BUFFER_SIZE,
+    );
+    let json = match format.as_str() {
+        "json" | "ndjson" => true,
+        "rdf" | "ntriples" | "nt" => false,
+        other => {
+            return Err(FilterError::Parse(format!(
+                "split supports N-Triples and JSON dumps, not {}",
+                other
+            )))
+        }
+    };
+    let limit = match (args.parts, args.entities_per_part) {
+        (Some(0), _) | (_, Some(0)) => {
+            return Err(FilterError::Parse(
+                "--parts and --entities-per-part must be at least 1".to_string(),
+            ))
+        }
+        (Some(count), _) => Limit::Parts { count, input_size },
+        (None, entities) => Limit::Entities(entities.unwrap_or(u64::MAX)),
+    };
+
+    let partition = Partition::split(
+        SplitLimits {
+            max_bytes: u64::MAX,
+            max_entities: u64::MAX,
+        },
+        &args.output,
+        OUTPUT_BUFFER_SIZE,
+    );
+    let options = ProcessingOptions::default();
+    let mut splitter = Splitter::new(
+        |part| partition.open(&partition.shard_name(part), &options),
+        |writer: PartitionWriter| writer.close(false),
+        limit,
+        json,
+    );
+    // Compressed input is measured in stored bytes, which the decoder reads ahead
+    let compressed = input_compression(&args.input) != "none";
+    let mut line = String::new();
+    let mut offset = 0;
+    loop {
+        line.clear();
+        let length = reader.read_line(&mut line)?;
+        if length == 0 {
+            break;
+        }
+        let content = line.strip_suffix('\n').unwrap_or(&line);
+        let position = if compressed {
+            read.load(Ordering::Relaxed)
+        } else {
+            offset
+        };
+        splitter.line(content, position)?;
+        offset += length as u64;
+    }
+    splitter.finish_part()?;
+    eprintln!(
+        "Wrote {} entities to {} parts",
+        splitter.total, splitter.parts
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Split `data`, returning the parts
+    fn split(data: &str, limit: Limit, json: bool) -> Vec<String> {
+        let parts = std::cell::RefCell::new(Vec::<Vec<u8>>::new());
+        let mut splitter = Splitter::new(
+            |_| {
+                parts.borrow_mut().push(Vec::new());
+                Ok(Part(&parts))
+            },
+            |_| Ok(()),
+            limit,
+            json,
+        );
+        let mut offset = 0;
+        for line in data.lines() {
+            splitter.line(line, offset).unwrap();
+            offset += line.len() as u64 + 1;
+        }
+        splitter.finish_part().unwrap();
+        drop(splitter);
+        parts
+            .into_inner()
+            .into_iter()
+            .map(|part| String::from_utf8(part).unwrap())
+            .collect()
+    }
+
+    /// Writer appending to the last part
+    struct Part<'a>(&'a std::cell::RefCell<Vec<Vec<u8>>>);
+
+    impl Write for Part<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0
+                .borrow_mut()
+                .last_mut()
+                .unwrap()
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_split_ntriples() {
+        let header =
+            "<http://wikiba.se/ontology#Dump> <http://schema.org/dateModified> \"2026-10-01\" .\n";
+        let entity = |id: &str, lines: usize| -> String {
+            (0..lines)
+                .map(|i| {
+                    format!(
+                        "<http://www.wikidata.org/entity/{}> <http://schema.org/name> \"{}\" .\n",
+                        id, i
+                    )
+                })
+                .collect()
+        };
+        let data = format!(
+            "{}{}{}{}",
+            header,
+            entity("Q1", 3),
+            entity("P31", 1),
+            entity("Q2", 2)
+        );
+        let parts = split(&data, Limit::Entities(2), false);
+        assert_eq!(
+            parts,
+            [
+                format!("{}{}{}", header, entity("Q1", 3), entity("P31", 1)),
+                format!("{}{}", header, entity("Q2", 2)),
+            ]
+        );
+
+        // Cuts by size go to the next entity boundary
+        let parts = split(
+            &data,
+            Limit::Parts {
+                count: 2,
+                input_size: data.len() as u64,
+            },
+            false,
+        );
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts.concat().lines().count(), data.lines().count() + 1);
+    }
+
+    #[test]
+    fn test_split_json_array() {
+        let data = "[\n{\"id\":\"Q1\"},\n{\"id\":\"Q2\"},\n{\"id\":\"Q3\"}\n]\n";
+        let parts = split(data, Limit::Entities(2), true);
+        assert_eq!(
+            parts,
+            [
+                "[\n{\"id\":\"Q1\"},\n{\"id\":\"Q2\"}\n]\n",
+                "[\n{\"id\":\"Q3\"}\n]\n"
+            ]
+        );
+        let parts = split(
+            "{\"id\":\"Q1\"}\n{\"id\":\"Q2\"}\n",
+            Limit::Entities(1),
+            true,
+        );
+        assert_eq!(parts, ["{\"id\":\"Q1\"}\n", "{\"id\":\"Q2\"}\n"]);
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::Arc;
+
+use rusqlite::{params, Connection};
+use serde_json::Value;
+
+use crate::claim_parser::parse_claim_filter;
+use crate::compression::{create_input_reader, INPUT_BUFFER_SIZE};
+use crate::filter::EntityFilter;
+use crate::idset::IdSet;
+use crate::rdf::{OutputFormat, ProcessingOptions};
+use crate::tabular::json_datavalue_text;
+use crate::{run_filter, FilterError};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS entities (id TEXT PRIMARY KEY, type TEXT);
+CREATE TABLE IF NOT EXISTS labels (entity_id TEXT NOT NULL, language TEXT NOT NULL, value TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS descriptions (entity_id TEXT NOT NULL, language TEXT NOT NULL, value TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS aliases (entity_id TEXT NOT NULL, language TEXT NOT NULL, value TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS claims (entity_id TEXT NOT NULL, property TEXT NOT NULL, value TEXT, value_type TEXT, rank TEXT);
+CREATE TABLE IF NOT EXISTS sitelinks (entity_id TEXT NOT NULL, site TEXT NOT NULL, title TEXT NOT NULL);
+";
+
+/// Created after loading, which is faster than maintaining them during inserts
+const INDEXES: &str = "
+CREATE INDEX IF NOT EXISTS labels_entity ON labels (entity_id);
+CREATE INDEX IF NOT EXISTS descriptions_entity ON descriptions (entity_id);
+CREATE INDEX IF NOT EXISTS aliases_entity ON aliases (entity_id);
+CREATE INDEX IF NOT EXISTS claims_entity ON claims (entity_id);
+CREATE INDEX IF NOT EXISTS claims_property ON claims (property, value);
+CREATE INDEX IF NOT EXISTS sitelinks_entity ON sitelinks (entity_id);
+";
+
+/// Arguments for the `to-sqlite` subcommand
+#[derive(clap::Args, Debug)]
+pub struct SqliteArgs {
+    /// Input file (stdin if not provided, supports .bz2, .gz, .lz4)
+    #[arg()]
+    input: Option<String>,
+
+    /// SQLite database file to create or append to
+    #[arg(long)]
+    output: String,
+
+    /// Input format: auto, rdf, turtle, json (auto-detects from extension/content)
+    #[arg(short = 'f', long, default_value = "auto")]
+    format: String,
+
+    /// Filter by claim (e.g., P31:Q5, P31:Q5,Q6256, P31:Q5&P18)
+    #[arg(short, long)]
+    claim: Option<String>,
+
+    /// Entity type to filter: item, property, or both
+    #[arg(short = 't', long, default_value = "both")]
+    r#type: String,
+
+    /// Keep only specified subject entity IDs (comma-separated, e.g., Q31,Q42)
+    #[arg(long)]
+    subject: Option<String>,
+
+    /// Filter languages for labels/descriptions (comma-separated, e.g., en,de,fr)
+    #[arg(short = 'l', long)]
+    languages: Option<String>,
+
+    /// Show progress info on stderr
+    #[arg(short = 'p', long)]
+    progress: bool,
+}
+
+/// Tables with rows of each entity, keyed by `entity_id`
+const CHILD_TABLES: [&str; 5] = ["labels", "descriptions", "aliases", "claims", "sitelinks"];
+
+/// Insert one JSON entity into the database, replacing a stored one
+pub fn insert_entity(conn: &Connection, entity: &Value) -> rusqlite::Result<()> {
+    let id = match entity.get("id").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let entity_type = entity.get("type").and_then(|v| v.as_str());
+    // Look the entity up by its key first: before the indexes are created,
+    // deleting from the other tables scans them
+    let stored = conn
+        .prepare_cached("SELECT 1 FROM entities WHERE id = ?1")?
+        .exists(params![id])?;
+    if stored {
+        for table in CHILD_TABLES {
+            conn.prepare_cached(&format!("DELETE FROM {} WHERE entity_id = ?1", table))?
+                .execute(params![id])?;
+        }
+    }
+    conn.prepare_cached("INSERT OR REPLACE INTO entities (id, type) VALUES (?1, ?2)")?
+        .execute(params![id, entity_type])?;
+
+    for table in ["labels", "descriptions"] {
+        if let Some(map) = entity.get(table).and_then(|v| v.as_object()) {
+            let mut stmt = conn.prepare_cached(&format!(
+                "INSERT INTO {} (entity_id, language, value) VALUES (?1, ?2, ?3)",
+                table
+            ))?;
+            for (lang, value) in map {
+                if let Some(text) = value.get("value").and_then(|v| v.as_str()) {
+                    stmt.execute(params![id, lang, text])?;
+                }
+            }
+        }
+    }
+
+    if let Some(map) = entity.get("aliases").and_then(|v| v.as_object()) {
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO aliases (entity_id, language, value) VALUES (?1, ?2, ?3)",
+        )?;
+        for (lang, values) in map {
+            for value in values.as_array().into_iter().flatten() {
+                if let Some(text) = value.get("value").and_then(|v| v.as_str()) {
+                    stmt.execute(params![id, lang, text])?;
+                }
+            }
+        }
+    }
+
+    if let Some(claims) = entity.get("claims").and_then(|v| v.as_object()) {
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO claims (entity_id, property, value, value_type, rank) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for (prop, statements) in claims {
+            for statement in statements.as_array().into_iter().flatten() {
+                let datavalue = statement.get("mainsnak").and_then(|m| m.get("datavalue"));
+                let value = datavalue.and_then(json_datavalue_text);
+                let value_type = datavalue
+                    .and_then(|d| d.get("type"))
+                    .and_then(|t| t.as_str());
+                let rank = statement.get("rank").and_then(|r| r.as_str());
+                stmt.execute(params![id, prop, value, value_type, rank])?;
+            }
+        }
+    }
+
+    if let Some(sitelinks) = entity.get("sitelinks").and_then(|v| v.as_object()) {
+        let mut stmt = conn
+            .prepare_cached("INSERT INTO sitelinks (entity_id, site, title) VALUES (?1, ?2, ?3)")?;
+        for (site, link) in sitelinks {
+            if let Some(title) = link.get("title").and_then(|t| t.as_str()) {
+                stmt.execute(params![id, site, title])?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Write sink that inserts the NDJSON lines it receives into SQLite
+///
+/// This lets the regular filter loops stream entities into the database
+/// with `OutputFormat::Json`, for both RDF and JSON input.
+struct SqliteSink<'a> {
+    conn: &'a Connection,
+    pending: Vec<u8>,
+    entities: u64,
+}
+
+impl SqliteSink<'_> {
+    fn insert_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if line.is_empty() {
+            return Ok(());
+        }
+        let entity: Value = serde_json::from_slice(line)?;
+        insert_entity(self.conn, &entity).map_err(io::Error::other)?;
+        self.entities += 1;
+        Ok(())
+    }
+}
+
+impl Write for SqliteSink<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let mut start = 0;
+        while let Some(pos) = self.pending[start..].iter().position(|&b| b == b'\n') {
+            let line = self.pending[start..start + pos].to_vec();
+            self.insert_line(&line)?;
+            start += pos + 1;
+        }
+        self.pending.drain(..start);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Run the `to-sqlite` subcommand
+pub fn run(args: SqliteArgs) -> Result<(), FilterError> {
+    let claim_filter = args.claim.as_deref().map(parse_claim_filter).transpose()?;
+    let split_ids =
+        |s: &String| -> HashSet<String> { s.split(',').map(|id| id.trim().to_string()).collect() };
+    let filter = Arc::new(EntityFilter {
+        claim_filter,
+        subject_filter: args.subject.as_ref().map(split_ids).map(IdSet::from),
+        language_filter: args.languages.as_ref().map(split_ids),
+        entity_type: args.r#type.clone(),
+        ..Default::default()
+    });
+
+    let (reader, format): (Box<dyn BufRead + Send>, String) = match &args.input {
+        Some(path) => create_input_reader(path, &args.format, INPUT_BUFFER_SIZE)?,
+        None => {
+            let format = if args.format == "auto" {
+                "rdf".to_string()
+            } else {
+                args.format.clone()
+            };
+            (Box::new(BufReader::new(io::stdin())), format)
+        }
+    };
+
+    let mut conn = Connection::open(&args.output)?;
+    conn.execute_batch("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;")?;
+    conn.execute_batch(SCHEMA)?;
+
+    let options = ProcessingOptions {
+        show_progress: args.progress,
+        batch_size: 0,
+        output_format: OutputFormat::Json,
+        ..Default::default()
+    };
+
+    let tx = conn.transaction()?;
+    let mut sink = SqliteSink {
+        conn: &tx,
+        pending: Vec::new(),
+        entities: 0,
+    };
+    run_filter(reader, &format, &mut sink, &mut [], &filter, &options)?;
+    let entities = sink.entities;
+    tx.commit()?;
+
+    if args.progress {
+        eprintln!("Inserted {} entities, creating indexes...", entities);
+    }
+    conn.execute_batch(INDEXES)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_entity() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+
+        let entity: Value = serde_json::from_str(
+            r#"{"id":"Q64","type":"item",
+                "labels":{"en":{"language":"en","value":"Berlin"},"de":{"language":"de","value":"Berlin"}},
+                "descriptions":{"en":{"language":"en","value":"capital of Germany"}},
+                "aliases":{"en":[{"language":"en","value":"Berlin, Germany"}]},
+                "sitelinks":{"dewiki":{"site":"dewiki","title":"Berlin"}},
+                "claims":{"P31":[{"mainsnak":{"datavalue":{"value":{"entity-type":"item","id":"Q515"},"type":"wikibase-entityid"}},"rank":"normal"}]}}"#,
+        )
+        .unwrap();
+        insert_entity(&conn, &entity).unwrap();
+
+        let count = |table: &str| -> i64 {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |r| r.get(0))
+                .unwrap()
+        };
+        assert_eq!(count("entities"), 1);
+        assert_eq!(count("labels"), 2);
+        assert_eq!(count("descriptions"), 1);
+        assert_eq!(count("aliases"), 1);
+        assert_eq!(count("sitelinks"), 1);
+
+        let (value, value_type, rank): (String, String, String) = conn
+            .query_row(
+                "SELECT value, value_type, rank FROM claims WHERE entity_id = 'Q64' AND property = 'P31'",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(value, "Q515");
+        assert_eq!(value_type, "wikibase-entityid");
+        assert_eq!(rank, "normal");
+
+        // A newer revision replaces the rows of the stored one
+        let mut entity = entity;
+        entity["labels"]
+            .as_object_mut()
+            .unwrap()
+            .remove("de")
+            .unwrap();
+        insert_entity(&conn, &entity).unwrap();
+        assert_eq!(count("entities"), 1);
+        assert_eq!(count("labels"), 1);
+        assert_eq!(count("aliases"), 1);
+        assert_eq!(count("claims"), 1);
+        assert_eq!(count("sitelinks"), 1);
+    }
+
+    #[test]
+    fn test_sqlite_sink_splits_lines() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+
+        let mut sink = SqliteSink {
+            conn: &conn,
+            pending: Vec::new(),
+            entities: 0,
+        };
+        sink.write_all(b"{\"id\":\"Q1\",\"type\":\"item\"}\n{\"id\":\"Q")
+            .unwrap();
+        sink.write_all(b"2\",\"type\":\"item\"}\n").unwrap();
+
+        assert_eq!(sink.entities, 2);
+        let ids: Vec<String> = conn
+            .prepare("SELECT id FROM entities ORDER BY id")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(ids, vec!["Q1", "Q2"]);
+    }
+}
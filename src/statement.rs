@@ -0,0 +1,857 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+
+use regex::Regex;
+
+use crate::entity::{Claim, DataValue, Snak};
+use crate::truthify::Rank;
+use crate::FilterError;
+
+const XSD_DATE_TIME: &str = "<http://www.w3.org/2001/XMLSchema#dateTime>";
+const XSD_DECIMAL: &str = "<http://www.w3.org/2001/XMLSchema#decimal>";
+/// Prefix of the unit IRIs of JSON quantities
+const ENTITY_IRI: &str = "http://www.wikidata.org/entity/";
+
+/// Wikidata time precision of a day, the finest one compared
+const DAY: u8 = 11;
+const MONTH: u8 = 10;
+const YEAR: u8 = 9;
+const DECADE: u8 = 8;
+
+/// A point in time as compared in conditions, to the given Wikidata precision
+/// (6 = millennium, 7 = century, 8 = decade, 9 = year, 10 = month, 11 = day)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Date {
+    pub year: i64,
+    pub month: u8,
+    pub day: u8,
+    pub precision: u8,
+}
+
+impl Date {
+    /// Parse `1990`, `1990-05`, `-0500-05-01` or a Wikidata time such as
+    /// `+1990-05-01T00:00:00Z`, with the precision given by the parts present
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.split('T').next()?;
+        let (sign, rest) = match text.as_bytes().first()? {
+            b'-' => (-1, &text[1..]),
+            b'+' => (1, &text[1..]),
+            _ => (1, text),
+        };
+        let mut parts = rest.split('-');
+        let year = parts.next().filter(|y| !y.is_empty())?;
+        if !year.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let mut date = Date {
+            year: sign * year.parse::<i64>().ok()?,
+            month: 0,
+            day: 0,
+            precision: YEAR,
+        };
+        if let Some(month) = parts.next() {
+            date.month = month.parse().ok().filter(|m| *m <= 12)?;
+            date.precision = MONTH;
+        }
+        if let Some(day) = parts.next() {
+            date.day = day.parse().ok().filter(|d| *d <= 31)?;
+            date.precision = DAY;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(date)
+    }
+
+    /// Compare to the coarser precision of both dates: 1990 equals 1990-05-01,
+    /// and a date of the 19th century (precision 7) equals 1850 and 1900
+    pub fn compare(&self, other: &Date) -> Ordering {
+        let precision = self.precision.min(other.precision);
+        let key = |date: &Date| match precision {
+            p if p >= DAY => (date.year, date.month, date.day),
+            MONTH => (date.year, date.month, 0),
+            YEAR => (date.year, 0, 0),
+            DECADE => (date.year.div_euclid(10), 0, 0),
+            // Centuries and millennia count from year 1: 1801 to 1900 is the 19th
+            p => (
+                (date.year - 1).div_euclid(10i64.pow(u32::from(YEAR - p))),
+                0,
+                0,
+            ),
+        };
+        key(self).cmp(&key(other))
+    }
+}
+
+/// Comparison operator of a condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl Comparison {
+    /// Split a leading operator off `text`
+    pub fn split(text: &str) -> Option<(Self, &str)> {
+        [
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+            ("=", Comparison::Eq),
+        ]
+        .into_iter()
+        .find_map(|(op, comparison)| text.strip_prefix(op).map(|rest| (comparison, rest)))
+    }
+
+    pub fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Lt => ordering == Ordering::Less,
+            Comparison::Le => ordering != Ordering::Greater,
+            Comparison::Eq => ordering == Ordering::Equal,
+            Comparison::Ge => ordering != Ordering::Less,
+            Comparison::Gt => ordering == Ordering::Greater,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Eq => "=",
+            Comparison::Ge => ">=",
+            Comparison::Gt => ">",
+        })
+    }
+}
+
+/// The right-hand side of a comparison, read as a number and as a date
+/// where it has that form (`1990` is both); a number may be followed by the
+/// unit its quantity must have (`50 Q712226`, `1` for none)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Literal {
+    pub text: String,
+    pub number: Option<f64>,
+    pub date: Option<Date>,
+    pub unit: Option<String>,
+}
+
+impl Literal {
+    pub fn parse(text: &str) -> Result<Self, FilterError> {
+        let text = text.trim();
+        let (value, unit) = match text.split_once(char::is_whitespace) {
+            Some((value, unit)) => (value, Some(unit.trim())),
+            None => (text, None),
+        };
+        let literal = Literal {
+            text: text.to_string(),
+            number: value.parse().ok().filter(|n: &f64| n.is_finite()),
+            date: Date::parse(value).filter(|_| unit.is_none()),
+            unit: unit.map(str::to_string),
+        };
+        if let Some(unit) = unit {
+            let is_item = unit
+                .strip_prefix('Q')
+                .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+            if literal.number.is_none() || !(is_item || unit == "1") {
+                return Err(FilterError::InvalidClaim(format!(
+                    "Expected a number and a unit like '50 Q712226', found '{}'",
+                    text
+                )));
+            }
+        }
+        if literal.number.is_none() && literal.date.is_none() {
+            return Err(FilterError::InvalidClaim(format!(
+                "Expected a number or date, found '{}'",
+                text
+            )));
+        }
+        Ok(literal)
+    }
+}
+
+/// A regular expression on string values: the whole value (`P212:"978-3-.*"`)
+/// or anywhere in it (`P856~"\.de/"`)
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub text: String,
+    pub whole: bool,
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn new(text: &str, whole: bool) -> Result<Self, FilterError> {
+        let source = if whole {
+            format!("^(?:{})$", text)
+        } else {
+            text.to_string()
+        };
+        let regex = Regex::new(&source).map_err(|e| {
+            FilterError::InvalidClaim(format!("Invalid regular expression \"{}\": {}", text, e))
+        })?;
+        Ok(Pattern {
+            text: text.to_string(),
+            whole,
+            regex,
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+}
+
+/// The value of a main snak or qualifier, from JSON or RDF
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnakValue<'a> {
+    Entity(Cow<'a, str>),
+    /// Strings, external IDs, URLs and monolingual texts; the IRIs of RDF
+    /// objects that are no entities
+    Text(&'a str),
+    Time(Date),
+    /// An amount with its unit: an item ID, `1` for none, `None` where the
+    /// input does not say (truthy RDF values)
+    Quantity(f64, Option<&'a str>),
+    SomeValue,
+    NoValue,
+    Other,
+}
+
+impl<'a> SnakValue<'a> {
+    /// The value of a JSON snak
+    pub fn of_snak(snak: &'a Snak<'a>) -> Self {
+        match snak.snaktype.as_ref().map(|t| t.as_str()) {
+            Some("somevalue") => return SnakValue::SomeValue,
+            Some("novalue") => return SnakValue::NoValue,
+            _ => {}
+        }
+        match snak.datavalue {
+            Some(DataValue::EntityId(ref value)) => value
+                .entity_id()
+                .map_or(SnakValue::Other, SnakValue::Entity),
+            Some(DataValue::String(ref text)) => SnakValue::Text(text.as_str()),
+            Some(DataValue::MonolingualText(ref text)) => SnakValue::Text(text.text.as_str()),
+            Some(DataValue::Time(ref time)) => match Date::parse(time.time.as_str()) {
+                Some(mut date) => {
+                    date.precision = time.precision.unwrap_or(DAY).min(date.precision);
+                    SnakValue::Time(date)
+                }
+                None => SnakValue::Other,
+            },
+            Some(DataValue::Quantity(ref quantity)) => {
+                let unit = quantity.unit.as_ref().map(|unit| {
+                    let unit = unit.as_str();
+                    unit.strip_prefix(ENTITY_IRI).unwrap_or(unit)
+                });
+                quantity
+                    .amount
+                    .as_str()
+                    .parse()
+                    .map_or(SnakValue::Other, |amount| SnakValue::Quantity(amount, unit))
+            }
+            _ => SnakValue::Other,
+        }
+    }
+
+    /// The value of an N-Triples object term: an entity IRI, a typed or
+    /// plain literal, or a generated IRI or blank node for an unknown value
+    pub fn of_rdf(object: &'a str) -> Self {
+        if let Some(id) = crate::rdf::entity_value_id(object) {
+            return SnakValue::Entity(Cow::Borrowed(id));
+        }
+        if object.starts_with("_:") || object.contains("/.well-known/genid/") {
+            return SnakValue::SomeValue;
+        }
+        if let Some(iri) = object.strip_prefix('<').and_then(|o| o.strip_suffix('>')) {
+            return SnakValue::Text(iri);
+        }
+        let Some(rest) = object.strip_prefix('"') else {
+            return SnakValue::Other;
+        };
+        let Some(end) = rest.rfind('"') else {
+            return SnakValue::Other;
+        };
+        let (text, suffix) = (&rest[..end], &rest[end + 1..]);
+        match suffix.strip_prefix("^^") {
+            Some(XSD_DATE_TIME) => Date::parse(text).map_or(SnakValue::Other, SnakValue::Time),
+            Some(XSD_DECIMAL) => text
+                .parse()
+                .map_or(SnakValue::Other, |amount| SnakValue::Quantity(amount, None)),
+            Some(_) => SnakValue::Other,
+            None => SnakValue::Text(text),
+        }
+    }
+}
+
+/// What a main snak or qualifier value must be
+#[derive(Debug, Clone)]
+pub enum ValueTest {
+    /// Any snak, including unknown and no value
+    Any,
+    /// A snak with a value, not unknown or no value (--exclude-special-values)
+    Value,
+    /// An unknown value (somevalue)
+    SomeValue,
+    /// Explicitly no value (novalue)
+    NoValue,
+    /// An entity value, with an ID starting with the letter if given
+    AnyEntity(Option<char>),
+    /// One of these entity IDs
+    Entities(HashSet<String>),
+    /// A time, quantity or numeric string compared to a literal
+    Compare(Comparison, Literal),
+    /// A time, quantity or numeric string between two literals, both included
+    Range(Literal, Literal),
+    /// A string, external ID or URL matching a regular expression
+    Pattern(Pattern),
+}
+
+impl ValueTest {
+    pub fn matches(&self, value: &SnakValue) -> bool {
+        match (self, value) {
+            (ValueTest::Any, _) => true,
+            (ValueTest::Value, value) => {
+                !matches!(value, SnakValue::SomeValue | SnakValue::NoValue)
+            }
+            (ValueTest::SomeValue, SnakValue::SomeValue) => true,
+            (ValueTest::NoValue, SnakValue::NoValue) => true,
+            (ValueTest::AnyEntity(letter), SnakValue::Entity(id)) => {
+                letter.is_none_or(|letter| id.starts_with(letter))
+            }
+            (ValueTest::Entities(ids), SnakValue::Entity(id)) => ids.contains(id.as_ref()),
+            (ValueTest::Compare(op, literal), value) => compare(*op, literal, value),
+            (ValueTest::Range(low, high), value) => {
+                compare(Comparison::Ge, low, value) && compare(Comparison::Le, high, value)
+            }
+            (ValueTest::Pattern(pattern), SnakValue::Text(text)) => pattern.is_match(text),
+            _ => false,
+        }
+    }
+}
+
+/// Whether a time, quantity or numeric string compares to the literal as `op` says
+fn compare(op: Comparison, literal: &Literal, value: &SnakValue) -> bool {
+    match value {
+        SnakValue::Time(date) => literal
+            .date
+            .is_some_and(|other| op.holds(date.compare(&other))),
+        SnakValue::Quantity(amount, unit) => {
+            literal
+                .unit
+                .as_deref()
+                .is_none_or(|other| *unit == Some(other))
+                && literal
+                    .number
+                    .and_then(|other| amount.partial_cmp(&other))
+                    .is_some_and(|ordering| op.holds(ordering))
+        }
+        SnakValue::Text(text) if literal.unit.is_none() => {
+            match (text.parse::<f64>(), literal.number) {
+                (Ok(number), Some(other)) => number
+                    .partial_cmp(&other)
+                    .is_some_and(|ordering| op.holds(ordering)),
+                _ => op == Comparison::Eq && *text == literal.text,
+            }
+        }
+        _ => false,
+    }
+}
+
+impl fmt::Display for ValueTest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueTest::Any => f.write_str("(any value)"),
+            ValueTest::Value => f.write_str("(specific value)"),
+            ValueTest::SomeValue => f.write_str("= somevalue"),
+            ValueTest::NoValue => f.write_str("= novalue"),
+            ValueTest::AnyEntity(Some(letter)) => write!(f, "= {}!", letter),
+            ValueTest::AnyEntity(None) => f.write_str("= *"),
+            ValueTest::Entities(ids) => {
+                let mut ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+                ids.sort_unstable();
+                write!(f, "= {}", ids.join(" | "))
+            }
+            ValueTest::Compare(op, literal) => write!(f, "{} {}", op, literal.text),
+            ValueTest::Range(low, high) => write!(f, "in {}..{}", low.text, high.text),
+            ValueTest::Pattern(pattern) if pattern.whole => write!(f, "= \"{}\"", pattern.text),
+            ValueTest::Pattern(pattern) => write!(f, "~ \"{}\"", pattern.text),
+        }
+    }
+}
+
+/// A condition on the qualifiers of a statement (e.g., `{P580>=1990}`): some
+/// qualifier of the property passes the test
+#[derive(Debug, Clone)]
+pub struct SnakCondition {
+    pub property: String,
+    pub test: ValueTest,
+}
+
+impl fmt::Display for SnakCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{{} {}}}", self.property, self.test)
+    }
+}
+
+/// Statement ranks claim filters count (--ranks, `P1082@preferred`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RankSet(u8);
+
+impl RankSet {
+    const RANKS: [Rank; 3] = [Rank::Preferred, Rank::Normal, Rank::Deprecated];
+
+    pub fn contains(self, rank: Rank) -> bool {
+        self.0 & 1 << rank as u8 != 0
+    }
+
+    /// Parse comma-separated rank names, e.g. `preferred,normal`
+    pub fn parse(names: &str) -> Result<Self, FilterError> {
+        let mut set = RankSet(0);
+        for name in names.split(',').map(str::trim) {
+            let rank = Rank::from_name(name).ok_or_else(|| {
+                FilterError::InvalidClaim(format!(
+                    "Invalid rank '{}', expected preferred, normal or deprecated",
+                    name
+                ))
+            })?;
+            set.0 |= 1 << rank as u8;
+        }
+        Ok(set)
+    }
+}
+
+impl Default for RankSet {
+    /// Preferred and normal statements, as in the truthy dumps
+    fn default() -> Self {
+        RankSet(1 << Rank::Preferred as u8 | 1 << Rank::Normal as u8)
+    }
+}
+
+impl fmt::Display for RankSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = Self::RANKS
+            .into_iter()
+            .filter(|rank| self.contains(*rank))
+            .map(Rank::name)
+            .collect();
+        f.write_str(&names.join(","))
+    }
+}
+
+/// A statement of a property whose main value, qualifiers and references
+/// pass the tests
+#[derive(Debug, Clone)]
+pub struct StatementFilter {
+    pub property: String,
+    pub value: ValueTest,
+    pub qualifiers: Vec<SnakCondition>,
+    /// The statement has at least one reference (`[ref]`, --require-reference)
+    pub referenced: bool,
+    /// Conditions some reference snak of the statement meets (`[ref:P248:Q36578]`)
+    pub references: Vec<SnakCondition>,
+    /// Ranks counted (`P1082@preferred`, --ranks); preferred and normal if not given
+    pub ranks: Option<RankSet>,
+    /// Units one of which the quantity main value uses (`[unit:Q11573]`),
+    /// any if empty; `1` stands for dimensionless quantities
+    pub units: HashSet<String>,
+}
+
+impl StatementFilter {
+    /// A filter of the main value alone
+    pub fn new(property: String, value: ValueTest) -> Self {
+        Self {
+            property,
+            value,
+            qualifiers: Vec::new(),
+            referenced: false,
+            references: Vec::new(),
+            ranks: None,
+            units: HashSet::new(),
+        }
+    }
+
+    pub fn matches<S: StatementView + ?Sized>(&self, statement: &S) -> bool {
+        let holds = |condition: &SnakCondition, values: Vec<SnakValue>| {
+            values.iter().any(|value| condition.test.matches(value))
+        };
+        self.ranks.unwrap_or_default().contains(statement.rank())
+            && self.value.matches(&statement.value())
+            && (self.units.is_empty()
+                || matches!(statement.value(), SnakValue::Quantity(_, Some(unit)) if self.units.contains(unit)))
+            && (!self.referenced || statement.is_referenced())
+            && self
+                .qualifiers
+                .iter()
+                .all(|condition| holds(condition, statement.qualifier_values(&condition.property)))
+            && self
+                .references
+                .iter()
+                .all(|condition| holds(condition, statement.reference_values(&condition.property)))
+    }
+}
+
+impl fmt::Display for StatementFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.property)?;
+        if let Some(ranks) = self.ranks {
+            write!(f, "@{}", ranks)?;
+        }
+        write!(f, " {}", self.value)?;
+        for condition in &self.qualifiers {
+            write!(f, " {}", condition)?;
+        }
+        if self.referenced && self.references.is_empty() {
+            f.write_str(" [ref]")?;
+        }
+        for condition in &self.references {
+            write!(f, " [ref {} {}]", condition.property, condition.test)?;
+        }
+        if !self.units.is_empty() {
+            let mut units: Vec<&str> = self.units.iter().map(String::as_str).collect();
+            units.sort_unstable();
+            write!(f, " [unit {}]", units.join(" | "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A statement as seen by a `StatementFilter`
+pub trait StatementView {
+    fn value(&self) -> SnakValue<'_>;
+    fn qualifier_values(&self, property: &str) -> Vec<SnakValue<'_>>;
+    fn rank(&self) -> Rank;
+    /// Whether the statement has at least one reference
+    fn is_referenced(&self) -> bool;
+    /// Values of the property in all references of the statement
+    fn reference_values(&self, property: &str) -> Vec<SnakValue<'_>>;
+}
+
+impl StatementView for Claim<'_> {
+    fn value(&self) -> SnakValue<'_> {
+        SnakValue::of_snak(&self.mainsnak)
+    }
+
+    fn qualifier_values(&self, property: &str) -> Vec<SnakValue<'_>> {
+        self.qualifiers
+            .get(property)
+            .into_iter()
+            .flatten()
+            .map(SnakValue::of_snak)
+            .collect()
+    }
+
+    fn rank(&self) -> Rank {
+        self.rank
+            .as_ref()
+            .and_then(|rank| Rank::from_name(rank.as_str()))
+            .unwrap_or(Rank::Normal)
+    }
+
+    fn is_referenced(&self) -> bool {
+        !self.references.is_empty()
+    }
+
+    fn reference_values(&self, property: &str) -> Vec<SnakValue<'_>> {
+        self.references
+            .iter()
+            .filter_map(|reference| reference.snaks.get(property))
+            .flatten()
+            .map(SnakValue::of_snak)
+            .collect()
+    }
+}
+
+/// A truthy value, a statement without qualifiers or references
+pub struct TruthyValue<'a>(pub SnakValue<'a>);
+
+impl StatementView for TruthyValue<'_> {
+    fn value(&self) -> SnakValue<'_> {
+        self.0.clone()
+    }
+
+    fn qualifier_values(&self, _property: &str) -> Vec<SnakValue<'_>> {
+        Vec::new()
+    }
+
+    /// Truthy values are of the best rank, taken as normal
+    fn rank(&self) -> Rank {
+        Rank::Normal
+    }
+
+    fn is_referenced(&self) -> bool {
+        false
+    }
+
+    fn reference_values(&self, _property: &str) -> Vec<SnakValue<'_>> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_date_parse_and_compare() {
+        let date = Date::parse("+1990-05-01T00:00:00Z").unwrap();
+        assert_eq!(
+            (date.year, date.month, date.day, date.precision),
+            (1990, 5, 1, DAY)
+        );
+        assert_eq!(Date::parse("-0500").unwrap().year, -500);
+        assert_eq!(Date::parse("1990-05").unwrap().precision, MONTH);
+        for invalid in ["", "-", "x1990", "1990-13", "1990-01-01-01"] {
+            assert!(Date::parse(invalid).is_none(), "{}", invalid);
+        }
+        let year = Date::parse("1990").unwrap();
+        assert_eq!(date.compare(&year), Ordering::Equal);
+        assert_eq!(
+            date.compare(&Date::parse("1990-04-30").unwrap()),
+            Ordering::Greater
+        );
+        assert_eq!(
+            Date::parse("-0500")
+                .unwrap()
+                .compare(&Date::parse("0100").unwrap()),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_date_compare_coarse_precision() {
+        let at = |text: &str, precision| Date {
+            precision,
+            ..Date::parse(text).unwrap()
+        };
+        let year = |text: &str| Date::parse(text).unwrap();
+        // The 19th century runs from 1801 to 1900
+        let century = at("+1801-00-00T00:00:00Z", 7);
+        assert_eq!(century.compare(&year("1850")), Ordering::Equal);
+        assert_eq!(century.compare(&year("1900")), Ordering::Equal);
+        assert_eq!(century.compare(&year("1901")), Ordering::Less);
+        assert_eq!(century.compare(&year("1800")), Ordering::Greater);
+        let decade = at("+1990-00-00T00:00:00Z", 8);
+        assert_eq!(decade.compare(&year("1999-12-31")), Ordering::Equal);
+        assert_eq!(decade.compare(&year("2000")), Ordering::Less);
+        let millennium = at("+2000-00-00T00:00:00Z", 6);
+        assert_eq!(millennium.compare(&year("1001")), Ordering::Equal);
+        assert_eq!(millennium.compare(&year("2001")), Ordering::Less);
+
+        let range = ValueTest::Range(
+            Literal::parse("1900").unwrap(),
+            Literal::parse("1950").unwrap(),
+        );
+        assert!(range.matches(&SnakValue::Time(year("1950-12-31"))));
+        assert!(!range.matches(&SnakValue::Time(year("1951-01-01"))));
+        assert!(range.matches(&SnakValue::Time(century)));
+        assert!(!range.matches(&SnakValue::Time(at("+1701-00-00T00:00:00Z", 7))));
+    }
+
+    #[test]
+    fn test_value_test_compare() {
+        let ge = |text| ValueTest::Compare(Comparison::Ge, Literal::parse(text).unwrap());
+        let time = SnakValue::Time(Date::parse("1995-03-01").unwrap());
+        assert!(ge("1990").matches(&time));
+        assert!(ge("1995").matches(&time));
+        assert!(!ge("1995-04").matches(&time));
+        assert!(ge("1000").matches(&SnakValue::Quantity(1500.0, None)));
+        assert!(!ge("2000").matches(&SnakValue::Quantity(1500.0, None)));
+        let area = SnakValue::Quantity(50.0, Some("Q712226"));
+        assert!(ge("50 Q712226").matches(&area) && ge("50").matches(&area));
+        assert!(!ge("50 Q35852").matches(&area) && !ge("50 1").matches(&area));
+        assert!(!ge("10 Q712226").matches(&SnakValue::Quantity(50.0, None)));
+        assert!(!ge("3 Q11573").matches(&SnakValue::Text("12")));
+        for invalid in ["50 km", "1990-05 Q5", "50 Q"] {
+            assert!(Literal::parse(invalid).is_err(), "{}", invalid);
+        }
+        assert!(ge("3").matches(&SnakValue::Text("12")));
+        assert!(!ge("1990").matches(&SnakValue::Entity(Cow::Borrowed("Q1990"))));
+        assert!(!ge("1990").matches(&SnakValue::SomeValue));
+        assert!(Literal::parse("Q5").is_err());
+    }
+
+    #[test]
+    fn test_snak_value_of_rdf() {
+        assert_eq!(
+            SnakValue::of_rdf("<http://www.wikidata.org/entity/Q5>"),
+            SnakValue::Entity(Cow::Borrowed("Q5"))
+        );
+        assert_eq!(
+            SnakValue::of_rdf(&format!("\"+1.5\"^^{}", XSD_DECIMAL)),
+            SnakValue::Quantity(1.5, None)
+        );
+        assert!(matches!(
+            SnakValue::of_rdf(&format!("\"1990-01-01T00:00:00Z\"^^{}", XSD_DATE_TIME)),
+            SnakValue::Time(Date { year: 1990, .. })
+        ));
+        assert_eq!(SnakValue::of_rdf("\"1\""), SnakValue::Text("1"));
+        assert_eq!(SnakValue::of_rdf("_:b1"), SnakValue::SomeValue);
+    }
+
+    #[test]
+    fn test_qualifier_conditions_on_entity() {
+        use crate::claim_parser::parse_claim_filter;
+        use crate::entity::Entity;
+
+        let position = |id: &str, start: &str| {
+            format!(
+                r#"{{"mainsnak":{{"datavalue":{{"value":{{"id":"{}"}},"type":"wikibase-entityid"}}}},
+                    "qualifiers":{{"P580":[{{"snaktype":"value","datavalue":{{"value":{{"time":"{}",
+                        "precision":9}},"type":"time"}}}}]}}}}"#,
+                id, start
+            )
+        };
+        let line = format!(
+            r#"{{"id":"Q1","claims":{{"P39":[{},{}]}}}}"#,
+            position("Q11696", "+1985-00-00T00:00:00Z"),
+            position("Q4416090", "+1995-00-00T00:00:00Z")
+        );
+        let entity: Entity = serde_json::from_str(&line).unwrap();
+        let matches = |input: &str| parse_claim_filter(input).unwrap().matches(&entity);
+        assert!(matches("P39{P580>=1990}"));
+        assert!(matches("P39:Q11696{P580}"));
+        assert!(matches("P39:Q11696{P580=1985-06}"));
+        // Value and qualifiers must hold on the same statement
+        assert!(!matches("P39:Q11696{P580>=1990}"));
+        assert!(!matches("P39{P582}"));
+        assert!(!matches("P39{P580>=1990}{P580<1990}"));
+    }
+
+    #[test]
+    fn test_rank_set() {
+        let ranks = RankSet::parse("deprecated, preferred").unwrap();
+        assert!(ranks.contains(Rank::Preferred) && ranks.contains(Rank::Deprecated));
+        assert!(!ranks.contains(Rank::Normal));
+        assert_eq!(ranks.to_string(), "preferred,deprecated");
+        assert_eq!(RankSet::default().to_string(), "preferred,normal");
+        assert_eq!(
+            RankSet::parse("normal,preferred").unwrap(),
+            RankSet::default()
+        );
+        assert!(RankSet::parse("best").is_err());
+    }
+
+    #[test]
+    fn test_ranks_on_entity() {
+        use crate::claim_parser::parse_claim_filter;
+        use crate::entity::Entity;
+
+        let entity: Entity = serde_json::from_str(
+            r#"{"id":"Q1","claims":{"P31":[{"mainsnak":{"snaktype":"value","datavalue":{"value":{"id":"Q5"},"type":"wikibase-entityid"}},"rank":"deprecated"}],
+                "P1082":[{"mainsnak":{"snaktype":"value"},"rank":"preferred"},{"mainsnak":{"snaktype":"value"}}]}}"#,
+        )
+        .unwrap();
+        let matches = |input: &str| parse_claim_filter(input).unwrap().matches(&entity);
+        // Deprecated statements do not count unless asked for
+        assert!(!matches("P31"));
+        assert!(!matches("P31:Q5"));
+        assert!(!matches("P31:*"));
+        assert!(matches("P31@deprecated:Q5"));
+        assert!(matches("P1082@preferred"));
+        assert!(!matches("P1082@deprecated"));
+        let all = RankSet::parse("preferred,normal,deprecated").unwrap();
+        let filter = parse_claim_filter("P31:Q5")
+            .unwrap()
+            .with_ranks(all)
+            .unwrap();
+        assert!(filter.matches(&entity));
+    }
+
+    #[test]
+    fn test_quantities_on_entity() {
+        use crate::claim_parser::parse_claim_filter;
+        use crate::entity::Entity;
+
+        let entity: Entity = serde_json::from_str(
+            r#"{"id":"Q64","claims":{"P1082":[{"mainsnak":{"snaktype":"value","datavalue":{"value":{"amount":"+3755251","unit":"1"},"type":"quantity"}}}],
+                "P2046":[{"mainsnak":{"snaktype":"value","datavalue":{"value":{"amount":"+891.1","unit":"http://www.wikidata.org/entity/Q712226"},"type":"quantity"}}}]}}"#,
+        )
+        .unwrap();
+        let matches = |input: &str| parse_claim_filter(input).unwrap().matches(&entity);
+        assert!(matches("P1082>1000000") && !matches("P1082<1000000"));
+        assert!(matches("P1082>=3755251 1") && matches("P1082=3755251"));
+        assert!(matches("P2046<=1000 Q712226") && !matches("P2046<=1000 Q35852"));
+        assert!(!matches("P2046<=50") && !matches("P31>5"));
+        assert!(matches("P1082>1000000&~P2046>1000"));
+        assert!(matches("P2046:*[unit:Q712226]") && !matches("P2046:*[unit:Q35852]"));
+        assert!(matches("P2046[unit:Q35852,Q712226]") && matches("P1082[unit:1]"));
+        assert!(matches("P2046>500[unit:Q712226]") && !matches("P2046>1000[unit:Q712226]"));
+        assert!(!matches("P31:*[unit:Q712226]"));
+    }
+
+    #[test]
+    fn test_patterns_on_entity() {
+        use crate::claim_parser::parse_claim_filter;
+        use crate::entity::Entity;
+
+        let entity: Entity = serde_json::from_str(
+            r#"{"id":"Q1","claims":{"P212":[{"mainsnak":{"snaktype":"value","datavalue":{"value":"978-3-16-148410-0","type":"string"}}}],
+                "P856":[{"mainsnak":{"snaktype":"value","datavalue":{"value":"https://www.berlin.de/","type":"string"}}}],
+                "P31":[{"mainsnak":{"snaktype":"value","datavalue":{"value":{"id":"Q5"},"type":"wikibase-entityid"}}}]}}"#,
+        )
+        .unwrap();
+        let matches = |input: &str| parse_claim_filter(input).unwrap().matches(&entity);
+        assert!(matches(r#"P212:"978-3-.*""#) && !matches(r#"P212:"978-3""#));
+        assert!(matches(r#"P212~"^978-3""#) && matches(r#"P212~"-3-""#));
+        assert!(matches(r#"P856~"\.de/""#) && !matches(r#"P856~"\.fr/""#));
+        assert!(!matches(r#"P31~"Q5""#));
+    }
+
+    #[test]
+    fn test_special_values_on_entity() {
+        use crate::claim_parser::parse_claim_filter;
+        use crate::entity::Entity;
+
+        let entity: Entity = serde_json::from_str(
+            r#"{"id":"Q1","claims":{"P570":[{"mainsnak":{"snaktype":"somevalue"}}],
+                "P40":[{"mainsnak":{"snaktype":"novalue"}}],
+                "P39":[{"mainsnak":{"snaktype":"value","datavalue":{"value":{"id":"Q30185"},"type":"wikibase-entityid"}},
+                    "qualifiers":{"P582":[{"snaktype":"novalue"}]}}]}}"#,
+        )
+        .unwrap();
+        let matches = |input: &str| parse_claim_filter(input).unwrap().matches(&entity);
+        assert!(matches("P570") && matches("P40"));
+        assert!(matches("P570:somevalue") && !matches("P570:novalue"));
+        assert!(matches("P40:novalue") && !matches("P39:novalue"));
+        assert!(!matches("P570:*"));
+        assert!(matches("P39{P582:novalue}") && !matches("P39{P582>=2000}"));
+        let values_only = |input: &str| {
+            parse_claim_filter(input)
+                .unwrap()
+                .exclude_special_values()
+                .matches(&entity)
+        };
+        assert!(!values_only("P570") && !values_only("P40"));
+        assert!(!values_only("P39{P582}"));
+        assert!(values_only("P39") && values_only("P570:somevalue"));
+    }
+
+    #[test]
+    fn test_reference_conditions_on_entity() {
+        use crate::claim_parser::parse_claim_filter;
+        use crate::entity::Entity;
+
+        let entity: Entity = serde_json::from_str(
+            r#"{"id":"Q1","claims":{"P569":[{"mainsnak":{"snaktype":"value"},"references":[
+                {"snaks":{"P248":[{"snaktype":"value","datavalue":{"value":{"id":"Q36578"},"type":"wikibase-entityid"}}]}},
+                {"snaks":{"P813":[{"snaktype":"value","datavalue":{"value":{"time":"+2021-05-01T00:00:00Z","precision":11},"type":"time"}}]}}]}],
+                "P570":[{"mainsnak":{"snaktype":"value"},"references":[]}]}}"#,
+        )
+        .unwrap();
+        let matches = |input: &str| parse_claim_filter(input).unwrap().matches(&entity);
+        assert!(matches("P569[ref]"));
+        assert!(matches("P569[ref:P248:Q36578]"));
+        // Each condition may hold in another reference of the statement
+        assert!(matches("P569[ref:P248:Q36578][ref:P813>=2020]"));
+        assert!(!matches("P569[ref:P248:Q5]"));
+        assert!(!matches("P570[ref]"));
+        assert!(matches("P570"));
+    }
+}
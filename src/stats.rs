@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::filter::{EntityFilter, FilterStage};
+
+/// Counters gathered by a worker for one batch, merged into `RunStats`
+#[derive(Debug, Default)]
+pub struct Counts {
+    pub lines_read: u64,
+    pub entities_seen: u64,
+    pub matched_by_type: BTreeMap<String, u64>,
+    /// Entities dropped by each filter
+    pub rejected: BTreeMap<FilterStage, u64>,
+    pub triples_written: u64,
+    /// Input lines dropped because they do not parse
+    pub malformed_lines: u64,
+}
+
+impl Counts {
+    /// Count an entity that was checked against the filters
+    ///
+    /// `selected` entities are written; with --invert-match those are the
+    /// rejected ones.
+    pub fn record(
+        &mut self,
+        rejection: Option<FilterStage>,
+        selected: bool,
+        entity_type: Option<&str>,
+    ) {
+        self.entities_seen += 1;
+        if let Some(stage) = rejection {
+            *self.rejected.entry(stage).or_default() += 1;
+        }
+        if selected {
+            let entity_type = entity_type.unwrap_or("unknown");
+            match self.matched_by_type.get_mut(entity_type) {
+                Some(count) => *count += 1,
+                None => {
+                    self.matched_by_type.insert(entity_type.to_string(), 1);
+                }
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Counts) {
+        self.lines_read += other.lines_read;
+        self.entities_seen += other.entities_seen;
+        for (entity_type, count) in other.matched_by_type {
+            *self.matched_by_type.entry(entity_type).or_default() += count;
+        }
+        for (stage, count) in other.rejected {
+            *self.rejected.entry(stage).or_default() += count;
+        }
+        self.triples_written += other.triples_written;
+        self.malformed_lines += other.malformed_lines;
+    }
+}
+
+/// Entity types of the main output results of a batch, in order and run
+/// length encoded, so the matches --max-entities cuts off can be taken back
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ResultTypes(Vec<(String, u64)>);
+
+impl ResultTypes {
+    pub fn push(&mut self, entity_type: Option<&str>) {
+        let entity_type = entity_type.unwrap_or("unknown");
+        match self.0.last_mut() {
+            Some((last, count)) if last == entity_type => *count += 1,
+            _ => self.0.push((entity_type.to_string(), 1)),
+        }
+    }
+
+    /// Take back the last result, which had no output after all
+    pub fn pop(&mut self) {
+        if let Some((_, count)) = self.0.last_mut() {
+            *count -= 1;
+            if *count == 0 {
+                self.0.pop();
+            }
+        }
+    }
+}
+
+/// Totals of a run for the --stats-output report
+#[derive(Debug, Default)]
+pub struct RunStats {
+    counts: Mutex<Counts>,
+    /// Bytes read from the input, as stored (compressed)
+    pub bytes_read: Arc<AtomicU64>,
+    /// Bytes written to the output, after compression
+    pub bytes_written: Arc<AtomicU64>,
+}
+
+impl RunStats {
+    pub fn add(&self, counts: Counts) {
+        self.counts
+            .lock()
+            .expect("stats lock poisoned")
+            .merge(counts);
+    }
+
+    /// Take back the matches of the last `dropped` results of a batch, which
+    /// --max-entities kept from being written
+    pub fn unmatch(&self, types: &ResultTypes, mut dropped: u64) {
+        let mut counts = self.counts.lock().expect("stats lock poisoned");
+        for (entity_type, count) in types.0.iter().rev() {
+            if dropped == 0 {
+                break;
+            }
+            let taken = dropped.min(*count);
+            if let Some(matched) = counts.matched_by_type.get_mut(entity_type) {
+                *matched -= taken;
+                if *matched == 0 {
+                    counts.matched_by_type.remove(entity_type);
+                }
+            }
+            dropped -= taken;
+        }
+    }
+
+    /// Build the report; filters list how many entities reached and passed them
+    pub fn report(&self, filter: &EntityFilter, wall_time: Duration) -> Value {
+        let counts = self.counts.lock().expect("stats lock poisoned");
+
+        let mut filters = serde_json::Map::new();
+        let mut checked = counts.entities_seen;
+        for stage in filter.active_stages() {
+            let rejected = counts.rejected.get(&stage).copied().unwrap_or(0);
+            filters.insert(
+                stage.name().to_string(),
+                json!({"checked": checked, "matched": checked - rejected}),
+            );
+            checked -= rejected;
+        }
+
+        json!({
+            "lines_read": counts.lines_read,
+            "entities_seen": counts.entities_seen,
+            "entities_matched": counts.matched_by_type.values().sum::<u64>(),
+            "entities_matched_by_type": counts.matched_by_type,
+            "triples_written": counts.triples_written,
+            "malformed_lines": counts.malformed_lines,
+            "bytes_read": self.bytes_read.load(Ordering::Relaxed),
+            "bytes_written": self.bytes_written.load(Ordering::Relaxed),
+            "wall_time_seconds": wall_time.as_secs_f64(),
+            "filters": filters,
+        })
+    }
+
+    /// Write the report as pretty-printed JSON
+    pub fn write_report(
+        &self,
+        path: &str,
+        filter: &EntityFilter,
+        wall_time: Duration,
+    ) -> io::Result<()> {
+        let report = serde_json::to_string_pretty(&self.report(filter, wall_time))?;
+        std::fs::write(path, report + "\n")
+    }
+}
+
+/// Reader or writer that counts the bytes passing through
+pub struct Counting<T> {
+    inner: T,
+    count: Arc<AtomicU64>,
+}
+
+impl<T> Counting<T> {
+    pub fn new(inner: T, count: Arc<AtomicU64>) -> Self {
+        Self { inner, count }
+    }
+}
+
+impl<T: Read> Read for Counting<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl<T: Write> Write for Counting<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_counts_filter_stages() {
+        let filter = EntityFilter {
+            entity_type: "item".to_string(),
+            claim_filter: Some(crate::claim_parser::parse_claim_filter("P31:Q5").unwrap()),
+            ..Default::default()
+        };
+        let stats = RunStats::default();
+        let mut counts = Counts {
+            lines_read: 10,
+            ..Default::default()
+        };
+        counts.record(None, true, Some("item"));
+        counts.record(Some(FilterStage::Type), false, Some("property"));
+        counts.record(Some(FilterStage::Claim), false, Some("item"));
+        stats.add(counts);
+        let mut counts = Counts::default();
+        counts.record(None, true, Some("item"));
+        stats.add(counts);
+
+        let report = stats.report(&filter, Duration::from_millis(1500));
+        assert_eq!(report["lines_read"], 10);
+        assert_eq!(report["entities_seen"], 4);
+        assert_eq!(report["entities_matched"], 2);
+        assert_eq!(report["entities_matched_by_type"]["item"], 2);
+        assert_eq!(
+            report["filters"]["type"],
+            json!({"checked": 4, "matched": 3})
+        );
+        assert_eq!(
+            report["filters"]["claim"],
+            json!({"checked": 3, "matched": 2})
+        );
+        assert!(report["filters"].get("subject").is_none());
+        assert_eq!(report["wall_time_seconds"], 1.5);
+    }
+
+    #[test]
+    fn test_unmatch_cut_off_results() {
+        let stats = RunStats::default();
+        let mut counts = Counts::default();
+        let mut types = ResultTypes::default();
+        for entity_type in ["item", "item", "property", "item", "lexeme"] {
+            counts.record(None, true, Some(entity_type));
+            types.push(Some(entity_type));
+        }
+        types.push(Some("form"));
+        types.pop();
+        stats.add(counts);
+        // Only the first two results were written
+        stats.unmatch(&types, 3);
+
+        let report = stats.report(&EntityFilter::default(), Duration::ZERO);
+        assert_eq!(report["entities_matched"], 2);
+        assert_eq!(report["entities_matched_by_type"], json!({"item": 2}));
+    }
+
+    #[test]
+    fn test_counting_writer() {
+        let count = Arc::new(AtomicU64::new(0));
+        let mut writer = Counting::new(Vec::new(), count.clone());
+        writer.write_all(b"hello").unwrap();
+        assert_eq!(count.load(Ordering::Relaxed), 5);
+    }
+}
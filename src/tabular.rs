@@ -0,0 +1,411 @@
+use std::io::Write;
+
+use serde_json::Value;
+
+use crate::ntriples::{self, NTriple};
+use crate::rdf::{OutputFormat, RdfEntity};
+use crate::FilterError;
+
+/// Columns used when `--columns` is not given
+pub const DEFAULT_COLUMNS: &str = "id,labels.en,descriptions.en";
+
+/// A single CSV/TSV column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Id,
+    Type,
+    Label(String),
+    Description(String),
+    Alias(String),
+    Sitelink(String),
+    Property(String),
+}
+
+impl Column {
+    /// Check if the column can hold several values (aliases, claims)
+    pub fn is_multi_valued(&self) -> bool {
+        matches!(self, Column::Alias(_) | Column::Property(_))
+    }
+}
+
+/// Column layout for CSV/TSV output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableSpec {
+    /// Column names as given on the command line (used for the header row)
+    pub names: Vec<String>,
+    pub columns: Vec<Column>,
+    /// Separator for joining multi-valued cells (claims, aliases)
+    pub value_separator: String,
+}
+
+impl Default for TableSpec {
+    fn default() -> Self {
+        Self::parse(DEFAULT_COLUMNS, "|").expect("default columns are valid")
+    }
+}
+
+fn is_property_id(s: &str) -> bool {
+    s.len() > 1 && s.starts_with('P') && s[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+impl TableSpec {
+    /// Parse a column list like `id,labels.en,descriptions.en,P31,P625`
+    pub fn parse(columns: &str, value_separator: &str) -> Result<Self, FilterError> {
+        let mut names = Vec::new();
+        let mut parsed = Vec::new();
+
+        for name in columns.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let column = match name.split_once('.') {
+                None if name == "id" => Column::Id,
+                None if name == "type" => Column::Type,
+                None if is_property_id(name) => Column::Property(name.to_string()),
+                Some(("labels", lang)) if !lang.is_empty() => Column::Label(lang.to_string()),
+                Some(("descriptions", lang)) if !lang.is_empty() => {
+                    Column::Description(lang.to_string())
+                }
+                Some(("aliases", lang)) if !lang.is_empty() => Column::Alias(lang.to_string()),
+                Some(("sitelinks", site)) if !site.is_empty() => Column::Sitelink(site.to_string()),
+                _ => {
+                    return Err(FilterError::Parse(format!(
+                        "Invalid column '{}'. Valid columns: id, type, labels.<lang>, descriptions.<lang>, aliases.<lang>, sitelinks.<site>, P<number>",
+                        name
+                    )));
+                }
+            };
+            names.push(name.to_string());
+            parsed.push(column);
+        }
+
+        if parsed.is_empty() {
+            return Err(FilterError::Parse(
+                "--columns must not be empty".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            names,
+            columns: parsed,
+            value_separator: value_separator.to_string(),
+        })
+    }
+
+    /// Build a row from a (filtered) JSON entity
+    pub fn json_row(&self, entity: &Value) -> Vec<String> {
+        self.join_cells(self.json_cells(entity))
+    }
+
+    /// Build a row from a (filtered) RDF entity
+    pub fn rdf_row(&self, entity: &RdfEntity) -> Vec<String> {
+        self.join_cells(self.rdf_cells(entity))
+    }
+
+    /// Join the values of each cell with the value separator
+    fn join_cells(&self, cells: Vec<Vec<String>>) -> Vec<String> {
+        cells
+            .into_iter()
+            .map(|values| values.join(&self.value_separator))
+            .collect()
+    }
+
+    /// Extract the cell values of a (filtered) JSON entity, one list per column
+    pub fn json_cells(&self, entity: &Value) -> Vec<Vec<String>> {
+        let text = |field: &str, key: &str| {
+            entity
+                .get(field)
+                .and_then(|m| m.get(key))
+                .and_then(|v| v.get("value"))
+                .and_then(|v| v.as_str())
+                .map(str::to_string)
+        };
+        let string = |field: &str| {
+            entity
+                .get(field)
+                .and_then(|v| v.as_str())
+                .map(str::to_string)
+        };
+
+        self.columns
+            .iter()
+            .map(|column| match column {
+                Column::Id => string("id").into_iter().collect(),
+                Column::Type => string("type").into_iter().collect(),
+                Column::Label(lang) => text("labels", lang).into_iter().collect(),
+                Column::Description(lang) => text("descriptions", lang).into_iter().collect(),
+                Column::Alias(lang) => {
+                    let values = entity
+                        .get("aliases")
+                        .and_then(|a| a.get(lang))
+                        .and_then(|a| a.as_array())
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|a| a.get("value").and_then(|v| v.as_str()))
+                        .map(str::to_string);
+                    dedup(values)
+                }
+                Column::Sitelink(site) => entity
+                    .get("sitelinks")
+                    .and_then(|s| s.get(site))
+                    .and_then(|s| s.get("title"))
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string)
+                    .into_iter()
+                    .collect(),
+                Column::Property(prop) => {
+                    let values = entity
+                        .get("claims")
+                        .and_then(|c| c.get(prop))
+                        .and_then(|c| c.as_array())
+                        .into_iter()
+                        .flatten()
+                        .filter(|s| s.get("rank").and_then(|r| r.as_str()) != Some("deprecated"))
+                        .filter_map(|s| s.get("mainsnak")?.get("datavalue"))
+                        .filter_map(json_datavalue_text);
+                    dedup(values)
+                }
+            })
+            .collect()
+    }
+
+    /// Extract the cell values of a (filtered) RDF entity, one list per column
+    ///
+    /// Sitelinks are not part of the entity's triples in RDF dumps, so those
+    /// columns stay empty.
+    pub fn rdf_cells(&self, entity: &RdfEntity) -> Vec<Vec<String>> {
+        self.columns
+            .iter()
+            .map(|column| match column {
+                Column::Id => vec![entity.id.clone()],
+                Column::Type => entity.entity_type.clone().into_iter().collect(),
+                Column::Label(lang) => entity.labels.get(lang).cloned().into_iter().collect(),
+                Column::Description(lang) => {
+                    entity.descriptions.get(lang).cloned().into_iter().collect()
+                }
+                Column::Alias(lang) => {
+                    dedup(entity.aliases.get(lang).into_iter().flatten().cloned())
+                }
+                Column::Sitelink(site) => entity
+                    .sitelinks
+                    .get(site)
+                    .filter(|sitelink| sitelink.written)
+                    .map(|sitelink| sitelink.title.clone())
+                    .into_iter()
+                    .collect(),
+                Column::Property(prop) => {
+                    let predicate = format!("<http://www.wikidata.org/prop/direct/{}>", prop);
+                    let values = entity
+                        .triples
+                        .iter()
+                        .filter(|line| line.contains(&predicate))
+                        .filter_map(|line| NTriple::parse(line))
+                        .filter(|triple| triple.predicate == predicate)
+                        .map(|triple| rdf_object_text(&triple.object));
+                    dedup(values)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Collect cell values in order, dropping duplicates
+fn dedup<I: Iterator<Item = String>>(values: I) -> Vec<String> {
+    let mut seen: Vec<String> = Vec::new();
+    for value in values {
+        if !seen.contains(&value) {
+            seen.push(value);
+        }
+    }
+    seen
+}
+
+/// Render a JSON snak datavalue as a plain cell value
+///
+/// Values are rendered like their RDF lexical forms so that both input
+/// formats give the same table.
+pub fn json_datavalue_text(datavalue: &Value) -> Option<String> {
+    let value = datavalue.get("value")?;
+    if let Some(s) = value.as_str() {
+        return Some(s.to_string());
+    }
+
+    match datavalue.get("type").and_then(|t| t.as_str()) {
+        Some("wikibase-entityid") => value.get("id")?.as_str().map(str::to_string),
+        Some("time") => value
+            .get("time")?
+            .as_str()
+            .map(|t| t.trim_start_matches('+').to_string()),
+        Some("quantity") => value
+            .get("amount")?
+            .as_str()
+            .map(|a| a.trim_start_matches('+').to_string()),
+        Some("monolingualtext") => value.get("text")?.as_str().map(str::to_string),
+        Some("globecoordinate") => {
+            let lat = value.get("latitude")?.as_f64()?;
+            let lon = value.get("longitude")?.as_f64()?;
+            Some(format!("Point({} {})", lon, lat))
+        }
+        _ => Some(value.to_string()),
+    }
+}
+
+/// Render an N-Triples object term as a plain cell value
+fn rdf_object_text(object: &str) -> String {
+    if let Some(rest) = object.strip_prefix('"') {
+        // Text of a literal, without language tag or datatype
+        let lexical = match rest.rfind('"') {
+            Some(end) => &rest[..end],
+            None => rest,
+        };
+        return ntriples::unescape_literal(lexical).into_owned();
+    }
+
+    let iri = object.trim_start_matches('<').trim_end_matches('>');
+    match iri.strip_prefix("http://www.wikidata.org/entity/") {
+        Some(id) => id.to_string(),
+        None => iri.to_string(),
+    }
+}
+
+/// Format one CSV/TSV record (without trailing newline)
+///
+/// CSV fields are quoted as in RFC 4180. TSV has no quoting, so tabs and
+/// line breaks inside fields are replaced by spaces.
+pub fn format_record(fields: &[String], format: OutputFormat) -> String {
+    if format == OutputFormat::Tsv {
+        return fields
+            .iter()
+            .map(|f| f.replace(['\t', '\n', '\r'], " "))
+            .collect::<Vec<_>>()
+            .join("\t");
+    }
+
+    fields
+        .iter()
+        .map(|f| {
+            if f.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", f.replace('"', "\"\""))
+            } else {
+                f.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Write the header row with the column names
+pub fn write_header<W: Write>(
+    output: &mut W,
+    spec: &TableSpec,
+    format: OutputFormat,
+) -> std::io::Result<()> {
+    writeln!(output, "{}", format_record(&spec.names, format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_columns() {
+        let spec = TableSpec::parse("id, labels.en,P31,sitelinks.dewiki", ";").unwrap();
+        assert_eq!(
+            spec.columns,
+            vec![
+                Column::Id,
+                Column::Label("en".to_string()),
+                Column::Property("P31".to_string()),
+                Column::Sitelink("dewiki".to_string()),
+            ]
+        );
+        assert_eq!(
+            spec.names,
+            vec!["id", "labels.en", "P31", "sitelinks.dewiki"]
+        );
+
+        assert!(TableSpec::parse("id,claims", "|").is_err());
+        assert!(TableSpec::parse("labels.", "|").is_err());
+        assert!(TableSpec::parse("", "|").is_err());
+    }
+
+    #[test]
+    fn test_json_row() {
+        let entity: Value = serde_json::from_str(
+            r#"{"id":"Q64","type":"item",
+                "labels":{"en":{"language":"en","value":"Berlin"}},
+                "aliases":{"en":[{"language":"en","value":"Berlin, Germany"},{"language":"en","value":"DE-BE"}]},
+                "sitelinks":{"dewiki":{"site":"dewiki","title":"Berlin"}},
+                "claims":{
+                    "P31":[
+                        {"mainsnak":{"datavalue":{"value":{"entity-type":"item","id":"Q515"},"type":"wikibase-entityid"}},"rank":"normal"},
+                        {"mainsnak":{"datavalue":{"value":{"entity-type":"item","id":"Q1637706"},"type":"wikibase-entityid"}},"rank":"preferred"},
+                        {"mainsnak":{"datavalue":{"value":{"entity-type":"item","id":"Q5"},"type":"wikibase-entityid"}},"rank":"deprecated"}
+                    ],
+                    "P625":[{"mainsnak":{"datavalue":{"value":{"latitude":52.52,"longitude":13.405},"type":"globecoordinate"}},"rank":"normal"}],
+                    "P1082":[{"mainsnak":{"datavalue":{"value":{"amount":"+3677472","unit":"1"},"type":"quantity"}},"rank":"normal"}]
+                }}"#,
+        )
+        .unwrap();
+
+        let spec = TableSpec::parse(
+            "id,type,labels.en,descriptions.en,aliases.en,sitelinks.dewiki,P31,P625,P1082",
+            "|",
+        )
+        .unwrap();
+        assert_eq!(
+            spec.json_row(&entity),
+            vec![
+                "Q64",
+                "item",
+                "Berlin",
+                "",
+                "Berlin, Germany|DE-BE",
+                "Berlin",
+                "Q515|Q1637706",
+                "Point(13.405 52.52)",
+                "3677472",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rdf_row() {
+        let mut entity = RdfEntity::new("Q64");
+        entity.labels.insert("en".to_string(), "Berlin".to_string());
+        entity.triples = vec![
+            "<http://www.wikidata.org/entity/Q64> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q515> .".to_string(),
+            "<http://www.wikidata.org/entity/Q64> <http://www.wikidata.org/prop/direct/P625> \"Point(13.405 52.52)\"^^<http://www.opengis.net/ont/geosparql#wktLiteral> .".to_string(),
+            "<http://www.wikidata.org/entity/Q64> <http://www.wikidata.org/prop/direct/P3100> <http://www.wikidata.org/entity/Q1> .".to_string(),
+            "<http://www.wikidata.org/entity/Q64> <http://www.wikidata.org/prop/direct/P1449> \"\\\"Spree-Athen\\\"\\nBerlin\\u00E9\"@de .".to_string(),
+        ];
+
+        let spec = TableSpec::parse("id,labels.en,P31,P625,P310,P1449", "|").unwrap();
+        assert_eq!(
+            spec.rdf_row(&entity),
+            vec![
+                "Q64",
+                "Berlin",
+                "Q515",
+                "Point(13.405 52.52)",
+                "",
+                "\"Spree-Athen\"\nBerlin\u{e9}"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_record() {
+        let fields = vec![
+            "Q64".to_string(),
+            "Berlin, Germany".to_string(),
+            "say \"hi\"".to_string(),
+            "a\tb".to_string(),
+        ];
+        assert_eq!(
+            format_record(&fields, OutputFormat::Csv),
+            "Q64,\"Berlin, Germany\",\"say \"\"hi\"\"\",a\tb"
+        );
+        assert_eq!(
+            format_record(&fields, OutputFormat::Tsv),
+            "Q64\tBerlin, Germany\tsay \"hi\"\ta b"
+        );
+    }
+}
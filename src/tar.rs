@@ -0,0 +1,308 @@
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+
+use crate::compression::{decompressing_reader, strip_compression_suffix};
+
+/// Size of tar headers and of the blocks member data is padded to
+const BLOCK_SIZE: u64 = 512;
+
+/// Whether `path` names a tar archive, optionally compressed (dump.tar, dump.tar.gz)
+pub fn is_tar(path: &str) -> bool {
+    strip_compression_suffix(&path.to_lowercase()).ends_with(".tar")
+}
+
+/// Whether a member holds dump data, judged by its name; checksums, READMEs
+/// and the like are skipped
+fn is_dump_member(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    let stem = strip_compression_suffix(&lower);
+    let file_name = stem.rsplit('/').next().unwrap_or(stem);
+    !file_name.starts_with('.')
+        && [".nt", ".ttl", ".json", ".ndjson"]
+            .iter()
+            .any(|ext| file_name.ends_with(ext))
+}
+
+/// The archive stream and how much of the current member is left in it
+struct Archive<R> {
+    inner: R,
+    remaining: u64,
+}
+
+/// Data of the current member, shared with its decompressor
+struct MemberData<R>(Arc<Mutex<Archive<R>>>);
+
+impl<R: Read> Read for MemberData<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut archive = self.0.lock().expect("tar archive poisoned");
+        if archive.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min(archive.remaining as usize);
+        let read = archive.inner.read(&mut buf[..len])?;
+        if read == 0 {
+            return Err(truncated());
+        }
+        archive.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+/// Streams the dump members of a tar archive one after another, each
+/// decompressed according to its own extension, without unpacking to disk
+pub struct TarReader<R> {
+    archive: Arc<Mutex<Archive<R>>>,
+    member: Option<Box<dyn Read + Send>>,
+    /// Size of the current member's data
+    member_size: u64,
+    /// Name from a GNU long name or pax header, for the next member
+    long_name: Option<String>,
+    finished: bool,
+}
+
+impl<R: Read + Send + 'static> TarReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            archive: Arc::new(Mutex::new(Archive {
+                inner,
+                remaining: 0,
+            })),
+            member: None,
+            member_size: 0,
+            long_name: None,
+            finished: false,
+        }
+    }
+
+    /// Skip the rest of the current member and its padding
+    fn skip_member(&mut self, size: u64) -> io::Result<()> {
+        let mut archive = self.archive.lock().expect("tar archive poisoned");
+        let padding = (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE;
+        let skip = archive.remaining + padding;
+        let skipped = io::copy(&mut (&mut archive.inner).take(skip), &mut io::sink())?;
+        if skipped < skip {
+            return Err(truncated());
+        }
+        archive.remaining = 0;
+        Ok(())
+    }
+
+    /// Read the data of a metadata member (long names, pax headers)
+    fn read_data(&mut self, size: u64) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        MemberData(self.archive.clone())
+            .take(size)
+            .read_to_end(&mut data)?;
+        self.skip_member(size)?;
+        Ok(data)
+    }
+
+    /// Move to the next dump member; false at the end of the archive
+    fn next_member(&mut self) -> io::Result<bool> {
+        loop {
+            let mut header = [0u8; BLOCK_SIZE as usize];
+            {
+                let mut archive = self.archive.lock().expect("tar archive poisoned");
+                let read = read_full(&mut archive.inner, &mut header)?;
+                // A missing or zero end-of-archive block both end the archive
+                if read == 0 || header.iter().all(|&b| b == 0) {
+                    return Ok(false);
+                }
+                if read < header.len() {
+                    return Err(truncated());
+                }
+            }
+            let size = parse_size(&header[124..136])?;
+            self.archive.lock().expect("tar archive poisoned").remaining = size;
+            match header[156] {
+                // GNU long name of the next member
+                b'L' => {
+                    let name = self.read_data(size)?;
+                    self.long_name = Some(c_string(&name));
+                }
+                // pax extended header, possibly with the path of the next member
+                b'x' => {
+                    let records = self.read_data(size)?;
+                    if let Some(path) = pax_path(&records) {
+                        self.long_name = Some(path);
+                    }
+                }
+                b'0' | b'\0' | b'7' => {
+                    let name = self
+                        .long_name
+                        .take()
+                        .unwrap_or_else(|| header_name(&header));
+                    if is_dump_member(&name) {
+                        let data = MemberData(self.archive.clone());
+                        self.member = Some(decompressing_reader(data, &name));
+                        self.member_size = size;
+                        return Ok(true);
+                    }
+                    self.skip_member(size)?;
+                }
+                // Directories, links, global pax headers
+                _ => {
+                    self.long_name = None;
+                    self.skip_member(size)?;
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read + Send + 'static> Read for TarReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.finished {
+            if let Some(member) = &mut self.member {
+                let read = member.read(buf)?;
+                if read > 0 || buf.is_empty() {
+                    return Ok(read);
+                }
+                self.member = None;
+                // Decompressors may stop before the end of the member data
+                self.skip_member(self.member_size)?;
+            }
+            if !self.next_member()? {
+                self.finished = true;
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Read until `buf` is full or the input ends
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Member size: octal digits, or base-256 for members of 8 GiB and more
+fn parse_size(field: &[u8]) -> io::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold(u64::from(field[0] & 0x7f), |size, &b| {
+                size << 8 | u64::from(b)
+            }));
+    }
+    let digits = c_string(field);
+    let digits = digits.trim();
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid tar member size '{}'", digits),
+        )
+    })
+}
+
+/// Path of a member from its header, with the ustar prefix
+fn header_name(header: &[u8]) -> String {
+    let name = c_string(&header[..100]);
+    // GNU headers ("ustar  ") keep other fields where ustar has the prefix
+    if &header[257..263] == b"ustar\0" {
+        let prefix = c_string(&header[345..500]);
+        if !prefix.is_empty() {
+            return format!("{}/{}", prefix, name);
+        }
+    }
+    name
+}
+
+/// The `path` record of a pax extended header ("LEN path=VALUE\n")
+fn pax_path(records: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(records).lines().find_map(|record| {
+        let (_, keyword_value) = record.split_once(' ')?;
+        keyword_value.strip_prefix("path=").map(str::to_string)
+    })
+}
+
+fn c_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated tar archive")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Append a ustar member to `archive`
+    fn add_member(archive: &mut Vec<u8>, name: &str, kind: u8, data: &[u8]) {
+        let mut header = [0u8; BLOCK_SIZE as usize];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        archive.extend_from_slice(&header);
+        archive.extend_from_slice(data);
+        archive.resize(archive.len().div_ceil(512) * 512, 0);
+    }
+
+    #[test]
+    fn test_members_are_streamed_in_sequence() {
+        let mut gzipped = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gzipped.write_all(b"{\"id\":\"Q2\"}\n").unwrap();
+        let gzipped = gzipped.finish().unwrap();
+
+        let mut archive = Vec::new();
+        add_member(&mut archive, "dump/", b'5', b"");
+        add_member(&mut archive, "dump/part-0.json", b'0', b"{\"id\":\"Q1\"}\n");
+        add_member(
+            &mut archive,
+            "dump/md5sums.txt",
+            b'0',
+            b"abc  part-0.json\n",
+        );
+        add_member(&mut archive, "dump/part-1.json.gz", b'0', &gzipped);
+        // A GNU long name applies to the member after it
+        let long_name = format!("dump/{}.json", "x".repeat(120));
+        add_member(&mut archive, "././@LongLink", b'L', long_name.as_bytes());
+        add_member(&mut archive, "truncated-name", b'0', b"{\"id\":\"Q3\"}\n");
+        archive.extend_from_slice(&[0u8; 1024]);
+
+        let mut output = String::new();
+        TarReader::new(io::Cursor::new(archive.clone()))
+            .read_to_string(&mut output)
+            .unwrap();
+        assert_eq!(
+            output,
+            "{\"id\":\"Q1\"}\n{\"id\":\"Q2\"}\n{\"id\":\"Q3\"}\n"
+        );
+
+        // Cut inside a member
+        let mut output = String::new();
+        assert!(TarReader::new(io::Cursor::new(archive[..700].to_vec()))
+            .read_to_string(&mut output)
+            .is_err());
+    }
+
+    #[test]
+    fn test_tar_names() {
+        assert!(is_tar("latest-all.json.tar"));
+        assert!(is_tar("dumps.TAR.gz"));
+        assert!(!is_tar("latest-all.json.gz"));
+        assert!(is_dump_member("a/b/part-1.nt.bz2"));
+        assert!(!is_dump_member("a/b/SHA1SUMS"));
+        assert!(!is_dump_member("a/._part-1.json"));
+        assert_eq!(
+            pax_path(b"30 mtime=1700000000.0\n22 path=a/b.json\n"),
+            Some("a/b.json".to_string())
+        );
+        assert_eq!(parse_size(b"00000001750\0").unwrap(), 1000);
+    }
+}
@@ -0,0 +1,209 @@
+use regex::Regex;
+
+use crate::entity::ClaimLookup;
+use crate::filter::MUL_LANGUAGE;
+use crate::FilterError;
+
+/// Tests of entity labels and descriptions (--label-contains, --label-regex,
+/// --description-regex, --require-label), each passed by some term in a
+/// selected language
+#[derive(Debug, Clone, Default)]
+pub struct TermFilter {
+    /// Text some label contains
+    pub label_contains: Option<String>,
+    /// Regular expression some label matches
+    pub label_regex: Option<Regex>,
+    /// Regular expression some description matches
+    pub description_regex: Option<Regex>,
+    /// Languages the entity needs a label in (--require-label)
+    pub required_labels: Vec<String>,
+    /// Whether a label is needed in all of `required_labels`, not just one
+    pub all_labels: bool,
+}
+
+impl TermFilter {
+    /// The filter of the options given, `None` without any; `mode` of
+    /// --require-label is `any` or `all`
+    pub fn new(
+        label_contains: Option<&str>,
+        label_regex: Option<&str>,
+        description_regex: Option<&str>,
+        required_labels: Option<&str>,
+        mode: &str,
+    ) -> Result<Option<Self>, FilterError> {
+        let all_labels = match mode {
+            "any" => false,
+            "all" => true,
+            _ => {
+                return Err(FilterError::Parse(format!(
+                    "Invalid --require-label-mode '{}'. Valid: any, all",
+                    mode
+                )))
+            }
+        };
+        let required_labels: Vec<String> = required_labels
+            .into_iter()
+            .flat_map(|list| list.split(','))
+            .map(str::trim)
+            .filter(|lang| !lang.is_empty())
+            .map(str::to_string)
+            .collect();
+        let filter = TermFilter {
+            label_contains: label_contains.map(str::to_string),
+            label_regex: label_regex.map(parse_regex).transpose()?,
+            description_regex: description_regex.map(parse_regex).transpose()?,
+            required_labels,
+            all_labels,
+        };
+        let any = filter.label_contains.is_some()
+            || filter.label_regex.is_some()
+            || filter.description_regex.is_some()
+            || !filter.required_labels.is_empty();
+        Ok(any.then_some(filter))
+    }
+
+    /// The first failed test, if any, given which languages are selected and
+    /// whether a `mul` label counts as one in every required language
+    pub fn rejection<C: ClaimLookup + ?Sized>(
+        &self,
+        entity: &C,
+        selected: impl Fn(&str) -> bool,
+        include_mul: bool,
+    ) -> Option<String> {
+        let labels: Vec<(&str, &str)> = entity
+            .labels()
+            .into_iter()
+            .filter(|(lang, _)| selected(lang))
+            .collect();
+        if !self.required_labels.is_empty() {
+            let mul = include_mul && labels.iter().any(|(lang, _)| *lang == MUL_LANGUAGE);
+            let mut missing = self
+                .required_labels
+                .iter()
+                .filter(|required| !mul && !labels.iter().any(|(lang, _)| lang == required));
+            let failed = if self.all_labels {
+                missing.next().is_some()
+            } else {
+                missing.count() == self.required_labels.len()
+            };
+            if failed {
+                return Some(format!("no label in {}", self.label_languages()));
+            }
+        }
+        let labels: Vec<&str> = labels.into_iter().map(|(_, text)| text).collect();
+        if let Some(ref text) = self.label_contains {
+            if !labels.iter().any(|label| label.contains(text.as_str())) {
+                return Some(format!("no label contains \"{}\"", text));
+            }
+        }
+        if let Some(ref regex) = self.label_regex {
+            if !labels.iter().any(|label| regex.is_match(label)) {
+                return Some(format!("no label matches \"{}\"", regex));
+            }
+        }
+        if let Some(ref regex) = self.description_regex {
+            let matched = entity
+                .descriptions()
+                .into_iter()
+                .any(|(lang, text)| selected(lang) && regex.is_match(text));
+            if !matched {
+                return Some(format!("no description matches \"{}\"", regex));
+            }
+        }
+        None
+    }
+
+    /// The required label languages, joined by `|` for any or `&` for all
+    fn label_languages(&self) -> String {
+        let separator = if self.all_labels { " & " } else { " | " };
+        self.required_labels.join(separator)
+    }
+}
+
+impl std::fmt::Display for TermFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut tests = Vec::new();
+        if !self.required_labels.is_empty() {
+            tests.push(format!("label in {}", self.label_languages()));
+        }
+        if let Some(ref text) = self.label_contains {
+            tests.push(format!("label contains \"{}\"", text));
+        }
+        if let Some(ref regex) = self.label_regex {
+            tests.push(format!("label ~ \"{}\"", regex));
+        }
+        if let Some(ref regex) = self.description_regex {
+            tests.push(format!("description ~ \"{}\"", regex));
+        }
+        f.write_str(&tests.join(", "))
+    }
+}
+
+fn parse_regex(source: &str) -> Result<Regex, FilterError> {
+    Regex::new(source).map_err(|e| {
+        FilterError::Parse(format!("Invalid regular expression \"{}\": {}", source, e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::Entity;
+
+    #[test]
+    fn test_term_filter() {
+        let entity: Entity = serde_json::from_str(
+            r#"{"id":"Q64","labels":{"en":{"language":"en","value":"Berlin"},"fr":{"language":"fr","value":"Berlin (ville)"}},
+                "descriptions":{"en":{"language":"en","value":"capital and largest city of Germany"}}}"#,
+        )
+        .unwrap();
+        let rejection = |contains, label, description, selected: fn(&str) -> bool| {
+            TermFilter::new(contains, label, description, None, "any")
+                .unwrap()
+                .unwrap()
+                .rejection(&entity, selected, false)
+        };
+        let all = |_: &str| true;
+        let english = |lang: &str| lang == "en";
+        assert_eq!(rejection(Some("Berl"), None, None, all), None);
+        assert_eq!(rejection(None, Some(r"\(ville\)$"), None, all), None);
+        assert_eq!(
+            rejection(None, Some(r"\(ville\)$"), None, english),
+            Some("no label matches \"\\(ville\\)$\"".to_string())
+        );
+        assert_eq!(rejection(None, None, Some("^capital"), english), None);
+        assert!(rejection(Some("Berlin"), None, Some("village"), all).is_some());
+        assert!(rejection(Some("berlin"), None, None, all).is_some());
+
+        assert!(TermFilter::new(None, None, None, None, "any")
+            .unwrap()
+            .is_none());
+        assert!(TermFilter::new(None, Some("("), None, None, "any").is_err());
+    }
+
+    #[test]
+    fn test_required_labels() {
+        let entity: Entity = serde_json::from_str(
+            r#"{"id":"Q64","labels":{"en":{"language":"en","value":"Berlin"},"fr":{"language":"fr","value":"Berlin"}}}"#,
+        )
+        .unwrap();
+        let rejection = |languages, mode| {
+            TermFilter::new(None, None, None, Some(languages), mode)
+                .unwrap()
+                .unwrap()
+                .rejection(&entity, |lang| lang != "fr", false)
+        };
+        assert_eq!(rejection("en,de", "any"), None);
+        assert_eq!(
+            rejection("en,de", "all"),
+            Some("no label in en & de".to_string())
+        );
+        assert_eq!(rejection("en", "all"), None);
+        // Labels outside the selected languages do not count
+        assert_eq!(rejection("fr", "any"), Some("no label in fr".to_string()));
+        assert!(TermFilter::new(None, None, None, Some("en"), "most").is_err());
+        assert!(TermFilter::new(None, None, None, Some(" , "), "any")
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,57 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A new, empty directory for the files of a test, removed with its contents
+/// when dropped, also when the test panics
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create a directory named after `prefix`, unique across the threads
+    /// and processes running tests
+    pub fn new(prefix: &str) -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        loop {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.subsec_nanos());
+            let path = std::env::temp_dir().join(format!(
+                "ww-{}-{}-{}-{}",
+                prefix,
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed),
+                nanos
+            ));
+            // Creating fails for a taken name, even one of another process
+            match fs::create_dir(&path) {
+                Ok(()) => return TempDir { path },
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => panic!("Cannot create {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path of a file in the directory
+    pub fn join(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.path.join(name)
+    }
+
+    /// Path of a file in the directory as a string, as options take it
+    pub fn file(&self, name: impl AsRef<Path>) -> String {
+        self.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
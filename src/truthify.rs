@@ -0,0 +1,469 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::statement::{SnakValue, StatementView};
+
+const STATEMENT_NODE_PREFIX: &str = "<http://www.wikidata.org/entity/statement/";
+const REFERENCE_NODE_PREFIX: &str = "<http://www.wikidata.org/reference/";
+const VALUE_NODE_PREFIX: &str = "<http://www.wikidata.org/value/";
+const PROP_NAMESPACE_PREFIX: &str = "<http://www.wikidata.org/prop/";
+const PROP_STATEMENT_PREFIX: &str = "<http://www.wikidata.org/prop/statement/";
+const PROP_QUALIFIER_PREFIX: &str = "<http://www.wikidata.org/prop/qualifier/";
+const PROP_REFERENCE_PREFIX: &str = "<http://www.wikidata.org/prop/reference/";
+const DERIVED_FROM_PREDICATE: &str = "<http://www.w3.org/ns/prov#wasDerivedFrom>";
+const RANK_PREDICATE: &str = "<http://wikiba.se/ontology#rank>";
+const RDF_TYPE: &str = "<http://www.w3.org/1999/02/22-rdf-syntax-ns#type>";
+const PROP_NOVALUE_PREFIX: &str = "<http://www.wikidata.org/prop/novalue/";
+
+/// Statement rank, ordered from worst to best
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rank {
+    Deprecated,
+    Normal,
+    Preferred,
+}
+
+impl Rank {
+    /// Parse a wikibase rank IRI, e.g. <http://wikiba.se/ontology#PreferredRank>
+    pub fn from_iri(iri: &str) -> Option<Self> {
+        if iri.contains("#PreferredRank") {
+            Some(Rank::Preferred)
+        } else if iri.contains("#NormalRank") {
+            Some(Rank::Normal)
+        } else if iri.contains("#DeprecatedRank") {
+            Some(Rank::Deprecated)
+        } else {
+            None
+        }
+    }
+
+    /// Parse a rank as named in JSON dumps, e.g. `preferred`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "preferred" => Some(Rank::Preferred),
+            "normal" => Some(Rank::Normal),
+            "deprecated" => Some(Rank::Deprecated),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rank::Preferred => "preferred",
+            Rank::Normal => "normal",
+            Rank::Deprecated => "deprecated",
+        }
+    }
+}
+
+/// Statement node collected from a full dump
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub property: Option<String>,
+    pub rank: Rank,
+    /// Objects of ps: triples
+    pub values: Vec<String>,
+    /// Objects of psn: (value-normalized) triples
+    pub normalized: Vec<String>,
+    /// Property and object of pq: triples
+    pub qualifiers: Vec<(String, String)>,
+    /// Reference nodes of prov:wasDerivedFrom triples
+    pub references: Vec<String>,
+    /// Property and object of the pr: triples of the references, as far as
+    /// their nodes appear within the entity
+    pub reference_snaks: Vec<(String, String)>,
+}
+
+impl StatementView for Statement {
+    fn value(&self) -> SnakValue<'_> {
+        self.values
+            .first()
+            .map_or(SnakValue::NoValue, |value| SnakValue::of_rdf(value))
+    }
+
+    fn qualifier_values(&self, property: &str) -> Vec<SnakValue<'_>> {
+        self.qualifiers
+            .iter()
+            .filter(|(p, _)| p == property)
+            .map(|(_, object)| SnakValue::of_rdf(object))
+            .collect()
+    }
+
+    fn rank(&self) -> Rank {
+        self.rank
+    }
+
+    fn is_referenced(&self) -> bool {
+        !self.references.is_empty()
+    }
+
+    fn reference_values(&self, property: &str) -> Vec<SnakValue<'_>> {
+        self.reference_snaks
+            .iter()
+            .filter(|(p, _)| p == property)
+            .map(|(_, object)| SnakValue::of_rdf(object))
+            .collect()
+    }
+}
+
+/// Collapses full-dump statement nodes of one entity into truthy `wdt:` triples
+///
+/// Only statements of the best non-deprecated rank per property are kept
+/// (preferred over normal), matching the semantics of the truthy dumps.
+#[derive(Debug, Default)]
+pub struct Truthifier {
+    statements: Vec<Statement>,
+    index: HashMap<String, usize>,
+    /// pr: snaks by reference node
+    reference_snaks: HashMap<String, Vec<(String, String)>>,
+}
+
+/// Split an N-Triples line into subject, predicate and object
+fn split_triple(line: &str) -> Option<(&str, &str, &str)> {
+    let (subject, rest) = line.trim().split_once(char::is_whitespace)?;
+    let (predicate, rest) = rest.trim_start().split_once(char::is_whitespace)?;
+    let object = rest.trim();
+    let object = object.strip_suffix('.').unwrap_or(object).trim_end();
+    Some((subject, predicate, object))
+}
+
+impl Truthifier {
+    /// Consume a line whose subject is a statement, reference or value node.
+    /// Returns true if the line belongs to such a node and must not be output.
+    pub fn handle_node_line(&mut self, line: &str) -> bool {
+        if line.starts_with(VALUE_NODE_PREFIX) {
+            return true;
+        }
+        if line.starts_with(REFERENCE_NODE_PREFIX) {
+            // Only simple values; prv: value nodes are not followed
+            if let Some((subject, predicate, object)) = split_triple(line) {
+                let prop = predicate
+                    .strip_prefix(PROP_REFERENCE_PREFIX)
+                    .and_then(|p| p.strip_suffix('>'))
+                    .filter(|p| !p.contains('/'));
+                if let Some(prop) = prop {
+                    self.reference_snaks
+                        .entry(subject.to_string())
+                        .or_default()
+                        .push((prop.to_string(), object.to_string()));
+                }
+            }
+            return true;
+        }
+        if !line.starts_with(STATEMENT_NODE_PREFIX) {
+            return false;
+        }
+
+        let (subject, predicate, object) = match split_triple(line) {
+            Some(parts) => parts,
+            None => return true,
+        };
+
+        let idx = match self.index.get(subject) {
+            Some(&idx) => idx,
+            None => {
+                self.statements.push(Statement {
+                    property: None,
+                    rank: Rank::Normal,
+                    values: Vec::new(),
+                    normalized: Vec::new(),
+                    qualifiers: Vec::new(),
+                    references: Vec::new(),
+                    reference_snaks: Vec::new(),
+                });
+                self.index
+                    .insert(subject.to_string(), self.statements.len() - 1);
+                self.statements.len() - 1
+            }
+        };
+        let statement = &mut self.statements[idx];
+
+        if predicate == DERIVED_FROM_PREDICATE {
+            statement.references.push(object.to_string());
+        } else if predicate == RANK_PREDICATE {
+            if let Some(rank) = Rank::from_iri(object) {
+                statement.rank = rank;
+            }
+        } else if predicate == RDF_TYPE {
+            // No value statements are typed with the wdno: class of their property
+            if let Some(prop) = object
+                .strip_prefix(PROP_NOVALUE_PREFIX)
+                .and_then(|p| p.strip_suffix('>'))
+            {
+                statement.property = Some(prop.to_string());
+            }
+        } else if let Some(local) = predicate
+            .strip_prefix(PROP_STATEMENT_PREFIX)
+            .and_then(|p| p.strip_suffix('>'))
+        {
+            if let Some(prop) = local.strip_prefix("value-normalized/") {
+                statement.property = Some(prop.to_string());
+                statement.normalized.push(object.to_string());
+            } else if !local.starts_with("value/") {
+                statement.property = Some(local.to_string());
+                statement.values.push(object.to_string());
+            }
+        } else if let Some(prop) = predicate
+            .strip_prefix(PROP_QUALIFIER_PREFIX)
+            .and_then(|p| p.strip_suffix('>'))
+        {
+            // Only simple values; pqv: value nodes are not followed
+            if !prop.contains('/') {
+                statement
+                    .qualifiers
+                    .push((prop.to_string(), object.to_string()));
+            }
+        }
+
+        true
+    }
+
+    /// Check if an entity line is replaced in truthify mode: statement links
+    /// (p:) and existing direct claims (wdt:/wdtn:), which are regenerated
+    pub fn is_replaced_entity_line(line: &str) -> bool {
+        split_triple(line)
+            .is_some_and(|(_, predicate, _)| predicate.starts_with(PROP_NAMESPACE_PREFIX))
+    }
+
+    /// Generate the truthy direct triples for the collected statements and
+    /// reset, handing over the statements for statement-level claim filters
+    pub fn finish(&mut self, entity_id: &str) -> (Vec<String>, Vec<Statement>) {
+        let mut statements = std::mem::take(&mut self.statements);
+        self.index.clear();
+        for statement in &mut statements {
+            for reference in &statement.references {
+                if let Some(snaks) = self.reference_snaks.get(reference) {
+                    statement.reference_snaks.extend(snaks.iter().cloned());
+                }
+            }
+        }
+        self.reference_snaks.clear();
+
+        // Best rank per property, in order of first appearance
+        let mut properties: Vec<&str> = Vec::new();
+        let mut best_rank: HashMap<&str, Rank> = HashMap::new();
+        for statement in &statements {
+            let prop = match statement.property.as_deref() {
+                Some(p) => p,
+                None => continue,
+            };
+            if statement.rank == Rank::Deprecated {
+                continue;
+            }
+            match best_rank.get_mut(prop) {
+                Some(best) => *best = (*best).max(statement.rank),
+                None => {
+                    properties.push(prop);
+                    best_rank.insert(prop, statement.rank);
+                }
+            }
+        }
+
+        let subject = format!("<http://www.wikidata.org/entity/{}>", entity_id);
+        let mut seen = HashSet::new();
+        let mut lines = Vec::new();
+        for prop in properties {
+            let best = best_rank[prop];
+            for statement in &statements {
+                if statement.property.as_deref() != Some(prop) || statement.rank != best {
+                    continue;
+                }
+                for value in &statement.values {
+                    let line = format!(
+                        "{} <http://www.wikidata.org/prop/direct/{}> {} .",
+                        subject, prop, value
+                    );
+                    if seen.insert(line.clone()) {
+                        lines.push(line);
+                    }
+                }
+                for value in &statement.normalized {
+                    let line = format!(
+                        "{} <http://www.wikidata.org/prop/direct-normalized/{}> {} .",
+                        subject, prop, value
+                    );
+                    if seen.insert(line.clone()) {
+                        lines.push(line);
+                    }
+                }
+            }
+        }
+        (lines, statements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::ClaimFilter;
+
+    fn statement_lines(id: &str, prop: &str, value: &str, rank: &str) -> Vec<String> {
+        let node = format!("<http://www.wikidata.org/entity/statement/Q42-{}>", id);
+        vec![
+            format!(
+                "{} <http://wikiba.se/ontology#rank> <http://wikiba.se/ontology#{}> .",
+                node, rank
+            ),
+            format!(
+                "{} <http://www.wikidata.org/prop/statement/{}> {} .",
+                node, prop, value
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_preferred_rank_wins() {
+        let mut truthifier = Truthifier::default();
+        let mut lines = statement_lines(
+            "a",
+            "P31",
+            "<http://www.wikidata.org/entity/Q5>",
+            "NormalRank",
+        );
+        lines.extend(statement_lines(
+            "b",
+            "P31",
+            "<http://www.wikidata.org/entity/Q15632617>",
+            "PreferredRank",
+        ));
+        lines.extend(statement_lines(
+            "c",
+            "P569",
+            r#""1952-03-11T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime>"#,
+            "NormalRank",
+        ));
+        for line in &lines {
+            assert!(truthifier.handle_node_line(line));
+        }
+
+        let (direct, _) = truthifier.finish("Q42");
+        assert_eq!(
+            direct,
+            vec![
+                "<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q15632617> .",
+                "<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P569> \"1952-03-11T00:00:00Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .",
+            ]
+        );
+
+        // State is reset for the next entity
+        assert!(truthifier.finish("Q43").0.is_empty());
+    }
+
+    #[test]
+    fn test_deprecated_dropped_and_normalized_values() {
+        let mut truthifier = Truthifier::default();
+        for line in statement_lines(
+            "a",
+            "P31",
+            "<http://www.wikidata.org/entity/Q5>",
+            "DeprecatedRank",
+        ) {
+            truthifier.handle_node_line(&line);
+        }
+        truthifier.handle_node_line(
+            "<http://www.wikidata.org/entity/statement/Q42-b> <http://www.wikidata.org/prop/statement/P214> \"113230702\" .",
+        );
+        truthifier.handle_node_line(
+            "<http://www.wikidata.org/entity/statement/Q42-b> <http://www.wikidata.org/prop/statement/value-normalized/P214> <http://viaf.org/viaf/113230702> .",
+        );
+        // Full value nodes are ignored
+        truthifier.handle_node_line(
+            "<http://www.wikidata.org/entity/statement/Q42-b> <http://www.wikidata.org/prop/statement/value/P214> <http://www.wikidata.org/value/abc> .",
+        );
+
+        let (direct, _) = truthifier.finish("Q42");
+        assert_eq!(direct.len(), 2);
+        assert!(direct[0].contains("prop/direct/P214> \"113230702\""));
+        assert!(direct[1].contains("prop/direct-normalized/P214> <http://viaf.org/viaf/113230702>"));
+    }
+
+    #[test]
+    fn test_statement_qualifiers() {
+        let mut truthifier = Truthifier::default();
+        let mut lines = statement_lines(
+            "a",
+            "P39",
+            "<http://www.wikidata.org/entity/Q11696>",
+            "NormalRank",
+        );
+        lines.push(
+            "<http://www.wikidata.org/entity/statement/Q42-a> <http://www.wikidata.org/prop/qualifier/P580> \"1993-01-20T00:00:00Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .".to_string(),
+        );
+        lines.push(
+            "<http://www.wikidata.org/entity/statement/Q42-a> <http://www.wikidata.org/prop/qualifier/value/P580> <http://www.wikidata.org/value/abc> .".to_string(),
+        );
+        for line in &lines {
+            assert!(truthifier.handle_node_line(line));
+        }
+
+        let (_, statements) = truthifier.finish("Q42");
+        assert_eq!(statements.len(), 1);
+        assert_eq!(statements[0].property.as_deref(), Some("P39"));
+        assert_eq!(statements[0].qualifiers.len(), 1);
+        let filter = crate::claim_parser::parse_claim_filter("P39:Q11696{P580>=1990}").unwrap();
+        let ClaimFilter::Statement(filter) = filter else {
+            panic!("expected a statement filter");
+        };
+        assert!(filter.matches(&statements[0]));
+    }
+
+    #[test]
+    fn test_statement_references() {
+        let mut truthifier = Truthifier::default();
+        let mut lines = statement_lines("a", "P569", "\"1952\"", "NormalRank");
+        lines.extend(statement_lines("b", "P570", "\"2001\"", "NormalRank"));
+        lines.push(
+            "<http://www.wikidata.org/entity/statement/Q42-a> <http://www.w3.org/ns/prov#wasDerivedFrom> <http://www.wikidata.org/reference/abc> .".to_string(),
+        );
+        lines.push(
+            "<http://www.wikidata.org/reference/abc> <http://www.wikidata.org/prop/reference/P248> <http://www.wikidata.org/entity/Q36578> .".to_string(),
+        );
+        for line in &lines {
+            assert!(truthifier.handle_node_line(line));
+        }
+
+        let (_, statements) = truthifier.finish("Q42");
+        assert!(statements[0].is_referenced());
+        assert!(!statements[1].is_referenced());
+        assert_eq!(
+            statements[0].reference_values("P248"),
+            [SnakValue::of_rdf("<http://www.wikidata.org/entity/Q36578>")]
+        );
+        assert!(truthifier.reference_snaks.is_empty());
+    }
+
+    #[test]
+    fn test_novalue_statement() {
+        let mut truthifier = Truthifier::default();
+        for line in [
+            "<http://www.wikidata.org/entity/statement/Q42-a> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://www.wikidata.org/prop/novalue/P40> .",
+            "<http://www.wikidata.org/entity/statement/Q42-a> <http://wikiba.se/ontology#rank> <http://wikiba.se/ontology#NormalRank> .",
+        ] {
+            assert!(truthifier.handle_node_line(line));
+        }
+        let (lines, statements) = truthifier.finish("Q42");
+        assert!(lines.is_empty());
+        assert_eq!(statements[0].property.as_deref(), Some("P40"));
+        assert_eq!(statements[0].value(), SnakValue::NoValue);
+    }
+
+    #[test]
+    fn test_line_classification() {
+        let mut truthifier = Truthifier::default();
+        assert!(truthifier.handle_node_line(
+            "<http://www.wikidata.org/reference/abc> <http://www.wikidata.org/prop/reference/P248> <http://www.wikidata.org/entity/Q1> ."
+        ));
+        assert!(!truthifier.handle_node_line(
+            "<http://www.wikidata.org/entity/Q42> <http://www.w3.org/2000/01/rdf-schema#label> \"Douglas Adams\"@en ."
+        ));
+
+        assert!(Truthifier::is_replaced_entity_line(
+            "<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/P31> <http://www.wikidata.org/entity/statement/Q42-a> ."
+        ));
+        assert!(Truthifier::is_replaced_entity_line(
+            "<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> ."
+        ));
+        assert!(!Truthifier::is_replaced_entity_line(
+            "<http://www.wikidata.org/entity/Q42> <http://schema.org/description> \"author\"@en ."
+        ));
+    }
+}
@@ -0,0 +1,843 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read};
+
+use crate::ntriples::escape_literal;
+
+const RDF_TYPE: &str = "<http://www.w3.org/1999/02/22-rdf-syntax-ns#type>";
+const RDF_FIRST: &str = "<http://www.w3.org/1999/02/22-rdf-syntax-ns#first>";
+const RDF_REST: &str = "<http://www.w3.org/1999/02/22-rdf-syntax-ns#rest>";
+const RDF_NIL: &str = "<http://www.w3.org/1999/02/22-rdf-syntax-ns#nil>";
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// Streaming Turtle to N-Triples converter
+///
+/// Reads Turtle statements from the wrapped reader and yields them as
+/// N-Triples lines, so the result can be fed to the regular RDF processing.
+/// Supports prefix/base directives (both `@prefix` and SPARQL style),
+/// `;`/`,` continuation, the `a` keyword, blank node property lists,
+/// collections, long strings and numeric/boolean literals.
+pub struct TurtleReader<R: BufRead> {
+    input: R,
+    parser: TurtleParser,
+    scanner: StatementScanner,
+    /// Input text of the statement currently being collected
+    statement: String,
+    /// Line number where the current statement started
+    statement_line: u64,
+    line_number: u64,
+    /// Converted N-Triples output not yet handed to the caller
+    pending: Vec<u8>,
+    pending_pos: usize,
+    eof: bool,
+}
+
+impl<R: BufRead> TurtleReader<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            input,
+            parser: TurtleParser::default(),
+            scanner: StatementScanner::default(),
+            statement: String::new(),
+            statement_line: 1,
+            line_number: 0,
+            pending: Vec::new(),
+            pending_pos: 0,
+            eof: false,
+        }
+    }
+
+    /// Read input until at least one statement has been converted or input ends
+    fn fill_pending(&mut self) -> io::Result<()> {
+        self.pending.clear();
+        self.pending_pos = 0;
+
+        while self.pending.is_empty() && !self.eof {
+            // Statements left over from the previous line come first
+            if let Some(end) = self.scanner.scan(&self.statement) {
+                let rest = self.statement.split_off(end);
+                self.convert_statement()?;
+                self.statement = rest;
+                self.statement_line = self.line_number;
+                self.scanner = StatementScanner::default();
+                continue;
+            }
+
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                self.eof = true;
+                if !self.statement.trim().is_empty()
+                    && !self.scanner.only_whitespace_or_comment(&self.statement)
+                {
+                    return Err(self.error("unterminated statement at end of input"));
+                }
+                break;
+            }
+            self.line_number += 1;
+            if self.statement.trim().is_empty() {
+                self.statement.clear();
+                self.scanner = StatementScanner::default();
+                self.statement_line = self.line_number;
+            }
+            self.statement.push_str(&line);
+        }
+
+        Ok(())
+    }
+
+    /// Parse the collected statement and append its triples to the output
+    fn convert_statement(&mut self) -> io::Result<()> {
+        let mut triples = Vec::new();
+        self.parser
+            .parse_statement(&self.statement, &mut triples)
+            .map_err(|e| self.error(&e))?;
+        for triple in triples {
+            self.pending.extend_from_slice(triple.as_bytes());
+            self.pending.push(b'\n');
+        }
+        Ok(())
+    }
+
+    fn error(&self, message: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Turtle parse error in statement starting at line {}: {}",
+                self.statement_line, message
+            ),
+        )
+    }
+}
+
+impl<R: BufRead> Read for TurtleReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending_pos >= self.pending.len() {
+            self.fill_pending()?;
+            if self.pending.is_empty() {
+                return Ok(0);
+            }
+        }
+
+        let available = &self.pending[self.pending_pos..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.pending_pos += n;
+        Ok(n)
+    }
+}
+
+/// Lexical state of the statement scanner
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum ScanState {
+    #[default]
+    Normal,
+    Comment,
+    Iri,
+    /// Inside a string literal: quote character, long string, next char escaped
+    Str(char, bool, bool),
+}
+
+/// Finds the end of a Turtle statement in text that grows line by line
+#[derive(Debug, Default)]
+struct StatementScanner {
+    state: ScanState,
+    /// Byte position up to which the text has been scanned
+    pos: usize,
+    depth: i32,
+}
+
+impl StatementScanner {
+    /// Continue scanning `text`; returns the byte position just after the
+    /// terminating '.' (or after the IRI of a SPARQL-style directive)
+    fn scan(&mut self, text: &str) -> Option<usize> {
+        let bytes = text.as_bytes();
+        while self.pos < bytes.len() {
+            let i = self.pos;
+            let c = bytes[i];
+            self.pos += 1;
+
+            match self.state {
+                ScanState::Comment => {
+                    if c == b'\n' {
+                        self.state = ScanState::Normal;
+                    }
+                }
+                ScanState::Iri => {
+                    if c == b'>' {
+                        self.state = ScanState::Normal;
+                        if self.depth == 0 && is_sparql_directive(text) {
+                            return Some(self.pos);
+                        }
+                    }
+                }
+                ScanState::Str(quote, long, escaped) => {
+                    if escaped {
+                        self.state = ScanState::Str(quote, long, false);
+                    } else if c == b'\\' {
+                        self.state = ScanState::Str(quote, long, true);
+                    } else if c as char == quote {
+                        if !long {
+                            self.state = ScanState::Normal;
+                        } else if bytes.get(i + 1) == Some(&c) && bytes.get(i + 2) == Some(&c) {
+                            self.pos += 2;
+                            self.state = ScanState::Normal;
+                        }
+                    }
+                }
+                ScanState::Normal => match c {
+                    b'#' => self.state = ScanState::Comment,
+                    b'<' => self.state = ScanState::Iri,
+                    b'"' | b'\'' => {
+                        let long = bytes.get(i + 1) == Some(&c) && bytes.get(i + 2) == Some(&c);
+                        if long {
+                            self.pos += 2;
+                        }
+                        self.state = ScanState::Str(c as char, long, false);
+                    }
+                    b'[' | b'(' => self.depth += 1,
+                    b']' | b')' => self.depth -= 1,
+                    b'.' if self.depth == 0 => {
+                        let next = bytes.get(i + 1);
+                        if next.is_none_or(|n| n.is_ascii_whitespace() || *n == b'#') {
+                            return Some(self.pos);
+                        }
+                    }
+                    _ => {}
+                },
+            }
+        }
+        None
+    }
+
+    /// True if the text contains nothing but whitespace and comments
+    fn only_whitespace_or_comment(&self, text: &str) -> bool {
+        text.lines().all(|l| {
+            let t = l.trim();
+            t.is_empty() || t.starts_with('#')
+        })
+    }
+}
+
+/// Check for a SPARQL-style `PREFIX`/`BASE` directive, which has no trailing '.'
+fn is_sparql_directive(text: &str) -> bool {
+    let mut rest = text.trim_start();
+    while rest.starts_with('#') {
+        rest = rest
+            .split_once('\n')
+            .map(|(_, r)| r)
+            .unwrap_or("")
+            .trim_start();
+    }
+    let word: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    word.eq_ignore_ascii_case("prefix") || word.eq_ignore_ascii_case("base")
+}
+
+/// Converts Turtle statements to N-Triples, keeping prefix and base state
+#[derive(Debug, Default)]
+struct TurtleParser {
+    prefixes: HashMap<String, String>,
+    base: String,
+    blank_counter: u64,
+}
+
+impl TurtleParser {
+    /// Parse one complete statement (directive or triples)
+    fn parse_statement(&mut self, text: &str, out: &mut Vec<String>) -> Result<(), String> {
+        let mut cur = Cursor::new(text);
+        cur.skip_ws();
+        if cur.at_end() {
+            return Ok(());
+        }
+
+        if cur.eat_keyword("@prefix") || cur.eat_keyword_ci("prefix") {
+            cur.skip_ws();
+            let prefix = cur.take_while(|c| c != ':' && !c.is_whitespace());
+            if !cur.eat(':') {
+                return Err("expected ':' after prefix name".to_string());
+            }
+            cur.skip_ws();
+            let iri = self.parse_iriref(&mut cur)?;
+            self.prefixes.insert(prefix, iri);
+            return Ok(());
+        }
+
+        if cur.eat_keyword("@base") || cur.eat_keyword_ci("base") {
+            cur.skip_ws();
+            self.base = self.parse_iriref(&mut cur)?;
+            return Ok(());
+        }
+
+        let subject = if cur.peek() == Some('[') {
+            cur.next();
+            let node = self.new_blank_node();
+            cur.skip_ws();
+            if cur.peek() != Some(']') {
+                self.parse_predicate_object_list(&mut cur, &node, out)?;
+            }
+            cur.skip_ws();
+            if !cur.eat(']') {
+                return Err("expected ']'".to_string());
+            }
+            cur.skip_ws();
+            // "[ ... ] ." is a complete statement on its own
+            if cur.peek() == Some('.') {
+                return Ok(());
+            }
+            node
+        } else {
+            self.parse_term(&mut cur, out)?
+        };
+
+        self.parse_predicate_object_list(&mut cur, &subject, out)?;
+        cur.skip_ws();
+        if !cur.eat('.') {
+            return Err(format!("expected '.' but found {:?}", cur.rest_preview()));
+        }
+        Ok(())
+    }
+
+    /// predicate object (, object)* (; predicate object (, object)*)*
+    fn parse_predicate_object_list(
+        &mut self,
+        cur: &mut Cursor,
+        subject: &str,
+        out: &mut Vec<String>,
+    ) -> Result<(), String> {
+        loop {
+            cur.skip_ws();
+            let predicate = if cur.peek() == Some('a') && cur.peek_at(1).is_none_or(is_delimiter) {
+                cur.next();
+                RDF_TYPE.to_string()
+            } else {
+                self.parse_term(cur, out)?
+            };
+
+            loop {
+                cur.skip_ws();
+                let object = self.parse_term(cur, out)?;
+                out.push(format!("{} {} {} .", subject, predicate, object));
+                cur.skip_ws();
+                if !cur.eat(',') {
+                    break;
+                }
+            }
+
+            cur.skip_ws();
+            if !cur.eat(';') {
+                return Ok(());
+            }
+            // Repeated or trailing semicolons are allowed
+            loop {
+                cur.skip_ws();
+                if !cur.eat(';') {
+                    break;
+                }
+            }
+            cur.skip_ws();
+            if matches!(cur.peek(), Some('.') | Some(']') | None) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Parse an IRI, prefixed name, blank node, collection or literal
+    fn parse_term(&mut self, cur: &mut Cursor, out: &mut Vec<String>) -> Result<String, String> {
+        cur.skip_ws();
+        match cur.peek() {
+            Some('<') => Ok(format!("<{}>", self.parse_iriref(cur)?)),
+            Some('"') | Some('\'') => self.parse_literal(cur),
+            Some('[') => {
+                cur.next();
+                let node = self.new_blank_node();
+                cur.skip_ws();
+                if cur.peek() != Some(']') {
+                    self.parse_predicate_object_list(cur, &node, out)?;
+                    cur.skip_ws();
+                }
+                if !cur.eat(']') {
+                    return Err("expected ']'".to_string());
+                }
+                Ok(node)
+            }
+            Some('(') => {
+                cur.next();
+                let mut items = Vec::new();
+                loop {
+                    cur.skip_ws();
+                    if cur.eat(')') {
+                        break;
+                    }
+                    if cur.at_end() {
+                        return Err("unterminated collection".to_string());
+                    }
+                    items.push(self.parse_term(cur, out)?);
+                }
+                if items.is_empty() {
+                    return Ok(RDF_NIL.to_string());
+                }
+                let nodes: Vec<String> = items.iter().map(|_| self.new_blank_node()).collect();
+                for (i, item) in items.iter().enumerate() {
+                    out.push(format!("{} {} {} .", nodes[i], RDF_FIRST, item));
+                    let rest = nodes.get(i + 1).map(String::as_str).unwrap_or(RDF_NIL);
+                    out.push(format!("{} {} {} .", nodes[i], RDF_REST, rest));
+                }
+                Ok(nodes[0].clone())
+            }
+            Some('_') if cur.peek_at(1) == Some(':') => {
+                cur.next();
+                cur.next();
+                let mut label = cur.take_while(|c| !is_delimiter(c));
+                // A trailing '.' terminates the statement
+                while label.ends_with('.') {
+                    label.pop();
+                    cur.pos -= 1;
+                }
+                Ok(format!("_:{}", label))
+            }
+            Some(c) if c.is_ascii_digit() || c == '+' || c == '-' || c == '.' => {
+                self.parse_number(cur)
+            }
+            Some(_) => {
+                let start = cur.pos;
+                if cur.eat_keyword("true") {
+                    return Ok(format!("\"true\"^^<{}boolean>", XSD));
+                }
+                if cur.eat_keyword("false") {
+                    return Ok(format!("\"false\"^^<{}boolean>", XSD));
+                }
+                cur.pos = start;
+                self.parse_prefixed_name(cur)
+            }
+            None => Err("unexpected end of statement".to_string()),
+        }
+    }
+
+    /// Parse `<iri>` and resolve it against the base IRI
+    fn parse_iriref(&self, cur: &mut Cursor) -> Result<String, String> {
+        if !cur.eat('<') {
+            return Err(format!("expected IRI but found {:?}", cur.rest_preview()));
+        }
+        let raw = cur.take_while(|c| c != '>');
+        if !cur.eat('>') {
+            return Err("unterminated IRI".to_string());
+        }
+        let iri = unescape_numeric(&raw)?;
+        Ok(self.resolve(&iri))
+    }
+
+    /// Resolve a (possibly relative) IRI against the base
+    fn resolve(&self, iri: &str) -> String {
+        let has_scheme = iri.find(':').is_some_and(|p| {
+            p > 0
+                && iri[..p]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        });
+        if has_scheme || self.base.is_empty() {
+            return iri.to_string();
+        }
+        if iri.is_empty() {
+            return self.base.clone();
+        }
+        if iri.starts_with('#') {
+            let without_fragment = self.base.split('#').next().unwrap_or(&self.base);
+            return format!("{}{}", without_fragment, iri);
+        }
+        let dir_end = self
+            .base
+            .rfind('/')
+            .map(|p| p + 1)
+            .unwrap_or(self.base.len());
+        format!("{}{}", &self.base[..dir_end], iri)
+    }
+
+    /// Parse `prefix:local`
+    fn parse_prefixed_name(&self, cur: &mut Cursor) -> Result<String, String> {
+        let prefix = cur.take_while(|c| c != ':' && !is_delimiter(c));
+        if !cur.eat(':') {
+            return Err(format!("unexpected token {:?}", prefix));
+        }
+        let namespace = self
+            .prefixes
+            .get(&prefix)
+            .ok_or_else(|| format!("undefined prefix '{}:'", prefix))?;
+
+        // Local names may contain '.' but not end with it, and allow '\' escapes
+        let mut local = String::new();
+        while let Some(c) = cur.peek() {
+            if c == '\\' {
+                cur.next();
+                if let Some(escaped) = cur.next() {
+                    local.push(escaped);
+                }
+            } else if !is_delimiter(c) {
+                local.push(c);
+                cur.next();
+            } else {
+                break;
+            }
+        }
+        while local.ends_with('.') {
+            local.pop();
+            cur.pos -= 1;
+        }
+
+        Ok(format!("<{}{}>", namespace, local))
+    }
+
+    /// Parse a string literal with optional language tag or datatype
+    fn parse_literal(&self, cur: &mut Cursor) -> Result<String, String> {
+        let quote = cur.next().ok_or("expected string")?;
+        let long = cur.peek() == Some(quote) && cur.peek_at(1) == Some(quote);
+        if long {
+            cur.next();
+            cur.next();
+        }
+
+        let mut value = String::new();
+        loop {
+            let c = cur.next().ok_or("unterminated string literal")?;
+            if c == '\\' {
+                let escaped = cur.next().ok_or("unterminated escape sequence")?;
+                match escaped {
+                    't' => value.push('\t'),
+                    'b' => value.push('\u{8}'),
+                    'n' => value.push('\n'),
+                    'r' => value.push('\r'),
+                    'f' => value.push('\u{c}'),
+                    '"' | '\'' | '\\' => value.push(escaped),
+                    'u' | 'U' => {
+                        let len = if escaped == 'u' { 4 } else { 8 };
+                        let hex: String = (0..len).filter_map(|_| cur.next()).collect();
+                        value.push(parse_hex_char(&hex)?);
+                    }
+                    other => return Err(format!("invalid escape '\\{}'", other)),
+                }
+            } else if c == quote {
+                if !long {
+                    break;
+                }
+                if cur.peek() == Some(quote) && cur.peek_at(1) == Some(quote) {
+                    cur.next();
+                    cur.next();
+                    break;
+                }
+                value.push(c);
+            } else {
+                value.push(c);
+            }
+        }
+
+        let literal = format!("\"{}\"", escape_literal(&value));
+        if cur.eat('@') {
+            let lang = cur.take_while(|c| c.is_ascii_alphanumeric() || c == '-');
+            if lang.is_empty() {
+                return Err("empty language tag".to_string());
+            }
+            return Ok(format!("{}@{}", literal, lang));
+        }
+        if cur.peek() == Some('^') && cur.peek_at(1) == Some('^') {
+            cur.next();
+            cur.next();
+            let datatype = if cur.peek() == Some('<') {
+                format!("<{}>", self.parse_iriref(cur)?)
+            } else {
+                self.parse_prefixed_name(cur)?
+            };
+            return Ok(format!("{}^^{}", literal, datatype));
+        }
+        Ok(literal)
+    }
+
+    /// Parse an integer, decimal or double literal
+    fn parse_number(&self, cur: &mut Cursor) -> Result<String, String> {
+        let start = cur.pos;
+        if matches!(cur.peek(), Some('+') | Some('-')) {
+            cur.next();
+        }
+        cur.take_while(|c| c.is_ascii_digit());
+        let mut datatype = "integer";
+        if cur.peek() == Some('.') && cur.peek_at(1).is_some_and(|c| c.is_ascii_digit()) {
+            cur.next();
+            cur.take_while(|c| c.is_ascii_digit());
+            datatype = "decimal";
+        }
+        if matches!(cur.peek(), Some('e') | Some('E')) {
+            cur.next();
+            if matches!(cur.peek(), Some('+') | Some('-')) {
+                cur.next();
+            }
+            cur.take_while(|c| c.is_ascii_digit());
+            datatype = "double";
+        }
+        let number = &cur.text[start..cur.pos];
+        if !number.chars().any(|c| c.is_ascii_digit()) {
+            return Err(format!("invalid number {:?}", number));
+        }
+        Ok(format!("\"{}\"^^<{}{}>", number, XSD, datatype))
+    }
+
+    fn new_blank_node(&mut self) -> String {
+        self.blank_counter += 1;
+        format!("_:ttl{}", self.blank_counter)
+    }
+}
+
+/// Characters that end a prefixed name or keyword
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace()
+        || matches!(
+            c,
+            ';' | ',' | '[' | ']' | '(' | ')' | '<' | '"' | '\'' | '#'
+        )
+}
+
+/// Resolve \uXXXX and \UXXXXXXXX escapes (allowed in IRIs)
+fn unescape_numeric(s: &str) -> Result<String, String> {
+    if !s.contains('\\') {
+        return Ok(s.to_string());
+    }
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        let len = match chars.next() {
+            Some('u') => 4,
+            Some('U') => 8,
+            other => return Err(format!("invalid IRI escape {:?}", other)),
+        };
+        let hex: String = chars.by_ref().take(len).collect();
+        result.push(parse_hex_char(&hex)?);
+    }
+    Ok(result)
+}
+
+fn parse_hex_char(hex: &str) -> Result<char, String> {
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(|| format!("invalid unicode escape '{}'", hex))
+}
+
+/// Character cursor over a statement
+struct Cursor<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, pos: 0 }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.text.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.text[self.pos..].chars().nth(n)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.pos += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume a keyword if it is followed by a delimiter
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let rest = &self.text[self.pos..];
+        if rest.starts_with(keyword)
+            && rest[keyword.len()..]
+                .chars()
+                .next()
+                .is_none_or(|c| is_delimiter(c) || c == '.')
+        {
+            self.pos += keyword.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Case-insensitive variant of `eat_keyword` for SPARQL-style directives
+    fn eat_keyword_ci(&mut self, keyword: &str) -> bool {
+        let rest = &self.text[self.pos..];
+        if rest.len() >= keyword.len()
+            && rest.is_char_boundary(keyword.len())
+            && rest[..keyword.len()].eq_ignore_ascii_case(keyword)
+            && rest[keyword.len()..]
+                .chars()
+                .next()
+                .is_some_and(char::is_whitespace)
+        {
+            self.pos += keyword.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, pred: F) -> String {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !pred(c) {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        self.text[start..self.pos].to_string()
+    }
+
+    /// Skip whitespace and comments
+    fn skip_ws(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.next();
+                }
+                Some('#') => {
+                    self.take_while(|c| c != '\n');
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn rest_preview(&self) -> String {
+        self.text[self.pos..].chars().take(20).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(input: &str) -> Vec<String> {
+        let mut reader = TurtleReader::new(input.as_bytes());
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        output.lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_prefixes_and_continuations() {
+        let triples = convert(
+            r#"@prefix wd: <http://www.wikidata.org/entity/> .
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+
+wd:Q42 a <http://wikiba.se/ontology#Item> ;
+    wdt:P31 wd:Q5 ;
+    rdfs:label "Douglas Adams"@en, "Douglas Adams"@de .
+"#,
+        );
+
+        assert_eq!(
+            triples,
+            vec![
+                "<http://www.wikidata.org/entity/Q42> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://wikiba.se/ontology#Item> .",
+                "<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .",
+                "<http://www.wikidata.org/entity/Q42> <http://www.w3.org/2000/01/rdf-schema#label> \"Douglas Adams\"@en .",
+                "<http://www.wikidata.org/entity/Q42> <http://www.w3.org/2000/01/rdf-schema#label> \"Douglas Adams\"@de .",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_literals() {
+        let triples = convert(
+            r#"@prefix ex: <http://example.org/> .
+@prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+ex:s ex:int 42 ; ex:dec -1.5 ; ex:dbl 1e3 ; ex:bool true ;
+    ex:typed "2001-01-01T00:00:00Z"^^xsd:dateTime ;
+    ex:long """line one
+"quoted" line two""" ;
+    ex:single 'it\'s' .
+"#,
+        );
+
+        assert!(triples[0].ends_with(r#""42"^^<http://www.w3.org/2001/XMLSchema#integer> ."#));
+        assert!(triples[1].ends_with(r#""-1.5"^^<http://www.w3.org/2001/XMLSchema#decimal> ."#));
+        assert!(triples[2].ends_with(r#""1e3"^^<http://www.w3.org/2001/XMLSchema#double> ."#));
+        assert!(triples[3].ends_with(r#""true"^^<http://www.w3.org/2001/XMLSchema#boolean> ."#));
+        assert!(triples[4]
+            .ends_with(r#""2001-01-01T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> ."#));
+        assert!(triples[5].ends_with(r#""line one\n\"quoted\" line two" ."#));
+        assert!(triples[6].ends_with(r#""it's" ."#));
+    }
+
+    #[test]
+    fn test_blank_nodes_and_collections() {
+        let triples = convert(
+            "@prefix ex: <http://example.org/> .\nex:s ex:p [ ex:q ex:o ] ; ex:list ( ex:a ex:b ) .\n",
+        );
+
+        assert_eq!(triples.len(), 7);
+        assert_eq!(
+            triples[0],
+            "_:ttl1 <http://example.org/q> <http://example.org/o> ."
+        );
+        assert_eq!(
+            triples[1],
+            "<http://example.org/s> <http://example.org/p> _:ttl1 ."
+        );
+        assert!(triples[6].starts_with("<http://example.org/s> <http://example.org/list> _:ttl2"));
+    }
+
+    #[test]
+    fn test_base_and_multiple_statements_per_line() {
+        let triples =
+            convert("@base <http://example.org/data/> .\n<a> <p> <#frag> . <b> <p> <c> .\n");
+        assert_eq!(
+            triples,
+            vec![
+                "<http://example.org/data/a> <http://example.org/data/p> <http://example.org/data/#frag> .",
+                "<http://example.org/data/b> <http://example.org/data/p> <http://example.org/data/c> .",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dot_inside_names_and_comments() {
+        let triples = convert(
+            "@prefix ex: <http://example.org/> . # comment with a . dot\nex:a.b ex:p ex:c. # trailing\n",
+        );
+        assert_eq!(
+            triples,
+            vec!["<http://example.org/a.b> <http://example.org/p> <http://example.org/c> ."]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let mut output = String::new();
+        let err = TurtleReader::new("ex:s ex:p ex:o .\n".as_bytes())
+            .read_to_string(&mut output)
+            .unwrap_err();
+        assert!(err.to_string().contains("undefined prefix"));
+
+        let err = TurtleReader::new("<a> <b> <c>\n".as_bytes())
+            .read_to_string(&mut output)
+            .unwrap_err();
+        assert!(err.to_string().contains("unterminated statement"));
+    }
+}
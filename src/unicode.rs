@@ -0,0 +1,294 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+use serde_json::Value;
+
+use crate::unicode_tables::{COMBINING_CLASSES, COMPOSITIONS, DECOMPOSITIONS};
+use crate::FilterError;
+
+// Hangul syllables are composed of leading, vowel and trailing jamo
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const N_COUNT: u32 = V_COUNT * T_COUNT;
+const S_COUNT: u32 = L_COUNT * N_COUNT;
+
+/// Unicode normalization form of the written text (--normalize-unicode)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalizationForm {
+    /// Canonical composition, as most text on the web
+    Nfc,
+    /// Canonical decomposition
+    Nfd,
+}
+
+impl NormalizationForm {
+    pub fn parse(name: &str) -> Result<Self, FilterError> {
+        match name.to_ascii_lowercase().as_str() {
+            "nfc" => Ok(NormalizationForm::Nfc),
+            "nfd" => Ok(NormalizationForm::Nfd),
+            _ => Err(FilterError::Parse(format!(
+                "Invalid --normalize-unicode '{}'. Valid: nfc, nfd",
+                name
+            ))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NormalizationForm::Nfc => "NFC",
+            NormalizationForm::Nfd => "NFD",
+        }
+    }
+
+    /// The text in this form, borrowed if it already is
+    pub fn normalize(self, text: &str) -> Cow<'_, str> {
+        if text.is_ascii() {
+            return Cow::Borrowed(text);
+        }
+        let mut chars = Vec::with_capacity(text.len());
+        for c in text.chars() {
+            decompose(c as u32, &mut chars);
+        }
+        canonical_order(&mut chars);
+        if self == NormalizationForm::Nfc {
+            compose(&mut chars);
+        }
+        let normalized: String = chars.into_iter().filter_map(char::from_u32).collect();
+        if normalized == text {
+            Cow::Borrowed(text)
+        } else {
+            Cow::Owned(normalized)
+        }
+    }
+
+    /// Normalize the values of a labels, descriptions or aliases map
+    pub fn normalize_terms(self, terms: &mut serde_json::Map<String, Value>) {
+        for term in terms.values_mut() {
+            // Labels and descriptions are single terms, aliases lists of them
+            let terms = match term.as_array_mut() {
+                Some(aliases) => aliases.iter_mut().collect(),
+                None => vec![term],
+            };
+            for term in terms {
+                if let Some(value) = term.get_mut("value") {
+                    self.normalize_value(value);
+                }
+            }
+        }
+    }
+
+    /// Normalize the string and monolingual text values of all statements,
+    /// qualifiers and references
+    pub fn normalize_claims(self, claims: &mut serde_json::Map<String, Value>) {
+        for statement in claims
+            .values_mut()
+            .filter_map(Value::as_array_mut)
+            .flatten()
+        {
+            if let Some(snak) = statement.get_mut("mainsnak") {
+                self.normalize_snak(snak);
+            }
+            if let Some(qualifiers) = statement
+                .get_mut("qualifiers")
+                .and_then(Value::as_object_mut)
+            {
+                self.normalize_snaks(qualifiers);
+            }
+            let references = statement
+                .get_mut("references")
+                .and_then(Value::as_array_mut);
+            for reference in references.into_iter().flatten() {
+                if let Some(snaks) = reference.get_mut("snaks").and_then(Value::as_object_mut) {
+                    self.normalize_snaks(snaks);
+                }
+            }
+        }
+    }
+
+    fn normalize_snaks(self, snaks: &mut serde_json::Map<String, Value>) {
+        for snak in snaks.values_mut().filter_map(Value::as_array_mut).flatten() {
+            self.normalize_snak(snak);
+        }
+    }
+
+    fn normalize_snak(self, snak: &mut Value) {
+        let Some(datavalue) = snak.get_mut("datavalue") else {
+            return;
+        };
+        let text = match datavalue.get("type").and_then(Value::as_str) {
+            Some("string") => datavalue.get_mut("value"),
+            Some("monolingualtext") => datavalue
+                .get_mut("value")
+                .and_then(|value| value.get_mut("text")),
+            _ => None,
+        };
+        if let Some(text) = text {
+            self.normalize_value(text);
+        }
+    }
+
+    fn normalize_value(self, value: &mut Value) {
+        if let Some(Cow::Owned(normalized)) = value.as_str().map(|text| self.normalize(text)) {
+            *value = Value::String(normalized);
+        }
+    }
+}
+
+fn combining_class(c: u32) -> u8 {
+    COMBINING_CLASSES
+        .binary_search_by(|&(first, last, _)| {
+            if last < c {
+                Ordering::Less
+            } else if first > c {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+        .map_or(0, |i| COMBINING_CLASSES[i].2)
+}
+
+/// Append the full canonical decomposition of a character
+fn decompose(c: u32, out: &mut Vec<u32>) {
+    if (S_BASE..S_BASE + S_COUNT).contains(&c) {
+        let index = c - S_BASE;
+        out.push(L_BASE + index / N_COUNT);
+        out.push(V_BASE + index % N_COUNT / T_COUNT);
+        if !index.is_multiple_of(T_COUNT) {
+            out.push(T_BASE + index % T_COUNT);
+        }
+        return;
+    }
+    match DECOMPOSITIONS.binary_search_by_key(&c, |&(c, _, _)| c) {
+        Ok(i) => {
+            let (_, first, second) = DECOMPOSITIONS[i];
+            decompose(first, out);
+            if second != 0 {
+                decompose(second, out);
+            }
+        }
+        Err(_) => out.push(c),
+    }
+}
+
+/// Sort each run of combining marks by class, keeping the order of marks of
+/// the same class
+fn canonical_order(chars: &mut [u32]) {
+    let mut start = 0;
+    while start < chars.len() {
+        if combining_class(chars[start]) == 0 {
+            start += 1;
+            continue;
+        }
+        let end = chars[start..]
+            .iter()
+            .position(|&c| combining_class(c) == 0)
+            .map_or(chars.len(), |len| start + len);
+        chars[start..end].sort_by_key(|&c| combining_class(c));
+        start = end;
+    }
+}
+
+/// The primary composite of two characters, if any
+fn compose_pair(first: u32, second: u32) -> Option<u32> {
+    if (L_BASE..L_BASE + L_COUNT).contains(&first) && (V_BASE..V_BASE + V_COUNT).contains(&second) {
+        return Some(S_BASE + ((first - L_BASE) * V_COUNT + second - V_BASE) * T_COUNT);
+    }
+    if (S_BASE..S_BASE + S_COUNT).contains(&first)
+        && (first - S_BASE).is_multiple_of(T_COUNT)
+        && (T_BASE + 1..T_BASE + T_COUNT).contains(&second)
+    {
+        return Some(first + second - T_BASE);
+    }
+    COMPOSITIONS
+        .binary_search_by_key(&(first, second), |&(first, second, _)| (first, second))
+        .ok()
+        .map(|i| COMPOSITIONS[i].2)
+}
+
+/// Compose a decomposed, canonically ordered string in place: each character
+/// joins the last starter unless a character between them blocks it
+fn compose(chars: &mut Vec<u32>) {
+    let mut starter: Option<usize> = None;
+    let mut last_class = 0;
+    let mut len = 0;
+    for i in 0..chars.len() {
+        let c = chars[i];
+        let class = combining_class(c);
+        if let Some(starter) = starter {
+            let blocked = len - 1 != starter && (last_class == 0 || last_class >= class);
+            if let Some(composite) = compose_pair(chars[starter], c).filter(|_| !blocked) {
+                chars[starter] = composite;
+                continue;
+            }
+        }
+        if class == 0 {
+            starter = Some(len);
+        }
+        last_class = class;
+        chars[len] = c;
+        len += 1;
+    }
+    chars.truncate(len);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_normalize() {
+        let nfc = NormalizationForm::Nfc;
+        let nfd = NormalizationForm::Nfd;
+        assert_eq!(nfc.normalize("Zu\u{308}rich"), "Zürich");
+        assert_eq!(nfd.normalize("Zürich"), "Zu\u{308}rich");
+        assert!(matches!(nfc.normalize("Zürich"), Cow::Borrowed(_)));
+        // Marks are reordered by class before composing
+        assert_eq!(nfc.normalize("a\u{302}\u{323}"), "\u{1EAD}");
+        assert_eq!(nfd.normalize("\u{1EAD}"), "a\u{323}\u{302}");
+        // Singletons and excluded composites stay decomposed
+        assert_eq!(nfc.normalize("\u{212B}"), "\u{C5}");
+        assert_eq!(nfc.normalize("\u{FB1D}"), "\u{5D9}\u{5B4}");
+        // Hangul syllables
+        assert_eq!(nfc.normalize("\u{1112}\u{1161}\u{11AB}"), "한");
+        assert_eq!(
+            nfd.normalize("한국"),
+            "\u{1112}\u{1161}\u{11AB}\u{1100}\u{116E}\u{11A8}"
+        );
+        // A mark of the same class blocks the second
+        assert_eq!(nfc.normalize("e\u{301}\u{301}"), "\u{E9}\u{301}");
+
+        assert_eq!(NormalizationForm::parse("NFC").unwrap(), nfc);
+        assert!(NormalizationForm::parse("nfkc").is_err());
+    }
+
+    #[test]
+    fn test_normalize_entity_parts() {
+        let nfc = NormalizationForm::Nfc;
+        let mut aliases = json!({"de": [{"language": "de", "value": "Zu\u{308}rich"}]});
+        nfc.normalize_terms(aliases.as_object_mut().unwrap());
+        assert_eq!(aliases["de"][0]["value"], "Zürich");
+
+        let mut claims = json!({
+            "P1448": [{"mainsnak": {"datavalue": {"type": "monolingualtext",
+                "value": {"text": "Zu\u{308}rich", "language": "de"}}},
+                "qualifiers": {"P1810": [{"datavalue": {"type": "string", "value": "Zu\u{308}rich"}}]}}]
+        });
+        nfc.normalize_claims(claims.as_object_mut().unwrap());
+        let statement = &claims["P1448"][0];
+        assert_eq!(
+            statement["mainsnak"]["datavalue"]["value"]["text"],
+            "Zürich"
+        );
+        assert_eq!(
+            statement["qualifiers"]["P1810"][0]["datavalue"]["value"],
+            "Zürich"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+
+use serde_json::Value;
+
+use crate::compression::decompressing_reader;
+use crate::FilterError;
+
+const ENTITY_IRI: &str = "http://www.wikidata.org/entity/";
+
+/// Conversion factors of quantity units (--normalize-units): a value in a
+/// unit times its factor is the value in the target unit
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UnitConversions {
+    /// Target unit and factor by unit ID, `1` for dimensionless quantities
+    pub factors: HashMap<String, (f64, String)>,
+}
+
+impl UnitConversions {
+    /// Read a file of `Q174728 0.01 Q11573` lines (centimetre to metre),
+    /// decompressed by its extension; blank lines and `#` comments are skipped
+    pub fn read(path: &str) -> Result<Self, FilterError> {
+        let file = std::fs::File::open(path).map_err(|e| {
+            FilterError::Parse(format!(
+                "Cannot open unit conversion file '{}': {}",
+                path, e
+            ))
+        })?;
+        let mut factors = HashMap::new();
+        for line in BufReader::new(decompressing_reader(file, path)).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (unit, factor, target) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [unit, factor, target] => (unit, factor, target),
+                _ => {
+                    return Err(FilterError::Parse(format!(
+                        "Expected 'Q174728 0.01 Q11573' in {}, found '{}'",
+                        path, line
+                    )))
+                }
+            };
+            let factor = factor
+                .parse::<f64>()
+                .ok()
+                .filter(|f| f.is_finite() && *f != 0.0)
+                .ok_or_else(|| {
+                    FilterError::Parse(format!(
+                        "Invalid conversion factor '{}' in {}",
+                        factor, path
+                    ))
+                })?;
+            factors.insert(unit.to_string(), (factor, target.to_string()));
+        }
+        Ok(UnitConversions { factors })
+    }
+
+    /// Convert the quantities of all statements, qualifiers and references
+    /// whose unit has a factor
+    pub fn normalize(&self, claims: &mut serde_json::Map<String, Value>) {
+        for statement in claims
+            .values_mut()
+            .filter_map(Value::as_array_mut)
+            .flatten()
+        {
+            if let Some(snak) = statement.get_mut("mainsnak") {
+                self.normalize_snak(snak);
+            }
+            if let Some(qualifiers) = statement
+                .get_mut("qualifiers")
+                .and_then(Value::as_object_mut)
+            {
+                self.normalize_snaks(qualifiers);
+            }
+            let references = statement
+                .get_mut("references")
+                .and_then(Value::as_array_mut);
+            for reference in references.into_iter().flatten() {
+                if let Some(snaks) = reference.get_mut("snaks").and_then(Value::as_object_mut) {
+                    self.normalize_snaks(snaks);
+                }
+            }
+        }
+    }
+
+    fn normalize_snaks(&self, snaks: &mut serde_json::Map<String, Value>) {
+        for snak in snaks.values_mut().filter_map(Value::as_array_mut).flatten() {
+            self.normalize_snak(snak);
+        }
+    }
+
+    fn normalize_snak(&self, snak: &mut Value) {
+        let Some(datavalue) = snak.get_mut("datavalue") else {
+            return;
+        };
+        if datavalue.get("type").and_then(Value::as_str) != Some("quantity") {
+            return;
+        }
+        let Some(quantity) = datavalue.get_mut("value").and_then(Value::as_object_mut) else {
+            return;
+        };
+        let unit = quantity.get("unit").and_then(Value::as_str).unwrap_or("1");
+        let Some((factor, target)) = self
+            .factors
+            .get(unit.strip_prefix(ENTITY_IRI).unwrap_or(unit))
+        else {
+            return;
+        };
+        for key in ["amount", "upperBound", "lowerBound"] {
+            let amount = quantity
+                .get(key)
+                .and_then(Value::as_str)
+                .and_then(|amount| amount.parse::<f64>().ok());
+            if let Some(amount) = amount {
+                quantity.insert(key.to_string(), Value::from(format_amount(amount * factor)));
+            }
+        }
+        let target = match target.as_str() {
+            "1" => "1".to_string(),
+            id => format!("{}{}", ENTITY_IRI, id),
+        };
+        quantity.insert("unit".to_string(), Value::from(target));
+    }
+}
+
+/// A quantity amount as in the dumps, signed and rounded to 15 significant
+/// digits so that conversions do not print float noise
+fn format_amount(amount: f64) -> String {
+    let rounded: f64 = format!("{:.14e}", amount).parse().unwrap_or(amount);
+    if rounded < 0.0 {
+        rounded.to_string()
+    } else {
+        format!("+{}", rounded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_normalize() {
+        let conversions = UnitConversions {
+            factors: HashMap::from([
+                ("Q174728".to_string(), (0.01, "Q11573".to_string())),
+                ("Q3710".to_string(), (0.3048, "Q11573".to_string())),
+            ]),
+        };
+        let quantity = |amount: &str, unit: &str| {
+            json!({"snaktype": "value", "datavalue": {"type": "quantity", "value": {
+                "amount": amount, "unit": format!("{}{}", ENTITY_IRI, unit)}}})
+        };
+        let mut claims = json!({
+            "P2048": [
+                {"mainsnak": quantity("+180", "Q174728"),
+                 "qualifiers": {"P1480": [quantity("+10", "Q3710")]}},
+                {"mainsnak": quantity("+2", "Q11573")},
+                {"mainsnak": {"snaktype": "somevalue"}}
+            ]
+        });
+        conversions.normalize(claims.as_object_mut().unwrap());
+        let value = |snak: &Value| snak["datavalue"]["value"].clone();
+        let statements = &claims["P2048"];
+        assert_eq!(
+            value(&statements[0]["mainsnak"]),
+            json!({"amount": "+1.8", "unit": format!("{}Q11573", ENTITY_IRI)})
+        );
+        assert_eq!(
+            value(&statements[0]["qualifiers"]["P1480"][0])["amount"],
+            "+3.048"
+        );
+        assert_eq!(
+            value(&statements[1]["mainsnak"]),
+            value(&quantity("+2", "Q11573"))
+        );
+        assert_eq!(statements[2]["mainsnak"], json!({"snaktype": "somevalue"}));
+    }
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(0.3 * 3.0), "+0.9");
+        assert_eq!(format_amount(-1.5), "-1.5");
+        assert_eq!(format_amount(0.0), "+0");
+    }
+}
@@ -0,0 +1,419 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::claim_parser::parse_claim_filter;
+use crate::compression::{
+    create_member_writer, determine_compression, finish_output, MemberBoundary, OUTPUT_BUFFER_SIZE,
+};
+use crate::filter::EntityFilter;
+use crate::idset::IdSet;
+use crate::{http, signal, FilterError};
+
+/// Wikimedia EventStreams feed of all recent changes
+pub const DEFAULT_STREAM_URL: &str = "https://stream.wikimedia.org/v2/stream/recentchange";
+
+/// Where an entity revision is fetched from (placeholders: {id}, {revision})
+pub const DEFAULT_ENTITY_URL: &str =
+    "https://www.wikidata.org/wiki/Special:EntityData/{id}.json?revision={revision}";
+
+/// How often the output is flushed and the stream position saved
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Arguments for the `update` subcommand
+#[derive(clap::Args, Debug)]
+pub struct UpdateArgs {
+    /// NDJSON file the matching entity snapshots are appended to (.gz, .lz4)
+    #[arg(long)]
+    output: String,
+
+    /// Filter by claim (e.g., P31:Q5, P31:Q5,Q6256, P31:Q5&P18)
+    #[arg(short, long)]
+    claim: Option<String>,
+
+    /// Entity type to filter: item, property, or both
+    #[arg(short = 't', long, default_value = "both")]
+    r#type: String,
+
+    /// Keep only specified subject entity IDs (comma-separated, e.g., Q31,Q42)
+    #[arg(long)]
+    subject: Option<String>,
+
+    /// Filter languages for labels/descriptions (comma-separated, e.g., en,de,fr)
+    #[arg(short = 'l', long)]
+    languages: Option<String>,
+
+    /// File with the ID of the last event handled; the stream continues after it
+    #[arg(long)]
+    state: Option<String>,
+
+    /// Start at this time (ISO 8601, e.g. 2026-10-01T00:00:00Z) when there is no state yet
+    #[arg(long)]
+    since: Option<String>,
+
+    /// Stop after N change events (0 = run until interrupted)
+    #[arg(long, default_value = "0")]
+    max_events: u64,
+
+    /// EventStreams feed: recentchange or revision-create
+    #[arg(long, default_value = DEFAULT_STREAM_URL)]
+    stream_url: String,
+
+    /// Wiki whose changes are followed
+    #[arg(long, default_value = "wikidatawiki")]
+    wiki: String,
+
+    /// URL of an entity revision as JSON (placeholders: {id}, {revision})
+    #[arg(long, default_value = DEFAULT_ENTITY_URL)]
+    entity_url: String,
+
+    /// Reconnects after transient network errors
+    #[arg(long, default_value_t = http::DEFAULT_RETRIES)]
+    http_retries: u32,
+
+    /// Show progress info on stderr
+    #[arg(short = 'p', long)]
+    progress: bool,
+}
+
+/// A server-sent event
+#[derive(Debug, Default, PartialEq)]
+struct Event {
+    id: Option<String>,
+    data: String,
+}
+
+/// Read the next event of a text/event-stream; None at the end of the stream
+fn next_event<R: BufRead>(reader: &mut R) -> io::Result<Option<Event>> {
+    let mut event = Event::default();
+    let mut has_data = false;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            if has_data {
+                return Ok(Some(event));
+            }
+            continue;
+        }
+        // Comments keep the connection alive
+        if line.starts_with(':') {
+            continue;
+        }
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "data" => {
+                if has_data {
+                    event.data.push('\n');
+                }
+                event.data.push_str(value);
+                has_data = true;
+            }
+            "id" => event.id = Some(value.to_string()),
+            _ => {}
+        }
+    }
+}
+
+/// The fields of recentchange and revision-create events that are used
+#[derive(Deserialize, Debug, Default)]
+struct Change {
+    wiki: Option<String>,
+    namespace: Option<i64>,
+    title: Option<String>,
+    revision: Option<Revisions>,
+    database: Option<String>,
+    page_namespace: Option<i64>,
+    page_title: Option<String>,
+    rev_id: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Revisions {
+    new: Option<u64>,
+}
+
+impl Change {
+    /// ID and new revision of the entity that was edited on `wiki`, if any;
+    /// log events (deletions, protections) have no revision
+    fn edited_entity(&self, wiki: &str) -> Option<(String, u64)> {
+        let change_wiki = self.wiki.as_ref().or(self.database.as_ref())?;
+        let namespace = self.namespace.or(self.page_namespace)?;
+        let title = self.title.as_ref().or(self.page_title.as_ref())?;
+        let revision = self
+            .revision
+            .as_ref()
+            .and_then(|revisions| revisions.new)
+            .or(self.rev_id)?;
+        if change_wiki != wiki {
+            return None;
+        }
+        // Items, properties and lexemes
+        let id = match namespace {
+            0 => title.as_str(),
+            120 => title.strip_prefix("Property:")?,
+            146 => title.strip_prefix("Lexeme:")?,
+            _ => return None,
+        };
+        let is_entity_id = matches!(id.as_bytes().first(), Some(b'Q' | b'P' | b'L'))
+            && id.len() > 1
+            && id[1..].bytes().all(|b| b.is_ascii_digit());
+        is_entity_id.then(|| (id.to_string(), revision))
+    }
+}
+
+/// The entity in a Special:EntityData response
+fn response_entity(response: Value) -> Option<Value> {
+    match response {
+        Value::Object(mut object) => match object.remove("entities")? {
+            Value::Object(entities) => entities.into_iter().next().map(|(_, entity)| entity),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Fetch revision `revision` of entity `id`
+fn fetch_entity(template: &str, id: &str, revision: u64, retries: u32) -> io::Result<Value> {
+    let url = template
+        .replace("{id}", id)
+        .replace("{revision}", &revision.to_string());
+    let response: Value = serde_json::from_reader(http::open_source(&url, 0, retries)?)?;
+    response_entity(response).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no entity in the response of {}", url),
+        )
+    })
+}
+
+/// Write `id` to the state file, through a temporary file so it is never cut
+fn save_state(path: &str, id: &str) -> io::Result<()> {
+    let temp = format!("{}.tmp", path);
+    fs::write(&temp, id)?;
+    fs::rename(temp, path)
+}
+
+/// The feed URL, starting at `since` if given
+fn stream_url(url: &str, since: Option<&str>) -> String {
+    match since {
+        Some(since) => {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            format!("{}{}since={}", url, separator, since)
+        }
+        None => url.to_string(),
+    }
+}
+
+/// Run the `update` subcommand: follow the change feed and append the new
+/// revision of every changed entity that matches the filter
+pub fn run(args: UpdateArgs) -> Result<(), FilterError> {
+    let claim_filter = args.claim.as_deref().map(parse_claim_filter).transpose()?;
+    let split_ids =
+        |s: &String| -> HashSet<String> { s.split(',').map(|id| id.trim().to_string()).collect() };
+    let filter = Arc::new(EntityFilter {
+        claim_filter,
+        subject_filter: args.subject.as_ref().map(split_ids).map(IdSet::from),
+        language_filter: args.languages.as_ref().map(split_ids),
+        entity_type: args.r#type.clone(),
+        ..Default::default()
+    });
+
+    let mut last_event_id = match &args.state {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(id) if !id.trim().is_empty() => Some(id.trim().to_string()),
+            Ok(_) => None,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        },
+        None => None,
+    };
+    let url = stream_url(
+        &args.stream_url,
+        args.since.as_deref().filter(|_| last_event_id.is_none()),
+    );
+
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&args.output)?;
+    let compression = determine_compression("none", Some(&args.output));
+    let boundary = MemberBoundary::default();
+    let mut output = BufWriter::with_capacity(
+        OUTPUT_BUFFER_SIZE,
+        create_member_writer(file, &compression, boundary.clone()),
+    );
+    let interrupted = signal::install_interrupt_handler()?;
+
+    let (mut events, mut appended) = (0u64, 0u64);
+    let mut unsaved = false;
+    let mut last_save = Instant::now();
+    let mut reconnects = 0;
+    let mut stream = None;
+    while !interrupted.load(Ordering::Relaxed) && (args.max_events == 0 || events < args.max_events)
+    {
+        let reader = match &mut stream {
+            Some(reader) => reader,
+            None => stream.insert(BufReader::new(http::open_event_stream(
+                &url,
+                last_event_id.as_deref(),
+                args.http_retries,
+            )?)),
+        };
+        let event = match next_event(reader) {
+            Ok(Some(event)) => {
+                reconnects = 0;
+                event
+            }
+            // The feed closes connections now and then; continue after the last event
+            result if reconnects < args.http_retries => {
+                reconnects += 1;
+                let reason = match result {
+                    Err(e) => e.to_string(),
+                    _ => "connection closed".to_string(),
+                };
+                eprintln!(
+                    "Warning: {}: {}; reconnecting ({} of {})",
+                    args.stream_url, reason, reconnects, args.http_retries
+                );
+                stream = None;
+                std::thread::sleep(Duration::from_secs(1 << reconnects.min(6)));
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+            Ok(None) => {
+                return Err(FilterError::Parse(format!(
+                    "{} keeps closing the connection",
+                    args.stream_url
+                )))
+            }
+        };
+
+        if let Ok(change) = serde_json::from_str::<Change>(&event.data) {
+            if let Some((id, revision)) = change.edited_entity(&args.wiki) {
+                events += 1;
+                match fetch_entity(&args.entity_url, &id, revision, args.http_retries) {
+                    Ok(entity) if filter.matches_json(&entity) => {
+                        let entity = filter.filter_json_entity(&entity);
+                        serde_json::to_writer(&mut output, &entity)?;
+                        output.write_all(b"\n")?;
+                        appended += 1;
+                    }
+                    Ok(_) => {}
+                    // Deleted since, or a revision that cannot be shown
+                    Err(e) => eprintln!("Warning: skipped {} revision {}: {}", id, revision, e),
+                }
+                if args.progress && events % 100 == 0 {
+                    eprintln!("{} changes, {} entities appended", events, appended);
+                }
+            }
+        }
+        if event.id.is_some() {
+            last_event_id = event.id;
+            unsaved = true;
+        }
+        if unsaved && last_save.elapsed() >= SAVE_INTERVAL {
+            // The output has everything up to the saved event, in complete members
+            boundary.request();
+            output.flush()?;
+            if let (Some(path), Some(id)) = (&args.state, &last_event_id) {
+                save_state(path, id)?;
+            }
+            unsaved = false;
+            last_save = Instant::now();
+        }
+    }
+
+    finish_output(output)?;
+    if let (Some(path), Some(id)) = (&args.state, &last_event_id) {
+        save_state(path, id)?;
+    }
+    eprintln!(
+        "{} changes, {} entities appended to {}",
+        events, appended, args.output
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_event() {
+        let stream = ":ok\n\nevent: message\nid: [{\"offset\":1}]\ndata: {\"a\":\ndata: 1}\n\nid: 2\r\ndata: x\r\n\r\n";
+        let mut reader = io::Cursor::new(stream);
+        assert_eq!(
+            next_event(&mut reader).unwrap(),
+            Some(Event {
+                id: Some("[{\"offset\":1}]".to_string()),
+                data: "{\"a\":\n1}".to_string()
+            })
+        );
+        assert_eq!(
+            next_event(&mut reader).unwrap(),
+            Some(Event {
+                id: Some("2".to_string()),
+                data: "x".to_string()
+            })
+        );
+        assert_eq!(next_event(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_edited_entity() {
+        let change = |json: &str| serde_json::from_str::<Change>(json).unwrap();
+        assert_eq!(
+            change(r#"{"wiki":"wikidatawiki","namespace":0,"title":"Q42","type":"edit","revision":{"old":1,"new":2}}"#)
+                .edited_entity("wikidatawiki"),
+            Some(("Q42".to_string(), 2))
+        );
+        assert_eq!(
+            change(r#"{"database":"wikidatawiki","page_namespace":120,"page_title":"Property:P31","rev_id":7}"#)
+                .edited_entity("wikidatawiki"),
+            Some(("P31".to_string(), 7))
+        );
+        // Other wikis, talk pages and log events
+        assert_eq!(
+            change(r#"{"wiki":"enwiki","namespace":0,"title":"Q42","revision":{"new":2}}"#)
+                .edited_entity("wikidatawiki"),
+            None
+        );
+        assert_eq!(
+            change(
+                r#"{"wiki":"wikidatawiki","namespace":1,"title":"Talk:Q42","revision":{"new":2}}"#
+            )
+            .edited_entity("wikidatawiki"),
+            None
+        );
+        assert_eq!(
+            change(r#"{"wiki":"wikidatawiki","namespace":0,"title":"Q42","type":"log"}"#)
+                .edited_entity("wikidatawiki"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_response_entity() {
+        let response = serde_json::json!({"entities": {"Q42": {"id": "Q42", "type": "item"}}});
+        assert_eq!(
+            response_entity(response),
+            Some(serde_json::json!({"id": "Q42", "type": "item"}))
+        );
+        assert_eq!(
+            stream_url(DEFAULT_STREAM_URL, Some("2026-10-01T00:00:00Z")),
+            "https://stream.wikimedia.org/v2/stream/recentchange?since=2026-10-01T00:00:00Z"
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Write};
+
+use serde::Deserialize;
+
+use crate::compression::{create_input_reader, INPUT_BUFFER_SIZE};
+use crate::json::dump_array_elements;
+use crate::malformed::MalformedLine;
+use crate::ntriples::Triple;
+use crate::rdf::line_entity_id;
+use crate::FilterError;
+
+/// Arguments for the `validate` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ValidateArgs {
+    /// Input dump file (stdin if not provided, supports .bz2, .gz, .lz4)
+    #[arg()]
+    input: Option<String>,
+
+    /// Input format: auto, rdf, json
+    #[arg(short = 'f', long, default_value = "auto")]
+    format: String,
+
+    /// Print at most N problems (0 = no limit); all of them are counted
+    #[arg(long, default_value = "100")]
+    max_reports: u64,
+}
+
+/// Kind of problem found by `validate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Problem {
+    InvalidUtf8,
+    MalformedTriple,
+    InvalidJson,
+    /// Lines of an entity that are not contiguous, or an entity repeated
+    Interleaved,
+    /// Compressed stream or JSON array that ends early
+    Truncated,
+}
+
+impl Problem {
+    fn name(self) -> &'static str {
+        match self {
+            Problem::InvalidUtf8 => "invalid UTF-8",
+            Problem::MalformedTriple => "malformed N-Triples",
+            Problem::InvalidJson => "invalid JSON entity",
+            Problem::Interleaved => "interleaved entity",
+            Problem::Truncated => "truncated input",
+        }
+    }
+}
+
+/// The part of a JSON entity `validate` looks at; the rest is only checked for syntax
+#[derive(Deserialize)]
+struct EntityId<'a> {
+    #[serde(borrow)]
+    id: Cow<'a, str>,
+}
+
+/// Entity IDs seen so far, as bitsets of the numbers of `Q`/`P`/`L`... IDs
+#[derive(Default)]
+pub struct SeenIds {
+    numbered: HashMap<u8, Vec<u64>>,
+    other: HashSet<String>,
+}
+
+/// Largest ID number kept in a bitset (512 MiB); larger ones go to the hash set
+const MAX_BITSET_ID: u64 = 1 << 32;
+
+/// Number of an ID that is kept in a bitset
+fn bitset_number(id: &str) -> Option<u64> {
+    id.get(1..)
+        .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|digits| digits.parse::<u64>().ok())
+        .filter(|&number| number < MAX_BITSET_ID)
+}
+
+impl SeenIds {
+    /// Add an ID, returning false if it was already seen
+    pub fn insert(&mut self, id: &str) -> bool {
+        match bitset_number(id) {
+            Some(number) => {
+                let bits = self.numbered.entry(id.as_bytes()[0]).or_default();
+                let (word, bit) = ((number / 64) as usize, 1 << (number % 64));
+                if bits.len() <= word {
+                    bits.resize(word + 1, 0);
+                }
+                let new = bits[word] & bit == 0;
+                bits[word] |= bit;
+                new
+            }
+            None => self.other.insert(id.to_string()),
+        }
+    }
+
+    /// Whether an ID was seen
+    pub fn contains(&self, id: &str) -> bool {
+        match bitset_number(id) {
+            Some(number) => self
+                .numbered
+                .get(&id.as_bytes()[0])
+                .and_then(|bits| bits.get((number / 64) as usize))
+                .is_some_and(|word| word & (1 << (number % 64)) != 0),
+            None => self.other.contains(id),
+        }
+    }
+}
+
+/// Problem counts of a `validate` run
+#[derive(Debug, Default)]
+struct Summary {
+    lines: u64,
+    entities: u64,
+    problems: BTreeMap<Problem, u64>,
+}
+
+impl Summary {
+    fn total(&self) -> u64 {
+        self.problems.values().sum()
+    }
+
+    fn message(&self) -> String {
+        let checked = format!("Checked {} lines, {} entities", self.lines, self.entities);
+        if self.problems.is_empty() {
+            return format!("{}: no problems found", checked);
+        }
+        let kinds: Vec<String> = self
+            .problems
+            .iter()
+            .map(|(problem, count)| format!("{} {}", count, problem.name()))
+            .collect();
+        format!(
+            "{}: {} problems ({})",
+            checked,
+            self.total(),
+            kinds.join(", ")
+        )
+    }
+}
+
+/// Streams a dump line by line, printing problems as they are found
+struct Validator<W> {
+    json: bool,
+    report: W,
+    max_reports: u64,
+    summary: Summary,
+    seen: SeenIds,
+    /// Entity of the previous N-Triples line with an entity subject
+    current: Option<String>,
+    /// Opened by `[` of a JSON array dump and not yet closed by `]`
+    open_array: bool,
+}
+
+impl<W: Write> Validator<W> {
+    fn new(json: bool, report: W, max_reports: u64) -> Self {
+        Self {
+            json,
+            report,
+            max_reports,
+            summary: Summary::default(),
+            seen: SeenIds::default(),
+            current: None,
+            open_array: false,
+        }
+    }
+
+    fn problem(&mut self, problem: Problem, line: MalformedLine) -> io::Result<()> {
+        let count = self.summary.problems.entry(problem).or_default();
+        *count += 1;
+        let reported: u64 = self.summary.problems.values().sum();
+        if self.max_reports == 0 || reported <= self.max_reports {
+            write!(
+                self.report,
+                "line {}: {}: {}",
+                line.line,
+                problem.name(),
+                line.error
+            )?;
+            if !line.content.is_empty() {
+                write!(self.report, ": {}", line.excerpt())?;
+            }
+            writeln!(self.report)?;
+        }
+        Ok(())
+    }
+
+    fn check_line(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.summary.lines += 1;
+        let number = self.summary.lines;
+        let line = match std::str::from_utf8(bytes) {
+            Ok(line) => line,
+            Err(e) => {
+                let content = String::from_utf8_lossy(bytes);
+                return self.problem(
+                    Problem::InvalidUtf8,
+                    MalformedLine::new(number, e.to_string(), &content),
+                );
+            }
+        };
+        if self.json {
+            self.check_json_line(number, line)
+        } else {
+            self.check_ntriples_line(number, line)
+        }
+    }
+
+    fn check_ntriples_line(&mut self, number: u64, line: &str) -> io::Result<()> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return Ok(());
+        }
+        if Triple::parse(line).is_none() {
+            return self.problem(
+                Problem::MalformedTriple,
+                MalformedLine::new(number, "not a valid N-Triples statement", line),
+            );
+        }
+        let id = match line_entity_id(line) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        if self.current.as_deref() == Some(id) {
+            return Ok(());
+        }
+        self.current = Some(id.to_string());
+        if !self.seen.insert(id) {
+            let error = format!("{} continues after lines of other entities", id);
+            return self.problem(
+                Problem::Interleaved,
+                MalformedLine::new(number, error, line),
+            );
+        }
+        self.summary.entities += 1;
+        Ok(())
+    }
+
+    fn check_json_line(&mut self, number: u64, line: &str) -> io::Result<()> {
+        let trimmed = line.trim_start_matches('\u{feff}').trim();
+        if trimmed.starts_with('[') {
+            self.open_array = true;
+        }
+        if trimmed.ends_with(']') {
+            self.open_array = false;
+        }
+        for element in dump_array_elements(line) {
+            let id = match serde_json::from_str::<EntityId>(element) {
+                Ok(entity) => entity.id,
+                Err(e) => {
+                    self.problem(
+                        Problem::InvalidJson,
+                        MalformedLine::new(number, e.to_string(), element),
+                    )?;
+                    continue;
+                }
+            };
+            self.summary.entities += 1;
+            if !self.seen.insert(&id) {
+                let error = format!("{} appears more than once", id);
+                self.problem(Problem::Interleaved, MalformedLine::new(number, error, ""))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a whole input; a read error (e.g. a truncated compressed stream) ends it
+    fn check<R: BufRead>(&mut self, mut reader: R) -> io::Result<()> {
+        let mut buffer = Vec::new();
+        loop {
+            buffer.clear();
+            match reader.read_until(b'\n', &mut buffer) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    let error = format!("input ends early or is corrupt: {}", e);
+                    let line = MalformedLine::new(self.summary.lines + 1, error, "");
+                    return self.problem(Problem::Truncated, line);
+                }
+            }
+            let mut line = &buffer[..];
+            line = line.strip_suffix(b"\n").unwrap_or(line);
+            line = line.strip_suffix(b"\r").unwrap_or(line);
+            self.check_line(line)?;
+        }
+        if self.open_array {
+            let line = MalformedLine::new(self.summary.lines, "JSON array is not closed", "");
+            self.problem(Problem::Truncated, line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run the `validate` subcommand
+///
+/// Problems are printed to stdout with their line numbers, followed by a
+/// summary; the run fails if any were found.
+pub fn run(args: ValidateArgs) -> Result<(), FilterError> {
+    let (reader, format): (Box<dyn BufRead + Send>, String) = match &args.input {
+        Some(path) => create_input_reader(path, &args.format, INPUT_BUFFER_SIZE)?,
+        None => {
+            let format = if args.format == "auto" {
+                "rdf".to_string()
+            } else {
+                args.format.clone()
+            };
+            (Box::new(BufReader::new(io::stdin())), format)
+        }
+    };
+    let json = match format.as_str() {
+        "json" | "ndjson" => true,
+        "rdf" | "ntriples" | "nt" => false,
+        _ => {
+            return Err(FilterError::Parse(format!(
+                "validate supports N-Triples and JSON input, not {}",
+                format
+            )))
+        }
+    };
+
+    let stdout = io::stdout();
+    let mut validator = Validator::new(json, stdout.lock(), args.max_reports);
+    validator.check(reader)?;
+    let summary = validator.summary;
+    println!("{}", summary.message());
+    if summary.total() > 0 {
+        return Err(FilterError::Parse(format!(
+            "{} problems found in {}",
+            summary.total(),
+            args.input.as_deref().unwrap_or("stdin")
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(input: impl io::Read, json: bool) -> (Summary, String) {
+        let mut report = Vec::new();
+        let mut validator = Validator::new(json, &mut report, 0);
+        validator.check(BufReader::new(input)).unwrap();
+        let summary = validator.summary;
+        (summary, String::from_utf8(report).unwrap())
+    }
+
+    #[test]
+    fn test_ntriples_problems() {
+        let input = concat!(
+            "<http://www.wikidata.org/entity/Q1> <p> \"1\" .\n",
+            "<http://www.wikidata.org/entity/Q2> <p> \"2\" .\n",
+            "<http://www.wikidata.org/entity/Q2> <p> \"unterminated .\n",
+            "<http://www.wikidata.org/entity/Q1> <p> \"again\" .\n",
+        );
+        let (summary, report) = validate(input.as_bytes(), false);
+        assert_eq!(summary.lines, 4);
+        assert_eq!(summary.problems[&Problem::MalformedTriple], 1);
+        assert_eq!(summary.problems[&Problem::Interleaved], 1);
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[0].starts_with("line 3: malformed N-Triples"));
+        assert!(lines[1].starts_with("line 4: interleaved entity: Q1 continues"));
+    }
+
+    #[test]
+    fn test_json_problems() {
+        let input = concat!(
+            "[\n",
+            "{\"id\":\"Q1\",\"type\":\"item\"},\n",
+            "{\"id\":\"Q2\",\"type\":\n",
+            "{\"id\":\"Q1\",\"type\":\"item\"},\n",
+            "{\"type\":\"item\"},\n",
+        );
+        let (summary, report) = validate(input.as_bytes(), true);
+        assert_eq!(summary.entities, 2);
+        assert_eq!(summary.problems[&Problem::InvalidJson], 2);
+        assert_eq!(summary.problems[&Problem::Interleaved], 1);
+        assert_eq!(summary.problems[&Problem::Truncated], 1);
+        assert!(report.contains("line 4: interleaved entity: Q1 appears more than once"));
+        assert!(report.contains("missing field `id`"));
+        assert!(report.ends_with("line 5: truncated input: JSON array is not closed\n"));
+    }
+
+    #[test]
+    fn test_truncated_gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        for n in 0..1000 {
+            writeln!(
+                encoder,
+                "<http://www.wikidata.org/entity/Q{n}> <p> \"{n}\" ."
+            )
+            .unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+        let truncated = &compressed[..compressed.len() / 2];
+        let (summary, report) = validate(flate2::read::GzDecoder::new(truncated), false);
+        assert!(summary.lines < 1000);
+        assert_eq!(
+            summary.problems.keys().collect::<Vec<_>>(),
+            [&Problem::Truncated]
+        );
+        assert!(report.contains("input ends early"), "{report}");
+    }
+
+    #[test]
+    fn test_seen_ids() {
+        let mut seen = SeenIds::default();
+        assert!(seen.insert("Q42"));
+        assert!(seen.insert("P42"));
+        assert!(seen.insert("L1-F1"));
+        assert!(!seen.insert("Q42"));
+        assert!(!seen.insert("L1-F1"));
+        assert!(seen.insert("Q99999999999"));
+        assert!(!seen.insert("Q99999999999"));
+        assert!(seen.contains("Q42") && seen.contains("L1-F1"));
+        assert!(!seen.contains("Q43") && !seen.contains("Q100000") && !seen.contains("L2"));
+    }
+}